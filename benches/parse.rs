@@ -0,0 +1,115 @@
+//! Benchmark the parsing of a large log, with the records matched on 1 thread
+//! and on all the cores, see `--threads`. Run with `cargo bench`.
+
+use std::{
+    env,
+    fmt::Write as _,
+    fs,
+    process::Command,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Number of requests of the log, each logged on 2 lines.
+const NUMBER_OF_REQUESTS: usize = 200_000;
+
+/// Number of runs per number of threads, of which the fastest is kept.
+const NUMBER_OF_RUNS: usize = 3;
+
+fn main() {
+    let directory = env::temp_dir().join(format!("network-viewer-bench-{}", std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+
+    let log_path = directory.join("large.log");
+    let mut log = String::new();
+
+    for nth in 0..NUMBER_OF_REQUESTS {
+        let (connection_id, uri) = match nth % 3 {
+            0 => (
+                "room-list",
+                "https://matrix.example.org/_matrix/client/unstable/org.matrix.simplified_msc3575/sync?timeout=30000",
+            ),
+            1 => (
+                "encryption",
+                "https://matrix.example.org/_matrix/client/v3/keys/query",
+            ),
+            _ => (
+                "send-queue",
+                "https://matrix.example.org/_matrix/client/v3/rooms/!abc:example.org/send/m.room.message/txn",
+            ),
+        };
+        // A request every 100ms, answered after 50ms.
+        let at = |milliseconds: usize| {
+            let milliseconds = nth * 100 + milliseconds;
+
+            format!(
+                "2024-06-01T{:02}:{:02}:{:02}.{:03}000Z",
+                10 + milliseconds / 3_600_000,
+                milliseconds / 60_000 % 60,
+                milliseconds / 1_000 % 60,
+                milliseconds % 1_000,
+            )
+        };
+
+        writeln!(
+            log,
+            r#"{} DEBUG matrix_sdk::http_client: Sending request | crates/matrix-sdk/src/http_client/mod.rs:180 | spans: root > sync_once{{conn_id="{connection_id}"}} > send{{request_id="REQ-{nth}" method=POST uri="{uri}" request_size="92B"}}"#,
+            at(0),
+        )
+        .unwrap();
+        writeln!(
+            log,
+            r#"{} DEBUG matrix_sdk::http_client: Got response | crates/matrix-sdk/src/http_client/mod.rs:190 | spans: root > sync_once{{conn_id="{connection_id}"}} > send{{request_id="REQ-{nth}" method=POST uri="{uri}" request_size="92B" status=200 response_size="4.2kB"}}"#,
+            at(50),
+        )
+        .unwrap();
+    }
+
+    fs::write(&log_path, log).unwrap();
+
+    let number_of_cores = thread::available_parallelism().map_or(1, usize::from);
+    let output_path = directory.join("large.csv");
+    let parse = |threads: usize| {
+        (0..NUMBER_OF_RUNS)
+            .map(|_| {
+                let start = Instant::now();
+                let output = Command::new(env!("CARGO_BIN_EXE_network-viewer"))
+                    .args(["--quiet", "--threads", &threads.to_string()])
+                    .arg(&log_path)
+                    .arg(&output_path)
+                    .output()
+                    .unwrap();
+
+                assert!(
+                    output.status.success(),
+                    "{}",
+                    String::from_utf8_lossy(&output.stderr)
+                );
+
+                start.elapsed()
+            })
+            .min()
+            .unwrap_or(Duration::ZERO)
+    };
+
+    let sequential = parse(1);
+
+    println!(
+        "{} lines, 1 thread: {sequential:.2?}",
+        NUMBER_OF_REQUESTS * 2
+    );
+
+    if number_of_cores > 1 {
+        let parallel = parse(number_of_cores);
+
+        println!(
+            "{} lines, {number_of_cores} threads: {parallel:.2?}, {:.2}× faster",
+            NUMBER_OF_REQUESTS * 2,
+            sequential.as_secs_f64() / parallel.as_secs_f64(),
+        );
+    } else {
+        println!("1 core only: nothing to compare");
+    }
+
+    fs::remove_dir_all(&directory).unwrap();
+}
@@ -0,0 +1,121 @@
+//! Anomalies found by analysing the spans, rendered as a dedicated section of
+//! the output, and as marks on the rows of the offending spans.
+
+mod busy_syncs;
+mod duplicate_txn_ids;
+mod payload_sizes;
+mod retry_chains;
+mod stuck_syncs;
+
+use crate::{RequestId, Spans, html};
+
+pub use payload_sizes::Thresholds as PayloadSizeThresholds;
+pub use retry_chains::Retry;
+
+/// Configuration of the anomaly detectors.
+#[derive(Clone)]
+pub struct Config {
+    /// Minimum number of consecutive over-budget syncs to report them.
+    pub stuck_sync_run_length: usize,
+    /// Number of MADs from the median beyond which a response size is out of
+    /// line, per endpoint kind.
+    pub payload_size_thresholds: PayloadSizeThresholds,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            stuck_sync_run_length: 5,
+            payload_size_thresholds: PayloadSizeThresholds::default(),
+        }
+    }
+}
+
+/// All the anomalies found in some spans.
+pub struct Anomalies<'a> {
+    duplicate_txn_ids: duplicate_txn_ids::Report<'a>,
+    stuck_syncs: stuck_syncs::Report<'a>,
+    busy_syncs: busy_syncs::Report<'a>,
+    payload_sizes: payload_sizes::Report<'a>,
+    retry_chains: retry_chains::Report<'a>,
+}
+
+/// Run all the anomaly detectors.
+pub fn detect<'a>(spans: &'a Spans, config: &Config) -> Anomalies<'a> {
+    Anomalies {
+        duplicate_txn_ids: duplicate_txn_ids::detect(spans),
+        stuck_syncs: stuck_syncs::detect(spans, config.stuck_sync_run_length),
+        busy_syncs: busy_syncs::detect(spans),
+        payload_sizes: payload_sizes::detect(spans, &config.payload_size_thresholds),
+        retry_chains: retry_chains::detect(spans),
+    }
+}
+
+impl Anomalies<'_> {
+    /// Render the anomalies section.
+    ///
+    /// An empty string is returned if there is nothing to report.
+    pub fn to_html(&self) -> String {
+        let sections = [
+            self.duplicate_txn_ids.to_html(),
+            self.stuck_syncs.to_html(),
+            self.busy_syncs.to_html(),
+            self.payload_sizes.to_html(),
+            self.retry_chains.to_html(),
+        ]
+        .concat();
+
+        if sections.is_empty() {
+            return String::new();
+        }
+
+        format!(
+            "<section class=\"anomalies\">
+  <h2>Anomalies</h2>
+{sections}</section>
+"
+        )
+    }
+
+    /// Space-separated list of the anomalies a span is part of, used to mark
+    /// its row.
+    pub fn marks(&self, connection_id: &str, request_id: RequestId) -> String {
+        let mut marks = Vec::new();
+
+        if self.stuck_syncs.contains(connection_id, request_id) {
+            marks.push("stuck-sync");
+        }
+
+        if self.busy_syncs.contains(connection_id, request_id) {
+            marks.push("busy-sync");
+        }
+
+        if self.payload_sizes.contains(connection_id, request_id) {
+            marks.push("payload-size");
+        }
+
+        if self.retry_chains.get(connection_id, request_id).is_some() {
+            marks.push("retry");
+        }
+
+        marks.join(" ")
+    }
+
+    /// The previous attempt of a span retrying a failed request, if any.
+    pub fn retry_of(&self, connection_id: &str, request_id: RequestId) -> Option<Retry> {
+        self.retry_chains.get(connection_id, request_id)
+    }
+
+    /// Number of responses whose size is out of line.
+    pub fn number_of_payload_size_outliers(&self) -> usize {
+        self.payload_sizes.len()
+    }
+}
+
+/// Render a link to the row of a span.
+fn link(connection_id: &str, request_id: RequestId) -> String {
+    format!(
+        "<a href=\"#{connection_id}-{request_id}\"><code>{connection_id}-{request_id}</code></a>",
+        connection_id = html::escape(connection_id),
+    )
+}
@@ -0,0 +1,205 @@
+//! Detect the sync loops busy-looping: back-to-back syncs on the same
+//! connection returning immediately with a tiny response, e.g. the same
+//! `next_batch` again and again, instead of long-polling.
+//!
+//! A healthy long-poll is held by the server until its `timeout`, or returns
+//! with something new. Several syncs in a row returning nothing at once mean
+//! that the client syncs in a tight loop, burning the battery and the bandwidth
+//! for nothing.
+
+use std::collections::BTreeSet;
+
+use chrono::TimeDelta;
+
+use crate::{ConnectionId, RequestId, Span, Spans, human};
+
+/// Minimum number of back-to-back busy syncs to report them.
+const MINIMUM_RUN_LENGTH: usize = 5;
+
+/// A sync returning faster than this is immediate.
+const MAXIMUM_DURATION: TimeDelta = TimeDelta::seconds(1);
+
+/// A response up to this size, in bytes, carries nothing new.
+const MAXIMUM_RESPONSE_SIZE: u64 = 1_024;
+
+/// A sync starting this long after the end of the previous one isn't
+/// back-to-back.
+const MAXIMUM_GAP: TimeDelta = TimeDelta::seconds(1);
+
+/// Consecutive busy syncs on the same connection.
+struct Run<'a> {
+    connection_id: &'a ConnectionId,
+    syncs: Vec<(RequestId, &'a Span)>,
+}
+
+impl Run<'_> {
+    /// From the start of the first sync to the end of the last one.
+    fn duration(&self) -> TimeDelta {
+        let (_, first) = self.syncs[0];
+        let (_, last) = self.syncs[self.syncs.len() - 1];
+
+        (last.start_at + last.duration) - first.start_at
+    }
+}
+
+pub struct Report<'a> {
+    runs: Vec<Run<'a>>,
+    members: BTreeSet<(&'a str, RequestId)>,
+}
+
+/// Whether a sync has returned immediately with a tiny response. The syncs
+/// whose response size isn't logged are never busy.
+fn is_busy(span: &Span) -> bool {
+    span.is_successful()
+        && span.duration < MAXIMUM_DURATION
+        && span
+            .response_bytes()
+            .is_some_and(|bytes| bytes <= MAXIMUM_RESPONSE_SIZE)
+}
+
+pub fn detect(spans: &Spans) -> Report<'_> {
+    let mut runs = Vec::new();
+
+    for (connection_id, spans) in spans {
+        let mut syncs = spans
+            .iter()
+            .filter(|(_, span)| span.is_sync())
+            .map(|(request_id, span)| (*request_id, span))
+            .collect::<Vec<_>>();
+        syncs.sort_by_key(|(request_id, span)| (span.start_at, *request_id));
+
+        let mut current = Vec::<(RequestId, &Span)>::new();
+
+        for (request_id, span) in syncs {
+            let is_back_to_back = current.last().is_none_or(|(_, previous)| {
+                span.start_at - (previous.start_at + previous.duration) <= MAXIMUM_GAP
+            });
+
+            if !(is_busy(span) && is_back_to_back) {
+                if current.len() >= MINIMUM_RUN_LENGTH {
+                    runs.push(Run {
+                        connection_id,
+                        syncs: current,
+                    });
+                }
+
+                current = Vec::new();
+            }
+
+            if is_busy(span) {
+                current.push((request_id, span));
+            }
+        }
+
+        if current.len() >= MINIMUM_RUN_LENGTH {
+            runs.push(Run {
+                connection_id,
+                syncs: current,
+            });
+        }
+    }
+
+    let members = runs
+        .iter()
+        .flat_map(|run| {
+            run.syncs
+                .iter()
+                .map(|(request_id, _)| (run.connection_id.as_str(), *request_id))
+        })
+        .collect();
+
+    Report { runs, members }
+}
+
+impl Report<'_> {
+    /// Whether a span is part of a run.
+    pub fn contains(&self, connection_id: &str, request_id: RequestId) -> bool {
+        self.members.contains(&(connection_id, request_id))
+    }
+
+    /// Render the report, or an empty string if no run has been found.
+    pub fn to_html(&self) -> String {
+        if self.runs.is_empty() {
+            return String::new();
+        }
+
+        let runs = self
+            .runs
+            .iter()
+            .map(|run| {
+                let (first_request_id, _) = run.syncs[0];
+                let (last_request_id, _) = run.syncs[run.syncs.len() - 1];
+
+                format!(
+                    "    <li>Connection <code>{connection_id}</code>: {count} back-to-back syncs during {duration}, from {first} to {last}</li>\n",
+                    connection_id = run.connection_id,
+                    count = human::count(run.syncs.len()),
+                    duration = human::duration(run.duration()),
+                    first = super::link(run.connection_id, first_request_id),
+                    last = super::link(run.connection_id, last_request_id),
+                )
+            })
+            .collect::<String>();
+
+        format!(
+            "  <h3>Busy sync loops</h3>
+  <p>{number_of_runs} periods of syncs returning in less than {maximum_duration} with at most {maximum_response_size}, back-to-back, during {total} in total.</p>
+  <ul>
+{runs}  </ul>
+",
+            number_of_runs = human::count(self.runs.len()),
+            maximum_duration = human::duration(MAXIMUM_DURATION),
+            maximum_response_size = human::bytes(MAXIMUM_RESPONSE_SIZE),
+            total = human::duration(self.runs.iter().map(Run::duration).sum()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SpansBuilder, size::Size};
+
+    #[test]
+    fn test_detect() {
+        let uri = "https://example.org/_matrix/client/v3/sync?timeout=30000";
+        let mut spans = SpansBuilder::default();
+
+        // A long-poll, then 6 busy syncs, then a sync with news.
+        spans
+            .span("main", 0, uri, Some(200), -40_000, 30_000)
+            .response_size = Some(Size::new("120B"));
+
+        let mut sync = |request_id: RequestId, duration: i64, size: &str| {
+            spans
+                .span(
+                    "main",
+                    request_id,
+                    uri,
+                    Some(200),
+                    i64::from(request_id) * 200,
+                    duration,
+                )
+                .response_size = Some(Size::new(size));
+        };
+
+        for request_id in 1..=6 {
+            sync(request_id, 50, "120B");
+        }
+        sync(7, 50, "20kB");
+        // 4 busy syncs only.
+        for request_id in 8..=11 {
+            sync(request_id, 50, "120B");
+        }
+
+        let spans = spans.build();
+        let report = detect(&spans);
+
+        assert_eq!(report.runs.len(), 1);
+        assert_eq!(report.runs[0].syncs.len(), 6);
+        assert!(report.contains("main", 1));
+        assert!(!report.contains("main", 0));
+        assert!(!report.contains("main", 8));
+        assert_eq!(report.runs[0].duration(), TimeDelta::milliseconds(1_050));
+    }
+}
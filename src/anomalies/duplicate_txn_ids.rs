@@ -0,0 +1,235 @@
+//! Detect transaction IDs that are misused across event sends.
+//!
+//! Sending the same `{txnId}` twice is expected: it's how a client retries a
+//! send idempotently. However, the same transaction ID used for different
+//! rooms, or reused long after its previous use, indicates a client bug.
+
+use ada_url::Url;
+use chrono::TimeDelta;
+use std::collections::BTreeMap;
+
+use crate::{ConnectionId, RequestId, Span, Spans, endpoint, human};
+
+/// Maximum delay between two uses of the same transaction ID for the second
+/// one to be considered as a retry of the first one.
+const RETRY_WINDOW: TimeDelta = TimeDelta::minutes(5);
+
+/// A send of an event, i.e. a span with a transaction ID in its path.
+#[derive(Clone)]
+struct Send<'a> {
+    connection_id: &'a ConnectionId,
+    request_id: RequestId,
+    span: &'a Span,
+    room_id: String,
+}
+
+impl Send<'_> {
+    fn to_html(&self) -> String {
+        super::link(self.connection_id, self.request_id)
+    }
+}
+
+enum Violation<'a> {
+    /// The same transaction ID is used for different rooms.
+    DifferentRooms {
+        txn_id: String,
+        sends: Vec<Send<'a>>,
+    },
+
+    /// The same transaction ID is used again outside of the retry window.
+    LongGap {
+        txn_id: String,
+        gap: TimeDelta,
+        previous: Send<'a>,
+        next: Send<'a>,
+    },
+}
+
+pub struct Report<'a> {
+    violations: Vec<Violation<'a>>,
+
+    /// Number of transactions, indexed by their number of retries.
+    retries: BTreeMap<usize, usize>,
+}
+
+pub fn detect(spans: &Spans) -> Report<'_> {
+    let mut sends_by_txn_id: BTreeMap<String, Vec<Send<'_>>> = BTreeMap::new();
+
+    for (connection_id, spans) in spans {
+        for (request_id, span) in spans {
+            if let Some((room_id, txn_id)) = parse_send_path(&span.uri) {
+                sends_by_txn_id.entry(txn_id).or_default().push(Send {
+                    connection_id,
+                    request_id: *request_id,
+                    span,
+                    room_id,
+                });
+            }
+        }
+    }
+
+    let mut violations = Vec::new();
+    let mut retries = BTreeMap::new();
+
+    for (txn_id, mut sends) in sends_by_txn_id {
+        *retries.entry(sends.len() - 1).or_default() += 1;
+
+        if sends.len() < 2 {
+            continue;
+        }
+
+        sends.sort_by_key(|send| send.span.start_at);
+
+        for (previous, next) in sends.iter().zip(sends.iter().skip(1)) {
+            let gap = next.span.start_at - (previous.span.start_at + previous.span.duration);
+
+            if gap > RETRY_WINDOW {
+                violations.push(Violation::LongGap {
+                    txn_id: txn_id.clone(),
+                    gap,
+                    previous: previous.clone(),
+                    next: next.clone(),
+                });
+            }
+        }
+
+        if sends.iter().any(|send| send.room_id != sends[0].room_id) {
+            violations.push(Violation::DifferentRooms { txn_id, sends });
+        }
+    }
+
+    Report {
+        violations,
+        retries,
+    }
+}
+
+impl Report<'_> {
+    /// Render the report, or an empty string if no event send has been found.
+    pub fn to_html(&self) -> String {
+        if self.retries.is_empty() {
+            return String::new();
+        }
+
+        let violations = if self.violations.is_empty() {
+            "  <p>No misused transaction ID.</p>\n".to_owned()
+        } else {
+            format!(
+                "  <ul>\n{}  </ul>\n",
+                self.violations
+                    .iter()
+                    .map(|violation| match violation {
+                        Violation::DifferentRooms { txn_id, sends } => format!(
+                            "    <li>Transaction <code>{txn_id}</code> is used for different rooms: {}</li>\n",
+                            sends
+                                .iter()
+                                .map(|send| format!("{} (<code>{}</code>)", send.to_html(), send.room_id))
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        ),
+                        Violation::LongGap {
+                            txn_id,
+                            gap,
+                            previous,
+                            next,
+                        } => format!(
+                            "    <li>Transaction <code>{txn_id}</code> is used again {gap} after its previous use: {} then {}</li>\n",
+                            previous.to_html(),
+                            next.to_html(),
+                            gap = human::duration(*gap),
+                        ),
+                    })
+                    .collect::<String>()
+            )
+        };
+
+        let retries = self
+            .retries
+            .iter()
+            .map(|(retries, transactions)| {
+                format!(
+                    "      <tr><td>{retries}</td><td>{transactions}</td></tr>\n",
+                    retries = human::count(*retries),
+                    transactions = human::count(*transactions),
+                )
+            })
+            .collect::<String>();
+
+        format!(
+            "  <h3>Duplicate transaction IDs</h3>
+{violations}  <table>
+    <thead>
+      <tr><th scope=\"col\">Retries</th><th scope=\"col\">Transactions</th></tr>
+    </thead>
+    <tbody>
+{retries}    </tbody>
+  </table>
+"
+        )
+    }
+}
+
+/// Extract the room ID and the transaction ID from the path of an event send,
+/// i.e. `/rooms/{roomId}/send/{eventType}/{txnId}` or
+/// `/rooms/{roomId}/redact/{eventId}/{txnId}`. The IDs are percent-decoded, so
+/// that `!abc%3Aexample.org` and `!abc:example.org` are the same room.
+fn parse_send_path(uri: &str) -> Option<(String, String)> {
+    let uri = Url::parse(uri, None).ok()?;
+    let mut segments = uri
+        .pathname()
+        .split('/')
+        .skip_while(|segment| *segment != "rooms")
+        .skip(1);
+
+    let room_id = segments.next().filter(|segment| !segment.is_empty())?;
+
+    if !matches!(segments.next()?, "send" | "redact") {
+        return None;
+    }
+
+    // The event type, or the ID of the redacted event.
+    segments.next()?;
+    let txn_id = segments.next()?;
+
+    if txn_id.is_empty() || segments.next().is_some() {
+        return None;
+    }
+
+    Some((
+        endpoint::percent_decode(room_id),
+        endpoint::percent_decode(txn_id),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_send_path() {
+        assert_eq!(
+            parse_send_path(
+                "https://example.org/_matrix/client/v3/rooms/!abc%3Aexample.org/send/m.room.message/txn1"
+            ),
+            Some(("!abc:example.org".to_owned(), "txn1".to_owned()))
+        );
+        assert_eq!(
+            parse_send_path(
+                "https://example.org/_matrix/client/v3/rooms/!abc:example.org/redact/$event/txn2"
+            ),
+            Some(("!abc:example.org".to_owned(), "txn2".to_owned()))
+        );
+        assert_eq!(
+            parse_send_path(
+                "https://example.org/_matrix/client/v3/rooms/!abc:example.org/send/m.room.message/m.1%2Fa"
+            ),
+            Some(("!abc:example.org".to_owned(), "m.1/a".to_owned()))
+        );
+        assert_eq!(
+            parse_send_path(
+                "https://example.org/_matrix/client/v3/rooms/!abc:example.org/messages"
+            ),
+            None
+        );
+    }
+}
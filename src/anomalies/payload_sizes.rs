@@ -0,0 +1,264 @@
+//! Detect responses whose size is out of line for their endpoint, e.g. a
+//! 40 MiB sync response, or an empty media download.
+//!
+//! The sizes of the successful responses of an endpoint are compared to their
+//! median, in median absolute deviations (MADs), which a few huge responses
+//! can't skew. A successful but empty response is out of line as soon as the
+//! median isn't empty.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{ConnectionId, RequestId, Span, Spans, endpoint::Kind, html, human, size::Size, stats};
+
+/// Minimum number of sized responses of an endpoint to detect outliers: below,
+/// the median isn't representative.
+const MINIMUM_NUMBER_OF_SPANS: usize = 10;
+
+/// Number of MADs from the median beyond which a size is out of line, per
+/// endpoint kind.
+#[derive(Clone, Debug)]
+pub struct Thresholds {
+    default: f64,
+    per_kind: BTreeMap<Kind, f64>,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Self {
+            default: 5.,
+            // The sizes of media vary naturally a lot.
+            per_kind: BTreeMap::from([(Kind::Media, 20.)]),
+        }
+    }
+}
+
+impl Thresholds {
+    /// Parse a threshold like `5`, or like `media=20` to override it for an
+    /// endpoint kind.
+    pub fn parse(&mut self, value: &str) -> Result<(), String> {
+        let (kind, threshold) = match value.split_once('=') {
+            Some((kind, threshold)) => {
+                let kind = Kind::ALL
+                    .into_iter()
+                    .find(|candidate| candidate.as_str() == kind.trim())
+                    .ok_or_else(|| {
+                        format!(
+                            "Unknown endpoint kind `{kind}`; valid kinds are {}",
+                            Kind::ALL
+                                .iter()
+                                .map(|kind| format!("`{}`", kind.as_str()))
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        )
+                    })?;
+
+                (Some(kind), threshold)
+            }
+            None => (None, value),
+        };
+
+        let threshold = threshold
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|threshold| *threshold > 0.)
+            .ok_or_else(|| format!("`{threshold}` isn't a positive number of MADs"))?;
+
+        match kind {
+            Some(kind) => {
+                self.per_kind.insert(kind, threshold);
+            }
+            None => self.default = threshold,
+        }
+
+        Ok(())
+    }
+
+    fn for_kind(&self, kind: Kind) -> f64 {
+        self.per_kind.get(&kind).copied().unwrap_or(self.default)
+    }
+}
+
+/// A response whose size is out of line.
+struct Outlier<'a> {
+    connection_id: &'a ConnectionId,
+    request_id: RequestId,
+    endpoint: String,
+    size: u64,
+    median: u64,
+    /// Number of MADs from the median, if the size isn't empty.
+    mads: Option<f64>,
+}
+
+pub struct Report<'a> {
+    outliers: Vec<Outlier<'a>>,
+    members: BTreeSet<(&'a str, RequestId)>,
+}
+
+/// Get the size of the response of a successful span, if logged.
+fn response_size(span: &Span) -> Option<u64> {
+    span.is_successful()
+        .then(|| span.response_size.as_ref().and_then(Size::bytes))
+        .flatten()
+}
+
+pub fn detect<'a>(spans: &'a Spans, thresholds: &Thresholds) -> Report<'a> {
+    let mut per_endpoint = BTreeMap::<String, Vec<(&ConnectionId, RequestId, &Span, u64)>>::new();
+
+    for (connection_id, spans) in spans {
+        for (request_id, span) in spans {
+            if let Some(size) = response_size(span) {
+                per_endpoint.entry(span.endpoint()).or_default().push((
+                    connection_id,
+                    *request_id,
+                    span,
+                    size,
+                ));
+            }
+        }
+    }
+
+    let mut outliers = Vec::new();
+
+    for (endpoint, responses) in per_endpoint {
+        if responses.len() < MINIMUM_NUMBER_OF_SPANS {
+            continue;
+        }
+
+        let mut sizes = responses.iter().map(|(.., size)| *size).collect::<Vec<_>>();
+        sizes.sort_unstable();
+        let median = stats::percentile(&sizes, 50.).unwrap_or_default();
+
+        let mut deviations = sizes
+            .iter()
+            .map(|size| size.abs_diff(median))
+            .collect::<Vec<_>>();
+        deviations.sort_unstable();
+        let mad = stats::percentile(&deviations, 50.).unwrap_or_default();
+
+        for (connection_id, request_id, span, size) in responses {
+            let threshold = thresholds.for_kind(span.kind());
+            let mads = (mad > 0).then(|| size.abs_diff(median) as f64 / mad as f64);
+
+            if (size == 0 && median > 0) || mads.is_some_and(|mads| mads >= threshold) {
+                outliers.push(Outlier {
+                    connection_id,
+                    request_id,
+                    endpoint: endpoint.clone(),
+                    size,
+                    median,
+                    mads: mads.filter(|_| size > 0),
+                });
+            }
+        }
+    }
+
+    outliers.sort_by_key(|outlier| (outlier.connection_id, outlier.request_id));
+
+    let members = outliers
+        .iter()
+        .map(|outlier| (outlier.connection_id.as_str(), outlier.request_id))
+        .collect();
+
+    Report { outliers, members }
+}
+
+impl Report<'_> {
+    /// Whether the response of a span is out of line.
+    pub fn contains(&self, connection_id: &str, request_id: RequestId) -> bool {
+        self.members.contains(&(connection_id, request_id))
+    }
+
+    /// Number of responses out of line.
+    pub fn len(&self) -> usize {
+        self.outliers.len()
+    }
+
+    /// Render the report, or an empty string if no outlier has been found.
+    pub fn to_html(&self) -> String {
+        if self.outliers.is_empty() {
+            return String::new();
+        }
+
+        let outliers = self
+            .outliers
+            .iter()
+            .map(|outlier| {
+                format!(
+                    "    <li>{link} <code>{endpoint}</code>: {size}, {deviation} the median of {median}</li>\n",
+                    link = super::link(outlier.connection_id, outlier.request_id),
+                    endpoint = html::escape(&outlier.endpoint),
+                    size = human::bytes(outlier.size),
+                    deviation = match outlier.mads {
+                        Some(mads) => format!(
+                            "{} MADs {}",
+                            human::ratio(mads),
+                            if outlier.size > outlier.median { "above" } else { "below" }
+                        ),
+                        None => "empty, unlike".to_owned(),
+                    },
+                    median = human::bytes(outlier.median),
+                )
+            })
+            .collect::<String>();
+
+        format!(
+            "  <h3>Payload sizes out of line</h3>
+  <p>Successful responses whose size is far from the median of their endpoint, in median absolute deviations (MADs).</p>
+  <ul>
+{outliers}  </ul>
+"
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SpansBuilder;
+
+    fn spans(uri: &str, sizes: &[&str]) -> Spans {
+        let mut spans = SpansBuilder::default();
+
+        for (request_id, size) in (0..).zip(sizes) {
+            spans
+                .span("c", request_id, uri, Some(200), 0, 100)
+                .response_size = Some(Size::new(size));
+        }
+
+        spans.build()
+    }
+
+    #[test]
+    fn test_detect() {
+        let sync = spans(
+            "https://example.org/_matrix/client/v3/sync",
+            &[
+                "10kB", "11kB", "9kB", "12kB", "10kB", "8kB", "11kB", "10kB", "9kB", "40MB",
+            ],
+        );
+        let report = detect(&sync, &Thresholds::default());
+
+        assert_eq!(report.len(), 1);
+        assert!(report.contains("c", 9));
+
+        let media = spans(
+            "https://example.org/_matrix/client/v1/media/download/example.org/abc",
+            &[
+                "100kB", "200kB", "30kB", "500kB", "0B", "90kB", "300kB", "250kB", "40kB", "1.5MB",
+            ],
+        );
+        let report = detect(&media, &Thresholds::default());
+
+        // The empty download is out of line, but not the 1.5 MB one, within the
+        // threshold of the media.
+        assert_eq!(report.len(), 1);
+        assert!(report.contains("c", 4));
+
+        let mut thresholds = Thresholds::default();
+        thresholds.parse("media=5").unwrap();
+        assert_eq!(detect(&media, &thresholds).len(), 2);
+        assert!(thresholds.parse("media=-1").is_err());
+        assert!(thresholds.parse("video=5").is_err());
+    }
+}
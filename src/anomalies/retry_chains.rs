@@ -0,0 +1,223 @@
+//! Detect chains of retries of the same request.
+//!
+//! The SDK retries the failed requests with an exponential backoff, and each
+//! attempt is logged as a request of its own, with the same method and URI.
+//! A request is a retry of the previous request of its connection to the same
+//! method and URI if the previous one has failed, and if it starts shortly
+//! after. A long chain is a backoff storm, costing the sum of its attempts.
+
+use std::collections::BTreeMap;
+
+use chrono::TimeDelta;
+
+use crate::{ConnectionId, RequestId, Span, Spans, html, human};
+
+/// Maximum delay between the end of a failed attempt and the start of the
+/// next one for the latter to be considered as a retry.
+const BACKOFF_WINDOW: TimeDelta = TimeDelta::minutes(2);
+
+/// A retry, as seen from one of the rows of the chain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Retry {
+    /// The previous attempt.
+    pub previous: RequestId,
+    /// Time elapsed from the start of the first attempt to the end of this
+    /// one.
+    pub latency: TimeDelta,
+}
+
+/// Consecutive attempts of the same request on the same connection.
+struct Chain<'a> {
+    connection_id: &'a ConnectionId,
+    attempts: Vec<(RequestId, &'a Span)>,
+}
+
+impl Chain<'_> {
+    /// Time elapsed from the start of the first attempt to the end of the
+    /// latest one.
+    fn latency(&self) -> TimeDelta {
+        let (_, first) = self.attempts[0];
+        let (_, last) = self.attempts[self.attempts.len() - 1];
+
+        last.start_at + last.duration - first.start_at
+    }
+
+    fn outcome(&self) -> &'static str {
+        let (_, last) = self.attempts[self.attempts.len() - 1];
+
+        if last.is_pending() {
+            "pending"
+        } else if last.is_successful() {
+            "succeeded"
+        } else {
+            "failed"
+        }
+    }
+}
+
+pub struct Report<'a> {
+    chains: Vec<Chain<'a>>,
+    retries: BTreeMap<(&'a str, RequestId), Retry>,
+}
+
+pub fn detect(spans: &Spans) -> Report<'_> {
+    let mut chains = Vec::new();
+
+    for (connection_id, spans) in spans {
+        let mut spans = spans.iter().collect::<Vec<_>>();
+        spans.sort_by_key(|(request_id, span)| (span.start_at, **request_id));
+
+        // The chain of each method and URI, whose latest attempt may be retried.
+        let mut open_chains: BTreeMap<(&str, &str), Chain<'_>> = BTreeMap::new();
+
+        for (request_id, span) in spans {
+            let key = (span.method.as_str(), span.uri.as_str());
+            let is_retry = open_chains.get(&key).is_some_and(|chain| {
+                let (_, previous) = chain.attempts[chain.attempts.len() - 1];
+                let gap = span.start_at - (previous.start_at + previous.duration);
+
+                !previous.is_pending()
+                    && !previous.is_successful()
+                    && gap >= TimeDelta::zero()
+                    && gap <= BACKOFF_WINDOW
+            });
+
+            if is_retry {
+                if let Some(chain) = open_chains.get_mut(&key) {
+                    chain.attempts.push((*request_id, span));
+                }
+            } else if let Some(chain) = open_chains.insert(
+                key,
+                Chain {
+                    connection_id,
+                    attempts: vec![(*request_id, span)],
+                },
+            ) {
+                chains.push(chain);
+            }
+        }
+
+        chains.extend(open_chains.into_values());
+    }
+
+    chains.retain(|chain| chain.attempts.len() > 1);
+    // The longest storms first.
+    chains.sort_by_key(|chain| (usize::MAX - chain.attempts.len(), -chain.latency()));
+
+    let mut retries = BTreeMap::new();
+
+    for chain in &chains {
+        let (_, first) = chain.attempts[0];
+
+        for window in chain.attempts.windows(2) {
+            let [(previous, _), (request_id, span)] = window else {
+                continue;
+            };
+
+            retries.insert(
+                (chain.connection_id.as_str(), *request_id),
+                Retry {
+                    previous: *previous,
+                    latency: span.start_at + span.duration - first.start_at,
+                },
+            );
+        }
+    }
+
+    Report { chains, retries }
+}
+
+impl Report<'_> {
+    /// Whether a span is a retry of a previous one.
+    pub fn get(&self, connection_id: &str, request_id: RequestId) -> Option<Retry> {
+        self.retries.get(&(connection_id, request_id)).copied()
+    }
+
+    /// Render the report, or an empty string if no request has been retried.
+    pub fn to_html(&self) -> String {
+        if self.chains.is_empty() {
+            return String::new();
+        }
+
+        let rows = self
+            .chains
+            .iter()
+            .map(|chain| {
+                let (_, first) = chain.attempts[0];
+
+                format!(
+                    "      <tr><td><code>{endpoint}</code></td><td>{attempts}</td><td>{number_of_attempts}</td><td>{latency}</td><td>{outcome}</td></tr>\n",
+                    endpoint = html::escape(&first.endpoint()),
+                    attempts = chain
+                        .attempts
+                        .iter()
+                        .map(|(request_id, _)| super::link(chain.connection_id, *request_id))
+                        .collect::<Vec<_>>()
+                        .join(" → "),
+                    number_of_attempts = human::count(chain.attempts.len()),
+                    latency = human::duration(chain.latency()),
+                    outcome = chain.outcome(),
+                )
+            })
+            .collect::<String>();
+
+        format!(
+            "  <h3>Retry chains</h3>
+  <table>
+    <thead>
+      <tr><th scope=\"col\">Endpoint</th><th scope=\"col\">Chain</th><th scope=\"col\">Attempts</th><th scope=\"col\">Cumulative latency</th><th scope=\"col\">Outcome</th></tr>
+    </thead>
+    <tbody>
+{rows}    </tbody>
+  </table>
+"
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+
+    use super::*;
+    use crate::SpansBuilder;
+
+    #[test]
+    fn test_detect() {
+        let uri = "https://example.org/_matrix/client/v3/keys/query";
+        let mut spans = SpansBuilder::default();
+        spans.span("encryption", 1, uri, Some(502), 0, 100);
+        spans.span("encryption", 2, uri, Some(502), 1_100, 100);
+        spans.span("encryption", 3, uri, Some(200), 3_200, 100);
+        // A new request, since the previous one has succeeded.
+        spans.span("encryption", 4, uri, Some(502), 3_400, 100);
+        // Too late to be a retry.
+        spans.span("encryption", 5, uri, Some(200), 300_000, 100);
+        let spans = spans.build();
+        let report = detect(&spans);
+
+        assert_eq!(report.chains.len(), 1);
+        assert_eq!(report.get("encryption", 1), None);
+        assert_eq!(
+            report.get("encryption", 2),
+            Some(Retry {
+                previous: 1,
+                latency: TimeDelta::milliseconds(1_200),
+            })
+        );
+        assert_eq!(
+            report.get("encryption", 3),
+            Some(Retry {
+                previous: 2,
+                latency: TimeDelta::milliseconds(3_300),
+            })
+        );
+        assert_eq!(report.get("encryption", 4), None);
+        assert_eq!(report.get("encryption", 5), None);
+        assert!(
+            report
+                .to_html()
+                .contains("<td>3</td><td>3.30s</td><td>succeeded</td>")
+        );
+    }
+}
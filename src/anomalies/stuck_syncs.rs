@@ -0,0 +1,146 @@
+//! Detect runs of consecutive long-polls exceeding their timeout budget.
+//!
+//! One sync taking longer than its `timeout` is noise. Several consecutive
+//! syncs doing so on the same connection mean that a proxy is buffering the
+//! responses, or that the connection is half-dead.
+
+use std::collections::BTreeSet;
+
+use crate::{ConnectionId, RequestId, Span, Spans, human};
+
+/// A sync taking more than this factor of its `timeout` is over budget.
+const BUDGET_FACTOR: i32 = 2;
+
+/// How a run of over-budget syncs has ended.
+enum Outcome {
+    /// The run has ended with a failed sync.
+    Error(RequestId),
+
+    /// The run has ended with a successful sync within its budget.
+    Recovery(RequestId),
+
+    /// The run lasts until the end of the log.
+    Ongoing,
+}
+
+/// Consecutive over-budget syncs on the same connection.
+struct Run<'a> {
+    connection_id: &'a ConnectionId,
+    syncs: Vec<(RequestId, &'a Span)>,
+    outcome: Outcome,
+}
+
+pub struct Report<'a> {
+    runs: Vec<Run<'a>>,
+    members: BTreeSet<(&'a str, RequestId)>,
+}
+
+pub fn detect(spans: &Spans, run_length: usize) -> Report<'_> {
+    let mut runs = Vec::new();
+
+    for (connection_id, spans) in spans {
+        let mut long_polls = spans
+            .iter()
+            .filter(|(_, span)| span.is_sync())
+            .filter_map(|(request_id, span)| {
+                let timeout = span.timeout().filter(|timeout| !timeout.is_zero())?;
+
+                Some((*request_id, span, timeout))
+            })
+            .collect::<Vec<_>>();
+        long_polls.sort_by_key(|(request_id, span, _)| (span.start_at, *request_id));
+
+        let mut current = Vec::new();
+
+        for (request_id, span, timeout) in long_polls {
+            if !span.is_successful() {
+                if current.len() >= run_length {
+                    runs.push(Run {
+                        connection_id,
+                        syncs: current,
+                        outcome: Outcome::Error(request_id),
+                    });
+                }
+
+                current = Vec::new();
+            } else if span.duration > timeout * BUDGET_FACTOR {
+                current.push((request_id, span));
+            } else {
+                if current.len() >= run_length {
+                    runs.push(Run {
+                        connection_id,
+                        syncs: current,
+                        outcome: Outcome::Recovery(request_id),
+                    });
+                }
+
+                current = Vec::new();
+            }
+        }
+
+        if current.len() >= run_length {
+            runs.push(Run {
+                connection_id,
+                syncs: current,
+                outcome: Outcome::Ongoing,
+            });
+        }
+    }
+
+    let members = runs
+        .iter()
+        .flat_map(|run| {
+            run.syncs
+                .iter()
+                .map(|(request_id, _)| (run.connection_id.as_str(), *request_id))
+        })
+        .collect();
+
+    Report { runs, members }
+}
+
+impl Report<'_> {
+    /// Whether a span is part of a run.
+    pub fn contains(&self, connection_id: &str, request_id: RequestId) -> bool {
+        self.members.contains(&(connection_id, request_id))
+    }
+
+    /// Render the report, or an empty string if no run has been found.
+    pub fn to_html(&self) -> String {
+        if self.runs.is_empty() {
+            return String::new();
+        }
+
+        let runs = self
+            .runs
+            .iter()
+            .map(|run| {
+                let (first_request_id, first) = run.syncs[0];
+                let (last_request_id, last) = run.syncs[run.syncs.len() - 1];
+                let duration = (last.start_at + last.duration) - first.start_at;
+
+                format!(
+                    "    <li>Connection <code>{connection_id}</code>: {count} consecutive syncs over budget during {duration}, from {first} to {last}, {outcome}</li>\n",
+                    connection_id = run.connection_id,
+                    count = human::count(run.syncs.len()),
+                    duration = human::duration(duration),
+                    first = super::link(run.connection_id, first_request_id),
+                    last = super::link(run.connection_id, last_request_id),
+                    outcome = match run.outcome {
+                        Outcome::Error(request_id) => format!("ended with an error on {}", super::link(run.connection_id, request_id)),
+                        Outcome::Recovery(request_id) => format!("recovered on {}", super::link(run.connection_id, request_id)),
+                        Outcome::Ongoing => "until the end of the log".to_owned(),
+                    }
+                )
+            })
+            .collect::<String>();
+
+        format!(
+            "  <h3>Stuck long-polls</h3>
+  <p>Syncs taking more than {BUDGET_FACTOR}× their <code>timeout</code>.</p>
+  <ul>
+{runs}  </ul>
+"
+        )
+    }
+}
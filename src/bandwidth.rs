@@ -0,0 +1,146 @@
+//! Sum the bytes transferred over time, to draw the bandwidth of the app
+//! above the table: a single large response dominates the durations, not
+//! necessarily the throughput.
+//!
+//! The logs don't tell when the bytes of a span have been transferred, so
+//! they aren't spread over its duration: the bytes sent are attributed to the
+//! bucket holding the start of the span, and the bytes received to the bucket
+//! holding its end, when the response has been received.
+//!
+//! The bytes are also summed per kind of endpoint, to tell whether a sync
+//! storm or a media download is responsible for the data usage.
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use serde::Serialize;
+
+use crate::{Spans, concurrency};
+
+/// Bytes transferred per bucket of time.
+#[derive(Default, Serialize)]
+pub struct Bandwidth {
+    /// Start of the first bucket, in milliseconds since the Unix epoch.
+    pub start_at: i64,
+
+    /// Duration of a bucket, in milliseconds.
+    pub resolution: i64,
+
+    /// Bytes of the requests, per bucket.
+    pub sent: Vec<u64>,
+
+    /// Bytes of the responses, per bucket.
+    pub received: Vec<u64>,
+
+    /// Bytes sent and received, per kind of endpoint having bytes, then per
+    /// bucket.
+    pub per_kind: BTreeMap<&'static str, Vec<u64>>,
+}
+
+/// Sum the bytes transferred by the spans, from `start_at` to `end_at`, per
+/// bucket of `bucket`, or of the resolution of the concurrency timeline if
+/// `None`.
+pub fn compute(
+    spans: &Spans,
+    (start_at, end_at): (DateTime<FixedOffset>, DateTime<FixedOffset>),
+    bucket: Option<TimeDelta>,
+) -> Bandwidth {
+    let resolution = bucket
+        .map(|bucket| bucket.num_milliseconds().max(1))
+        .unwrap_or_else(|| concurrency::resolution(end_at - start_at));
+    let origin = start_at.timestamp_millis();
+    let number_of_buckets = ((end_at.timestamp_millis() - origin) / resolution + 1) as usize;
+    let bucket_of = |at: DateTime<FixedOffset>| {
+        ((at.timestamp_millis() - origin).max(0) / resolution).min(number_of_buckets as i64 - 1)
+            as usize
+    };
+
+    let mut sent = vec![0; number_of_buckets];
+    let mut received = vec![0; number_of_buckets];
+    let mut per_kind = BTreeMap::<_, Vec<u64>>::new();
+
+    for span in spans.values().flat_map(|spans| spans.values()) {
+        let mut add = |bytes, bucket| {
+            per_kind
+                .entry(span.kind().as_str())
+                .or_insert_with(|| vec![0; number_of_buckets])[bucket] += bytes;
+        };
+
+        if let Some(bytes) = span.request_bytes() {
+            sent[bucket_of(span.start_at)] += bytes;
+            add(bytes, bucket_of(span.start_at));
+        }
+
+        if let Some(bytes) = span.response_bytes() {
+            received[bucket_of(span.start_at + span.duration)] += bytes;
+            add(bytes, bucket_of(span.start_at + span.duration));
+        }
+    }
+
+    Bandwidth {
+        start_at: origin,
+        resolution,
+        sent,
+        received,
+        per_kind,
+    }
+}
+
+impl Bandwidth {
+    /// Serialize the buckets, safe to embed in a `<script>` element.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self)
+            .expect("Failed to serialize the bandwidth")
+            .replace("</", "<\\/")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::{SpansBuilder, size::Size};
+
+    #[test]
+    fn test_compute() {
+        let mut spans = SpansBuilder::default();
+        let mut span = |request_id, path: &str, start_at, duration, sizes: [&str; 2]| {
+            let span = spans.span(
+                "room-list",
+                request_id,
+                &format!("https://example.org/_matrix/{path}"),
+                Some(200),
+                start_at,
+                duration,
+            );
+            span.request_size = Some(Size::new(sizes[0]));
+            span.response_size = Some(Size::new(sizes[1]));
+        };
+        // Sent in the 1st bucket, received in the 3rd one.
+        span(
+            1,
+            "client/v1/media/download/example.org/AbCdEf",
+            0,
+            2_500,
+            ["100", "8000"],
+        );
+        span(2, "client/v3/sync", 1_200, 300, ["10", "20"]);
+        let spans = spans.build();
+        let start_at = spans["room-list"][&1].start_at;
+
+        let bandwidth = compute(
+            &spans,
+            (start_at, start_at + TimeDelta::milliseconds(2_500)),
+            Some(TimeDelta::seconds(1)),
+        );
+
+        assert_eq!(bandwidth.resolution, 1_000);
+        assert_eq!(bandwidth.sent, [100, 10, 0]);
+        assert_eq!(bandwidth.received, [0, 20, 8000]);
+        assert_eq!(
+            bandwidth.per_kind,
+            BTreeMap::from([("media", vec![100, 0, 8000]), ("sync", vec![0, 30, 0])])
+        );
+    }
+}
@@ -0,0 +1,74 @@
+//! Capture the bodies of the requests and of the responses, logged by the SDK
+//! at the trace level, e.g. `Response body body={"next_batch": …}`, with
+//! `--capture-bodies`. They are attached to their span by the request ID of
+//! the line, like the headers.
+//!
+//! The JSON bodies are pretty-printed, with their keys sorted, and all the
+//! bodies are truncated, so that a sync response doesn't make the report
+//! unusable.
+
+use crate::html;
+
+/// Maximum number of characters of a body, once pretty-printed.
+const MAXIMUM_LENGTH: usize = 4_096;
+
+/// Whether a body is the one of a request or of a response.
+pub enum Direction {
+    Request,
+    Response,
+}
+
+/// The logged bodies of a span.
+#[derive(Clone, Debug, Default)]
+pub struct Bodies {
+    pub request: Option<String>,
+    pub response: Option<String>,
+}
+
+impl Bodies {
+    /// Render the bodies as items of the details of a span, or nothing if no
+    /// body has been logged.
+    pub fn to_html(&self) -> String {
+        [("Request body", &self.request), ("Response body", &self.response)]
+            .into_iter()
+            .filter_map(|(label, body)| {
+                Some(format!(
+                    "            <li><details class=\"body\"><summary>{label}</summary><pre><code>{}</code></pre></details></li>\n",
+                    html::escape(body.as_deref()?)
+                ))
+            })
+            .collect()
+    }
+}
+
+/// Prepare a logged body: pretty-print it if it is JSON, and truncate it.
+pub fn prepare(body: &str) -> String {
+    let body = serde_json::from_str::<serde_json::Value>(body)
+        .ok()
+        .and_then(|value| serde_json::to_string_pretty(&value).ok())
+        .unwrap_or_else(|| body.to_owned());
+
+    match body.char_indices().nth(MAXIMUM_LENGTH) {
+        Some((end, _)) => format!("{}…", &body[..end]),
+        None => body,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prepare() {
+        assert_eq!(
+            prepare(r#"{"timeout":30000,"lists":["all"]}"#),
+            "{\n  \"lists\": [\n    \"all\"\n  ],\n  \"timeout\": 30000\n}"
+        );
+        assert_eq!(prepare("not json"), "not json");
+
+        let body = prepare(&format!("\"{}\"", "é".repeat(MAXIMUM_LENGTH)));
+
+        assert_eq!(body.chars().count(), MAXIMUM_LENGTH + 1);
+        assert!(body.ends_with("é…"));
+    }
+}
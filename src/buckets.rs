@@ -0,0 +1,496 @@
+//! Aggregate spans into buckets aligned on the wall-clock hours or days of the
+//! display timezone.
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, FixedOffset, NaiveTime, TimeDelta, Timelike};
+
+use crate::{Span, Spans, endpoint, gaps::Gap, human, size::Size, stats};
+
+/// Aggregated metrics of some spans.
+#[derive(Default)]
+pub struct Aggregate {
+    pub requests: usize,
+    pub errors: usize,
+    pub bytes_down: u64,
+    pub bytes_up: u64,
+
+    /// Sorted once all the spans are added.
+    pub durations: Vec<TimeDelta>,
+}
+
+impl Aggregate {
+    pub fn add(&mut self, span: &Span) {
+        self.requests += 1;
+
+        if span.response_log_line.is_some() && !span.is_successful() {
+            self.errors += 1;
+        }
+
+        self.bytes_down += span
+            .response_size
+            .as_ref()
+            .and_then(Size::bytes)
+            .unwrap_or_default();
+        self.bytes_up += span
+            .request_size
+            .as_ref()
+            .and_then(Size::bytes)
+            .unwrap_or_default();
+        self.durations.push(span.duration);
+    }
+
+    pub fn finish(&mut self) {
+        self.durations.sort();
+    }
+
+    pub fn percentile_duration(&self, percentile: f64) -> Option<TimeDelta> {
+        stats::percentile(&self.durations, percentile)
+    }
+
+    pub fn p95_duration(&self) -> String {
+        stats::percentile(&self.durations, 95.)
+            .map(human::duration)
+            .unwrap_or_default()
+    }
+}
+
+/// Spans starting during one hour.
+pub struct Bucket {
+    pub start_at: DateTime<FixedOffset>,
+
+    /// How much of the hour is covered by the log. Edge hours are usually
+    /// partially covered, hence their lower counts.
+    pub covered: TimeDelta,
+
+    pub aggregate: Aggregate,
+
+    /// Number of gaps starting during the hour.
+    pub gaps: usize,
+}
+
+impl Bucket {
+    pub fn is_partial(&self) -> bool {
+        self.covered < TimeDelta::hours(1)
+    }
+}
+
+/// Aggregate all the spans, and the spans per endpoint kind.
+pub fn per_kind(spans: &Spans) -> (Aggregate, Vec<(endpoint::Kind, Aggregate)>) {
+    let mut all = Aggregate::default();
+    let mut per_kind = endpoint::Kind::ALL.map(|kind| (kind, Aggregate::default()));
+
+    for span in spans.values().flat_map(|spans| spans.values()) {
+        all.add(span);
+        per_kind[kind_index(span.kind())].1.add(span);
+    }
+
+    all.finish();
+
+    for (_, aggregate) in &mut per_kind {
+        aggregate.finish();
+    }
+
+    (all, per_kind.into())
+}
+
+/// Aggregate the spans per endpoint, see [`Span::endpoint`].
+pub fn per_endpoint(spans: &Spans) -> BTreeMap<String, Aggregate> {
+    let mut per_endpoint = BTreeMap::<_, Aggregate>::new();
+
+    for span in spans.values().flat_map(|spans| spans.values()) {
+        per_endpoint.entry(span.endpoint()).or_default().add(span);
+    }
+
+    for aggregate in per_endpoint.values_mut() {
+        aggregate.finish();
+    }
+
+    per_endpoint
+}
+
+/// Spans starting during one minute, grouped by endpoint kind.
+pub struct MinuteBucket {
+    pub start_at: DateTime<FixedOffset>,
+
+    /// One aggregate per kind of [`endpoint::Kind::ALL`], followed by one
+    /// aggregate for all the kinds.
+    pub per_kind: Vec<Aggregate>,
+}
+
+/// Aggregate spans per minute and per endpoint kind, from the minute including
+/// `start_at` to the minute including `end_at`. Minutes without any span are
+/// kept.
+pub fn per_minute_and_kind(
+    spans: &Spans,
+    (start_at, end_at): (DateTime<FixedOffset>, DateTime<FixedOffset>),
+) -> Vec<MinuteBucket> {
+    let first_minute = start_at
+        .with_nanosecond(0)
+        .and_then(|date_time| date_time.with_second(0))
+        .expect("Truncating to the minute always exists with a fixed offset");
+    let number_of_minutes = (end_at - first_minute).num_minutes() as usize + 1;
+
+    let mut buckets = (0..number_of_minutes)
+        .map(|nth| MinuteBucket {
+            start_at: first_minute + TimeDelta::minutes(nth as i64),
+            per_kind: (0..=endpoint::Kind::ALL.len())
+                .map(|_| Aggregate::default())
+                .collect(),
+        })
+        .collect::<Vec<_>>();
+
+    for span in spans.values().flat_map(|spans| spans.values()) {
+        let bucket = &mut buckets
+            [((span.start_at - first_minute).num_minutes() as usize).min(number_of_minutes - 1)];
+
+        bucket.per_kind[kind_index(span.kind())].add(span);
+        bucket.per_kind[endpoint::Kind::ALL.len()].add(span);
+    }
+
+    for aggregate in buckets.iter_mut().flat_map(|bucket| &mut bucket.per_kind) {
+        aggregate.finish();
+    }
+
+    buckets
+}
+
+/// Get the index of a kind in [`endpoint::Kind::ALL`].
+fn kind_index(kind: endpoint::Kind) -> usize {
+    endpoint::Kind::ALL
+        .iter()
+        .position(|candidate| *candidate == kind)
+        .expect("All kinds are listed")
+}
+
+/// Aggregate spans and gaps per hour, from the hour including `start_at` to
+/// the hour including `end_at`. Hours without any span are kept.
+pub fn hourly(
+    spans: &Spans,
+    gaps: &[Gap],
+    (start_at, end_at): (DateTime<FixedOffset>, DateTime<FixedOffset>),
+    timezone: FixedOffset,
+) -> Vec<Bucket> {
+    let first_hour = truncate_to_hour(start_at.with_timezone(&timezone));
+    let last_hour = truncate_to_hour(end_at.with_timezone(&timezone));
+    let number_of_hours = (last_hour - first_hour).num_hours() as usize + 1;
+    let index_of = |date_time: DateTime<FixedOffset>| {
+        ((date_time - first_hour).num_seconds().div_euclid(3600) as usize).min(number_of_hours - 1)
+    };
+
+    let mut buckets = (0..number_of_hours)
+        .map(|nth| {
+            let bucket_start_at = first_hour + TimeDelta::hours(nth as i64);
+            let bucket_end_at = bucket_start_at + TimeDelta::hours(1);
+
+            Bucket {
+                start_at: bucket_start_at,
+                covered: bucket_end_at.min(end_at) - bucket_start_at.max(start_at),
+                aggregate: Aggregate::default(),
+                gaps: 0,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    for span in spans.values().flat_map(|spans| spans.values()) {
+        buckets[index_of(span.start_at)].aggregate.add(span);
+    }
+
+    for gap in gaps {
+        buckets[index_of(gap.start_at)].gaps += 1;
+    }
+
+    for bucket in &mut buckets {
+        bucket.aggregate.finish();
+    }
+
+    buckets
+}
+
+/// Render the hourly buckets as a table.
+pub fn to_html(buckets: &[Bucket]) -> String {
+    let rows = buckets
+        .iter()
+        .map(|bucket| {
+            format!(
+                "      <tr data-partial=\"{partial}\">
+        <td>{hour}{coverage}</td>
+        <td>{requests}</td>
+        <td>{errors}</td>
+        <td>{bytes_down}</td>
+        <td>{bytes_up}</td>
+        <td>{p95_duration}</td>
+        <td>{gaps}</td>
+      </tr>
+",
+                partial = bucket.is_partial(),
+                hour = bucket.start_at.format("%Y-%m-%d %H:%M %:z"),
+                coverage = if bucket.is_partial() {
+                    format!(
+                        " <small>(partial, {})</small>",
+                        human::duration(bucket.covered)
+                    )
+                } else {
+                    String::new()
+                },
+                requests = human::count(bucket.aggregate.requests),
+                errors = human::count(bucket.aggregate.errors),
+                bytes_down = human::bytes_to_html(bucket.aggregate.bytes_down),
+                bytes_up = human::bytes_to_html(bucket.aggregate.bytes_up),
+                p95_duration = bucket.aggregate.p95_duration(),
+                gaps = human::count(bucket.gaps),
+            )
+        })
+        .collect::<String>();
+
+    format!(
+        "  <h3>Requests per hour</h3>
+  <table>
+    <thead>
+      <tr>
+        <th scope=\"col\">Hour</th>
+        <th scope=\"col\">Requests</th>
+        <th scope=\"col\">Errors</th>
+        <th scope=\"col\">Bytes down</th>
+        <th scope=\"col\">Bytes up</th>
+        <th scope=\"col\">p95 duration</th>
+        <th scope=\"col\">Gaps</th>
+      </tr>
+    </thead>
+    <tbody>
+{rows}    </tbody>
+  </table>
+"
+    )
+}
+
+/// Render the hourly buckets as CSV.
+pub fn to_csv(buckets: &[Bucket]) -> String {
+    let mut output =
+        "hour,partial,covered_seconds,requests,errors,bytes_down,bytes_up,p95_duration_ms,gaps\n"
+            .to_owned();
+
+    for bucket in buckets {
+        output.push_str(&format!(
+            "{hour},{partial},{covered},{requests},{errors},{bytes_down},{bytes_up},{p95_duration},{gaps}\n",
+            hour = bucket.start_at.to_rfc3339(),
+            partial = bucket.is_partial(),
+            covered = bucket.covered.num_seconds(),
+            requests = bucket.aggregate.requests,
+            errors = bucket.aggregate.errors,
+            bytes_down = bucket.aggregate.bytes_down,
+            bytes_up = bucket.aggregate.bytes_up,
+            p95_duration = stats::percentile(&bucket.aggregate.durations, 95.)
+                .map(|duration| duration.num_milliseconds().to_string())
+                .unwrap_or_default(),
+            gaps = bucket.gaps,
+        ));
+    }
+
+    output
+}
+
+/// Spans of one endpoint kind starting during one day.
+struct DayKind {
+    kind: endpoint::Kind,
+    aggregate: Aggregate,
+
+    /// Number of requests per hour of the day.
+    sparkline: [usize; 24],
+}
+
+/// Spans starting during one day, grouped by endpoint kind.
+pub struct Day {
+    start_at: DateTime<FixedOffset>,
+
+    /// How much of the day is covered by the log.
+    covered: TimeDelta,
+
+    kinds: Vec<DayKind>,
+}
+
+impl Day {
+    fn is_partial(&self) -> bool {
+        self.covered < TimeDelta::days(1)
+    }
+}
+
+/// Aggregate spans per day and per endpoint kind, from the day including
+/// `start_at` to the day including `end_at`. Days without any span are kept.
+pub fn daily(
+    spans: &Spans,
+    (start_at, end_at): (DateTime<FixedOffset>, DateTime<FixedOffset>),
+    timezone: FixedOffset,
+) -> Vec<Day> {
+    let first_day = truncate_to_day(start_at.with_timezone(&timezone));
+    let last_day = truncate_to_day(end_at.with_timezone(&timezone));
+    let number_of_days = (last_day - first_day).num_days() as usize + 1;
+
+    let mut days = (0..number_of_days)
+        .map(|nth| {
+            let day_start_at = first_day + TimeDelta::days(nth as i64);
+            let day_end_at = day_start_at + TimeDelta::days(1);
+
+            Day {
+                start_at: day_start_at,
+                covered: day_end_at.min(end_at) - day_start_at.max(start_at),
+                kinds: endpoint::Kind::ALL
+                    .into_iter()
+                    .map(|kind| DayKind {
+                        kind,
+                        aggregate: Aggregate::default(),
+                        sparkline: [0; 24],
+                    })
+                    .collect(),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    for span in spans.values().flat_map(|spans| spans.values()) {
+        let since_first_day = span.start_at - first_day;
+        let day = &mut days[(since_first_day.num_days() as usize).min(number_of_days - 1)];
+        let day_kind = &mut day.kinds[kind_index(span.kind())];
+
+        day_kind.aggregate.add(span);
+        day_kind.sparkline[(span.start_at - day.start_at).num_hours().clamp(0, 23) as usize] += 1;
+    }
+
+    for day in &mut days {
+        for day_kind in &mut day.kinds {
+            day_kind.aggregate.finish();
+        }
+    }
+
+    days
+}
+
+/// Render the daily rollup as a table, with one row per day and per endpoint
+/// kind having requests.
+pub fn daily_to_html(days: &[Day]) -> String {
+    let rows = days
+        .iter()
+        .map(|day| {
+            let label = format!(
+                "{date}{coverage}",
+                date = day.start_at.format("%Y-%m-%d %:z"),
+                coverage = if day.is_partial() {
+                    format!(
+                        " <small>(partial, {})</small>",
+                        human::duration(day.covered)
+                    )
+                } else {
+                    String::new()
+                },
+            );
+            let kinds = day
+                .kinds
+                .iter()
+                .filter(|day_kind| day_kind.aggregate.requests > 0)
+                .collect::<Vec<_>>();
+
+            if kinds.is_empty() {
+                return format!(
+                    "      <tr data-partial=\"{partial}\">
+        <td>{label}</td>
+        <td colspan=\"8\"><em>No request</em></td>
+      </tr>
+",
+                    partial = day.is_partial(),
+                );
+            }
+
+            kinds
+                .into_iter()
+                .map(|day_kind| {
+                    let aggregate = &day_kind.aggregate;
+                    let sparkline = day_kind
+                        .sparkline
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join(",");
+
+                    format!(
+                        "      <tr data-partial=\"{partial}\">
+        <td>{label}</td>
+        <td>{kind}</td>
+        <td>{requests}</td>
+        <td>{errors}</td>
+        <td>{error_rate}</td>
+        <td>{bytes_down}</td>
+        <td>{bytes_up}</td>
+        <td>{p95_duration}</td>
+        <td data-sparkline=\"{sparkline}\">{sparkline_svg}</td>
+      </tr>
+",
+                        partial = day.is_partial(),
+                        kind = day_kind.kind.as_str(),
+                        requests = human::count(aggregate.requests),
+                        errors = human::count(aggregate.errors),
+                        error_rate =
+                            human::percentage(aggregate.errors as f64, aggregate.requests as f64),
+                        bytes_down = human::bytes_to_html(aggregate.bytes_down),
+                        bytes_up = human::bytes_to_html(aggregate.bytes_up),
+                        p95_duration = aggregate.p95_duration(),
+                        sparkline_svg = sparkline_to_svg(&day_kind.sparkline),
+                    )
+                })
+                .collect::<String>()
+        })
+        .collect::<String>();
+
+    format!(
+        "  <h3>Requests per day</h3>
+  <table>
+    <thead>
+      <tr>
+        <th scope=\"col\">Day</th>
+        <th scope=\"col\">Kind</th>
+        <th scope=\"col\">Requests</th>
+        <th scope=\"col\">Errors</th>
+        <th scope=\"col\">Error rate</th>
+        <th scope=\"col\">Bytes down</th>
+        <th scope=\"col\">Bytes up</th>
+        <th scope=\"col\">p95 duration</th>
+        <th scope=\"col\">Requests per hour</th>
+      </tr>
+    </thead>
+    <tbody>
+{rows}    </tbody>
+  </table>
+"
+    )
+}
+
+/// Draw the number of requests per hour as a polyline.
+fn sparkline_to_svg(sparkline: &[usize]) -> String {
+    let max = sparkline.iter().copied().max().unwrap_or_default().max(1);
+    let points = sparkline
+        .iter()
+        .enumerate()
+        .map(|(nth, value)| format!("{nth},{}", 10 - value * 10 / max))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    format!(
+        "<svg class=\"sparkline\" viewBox=\"0 0 {width} 10\" preserveAspectRatio=\"none\"><polyline points=\"{points}\" /></svg>",
+        width = sparkline.len() - 1,
+    )
+}
+
+fn truncate_to_hour(date_time: DateTime<FixedOffset>) -> DateTime<FixedOffset> {
+    date_time
+        .with_nanosecond(0)
+        .and_then(|date_time| date_time.with_second(0))
+        .and_then(|date_time| date_time.with_minute(0))
+        .expect("Truncating to the hour always exists with a fixed offset")
+}
+
+pub fn truncate_to_day(date_time: DateTime<FixedOffset>) -> DateTime<FixedOffset> {
+    date_time
+        .date_naive()
+        .and_time(NaiveTime::MIN)
+        .and_local_timezone(date_time.timezone())
+        .single()
+        .expect("Truncating to the day always exists with a fixed offset")
+}
@@ -0,0 +1,144 @@
+//! Capture the logs of an app in-process, for the apps embedding the SDK:
+//! the events are written to a [`Capture`] rather than to a log file, and the
+//! report is rendered on demand, e.g. from a debug menu.
+//!
+//! A [`Capture`] is a writer, so that it can be installed as the writer of a
+//! `tracing-subscriber` layer without this crate depending on `tracing`. The
+//! JSON format is recommended: it keeps the fields of the spans structured,
+//! see [`crate::json_format`].
+//!
+//! ```ignore
+//! let capture = network_viewer::Capture::new();
+//!
+//! tracing_subscriber::registry()
+//!     .with(
+//!         tracing_subscriber::fmt::layer()
+//!             .json()
+//!             .with_span_list(true)
+//!             .with_writer({
+//!                 let capture = capture.clone();
+//!                 move || capture.clone()
+//!             })
+//!             .with_filter(EnvFilter::new("matrix_sdk::http_client=debug")),
+//!     )
+//!     .init();
+//!
+//! // Later, on demand.
+//! std::fs::write("report.html", capture.render_html("app"))?;
+//! ```
+
+use std::{
+    io,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use crate::{LogParser, ParseSummary, Session};
+
+/// The events written so far, parsed as they are written. A clone writes to
+/// the same capture.
+#[derive(Clone, Default)]
+pub struct Capture {
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Default)]
+struct State {
+    log_parser: LogParser,
+    /// The beginning of a line whose end hasn't been written yet.
+    partial_line: Vec<u8>,
+}
+
+impl Capture {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count the lines and the spans captured so far.
+    pub fn summary(&self) -> ParseSummary {
+        self.lock().log_parser.summary()
+    }
+
+    /// Get the session captured so far, named `name`, e.g. to render it with
+    /// [`crate::render_html`].
+    pub fn session(&self, name: &str) -> Session {
+        self.lock().log_parser.snapshot(name)
+    }
+
+    /// Render the HTML report of the spans captured so far.
+    pub fn render_html(&self, name: &str) -> String {
+        crate::render_html(&self.session(name))
+    }
+
+    /// Lock the state, even if a writer has panicked while holding it: the
+    /// parser is never left inconsistent in between two lines.
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl io::Write for Capture {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        let mut state = self.lock();
+        let State {
+            log_parser,
+            partial_line,
+        } = &mut *state;
+
+        partial_line.extend_from_slice(bytes);
+
+        let Some(end) = partial_line.iter().rposition(|byte| *byte == b'\n') else {
+            return Ok(bytes.len());
+        };
+
+        for line in partial_line[..end].split(|byte| *byte == b'\n') {
+            log_parser.parser.parse(crate::source::decode(line), None);
+        }
+
+        partial_line.drain(..=end);
+
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    #[test]
+    fn test_write() {
+        let capture = Capture::new();
+        let mut writer = capture.clone();
+
+        writer
+            .write_all(br#"{"timestamp":"2024-06-01T10:00:00Z","level":"DEBUG","fields":{"message":"Sending request"},"target":"matrix_sdk::http_client","span":{"method":"GET","name":"send","request_id":"REQ-1","uri":"https://example.org/_matrix/client/versions"}}"#)
+            .unwrap();
+        writer.write_all(b"\n").unwrap();
+
+        assert_eq!(capture.summary().number_of_pending_spans, 1);
+
+        // A line written in several parts is parsed once complete.
+        writer.write_all(br#"{"timestamp":"2024-06-01T10:00:01Z","level":"DEBUG","fields":{"message":"Got response"},"target":"matrix_sdk::http_client","#).unwrap();
+        writer.write_all(br#""span":{"method":"GET","name":"send","request_id":"REQ-1","uri":"https://example.org/_matrix/client/versions","status":200}}"#).unwrap();
+
+        assert_eq!(capture.summary().number_of_pending_spans, 1);
+
+        writer.write_all(b"\n").unwrap();
+
+        let session = capture.session("app");
+
+        assert_eq!(capture.summary().number_of_pending_spans, 0);
+        assert_eq!(
+            session.spans[crate::NO_CONNECTION_ID][&1].status(),
+            Some(200)
+        );
+        assert!(capture.render_html("app").contains("<code>app</code>"));
+    }
+}
@@ -0,0 +1,162 @@
+//! Gate a run on the network behaviour, e.g. in the integration tests of the
+//! SDK: `network-viewer check --max-error-rate 1% --max-p95-ms 2000 <log_path>`
+//! parses the log, evaluates the budgets, and fails if one is exceeded.
+//!
+//! The error rate is the ratio of the requests answered without a 2xx status.
+//! The p95 is of the answered requests except the syncs, which long-poll for
+//! up to their `timeout` whatever the network. The unfinished requests are
+//! the ones without a response.
+
+use chrono::TimeDelta;
+
+use crate::{Spans, buckets::Aggregate, human};
+
+/// The budgets of `check`.
+#[derive(Clone, Debug, Default)]
+pub struct Budgets {
+    /// Between 0 and 1.
+    pub max_error_rate: Option<f64>,
+    pub max_p95: Option<TimeDelta>,
+    pub max_unfinished: Option<usize>,
+}
+
+/// The outcome of the budgets.
+#[derive(Debug, Default, PartialEq)]
+pub struct Evaluation {
+    /// A line per budget, for the summary.
+    pub summary: String,
+    /// The exceeded budgets.
+    pub violations: Vec<String>,
+}
+
+impl Budgets {
+    pub fn is_empty(&self) -> bool {
+        self.max_error_rate.is_none() && self.max_p95.is_none() && self.max_unfinished.is_none()
+    }
+
+    /// Parse an error rate, like `5%` or `0.05`.
+    pub fn parse_error_rate(value: &str) -> Option<f64> {
+        let rate = match value.trim().strip_suffix('%') {
+            Some(percentage) => percentage.trim().parse::<f64>().ok()? / 100.,
+            None => value.trim().parse().ok()?,
+        };
+
+        (0. ..=1.).contains(&rate).then_some(rate)
+    }
+
+    /// Evaluate the budgets on some spans.
+    pub fn evaluate(&self, spans: &Spans) -> Evaluation {
+        let mut all = Aggregate::default();
+        let mut answered = Aggregate::default();
+        let mut number_of_unfinished_spans = 0;
+
+        for span in spans.values().flat_map(|spans| spans.values()) {
+            all.add(span);
+
+            if span.is_pending() {
+                number_of_unfinished_spans += 1;
+            } else if !span.is_sync() {
+                answered.add(span);
+            }
+        }
+
+        answered.finish();
+
+        let mut evaluation = Evaluation::default();
+        let mut evaluate = |flag: &str, name: &str, value: String, budget: String, is_exceeded| {
+            evaluation
+                .summary
+                .push_str(&format!("{name}: {value} (budget: {budget})\n"));
+
+            if is_exceeded {
+                evaluation.violations.push(format!(
+                    "{name}: {value}, above the budget of `{flag}` ({budget})"
+                ));
+            }
+        };
+
+        if let Some(max_error_rate) = self.max_error_rate {
+            let error_rate = if all.requests == 0 {
+                0.
+            } else {
+                all.errors as f64 / all.requests as f64
+            };
+
+            evaluate(
+                "--max-error-rate",
+                "Error rate",
+                human::percentage(all.errors as f64, all.requests as f64),
+                human::percentage(max_error_rate, 1.),
+                error_rate > max_error_rate,
+            );
+        }
+
+        if let Some(max_p95) = self.max_p95 {
+            let p95 = answered.percentile_duration(95.);
+
+            evaluate(
+                "--max-p95-ms",
+                "p95 of the answered requests",
+                p95.map(human::duration)
+                    .unwrap_or_else(|| "none".to_owned()),
+                human::duration(max_p95),
+                p95.is_some_and(|p95| p95 > max_p95),
+            );
+        }
+
+        if let Some(max_unfinished) = self.max_unfinished {
+            evaluate(
+                "--max-unfinished",
+                "Number of unfinished requests",
+                human::count(number_of_unfinished_spans),
+                human::count(max_unfinished),
+                number_of_unfinished_spans > max_unfinished,
+            );
+        }
+
+        evaluation
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SpansBuilder;
+
+    #[test]
+    fn test_evaluate() {
+        assert_eq!(Budgets::parse_error_rate("5%"), Some(0.05));
+        assert_eq!(Budgets::parse_error_rate("0.1"), Some(0.1));
+        assert_eq!(Budgets::parse_error_rate("150%"), None);
+
+        let sync = "https://example.org/_matrix/client/v3/sync";
+        let keys_query = "https://example.org/_matrix/client/v3/keys/query";
+        let mut spans = SpansBuilder::default();
+        spans.span("room-list", 1, sync, Some(200), 0, 30_000);
+        spans.span("room-list", 2, keys_query, Some(200), 0, 300);
+        spans.span("room-list", 3, keys_query, Some(502), 0, 100);
+        spans.span("room-list", 4, keys_query, None, 0, 0);
+        let spans = spans.build();
+
+        let budgets = Budgets {
+            max_error_rate: Some(0.5),
+            max_p95: Some(TimeDelta::milliseconds(200)),
+            max_unfinished: Some(0),
+        };
+        let evaluation = budgets.evaluate(&spans);
+
+        assert_eq!(
+            evaluation.summary,
+            "Error rate: 25.00% (budget: 50.00%)\n\
+            p95 of the answered requests: 300ms (budget: 200ms)\n\
+            Number of unfinished requests: 1 (budget: 0)\n"
+        );
+        assert_eq!(
+            evaluation.violations,
+            [
+                "p95 of the answered requests: 300ms, above the budget of `--max-p95-ms` (200ms)",
+                "Number of unfinished requests: 1, above the budget of `--max-unfinished` (0)",
+            ]
+        );
+    }
+}
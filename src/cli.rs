@@ -0,0 +1,3611 @@
+//! Command line interface of the `network-viewer` binary: parse the arguments,
+//! read the logs from their source, and write the reports.
+
+use chrono::{DateTime, FixedOffset, Local, Offset, SecondsFormat, TimeDelta, Utc};
+use regex::Regex;
+use std::{
+    cmp::Reverse,
+    collections::BTreeMap,
+    env, fs,
+    io::{self, IsTerminal, Write},
+    mem,
+    path::Path,
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    ConnectionId, RequestId, Span, Spans, anomalies, bandwidth, buckets, check, cohort,
+    columns::{self, Column},
+    concurrency, conditions, config,
+    connections::ConnectionOrder,
+    context, csv, dataset, diff, duration, duration_bands, endpoint_stats, errcodes,
+    error::Error,
+    expression, filters,
+    format::{self, Format, Output},
+    gaps, grafana, har, html, human, import, influx, initial_sync, intermediary, interrupt,
+    iterations, json, lifecycle, listen, media, merge,
+    meta::{self, Filter, Meta, SourceFile},
+    otlp, parquet,
+    parser::{Batch, Parser},
+    percentiles::{self, Percentiles},
+    progress::Progress,
+    rate_limits, redact, rooms, serve, slow,
+    source::{self, Source},
+    split, statsd, status, status_matrix, stream, summary, sync_overhead,
+    template::{self, Assets, Template, Theme},
+    term, ticks, timestamp, traffic, tui, warnings, watch, xlsx, zoom,
+};
+
+/// Path of the standard input as a log path, and of the standard output as an
+/// output path.
+pub(crate) const STDIO: &str = "-";
+
+/// Default period after which the report is regenerated in live mode.
+const DEFAULT_LIVE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Default number of completed spans after which the report is regenerated in
+/// live mode.
+const DEFAULT_LIVE_SPANS: usize = 100;
+
+/// Default period after which a followed log file is checked for new lines.
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Render the usage of the binary, printed by `--help`.
+fn usage(this_bin: &str) -> String {
+    format!(
+        "\
+Render the HTTP requests of the logs of the Matrix Rust SDK as a waterfall.
+
+Usage:
+  {this_bin} [options] <log_path>... <output_path>
+  {this_bin} cohort [options] <directory> <output_path>
+  {this_bin} diff [options] <log_a> <log_b> <output_path>
+  {this_bin} serve [options] <log_path>...
+  {this_bin} check [options] <log_path>...
+
+A `<log_path>` is a file, possibly gzipped or compressed with zstd, a
+directory of `*.log` files, or `-` for the standard input. Several log
+paths are merged on one timeline, with a `source` column. An
+`<output_path>` of `-` is the standard output. The comparison of `diff` is
+an HTML report, or JSON with a `.json` output. `serve` serves the report
+over HTTP on the loopback interface, with `/api/spans` and `/api/summary`.
+`check` writes no report, but fails if a budget is exceeded.
+
+Output:
+  -o, --output <path>               Write a report to <path>; can be repeated
+  --format <format>                 `html`, `csv`, `xlsx`, `parquet`, `grafana`,
+                                    `influx`, `json`, `har`, `otlp` or `term`
+  --title <title>                   Title of the report
+  --template <path>                 HTML file, or directory of `index.html`,
+                                    `style.css` and `script.js`
+  --theme <theme>                   `dark`, `light`, or `auto` to follow the
+                                    color scheme of the browser
+  --assets <mode>                   `inline`, or `external` to write `style.css`,
+                                    `app.js` and `data.json` next to the HTML
+                                    report, to be served over HTTP
+  --columns <names>                 Columns of the table and of the CSV export,
+                                    in order, like `connection,status,duration`;
+                                    `concurrency` is not in the CSV export
+  --force-columns                   Keep the columns with no value
+  --virtual-table                   Render only the visible rows of the table
+  --split-by day                    Write a report per day
+  --timezone <offset>               `utc`, `local` or an offset like `+02:00`;
+                                    `local` is the current offset, for all the
+                                    dates, even across a change of daylight
+                                    saving time
+  --tui                             Browse the requests and the endpoints in the
+                                    terminal, instead of writing a report
+  --stats-out <path>                Also write the summary of the run as JSON
+
+Selection:
+  --from <bound>, --to <bound>      Keep the requests in a range, of RFC 3339
+                                    datetimes, or of offsets from the start or
+                                    the end of the log like `+5m` or `-30m`
+  --last <duration>                 Keep the last <duration>, like `30m`
+  --conn-id <id>                    Keep a connection; can be repeated
+  --room <id>                       Keep the requests of a room, like
+                                    `!abc:example.org`; can be repeated
+  --method <method>                 Keep a method; can be repeated
+  --status <status>                 Keep a status like `429`, or a family like
+                                    `4xx`; can be repeated
+  --include-uri <regex>             Keep the requests whose URI matches
+  --exclude-uri <regex>             Drop the requests whose URI matches
+  --hide <expression>               Hide the spans, like `status-family=2`
+  --every <n>                       Keep a span every <n> spans
+
+Layout:
+  --sort <order>                    `start`, `duration`, `size` or `connection`
+  --order chrono                    Same as `--sort start`
+  --connection-order <ids>          Connections first, like `room-list,encryption`
+  --origin <origin>                 `global` or `per-connection`
+  --group-by <period>               Group the spans per `hour`, or per `room`
+  --rollup day                      Summarize the spans per day
+  --bucket <duration>               Bandwidth per bucket, like `1s`
+  --timeline-resolution <duration>  Finest zoom of the timeline, like `10s`
+  --merge-connections               Merge the connections of restarted processes
+  --no-merge-connections            Don't merge them (default)
+  --merge-window <duration>         Window of a restart, like `30s`
+
+Analysis:
+  --with-context <n>                Keep <n> lines of the log around the spans
+  --warning-targets <targets>       Targets of the warnings, like `matrix_sdk*`
+  --warnings-per-span <n>           Maximum number of warnings per span
+  --capture-bodies                  Keep the bodies of the requests and of the
+                                    responses, if logged at the trace level
+  --lifecycle-pattern <pattern>     Lifecycle event, like `background=onPause`
+  --config <path>                   TOML file of patterns of other request and
+                                    response lines, like `patterns.toml`
+  --duration-thresholds <list>      Duration bands, like `500ms,2s,10s`
+  --slow-threshold <duration>       Highlight the slower requests, like `2s`, or
+                                    `keys_query=1s` per category; can be repeated
+  --latency-heatmap                 Color the spans by the percentile of their
+                                    duration among those of their endpoint
+  --stuck-sync-run-length <n>       Number of syncs of a stuck sync loop
+  --payload-size-threshold <mads>   Outliers of the payload sizes, like `5`
+  --gap-threshold <duration>        Idle gaps between the syncs, like `5s`;
+                                    alias `--max-sync-gap`
+
+Privacy:
+  --no-redact                       Keep the tokens and the identifiers
+  --redact                          Also replace the server names and the room,
+                                    user and event IDs by pseudonyms
+  --redact-param <name>             Also redact a query parameter; can be repeated
+
+Sources:
+  --stdin                           Read the log from the standard input
+  --threads <n>                     Threads matching the lines, by default the
+                                    number of cores
+  --timestamp-format <format>       Format of the datetimes of the lines, like
+                                    `%d/%m/%Y %H:%M:%S%.f`, if not RFC 3339
+  --listen <address>                Read the logs sent to a TCP address
+  --idle-timeout <duration>         Stop listening once idle, like `30s`
+  --follow                          Follow a log file as it grows, or `-`
+  --watch <directory>               Regenerate the report whenever a log file of
+                                    the directory is added or updated
+  --watch-glob <glob>               Log files of `--watch`, like `console*.log`
+  --poll-interval <duration>        Period of the checks of `--follow` and
+                                    `--watch`
+  --live                            Regenerate the report while reading
+  --live-interval <duration>        Period of the regeneration, like `5s`
+  --live-spans <n>                  Or every <n> completed spans
+  --stream                          Write the CSV rows while reading, in bounded
+                                    memory, for the very large logs
+  --statsd <address>                Send the metrics to a StatsD server
+  --otlp-endpoint <url>             Push the spans as OpenTelemetry traces, to
+                                    a collector like `http://localhost:4318`;
+                                    the other outputs are then given with `-o`
+
+Diagnostics:
+  --verbose                         Print the conditions of the logs
+  --explain                         Explain why the lines don't match
+  --quiet                           Hide the progress of the parsing of the files
+  --strict                          Fail on any condition of the logs
+  --strict-except <conditions>      Except these, like `unterminated-spans`
+  --unterminated-threshold <duration>
+                                    Age of an unterminated span, like `2m`
+
+Budgets, with `check`:
+  --max-error-rate <rate>           Ratio of failed requests, like `1%`
+  --max-p95-ms <ms>                 p95 of the answered requests, except the
+                                    syncs, in milliseconds
+  --max-unfinished <n>              Number of requests without a response
+
+  -h, --help                        Print this help
+  -V, --version                     Print the version
+
+Exit codes:
+  0                                 Success
+  1                                 Usage error
+  2                                 Parse failure: an export or a template
+                                    which can't be used, or a condition of the
+                                    logs with `--strict`
+  3                                 A file or a stream can't be read or written
+  4                                 A budget of `check` is exceeded"
+    )
+}
+
+/// The subcommands, the first positional argument.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Subcommand {
+    /// Compare the logs of a directory.
+    Cohort,
+    /// Compare 2 logs.
+    Diff,
+    /// Serve the report over HTTP.
+    Serve,
+    /// Evaluate the budgets.
+    Check,
+}
+
+impl Subcommand {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "cohort" => Self::Cohort,
+            "diff" => Self::Diff,
+            "serve" => Self::Serve,
+            "check" => Self::Check,
+            _ => return None,
+        })
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Cohort => "cohort",
+            Self::Diff => "diff",
+            Self::Serve => "serve",
+            Self::Check => "check",
+        }
+    }
+}
+
+/// The arguments of the binary, as parsed by [`parse_args`], before they are
+/// checked against each other by [`validate`].
+struct Args {
+    this_bin: String,
+    help: bool,
+    version: bool,
+    subcommand: Option<Subcommand>,
+    /// The log paths, and the output path without any `-o`.
+    positionals: Vec<String>,
+    anomalies_config: anomalies::Config,
+    timezone: FixedOffset,
+    /// The format of all the outputs, instead of the one of their extension.
+    format: Option<Format>,
+    /// The paths of `-o`; the last positional argument otherwise.
+    output_paths: Vec<String>,
+    group_by: Option<GroupBy>,
+    rollup: Option<Rollup>,
+    order: Option<Order>,
+    connection_order: ConnectionOrder,
+    columns: Vec<Column>,
+    force_columns: bool,
+    origin: Option<Origin>,
+    split_by: Option<SplitBy>,
+    title: Option<String>,
+    /// The path of `--template`, loaded once the arguments are valid.
+    template: Option<String>,
+    assets: Assets,
+    theme: Theme,
+    with_context: usize,
+    warning_targets: Vec<warnings::Target>,
+    warnings_per_span: usize,
+    /// The number of threads matching the records, all the cores if `None`.
+    threads: Option<usize>,
+    lifecycle_patterns: Vec<lifecycle::Pattern>,
+    /// The paths of `--config`, loaded by [`run`].
+    configs: Vec<String>,
+    timestamp_format: Option<timestamp::Format>,
+    duration_thresholds: duration_bands::Thresholds,
+    slow_thresholds: slow::Thresholds,
+    latency_heatmap: bool,
+    hide: Option<expression::Expression>,
+    merge_connections: bool,
+    /// `None` with `--no-redact`.
+    redaction: Option<redact::Redaction>,
+    redact_parameters: Vec<String>,
+    /// Whether the IDs are pseudonymized, with `--redact`.
+    pseudonymizes: bool,
+    verbose: bool,
+    explain: bool,
+    capture_bodies: bool,
+    quiet: bool,
+    strict: bool,
+    strict_except: Vec<conditions::Condition>,
+    unterminated_threshold: TimeDelta,
+    gap_threshold: TimeDelta,
+    timeline_resolution: TimeDelta,
+    bucket: Option<TimeDelta>,
+    /// Window of `--merge-connections`.
+    merge_window: TimeDelta,
+    last: Option<(String, TimeDelta)>,
+    selection: filters::Selection,
+    every: Option<usize>,
+    virtual_table: bool,
+    statsd: Option<String>,
+    /// The URL the traces are pushed to.
+    otlp_endpoint: Option<String>,
+    /// The address the logs are received on, instead of being read from files.
+    listen: Option<String>,
+    idle_timeout: Duration,
+    stdin: bool,
+    live: bool,
+    /// The port of `serve`.
+    port: Option<u16>,
+    stream: bool,
+    live_interval: Duration,
+    live_spans: usize,
+    follow: bool,
+    /// The directory of `--watch`, whose log files are read on every change.
+    watch_directory: Option<String>,
+    watch_glob: Option<String>,
+    poll_interval: Duration,
+    tui: bool,
+    stats_out: Option<String>,
+    budgets: check::Budgets,
+}
+
+impl Args {
+    /// The arguments without any flag.
+    fn new(this_bin: String) -> Self {
+        Self {
+            this_bin,
+            help: false,
+            version: false,
+            subcommand: None,
+            positionals: Vec::new(),
+            anomalies_config: anomalies::Config::default(),
+            timezone: FixedOffset::east_opt(0).expect("UTC is a valid offset"),
+            format: None,
+            output_paths: Vec::new(),
+            group_by: None,
+            rollup: None,
+            order: None,
+            connection_order: ConnectionOrder::default(),
+            columns: Column::ALL.to_vec(),
+            force_columns: false,
+            origin: None,
+            split_by: None,
+            title: None,
+            template: None,
+            assets: Assets::Inline,
+            theme: Theme::Dark,
+            with_context: 0,
+            warning_targets: warnings::Target::defaults(),
+            warnings_per_span: warnings::DEFAULT_PER_SPAN,
+            threads: None,
+            lifecycle_patterns: Vec::new(),
+            configs: Vec::new(),
+            timestamp_format: None,
+            duration_thresholds: duration_bands::Thresholds::default(),
+            slow_thresholds: slow::Thresholds::default(),
+            latency_heatmap: false,
+            hide: None,
+            merge_connections: false,
+            redaction: Some(redact::Redaction::default()),
+            redact_parameters: Vec::new(),
+            pseudonymizes: false,
+            verbose: false,
+            explain: false,
+            capture_bodies: false,
+            quiet: false,
+            strict: false,
+            strict_except: Vec::new(),
+            unterminated_threshold: conditions::DEFAULT_UNTERMINATED_THRESHOLD,
+            gap_threshold: gaps::DEFAULT_THRESHOLD,
+            timeline_resolution: zoom::DEFAULT_RESOLUTION,
+            bucket: None,
+            merge_window: merge::DEFAULT_WINDOW,
+            last: None,
+            selection: filters::Selection::default(),
+            every: None,
+            virtual_table: false,
+            statsd: None,
+            otlp_endpoint: None,
+            listen: None,
+            idle_timeout: listen::DEFAULT_IDLE_TIMEOUT,
+            stdin: false,
+            live: false,
+            port: None,
+            stream: false,
+            live_interval: DEFAULT_LIVE_INTERVAL,
+            live_spans: DEFAULT_LIVE_SPANS,
+            follow: false,
+            watch_directory: None,
+            watch_glob: None,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            tui: false,
+            stats_out: None,
+            budgets: check::Budgets::default(),
+        }
+    }
+}
+
+/// Parse the arguments, the name of the binary first. Only the values of the
+/// flags are checked; see [`validate`] for the flags conflicting with each
+/// other.
+fn parse_args(arguments: impl IntoIterator<Item = String>) -> Result<Args, Error> {
+    let mut arguments = arguments.into_iter();
+    let mut args = Args::new(arguments.next().expect("<bin-name> is unknown, really?"));
+
+    while let Some(arg) = arguments.next() {
+        match arg.as_str() {
+            // The arguments after them are ignored.
+            "-h" | "--help" => {
+                args.help = true;
+
+                return Ok(args);
+            }
+
+            "-V" | "--version" => {
+                args.version = true;
+
+                return Ok(args);
+            }
+
+            "--stuck-sync-run-length" => {
+                let Some(run_length) = arguments.next().and_then(|value| value.parse().ok()) else {
+                    return Err(Error::Usage(
+                        "`--stuck-sync-run-length` expects a number of syncs".to_owned(),
+                    ));
+                };
+
+                args.anomalies_config.stuck_sync_run_length = run_length;
+            }
+
+            "--payload-size-threshold" => {
+                let Some(value) = arguments.next() else {
+                    return Err(Error::Usage("`--payload-size-threshold` expects a number of MADs like `5`, or like `media=20` for an endpoint kind".to_owned()));
+                };
+
+                args.anomalies_config
+                    .payload_size_thresholds
+                    .parse(&value)
+                    .map_err(|error| {
+                        Error::Usage(format!("`--payload-size-threshold`: {error}"))
+                    })?;
+            }
+
+            "--timezone" => {
+                let Some(offset) = arguments.next().and_then(|value| match value.as_str() {
+                    "utc" | "UTC" | "Z" => FixedOffset::east_opt(0),
+                    // The current offset, for all the dates: the offset of
+                    // a log across a change of daylight saving time differs
+                    // for a part of its dates, see `--help`.
+                    "local" => Some(Local::now().offset().fix()),
+                    offset => offset.parse().ok(),
+                }) else {
+                    return Err(Error::Usage(
+                        "`--timezone` expects `utc`, `local` or an offset like `+02:00`".to_owned(),
+                    ));
+                };
+
+                args.timezone = offset;
+            }
+
+            "--format" => {
+                let Some(value) = arguments.next().as_deref().and_then(Format::parse) else {
+                    return Err(Error::Usage("`--format` expects `html`, `csv`, `xlsx`, `parquet`, `grafana`, `influx`, `json`, `har`, `otlp` or `term`".to_owned()));
+                };
+
+                args.format = Some(value);
+            }
+
+            "-o" | "--output" => {
+                let Some(output_path) = arguments.next() else {
+                    return Err(Error::Usage(format!("`{arg}` expects an output path")));
+                };
+
+                args.output_paths.push(output_path);
+            }
+
+            "--group-by" => {
+                args.group_by = match arguments.next().as_deref() {
+                    Some("hour") => Some(GroupBy::Hour),
+                    Some("room") => Some(GroupBy::Room),
+                    _ => {
+                        return Err(Error::Usage(
+                            "`--group-by` expects `hour` or `room`".to_owned(),
+                        ));
+                    }
+                };
+            }
+
+            "--rollup" => {
+                args.rollup = match arguments.next().as_deref() {
+                    Some("day") => Some(Rollup::Day),
+                    _ => return Err(Error::Usage("`--rollup` expects `day`".to_owned())),
+                };
+            }
+
+            "--columns" => {
+                let Some(names) = arguments.next() else {
+                    return Err(Error::Usage(
+                        "`--columns` expects column names like `connection,request,status`"
+                            .to_owned(),
+                    ));
+                };
+
+                args.columns = Column::parse_list(&names).map_err(Error::Usage)?;
+            }
+
+            "--force-columns" => args.force_columns = true,
+
+            "--with-context" => {
+                let Some(number_of_lines) = arguments
+                    .next()
+                    .and_then(|value| value.parse::<usize>().ok())
+                    .filter(|number_of_lines| *number_of_lines > 0)
+                else {
+                    return Err(Error::Usage(
+                        "`--with-context` expects a positive number of lines".to_owned(),
+                    ));
+                };
+
+                args.with_context = number_of_lines;
+            }
+
+            "--warning-targets" => {
+                let Some(value) = arguments.next() else {
+                    return Err(Error::Usage(
+                        "`--warning-targets` expects targets like `matrix_sdk*,my_app::sync`"
+                            .to_owned(),
+                    ));
+                };
+
+                args.warning_targets = warnings::Target::parse_list(&value);
+            }
+
+            "--warnings-per-span" => {
+                let Some(number_of_lines) = arguments.next().and_then(|value| value.parse().ok())
+                else {
+                    return Err(Error::Usage(
+                        "`--warnings-per-span` expects a number of lines".to_owned(),
+                    ));
+                };
+
+                args.warnings_per_span = number_of_lines;
+            }
+
+            "--threads" => {
+                let Some(number_of_threads) = arguments
+                    .next()
+                    .and_then(|value| value.parse().ok())
+                    .filter(|number_of_threads| *number_of_threads > 0)
+                else {
+                    return Err(Error::Usage(
+                        "`--threads` expects a number of threads".to_owned(),
+                    ));
+                };
+
+                args.threads = Some(number_of_threads);
+            }
+
+            "--lifecycle-pattern" => {
+                let Some(value) = arguments.next() else {
+                    return Err(Error::Usage(
+                        "`--lifecycle-pattern` expects a pattern like `background=onPause`"
+                            .to_owned(),
+                    ));
+                };
+
+                args.lifecycle_patterns
+                    .push(lifecycle::Pattern::parse(&value).map_err(|error| {
+                        Error::Usage(format!("`--lifecycle-pattern`: {error}"))
+                    })?);
+            }
+
+            "--config" => {
+                let Some(path) = arguments.next() else {
+                    return Err(Error::Usage(
+                        "`--config` expects the path of a TOML file of patterns".to_owned(),
+                    ));
+                };
+                args.configs.push(path);
+            }
+
+            "--duration-thresholds" => {
+                let Some(value) = arguments.next() else {
+                    return Err(Error::Usage("`--duration-thresholds` expects durations like `500ms,2s,10s`, or like `sync=5s,35s,60s` for an endpoint kind".to_owned()));
+                };
+
+                args.duration_thresholds
+                    .parse(&value)
+                    .map_err(|error| Error::Usage(format!("`--duration-thresholds`: {error}")))?;
+            }
+
+            "--slow-threshold" => {
+                let Some(value) = arguments.next() else {
+                    return Err(Error::Usage("`--slow-threshold` expects a duration like `2s`, or like `keys_query=1s` for a category".to_owned()));
+                };
+
+                args.slow_thresholds
+                    .parse(&value)
+                    .map_err(|error| Error::Usage(format!("`--slow-threshold`: {error}")))?;
+            }
+
+            "--latency-heatmap" => args.latency_heatmap = true,
+
+            "--hide" => {
+                let Some(value) = arguments.next() else {
+                    return Err(Error::Usage(
+                        "`--hide` expects an expression like `status-family=2 && duration<300ms`"
+                            .to_owned(),
+                    ));
+                };
+
+                args.hide = Some(
+                    expression::Expression::parse(&value)
+                        .map_err(|error| Error::Usage(format!("`--hide`: {error}")))?,
+                );
+            }
+
+            "--merge-connections" => args.merge_connections = true,
+
+            "--no-merge-connections" => args.merge_connections = false,
+
+            "--no-redact" => args.redaction = None,
+
+            "--redact" => args.pseudonymizes = true,
+
+            "--redact-param" => {
+                let Some(name) = arguments.next() else {
+                    return Err(Error::Usage(
+                        "`--redact-param` expects the name of a query parameter like `from`"
+                            .to_owned(),
+                    ));
+                };
+
+                args.redact_parameters.push(name);
+            }
+
+            "--merge-window" => {
+                let Some(window) = arguments.next().and_then(|value| duration::parse(&value))
+                else {
+                    return Err(Error::Usage(
+                        "`--merge-window` expects a duration like `30s`".to_owned(),
+                    ));
+                };
+
+                args.merge_window = window;
+            }
+
+            "--timestamp-format" => {
+                let Some(format) = arguments.next() else {
+                    return Err(Error::Usage(
+                        "`--timestamp-format` expects a format like `%d/%m/%Y %H:%M:%S`".to_owned(),
+                    ));
+                };
+
+                args.timestamp_format = Some(
+                    timestamp::Format::new(&format)
+                        .map_err(|error| Error::Usage(format!("`--timestamp-format`: {error}")))?,
+                );
+            }
+
+            "--title" => {
+                let Some(value) = arguments.next() else {
+                    return Err(Error::Usage("`--title` expects a title".to_owned()));
+                };
+
+                args.title = Some(value);
+            }
+
+            "--template" => {
+                let Some(value) = arguments.next() else {
+                    return Err(Error::Usage("`--template` expects an HTML file, or a directory holding `index.html`, `style.css` and `script.js`".to_owned()));
+                };
+
+                args.template = Some(value);
+            }
+
+            "--assets" => {
+                let Some(value) = arguments.next().and_then(|value| Assets::parse(&value)) else {
+                    return Err(Error::Usage(
+                        "`--assets` expects `inline` or `external`".to_owned(),
+                    ));
+                };
+
+                args.assets = value;
+            }
+
+            "--theme" => {
+                let Some(value) = arguments.next().and_then(|value| Theme::parse(&value)) else {
+                    return Err(Error::Usage(
+                        "`--theme` expects `dark`, `light` or `auto`".to_owned(),
+                    ));
+                };
+
+                args.theme = value;
+            }
+
+            "--split-by" => {
+                args.split_by = match arguments.next().as_deref() {
+                    Some("day") => Some(SplitBy::Day),
+                    _ => return Err(Error::Usage("`--split-by` expects `day`".to_owned())),
+                };
+            }
+
+            "--origin" => {
+                args.origin = match arguments.next().as_deref() {
+                    Some("global") => None,
+                    Some("per-connection") => Some(Origin::PerConnection),
+                    _ => {
+                        return Err(Error::Usage(
+                            "`--origin` expects `global` or `per-connection`".to_owned(),
+                        ));
+                    }
+                };
+            }
+
+            "--connection-order" => {
+                let Some(pinned) = arguments.next() else {
+                    return Err(Error::Usage(
+                        "`--connection-order` expects connection IDs like `room-list,encryption`"
+                            .to_owned(),
+                    ));
+                };
+
+                args.connection_order.pinned = pinned.split(',').map(ToOwned::to_owned).collect();
+            }
+
+            "--sort" => {
+                args.order = match arguments.next().as_deref() {
+                    Some("start") => Some(Order::Start),
+                    Some("duration") => Some(Order::Duration),
+                    Some("size") => Some(Order::Size),
+                    Some("connection") => Some(Order::Connection),
+                    _ => {
+                        return Err(Error::Usage(
+                            "`--sort` expects `start`, `duration`, `size` or `connection`"
+                                .to_owned(),
+                        ));
+                    }
+                };
+            }
+
+            // Before `--sort`.
+            "--order" => {
+                args.order = match arguments.next().as_deref() {
+                    Some("chrono") => Some(Order::Start),
+                    _ => return Err(Error::Usage("`--order` expects `chrono`".to_owned())),
+                };
+            }
+
+            "--last" => {
+                let Some((value, duration)) = arguments
+                    .next()
+                    .and_then(|value| duration::parse(&value).map(|duration| (value, duration)))
+                else {
+                    return Err(Error::Usage(
+                        "`--last` expects a duration like `30m` or `1h30m`".to_owned(),
+                    ));
+                };
+
+                args.last = Some((value, duration));
+            }
+
+            "--from" | "--to" => {
+                let Some(bound) = arguments
+                    .next()
+                    .and_then(|value| filters::Bound::parse(&value))
+                else {
+                    return Err(Error::Usage(format!(
+                        "`{arg}` expects an RFC 3339 datetime like `2024-06-01T09:13:00Z`, or an offset from the start or the end of the log like `+5m` or `-30m`"
+                    )));
+                };
+
+                if arg == "--from" {
+                    args.selection.from = Some(bound);
+                } else {
+                    args.selection.to = Some(bound);
+                }
+            }
+
+            "--conn-id" => {
+                let Some(connection_id) = arguments.next() else {
+                    return Err(Error::Usage(
+                        "`--conn-id` expects a connection ID".to_owned(),
+                    ));
+                };
+
+                args.selection.connection_ids.push(connection_id);
+            }
+
+            "--room" => {
+                let Some(room_id) = arguments.next() else {
+                    return Err(Error::Usage(
+                        "`--room` expects a room ID, like `!abc:example.org`".to_owned(),
+                    ));
+                };
+
+                args.selection.room_ids.push(room_id);
+            }
+
+            "--method" => {
+                let Some(method) = arguments.next() else {
+                    return Err(Error::Usage(
+                        "`--method` expects a method like `GET`".to_owned(),
+                    ));
+                };
+
+                args.selection.methods.push(method.to_uppercase());
+            }
+
+            "--status" => {
+                let Some(status) = arguments
+                    .next()
+                    .and_then(|value| filters::Status::parse(&value))
+                else {
+                    return Err(Error::Usage(
+                        "`--status` expects a status like `429`, or a family like `4xx`".to_owned(),
+                    ));
+                };
+
+                args.selection.statuses.push(status);
+            }
+
+            // `--uri-matches` before `--include-uri`.
+            "--include-uri" | "--uri-matches" | "--exclude-uri" => {
+                let Some(value) = arguments.next() else {
+                    return Err(Error::Usage(format!("`{arg}` expects a regex")));
+                };
+                let regex = Some(
+                    Regex::new(&value)
+                        .map_err(|error| Error::Usage(format!("`{arg}`: {error}")))?,
+                );
+
+                if arg == "--exclude-uri" {
+                    args.selection.uri_excludes = regex;
+                } else {
+                    args.selection.uri_matches = regex;
+                }
+            }
+
+            "--every" => {
+                let Some(stride) = arguments
+                    .next()
+                    .and_then(|value| value.parse::<usize>().ok())
+                    .filter(|stride| *stride > 0)
+                else {
+                    return Err(Error::Usage(
+                        "`--every` expects a positive number of spans".to_owned(),
+                    ));
+                };
+
+                args.every = Some(stride);
+            }
+
+            "--virtual-table" => args.virtual_table = true,
+
+            "--statsd" => {
+                let Some(address) = arguments.next() else {
+                    return Err(Error::Usage(
+                        "`--statsd` expects an address like `localhost:8125`".to_owned(),
+                    ));
+                };
+
+                args.statsd = Some(address);
+            }
+
+            "--otlp-endpoint" => {
+                let Some(url) = arguments.next() else {
+                    return Err(Error::Usage(
+                        "`--otlp-endpoint` expects a URL like `http://localhost:4318`".to_owned(),
+                    ));
+                };
+
+                args.otlp_endpoint = Some(
+                    otlp::parse_endpoint(&url)
+                        .map_err(|error| Error::Usage(format!("`--otlp-endpoint`: {error}")))?,
+                );
+            }
+
+            "--listen" => {
+                let Some(address) = arguments.next() else {
+                    return Err(Error::Usage(
+                        "`--listen` expects an address like `127.0.0.1:9999`".to_owned(),
+                    ));
+                };
+
+                args.listen = Some(address);
+            }
+
+            "--idle-timeout" => {
+                let Some(timeout) = arguments
+                    .next()
+                    .and_then(|value| duration::parse(&value))
+                    .and_then(|timeout| timeout.to_std().ok())
+                else {
+                    return Err(Error::Usage(
+                        "`--idle-timeout` expects a duration like `30s`".to_owned(),
+                    ));
+                };
+
+                args.idle_timeout = timeout;
+            }
+
+            "--stdin" => args.stdin = true,
+
+            "--verbose" => args.verbose = true,
+
+            "--explain" => args.explain = true,
+
+            "--capture-bodies" => args.capture_bodies = true,
+
+            "--quiet" => args.quiet = true,
+
+            "--strict" => args.strict = true,
+
+            "--strict-except" => {
+                let Some(names) = arguments.next() else {
+                    return Err(Error::Usage(
+                        "`--strict-except` expects a list of conditions like `unterminated-spans`"
+                            .to_owned(),
+                    ));
+                };
+
+                args.strict_except.extend(
+                    conditions::Condition::parse_list(&names)
+                        .map_err(|error| Error::Usage(format!("`--strict-except`: {error}")))?,
+                );
+            }
+
+            "--bucket" => {
+                let Some(duration) = arguments
+                    .next()
+                    .and_then(|value| duration::parse(&value))
+                    .filter(|duration| duration.num_milliseconds() > 0)
+                else {
+                    return Err(Error::Usage(
+                        "`--bucket` expects a duration like `1s`".to_owned(),
+                    ));
+                };
+
+                args.bucket = Some(duration);
+            }
+
+            "--gap-threshold" | "--max-sync-gap" => {
+                let Some(threshold) = arguments.next().and_then(|value| duration::parse(&value))
+                else {
+                    return Err(Error::Usage(format!(
+                        "`{arg}` expects a duration like `5s`"
+                    )));
+                };
+
+                args.gap_threshold = threshold;
+            }
+
+            "--timeline-resolution" => {
+                let Some(resolution) = arguments
+                    .next()
+                    .and_then(|value| zoom::parse_resolution(&value))
+                else {
+                    return Err(Error::Usage(
+                        "`--timeline-resolution` expects a duration from `1s` to `1h`, like `10s`"
+                            .to_owned(),
+                    ));
+                };
+
+                args.timeline_resolution = resolution;
+            }
+
+            "--unterminated-threshold" => {
+                let Some(threshold) = arguments.next().and_then(|value| duration::parse(&value))
+                else {
+                    return Err(Error::Usage(
+                        "`--unterminated-threshold` expects a duration like `2m`".to_owned(),
+                    ));
+                };
+
+                args.unterminated_threshold = threshold;
+            }
+
+            "--live" => args.live = true,
+
+            "--port" => {
+                let Some(value) = arguments.next().and_then(|value| value.parse().ok()) else {
+                    return Err(Error::Usage(
+                        "`--port` expects a port number like `8080`".to_owned(),
+                    ));
+                };
+
+                args.port = Some(value);
+            }
+
+            "--max-error-rate" => {
+                let Some(rate) = arguments
+                    .next()
+                    .and_then(|value| check::Budgets::parse_error_rate(&value))
+                else {
+                    return Err(Error::Usage(
+                        "`--max-error-rate` expects a rate like `1%` or `0.01`".to_owned(),
+                    ));
+                };
+
+                args.budgets.max_error_rate = Some(rate);
+            }
+
+            "--max-p95-ms" => {
+                let Some(p95) = arguments
+                    .next()
+                    .and_then(|value| value.parse().ok())
+                    .and_then(TimeDelta::try_milliseconds)
+                else {
+                    return Err(Error::Usage(
+                        "`--max-p95-ms` expects a number of milliseconds like `2000`".to_owned(),
+                    ));
+                };
+
+                args.budgets.max_p95 = Some(p95);
+            }
+
+            "--max-unfinished" => {
+                let Some(number_of_spans) = arguments.next().and_then(|value| value.parse().ok())
+                else {
+                    return Err(Error::Usage(
+                        "`--max-unfinished` expects a number of requests like `0`".to_owned(),
+                    ));
+                };
+
+                args.budgets.max_unfinished = Some(number_of_spans);
+            }
+
+            "--live-interval" => {
+                let Some(interval) = arguments
+                    .next()
+                    .and_then(|value| duration::parse(&value))
+                    .and_then(|interval| interval.to_std().ok())
+                else {
+                    return Err(Error::Usage(
+                        "`--live-interval` expects a duration like `5s`".to_owned(),
+                    ));
+                };
+
+                args.live_interval = interval;
+            }
+
+            "--live-spans" => {
+                let Some(number_of_spans) = arguments
+                    .next()
+                    .and_then(|value| value.parse::<usize>().ok())
+                    .filter(|number_of_spans| *number_of_spans > 0)
+                else {
+                    return Err(Error::Usage(
+                        "`--live-spans` expects a positive number of spans".to_owned(),
+                    ));
+                };
+
+                args.live_spans = number_of_spans;
+            }
+
+            "--follow" => args.follow = true,
+
+            "--watch" => {
+                let Some(directory) = arguments.next() else {
+                    return Err(Error::Usage(
+                        "`--watch` expects the path of a directory".to_owned(),
+                    ));
+                };
+
+                args.watch_directory = Some(directory);
+            }
+
+            "--watch-glob" => {
+                let Some(glob) = arguments.next() else {
+                    return Err(Error::Usage(
+                        "`--watch-glob` expects a glob like `console*.log`".to_owned(),
+                    ));
+                };
+
+                args.watch_glob = Some(glob);
+            }
+
+            "--stream" => args.stream = true,
+
+            "--tui" => args.tui = true,
+
+            "--stats-out" => {
+                let Some(path) = arguments.next() else {
+                    return Err(Error::Usage(
+                        "`--stats-out` expects the path of a JSON file".to_owned(),
+                    ));
+                };
+
+                args.stats_out = Some(path);
+            }
+
+            "--poll-interval" => {
+                let Some(interval) = arguments
+                    .next()
+                    .and_then(|value| duration::parse(&value))
+                    .and_then(|interval| interval.to_std().ok())
+                else {
+                    return Err(Error::Usage(
+                        "`--poll-interval` expects a duration like `1s`".to_owned(),
+                    ));
+                };
+
+                args.poll_interval = interval;
+            }
+
+            // A mistyped flag mustn't be read as a path.
+            _ if arg.starts_with('-') && arg != STDIO => {
+                return Err(Error::Usage(format!("Unknown flag `{arg}`")));
+            }
+
+            _ => args.positionals.push(arg),
+        }
+    }
+
+    if let Some(subcommand) = args
+        .positionals
+        .first()
+        .and_then(|name| Subcommand::parse(name))
+    {
+        args.subcommand = Some(subcommand);
+        args.positionals.remove(0);
+    }
+
+    Ok(args)
+}
+
+/// Check the flags against the subcommand, and against each other, before any
+/// log is read.
+fn validate(args: &Args) -> Result<(), Error> {
+    if args.port.is_some() && args.subcommand != Some(Subcommand::Serve) {
+        return Err(Error::Usage("`--port` requires `serve`".to_owned()));
+    }
+
+    if !args.budgets.is_empty() && args.subcommand != Some(Subcommand::Check) {
+        return Err(Error::Usage(
+            "`--max-error-rate`, `--max-p95-ms` and `--max-unfinished` require `check`".to_owned(),
+        ));
+    }
+
+    match args.subcommand {
+        Some(Subcommand::Serve) => validate_serve(args)?,
+        Some(Subcommand::Check) => validate_check(args)?,
+        Some(subcommand @ (Subcommand::Cohort | Subcommand::Diff)) => {
+            validate_comparison(subcommand, args)?;
+        }
+        None => {}
+    }
+
+    if args.watch_directory.is_some() {
+        if args.subcommand.is_some()
+            || args.live
+            || args.follow
+            || args.stream
+            || args.tui
+            || args.stdin
+            || args.listen.is_some()
+            || args.statsd.is_some()
+            || args.otlp_endpoint.is_some()
+            || args.stats_out.is_some()
+            || args.with_context > 0
+        {
+            return Err(Error::Usage(
+                "`--watch` cannot be combined with a subcommand, `--live`, `--follow`, `--stream`, `--tui`, `--stdin`, `--listen`, `--statsd`, `--otlp-endpoint`, `--stats-out` or `--with-context`".to_owned(),
+            ));
+        }
+    } else if args.watch_glob.is_some() {
+        return Err(Error::Usage("`--watch-glob` requires `--watch`".to_owned()));
+    }
+
+    if args.stats_out.is_some()
+        && (matches!(args.subcommand, Some(Subcommand::Cohort | Subcommand::Diff)) || args.stream)
+    {
+        return Err(Error::Usage(
+            "`--stats-out` summarizes the spans of a log; it cannot be combined with `cohort`, `diff` or `--stream`".to_owned(),
+        ));
+    }
+
+    if args.tui {
+        if args.subcommand.is_some()
+            || !args.output_paths.is_empty()
+            || args.format.is_some()
+            || args.live
+            || args.follow
+            || args.stream
+            || args.split_by.is_some()
+            || args.listen.is_some()
+            || args.otlp_endpoint.is_some()
+        {
+            return Err(Error::Usage(
+                "`--tui` browses the spans in the terminal; it cannot be combined with a subcommand, `-o`, `--format`, `--live`, `--follow`, `--stream`, `--split-by`, `--listen` or `--otlp-endpoint`".to_owned(),
+            ));
+        }
+
+        // The keys are read from the standard input.
+        if args.stdin
+            || args
+                .positionals
+                .iter()
+                .any(|positional| positional == STDIO)
+        {
+            return Err(Error::Usage(
+                "`--tui` reads the keys from the standard input; the log cannot be read from it"
+                    .to_owned(),
+            ));
+        }
+    }
+
+    if let Some(Origin::PerConnection) = args.origin {
+        if let Some(Order::Start | Order::Duration | Order::Size) = args.order {
+            return Err(Error::Usage(
+                "`--origin per-connection` requires `--sort connection`".to_owned(),
+            ));
+        }
+
+        if args.virtual_table {
+            return Err(Error::Usage(
+                "`--origin per-connection` cannot be combined with `--virtual-table`".to_owned(),
+            ));
+        }
+    }
+
+    if args.pseudonymizes && args.redaction.is_none() {
+        return Err(Error::Usage(
+            "`--redact` cannot be combined with `--no-redact`".to_owned(),
+        ));
+    }
+
+    if args.otlp_endpoint.is_some() && (args.live || args.follow) {
+        return Err(Error::Usage(
+            "`--otlp-endpoint` cannot be combined with `--live` or `--follow`, which would push the spans several times".to_owned(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// `serve` serves the report from memory.
+fn validate_serve(args: &Args) -> Result<(), Error> {
+    if !args.output_paths.is_empty()
+        || args.format.is_some()
+        || args.stream
+        || args.split_by.is_some()
+    {
+        return Err(Error::Usage(
+            "`serve` serves the report from memory; it cannot be combined with `-o`, `--format`, `--stream` or `--split-by`".to_owned(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// `check` evaluates the budgets, and writes no report.
+fn validate_check(args: &Args) -> Result<(), Error> {
+    if args.budgets.is_empty() {
+        return Err(Error::Usage(
+            "`check` expects budgets, like `--max-error-rate 1%`, `--max-p95-ms 2000` or `--max-unfinished 0`".to_owned(),
+        ));
+    }
+
+    if !args.output_paths.is_empty()
+        || args.format.is_some()
+        || args.live
+        || args.follow
+        || args.stream
+        || args.split_by.is_some()
+        || args.otlp_endpoint.is_some()
+    {
+        return Err(Error::Usage(
+            "`check` writes no report; it cannot be combined with `-o`, `--format`, `--live`, `--follow`, `--stream`, `--split-by` or `--otlp-endpoint`".to_owned(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// `cohort` and `diff` read several logs entirely.
+fn validate_comparison(subcommand: Subcommand, args: &Args) -> Result<(), Error> {
+    if args.with_context > 0
+        || args.live
+        || args.follow
+        || args.listen.is_some()
+        || args.stdin
+        || args.statsd.is_some()
+    {
+        return Err(Error::Usage(format!(
+            "`{}` cannot be combined with `--with-context`, `--live`, `--follow`, `--listen`, `--stdin` or `--statsd`",
+            subcommand.as_str()
+        )));
+    }
+
+    Ok(())
+}
+
+/// Resolve the outputs. Without any `-o`, the output path is the last
+/// positional argument, but the waterfall of text is printed, and the traces
+/// pushed to an endpoint need no file.
+fn outputs(args: &mut Args) -> Result<Vec<Output>, Error> {
+    let writes_no_file =
+        matches!(args.subcommand, Some(Subcommand::Serve | Subcommand::Check)) || args.tui;
+    let mut output_paths = mem::take(&mut args.output_paths);
+
+    if output_paths.is_empty() && !writes_no_file {
+        if args.format == Some(Format::Term) {
+            output_paths.push(STDIO.to_owned());
+        } else if args.otlp_endpoint.is_none()
+            && let Some(output_path) = args.positionals.pop()
+        {
+            output_paths.push(output_path);
+        }
+    }
+
+    let mut outputs = if output_paths.is_empty() && (args.otlp_endpoint.is_some() || writes_no_file)
+    {
+        Vec::new()
+    } else {
+        format::outputs(args.format, output_paths).map_err(|error| {
+            Error::Usage(format!(
+                "{error}; try `{} [options] <log_path>... <output_path>`",
+                args.this_bin
+            ))
+        })?
+    };
+
+    if let Some(url) = &args.otlp_endpoint {
+        outputs.push(Output {
+            format: Format::Otlp,
+            path: url.clone(),
+        });
+    }
+
+    Ok(outputs)
+}
+
+/// Check the flags against the outputs.
+fn validate_outputs(args: &Args, outputs: &[Output]) -> Result<(), Error> {
+    if args.assets == Assets::External {
+        if args.split_by.is_some() || args.subcommand.is_some() {
+            return Err(Error::Usage(
+                "`--assets external` cannot be combined with `--split-by`, `cohort` or `diff`"
+                    .to_owned(),
+            ));
+        }
+
+        if outputs
+            .iter()
+            .any(|output| output.format == Format::Html && output.path == STDIO)
+        {
+            return Err(Error::Usage(
+                "`--assets external` writes files next to the HTML report, which cannot be the standard output".to_owned(),
+            ));
+        }
+    }
+
+    if let Some(SplitBy::Day) = args.split_by {
+        if !matches!(
+            outputs,
+            [Output {
+                format: Format::Html,
+                ..
+            }]
+        ) {
+            return Err(Error::Usage(
+                "`--split-by day` only supports a single HTML output".to_owned(),
+            ));
+        }
+
+        if args.last.is_some() {
+            return Err(Error::Usage(
+                "`--split-by day` cannot be combined with `--last`".to_owned(),
+            ));
+        }
+    }
+
+    if outputs.iter().any(|output| output.format == Format::Csv) {
+        let unsupported = csv::unsupported(&args.columns);
+
+        if !unsupported.is_empty() {
+            return Err(Error::Usage(format!(
+                "`--columns`: the CSV export has no field for {}",
+                unsupported
+                    .iter()
+                    .map(|column| format!("`{}`", column.as_str()))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )));
+        }
+    }
+
+    if args.stream {
+        if !matches!(
+            outputs,
+            [Output {
+                format: Format::Csv,
+                ..
+            }]
+        ) || args.group_by.is_some()
+        {
+            return Err(Error::Usage(
+                "`--stream` only supports a single CSV output of the spans".to_owned(),
+            ));
+        }
+
+        if args.live
+            || args.follow
+            || args.statsd.is_some()
+            || args.merge_connections
+            || args.split_by.is_some()
+            || args.last.is_some()
+            || args.with_context > 0
+        {
+            return Err(Error::Usage(
+                "`--stream` cannot be combined with `--live`, `--follow`, `--statsd`, `--merge-connections`, `--split-by`, `--last` or `--with-context`".to_owned(),
+            ));
+        }
+
+        if [args.selection.from, args.selection.to]
+            .iter()
+            .flatten()
+            .any(|bound| !bound.is_absolute())
+        {
+            return Err(Error::Usage(
+                "`--stream` only supports datetimes with `--from` and `--to`, as the end of the log is unknown while streaming".to_owned(),
+            ));
+        }
+    }
+
+    // Following a file is a live mode too.
+    if outputs.iter().any(|output| output.path == STDIO)
+        && (args.split_by.is_some() || args.live || args.follow || args.watch_directory.is_some())
+    {
+        return Err(Error::Usage(
+            "`--split-by`, `--live`, `--follow` and `--watch` write several times, to output files, not to `-`"
+                .to_owned(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Where the logs are read from. `reads_stdin` is whether the standard input
+/// is named, by `--stdin` or by a first positional argument `-`.
+fn source(args: &Args, reads_stdin: bool) -> Result<Source, Error> {
+    let positionals = &args.positionals;
+    let source = match &args.listen {
+        Some(address) => Source::Listen {
+            address: address.clone(),
+            idle_timeout: args.idle_timeout,
+        },
+        None if args.follow => match positionals.as_slice() {
+            // A pipe is followed until it's closed, e.g. when `adb logcat` is
+            // stopped by Ctrl-C.
+            [] if args.stdin => {
+                interrupt::catch();
+
+                Source::Stdin
+            }
+            [path] if path == STDIO => {
+                interrupt::catch();
+
+                Source::Stdin
+            }
+            [path] if !Path::new(path).is_dir() => {
+                interrupt::catch();
+
+                Source::Follow {
+                    path: path.clone(),
+                    poll_interval: args.poll_interval,
+                }
+            }
+            _ => {
+                return Err(Error::Usage(
+                    "`--follow` expects a single log file, or `-`".to_owned(),
+                ));
+            }
+        },
+        // The log files of a watched directory are listed on every change.
+        None if let Some(directory) = &args.watch_directory => {
+            if !positionals.is_empty() {
+                return Err(Error::Usage(format!(
+                    "`--watch` reads the log files of the directory; try `{} [options] --watch <directory> <output_path>`",
+                    args.this_bin
+                )));
+            }
+
+            if !Path::new(directory).is_dir() {
+                return Err(Error::Usage(format!(
+                    "`--watch`: `{directory}` isn't a directory"
+                )));
+            }
+
+            Source::Files(Vec::new())
+        }
+        None if positionals.len() > 1 && positionals.iter().any(|path| path == STDIO) => {
+            return Err(Error::Usage(
+                "`-`, the standard input, cannot be combined with log files".to_owned(),
+            ));
+        }
+        // The standard input is never read implicitly: with a single
+        // positional argument, it would be the log, overwritten by the report.
+        None if reads_stdin => Source::Stdin,
+        None if positionals.is_empty() => {
+            let hint = if io::stdin().is_terminal() {
+                ""
+            } else {
+                ", or `-` to read the standard input"
+            };
+
+            return Err(Error::Usage(format!(
+                "<log_path> is missing; try `{} [options] <log_path>... <output_path>`{hint}",
+                args.this_bin
+            )));
+        }
+        None => Source::from_paths(positionals.clone())?,
+    };
+
+    if args.with_context > 0 {
+        let Source::Files(paths) = &source else {
+            return Err(Error::Usage(
+                "`--with-context` requires log files, which can be read again".to_owned(),
+            ));
+        };
+
+        for path in paths {
+            if let Some(compression) = source::compression(path).map_err(Error::io(path))? {
+                return Err(Error::Usage(format!(
+                    "`--with-context` cannot read `{path}` again: it is {}",
+                    compression.as_str()
+                )));
+            }
+        }
+    }
+
+    Ok(source)
+}
+
+/// The exports of a previous run among the sources, imported instead of
+/// being parsed.
+fn exports(args: &Args, source: &Source) -> Result<Option<Vec<String>>, Error> {
+    let Source::Files(paths) = source else {
+        return Ok(None);
+    };
+
+    if !paths.iter().any(|path| import::is_export(path)) {
+        return Ok(None);
+    }
+
+    if !paths.iter().all(|path| import::is_export(path)) {
+        return Err(Error::Usage(
+            "Logs and exports cannot be mixed; pass either log files or exports".to_owned(),
+        ));
+    }
+
+    if args.with_context > 0 {
+        return Err(Error::Usage(
+            "`--with-context` requires the original logs, not exports".to_owned(),
+        ));
+    }
+
+    if args.live || args.follow {
+        return Err(Error::Usage(
+            "`--live` requires logs, not exports".to_owned(),
+        ));
+    }
+
+    Ok(Some(paths.clone()))
+}
+
+/// Run the binary with the arguments of the process.
+pub fn run() -> Result<Stats, Error> {
+    let mut args = parse_args(env::args())?;
+
+    if args.help {
+        return Ok(Stats::new(usage(&args.this_bin)));
+    }
+
+    if args.version {
+        return Ok(Stats::new(format!("network-viewer {}", meta::TOOL_VERSION)));
+    }
+
+    validate(&args)?;
+
+    if args.tui && (!io::stdin().is_terminal() || !io::stdout().is_terminal()) {
+        return Err(Error::Usage("`--tui` requires a terminal".to_owned()));
+    }
+
+    let cohort = args.subcommand == Some(Subcommand::Cohort);
+    let diff = args.subcommand == Some(Subcommand::Diff);
+    let serve = args.subcommand == Some(Subcommand::Serve);
+    let check = args.subcommand == Some(Subcommand::Check);
+
+    // Before `outputs` takes the output path out of the positional arguments:
+    // a lone `-` reads the standard input and writes to the standard output.
+    let reads_stdin = args.stdin || args.positionals.first().is_some_and(|path| path == STDIO);
+    let outputs = outputs(&mut args)?;
+    validate_outputs(&args, &outputs)?;
+
+    let report_to_stdout = outputs.iter().any(|output| output.path == STDIO);
+    let terminal = if outputs
+        .iter()
+        .any(|output| output.format == Format::Term && output.path == STDIO)
+    {
+        term::Terminal::stdout()
+    } else {
+        term::Terminal::default()
+    };
+
+    let source = source(&args, reads_stdin)?;
+    let exports = exports(&args, &source)?;
+    let log_name = source.name();
+    let sources = source.files();
+
+    // Following a file is a live mode whose source never ends, until Ctrl-C.
+    let live = args.live || args.follow;
+
+    // The flags of the command line override the thresholds of the configs.
+    let mut patterns = Vec::new();
+    let mut slow_thresholds = slow::Thresholds::default();
+
+    for path in &args.configs {
+        let text = fs::read_to_string(path).map_err(Error::io(path))?;
+        let config = config::parse(&text)
+            .map_err(|error| Error::Input(format!("`--config` {path}: {error}")))?;
+
+        patterns.extend(config.patterns);
+        slow_thresholds.extend(config.slow_thresholds);
+    }
+
+    slow_thresholds.extend(args.slow_thresholds);
+
+    let options = Options {
+        anomalies_config: args.anomalies_config,
+        timezone: args.timezone,
+        group_by: args.group_by,
+        rollup: args.rollup,
+        // Each connection has its own timeline, so its rows are together.
+        order: args.order.unwrap_or(match args.origin {
+            Some(Origin::PerConnection) => Order::Connection,
+            None => Order::Start,
+        }),
+        connection_order: args.connection_order,
+        columns: args.columns,
+        force_columns: args.force_columns,
+        origin: args.origin,
+        split_by: args.split_by,
+        title: args.title,
+        template: match &args.template {
+            Some(path) => Template::load(path)
+                .map_err(|error| Error::Input(format!("`--template`: {error}")))?,
+            None => Template::default(),
+        },
+        assets: args.assets,
+        theme: args.theme,
+        sources,
+        with_context: args.with_context,
+        last: args.last,
+        selection: args.selection,
+        every: args.every,
+        virtual_table: args.virtual_table,
+        duration_thresholds: args.duration_thresholds,
+        slow_thresholds,
+        latency_heatmap: args.latency_heatmap,
+        gap_threshold: args.gap_threshold,
+        timeline_resolution: args.timeline_resolution,
+        bucket: args.bucket,
+        hide: args.hide,
+        merge_connections: args.merge_connections.then_some(args.merge_window),
+        terminal,
+        redaction: args.redaction.map(|mut redaction| {
+            for name in &args.redact_parameters {
+                redaction.add_parameter(name);
+            }
+
+            if args.pseudonymizes {
+                redaction.pseudonymize();
+            }
+
+            redaction
+        }),
+        refresh: None,
+    };
+    let lifecycle_patterns = lifecycle::Pattern::with_defaults(args.lifecycle_patterns);
+    let new_parser = || {
+        let mut parser = Parser::new();
+        parser.context = options.with_context;
+        parser.warning_targets = args.warning_targets.clone();
+        parser.warnings_per_span = args.warnings_per_span;
+        parser.lifecycle_patterns = lifecycle_patterns.clone();
+        parser.patterns.extend(patterns.iter().cloned());
+        parser.explain = args.explain;
+        parser.capture_bodies = args.capture_bodies;
+
+        if let Some(threads) = args.threads {
+            parser.threads = threads;
+        }
+
+        if let Some(timestamp_format) = &args.timestamp_format {
+            parser.set_timestamp_format(timestamp_format.clone());
+        }
+
+        parser
+    };
+
+    if cohort {
+        match (&source, outputs.as_slice()) {
+            (
+                Source::Files(paths),
+                [
+                    Output {
+                        format: Format::Html,
+                        path: output_path,
+                    },
+                ],
+            ) if paths.len() == 1 => {
+                return cohort::run(&options, new_parser, &paths[0], output_path);
+            }
+            _ => {
+                return Err(Error::Usage(format!(
+                    "`cohort` expects a directory and an HTML output; try `{} cohort [options] <directory> <output_path>`",
+                    args.this_bin
+                )));
+            }
+        }
+    }
+
+    if let Some(directory) = &args.watch_directory {
+        return watch::run(
+            &options,
+            new_parser,
+            directory,
+            args.watch_glob.as_deref(),
+            args.poll_interval,
+            &outputs,
+        );
+    }
+
+    if diff {
+        match &source {
+            Source::Files(paths)
+                if exports.is_none()
+                    && paths.len() == 2
+                    && outputs
+                        .iter()
+                        .all(|output| matches!(output.format, Format::Html | Format::Json)) =>
+            {
+                return diff::run(&options, new_parser, [&paths[0], &paths[1]], &outputs);
+            }
+            _ => {
+                return Err(Error::Usage(format!(
+                    "`diff` expects 2 log files and HTML or JSON outputs; try `{} diff [options] <log_a> <log_b> <output_path>`",
+                    args.this_bin
+                )));
+            }
+        }
+    }
+
+    let mut parser = new_parser();
+
+    // The spans of several files are on a single timeline, e.g. the logs of
+    // 2 devices: each span tells which file it is from.
+    if let Source::Files(paths) = &source
+        && paths.len() > 1
+    {
+        parser.file_names = paths.clone();
+    }
+
+    if args.stream {
+        if exports.is_some() {
+            return Err(Error::Usage(
+                "`--stream` requires logs, not exports".to_owned(),
+            ));
+        }
+
+        let output_path = &outputs[0].path;
+        let (number_of_spans, number_of_unselected_spans) =
+            stream::run(&options, &mut parser, &source, output_path)?;
+
+        return Ok(Stats {
+            summary: format!(
+                "\nSource: {log_name}\n\
+                Number of analysed log lines: {number_of_analysed_lines}\n\
+                Number of matched lines: {number_of_matched_lines}\n\
+                Number of streamed spans: {number_of_spans}\n\
+                {unselected_spans}\
+                {conditions}\
+                Output file: {output_file}\n\
+                Done!",
+                number_of_analysed_lines = human::count(parser.number_of_analysed_lines),
+                number_of_matched_lines = human::count(parser.number_of_matched_lines),
+                number_of_spans = human::count(number_of_spans),
+                unselected_spans = if number_of_unselected_spans > 0 {
+                    format!(
+                        "Number of spans removed by the filters: {}\n",
+                        human::count(number_of_unselected_spans)
+                    )
+                } else {
+                    String::new()
+                },
+                conditions = parser.conditions.summary(args.verbose),
+                output_file = if output_path == STDIO {
+                    "(stdout)"
+                } else {
+                    output_path
+                },
+            ),
+            report_to_stdout,
+            strict_errors: if args.strict {
+                parser.conditions.strict_errors(&args.strict_except)
+            } else {
+                Vec::new()
+            },
+            budget_violations: Vec::new(),
+        });
+    }
+
+    let server = if serve {
+        let port = args.port.unwrap_or(serve::DEFAULT_PORT);
+        let server = serve::Server::bind(port).map_err(Error::io(format!("port {port}")))?;
+        eprintln!("Serving the report on {}", server.url());
+
+        Some(server)
+    } else {
+        None
+    };
+
+    if live {
+        let mut statsd = args
+            .statsd
+            .map(|address| statsd::Client::connect(address.as_str()).map_err(Error::io(address)))
+            .transpose()?;
+        let (sender, receiver) = mpsc::channel();
+
+        // Read in a separate thread, so that the report is regenerated on time
+        // even if the source is quiet.
+        let reader = thread::spawn(move || {
+            source.read_lines(|line, location| {
+                let line = line.map(ToOwned::to_owned).map_err(ToOwned::to_owned);
+                let _ = sender.send((line, location));
+            })
+        });
+
+        // The reports written until the source ends reload themselves in the
+        // browser; the final one doesn't.
+        let live_options = Options {
+            refresh: Some(args.live_interval),
+            ..options.clone()
+        };
+        let mut reported_at = Instant::now();
+        let mut number_of_reported_lines = 0;
+        let mut number_of_completed_spans = 0;
+
+        loop {
+            match receiver.recv_timeout(args.live_interval) {
+                Ok((line, location)) => {
+                    if let Some(span) =
+                        parser.parse(line.as_deref().map_err(Vec::as_slice), location)
+                    {
+                        number_of_completed_spans += 1;
+
+                        if let Some(client) = &mut statsd {
+                            client.send(span);
+                        }
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => {
+                    reader.join().expect("The reader never panics")?;
+
+                    break;
+                }
+            }
+
+            let is_due = reported_at.elapsed() >= args.live_interval
+                || number_of_completed_spans >= args.live_spans;
+
+            if is_due && parser.number_of_matched_lines > number_of_reported_lines {
+                if let Some(server) = &server {
+                    let (pages, _) = serve_pages(&live_options, &parser, &log_name)?;
+                    server.update(pages);
+                } else {
+                    write_reports(
+                        &live_options,
+                        parser.spans.clone(),
+                        &parser.lifecycle_events,
+                        &log_name,
+                        &outputs,
+                    )?;
+                }
+
+                eprintln!(
+                    "Regenerated the report after {} matched lines",
+                    human::count(parser.number_of_matched_lines)
+                );
+
+                reported_at = Instant::now();
+                number_of_reported_lines = parser.number_of_matched_lines;
+                number_of_completed_spans = 0;
+            }
+        }
+    } else if let Some(paths) = &exports {
+        for path in paths {
+            let import = import::read(path)
+                .map_err(|error| Error::Input(format!("Failed to import `{path}`: {error}")))?;
+
+            eprintln!(
+                "Imported {} spans from `{path}`",
+                human::count(import.number_of_spans)
+            );
+
+            for (connection_id, spans) in import.spans {
+                parser.spans.entry(connection_id).or_default().extend(spans);
+            }
+
+            parser.lifecycle_events.extend(import.lifecycle_events);
+        }
+
+        parser.lifecycle_events.sort_by_key(|event| event.at);
+    } else {
+        let mut progress = (!args.quiet).then(|| Progress::start(&source)).flatten();
+        let mut batch = Batch::default();
+
+        source.read_lines(|line, location| {
+            if let Some(progress) = &mut progress {
+                progress.update(location, line.map_or_else(<[u8]>::len, str::len));
+            }
+
+            if batch.push(line, location) {
+                parser.parse_batch(&mut batch);
+            }
+        })?;
+        parser.parse_batch(&mut batch);
+
+        if let Some(progress) = progress {
+            progress.finish();
+        }
+
+        if let Some(address) = &args.statsd {
+            let mut client =
+                statsd::Client::connect(address.as_str()).map_err(Error::io(address))?;
+            let mut spans_by_start_at = all_spans(&parser.spans, &ConnectionOrder::default());
+            spans_by_start_at.sort_by_key(|(_, _, span)| span.start_at);
+
+            eprintln!(
+                "StatsD carries no timestamp: the metrics of the whole log are replayed now, not at their original time."
+            );
+
+            for (_, _, span) in spans_by_start_at {
+                client.send(span);
+            }
+
+            if client.number_of_failures() > 0 {
+                eprintln!(
+                    "{} StatsD packets have failed to be sent",
+                    client.number_of_failures()
+                );
+            }
+        }
+    }
+
+    // The final report is always written, once the source is exhausted.
+    let number_of_ambiguous_warnings = parser.number_of_ambiguous_warnings;
+    let number_of_restarts = parser.number_of_restarts();
+    let number_of_reassembled_records = parser.number_of_reassembled_records;
+
+    // The spans imported from exports have been checked by the run which has
+    // exported them.
+    if exports.is_none() {
+        parser.record_unterminated_spans(args.unterminated_threshold);
+    }
+
+    let conditions = mem::take(&mut parser.conditions);
+
+    if let Some(diagnostic) = parser.no_match_diagnostic() {
+        eprintln!("{diagnostic}");
+    }
+
+    if let Some(explanation) = parser.explanation() {
+        eprintln!("{explanation}");
+    }
+
+    let bytes_per_connection = traffic::per_connection_to_text(&parser.spans);
+    let pending_per_connection = status::pending_per_connection_to_text(&parser.spans);
+    let iterations_per_connection = iterations::per_connection_to_text(&parser.spans);
+    let rate_limited_per_connection = rate_limits::per_connection_to_text(&parser.spans);
+    let repeated_media = media::repeated_to_text(&parser.spans);
+    let peak_concurrency = concurrency::lanes(&parser.spans).peaks_to_text();
+    let longest_gaps = gaps::longest_to_text(
+        &gaps::detect(&parser.spans, args.gap_threshold),
+        options.timezone,
+    );
+    let slow_requests = options.slow_thresholds.to_text(&parser.spans);
+    let stats = args.stats_out.map(|path| {
+        let stats = summary::to_json(
+            &log_name,
+            parser.number_of_analysed_lines,
+            parser.number_of_matched_lines,
+            &parser.spans,
+            options.timezone,
+        );
+
+        (path, stats)
+    });
+    let mut evaluation = check::Evaluation::default();
+    let (output_paths, number_of_unselected_spans) = match &server {
+        Some(server) => {
+            let (pages, number_of_unselected_spans) = serve_pages(&options, &parser, &log_name)?;
+            server.update(pages);
+
+            eprintln!("The report is complete; press Ctrl-C to stop the server");
+            server.wait();
+
+            (Vec::new(), number_of_unselected_spans)
+        }
+        None if args.tui => {
+            let mut spans = parser.spans;
+            let number_of_unselected_spans = prepare_spans(&options, &mut spans);
+            let (smallest_start_at, largest_end_at) = filters::time_range(&spans)
+                .map(|(start_at, end_at)| (start_at.timestamp_millis(), end_at.timestamp_millis()))
+                .unwrap_or_default();
+
+            tui::run(
+                &sorted_spans(&spans, &options),
+                &buckets::per_endpoint(&spans),
+                smallest_start_at,
+                largest_end_at,
+            )
+            .map_err(Error::io("the terminal"))?;
+
+            (Vec::new(), number_of_unselected_spans)
+        }
+        None if check => {
+            let mut spans = parser.spans;
+            let number_of_unselected_spans = prepare_spans(&options, &mut spans);
+            evaluation = args.budgets.evaluate(&spans);
+
+            (Vec::new(), number_of_unselected_spans)
+        }
+        None => write_reports(
+            &options,
+            parser.spans,
+            &parser.lifecycle_events,
+            &log_name,
+            &outputs,
+        )?,
+    };
+
+    if let Some((path, stats)) = &stats {
+        fs::write(path, stats).map_err(Error::io(path))?;
+    }
+
+    let summary = format!(
+        "\nSource: {log_name}\n\
+        Number of analysed log lines: {number_of_analysed_lines}\n\
+        Number of matched lines: {number_of_matched_lines}\n\
+        {reassembled_records}\
+        {bytes_per_connection}\
+        {pending_per_connection}\
+        {iterations_per_connection}\
+        {rate_limited_per_connection}\
+        {repeated_media}\
+        {peak_concurrency}\
+        {longest_gaps}\
+        {slow_requests}\
+        {restarts}\
+        {unselected_spans}\
+        {ambiguous_warnings}\
+        {conditions}\
+        {budgets}\
+        {output_files}\
+        Done!",
+        number_of_analysed_lines = human::count(parser.number_of_analysed_lines),
+        number_of_matched_lines = human::count(parser.number_of_matched_lines),
+        reassembled_records = if number_of_reassembled_records > 0 {
+            format!(
+                "Number of records reassembled from wrapped lines: {}\n",
+                human::count(number_of_reassembled_records)
+            )
+        } else {
+            String::new()
+        },
+        restarts = if number_of_restarts > 0 {
+            format!(
+                "Number of app restarts detected: {}\n",
+                human::count(number_of_restarts)
+            )
+        } else {
+            String::new()
+        },
+        unselected_spans = if number_of_unselected_spans > 0 {
+            format!(
+                "Number of spans removed by the filters: {}\n",
+                human::count(number_of_unselected_spans)
+            )
+        } else {
+            String::new()
+        },
+        ambiguous_warnings = if number_of_ambiguous_warnings > 0 {
+            format!(
+                "Number of warning lines matching several spans, not attached: {}\n",
+                human::count(number_of_ambiguous_warnings)
+            )
+        } else {
+            String::new()
+        },
+        conditions = conditions.summary(args.verbose),
+        budgets = evaluation.summary,
+        output_files = output_paths
+            .iter()
+            .map(|output_path| {
+                if output_path == STDIO {
+                    "Output file: (stdout)\n".to_owned()
+                } else if otlp::is_endpoint(output_path) {
+                    format!("Traces pushed to: {output_path}\n")
+                } else {
+                    format!("Output file: {output_path}\n")
+                }
+            })
+            .collect::<String>(),
+    );
+
+    Ok(Stats {
+        summary,
+        report_to_stdout,
+        strict_errors: if args.strict {
+            conditions.strict_errors(&args.strict_except)
+        } else {
+            Vec::new()
+        },
+        budget_violations: evaluation.violations,
+    })
+}
+
+/// What a run prints once done.
+pub struct Stats {
+    pub summary: String,
+    /// Whether the report has been written to the standard output, so that
+    /// the summary is printed to the standard error.
+    pub report_to_stdout: bool,
+    /// The conditions failing the run because of `--strict`.
+    pub strict_errors: Vec<String>,
+    /// The budgets of `check` exceeded by the run.
+    pub budget_violations: Vec<String>,
+}
+
+impl Stats {
+    /// Stats of a run with no condition failing it.
+    pub(crate) fn new(summary: String) -> Self {
+        Self {
+            summary,
+            report_to_stdout: false,
+            strict_errors: Vec::new(),
+            budget_violations: Vec::new(),
+        }
+    }
+}
+
+/// Options of the report.
+#[derive(Clone)]
+pub(crate) struct Options {
+    pub(crate) anomalies_config: anomalies::Config,
+    pub(crate) timezone: FixedOffset,
+    pub(crate) group_by: Option<GroupBy>,
+    pub(crate) rollup: Option<Rollup>,
+    pub(crate) order: Order,
+    pub(crate) connection_order: ConnectionOrder,
+    pub(crate) columns: Vec<Column>,
+    pub(crate) force_columns: bool,
+    pub(crate) origin: Option<Origin>,
+    pub(crate) split_by: Option<SplitBy>,
+    pub(crate) title: Option<String>,
+    /// The HTML template, with its style and script inlined.
+    pub(crate) template: Template,
+    /// Whether the assets of the HTML report are inlined, see [`Assets`].
+    pub(crate) assets: Assets,
+    /// The color theme of the HTML report.
+    pub(crate) theme: Theme,
+    pub(crate) sources: Vec<SourceFile>,
+    /// Number of raw log lines shown around the requests and the responses.
+    pub(crate) with_context: usize,
+    pub(crate) last: Option<(String, TimeDelta)>,
+    /// Spans not selected are removed, from the statistics too.
+    pub(crate) selection: filters::Selection,
+    pub(crate) every: Option<usize>,
+    pub(crate) virtual_table: bool,
+    pub(crate) duration_thresholds: duration_bands::Thresholds,
+    /// Thresholds above which the requests are highlighted as slow.
+    pub(crate) slow_thresholds: slow::Thresholds,
+    /// Whether the spans are colored by the percentile of their duration
+    /// among the spans of their endpoint.
+    pub(crate) latency_heatmap: bool,
+    /// Minimum idle period of the sync loop of a connection shown as a gap.
+    pub(crate) gap_threshold: TimeDelta,
+    /// Step of the finest zoom level of the timeline.
+    pub(crate) timeline_resolution: TimeDelta,
+    /// Duration of the buckets of the bandwidth, chosen from the duration of
+    /// the logs if `None`.
+    pub(crate) bucket: Option<TimeDelta>,
+    /// Spans matching this expression are removed from the table, but kept
+    /// in the statistics.
+    pub(crate) hide: Option<expression::Expression>,
+    /// Window of the merge of the restarted connections, if enabled.
+    pub(crate) merge_connections: Option<TimeDelta>,
+    /// Where the waterfall of text is printed, with `--format term`.
+    pub(crate) terminal: term::Terminal,
+    /// Redaction of the spans before they are written, unless `--no-redact`.
+    pub(crate) redaction: Option<redact::Redaction>,
+    /// Period after which the browser reloads the report, while it is
+    /// regenerated in live mode.
+    pub(crate) refresh: Option<Duration>,
+}
+
+impl Default for Options {
+    /// The options without any flag.
+    fn default() -> Self {
+        Self {
+            anomalies_config: anomalies::Config::default(),
+            timezone: FixedOffset::east_opt(0).expect("UTC is a valid offset"),
+            group_by: None,
+            rollup: None,
+            order: Order::Start,
+            connection_order: ConnectionOrder::default(),
+            columns: Column::ALL.to_vec(),
+            force_columns: false,
+            origin: None,
+            split_by: None,
+            title: None,
+            template: Template::default(),
+            assets: Assets::Inline,
+            theme: Theme::Dark,
+            sources: Vec::new(),
+            with_context: 0,
+            last: None,
+            selection: filters::Selection::default(),
+            every: None,
+            virtual_table: false,
+            duration_thresholds: duration_bands::Thresholds::default(),
+            slow_thresholds: slow::Thresholds::default(),
+            latency_heatmap: false,
+            gap_threshold: gaps::DEFAULT_THRESHOLD,
+            timeline_resolution: zoom::DEFAULT_RESOLUTION,
+            bucket: None,
+            hide: None,
+            merge_connections: None,
+            terminal: term::Terminal::default(),
+            redaction: Some(redact::Redaction::default()),
+            refresh: None,
+        }
+    }
+}
+
+/// Merge, select and redact the spans before they are rendered. Returns the
+/// number of spans removed by the filters.
+fn prepare_spans(options: &Options, spans: &mut Spans) -> usize {
+    if let Some(window) = options.merge_connections {
+        merge::merge(spans, window);
+    }
+
+    // The spans are selected once assembled and merged, but before their URIs
+    // are redacted.
+    let number_of_unselected_spans = options.selection.retain(spans);
+
+    // The tokens are redacted once the connections are merged.
+    if let Some(redaction) = &options.redaction {
+        redaction.spans(spans);
+    }
+
+    number_of_unselected_spans
+}
+
+/// Render the pages served by `serve`, see [`serve::Pages`]. Returns the
+/// number of spans removed by the filters too.
+fn serve_pages(
+    options: &Options,
+    parser: &Parser,
+    log_name: &str,
+) -> Result<(serve::Pages, usize), Error> {
+    let mut spans = parser.spans.clone();
+    let number_of_unselected_spans = prepare_spans(options, &mut spans);
+    let summary = summary::to_json(
+        log_name,
+        parser.number_of_analysed_lines,
+        parser.number_of_matched_lines,
+        &spans,
+        options.timezone,
+    );
+    let (contents, _) = render_report(
+        options,
+        spans,
+        &parser.lifecycle_events,
+        log_name,
+        &[Format::Html, Format::Json],
+        None,
+    )?;
+    let [report, spans] = <[_; 2]>::try_from(contents).unwrap_or_else(|_| unreachable!());
+
+    Ok((
+        serve::Pages {
+            report: report.into(),
+            spans: spans.into(),
+            summary: summary.into(),
+        },
+        number_of_unselected_spans,
+    ))
+}
+
+/// Write the report, or the reports if it is split. Returns the paths of the
+/// written files, and the number of spans removed by the selection.
+pub(crate) fn write_reports(
+    options: &Options,
+    mut spans: Spans,
+    lifecycle_events: &[lifecycle::Event],
+    log_name: &str,
+    outputs: &[Output],
+) -> Result<(Vec<String>, usize), Error> {
+    let number_of_unselected_spans = prepare_spans(options, &mut spans);
+
+    let Some(SplitBy::Day) = options.split_by else {
+        write_report(options, spans, lifecycle_events, log_name, outputs, None)?;
+
+        return Ok((
+            outputs.iter().map(|output| output.path.clone()).collect(),
+            number_of_unselected_spans,
+        ));
+    };
+
+    let output_path = &outputs[0].path;
+
+    let days = split::by_day(spans, options.timezone, output_path);
+    let mut output_paths = vec![output_path.to_owned()];
+
+    for (nth, day) in days.iter().enumerate() {
+        let day_path = split::day_path(output_path, day);
+
+        write_report(
+            options,
+            day.spans.clone(),
+            lifecycle_events,
+            log_name,
+            &[Output {
+                format: Format::Html,
+                path: day_path.clone(),
+            }],
+            Some((day, days.get(nth + 1))),
+        )?;
+        output_paths.push(day_path);
+    }
+
+    let header = format!(
+        "{title}  <p>Split in {number_of_days} reports, one per calendar day.</p>
+",
+        title = title_to_html(options, log_name),
+        number_of_days = human::count(days.len()),
+    );
+    let meta = meta(
+        options,
+        filters::time_range_of(
+            days.iter()
+                .flat_map(|day| day.spans.values().flat_map(BTreeMap::values)),
+        ),
+        Vec::new(),
+    );
+    let output = index_to_html(options, &header, &meta, "day", &split::index_to_html(&days));
+
+    fs::write(output_path, output).map_err(Error::io(output_path))?;
+
+    Ok((output_paths, number_of_unselected_spans))
+}
+
+/// Render the spans in the format of every output, and write them.
+///
+/// `day` is the day of the report and the next one, if the report is split by
+/// day.
+fn write_report(
+    options: &Options,
+    spans: Spans,
+    lifecycle_events: &[lifecycle::Event],
+    log_name: &str,
+    outputs: &[Output],
+    day: Option<(&split::Day, Option<&split::Day>)>,
+) -> Result<(), Error> {
+    let formats = outputs
+        .iter()
+        .map(|output| output.format)
+        .collect::<Vec<_>>();
+    let (contents, external) =
+        render_report(options, spans, lifecycle_events, log_name, &formats, day)?;
+
+    for (output, content) in outputs.iter().zip(contents) {
+        match output.format {
+            Format::Otlp if otlp::is_endpoint(&output.path) => {
+                otlp::push(&output.path, &content).map_err(Error::io(&output.path))?;
+            }
+            Format::Html if let Some(external) = &external => {
+                // The assets are next to `index.html`, and written before it.
+                let directory = Path::new(&output.path)
+                    .parent()
+                    .unwrap_or_else(|| Path::new(""));
+
+                for (name, asset) in [
+                    (template::STYLE_FILE, &external.style),
+                    (template::SCRIPT_FILE, &external.script),
+                    (template::DATA_FILE, &external.data),
+                ] {
+                    write_output(&directory.join(name).to_string_lossy(), asset.as_bytes())?;
+                }
+
+                write_output(&output.path, &content)?;
+            }
+            _ => write_output(&output.path, &content)?,
+        }
+    }
+
+    Ok(())
+}
+
+/// Render the HTML report of the spans, with the default options.
+pub(crate) fn render_html(
+    spans: Spans,
+    lifecycle_events: &[lifecycle::Event],
+    log_name: &str,
+) -> String {
+    let (mut contents, _) = render_report(
+        &Options::default(),
+        spans,
+        lifecycle_events,
+        log_name,
+        &[Format::Html],
+        None,
+    )
+    .expect("The default options read no file");
+
+    String::from_utf8(contents.remove(0)).expect("The HTML report is valid UTF-8")
+}
+
+/// Render the spans in every format, and the external assets of the HTML
+/// report, if any. The spans are aggregated once for all the formats.
+/// Lifecycle events outside of the time range of the spans are ignored.
+fn render_report(
+    options: &Options,
+    mut spans: Spans,
+    lifecycle_events: &[lifecycle::Event],
+    log_name: &str,
+    formats: &[Format],
+    day: Option<(&split::Day, Option<&split::Day>)>,
+) -> Result<(Vec<Vec<u8>>, Option<template::External>), Error> {
+    let (mut smallest_start_at, mut largest_end_at) = filters::time_range(&spans).unzip();
+
+    // The spans extending beyond the day continue in the report of the next
+    // day.
+    let continues_into = day.and_then(|(day, next_day)| {
+        next_day.map(|next_day| (day.end_at(), next_day.file_name.as_str()))
+    });
+    let log_name = match day {
+        Some((day, _)) => format!("{log_name} ({})", day.start_at.format("%Y-%m-%d")),
+        None => log_name.to_owned(),
+    };
+
+    let mut header_notes = String::new();
+    let mut filters = options.selection.to_filters();
+
+    if let Some((day, _)) = day {
+        filters.push(Filter {
+            flag: "--split-by day".to_owned(),
+            description: format!(
+                "only the spans starting on {}",
+                day.start_at.format("%Y-%m-%d %:z")
+            ),
+        });
+    }
+
+    if let (Some((last_label, last)), Some(end_at)) = (&options.last, largest_end_at) {
+        let (window_start_at, number_of_removed_spans) =
+            filters::trim_to_last(&mut spans, *last, end_at);
+
+        header_notes.push_str(&format!(
+            "  <p>Trimmed to the spans starting within the last <code>{last_label}</code> of the log, from {from} to {to} ({removed} spans removed).</p>\n",
+            removed = human::count(number_of_removed_spans),
+            from = window_start_at.with_timezone(&options.timezone).to_rfc3339(),
+            to = end_at.with_timezone(&options.timezone).to_rfc3339(),
+        ));
+
+        filters.push(Filter {
+            flag: format!("--last {last_label}"),
+            description: format!(
+                "only the spans starting within the last {last_label} of the log; {number_of_removed_spans} spans removed"
+            ),
+        });
+
+        (smallest_start_at, largest_end_at) = filters::time_range(&spans).unzip();
+    }
+
+    header_notes.push_str(&merge::to_html(&spans));
+    header_notes.push_str(&options.slow_thresholds.to_html(&spans));
+
+    let gaps = gaps::detect(&spans, options.gap_threshold);
+    let time_range = smallest_start_at.zip(largest_end_at);
+    let hourly_buckets = time_range
+        .map(|range| buckets::hourly(&spans, &gaps, range, options.timezone))
+        .unwrap_or_default();
+
+    let smallest_start_at = smallest_start_at
+        .map(|date_time| date_time.timestamp_millis())
+        .unwrap_or_default();
+    let largest_end_at = largest_end_at
+        .map(|date_time| date_time.timestamp_millis())
+        .unwrap_or_default();
+    let end_at = largest_end_at.saturating_sub(smallest_start_at).to_string();
+    let anomalies = anomalies::detect(&spans, &options.anomalies_config);
+    let meta = meta(options, time_range, filters.clone());
+    let status_matrix = status_matrix::compute(&all_spans(&spans, &options.connection_order));
+    let traffic = traffic::compute(&spans);
+    let endpoint_stats = time_range
+        .map(|range| endpoint_stats::compute(&spans, range))
+        .unwrap_or_default();
+    let concurrency = time_range
+        .map(|range| concurrency::timeline(&spans, range))
+        .unwrap_or_default();
+    let lanes = concurrency::lanes(&spans);
+    let percentiles = Percentiles::compute(&spans);
+    let bandwidth = time_range
+        .map(|range| bandwidth::compute(&spans, range, options.bucket))
+        .unwrap_or_default();
+    let sync_overhead = sync_overhead::compute(&spans);
+    let initial_syncs = initial_sync::detect(&spans, options.timezone);
+    let lifecycle_events = lifecycle_events
+        .iter()
+        .filter(|event| {
+            time_range.is_some_and(|(start_at, end_at)| (start_at..=end_at).contains(&event.at))
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+    let summaries = dataset::Summaries {
+        initial_syncs: &initial_syncs,
+        status_matrix: &status_matrix,
+        endpoint_stats: &endpoint_stats,
+        sync_overhead: &sync_overhead,
+        traffic: &traffic,
+        concurrency: &concurrency,
+        bandwidth: &bandwidth,
+        lifecycle_events: &lifecycle_events,
+        payload_size_outliers: anomalies.number_of_payload_size_outliers(),
+    };
+    let render_html = || {
+        let (displayed_spans, number_of_hidden_spans) =
+            displayed_spans(options, &spans, &anomalies);
+
+        let displayed_columns = if options.force_columns {
+            options.columns.clone()
+        } else {
+            columns::without_empty(&options.columns, &displayed_spans, &lanes)
+        };
+        let excerpts = if options.with_context > 0 && !options.virtual_table {
+            let paths = options
+                .sources
+                .iter()
+                .map(|source| source.name.clone())
+                .collect::<Vec<_>>();
+
+            let mut excerpts = context::collect(&paths, &spans).map_err(|error| Error::Io {
+                path: paths.join(", "),
+                error,
+            })?;
+
+            if let Some(redaction) = &options.redaction {
+                redaction.excerpts(&mut excerpts);
+            }
+
+            excerpts
+        } else {
+            context::Excerpts::new()
+        };
+        // A gap is rendered before the sync ending it.
+        let gaps_before = gaps
+            .iter()
+            .map(|gap| ((&gap.connection_id, gap.request_id), gap))
+            .collect::<BTreeMap<_, _>>();
+        let row = |connection_id: &ConnectionId,
+                   request_id: RequestId,
+                   span: &Span,
+                   origin: i64| {
+            let gap = gaps_before
+                .get(&(connection_id, request_id))
+                .map(|gap| gap.to_html(&displayed_columns, origin))
+                .unwrap_or_default();
+            let cells = displayed_columns
+                .iter()
+                .map(|column| {
+                    format!(
+                        "      {}\n",
+                        column.cell(
+                            connection_id,
+                            request_id,
+                            span,
+                            origin,
+                            options.timezone,
+                            excerpts.get(&(connection_id.clone(), request_id)),
+                            lanes.get(connection_id, request_id),
+                        )
+                    )
+                })
+                .collect::<String>();
+
+            format!(
+                "{gap}    <tr id=\"{connection_id}-{request_id}\"{attributes}>\n{cells}    </tr>\n",
+                connection_id = html::escape(connection_id),
+                attributes = row_attributes(
+                    options,
+                    (connection_id, request_id, span),
+                    &anomalies,
+                    &initial_syncs,
+                    &percentiles,
+                    continues_into,
+                ),
+            )
+        };
+        // A separator is rendered before the first span of each restarted
+        // process of the app, when the rows are in the order of time.
+        let rows = |displayed_spans: &[(&ConnectionId, RequestId, &Span)], origin: i64| {
+            let mut previous_process_nth = None;
+
+            displayed_spans
+                .iter()
+                .map(|(connection_id, request_id, span)| {
+                    let restart = if options.order == Order::Start
+                        && previous_process_nth
+                            .is_some_and(|process_nth| process_nth != span.process_nth)
+                    {
+                        format!(
+                            "    <tr class=\"restart\"><td colspan=\"{number_of_columns}\">The app has restarted, process {process_nth} starts at {start_at}</td></tr>\n",
+                            number_of_columns = displayed_columns.len(),
+                            process_nth = span.process_nth,
+                            start_at = span.start_at.with_timezone(&options.timezone).to_rfc3339(),
+                        )
+                    } else {
+                        String::new()
+                    };
+                    previous_process_nth = Some(span.process_nth);
+
+                    restart + &row(connection_id, *request_id, span, origin)
+                })
+                .collect::<String>()
+        };
+        let tbody = match options.origin {
+            // The virtual table renders the rows from the dataset instead.
+            _ if options.virtual_table => format!("  <tbody style=\"--end-at: {end_at}\">\n    \n  </tbody>"),
+            None => format!(
+                "  <tbody style=\"--end-at: {end_at}\">\n    {rows}\n  </tbody>",
+                rows = rows(&displayed_spans, smallest_start_at),
+            ),
+            // One section per connection, each with its own timeline.
+            Some(Origin::PerConnection) => displayed_spans
+                .chunk_by(|(left, ..), (right, ..)| left == right)
+                .map(|displayed_spans_for_connection_id| {
+                    let connection_id = displayed_spans_for_connection_id[0].0;
+                    let (start_at, end_at) = filters::time_range_of(spans[connection_id].values())
+                        .expect("A displayed connection has at least one span");
+                    let origin = start_at.timestamp_millis();
+                    let number_of_errors = spans[connection_id]
+                        .values()
+                        .filter(|span| !span.is_pending() && !span.is_successful())
+                        .count();
+
+                    format!(
+                        "  <tbody style=\"--end-at: {end_at}\">
+    <tr class=\"origin\"><th scope=\"rowgroup\" colspan=\"{number_of_columns}\"><button type=\"button\" aria-expanded=\"true\"><code>{connection_id}</code></button> starts at {start_at} (requests: {number_of_requests}, total duration: {total_duration}, errors: {number_of_errors})</th></tr>
+{rows}  </tbody>
+",
+                        connection_id = html::escape(connection_id),
+                        number_of_requests = human::count(spans[connection_id].len()),
+                        total_duration = human::duration(
+                            spans[connection_id]
+                                .values()
+                                .map(|span| span.duration)
+                                .sum::<TimeDelta>()
+                        ),
+                        number_of_errors = human::count(number_of_errors),
+                        end_at = end_at.timestamp_millis().saturating_sub(origin),
+                        number_of_columns = displayed_columns.len(),
+                        start_at = start_at.with_timezone(&options.timezone).to_rfc3339(),
+                        rows = rows(displayed_spans_for_connection_id, origin),
+                    )
+                })
+                .collect::<String>(),
+        };
+        // The bars of a per-connection timeline don't share an origin, and the
+        // virtual table has no rows to zoom on.
+        let zoom = match options.origin {
+            None if !options.virtual_table => zoom::compute(
+                &displayed_spans,
+                smallest_start_at,
+                options.timezone,
+                options.timeline_resolution,
+            ),
+            _ => None,
+        };
+        let ticks = match options.origin {
+            None => time_range.map(|range| ticks::compute(range, options.timezone)),
+            Some(Origin::PerConnection) => None,
+        };
+        let dataset = if options.virtual_table {
+            dataset::to_json(
+                &displayed_spans,
+                smallest_start_at,
+                options.timezone,
+                &anomalies,
+                &meta,
+                &summaries,
+                &options.duration_thresholds,
+                &options.slow_thresholds,
+                &lanes,
+                &percentiles,
+                options.latency_heatmap,
+            )
+        } else {
+            "null".to_owned()
+        };
+
+        let mut header_notes = header_notes.clone();
+
+        if let Some(every) = options.every {
+            header_notes.push_str(&format!(
+                "  <p>Sampled to 1 span out of every {every} per connection, plus all errors and anomalies: {shown} rows shown out of {total}. Statistics are computed over all the spans.</p>\n",
+                shown = human::count(displayed_spans.len()),
+                total = human::count(spans.values().map(BTreeMap::len).sum::<usize>()),
+            ));
+        }
+
+        if let Some(hide) = &options.hide {
+            header_notes.push_str(&format!(
+                "  <p class=\"hidden-rows\">{number_of_hidden_spans} rows hidden by <code>--hide {hide}</code>. Statistics are computed over all the spans.</p>\n",
+                number_of_hidden_spans = human::count(number_of_hidden_spans),
+                hide = html::escape(&hide.to_string()),
+            ));
+        }
+
+        let summary = summary_to_html(options, &spans, time_range, &hourly_buckets, &summaries);
+
+        let header = format!(
+            "{title}{header_notes}",
+            title = title_to_html(options, &log_name)
+        );
+        let filters = displayed_filters(options, filters.clone());
+
+        let meta = Meta {
+            filters,
+            ..meta.clone()
+        };
+        let mut duration_bands = options.duration_thresholds.legend_to_html();
+
+        if options.latency_heatmap {
+            duration_bands.push_str(percentiles::legend_to_html());
+        }
+
+        let values = [
+            ("theme", options.theme.as_str()),
+            ("refresh", &*refresh_to_html(options)),
+            ("title", &*page_title(options)),
+            ("header", &*header),
+            ("meta", &*meta.to_html()),
+            (
+                "rollup",
+                match options.rollup {
+                    Some(Rollup::Day) => "day",
+                    None => "",
+                },
+            ),
+            ("summary", &*summary),
+            ("anomalies", &*anomalies.to_html()),
+            (
+                "columns",
+                &*displayed_columns
+                    .iter()
+                    .map(Column::as_str)
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            ),
+            ("duration_bands", &*duration_bands),
+            ("headers", &*columns::headers_to_html(&displayed_columns)),
+            ("dataset", &*dataset),
+            ("status_matrix", &*status_matrix.to_json()),
+            ("concurrency", &*concurrency.to_json()),
+            ("bandwidth", &*bandwidth.to_json()),
+            ("zoom", &*zoom::to_json(zoom.as_ref())),
+            (
+                "ticks",
+                &*ticks.map_or_else(|| "null".to_owned(), |ticks| ticks.to_json()),
+            ),
+            (
+                "start_at_iso",
+                &*time_range
+                    .map(|(start_at, _)| {
+                        start_at
+                            .with_timezone(&options.timezone)
+                            .to_rfc3339_opts(SecondsFormat::Millis, true)
+                    })
+                    .unwrap_or_default(),
+            ),
+            (
+                "lifecycle",
+                &*serde_json::to_string(&lifecycle_events)
+                    .expect("Failed to serialize the lifecycle events"),
+            ),
+            ("tbody", &*tbody),
+        ];
+
+        Ok(match options.assets {
+            Assets::Inline => (options.template.render(&values), None),
+            Assets::External => {
+                let external = options.template.render_external(&values);
+
+                (external.index.clone(), Some(external))
+            }
+        })
+    };
+    let mut external = None;
+
+    let contents = formats
+        .iter()
+        .map(|format| {
+            Ok(match format {
+                Format::Html => {
+                    let (index, assets) = render_html()?;
+                    external = assets;
+
+                    index.into_bytes()
+                }
+                // One row per span, unless grouped by hour or by room.
+                Format::Csv => match options.group_by {
+                    Some(GroupBy::Hour) => buckets::to_csv(&hourly_buckets).into_bytes(),
+                    Some(GroupBy::Room) => rooms::to_csv(&rooms::per_room(&spans)).into_bytes(),
+                    None => csv::to_csv(
+                        &sorted_spans(&spans, options),
+                        smallest_start_at,
+                        options.timezone,
+                        &options.columns,
+                    )
+                    .into_bytes(),
+                },
+                Format::Xlsx => xlsx::to_xlsx(
+                    &sorted_spans(&spans, options),
+                    &buckets::per_kind(&spans),
+                    &hourly_buckets,
+                    options.timezone,
+                )
+                .unwrap_or_else(|error| panic!("Failed to build the workbook: {error}")),
+                Format::Parquet => parquet::to_parquet(&sorted_spans(&spans, options))
+                    .unwrap_or_else(|error| panic!("Failed to build the Parquet file: {error}")),
+                Format::Har => har::to_json(&spans).into_bytes(),
+                Format::Otlp => otlp::to_json(&spans).into_bytes(),
+                Format::Term => term::to_text(
+                    &sorted_spans(&spans, options),
+                    smallest_start_at,
+                    largest_end_at,
+                    options.terminal,
+                )
+                .into_bytes(),
+                Format::Grafana => time_range
+                    .map(|range| grafana::to_json(&spans, range))
+                    .unwrap_or_else(|| "[]".to_owned())
+                    .into_bytes(),
+                Format::Influx => influx::to_line_protocol(
+                    &all_spans(&spans, &options.connection_order),
+                    &spans,
+                    time_range,
+                )
+                .map_err(Error::Input)?
+                .into_bytes(),
+                Format::Json => json::to_json(
+                    &sorted_spans(&spans, options),
+                    smallest_start_at,
+                    options.timezone,
+                    &anomalies,
+                    &meta,
+                    &summaries,
+                )
+                .into_bytes(),
+            })
+        })
+        .collect::<Result<_, Error>>()?;
+
+    Ok((contents, external))
+}
+
+/// The spans displayed in the rows of the HTML report, in their order, and
+/// the number of spans hidden by `--hide`.
+fn displayed_spans<'a>(
+    options: &Options,
+    spans: &'a Spans,
+    anomalies: &anomalies::Anomalies<'_>,
+) -> (Vec<(&'a ConnectionId, RequestId, &'a Span)>, usize) {
+    let mut displayed_spans = options
+        .connection_order
+        .sort(spans)
+        .into_iter()
+        // The rollup replaces the detailed rows.
+        .filter(|_| options.rollup.is_none())
+        .flat_map(|(connection_id, spans)| {
+            spans
+                .iter()
+                .enumerate()
+                // Sampling never hides errors or anomalies.
+                .filter(|(nth, (request_id, span))| {
+                    options.every.is_none_or(|every| nth % every == 0)
+                        || !span.is_successful()
+                        || !anomalies.marks(connection_id, **request_id).is_empty()
+                })
+                .map(move |(_, (request_id, span))| (connection_id, *request_id, span))
+        })
+        .collect::<Vec<_>>();
+    let number_of_hidden_spans = match &options.hide {
+        Some(hide) => {
+            let before = displayed_spans.len();
+            displayed_spans.retain(|(_, _, span)| !hide.matches(span));
+
+            before - displayed_spans.len()
+        }
+        None => 0,
+    };
+
+    options.order.sort(&mut displayed_spans);
+
+    (displayed_spans, number_of_hidden_spans)
+}
+
+/// The attributes of the row of a span, read by the style and the script of
+/// the HTML report.
+fn row_attributes(
+    options: &Options,
+    (connection_id, request_id, span): (&ConnectionId, RequestId, &Span),
+    anomalies: &anomalies::Anomalies<'_>,
+    initial_syncs: &initial_sync::InitialSyncs,
+    percentiles: &Percentiles,
+    continues_into: Option<(DateTime<FixedOffset>, &str)>,
+) -> String {
+    format!(
+        " data-endpoint=\"{endpoint}\" data-status-family=\"{status_family}\" data-anomalies=\"{anomalies}\" data-start-iso=\"{start_iso}\" data-end-iso=\"{end_iso}\"{initial_sync}{restarted_as}{retry_of}{pos_stalled}{pos_reset}{iteration}{duration_band}{latency_heat}{continues_in}{warnings}{slow}",
+        endpoint = html::escape(&span.endpoint()),
+        status_family = span.status_family(),
+        anomalies = anomalies.marks(connection_id, request_id),
+        start_iso = span.start_at.to_rfc3339_opts(SecondsFormat::Millis, true),
+        end_iso =
+            (span.start_at + span.duration).to_rfc3339_opts(SecondsFormat::Millis, true),
+        initial_sync = if initial_syncs.contains(connection_id, request_id) {
+            " data-initial-sync"
+        } else {
+            ""
+        },
+        restarted_as = span
+            .restarted_as
+            .as_ref()
+            .map(|restarted_as| {
+                format!(" data-restarted-as=\"{}\"", html::escape(restarted_as))
+            })
+            .unwrap_or_default(),
+        retry_of = anomalies
+            .retry_of(connection_id, request_id)
+            .map(|retry| {
+                format!(
+                    " data-retry-of=\"{previous}\" title=\"Retry of REQ-{previous}, {latency} since the first attempt\"",
+                    previous = retry.previous,
+                    latency = human::duration(retry.latency),
+                )
+            })
+            .unwrap_or_default(),
+        pos_stalled = if span.pos_stalled {
+            " data-pos-stalled=\"true\""
+        } else {
+            ""
+        },
+        pos_reset = if span.pos_reset {
+            " data-pos-reset=\"true\""
+        } else {
+            ""
+        },
+        iteration = if span.iteration > 0 {
+            format!(
+                " data-iteration=\"{iteration}\" data-iteration-parity=\"{parity}\"",
+                iteration = span.iteration,
+                parity = if span.iteration.is_multiple_of(2) {
+                    "even"
+                } else {
+                    "odd"
+                },
+            )
+        } else {
+            String::new()
+        },
+        duration_band = options
+            .duration_thresholds
+            .band(span)
+            .map(|band| format!(" data-duration-band=\"{band}\""))
+            .unwrap_or_default(),
+        latency_heat = percentiles
+            .heat(connection_id, request_id)
+            .filter(|_| options.latency_heatmap)
+            .map(|heat| format!(" data-latency-heat=\"{heat}\""))
+            .unwrap_or_default(),
+        warnings = if span.warnings.is_empty() {
+            String::new()
+        } else {
+            format!(" data-warnings=\"{}\"", span.warnings.len())
+        },
+        continues_in = continues_into
+            .filter(|(end_at, _)| span.start_at + span.duration > *end_at)
+            .map(|(_, next_file_name)| format!(" data-continues-in=\"{next_file_name}\""))
+            .unwrap_or_default(),
+        slow = if options.slow_thresholds.is_slow(span) {
+            " class=\"slow\""
+        } else {
+            ""
+        },
+    )
+}
+
+/// Render the summary section of the HTML report.
+fn summary_to_html(
+    options: &Options,
+    spans: &Spans,
+    time_range: Option<(DateTime<FixedOffset>, DateTime<FixedOffset>)>,
+    hourly_buckets: &[buckets::Bucket],
+    summaries: &dataset::Summaries<'_>,
+) -> String {
+    let daily = match (options.rollup, time_range) {
+        (Some(Rollup::Day), Some(range)) => {
+            buckets::daily_to_html(&buckets::daily(spans, range, options.timezone))
+        }
+        _ => String::new(),
+    };
+    let rooms = match options.group_by {
+        Some(GroupBy::Room) => rooms::to_html(&rooms::per_room(spans)),
+        _ => String::new(),
+    };
+    format!(
+        "<section class=\"summary\">
+  <h2>Summary</h2>
+{daily}{rooms}{initial_syncs}{status_matrix}{endpoint_stats}{sync_overhead}{hourly}{traffic}{media}{intermediaries}{app_states}{errcodes}{rate_limits}</section>
+",
+        intermediaries = intermediary::to_html(spans),
+        app_states = lifecycle::to_html(spans, summaries.lifecycle_events),
+        initial_syncs = summaries.initial_syncs.to_html(),
+        status_matrix = summaries.status_matrix.to_html(),
+        endpoint_stats = summaries.endpoint_stats.to_html(),
+        sync_overhead = summaries.sync_overhead.to_html(),
+        traffic = summaries.traffic.to_html(),
+        media = media::to_html(spans),
+        hourly = buckets::to_html(hourly_buckets),
+        errcodes = errcodes::to_html(spans),
+        rate_limits = rate_limits::to_html(spans),
+    )
+}
+
+/// The filters of the spans, and the ones of the rows displayed in the HTML
+/// report.
+fn displayed_filters(options: &Options, mut filters: Vec<Filter>) -> Vec<Filter> {
+    if let Some(every) = options.every {
+        filters.push(Filter {
+            flag: format!("--every {every}"),
+            description: format!(
+                "only 1 span out of every {every} per connection is displayed, plus all errors and anomalies"
+            ),
+        });
+    }
+
+    if let Some(hide) = &options.hide {
+        filters.push(Filter {
+            flag: format!("--hide {hide}"),
+            description: "the matching rows are hidden from the table".to_owned(),
+        });
+    }
+
+    if let Some(Rollup::Day) = options.rollup {
+        filters.push(Filter {
+            flag: "--rollup day".to_owned(),
+            description: "the detailed rows are replaced by daily aggregates".to_owned(),
+        });
+    }
+
+    filters
+}
+
+/// Write an output, to the standard output if its path is `-`, or atomically.
+fn write_output(path: &str, content: &[u8]) -> Result<(), Error> {
+    if path == STDIO {
+        let mut stdout = io::stdout().lock();
+
+        return stdout
+            .write_all(content)
+            .and_then(|()| stdout.flush())
+            .map_err(Error::io("(stdout)"));
+    }
+
+    // Write to a temporary file first, so that a report regenerated in live
+    // mode is never read half-written.
+    let temporary_path = format!("{path}.tmp");
+
+    fs::write(&temporary_path, content)
+        .and_then(|()| fs::rename(&temporary_path, path))
+        .map_err(Error::io(path))
+}
+
+/// Collect the metadata of a report covering `time_range`.
+pub(crate) fn meta(
+    options: &Options,
+    time_range: Option<(DateTime<FixedOffset>, DateTime<FixedOffset>)>,
+    filters: Vec<Filter>,
+) -> Meta<'_> {
+    let to_rfc3339 =
+        |date_time: DateTime<FixedOffset>| date_time.with_timezone(&options.timezone).to_rfc3339();
+
+    Meta {
+        title: options.title.as_deref(),
+        sources: &options.sources,
+        start_at: time_range.map(|(start_at, _)| to_rfc3339(start_at)),
+        end_at: time_range.map(|(_, end_at)| to_rfc3339(end_at)),
+        tool_version: meta::TOOL_VERSION,
+        generated_at: Utc::now()
+            .with_timezone(&options.timezone)
+            .to_rfc3339_opts(SecondsFormat::Secs, true),
+        filters,
+    }
+}
+
+/// Render the main title of a report.
+fn title_to_html(options: &Options, log_name: &str) -> String {
+    match &options.title {
+        Some(title) => format!("  <h1>{}</h1>\n", html::escape(title)),
+        None => format!("  <h1>Analyse of <code>{log_name}</code></h1>\n"),
+    }
+}
+
+/// Render a page indexing other reports, e.g. the reports of the days, with
+/// `summary` instead of the spans.
+pub(crate) fn index_to_html(
+    options: &Options,
+    header: &str,
+    meta: &Meta<'_>,
+    rollup: &str,
+    summary: &str,
+) -> String {
+    options.template.render(&[
+        ("theme", options.theme.as_str()),
+        ("refresh", ""),
+        ("title", &*page_title(options)),
+        ("header", header),
+        ("meta", &*meta.to_html()),
+        ("rollup", rollup),
+        ("summary", summary),
+        ("anomalies", ""),
+        ("columns", ""),
+        ("duration_bands", ""),
+        ("headers", ""),
+        ("dataset", "null"),
+        ("status_matrix", "null"),
+        ("concurrency", &*concurrency::Timeline::default().to_json()),
+        ("bandwidth", &*bandwidth::Bandwidth::default().to_json()),
+        ("zoom", "null"),
+        ("ticks", "null"),
+        ("start_at_iso", ""),
+        ("lifecycle", "[]"),
+        ("tbody", ""),
+    ])
+}
+
+/// Render the `<meta>` element reloading the report, if any.
+fn refresh_to_html(options: &Options) -> String {
+    options
+        .refresh
+        .map(|refresh| {
+            format!(
+                "  <meta http-equiv=\"refresh\" content=\"{}\" />\n",
+                refresh.as_secs().max(1)
+            )
+        })
+        .unwrap_or_default()
+}
+
+/// Title of the page, as displayed by the browser.
+fn page_title(options: &Options) -> String {
+    options
+        .title
+        .as_deref()
+        .map(html::escape)
+        .unwrap_or_else(|| "Network viewer".to_owned())
+}
+
+/// List all the spans, ordered by connection ID then by request ID.
+fn all_spans<'a>(
+    spans: &'a Spans,
+    connection_order: &ConnectionOrder,
+) -> Vec<(&'a ConnectionId, RequestId, &'a Span)> {
+    connection_order
+        .sort(spans)
+        .into_iter()
+        .flat_map(|(connection_id, spans)| {
+            spans
+                .iter()
+                .map(move |(request_id, span)| (connection_id, *request_id, span))
+        })
+        .collect()
+}
+
+/// List all the spans, in the order of the detailed rows.
+fn sorted_spans<'a>(
+    spans: &'a Spans,
+    options: &Options,
+) -> Vec<(&'a ConnectionId, RequestId, &'a Span)> {
+    let mut spans = all_spans(spans, &options.connection_order);
+    options.order.sort(&mut spans);
+
+    spans
+}
+
+/// Period, or room, by which rows are grouped in tabular outputs.
+#[derive(Clone, Copy)]
+pub(crate) enum GroupBy {
+    Hour,
+    Room,
+}
+
+/// Order of the detailed rows, and of the spans of the exports.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Order {
+    /// By start, across all the connections.
+    Start,
+    /// The longest first.
+    Duration,
+    /// The most bytes sent and received first.
+    Size,
+    /// By connection ID, then by request ID.
+    Connection,
+}
+
+impl Order {
+    /// Sort spans ordered by connection ID then by request ID. The sort is
+    /// stable: spans starting at the same time, or lasting as long, stay
+    /// ordered by connection ID then by request ID.
+    fn sort(self, spans: &mut [(&ConnectionId, RequestId, &Span)]) {
+        match self {
+            Self::Start => spans.sort_by_key(|(_, _, span)| span.start_at),
+            Self::Duration => spans.sort_by_key(|(_, _, span)| Reverse(span.duration)),
+            Self::Size => spans.sort_by_key(|(_, _, span)| {
+                Reverse(
+                    span.request_bytes().unwrap_or_default()
+                        + span.response_bytes().unwrap_or_default(),
+                )
+            }),
+            Self::Connection => {}
+        }
+    }
+}
+
+/// Period by which the report is split in several files.
+#[derive(Clone, Copy)]
+pub(crate) enum SplitBy {
+    Day,
+}
+
+/// Origin of the bars of the timeline, when not the start of the first span.
+#[derive(Clone, Copy)]
+pub(crate) enum Origin {
+    /// Each connection starts at its own first span.
+    PerConnection,
+}
+
+/// Period by which the detailed rows are replaced by aggregates.
+#[derive(Clone, Copy)]
+pub(crate) enum Rollup {
+    Day,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SpansBuilder, size::Size};
+
+    fn args(arguments: &[&str]) -> Args {
+        parse_args(
+            ["network-viewer"]
+                .iter()
+                .chain(arguments)
+                .map(|argument| (*argument).to_owned()),
+        )
+        .expect("The arguments are parsed")
+    }
+
+    /// The usage error of the arguments once parsed and validated, against
+    /// the outputs too.
+    fn usage_error(arguments: &[&str]) -> Option<String> {
+        let mut args = args(arguments);
+        let result = validate(&args)
+            .and_then(|()| outputs(&mut args))
+            .and_then(|outputs| validate_outputs(&args, &outputs));
+
+        match result {
+            Ok(()) => None,
+            Err(Error::Usage(message)) => Some(message),
+            Err(error) => panic!("Unexpected error: {error:?}"),
+        }
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let parsed = args(&[
+            "serve", "--port", "9000", "--sort", "duration", "a.log", "b.log",
+        ]);
+
+        assert_eq!(parsed.subcommand, Some(Subcommand::Serve));
+        assert_eq!(parsed.port, Some(9000));
+        assert_eq!(parsed.order, Some(Order::Duration));
+        assert_eq!(parsed.positionals, ["a.log", "b.log"]);
+
+        // Only the first positional argument is a subcommand.
+        let parsed = args(&["diff.log", "diff", "report.html"]);
+
+        assert_eq!(parsed.subcommand, None);
+        assert_eq!(parsed.positionals, ["diff.log", "diff", "report.html"]);
+
+        // The config files are only read by `run`.
+        let parsed = args(&["--config", "missing.toml", "a.log", "r.html"]);
+
+        assert_eq!(parsed.configs, ["missing.toml"]);
+
+        // The arguments after `--help` aren't parsed.
+        assert!(args(&["--help", "--unknown"]).help);
+
+        for arguments in [&["--unknown"][..], &["--port", "none"], &["--sort"]] {
+            assert!(
+                matches!(
+                    parse_args(
+                        ["network-viewer"]
+                            .iter()
+                            .chain(arguments)
+                            .map(|argument| (*argument).to_owned())
+                    ),
+                    Err(Error::Usage(_))
+                ),
+                "{arguments:?} is parsed"
+            );
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        for arguments in [
+            &["session.log", "report.html"][..],
+            &["serve", "--port", "9000", "session.log"],
+            &["check", "--max-error-rate", "1%", "session.log"],
+            &["diff", "a.log", "b.log", "diff.html"],
+            &[
+                "--stream",
+                "--from",
+                "2024-06-01T09:13:00Z",
+                "session.log",
+                "spans.csv",
+            ],
+            &[
+                "--origin",
+                "per-connection",
+                "--sort",
+                "connection",
+                "a.log",
+                "r.html",
+            ],
+            &["--columns", "category,fields,retry_after", "a.log", "r.csv"],
+            &["--columns", "concurrency", "a.log", "r.html"],
+        ] {
+            assert_eq!(usage_error(arguments), None, "{arguments:?} is invalid");
+        }
+
+        for (arguments, error) in [
+            (
+                &["--port", "9000", "session.log", "report.html"][..],
+                "`--port` requires `serve`",
+            ),
+            (
+                &["--max-p95-ms", "2000", "session.log", "report.html"],
+                "require `check`",
+            ),
+            (
+                &["serve", "-o", "report.html", "session.log"],
+                "`serve` serves the report from memory",
+            ),
+            (
+                &["serve", "--stream", "session.log"],
+                "`serve` serves the report from memory",
+            ),
+            (&["check", "session.log"], "`check` expects budgets"),
+            (
+                &["check", "--max-unfinished", "0", "--follow", "session.log"],
+                "`check` writes no report",
+            ),
+            (
+                &["diff", "--live", "a.log", "b.log", "diff.html"],
+                "`diff` cannot be combined",
+            ),
+            (
+                &["cohort", "--stdin", "logs", "cohort.html"],
+                "`cohort` cannot be combined",
+            ),
+            (
+                &["--watch", "logs", "--stream", "report.csv"],
+                "`--watch` cannot be combined",
+            ),
+            (
+                &["--columns", "conn,concurrency,tls", "a.log", "r.csv"],
+                "the CSV export has no field for `concurrency`",
+            ),
+            (
+                &["--watch-glob", "*.log", "session.log", "report.html"],
+                "`--watch-glob` requires `--watch`",
+            ),
+            (
+                &[
+                    "diff",
+                    "--stats-out",
+                    "stats.json",
+                    "a.log",
+                    "b.log",
+                    "diff.html",
+                ],
+                "`--stats-out` summarizes",
+            ),
+            (
+                &["--tui", "-o", "report.html", "session.log"],
+                "`--tui` browses the spans",
+            ),
+            (
+                &["--tui", "-"],
+                "`--tui` reads the keys from the standard input",
+            ),
+            (
+                &[
+                    "--origin",
+                    "per-connection",
+                    "--sort",
+                    "start",
+                    "a.log",
+                    "r.html",
+                ],
+                "requires `--sort connection`",
+            ),
+            (
+                &["--redact", "--no-redact", "session.log", "report.html"],
+                "`--redact` cannot be combined",
+            ),
+            (
+                &[
+                    "--otlp-endpoint",
+                    "http://localhost:4318",
+                    "--live",
+                    "session.log",
+                ],
+                "`--otlp-endpoint` cannot be combined",
+            ),
+            (
+                &["--assets", "external", "session.log", "-"],
+                "`--assets external` writes files",
+            ),
+            (
+                &["--split-by", "day", "session.log", "report.csv"],
+                "only supports a single HTML output",
+            ),
+            (
+                &["--stream", "session.log", "report.html"],
+                "only supports a single CSV output",
+            ),
+            (
+                &["--stream", "--last", "5m", "session.log", "spans.csv"],
+                "`--stream` cannot be combined",
+            ),
+            (
+                &["--stream", "--from", "+5m", "session.log", "spans.csv"],
+                "only supports datetimes",
+            ),
+            (&["--follow", "session.log", "-"], "not to `-`"),
+        ] {
+            let message = usage_error(arguments);
+
+            assert!(
+                message
+                    .as_ref()
+                    .is_some_and(|message| message.contains(error)),
+                "{arguments:?} fails with {message:?}, not `{error}`"
+            );
+        }
+    }
+
+    #[test]
+    fn test_order() {
+        let uri = "https://example.org/_matrix/client/v3/sync";
+        // 2 interleaved connections.
+        let mut spans = SpansBuilder::default();
+        spans.span("encryption", 1, uri, Some(200), 10, 50);
+        spans.span("room-list", 0, uri, Some(200), 0, 20);
+        spans.span("room-list", 4, uri, Some(200), 40, 50);
+        // `2kB` is more bytes than `1.5 KiB`.
+        spans
+            .span("room-list", 2, uri, Some(200), 10, 10)
+            .response_size = Some(Size::new("2kB"));
+        spans
+            .span("encryption", 3, uri, Some(200), 30, 5)
+            .response_size = Some(Size::new("1.5 KiB"));
+        let spans = spans.build();
+        let sorted = |order: Order| {
+            let mut spans = all_spans(&spans, &ConnectionOrder::default());
+            order.sort(&mut spans);
+
+            spans
+                .into_iter()
+                .map(|(connection_id, request_id, _)| (connection_id.as_str(), request_id))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            sorted(Order::Start),
+            [
+                ("room-list", 0),
+                ("encryption", 1),
+                ("room-list", 2),
+                ("encryption", 3),
+                ("room-list", 4),
+            ]
+        );
+        assert_eq!(
+            sorted(Order::Duration),
+            [
+                ("encryption", 1),
+                ("room-list", 4),
+                ("room-list", 0),
+                ("room-list", 2),
+                ("encryption", 3),
+            ]
+        );
+        assert_eq!(
+            sorted(Order::Size),
+            [
+                ("room-list", 2),
+                ("encryption", 3),
+                ("encryption", 1),
+                ("room-list", 0),
+                ("room-list", 4),
+            ]
+        );
+        assert_eq!(
+            sorted(Order::Connection),
+            [
+                ("encryption", 1),
+                ("encryption", 3),
+                ("room-list", 0),
+                ("room-list", 2),
+                ("room-list", 4),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_escape_log_values() {
+        let mut spans = SpansBuilder::default();
+        let span = spans.span(
+            "<em>room-list</em>",
+            1,
+            "https://example.org/<script>alert(1)</script>?{tbody}",
+            Some(200),
+            0,
+            10,
+        );
+        span.method = "<b>GET</b>".to_owned();
+        span.request_size = Some(Size::new("<i>1 KiB</i>"));
+        let spans = spans.build();
+
+        let html = render_html(spans, &[], "session.log");
+
+        // The JSON embedded in the report has its closing tags escaped too.
+        for markup in [
+            "<script>alert(1)</script>",
+            "<b>GET</b>",
+            "<i>1 KiB</i>",
+            "<em>room-list</em>",
+        ] {
+            assert!(!html.contains(markup), "`{markup}` is injected");
+        }
+
+        assert!(html.contains("/&lt;script&gt;alert(1)&lt;/script&gt;?{tbody}"));
+        assert!(html.contains("<code>&lt;b&gt;GET&lt;/b&gt;</code>"));
+    }
+
+    #[test]
+    fn test_escape_gap_connection_id() {
+        let uri = "https://example.org/_matrix/client/unstable/org.matrix.simplified_msc3575/sync";
+        let connection_id = "<img src=x onerror=alert(1)>";
+        let mut spans = SpansBuilder::default();
+        spans.span(connection_id, 1, uri, Some(200), 0, 1_000);
+        spans.span(connection_id, 2, uri, Some(200), 20_000, 1_000);
+        let spans = spans.build();
+
+        let html = render_html(spans, &[], "session.log");
+
+        assert!(html.contains("No sync in flight"));
+        assert!(
+            !html.contains(connection_id),
+            "the connection ID is injected"
+        );
+        assert!(html.contains("<code>&lt;img src=x onerror=alert(1)&gt;</code>"));
+    }
+}
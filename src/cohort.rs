@@ -0,0 +1,593 @@
+//! Compare the logs of a cohort, e.g. the rageshakes of a release, to tell
+//! whether the release has regressed the network behaviour:
+//! `network-viewer cohort <directory> <output_path>`.
+//!
+//! Every entry of the directory is a log: a log file, a gzipped log file, a
+//! zip archive, or a directory of log files like an uploaded rageshake. The
+//! entries are parsed in parallel, and a report is written for each of them
+//! in a directory named after the output. The cohort report compares their
+//! headline metrics, and summarizes the distribution of each metric across
+//! the cohort.
+//!
+//! An entry which fails, e.g. a corrupt archive or a log without any matched
+//! line, is reported as such, without failing the others.
+
+use std::{
+    any::Any,
+    collections::BTreeMap,
+    fs,
+    io::{Cursor, Read},
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+    sync::{
+        Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    thread,
+};
+
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use flate2::read::MultiGzDecoder;
+
+use crate::{
+    Spans, buckets,
+    cli::{self, Options, Stats, index_to_html, write_reports},
+    dedup::Deduplicator,
+    error::Error,
+    filters,
+    format::{Format, Output},
+    gaps, html, human,
+    meta::SourceFile,
+    parser::Parser,
+    source::{self, GZIP_MAGIC},
+    stats,
+};
+
+/// Magic number of the zip archives.
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
+/// Headline metrics of a log.
+struct Metrics {
+    requests: usize,
+    /// From the first request to the response of the first successful sync.
+    time_to_first_sync: Option<TimeDelta>,
+    sync_p95: Option<TimeDelta>,
+    /// Ratio of failed requests, between 0 and 1.
+    error_rate: f64,
+    /// Bytes sent and received per hour of log.
+    bytes_per_hour: Option<f64>,
+    gaps: usize,
+    time_range: Option<(DateTime<FixedOffset>, DateTime<FixedOffset>)>,
+}
+
+impl Metrics {
+    fn compute(spans: &Spans) -> Self {
+        let (aggregate, _) = buckets::per_kind(spans);
+        let time_range = filters::time_range(spans);
+        let syncs = spans
+            .values()
+            .flat_map(BTreeMap::values)
+            .filter(|span| span.is_sync() && span.is_successful());
+        let mut sync_durations = syncs.clone().map(|span| span.duration).collect::<Vec<_>>();
+        sync_durations.sort_unstable();
+
+        Self {
+            requests: aggregate.requests,
+            time_to_first_sync: time_range
+                .zip(syncs.map(|span| span.start_at + span.duration).min())
+                .map(|((start_at, _), first_sync_at)| first_sync_at - start_at),
+            sync_p95: stats::percentile(&sync_durations, 95.),
+            error_rate: if aggregate.requests == 0 {
+                0.
+            } else {
+                aggregate.errors as f64 / aggregate.requests as f64
+            },
+            bytes_per_hour: time_range
+                .map(|(start_at, end_at)| (end_at - start_at).num_milliseconds())
+                .filter(|duration| *duration > 0)
+                .map(|duration| {
+                    (aggregate.bytes_down + aggregate.bytes_up) as f64 * 3_600_000.
+                        / duration as f64
+                }),
+            gaps: gaps::detect(spans, gaps::DEFAULT_THRESHOLD).len(),
+            time_range,
+        }
+    }
+}
+
+/// An entry of the cohort.
+struct Entry {
+    name: String,
+    sources: Vec<SourceFile>,
+    /// The metrics and the file name of the report, or why the entry has
+    /// failed.
+    outcome: Result<(Metrics, String), String>,
+}
+
+/// Compare the entries of `directory`, and write the cohort report to
+/// `output_path`. Each entry is parsed by a parser from `new_parser`.
+pub fn run(
+    options: &Options,
+    new_parser: impl Fn() -> Parser + Sync,
+    directory: &str,
+    output_path: &str,
+) -> Result<Stats, Error> {
+    let mut paths = fs::read_dir(directory)
+        .map_err(Error::io(directory))?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .collect::<Vec<_>>();
+    paths.sort();
+
+    // The reports of the entries are next to the cohort report, in a
+    // directory named after it.
+    let output_path = Path::new(output_path);
+    let reports_name = output_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "cohort".to_owned());
+    let reports_directory = output_path.with_file_name(&reports_name);
+    fs::create_dir_all(&reports_directory).map_err(Error::io(reports_directory.display()))?;
+
+    let next = AtomicUsize::new(0);
+    let entries = Mutex::new(Vec::with_capacity(paths.len()));
+    let number_of_workers = thread::available_parallelism()
+        .map(usize::from)
+        .unwrap_or(1)
+        .min(paths.len());
+
+    thread::scope(|scope| {
+        for _ in 0..number_of_workers {
+            scope.spawn(|| {
+                while let Some(path) = paths.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    let name = path
+                        .file_name()
+                        .map(|name| name.to_string_lossy().into_owned())
+                        .unwrap_or_default();
+                    let report_name = format!("{name}.html");
+                    let report_path = reports_directory.join(&report_name);
+                    let (sources, outcome) = match read(path) {
+                        Ok(files) => (
+                            files
+                                .iter()
+                                .map(|(name, content)| SourceFile {
+                                    name: name.clone(),
+                                    size: Some(content.len() as u64),
+                                })
+                                .collect(),
+                            // A panic fails the entry only.
+                            panic::catch_unwind(AssertUnwindSafe(|| {
+                                analyse(options, &new_parser, &name, &files, &report_path)
+                            }))
+                            .unwrap_or_else(|payload| Err(panic_message(payload)))
+                            .map(|metrics| (metrics, format!("{reports_name}/{report_name}"))),
+                        ),
+                        Err(error) => (Vec::new(), Err(error)),
+                    };
+
+                    match &outcome {
+                        Ok(_) => eprintln!("Analysed `{name}`"),
+                        Err(error) => eprintln!("Failed to analyse `{name}`: {error}"),
+                    }
+
+                    entries
+                        .lock()
+                        .expect("The entries are never poisoned")
+                        .push(Entry {
+                            name,
+                            sources,
+                            outcome,
+                        });
+                }
+            });
+        }
+    });
+
+    let mut entries = entries
+        .into_inner()
+        .expect("The entries are never poisoned");
+    entries.sort_by(|left, right| left.name.cmp(&right.name));
+
+    let sources = entries
+        .iter()
+        .flat_map(|entry| {
+            entry.sources.iter().map(|source| SourceFile {
+                name: if source.name == entry.name {
+                    entry.name.clone()
+                } else {
+                    format!("{}/{}", entry.name, source.name)
+                },
+                size: source.size,
+            })
+        })
+        .collect::<Vec<_>>();
+    let options = Options {
+        sources,
+        ..options.clone()
+    };
+    let time_range = entries
+        .iter()
+        .filter_map(|entry| entry.outcome.as_ref().ok()?.0.time_range)
+        .reduce(|(start_at, end_at), (other_start_at, other_end_at)| {
+            (start_at.min(other_start_at), end_at.max(other_end_at))
+        });
+    let number_of_failures = entries
+        .iter()
+        .filter(|entry| entry.outcome.is_err())
+        .count();
+    let header = format!(
+        "{title}  <p>Cohort of {number_of_entries} logs from <code>{directory}</code>, {number_of_failures} failed.</p>\n",
+        title = match &options.title {
+            Some(title) => format!("  <h1>{}</h1>\n", html::escape(title)),
+            None => "  <h1>Cohort</h1>\n".to_owned(),
+        },
+        number_of_entries = human::count(entries.len()),
+        directory = html::escape(directory),
+        number_of_failures = human::count(number_of_failures),
+    );
+    let meta = cli::meta(&options, time_range, Vec::new());
+    let output = index_to_html(&options, &header, &meta, "", &to_html(&entries));
+
+    fs::write(output_path, output).map_err(Error::io(output_path.display()))?;
+
+    Ok(Stats::new(format!(
+        "\nNumber of analysed logs: {number_of_entries}\n\
+        Number of failed logs: {number_of_failures}\n\
+        Output file: {output_path}\n\
+        Done!",
+        number_of_entries = human::count(entries.len()),
+        number_of_failures = human::count(number_of_failures),
+        output_path = output_path.display(),
+    )))
+}
+
+/// Parse the files of an entry, write its report to `report_path`, and
+/// compute its metrics.
+fn analyse(
+    options: &Options,
+    new_parser: &impl Fn() -> Parser,
+    name: &str,
+    files: &[(String, Vec<u8>)],
+    report_path: &Path,
+) -> Result<Metrics, String> {
+    let mut parser = new_parser();
+    let mut deduplicator = Deduplicator::default();
+
+    for (file_nth, (_, content)) in files.iter().enumerate() {
+        for line in content.split_inclusive(|byte| *byte == b'\n') {
+            match source::decode(line) {
+                Ok(line) if deduplicator.is_duplicate(file_nth, line) => {}
+                line => {
+                    parser.parse(line, None);
+                }
+            }
+        }
+    }
+
+    if let Some(diagnostic) = parser.no_match_diagnostic() {
+        return Err(diagnostic);
+    }
+
+    let metrics = Metrics::compute(&parser.spans);
+    let options = Options {
+        sources: files
+            .iter()
+            .map(|(name, content)| SourceFile {
+                name: name.clone(),
+                size: Some(content.len() as u64),
+            })
+            .collect(),
+        ..options.clone()
+    };
+    parser.lifecycle_events.sort_by_key(|event| event.at);
+
+    write_reports(
+        &options,
+        parser.spans,
+        &parser.lifecycle_events,
+        name,
+        &[Output {
+            format: Format::Html,
+            path: report_path.to_string_lossy().into_owned(),
+        }],
+    )
+    .map_err(|error| error.to_string())?;
+
+    Ok(metrics)
+}
+
+/// Read the log files of an entry, with their names: the entry itself, the
+/// files of an archive, or the log files of a directory, in the order of
+/// their names.
+fn read(path: &Path) -> Result<Vec<(String, Vec<u8>)>, String> {
+    if !path.is_dir() {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let content = fs::read(path).map_err(|error| format!("Failed to read: {error}"))?;
+
+        return if content.starts_with(ZIP_MAGIC) {
+            unzip(content)
+        } else {
+            Ok(vec![(name, gunzip(content)?)])
+        };
+    }
+
+    let mut paths = fs::read_dir(path)
+        .map_err(|error| format!("Failed to read the directory: {error}"))?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        // A rageshake holds other files, e.g. `details.json` or screenshots.
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .file_name()
+                    .is_some_and(|name| name.to_string_lossy().contains(".log"))
+        })
+        .collect::<Vec<_>>();
+    paths.sort();
+
+    if paths.is_empty() {
+        return Err("The directory holds no log file, i.e. no `*.log*` file".to_owned());
+    }
+
+    paths
+        .iter()
+        .flat_map(|path| match read(path) {
+            Ok(files) => files.into_iter().map(Ok).collect(),
+            Err(error) => vec![Err(error)],
+        })
+        .collect()
+}
+
+/// Decompress `content` if it's gzipped.
+fn gunzip(content: Vec<u8>) -> Result<Vec<u8>, String> {
+    if !content.starts_with(GZIP_MAGIC) {
+        return Ok(content);
+    }
+
+    let mut decompressed = Vec::new();
+    MultiGzDecoder::new(content.as_slice())
+        .read_to_end(&mut decompressed)
+        .map_err(|error| format!("Corrupt gzip file: {error}"))?;
+
+    Ok(decompressed)
+}
+
+/// Extract the files of a zip archive, in the order of their names.
+fn unzip(content: Vec<u8>) -> Result<Vec<(String, Vec<u8>)>, String> {
+    let corrupt = |error: zip::result::ZipError| format!("Corrupt zip archive: {error}");
+    let mut archive = zip::ZipArchive::new(Cursor::new(content)).map_err(corrupt)?;
+    let mut files = Vec::new();
+
+    for nth in 0..archive.len() {
+        let mut file = archive.by_index(nth).map_err(corrupt)?;
+
+        if file.is_dir() {
+            continue;
+        }
+
+        let mut content = Vec::new();
+        file.read_to_end(&mut content)
+            .map_err(|error| format!("Corrupt zip archive: {error}"))?;
+        files.push((file.name().to_owned(), gunzip(content)?));
+    }
+
+    files.sort_by(|(left, _), (right, _)| left.cmp(right));
+
+    if files.is_empty() {
+        return Err("The zip archive is empty".to_owned());
+    }
+
+    Ok(files)
+}
+
+/// Get the message of a panic.
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    payload
+        .downcast_ref::<String>()
+        .cloned()
+        .or_else(|| {
+            payload
+                .downcast_ref::<&str>()
+                .map(|message| (*message).to_owned())
+        })
+        .unwrap_or_else(|| "Unknown failure".to_owned())
+}
+
+/// A metric, with how to format it.
+struct Metric {
+    name: &'static str,
+    value: fn(&Metrics) -> Option<f64>,
+    format: fn(f64) -> String,
+}
+
+const METRICS: [Metric; 6] = [
+    Metric {
+        name: "Requests",
+        value: |metrics| Some(metrics.requests as f64),
+        format: |value| human::count(value as usize),
+    },
+    Metric {
+        name: "Time to first sync",
+        value: |metrics| {
+            metrics
+                .time_to_first_sync
+                .map(|duration| duration.num_milliseconds() as f64)
+        },
+        format: |value| human::milliseconds(value as i64),
+    },
+    Metric {
+        name: "Sync p95",
+        value: |metrics| {
+            metrics
+                .sync_p95
+                .map(|duration| duration.num_milliseconds() as f64)
+        },
+        format: |value| human::milliseconds(value as i64),
+    },
+    Metric {
+        name: "Error rate",
+        value: |metrics| Some(metrics.error_rate),
+        format: |value| human::percentage(value, 1.),
+    },
+    Metric {
+        name: "Bytes per hour",
+        value: |metrics| metrics.bytes_per_hour,
+        format: |value| format!("{}/h", human::bytes(value as u64)),
+    },
+    Metric {
+        name: "Gaps",
+        value: |metrics| Some(metrics.gaps as f64),
+        format: |value| human::count(value as usize),
+    },
+];
+
+/// Render the comparison of the entries, and the distributions of the
+/// metrics.
+fn to_html(entries: &[Entry]) -> String {
+    let headers = METRICS
+        .iter()
+        .map(|metric| format!("        <th scope=\"col\">{}</th>\n", metric.name))
+        .collect::<String>();
+    let rows = entries
+        .iter()
+        .map(|entry| {
+            let cells = match &entry.outcome {
+                Ok((metrics, report)) => format!(
+                    "        <th scope=\"row\"><a href=\"{report}\">{name}</a></th>\n{values}",
+                    report = html::escape(report),
+                    name = html::escape(&entry.name),
+                    values = METRICS
+                        .iter()
+                        .map(|metric| {
+                            format!(
+                                "        <td>{}</td>\n",
+                                (metric.value)(metrics)
+                                    .map(metric.format)
+                                    .unwrap_or_default()
+                            )
+                        })
+                        .collect::<String>(),
+                ),
+                Err(error) => format!(
+                    "        <th scope=\"row\">{name}</th>\n        <td class=\"failure\" colspan=\"{colspan}\">{error}</td>\n",
+                    name = html::escape(&entry.name),
+                    colspan = METRICS.len(),
+                    error = html::escape(error),
+                ),
+            };
+
+            format!("      <tr>\n{cells}      </tr>\n")
+        })
+        .collect::<String>();
+    let distributions = METRICS
+        .iter()
+        .map(|metric| {
+            let mut values = entries
+                .iter()
+                .filter_map(|entry| (metric.value)(&entry.outcome.as_ref().ok()?.0))
+                .collect::<Vec<_>>();
+            values.sort_by(f64::total_cmp);
+
+            let quantile = |percentile| {
+                stats::percentile(&values, percentile)
+                    .map(metric.format)
+                    .unwrap_or_default()
+            };
+
+            format!(
+                "      <tr>
+        <th scope=\"row\">{name}</th>
+        <td>{logs}</td>
+        <td>{min}</td>
+        <td>{p50}</td>
+        <td>{p95}</td>
+        <td>{max}</td>
+      </tr>
+",
+                name = metric.name,
+                logs = human::count(values.len()),
+                min = quantile(0.),
+                p50 = quantile(50.),
+                p95 = quantile(95.),
+                max = quantile(100.),
+            )
+        })
+        .collect::<String>();
+
+    format!(
+        "<section class=\"summary\">
+  <h2>Logs</h2>
+  <table class=\"cohort\">
+    <thead>
+      <tr>
+        <th scope=\"col\">Log</th>
+{headers}      </tr>
+    </thead>
+    <tbody>
+{rows}    </tbody>
+  </table>
+  <h2>Distributions across the cohort</h2>
+  <table class=\"cohort-distributions\">
+    <thead>
+      <tr>
+        <th scope=\"col\">Metric</th>
+        <th scope=\"col\">Logs</th>
+        <th scope=\"col\">Min</th>
+        <th scope=\"col\">p50</th>
+        <th scope=\"col\">p95</th>
+        <th scope=\"col\">Max</th>
+      </tr>
+    </thead>
+    <tbody>
+{distributions}    </tbody>
+  </table>
+</section>
+"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::{Compression, write::GzEncoder};
+
+    use super::*;
+
+    #[test]
+    fn test_read_archives() {
+        let log = b"2024-06-01T09:13:19.035Z line\n".to_vec();
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&log).unwrap();
+        let gzipped = encoder.finish().unwrap();
+
+        assert_eq!(gunzip(gzipped.clone()), Ok(log.clone()));
+        assert_eq!(gunzip(log.clone()), Ok(log.clone()));
+        assert!(
+            gunzip(gzipped[..gzipped.len() / 2].to_vec())
+                .is_err_and(|error| error.starts_with("Corrupt gzip file"))
+        );
+
+        let mut archive = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default();
+        archive.start_file("logs-0001.log.gz", options).unwrap();
+        archive.write_all(&gzipped).unwrap();
+        archive.start_file("logs-0000.log", options).unwrap();
+        archive.write_all(b"first\n").unwrap();
+        let archive = archive.finish().unwrap().into_inner();
+
+        assert_eq!(
+            unzip(archive.clone()),
+            Ok(vec![
+                ("logs-0000.log".to_owned(), b"first\n".to_vec()),
+                ("logs-0001.log.gz".to_owned(), log),
+            ])
+        );
+        assert!(
+            unzip(archive[..archive.len() / 2].to_vec())
+                .is_err_and(|error| error.starts_with("Corrupt zip archive"))
+        );
+    }
+}
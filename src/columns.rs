@@ -0,0 +1,562 @@
+//! Columns of the detailed table.
+//!
+//! The headers and the cells are rendered from the same selection, so that
+//! they always agree.
+
+use chrono::{FixedOffset, TimeDelta};
+
+use crate::{
+    ConnectionId, RequestId, Span,
+    concurrency::{Lane, Lanes},
+    context::Excerpt,
+    duration, html, human, rate_limits, retry_after, server_timing,
+    size::Size,
+    status, sync_overhead,
+    traffic_class::TrafficClass,
+    warnings,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Column {
+    Connection,
+    Source,
+    Parent,
+    Request,
+    Start,
+    Status,
+    Error,
+    Method,
+    TrafficClass,
+    Category,
+    Domain,
+    Path,
+    Pos,
+    Iteration,
+    Timeout,
+    TxnId,
+    Fields,
+    RequestSize,
+    ResponseSize,
+    RetryAfter,
+    Retries,
+    Concurrency,
+    Dns,
+    Connect,
+    Tls,
+    Ttfb,
+    Duration,
+}
+
+impl Column {
+    /// All the columns, in display order.
+    pub const ALL: [Self; 27] = [
+        Self::Connection,
+        Self::Source,
+        Self::Parent,
+        Self::Request,
+        Self::Start,
+        Self::Status,
+        Self::Error,
+        Self::Method,
+        Self::TrafficClass,
+        Self::Category,
+        Self::Domain,
+        Self::Path,
+        Self::Pos,
+        Self::Iteration,
+        Self::Timeout,
+        Self::TxnId,
+        Self::Fields,
+        Self::RequestSize,
+        Self::ResponseSize,
+        Self::RetryAfter,
+        Self::Retries,
+        Self::Concurrency,
+        Self::Dns,
+        Self::Connect,
+        Self::Tls,
+        Self::Ttfb,
+        Self::Duration,
+    ];
+
+    /// Parse a comma-separated list of column names, e.g.
+    /// `connection,request,status,path,duration`.
+    pub fn parse_list(names: &str) -> Result<Vec<Self>, String> {
+        names
+            .split(',')
+            .map(|name| {
+                Self::parse(name.trim()).ok_or_else(|| {
+                    format!(
+                        "Unknown column `{name}`; valid columns are {}",
+                        Self::ALL
+                            .iter()
+                            .map(|column| format!("`{}`", column.as_str()))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    )
+                })
+            })
+            .collect()
+    }
+
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "connection" | "conn" => Self::Connection,
+            "source" => Self::Source,
+            "parent" => Self::Parent,
+            "request" | "req" => Self::Request,
+            "start" => Self::Start,
+            "status" => Self::Status,
+            "error" => Self::Error,
+            "method" => Self::Method,
+            "traffic_class" | "class" => Self::TrafficClass,
+            "category" => Self::Category,
+            "domain" => Self::Domain,
+            "path" | "endpoint" => Self::Path,
+            "pos" => Self::Pos,
+            "iteration" => Self::Iteration,
+            "timeout" => Self::Timeout,
+            "txn_id" => Self::TxnId,
+            "fields" => Self::Fields,
+            "request_size" => Self::RequestSize,
+            "response_size" => Self::ResponseSize,
+            "retry_after" => Self::RetryAfter,
+            "retries" => Self::Retries,
+            "concurrency" => Self::Concurrency,
+            "dns" => Self::Dns,
+            "connect" => Self::Connect,
+            "tls" => Self::Tls,
+            "ttfb" => Self::Ttfb,
+            "duration" => Self::Duration,
+            _ => return None,
+        })
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Connection => "connection",
+            Self::Source => "source",
+            Self::Parent => "parent",
+            Self::Request => "request",
+            Self::Start => "start",
+            Self::Status => "status",
+            Self::Error => "error",
+            Self::Method => "method",
+            Self::TrafficClass => "traffic_class",
+            Self::Category => "category",
+            Self::Domain => "domain",
+            Self::Path => "path",
+            Self::Pos => "pos",
+            Self::Iteration => "iteration",
+            Self::Timeout => "timeout",
+            Self::TxnId => "txn_id",
+            Self::Fields => "fields",
+            Self::RequestSize => "request_size",
+            Self::ResponseSize => "response_size",
+            Self::RetryAfter => "retry_after",
+            Self::Retries => "retries",
+            Self::Concurrency => "concurrency",
+            Self::Dns => "dns",
+            Self::Connect => "connect",
+            Self::Tls => "tls",
+            Self::Ttfb => "ttfb",
+            Self::Duration => "duration",
+        }
+    }
+
+    /// Render the header of the column.
+    pub fn header(&self) -> &'static str {
+        match self {
+            Self::Connection => {
+                r#"<th scope="col" class="connection"><abbr title="Connection">Conn.</abbr> ID</th>"#
+            }
+            Self::Source => r#"<th scope="col" class="source">Source</th>"#,
+            Self::Parent => r#"<th scope="col" class="parent">Parent span</th>"#,
+            Self::Request => {
+                r#"<th scope="col" class="request"><abbr title="Request">Req.</abbr> ID</th>"#
+            }
+            Self::Start => r#"<th scope="col" class="start">Start</th>"#,
+            Self::Status => r#"<th scope="col" class="status">Status</th>"#,
+            Self::Error => r#"<th scope="col" class="error">Error</th>"#,
+            Self::Method => {
+                r#"<th scope="col" class="method"><abbr title="Method">Meth.</abbr></th>"#
+            }
+            Self::TrafficClass => r#"<th scope="col" class="traffic_class">Class</th>"#,
+            Self::Category => r#"<th scope="col" class="category">Category</th>"#,
+            Self::Domain => r#"<th scope="col" class="domain">Domain</th>"#,
+            Self::Path => r#"<th scope="col" class="path">Path</th>"#,
+            Self::Pos => {
+                r#"<th scope="col" class="pos"><abbr title="Position of the sliding sync">Pos</abbr></th>"#
+            }
+            Self::Iteration => {
+                r#"<th scope="col" class="iteration"><abbr title="Iteration of the sync loop">Iter.</abbr></th>"#
+            }
+            Self::Timeout => r#"<th scope="col" class="timeout">Timeout</th>"#,
+            Self::TxnId => {
+                r#"<th scope="col" class="txn_id"><abbr title="Transaction">Txn</abbr> ID</th>"#
+            }
+            Self::Fields => r#"<th scope="col" class="fields">Fields</th>"#,
+            Self::RequestSize => {
+                r#"<th scope="col" class="request_size"><abbr title="Request">Req.</abbr> size</th>"#
+            }
+            Self::ResponseSize => {
+                r#"<th scope="col" class="response_size"><abbr title="Response">Resp.</abbr> size</th>"#
+            }
+            Self::RetryAfter => r#"<th scope="col" class="retry_after">Retry after</th>"#,
+            Self::Retries => r#"<th scope="col" class="retries">Retries</th>"#,
+            Self::Concurrency => {
+                r#"<th scope="col" class="concurrency"><abbr title="Maximum number of requests of the connection in flight at once">Conc.</abbr></th>"#
+            }
+            Self::Dns => {
+                r#"<th scope="col" class="dns"><abbr title="Duration of the DNS resolution">DNS</abbr></th>"#
+            }
+            Self::Connect => {
+                r#"<th scope="col" class="connect"><abbr title="Duration of the TCP connection, or whether an idle connection is reused">Conn.</abbr></th>"#
+            }
+            Self::Tls => {
+                r#"<th scope="col" class="tls"><abbr title="Duration of the TLS handshake">TLS</abbr></th>"#
+            }
+            Self::Ttfb => {
+                r#"<th scope="col" class="ttfb"><abbr title="Time to the first byte of the response">TTFB</abbr></th>"#
+            }
+            Self::Duration => r#"<th scope="col" class="duration">Time</th>"#,
+        }
+    }
+
+    /// Render the cell of the column for a span. `smallest_start_at` is the
+    /// start of the timeline, in milliseconds, and dates are displayed in
+    /// `timezone`. The excerpt of the logs, if any, is rendered with the
+    /// details of the span, and the lane of the span offsets its bar.
+    #[allow(clippy::too_many_arguments)]
+    pub fn cell(
+        &self,
+        connection_id: &ConnectionId,
+        request_id: RequestId,
+        span: &Span,
+        smallest_start_at: i64,
+        timezone: FixedOffset,
+        excerpt: Option<&Excerpt>,
+        lane: Option<Lane>,
+    ) -> String {
+        match self {
+            Self::Connection => format!(
+                "<td class=\"connection\"><code>{}</code></td>",
+                html::escape(connection_id)
+            ),
+            Self::Source => format!(
+                "<td class=\"source\"><code>{}</code></td>",
+                span.source.as_deref().map(html::escape).unwrap_or_default()
+            ),
+            // The chain of the tracing spans is expanded from the parent.
+            Self::Parent if !span.frames.is_empty() => format!(
+                "<td class=\"parent\"><details><summary><code>{parent}</code></summary><ol class=\"frames\">{frames}</ol></details></td>",
+                parent = html::escape(
+                    span.parent
+                        .as_deref()
+                        .or(span.frames.last().map(|frame| frame.name.as_str()))
+                        .unwrap_or_default()
+                ),
+                frames = span
+                    .frames
+                    .iter()
+                    .map(|frame| format!(
+                        "<li><code>{}</code></li>",
+                        html::escape(&frame.to_string())
+                    ))
+                    .collect::<String>(),
+            ),
+            Self::Parent => format!(
+                "<td class=\"parent\"><code>{}</code></td>",
+                span.parent.as_deref().map(html::escape).unwrap_or_default()
+            ),
+            Self::Request => format!(
+                "<td class=\"request\"><a href=\"#{connection_id}-{request_id}\" title=\"Permalink to this line\"><code>{request_id}</code></a></td>",
+                connection_id = html::escape(connection_id),
+            ),
+            Self::Start => {
+                let start_at = span.start_at.with_timezone(&timezone);
+
+                format!(
+                    "<td class=\"start\"><time datetime=\"{datetime}\" title=\"{datetime}\">{time}</time></td>",
+                    datetime = start_at.to_rfc3339(),
+                    time = start_at.format("%H:%M:%S%.3f"),
+                )
+            }
+            Self::Status => format!(
+                "<td class=\"status\" data-status-family=\"{status_family}\"><span title=\"{tooltip}\">{status}</span></td>",
+                status = match status::label(span) {
+                    Some(label) => label,
+                    None if span.is_pending() => "pending".to_owned(),
+                    None => "×".to_owned(),
+                },
+                tooltip = html::escape(&status::tooltip(span).unwrap_or_else(|| {
+                    if span.is_pending() {
+                        "No response in the log".to_owned()
+                    } else {
+                        "Cancelled".to_owned()
+                    }
+                })),
+                status_family = span.status_family(),
+            ),
+            Self::Error => format!(
+                "<td class=\"error\" title=\"{error}\">{error}</td>",
+                error = span.error.as_deref().map(html::escape).unwrap_or_default()
+            ),
+            Self::Method => format!(
+                "<td class=\"method\"><code>{}</code></td>",
+                html::escape(&span.method)
+            ),
+            Self::TrafficClass => format!(
+                "<td class=\"traffic_class\">{}</td>",
+                span.traffic_class().as_str()
+            ),
+            Self::Category => format!("<td class=\"category\">{}</td>", span.category().as_str()),
+            Self::Domain => format!(
+                "<td class=\"domain\" title=\"{domain}\">{domain}</td>",
+                domain = html::escape(&span.domain())
+            ),
+            Self::Path => format!(
+                "<td class=\"path\" title=\"{path}\">{path}</td>",
+                path = html::escape(&span.path())
+            ),
+            Self::Pos => format!(
+                "<td class=\"pos\"><code>{}</code></td>",
+                span.pos.as_deref().map(html::escape).unwrap_or_default()
+            ),
+            Self::Iteration => format!(
+                "<td class=\"iteration\">{}</td>",
+                if span.iteration > 0 {
+                    span.iteration.to_string()
+                } else {
+                    String::new()
+                }
+            ),
+            Self::Timeout => format!(
+                "<td class=\"timeout\">{}</td>",
+                span.timeout()
+                    .map(|timeout| human::milliseconds(timeout.num_milliseconds()))
+                    .unwrap_or_default()
+            ),
+            Self::TxnId => format!(
+                "<td class=\"txn_id\"><code>{}</code></td>",
+                span.txn_id.as_deref().map(html::escape).unwrap_or_default()
+            ),
+            Self::Fields => format!(
+                "<td class=\"fields\">{}</td>",
+                span.fields
+                    .iter()
+                    .map(|(name, value)| format!(
+                        "<code>{name}={value}</code>",
+                        name = html::escape(name),
+                        value = html::escape(value),
+                    ))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            ),
+            Self::RequestSize => size_cell("request_size", span.request_size.as_ref()),
+            Self::ResponseSize => size_cell("response_size", span.response_size.as_ref()),
+            Self::RetryAfter => format!(
+                "<td class=\"retry_after\">{}</td>",
+                retry_after::label(span, timezone).unwrap_or_default()
+            ),
+            Self::Retries => format!(
+                "<td class=\"retries\">{}</td>",
+                if span.retries > 0 {
+                    span.retries.to_string()
+                } else {
+                    String::new()
+                }
+            ),
+            Self::Concurrency => format!(
+                "<td class=\"concurrency\">{}</td>",
+                lane.map(|lane| lane.concurrency.to_string())
+                    .unwrap_or_default()
+            ),
+            Self::Dns => setup_cell("dns", span.setup.dns),
+            Self::Connect => match span.setup.connect {
+                None if span.setup.reused == Some(true) => {
+                    "<td class=\"connect\" title=\"Idle connection of the pool\">reused</td>"
+                        .to_owned()
+                }
+                connect => setup_cell("connect", connect),
+            },
+            Self::Tls => setup_cell("tls", span.setup.tls),
+            Self::Ttfb => setup_cell("ttfb", span.setup.ttfb),
+            Self::Duration => {
+                let duration = duration::to_milliseconds(span.duration);
+                let (server, durations) = match span.server_duration() {
+                    Some(server_duration) => {
+                        let split = format!(
+                            "server: {server}, network/queueing: {network}",
+                            server = human::duration(duration::from_milliseconds(server_duration)),
+                            network = human::duration(duration::from_milliseconds(
+                                duration - server_duration
+                            )),
+                        );
+
+                        (
+                            format!(
+                                "<div class=\"server\" style=\"--server-duration: {server_duration}\" title=\"{split}\"></div>"
+                            ),
+                            format!(" ({split})"),
+                        )
+                    }
+                    None => (String::new(), String::new()),
+                };
+                let server_timing = if span.server_timing.is_empty() {
+                    String::new()
+                } else {
+                    format!(
+                        "            <li>Server-Timing: <code>{header}</code>{durations}</li>\n",
+                        header = html::escape(&server_timing::to_header(&span.server_timing)),
+                    )
+                };
+
+                format!(
+                    "<td class=\"duration\">
+        <div class=\"span\" style=\"--start-at: {start_at}; --duration: {duration}; --lane: {lane}\">{server}<span>{duration_label}</span></div>{rate_limited}
+        <details>
+          <summary><span class=\"hidden\">information</span></summary>
+          <ul>
+            <li>Request log line number: {request_log_line}</li>
+            <li>Response log line number: {response_log_line}</li>
+{app_state}{intermediary}{server_timing}{sync_overhead}{bodies}{warnings}          </ul>{excerpt}
+        </details>
+      </td>",
+                    start_at = duration::offset_in_milliseconds(span.start_at, smallest_start_at),
+                    lane = lane.map(|lane| lane.index).unwrap_or_default(),
+                    rate_limited = rate_limits::window(span)
+                        .map(|(start_at, end_at)| format!(
+                            "<div class=\"rate-limited\" style=\"--start-at: {start_at}; --duration: {duration}\" title=\"rate limited for {label}\"></div>",
+                            start_at = duration::offset_in_milliseconds(start_at, smallest_start_at),
+                            duration = duration::to_milliseconds(end_at - start_at),
+                            label = human::duration(end_at - start_at),
+                        ))
+                        .unwrap_or_default(),
+                    duration_label = if span.is_pending() {
+                        "<em>pending</em>".to_owned()
+                    } else if span.duration > TimeDelta::zero() {
+                        human::duration(span.duration)
+                    } else {
+                        "<em>cancelled</em>".to_owned()
+                    },
+                    request_log_line = span.request_log_line,
+                    response_log_line = span
+                        .response_log_line
+                        .map(|line| line.to_string())
+                        .unwrap_or_else(|| "(none)".to_owned()),
+                    app_state = span
+                        .app_state
+                        .map(|state| format!("            <li>App state: {state}</li>\n"))
+                        .unwrap_or_default(),
+                    intermediary = span
+                        .intermediary
+                        .describe()
+                        .map(|intermediary| {
+                            format!(
+                                "            <li>Intermediary: {}</li>\n",
+                                html::escape(&intermediary)
+                            )
+                        })
+                        .unwrap_or_default(),
+                    sync_overhead = sync_overhead::describe(span)
+                        .map(|overhead| format!("            <li>{overhead}</li>\n"))
+                        .unwrap_or_default(),
+                    bodies = span.bodies.to_html(),
+                    warnings = warnings::to_html(&span.warnings),
+                    excerpt = excerpt
+                        .map(|excerpt| format!("\n          {}", excerpt.to_html()))
+                        .unwrap_or_default(),
+                )
+            }
+        }
+    }
+}
+
+/// Render a size cell, with its number of bytes to sort or aggregate it.
+fn size_cell(class: &str, size: Option<&Size>) -> String {
+    match size {
+        // The sizes are logged in various units: they are formatted alike, and
+        // the logged size is kept as a tooltip.
+        Some(size) if let Some(bytes) = size.bytes() => format!(
+            "<td class=\"{class}\" data-bytes=\"{bytes}\" title=\"{logged}\">{formatted}</td>",
+            logged = html::escape(size),
+            formatted = human::bytes(bytes),
+        ),
+        size => format!(
+            "<td class=\"{class}\">{}</td>",
+            size.map(|size| html::escape(size)).unwrap_or_default()
+        ),
+    }
+}
+
+/// Render a duration of the setup of the connection, with its number of
+/// milliseconds to sort it.
+fn setup_cell(class: &str, duration: Option<f64>) -> String {
+    match duration {
+        Some(duration) => format!(
+            "<td class=\"{class}\" data-milliseconds=\"{duration}\">{formatted}</td>",
+            formatted = human::milliseconds(duration.round() as i64),
+        ),
+        None => format!("<td class=\"{class}\"></td>"),
+    }
+}
+
+/// Remove the optional columns which are empty for all the spans, e.g. the
+/// sizes in logs captured at the info level, the transport errors in logs
+/// without failed requests, the retry-after in logs without rate limiting, or
+/// the sliding sync fields in logs without sliding sync, or the setup of the
+/// connections in logs without the trace level. The traffic class is
+/// empty too if all the spans are client-server API calls, and the
+/// concurrency if no spans overlap.
+pub fn without_empty(
+    columns: &[Column],
+    spans: &[(&ConnectionId, RequestId, &Span)],
+    lanes: &Lanes<'_>,
+) -> Vec<Column> {
+    columns
+        .iter()
+        .copied()
+        .filter(|column| {
+            let has_value: fn(&Span) -> bool = match column {
+                Column::Concurrency => {
+                    return spans.iter().any(|(connection_id, request_id, _)| {
+                        lanes
+                            .get(connection_id, *request_id)
+                            .is_some_and(|lane| lane.concurrency > 1)
+                    });
+                }
+                Column::RequestSize => |span| span.request_size.is_some(),
+                Column::ResponseSize => |span| span.response_size.is_some(),
+                Column::RetryAfter => |span| span.retry_after.is_some(),
+                Column::Retries => |span| span.retries > 0,
+                Column::Source => |span| span.source.is_some(),
+                Column::Parent => |span| span.parent.is_some() || !span.frames.is_empty(),
+                Column::Pos => |span| span.pos.is_some(),
+                Column::Iteration => |span| span.iteration > 0,
+                Column::Timeout => |span| span.timeout().is_some(),
+                Column::TxnId => |span| span.txn_id.is_some(),
+                Column::Error => |span| span.error.is_some(),
+                Column::Fields => |span| !span.fields.is_empty(),
+                Column::Dns => |span| span.setup.dns.is_some(),
+                Column::Connect => {
+                    |span| span.setup.connect.is_some() || span.setup.reused.is_some()
+                }
+                Column::Tls => |span| span.setup.tls.is_some(),
+                Column::Ttfb => |span| span.setup.ttfb.is_some(),
+                Column::TrafficClass => |span| span.traffic_class() != TrafficClass::ClientServer,
+                _ => return true,
+            };
+
+            spans.iter().any(|(_, _, span)| has_value(span))
+        })
+        .collect()
+}
+
+/// Render the headers of the selected columns.
+pub fn headers_to_html(columns: &[Column]) -> String {
+    columns
+        .iter()
+        .map(|column| format!("      {}\n", column.header()))
+        .collect()
+}
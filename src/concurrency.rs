@@ -0,0 +1,323 @@
+//! Count the requests in flight over time, per endpoint kind, to draw a
+//! stacked area chart of the concurrency above the table.
+//!
+//! The overlapping requests of a connection are also assigned to lanes, so
+//! that their bars don't collide.
+
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use serde::Serialize;
+
+use crate::{ConnectionId, RequestId, Span, Spans, endpoint, filters, human};
+
+/// Maximum number of samples, to keep the embedded payload under ~100 KB for
+/// long logs.
+const MAXIMUM_NUMBER_OF_SAMPLES: i64 = 5_000;
+
+/// Resolutions to choose from, the finest first, in milliseconds. A short log
+/// is sampled per millisecond, to see the requests queued behind each other.
+const RESOLUTIONS: [i64; 21] = [
+    1, 5, 10, 25, 50, 100, 250, 500, 1_000, 2_000, 5_000, 10_000, 15_000, 30_000, 60_000, 120_000,
+    300_000, 600_000, 900_000, 1_800_000, 3_600_000,
+];
+
+/// Number of requests in flight, sampled at a fixed resolution.
+#[derive(Default, Serialize)]
+pub struct Timeline {
+    /// Start of the first sample, in milliseconds since the Unix epoch.
+    pub start_at: i64,
+
+    /// Duration of a sample, in milliseconds.
+    pub resolution: i64,
+
+    /// The endpoint kinds, in the order of the counts of the samples.
+    pub kinds: Vec<&'static str>,
+
+    /// For each sample, the peak number of requests in flight during the
+    /// sample, per kind. The peak rather than the number at the start of the
+    /// sample, so that short requests aren't missed.
+    pub samples: Vec<Vec<u32>>,
+}
+
+/// Choose the finest resolution keeping the number of samples reasonable.
+pub fn resolution(duration: TimeDelta) -> i64 {
+    let duration = duration.num_milliseconds();
+
+    RESOLUTIONS
+        .into_iter()
+        .find(|resolution| duration / resolution < MAXIMUM_NUMBER_OF_SAMPLES)
+        .unwrap_or_else(|| duration / MAXIMUM_NUMBER_OF_SAMPLES + 1)
+}
+
+/// Sweep the spans to count the requests in flight, from `start_at` to
+/// `end_at`.
+pub fn timeline(
+    spans: &Spans,
+    (start_at, end_at): (DateTime<FixedOffset>, DateTime<FixedOffset>),
+) -> Timeline {
+    let resolution = resolution(end_at - start_at);
+    let origin = start_at.timestamp_millis();
+    let number_of_samples = ((end_at.timestamp_millis() - origin) / resolution + 1) as usize;
+    let sample_of = |at: DateTime<FixedOffset>| {
+        ((at.timestamp_millis() - origin).max(0) / resolution).min(number_of_samples as i64 - 1)
+            as usize
+    };
+
+    // One event when a request starts, and one when it ends. At the same
+    // time, starts come first, so that instantaneous spans are counted.
+    let mut events = spans
+        .values()
+        .flat_map(|spans| spans.values())
+        .flat_map(|span| {
+            let kind = endpoint::Kind::ALL
+                .iter()
+                .position(|kind| *kind == span.kind())
+                .expect("A kind is always in `Kind::ALL`");
+
+            [
+                (span.start_at, -1, kind),
+                (span.start_at + span.duration, 1, kind),
+            ]
+        })
+        .collect::<Vec<_>>();
+    events.sort_by_key(|(at, order, _)| (*at, *order));
+
+    let mut in_flight = vec![0u32; endpoint::Kind::ALL.len()];
+    let mut samples = vec![in_flight.clone(); number_of_samples];
+    let mut sample = 0;
+
+    for (at, order, kind) in events {
+        let event_sample = sample_of(at);
+
+        // The samples without any event keep the current count.
+        while sample < event_sample {
+            sample += 1;
+            samples[sample].clone_from(&in_flight);
+        }
+
+        if order < 0 {
+            in_flight[kind] += 1;
+            samples[sample][kind] = samples[sample][kind].max(in_flight[kind]);
+        } else {
+            in_flight[kind] = in_flight[kind].saturating_sub(1);
+        }
+    }
+
+    Timeline {
+        start_at: origin,
+        resolution,
+        kinds: endpoint::Kind::ALL
+            .iter()
+            .map(endpoint::Kind::as_str)
+            .collect(),
+        samples,
+    }
+}
+
+impl Timeline {
+    /// Serialize the timeline, safe to embed in a `<script>` element.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self)
+            .expect("Failed to serialize the concurrency timeline")
+            .replace("</", "<\\/")
+    }
+}
+
+/// Lane of a span, among the spans of its connection.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Lane {
+    /// Index of the lane, the lowest one free when the span starts.
+    pub index: usize,
+
+    /// Maximum number of requests of the connection in flight at once while
+    /// the span is, itself included.
+    pub concurrency: usize,
+}
+
+/// Lanes of the spans, and the peaks of concurrency.
+#[derive(Default)]
+pub struct Lanes<'a> {
+    per_span: HashMap<(&'a str, RequestId), Lane>,
+    peak: usize,
+    peak_per_connection: BTreeMap<&'a ConnectionId, usize>,
+}
+
+/// Interval of a span: its start, its end, and whether it is instantaneous.
+type Interval = (DateTime<FixedOffset>, DateTime<FixedOffset>, bool);
+
+/// Get the interval of a span. A pending span runs until `end_at`, the end of
+/// the timeline.
+fn interval(span: &Span, end_at: DateTime<FixedOffset>) -> Interval {
+    let span_end_at = if span.is_pending() {
+        end_at
+    } else {
+        span.start_at + span.duration
+    };
+
+    (span.start_at, span_end_at, span_end_at <= span.start_at)
+}
+
+/// Sweep intervals, sorted by start, to assign them their lane. A span ends
+/// just before another one starting at its end, but an instantaneous span
+/// overlaps the spans starting at the same time.
+fn sweep(intervals: &[Interval]) -> Vec<Lane> {
+    let mut lanes = vec![Lane::default(); intervals.len()];
+    // The spans in flight, by their index in `intervals`.
+    let mut in_flight = Vec::<usize>::new();
+
+    for (nth, (start_at, ..)) in intervals.iter().enumerate() {
+        in_flight.retain(|other| {
+            let (_, end_at, instantaneous) = intervals[*other];
+
+            end_at > *start_at || (instantaneous && end_at == *start_at)
+        });
+
+        lanes[nth].index = (0..)
+            .find(|index| in_flight.iter().all(|other| lanes[*other].index != *index))
+            .expect("There is always a free lane");
+        in_flight.push(nth);
+
+        for other in &in_flight {
+            lanes[*other].concurrency = lanes[*other].concurrency.max(in_flight.len());
+        }
+    }
+
+    lanes
+}
+
+/// Assign the spans to the lanes of their connection.
+pub fn lanes(spans: &Spans) -> Lanes<'_> {
+    let Some((_, end_at)) = filters::time_range(spans) else {
+        return Lanes::default();
+    };
+    let mut lanes = Lanes::default();
+
+    for (connection_id, spans) in spans {
+        let mut intervals = spans
+            .iter()
+            .map(|(request_id, span)| (interval(span, end_at), *request_id))
+            .collect::<Vec<_>>();
+        intervals.sort();
+
+        let (intervals, request_ids) = intervals.into_iter().unzip::<_, _, Vec<_>, Vec<_>>();
+        let connection_lanes = sweep(&intervals);
+
+        lanes.peak_per_connection.insert(
+            connection_id,
+            connection_lanes
+                .iter()
+                .map(|lane| lane.concurrency)
+                .max()
+                .unwrap_or_default(),
+        );
+        lanes.per_span.extend(
+            request_ids
+                .into_iter()
+                .zip(connection_lanes)
+                .map(|(request_id, lane)| ((connection_id.as_str(), request_id), lane)),
+        );
+    }
+
+    // The lanes are per connection, but the peak is over all the spans.
+    let mut intervals = spans
+        .values()
+        .flat_map(BTreeMap::values)
+        .map(|span| interval(span, end_at))
+        .collect::<Vec<_>>();
+    intervals.sort();
+    lanes.peak = sweep(&intervals)
+        .iter()
+        .map(|lane| lane.concurrency)
+        .max()
+        .unwrap_or_default();
+
+    lanes
+}
+
+impl Lanes<'_> {
+    /// Get the lane of a span.
+    pub fn get(&self, connection_id: &str, request_id: RequestId) -> Option<Lane> {
+        self.per_span.get(&(connection_id, request_id)).copied()
+    }
+
+    /// Summarize the peaks of concurrency, overall and per connection, for the
+    /// end of a run, or nothing if there is no span.
+    pub fn peaks_to_text(&self) -> String {
+        if self.peak_per_connection.is_empty() {
+            return String::new();
+        }
+
+        format!(
+            "Peak concurrency: {}\n{}",
+            human::count(self.peak),
+            self.peak_per_connection
+                .iter()
+                .map(|(connection_id, peak)| format!(
+                    "  {connection_id}: {}\n",
+                    human::count(*peak)
+                ))
+                .collect::<String>()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SpansBuilder;
+
+    #[test]
+    fn test_resolution() {
+        for (duration, expected) in [
+            (TimeDelta::zero(), 1),
+            (TimeDelta::seconds(4), 1),
+            (TimeDelta::seconds(60), 25),
+            (TimeDelta::minutes(5), 100),
+            (TimeDelta::minutes(10), 250),
+            (TimeDelta::hours(8), 10_000),
+            (TimeDelta::days(30), 600_000),
+            (TimeDelta::days(365), 6_307_201),
+        ] {
+            let resolution = resolution(duration);
+
+            assert_eq!(resolution, expected, "{duration}");
+            assert!(duration.num_milliseconds() / resolution < MAXIMUM_NUMBER_OF_SAMPLES);
+        }
+    }
+
+    #[test]
+    fn test_lanes() {
+        let uri = "https://example.org/_matrix/client/v3/sync";
+        let mut spans = SpansBuilder::default();
+        spans.span("a", 0, uri, Some(200), 0, 100);
+        spans.span("a", 1, uri, Some(200), 50, 100);
+        // Starts when the first one ends.
+        spans.span("a", 2, uri, Some(200), 100, 10);
+        // Instantaneous, at the start of the fifth one.
+        spans.span("a", 3, uri, None, 200, 0).response_log_line = Some(3);
+        spans.span("a", 4, uri, Some(200), 200, 10);
+        // Pending until the end of the timeline.
+        spans.span("b", 0, uri, None, 120, 0);
+        spans.span("b", 1, uri, Some(200), 140, 10);
+        let spans = spans.build();
+        let lanes = lanes(&spans);
+        let lane = |connection_id, request_id| {
+            let lane = lanes.get(connection_id, request_id).unwrap();
+
+            (lane.index, lane.concurrency)
+        };
+
+        assert_eq!(lane("a", 0), (0, 2));
+        assert_eq!(lane("a", 1), (1, 2));
+        assert_eq!(lane("a", 2), (0, 2));
+        assert_eq!(lane("a", 3), (0, 2));
+        assert_eq!(lane("a", 4), (1, 2));
+        assert_eq!(lane("b", 0), (0, 2));
+        assert_eq!(lane("b", 1), (1, 2));
+        assert_eq!(
+            lanes.peaks_to_text(),
+            "Peak concurrency: 3\n  a: 2\n  b: 2\n"
+        );
+    }
+}
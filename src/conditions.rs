@@ -0,0 +1,207 @@
+//! Count the recoverable conditions of the input, e.g. the lines matching the
+//! pattern of the requests and responses but missing a mandatory part
+//! because the log format has drifted in a new version of the SDK.
+//!
+//! By default, they are warnings: the lines are skipped or kept as is, and
+//! the run continues. With `--strict`, the same conditions are errors setting
+//! a non-zero exit code, so that format drifts don't go unnoticed in a CI.
+
+use std::{collections::BTreeMap, fmt};
+
+use chrono::TimeDelta;
+
+use crate::human;
+
+/// Number of occurrences of a shape which are logged; the next ones are only
+/// counted.
+const MAXIMUM_LOGGED_PER_SHAPE: usize = 10;
+
+/// Default delay before the end of the log after which a span without a
+/// response is unterminated; the more recent ones may still be in flight.
+pub const DEFAULT_UNTERMINATED_THRESHOLD: TimeDelta = TimeDelta::minutes(2);
+
+/// A recoverable condition of the input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Condition {
+    /// A line which isn't valid UTF-8, e.g. the truncated first line of a
+    /// rotated log. The line is skipped.
+    InvalidUtf8,
+    /// A line about a request or a response whose date can't be parsed. The
+    /// line is skipped.
+    UnparseableTimestamps,
+    /// A line about a request or a response missing a mandatory part. The
+    /// line is skipped.
+    MalformedCaptures,
+    /// A request ID reused after the response of its first request.
+    DuplicateRequestIds,
+    /// A response logged before its request.
+    NegativeDurations,
+    /// A request without a response, long before the end of the log.
+    UnterminatedSpans,
+}
+
+impl Condition {
+    pub const ALL: [Self; 6] = [
+        Self::InvalidUtf8,
+        Self::UnparseableTimestamps,
+        Self::MalformedCaptures,
+        Self::DuplicateRequestIds,
+        Self::NegativeDurations,
+        Self::UnterminatedSpans,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::InvalidUtf8 => "invalid-utf8",
+            Self::UnparseableTimestamps => "unparseable-timestamps",
+            Self::MalformedCaptures => "malformed-captures",
+            Self::DuplicateRequestIds => "duplicate-request-ids",
+            Self::NegativeDurations => "negative-durations",
+            Self::UnterminatedSpans => "unterminated-spans",
+        }
+    }
+
+    /// Parse a comma-separated list of conditions, e.g.
+    /// `unterminated-spans,duplicate-request-ids`.
+    pub fn parse_list(names: &str) -> Result<Vec<Self>, String> {
+        names
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(|name| {
+                Self::ALL
+                    .into_iter()
+                    .find(|condition| condition.as_str() == name)
+                    .ok_or_else(|| {
+                        format!(
+                            "Unknown condition `{name}`; valid conditions are {}",
+                            Self::ALL
+                                .iter()
+                                .map(|condition| format!("`{}`", condition.as_str()))
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        )
+                    })
+            })
+            .collect()
+    }
+
+    /// Describe the occurrences of the condition.
+    fn description(&self) -> &'static str {
+        match self {
+            Self::InvalidUtf8 => "lines which aren't valid UTF-8, skipped",
+            Self::UnparseableTimestamps => "lines with an unparseable timestamp, skipped",
+            Self::MalformedCaptures => "malformed lines, skipped",
+            Self::DuplicateRequestIds => "duplicate request IDs",
+            Self::NegativeDurations => "negative durations",
+            Self::UnterminatedSpans => "unterminated spans",
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str(self.as_str())
+    }
+}
+
+/// The first occurrence of a shape, and the number of occurrences of this
+/// shape.
+#[derive(Clone, Debug)]
+pub struct Example {
+    pub count: usize,
+    pub log_line: usize,
+    pub line: String,
+}
+
+/// The occurrences of the conditions, per condition and per shape, e.g.
+/// which mandatory group is missing.
+#[derive(Clone, Debug, Default)]
+pub struct Conditions {
+    shapes: BTreeMap<(Condition, String), Example>,
+}
+
+impl Conditions {
+    /// Record and log an occurrence of a condition.
+    pub fn record(&mut self, condition: Condition, shape: &str, log_line: usize, line: &str) {
+        let example = self
+            .shapes
+            .entry((condition, shape.to_owned()))
+            .or_insert_with(|| Example {
+                count: 0,
+                log_line,
+                line: line.to_owned(),
+            });
+        example.count += 1;
+
+        if example.count <= MAXIMUM_LOGGED_PER_SHAPE {
+            eprintln!("Log line {log_line}: {condition}, {shape}");
+        }
+
+        if example.count == MAXIMUM_LOGGED_PER_SHAPE {
+            eprintln!("Further `{condition}` occurrences with {shape} are only counted");
+        }
+    }
+
+    /// Number of occurrences of a condition.
+    pub fn count(&self, condition: Condition) -> usize {
+        self.shapes
+            .iter()
+            .filter(|((candidate, _), _)| *candidate == condition)
+            .map(|(_, example)| example.count)
+            .sum()
+    }
+
+    /// The shapes, with their first occurrence.
+    pub fn examples(&self) -> impl Iterator<Item = (Condition, &str, &Example)> {
+        self.shapes
+            .iter()
+            .map(|((condition, shape), example)| (*condition, shape.as_str(), example))
+    }
+
+    /// Summarize the occurrences, one line per condition, followed by one
+    /// example per shape if `verbose`.
+    pub fn summary(&self, verbose: bool) -> String {
+        Condition::ALL
+            .into_iter()
+            .filter(|condition| self.count(*condition) > 0)
+            .map(|condition| {
+                let examples = self
+                    .examples()
+                    .filter(|(candidate, _, _)| verbose && *candidate == condition)
+                    .map(|(_, shape, example)| {
+                        format!(
+                            "  {count} with {shape}, e.g. log line {log_line}: {line}\n",
+                            count = human::count(example.count),
+                            log_line = example.log_line,
+                            line = example.line,
+                        )
+                    })
+                    .collect::<String>();
+
+                format!(
+                    "Number of {description}: {count}\n{examples}",
+                    description = condition.description(),
+                    count = human::count(self.count(condition)),
+                )
+            })
+            .collect()
+    }
+
+    /// Get the errors of the strict mode: the conditions which have occurred,
+    /// except the `except` ones.
+    pub fn strict_errors(&self, except: &[Condition]) -> Vec<String> {
+        Condition::ALL
+            .into_iter()
+            .filter(|condition| !except.contains(condition))
+            .filter(|condition| self.count(*condition) > 0)
+            .map(|condition| {
+                format!(
+                    "{count} {description} (`{condition}`)",
+                    count = human::count(self.count(condition)),
+                    description = condition.description(),
+                )
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,314 @@
+//! Read the patterns of `--config`, to parse the requests logged by other
+//! targets than `matrix_sdk::http_client`, e.g. `matrix_sdk_crypto` or the
+//! spans of an app, without recompiling:
+//!
+//! ```toml
+//! [[pattern]]
+//! name = "crypto"
+//! regex = '''^(?<datetime>\S+Z) .*matrix_sdk_crypto.* id=(?<id>\d+) (?<method>[A-Z]+) (?<uri>\S+)( status=(?<status>\d+))?( user=(?<user>\S+))?'''
+//!
+//! [pattern.captures]
+//! request_id = "id"
+//! ```
+//!
+//! The named groups of a regex are the captures of [`pattern::CAPTURES`], or
+//! are mapped to them in `[pattern.captures]`; `datetime`, `request_id`,
+//! `method` and `uri` are mandatory. The other named groups, like `user`, are
+//! kept on the spans, and displayed in the `fields` column. The patterns are
+//! tried in order, after the one of `matrix_sdk::http_client`.
+//!
+//! The thresholds of `--slow-threshold` can be set too, by default and per
+//! category of endpoints:
+//!
+//! ```toml
+//! [slow_thresholds]
+//! default = "2s"
+//! keys_query = "1s"
+//! ```
+//!
+//! The file is a subset of TOML: tables of `[[pattern]]`, a table of
+//! `[slow_thresholds]`, and strings, basic, literal or multi-line literal.
+
+use std::collections::BTreeMap;
+
+use crate::{
+    pattern::{self, Pattern},
+    slow,
+};
+
+/// The content of a config file.
+#[derive(Debug, Default)]
+pub struct Config {
+    pub patterns: Vec<Pattern>,
+    pub slow_thresholds: slow::Thresholds,
+}
+
+/// The table whose keys are being read.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Section {
+    Pattern,
+    Captures,
+    SlowThresholds,
+}
+
+/// A `[[pattern]]` table, being read.
+#[derive(Default)]
+struct Table {
+    name: Option<String>,
+    regex: Option<String>,
+    captures: BTreeMap<String, String>,
+    /// Line of the `[[pattern]]` header.
+    line_nth: usize,
+}
+
+impl Table {
+    fn into_pattern(self, nth: usize) -> Result<Pattern, String> {
+        let name = self.name.unwrap_or_else(|| format!("#{nth}"));
+        let regex = self.regex.ok_or_else(|| {
+            format!(
+                "line {}: the pattern `{name}` has no `regex`",
+                self.line_nth
+            )
+        })?;
+
+        Pattern::new(&regex, &self.captures)
+            .map_err(|error| format!("line {}: the pattern `{name}`: {error}", self.line_nth))
+    }
+}
+
+/// Parse a config file.
+pub fn parse(text: &str) -> Result<Config, String> {
+    let mut tables = Vec::<Table>::new();
+    let mut slow_thresholds = slow::Thresholds::default();
+    let mut section = None;
+    let mut lines = text.lines().enumerate().map(|(nth, line)| (nth + 1, line));
+
+    while let Some((line_nth, line)) = lines.next() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        match without_comment(line) {
+            "[[pattern]]" => {
+                tables.push(Table {
+                    line_nth,
+                    ..Table::default()
+                });
+                section = Some(Section::Pattern);
+
+                continue;
+            }
+            "[pattern.captures]" if !tables.is_empty() => {
+                section = Some(Section::Captures);
+
+                continue;
+            }
+            "[slow_thresholds]" => {
+                section = Some(Section::SlowThresholds);
+
+                continue;
+            }
+            header if header.starts_with('[') => {
+                return Err(format!(
+                    "line {line_nth}: unknown table `{header}`; expected `[[pattern]]`, `[pattern.captures]` or `[slow_thresholds]`"
+                ));
+            }
+            _ => {}
+        }
+
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(format!("line {line_nth}: expected `key = value`"));
+        };
+        let key = key.trim().trim_matches('"');
+        let value = string(value.trim(), &mut lines)
+            .map_err(|error| format!("line {line_nth}: {error}"))?;
+
+        if section == Some(Section::SlowThresholds) {
+            slow_thresholds
+                .insert((key != "default").then_some(key), &value)
+                .map_err(|error| format!("line {line_nth}: {error}"))?;
+
+            continue;
+        }
+
+        let Some(table) = tables.last_mut() else {
+            return Err(format!(
+                "line {line_nth}: `{key}` is outside of a `[[pattern]]` table"
+            ));
+        };
+
+        match key {
+            _ if section == Some(Section::Captures) => {
+                table.captures.insert(key.to_owned(), value);
+            }
+            "name" => table.name = Some(value),
+            "regex" => table.regex = Some(value),
+            _ => {
+                return Err(format!(
+                    "line {line_nth}: unknown key `{key}`; expected `name` or `regex`"
+                ));
+            }
+        }
+    }
+
+    if tables.is_empty() && slow_thresholds.is_empty() {
+        return Err(format!(
+            "no `[[pattern]]` or `[slow_thresholds]` table; a pattern captures {}",
+            pattern::CAPTURES
+                .iter()
+                .map(|capture| format!("`{capture}`"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+
+    Ok(Config {
+        patterns: tables
+            .into_iter()
+            .enumerate()
+            .map(|(nth, table)| table.into_pattern(nth + 1))
+            .collect::<Result<_, _>>()?,
+        slow_thresholds,
+    })
+}
+
+/// Remove the comment ending a line outside of a string.
+fn without_comment(line: &str) -> &str {
+    line.split_once(" #").map_or(line, |(line, _)| line).trim()
+}
+
+/// Parse a string value, reading the next lines of a multi-line string.
+fn string<'a>(
+    value: &str,
+    lines: &mut impl Iterator<Item = (usize, &'a str)>,
+) -> Result<String, String> {
+    if let Some(rest) = value.strip_prefix("'''") {
+        // The newline right after the opening delimiter is trimmed.
+        let mut string = rest.to_owned();
+
+        loop {
+            if let Some((string, _)) = string.split_once("'''") {
+                return Ok(string.to_owned());
+            }
+
+            let Some((_, line)) = lines.next() else {
+                return Err("unterminated multi-line string".to_owned());
+            };
+
+            if !string.is_empty() {
+                string.push('\n');
+            }
+
+            string.push_str(line);
+        }
+    }
+
+    if let Some(rest) = value.strip_prefix('\'') {
+        let (string, after) = rest
+            .split_once('\'')
+            .ok_or_else(|| "unterminated literal string".to_owned())?;
+
+        return trailing(after).map(|()| string.to_owned());
+    }
+
+    let Some(rest) = value.strip_prefix('"') else {
+        return Err("expected a string, like `\"…\"` or `'…'`".to_owned());
+    };
+    let mut string = String::new();
+    let mut characters = rest.chars();
+
+    while let Some(character) = characters.next() {
+        match character {
+            '"' => return trailing(characters.as_str()).map(|()| string),
+            '\\' => string.push(match characters.next() {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some('"') => '"',
+                Some('\\') => '\\',
+                other => {
+                    return Err(format!(
+                        "invalid escape `\\{}`; regexes are easier to write in literal strings, like `'\\d+'`",
+                        other.map(String::from).unwrap_or_default()
+                    ));
+                }
+            }),
+            character => string.push(character),
+        }
+    }
+
+    Err("unterminated string".to_owned())
+}
+
+/// Ensure nothing but a comment follows a string.
+fn trailing(after: &str) -> Result<(), String> {
+    let after = after.trim();
+
+    if after.is_empty() || after.starts_with('#') {
+        Ok(())
+    } else {
+        Err(format!("unexpected `{after}` after the string"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let config = parse(
+            r#"
+# Requests of the crypto crate.
+[[pattern]]
+name = "crypto" # In the errors only.
+regex = '''^(?<datetime>\S+Z) .*matrix_sdk_crypto.* id=(?<id>\d+) (?<method>[A-Z]+) (?<uri>\S+)'''
+
+[pattern.captures]
+request_id = "id"
+
+[[pattern]]
+regex = "^(?<datetime>\\S+Z) app: (?<request_id>\\d+) (?<method>\\S+) (?<uri>\\S+)"
+
+[slow_thresholds]
+default = "2s"
+keys_query = "1s"
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(config.patterns.len(), 2);
+        assert!(!config.slow_thresholds.is_empty());
+
+        assert_eq!(
+            parse("[[pattern]]\nname = 'crypto'").unwrap_err(),
+            "line 1: the pattern `crypto` has no `regex`"
+        );
+        assert_eq!(
+            parse("[[pattern]]\nregex = '(?<datetime>.+)'").unwrap_err(),
+            "line 1: the pattern `#1`: the regex has no `request_id` capture"
+        );
+        assert_eq!(
+            parse("[[pattern]]\nregex = 'x").unwrap_err(),
+            "line 2: unterminated literal string"
+        );
+        assert_eq!(
+            parse("[[pattern]]\nregex = \"\\d\"").unwrap_err(),
+            "line 2: invalid escape `\\d`; regexes are easier to write in literal strings, like `'\\d+'`"
+        );
+        assert_eq!(
+            parse("[[pattern]]\nlevel = 'debug'").unwrap_err(),
+            "line 2: unknown key `level`; expected `name` or `regex`"
+        );
+        assert_eq!(
+            parse("[slow_thresholds]\nsync = 'slow'").unwrap_err(),
+            "line 2: `slow` isn't a duration like `2s` or `500ms`"
+        );
+        assert!(
+            parse("")
+                .unwrap_err()
+                .starts_with("no `[[pattern]]` or `[slow_thresholds]` table")
+        );
+    }
+}
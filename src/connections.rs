@@ -0,0 +1,88 @@
+//! Order the connections for display: pinned connection IDs first, then the
+//! others in natural order, so that `room-list-2` comes before `room-list-10`.
+
+use std::{cmp::Ordering, collections::BTreeMap};
+
+use crate::{ConnectionId, RequestId, Span, Spans};
+
+#[derive(Clone, Default)]
+pub struct ConnectionOrder {
+    /// Connection IDs displayed first, in this order.
+    pub pinned: Vec<ConnectionId>,
+}
+
+impl ConnectionOrder {
+    /// Sort the spans of each connection in display order.
+    pub fn sort<'a>(
+        &self,
+        spans: &'a Spans,
+    ) -> Vec<(&'a ConnectionId, &'a BTreeMap<RequestId, Span>)> {
+        let mut connections = spans.iter().collect::<Vec<_>>();
+
+        connections.sort_by(|(left, _), (right, _)| {
+            let pinned_position = |connection_id: &str| {
+                self.pinned
+                    .iter()
+                    .position(|pinned| pinned == connection_id)
+                    .unwrap_or(usize::MAX)
+            };
+
+            pinned_position(left.as_str())
+                .cmp(&pinned_position(right.as_str()))
+                .then_with(|| natural_cmp(left, right))
+        });
+
+        connections
+    }
+}
+
+/// Compare two strings, with their sequences of digits compared by numeric
+/// value.
+fn natural_cmp(left: &str, right: &str) -> Ordering {
+    let mut left_chunks = chunks(left);
+    let mut right_chunks = chunks(right);
+
+    loop {
+        let ordering = match (left_chunks.next(), right_chunks.next()) {
+            (None, None) => return left.cmp(right),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(left_chunk), Some(right_chunk)) => {
+                let is_number = |chunk: &str| chunk.starts_with(|c: char| c.is_ascii_digit());
+
+                if is_number(left_chunk) && is_number(right_chunk) {
+                    let left_number = left_chunk.trim_start_matches('0');
+                    let right_number = right_chunk.trim_start_matches('0');
+
+                    left_number
+                        .len()
+                        .cmp(&right_number.len())
+                        .then_with(|| left_number.cmp(right_number))
+                } else {
+                    left_chunk.cmp(right_chunk)
+                }
+            }
+        };
+
+        if ordering.is_ne() {
+            return ordering;
+        }
+    }
+}
+
+/// Split a string in sequences of digits and of non-digits.
+fn chunks(string: &str) -> impl Iterator<Item = &str> {
+    let mut rest = string;
+
+    std::iter::from_fn(move || {
+        let first = rest.chars().next()?;
+        let is_digit = first.is_ascii_digit();
+        let end = rest
+            .find(|c: char| c.is_ascii_digit() != is_digit)
+            .unwrap_or(rest.len());
+        let (chunk, tail) = rest.split_at(end);
+        rest = tail;
+
+        Some(chunk)
+    })
+}
@@ -0,0 +1,131 @@
+//! Raw log lines around the request and the response of the spans, so that a
+//! reader can see the surrounding warnings and errors without opening the logs.
+//!
+//! The parser only records where the lines are; they are collected afterwards
+//! in a second pass, seeking to every location, rather than retained in memory
+//! during the first pass.
+
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, BufRead, Seek, SeekFrom},
+};
+
+use crate::{ConnectionId, RequestId, Spans, html, source::Location};
+
+/// Lines of a log file around a line of interest.
+#[derive(Clone, Copy, Debug)]
+pub struct Window {
+    /// Location of the first line.
+    pub start_at: Location,
+    pub number_of_lines: usize,
+}
+
+/// Raw lines around the request and the response of a span.
+#[derive(Default)]
+pub struct Excerpt {
+    pub request: Vec<String>,
+    pub response: Vec<String>,
+}
+
+impl Excerpt {
+    /// Render the excerpt, HTML-escaped.
+    pub fn to_html(&self) -> String {
+        let lines = |lines: &[String]| {
+            lines
+                .iter()
+                .map(|line| html::escape(line))
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        let mut output = format!(
+            "<p>Around the request:</p>\n          <pre class=\"context\">{}</pre>",
+            lines(&self.request)
+        );
+
+        if !self.response.is_empty() {
+            output.push_str(&format!(
+                "\n          <p>Around the response:</p>\n          <pre class=\"context\">{}</pre>",
+                lines(&self.response)
+            ));
+        }
+
+        output
+    }
+}
+
+pub type Excerpts = HashMap<(ConnectionId, RequestId), Excerpt>;
+
+/// Collect the excerpts of all the spans from the log files at `paths`.
+pub fn collect(paths: &[String], spans: &Spans) -> io::Result<Excerpts> {
+    let mut windows = spans
+        .iter()
+        .flat_map(|(connection_id, spans)| {
+            spans.iter().flat_map(move |(request_id, span)| {
+                [
+                    span.request_context
+                        .map(|window| (window, connection_id, *request_id, false)),
+                    span.response_context
+                        .map(|window| (window, connection_id, *request_id, true)),
+                ]
+                .into_iter()
+                .flatten()
+            })
+        })
+        .collect::<Vec<_>>();
+
+    // Read every file once, from its beginning to its end.
+    windows.sort_by_key(|(window, ..)| window.start_at);
+
+    let mut excerpts = Excerpts::new();
+    let mut reader = None;
+
+    for (window, connection_id, request_id, is_response) in windows {
+        let Location { file_nth, offset } = window.start_at;
+
+        let reader = match &mut reader {
+            Some((reader_file_nth, reader)) if *reader_file_nth == file_nth => reader,
+            reader => {
+                &mut reader
+                    .insert((
+                        file_nth,
+                        io::BufReader::new(fs::File::open(&paths[file_nth])?),
+                    ))
+                    .1
+            }
+        };
+
+        reader.seek(SeekFrom::Start(offset))?;
+
+        // The lines which aren't valid UTF-8 have been skipped by the parser,
+        // but are shown in the context, lossily.
+        let mut lines = Vec::with_capacity(window.number_of_lines);
+        let mut line = Vec::new();
+
+        while lines.len() < window.number_of_lines {
+            line.clear();
+
+            if reader.read_until(b'\n', &mut line)? == 0 {
+                break;
+            }
+
+            lines.push(
+                String::from_utf8_lossy(line.strip_suffix(b"\n").unwrap_or(&line))
+                    .trim_end_matches('\r')
+                    .to_owned(),
+            );
+        }
+
+        let excerpt = excerpts
+            .entry((connection_id.clone(), request_id))
+            .or_default();
+
+        if is_response {
+            excerpt.response = lines;
+        } else {
+            excerpt.request = lines;
+        }
+    }
+
+    Ok(excerpts)
+}
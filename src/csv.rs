@@ -0,0 +1,335 @@
+//! Export the spans as CSV, one row per span, e.g. for a spreadsheet.
+//!
+//! The cells are computed by the same methods of [`Span`] as the cells of the
+//! detailed table, so that the 2 outputs agree. A missing value is an empty
+//! cell.
+//!
+//! With `--columns`, the export only has the fields of the selected columns
+//! of the detailed table, in their order, see [`fields_of`]. Otherwise, it
+//! has all the fields of [`HEADER`], and can be imported back.
+
+use std::borrow::Cow;
+
+use chrono::{FixedOffset, SecondsFormat};
+
+use crate::{ConnectionId, RequestId, Span, columns::Column, duration};
+
+/// Header of the CSV export of the spans.
+pub const HEADER: &str = "connection_id,request_id,method,domain,path,status,request_bytes,response_bytes,start_offset_ms,start_at_iso8601,duration_ms,endpoint,traffic_class,category,status_family,errcode,error_message,error,retries,retry_after_ms,timeout_ms,pos,txn_id,fields,iteration,parent,source,server_duration_ms,dns_ms,connect_ms,tls_ms,ttfb_ms,app_state,number_of_warnings,request_log_line,response_log_line";
+
+/// Get the fields of a column of the detailed table, among the fields of
+/// [`HEADER`]. The columns without an equivalent in the export have none, see
+/// [`unsupported`].
+fn fields_of(column: Column) -> &'static [&'static str] {
+    match column {
+        Column::Connection => &["connection_id"],
+        Column::Source => &["source"],
+        Column::Parent => &["parent"],
+        Column::Request => &["request_id"],
+        Column::Start => &["start_offset_ms", "start_at_iso8601"],
+        Column::Status => &["status", "status_family"],
+        Column::Error => &["errcode", "error_message", "error"],
+        Column::Method => &["method"],
+        Column::Domain => &["domain"],
+        Column::Path => &["path", "endpoint"],
+        Column::Pos => &["pos"],
+        Column::Iteration => &["iteration"],
+        Column::Timeout => &["timeout_ms"],
+        Column::TxnId => &["txn_id"],
+        Column::Fields => &["fields"],
+        Column::RequestSize => &["request_bytes"],
+        Column::ResponseSize => &["response_bytes"],
+        Column::RetryAfter => &["retry_after_ms"],
+        Column::Retries => &["retries"],
+        Column::Duration => &["duration_ms", "server_duration_ms"],
+        Column::TrafficClass => &["traffic_class"],
+        Column::Category => &["category"],
+        // The concurrency depends on all the spans, which a stream of rows
+        // does not have.
+        Column::Concurrency => &[],
+        Column::Dns => &["dns_ms"],
+        Column::Connect => &["connect_ms"],
+        Column::Tls => &["tls_ms"],
+        Column::Ttfb => &["ttfb_ms"],
+    }
+}
+
+/// Get the indices of the fields of `columns`, in their order, or of all the
+/// fields if `columns` are all the columns, in their default order.
+fn selection(columns: &[Column]) -> Vec<usize> {
+    let fields = HEADER.split(',').collect::<Vec<_>>();
+
+    if columns == Column::ALL {
+        return (0..fields.len()).collect();
+    }
+
+    columns
+        .iter()
+        .flat_map(|column| fields_of(*column))
+        .map(|name| {
+            fields
+                .iter()
+                .position(|field| field == name)
+                .unwrap_or_else(|| panic!("`{name}` is not a field of the CSV export"))
+        })
+        .collect()
+}
+
+/// Get the columns of `columns` without a field in the export, to reject
+/// them rather than to drop them. All the columns, in their default order,
+/// are the whole export.
+pub fn unsupported(columns: &[Column]) -> Vec<Column> {
+    if columns == Column::ALL {
+        return Vec::new();
+    }
+
+    columns
+        .iter()
+        .copied()
+        .filter(|column| fields_of(*column).is_empty())
+        .collect()
+}
+
+/// Render the header of the export of `columns`, see [`selection`].
+pub fn header(columns: &[Column]) -> String {
+    let fields = HEADER.split(',').collect::<Vec<_>>();
+
+    selection(columns)
+        .into_iter()
+        .map(|nth| fields[nth])
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Quote a field if it holds a comma, a quote or a line break, e.g. a URI.
+fn field(value: &str) -> Cow<'_, str> {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\"")).into()
+    } else {
+        value.into()
+    }
+}
+
+/// Render an optional value, or an empty cell.
+fn optional(value: Option<impl ToString>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
+
+/// Render the spans as CSV, with the fields of `columns`. `smallest_start_at`
+/// is the origin of the offsets, in milliseconds, and the dates are in
+/// `timezone`.
+pub fn to_csv(
+    spans: &[(&ConnectionId, RequestId, &Span)],
+    smallest_start_at: i64,
+    timezone: FixedOffset,
+    columns: &[Column],
+) -> String {
+    format!(
+        "{}\n{}",
+        header(columns),
+        to_rows(spans, smallest_start_at, timezone, columns)
+    )
+}
+
+/// Render the rows of the spans, without the header, e.g. to append them to
+/// a CSV export while streaming.
+pub fn to_rows(
+    spans: &[(&ConnectionId, RequestId, &Span)],
+    smallest_start_at: i64,
+    timezone: FixedOffset,
+    columns: &[Column],
+) -> String {
+    let selection = selection(columns);
+    let mut output = String::new();
+
+    for (connection_id, request_id, span) in spans {
+        let row = [
+            field(connection_id),
+            request_id.to_string().into(),
+            field(&span.method),
+            field(&span.domain()).into_owned().into(),
+            field(&span.path()).into_owned().into(),
+            optional(span.status).into(),
+            optional(span.request_bytes()).into(),
+            optional(span.response_bytes()).into(),
+            duration::offset_in_milliseconds(span.start_at, smallest_start_at)
+                .to_string()
+                .into(),
+            span.start_at
+                .with_timezone(&timezone)
+                .to_rfc3339_opts(SecondsFormat::Millis, true)
+                .into(),
+            duration::to_milliseconds(span.duration).to_string().into(),
+            field(&span.endpoint()).into_owned().into(),
+            span.traffic_class().as_str().into(),
+            span.category().as_str().into(),
+            span.status_family().into(),
+            field(span.errcode.as_deref().unwrap_or_default()),
+            field(span.error_message.as_deref().unwrap_or_default()),
+            field(span.error.as_deref().unwrap_or_default()),
+            span.retries.to_string().into(),
+            optional(
+                span.retry_after
+                    .as_ref()
+                    .map(|retry_after| retry_after.delay(span).num_milliseconds()),
+            )
+            .into(),
+            optional(span.timeout().map(|timeout| timeout.num_milliseconds())).into(),
+            field(span.pos.as_deref().unwrap_or_default()),
+            field(span.txn_id.as_deref().unwrap_or_default()),
+            field(
+                &span
+                    .fields
+                    .iter()
+                    .map(|(name, value)| format!("{name}={value}"))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            )
+            .into_owned()
+            .into(),
+            span.iteration.to_string().into(),
+            field(span.parent.as_deref().unwrap_or_default()),
+            field(span.source.as_deref().unwrap_or_default()),
+            optional(span.server_duration()).into(),
+            optional(span.setup.dns).into(),
+            optional(span.setup.connect).into(),
+            optional(span.setup.tls).into(),
+            optional(span.setup.ttfb).into(),
+            optional(span.app_state.as_ref().map(|state| state.as_str())).into(),
+            span.warnings.len().to_string().into(),
+            span.request_log_line.to_string().into(),
+            optional(span.response_log_line).into(),
+        ];
+
+        output.push_str(
+            &selection
+                .iter()
+                .map(|nth| &*row[*nth])
+                .collect::<Vec<_>>()
+                .join(","),
+        );
+        output.push('\n');
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+
+    use super::*;
+
+    /// Split a CSV line in its fields, unquoting them.
+    fn parse_line(line: &str) -> Vec<String> {
+        let mut fields = vec![String::new()];
+        let mut is_quoted = false;
+        let mut characters = line.chars().peekable();
+
+        while let Some(character) = characters.next() {
+            match character {
+                '"' if is_quoted && characters.peek() == Some(&'"') => {
+                    characters.next();
+                    fields.last_mut().unwrap().push('"');
+                }
+                '"' => is_quoted = !is_quoted,
+                ',' if !is_quoted => fields.push(String::new()),
+                character => fields.last_mut().unwrap().push(character),
+            }
+        }
+
+        fields
+    }
+
+    #[test]
+    fn test_to_csv() {
+        let connection_id = "room-list".to_owned();
+        let sync = Span::for_tests(
+            "https://example.org/_matrix/client/v3/sync?filter=a,b&timeout=0",
+            Some(200),
+            TimeDelta::milliseconds(120),
+        );
+        let pending = Span::for_tests(
+            "https://example.org/_matrix/client/v3/keys/query",
+            None,
+            TimeDelta::zero(),
+        );
+        let origin = sync.start_at.timestamp_millis();
+        let utc = *sync.start_at.offset();
+
+        assert_eq!(
+            to_csv(&[], origin, utc, &Column::ALL),
+            format!("{HEADER}\n")
+        );
+
+        let csv = to_csv(
+            &[(&connection_id, 1, &sync), (&connection_id, 2, &pending)],
+            origin,
+            utc,
+            &Column::ALL,
+        );
+        let rows = csv.lines().map(parse_line).collect::<Vec<_>>();
+
+        assert_eq!(rows.len(), 3);
+        assert!(rows.iter().all(|row| row.len() == 36));
+        assert_eq!(
+            rows[1][..11],
+            [
+                "room-list",
+                "1",
+                "POST",
+                "example.org",
+                "/_matrix/client/v3/sync?filter=a,b&timeout=0",
+                "200",
+                "",
+                "",
+                "0",
+                "2024-06-01T09:13:19.035Z",
+                "120",
+            ]
+        );
+        assert_eq!(rows[1][11], "POST /_matrix/client/v3/sync");
+        assert_eq!(rows[1][12], "client-server");
+        assert_eq!(rows[1][13], "sync");
+        assert_eq!(rows[1][14], "2");
+        assert_eq!(rows[1][20], "0");
+        assert_eq!(rows[2][5], "");
+        assert_eq!(rows[2][13], "keys query");
+        assert_eq!(rows[2][14], "pending");
+
+        let columns = Column::parse_list("duration,conn,req,dns").unwrap();
+        let mut resolved = sync.clone();
+        resolved.setup.dns = Some(12.5);
+
+        assert_eq!(
+            to_csv(&[(&connection_id, 1, &resolved)], origin, utc, &columns),
+            "duration_ms,server_duration_ms,connection_id,request_id,dns_ms\n120,,room-list,1,12.5\n"
+        );
+
+        let columns = Column::parse_list("category,fields,retry_after").unwrap();
+        let mut sync = sync;
+        sync.fields.insert("room".to_owned(), "!a:b".to_owned());
+
+        assert_eq!(
+            to_csv(&[(&connection_id, 1, &sync)], origin, utc, &columns),
+            "category,fields,retry_after_ms\nsync,room=!a:b,\n"
+        );
+        assert_eq!(unsupported(&Column::ALL), []);
+        assert_eq!(
+            unsupported(&Column::parse_list("conn,concurrency").unwrap()),
+            [Column::Concurrency]
+        );
+
+        // The sub-millisecond durations and offsets are kept.
+        let mut fast = Span::for_tests(
+            "https://example.org/_matrix/client/versions",
+            Some(200),
+            TimeDelta::microseconds(350),
+        );
+        fast.start_at += TimeDelta::microseconds(1_500);
+        let csv = to_csv(&[(&connection_id, 3, &fast)], origin, utc, &Column::ALL);
+        let row = parse_line(csv.lines().nth(1).unwrap());
+
+        assert_eq!((&*row[8], &*row[10]), ("1.5", "0.35"));
+    }
+}
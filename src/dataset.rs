@@ -0,0 +1,471 @@
+//! Compact columnar JSON dataset of the spans, from which the template renders
+//! a virtualized table.
+//!
+//! Spans are stored as parallel arrays rather than as an array of objects, and
+//! repeated strings (connection IDs, methods, URIs) are stored once in string
+//! tables referenced by index, to keep the file size and the parse time
+//! reasonable for large logs.
+//!
+//! The `start_at` offsets are relative to `meta.start_at`, the start of the
+//! first span. The layout is internal to the template, for `--virtual-table`:
+//! `--format json` exports the spans as flat records, see [`crate::json`].
+
+use std::{borrow::Cow, collections::HashMap};
+
+use chrono::FixedOffset;
+use serde::Serialize;
+
+use crate::{
+    ConnectionId, RequestId, Span,
+    anomalies::Anomalies,
+    bandwidth::Bandwidth,
+    concurrency::{Lanes, Timeline},
+    duration,
+    duration_bands::Thresholds,
+    endpoint_stats::EndpointStats,
+    initial_sync::InitialSyncs,
+    lifecycle,
+    meta::Meta,
+    percentiles::Percentiles,
+    rate_limits, retry_after, server_timing,
+    size::Size,
+    slow, status,
+    status_matrix::StatusMatrix,
+    sync_overhead::{self, SyncOverhead},
+    traffic::Traffic,
+};
+
+/// Version of the layout, bumped on every breaking change of the schema.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// Description of a column of the dataset.
+#[derive(Serialize)]
+struct Column {
+    name: &'static str,
+
+    /// One of `integer`, `number`, `string`, `boolean` or `index`. All columns
+    /// except `boolean` and `index` ones are nullable.
+    r#type: &'static str,
+
+    /// For `index` columns, the name of the string table the values refer to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    strings: Option<&'static str>,
+}
+
+const COLUMNS: &[Column] = &[
+    index("connection", "connections"),
+    typed("request_id", "integer"),
+    typed("status", "integer"),
+    typed("status_label", "string"),
+    typed("status_tooltip", "string"),
+    index("method", "methods"),
+    index("uri", "uris"),
+    index("domain", "domains"),
+    typed("path_start", "integer"),
+    index("endpoint", "endpoints"),
+    typed("traffic_class", "string"),
+    typed("category", "string"),
+    typed("request_size", "string"),
+    typed("response_size", "string"),
+    typed("request_bytes", "integer"),
+    typed("response_bytes", "integer"),
+    typed("start_at", "number"),
+    typed("duration", "number"),
+    typed("duration_band", "integer"),
+    typed("latency_percentile", "integer"),
+    typed("latency_heat", "string"),
+    typed("slow", "boolean"),
+    typed("request_log_line", "integer"),
+    typed("response_log_line", "integer"),
+    typed("anomalies", "string"),
+    typed("retry_of", "integer"),
+    typed("retry_latency", "integer"),
+    typed("warnings", "string"),
+    typed("server_timing", "string"),
+    typed("server_duration", "number"),
+    typed("retry_after", "integer"),
+    typed("retry_after_label", "string"),
+    typed("rate_limited", "integer"),
+    typed("app_state", "string"),
+    typed("intermediary", "string"),
+    typed("intermediary_headers", "string"),
+    typed("errcode", "string"),
+    typed("error_message", "string"),
+    typed("process", "integer"),
+    typed("restarted_as", "string"),
+    typed("pos", "string"),
+    typed("pos_stalled", "boolean"),
+    typed("pos_reset", "boolean"),
+    typed("timeout", "integer"),
+    typed("txn_id", "string"),
+    typed("fields", "string"),
+    typed("error", "string"),
+    typed("retries", "integer"),
+    typed("iteration", "integer"),
+    typed("parent", "string"),
+    typed("frames", "string"),
+    typed("request_body", "string"),
+    typed("response_body", "string"),
+    typed("source", "string"),
+    typed("lane", "integer"),
+    typed("concurrency", "integer"),
+    typed("sync_overhead", "integer"),
+    typed("sync_overhead_label", "string"),
+    typed("connection_reused", "boolean"),
+    typed("dns", "number"),
+    typed("connect", "number"),
+    typed("tls", "number"),
+    typed("ttfb", "number"),
+];
+
+const fn typed(name: &'static str, r#type: &'static str) -> Column {
+    Column {
+        name,
+        r#type,
+        strings: None,
+    }
+}
+
+const fn index(name: &'static str, strings: &'static str) -> Column {
+    Column {
+        name,
+        r#type: "index",
+        strings: Some(strings),
+    }
+}
+
+#[derive(Serialize)]
+struct Schema {
+    version: u32,
+    columns: &'static [Column],
+}
+
+/// Strings stored once, and referenced by their index.
+#[derive(Default, Serialize)]
+#[serde(transparent)]
+struct StringTable<'a> {
+    strings: Vec<Cow<'a, str>>,
+
+    #[serde(skip)]
+    indices: HashMap<Cow<'a, str>, usize>,
+}
+
+impl<'a> StringTable<'a> {
+    fn intern(&mut self, string: impl Into<Cow<'a, str>>) -> usize {
+        let string = string.into();
+
+        if let Some(index) = self.indices.get(&string) {
+            return *index;
+        }
+
+        self.strings.push(string.clone());
+        self.indices.insert(string, self.strings.len() - 1);
+
+        self.strings.len() - 1
+    }
+}
+
+#[derive(Default, Serialize)]
+struct Strings<'a> {
+    connections: StringTable<'a>,
+    methods: StringTable<'a>,
+    uris: StringTable<'a>,
+    domains: StringTable<'a>,
+    endpoints: StringTable<'a>,
+}
+
+#[derive(Default, Serialize)]
+struct Columns<'a> {
+    connection: Vec<usize>,
+    request_id: Vec<RequestId>,
+    status: Vec<Option<u16>>,
+    status_label: Vec<Option<String>>,
+    status_tooltip: Vec<Option<String>>,
+    method: Vec<usize>,
+    uri: Vec<usize>,
+    domain: Vec<usize>,
+    /// Offset of the path in the URI, e.g. to slice it with `jq`.
+    path_start: Vec<Option<u32>>,
+    endpoint: Vec<usize>,
+    traffic_class: Vec<&'static str>,
+    category: Vec<&'static str>,
+    request_size: Vec<Option<&'a str>>,
+    response_size: Vec<Option<&'a str>>,
+    request_bytes: Vec<Option<u64>>,
+    response_bytes: Vec<Option<u64>>,
+    start_at: Vec<f64>,
+    duration: Vec<f64>,
+    duration_band: Vec<Option<usize>>,
+    /// Percentile of the duration of each span among the spans of its
+    /// endpoint, and its heat with `--latency-heatmap`.
+    latency_percentile: Vec<Option<u8>>,
+    latency_heat: Vec<Option<&'static str>>,
+    /// Whether each span is slower than its threshold of `--slow-threshold`.
+    slow: Vec<bool>,
+    request_log_line: Vec<usize>,
+    response_log_line: Vec<Option<usize>>,
+    anomalies: Vec<String>,
+    /// The previous attempt of each retry, and the time elapsed since the
+    /// start of the first attempt.
+    retry_of: Vec<Option<RequestId>>,
+    retry_latency: Vec<Option<i64>>,
+    /// The warnings of each span, one per line.
+    warnings: Vec<Option<String>>,
+    server_timing: Vec<Option<String>>,
+    server_duration: Vec<Option<f64>>,
+    retry_after: Vec<Option<i64>>,
+    retry_after_label: Vec<Option<String>>,
+    /// Duration of the rate limited window after the response, see
+    /// [`rate_limits::window`].
+    rate_limited: Vec<Option<i64>>,
+    app_state: Vec<Option<&'static str>>,
+    intermediary: Vec<Option<String>>,
+    /// The captured headers of each span, one `name: value` per line.
+    intermediary_headers: Vec<Option<String>>,
+    errcode: Vec<Option<&'a str>>,
+    error_message: Vec<Option<&'a str>>,
+    /// The number of the process of the app of each span, from 1.
+    process: Vec<usize>,
+    restarted_as: Vec<Option<&'a str>>,
+    pos: Vec<Option<&'a str>>,
+    /// Whether the `pos` of each sliding sync hasn't been bumped since the
+    /// previous request of the connection.
+    pos_stalled: Vec<bool>,
+    /// Whether each sliding sync has restarted without a `pos`.
+    pos_reset: Vec<bool>,
+    timeout: Vec<Option<i64>>,
+    txn_id: Vec<Option<&'a str>>,
+    /// The captures of the patterns of `--config` of each span, as
+    /// `name=value` lines.
+    fields: Vec<Option<String>>,
+    /// The transport error of each span failed without a response, or of its
+    /// latest failed attempt.
+    error: Vec<Option<&'a str>>,
+    retries: Vec<u32>,
+    /// The iteration of the sync loop of each span, or 0 outside of a sync
+    /// loop.
+    iteration: Vec<u32>,
+    parent: Vec<Option<&'a str>>,
+    /// The chain of the tracing spans of each span, one frame per line.
+    frames: Vec<Option<String>>,
+    /// The bodies captured with `--capture-bodies`.
+    request_body: Vec<Option<&'a str>>,
+    response_body: Vec<Option<&'a str>>,
+    source: Vec<Option<&'a str>>,
+    lane: Vec<Option<usize>>,
+    /// Maximum number of requests of the connection in flight at once during
+    /// each span.
+    concurrency: Vec<Option<usize>>,
+    sync_overhead: Vec<Option<i64>>,
+    sync_overhead_label: Vec<Option<String>>,
+    /// The setup of the connection of each span, see [`crate::setup`].
+    connection_reused: Vec<Option<bool>>,
+    dns: Vec<Option<f64>>,
+    connect: Vec<Option<f64>>,
+    tls: Vec<Option<f64>>,
+    ttfb: Vec<Option<f64>>,
+}
+
+#[derive(Serialize)]
+struct Dataset<'a> {
+    schema: Schema,
+    meta: &'a Meta<'a>,
+    summaries: &'a Summaries<'a>,
+    strings: Strings<'a>,
+    columns: Columns<'a>,
+}
+
+/// Aggregates of the spans, serialized along them.
+#[derive(Serialize)]
+pub struct Summaries<'a> {
+    pub initial_syncs: &'a InitialSyncs,
+    pub status_matrix: &'a StatusMatrix,
+    pub endpoint_stats: &'a EndpointStats,
+    pub sync_overhead: &'a SyncOverhead,
+    pub traffic: &'a Traffic,
+    pub concurrency: &'a Timeline,
+    pub bandwidth: &'a Bandwidth,
+    pub lifecycle_events: &'a [lifecycle::Event],
+    /// Number of responses whose size is out of line for their endpoint.
+    pub payload_size_outliers: usize,
+}
+
+/// Serialize the spans as a columnar dataset, safe to embed in a `<script>`
+/// element.
+///
+/// `smallest_start_at` is the origin of the `start_at` offsets, in
+/// milliseconds, labels display dates in `timezone`, the duration bands are
+/// computed from `duration_thresholds`, the slow spans from
+/// `slow_thresholds`, the lanes from all the spans, and the heats from the
+/// `percentiles` if `latency_heatmap`.
+#[allow(clippy::too_many_arguments)]
+pub fn to_json(
+    spans: &[(&ConnectionId, RequestId, &Span)],
+    smallest_start_at: i64,
+    timezone: FixedOffset,
+    anomalies: &Anomalies<'_>,
+    meta: &Meta<'_>,
+    summaries: &Summaries<'_>,
+    duration_thresholds: &Thresholds,
+    slow_thresholds: &slow::Thresholds,
+    lanes: &Lanes<'_>,
+    percentiles: &Percentiles<'_>,
+    latency_heatmap: bool,
+) -> String {
+    let mut strings = Strings::default();
+    let mut columns = Columns::default();
+
+    for (connection_id, request_id, span) in spans {
+        columns
+            .connection
+            .push(strings.connections.intern(connection_id.as_str()));
+        columns.request_id.push(*request_id);
+        columns.status.push(span.status);
+        columns.status_label.push(status::label(span));
+        columns.status_tooltip.push(status::tooltip(span));
+        columns.method.push(strings.methods.intern(&span.method));
+        columns.uri.push(strings.uris.intern(&span.uri));
+        columns.domain.push(strings.domains.intern(span.domain()));
+        columns.path_start.push(span.path_start());
+        columns
+            .endpoint
+            .push(strings.endpoints.intern(span.endpoint()));
+        columns.traffic_class.push(span.traffic_class().as_str());
+        columns.category.push(span.category().as_str());
+        columns.request_size.push(span.request_size.as_deref());
+        columns.response_size.push(span.response_size.as_deref());
+        columns
+            .request_bytes
+            .push(span.request_size.as_ref().and_then(Size::bytes));
+        columns
+            .response_bytes
+            .push(span.response_size.as_ref().and_then(Size::bytes));
+        columns.start_at.push(duration::offset_in_milliseconds(
+            span.start_at,
+            smallest_start_at,
+        ));
+        columns
+            .duration
+            .push(duration::to_milliseconds(span.duration));
+        columns.duration_band.push(duration_thresholds.band(span));
+        columns
+            .latency_percentile
+            .push(percentiles.get(connection_id, *request_id));
+        columns.latency_heat.push(
+            latency_heatmap
+                .then(|| percentiles.heat(connection_id, *request_id))
+                .flatten(),
+        );
+        columns.slow.push(slow_thresholds.is_slow(span));
+        columns.request_log_line.push(span.request_log_line);
+        columns.response_log_line.push(span.response_log_line);
+        columns
+            .anomalies
+            .push(anomalies.marks(connection_id, *request_id));
+        let retry = anomalies.retry_of(connection_id, *request_id);
+        columns.retry_of.push(retry.map(|retry| retry.previous));
+        columns
+            .retry_latency
+            .push(retry.map(|retry| retry.latency.num_milliseconds()));
+        columns.warnings.push((!span.warnings.is_empty()).then(|| {
+            span.warnings
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join("\n")
+        }));
+        columns.server_timing.push(
+            (!span.server_timing.is_empty()).then(|| server_timing::to_header(&span.server_timing)),
+        );
+        columns.server_duration.push(span.server_duration());
+        columns.retry_after.push(
+            span.retry_after
+                .as_ref()
+                .map(|retry_after| retry_after.delay(span).num_milliseconds()),
+        );
+        columns
+            .retry_after_label
+            .push(retry_after::label(span, timezone));
+        columns.rate_limited.push(
+            rate_limits::window(span)
+                .map(|(start_at, end_at)| (end_at - start_at).num_milliseconds()),
+        );
+        columns
+            .app_state
+            .push(span.app_state.as_ref().map(lifecycle::State::as_str));
+        columns.intermediary.push(span.intermediary.describe());
+        columns
+            .intermediary_headers
+            .push((!span.intermediary.is_empty()).then(|| {
+                span.intermediary
+                    .values
+                    .iter()
+                    .map(|(name, value)| format!("{name}: {value}"))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            }));
+        columns.errcode.push(span.errcode.as_deref());
+        columns.error_message.push(span.error_message.as_deref());
+        columns.process.push(span.process_nth);
+        columns.restarted_as.push(span.restarted_as.as_deref());
+        columns.pos.push(span.pos.as_deref());
+        columns.pos_stalled.push(span.pos_stalled);
+        columns.pos_reset.push(span.pos_reset);
+        columns
+            .timeout
+            .push(span.timeout().map(|timeout| timeout.num_milliseconds()));
+        columns.txn_id.push(span.txn_id.as_deref());
+        columns.fields.push((!span.fields.is_empty()).then(|| {
+            span.fields
+                .iter()
+                .map(|(name, value)| format!("{name}={value}"))
+                .collect::<Vec<_>>()
+                .join("\n")
+        }));
+        columns.error.push(span.error.as_deref());
+        columns.retries.push(span.retries);
+        columns.iteration.push(span.iteration);
+        columns.parent.push(span.parent.as_deref());
+        columns.frames.push((!span.frames.is_empty()).then(|| {
+            span.frames
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join("\n")
+        }));
+        columns.request_body.push(span.bodies.request.as_deref());
+        columns.response_body.push(span.bodies.response.as_deref());
+        columns.source.push(span.source.as_deref());
+
+        let lane = lanes.get(connection_id, *request_id);
+        columns.lane.push(lane.map(|lane| lane.index));
+        columns.concurrency.push(lane.map(|lane| lane.concurrency));
+        columns
+            .sync_overhead
+            .push(sync_overhead::estimate(span).map(|(_, overhead)| overhead.num_milliseconds()));
+        columns
+            .sync_overhead_label
+            .push(sync_overhead::describe(span));
+        columns.connection_reused.push(span.setup.reused);
+        columns.dns.push(span.setup.dns);
+        columns.connect.push(span.setup.connect);
+        columns.tls.push(span.setup.tls);
+        columns.ttfb.push(span.setup.ttfb);
+    }
+
+    let dataset = Dataset {
+        schema: Schema {
+            version: SCHEMA_VERSION,
+            columns: COLUMNS,
+        },
+        meta,
+        summaries,
+        strings,
+        columns,
+    };
+
+    serde_json::to_string(&dataset)
+        .expect("Failed to serialize the dataset")
+        // Prevent the dataset from closing its `<script>` element.
+        .replace("</", "<\\/")
+}
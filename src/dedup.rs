@@ -0,0 +1,45 @@
+//! Detect the lines present in several log files, e.g. after a copy-truncate
+//! rotation, so that the spans aren't created or merged twice.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    hash::{DefaultHasher, Hash, Hasher},
+};
+
+#[derive(Default)]
+pub struct Deduplicator {
+    /// Index of the file where a line has been seen first, by hash of the line.
+    first_seen_in: HashMap<u64, usize>,
+    /// Number of skipped lines, by pair of (first file, duplicating file).
+    skipped: BTreeMap<(usize, usize), usize>,
+}
+
+impl Deduplicator {
+    /// Whether `line`, read from the file `file_nth`, has already been read
+    /// from another file.
+    ///
+    /// Identical lines within the same file are never duplicates: only the
+    /// overlaps between files are.
+    pub fn is_duplicate(&mut self, file_nth: usize, line: &str) -> bool {
+        let mut hasher = DefaultHasher::new();
+        line.hash(&mut hasher);
+
+        let first_file_nth = *self
+            .first_seen_in
+            .entry(hasher.finish())
+            .or_insert(file_nth);
+
+        if first_file_nth == file_nth {
+            return false;
+        }
+
+        *self.skipped.entry((first_file_nth, file_nth)).or_default() += 1;
+
+        true
+    }
+
+    /// Number of skipped lines, by pair of (first file, duplicating file).
+    pub fn skipped(&self) -> &BTreeMap<(usize, usize), usize> {
+        &self.skipped
+    }
+}
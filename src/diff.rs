@@ -0,0 +1,457 @@
+//! Compare the logs of 2 sessions, e.g. before and after an upgrade of the
+//! SDK, to tell which endpoints got slower:
+//! `network-viewer diff <log_a> <log_b> <output_path>`.
+//!
+//! The endpoints are collapsed and grouped by traffic class like in the
+//! summary, see [`crate::endpoint_stats`]. They are sorted by regression, the
+//! largest increase of the median duration first; the endpoints requested by
+//! one log only come last, the other side blank.
+//!
+//! The spans of both logs are in a second report, next to the comparison one,
+//! their connection IDs prefixed by the side of their log, e.g. `B/room-list`.
+//! The spans of B are shifted to start with the ones of A, so that the 2
+//! timelines share their origin while each keeps its own start.
+//!
+//! The comparison is written as JSON too with a `.json` output, e.g.
+//! `-o diff.html -o diff.json`, to be checked by a script.
+
+use std::{collections::BTreeMap, fs, path::Path};
+
+use chrono::TimeDelta;
+use serde::Serialize;
+
+use crate::{
+    Spans,
+    buckets::Aggregate,
+    cli::{self, Options, Stats, index_to_html, write_reports},
+    error::Error,
+    filters,
+    format::{Format, Output},
+    html, human,
+    lifecycle::Event,
+    parser::Parser,
+    source::Source,
+    traffic_class::TrafficClass,
+};
+
+/// Labels of the 2 logs, in the order of the arguments.
+const SIDES: [&str; 2] = ["A", "B"];
+
+/// Aggregates of the spans of an endpoint in a log.
+#[derive(Serialize)]
+struct Side {
+    requests: usize,
+    #[serde(
+        rename = "median_duration_ms",
+        serialize_with = "serialize_milliseconds"
+    )]
+    median_duration: Option<TimeDelta>,
+    bytes: u64,
+}
+
+fn serialize_milliseconds<S: serde::Serializer>(
+    duration: &Option<TimeDelta>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    duration
+        .map(|duration| duration.num_milliseconds())
+        .serialize(serializer)
+}
+
+impl Side {
+    fn new(mut aggregate: Aggregate) -> Self {
+        aggregate.finish();
+
+        Self {
+            requests: aggregate.requests,
+            median_duration: aggregate.percentile_duration(50.),
+            bytes: aggregate.bytes_down + aggregate.bytes_up,
+        }
+    }
+}
+
+/// An endpoint, in A and in B.
+struct Row {
+    traffic_class: TrafficClass,
+    endpoint: String,
+    sides: [Option<Side>; 2],
+}
+
+impl Row {
+    /// Increase of the median duration from A to B, if both have one.
+    fn regression(&self) -> Option<TimeDelta> {
+        match &self.sides {
+            [Some(a), Some(b)] => Some(b.median_duration? - a.median_duration?),
+            _ => None,
+        }
+    }
+}
+
+/// Compare the endpoints of the spans of A and B.
+fn compare(spans: [&Spans; 2]) -> Vec<Row> {
+    let mut per_endpoint = BTreeMap::<(TrafficClass, String), [Option<Aggregate>; 2]>::new();
+
+    for (nth, spans) in spans.into_iter().enumerate() {
+        for span in spans.values().flat_map(BTreeMap::values) {
+            per_endpoint
+                .entry((span.traffic_class(), span.endpoint()))
+                .or_default()[nth]
+                .get_or_insert_default()
+                .add(span);
+        }
+    }
+
+    let mut rows = per_endpoint
+        .into_iter()
+        .map(|((traffic_class, endpoint), aggregates)| Row {
+            traffic_class,
+            endpoint,
+            sides: aggregates.map(|aggregate| aggregate.map(Side::new)),
+        })
+        .collect::<Vec<_>>();
+    // The map is sorted by class then by endpoint, and the sort is stable.
+    rows.sort_by_key(|row| {
+        (
+            row.regression().is_none(),
+            -row.regression().unwrap_or_default(),
+        )
+    });
+
+    rows
+}
+
+/// Prefix the connection IDs of the spans by the side of their log, and
+/// shift the spans of B, and its lifecycle events, to start with the ones of
+/// A. Returns the shift.
+fn combine([a, b]: [(Spans, Vec<Event>); 2]) -> (Spans, Vec<Event>, TimeDelta) {
+    let shift = filters::time_range(&a.0)
+        .zip(filters::time_range(&b.0))
+        .map(|((a_start_at, _), (b_start_at, _))| a_start_at - b_start_at)
+        .unwrap_or_default();
+    let mut spans = Spans::new();
+    let mut lifecycle_events = Vec::new();
+
+    for (side, ((side_spans, side_events), shift)) in
+        SIDES.into_iter().zip([(a, TimeDelta::zero()), (b, shift)])
+    {
+        for (connection_id, mut connection_spans) in side_spans {
+            for span in connection_spans.values_mut() {
+                span.start_at += shift;
+            }
+
+            spans.insert(format!("{side}/{connection_id}"), connection_spans);
+        }
+
+        lifecycle_events.extend(side_events.into_iter().map(|mut event| {
+            event.at += shift;
+            event
+        }));
+    }
+
+    lifecycle_events.sort_by_key(|event| event.at);
+
+    (spans, lifecycle_events, shift)
+}
+
+/// Compare the logs at `paths`, and write the comparison to `outputs`, in
+/// HTML or in JSON. Each log is parsed by a parser from `new_parser`.
+pub fn run(
+    options: &Options,
+    new_parser: impl Fn() -> Parser,
+    paths: [&str; 2],
+    outputs: &[Output],
+) -> Result<Stats, Error> {
+    let mut parsed = Vec::with_capacity(paths.len());
+
+    for path in paths {
+        let mut parser = new_parser();
+
+        Source::Files(vec![path.to_owned()]).read_lines(|line, location| {
+            parser.parse(line, location);
+        })?;
+
+        if let Some(diagnostic) = parser.no_match_diagnostic() {
+            return Err(Error::Input(format!("`{path}`: {diagnostic}")));
+        }
+
+        parsed.push((parser.spans, parser.lifecycle_events));
+    }
+
+    let [a, b] = <[_; 2]>::try_from(parsed).unwrap_or_else(|_| unreachable!());
+    let rows = compare([&a.0, &b.0]);
+    let (spans, lifecycle_events, shift) = combine([a, b]);
+
+    let mut output_files = String::new();
+
+    for output in outputs {
+        let output_path = Path::new(&output.path);
+
+        if output.format == Format::Json {
+            fs::write(output_path, to_json(&rows, paths, shift))
+                .map_err(Error::io(output_path.display()))?;
+            output_files.push_str(&format!("Output file: {}\n", output_path.display()));
+
+            continue;
+        }
+
+        // The report of the spans is next to the comparison.
+        let spans_name = format!(
+            "{}-spans.html",
+            output_path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_else(|| "diff".to_owned())
+        );
+        let spans_path = output_path.with_file_name(&spans_name);
+        let options = Options {
+            sources: Source::Files(paths.map(ToOwned::to_owned).to_vec()).files(),
+            ..options.clone()
+        };
+        let time_range = filters::time_range(&spans);
+
+        write_reports(
+            &options,
+            spans.clone(),
+            &lifecycle_events,
+            &paths.join(" vs "),
+            &[Output {
+                format: Format::Html,
+                path: spans_path.to_string_lossy().into_owned(),
+            }],
+        )?;
+
+        let header = format!(
+            "{title}  <p>Comparison of <code>{a}</code> (A) with <code>{b}</code> (B). The <a href=\"{spans_name}\">spans of both</a> start together, B shifted by {shift}.</p>\n",
+            title = match &options.title {
+                Some(title) => format!("  <h1>{}</h1>\n", html::escape(title)),
+                None => "  <h1>Diff</h1>\n".to_owned(),
+            },
+            a = html::escape(paths[0]),
+            b = html::escape(paths[1]),
+            spans_name = html::escape(&spans_name),
+            shift = human::duration(shift),
+        );
+        let meta = cli::meta(&options, time_range, Vec::new());
+        let output = index_to_html(&options, &header, &meta, "", &to_html(&rows));
+
+        fs::write(output_path, output).map_err(Error::io(output_path.display()))?;
+        output_files.push_str(&format!(
+            "Output file: {}\nOutput file: {}\n",
+            output_path.display(),
+            spans_path.display()
+        ));
+    }
+
+    Ok(Stats::new(format!(
+        "\nNumber of compared endpoints: {number_of_endpoints}\n\
+        Number of slower endpoints: {number_of_regressions}\n\
+        {output_files}\
+        Done!",
+        number_of_endpoints = human::count(rows.len()),
+        number_of_regressions = human::count(
+            rows.iter()
+                .filter(|row| row.regression() > Some(TimeDelta::zero()))
+                .count()
+        ),
+    )))
+}
+
+/// Format a difference, signed, or nothing if a side is missing.
+fn delta(a: Option<i64>, b: Option<i64>, format: fn(i64) -> String) -> String {
+    a.zip(b)
+        .map(|(a, b)| match b - a {
+            delta if delta > 0 => format!("+{}", format(delta)),
+            delta => format(delta),
+        })
+        .unwrap_or_default()
+}
+
+/// Format a value which may be negative with a format of the unsigned ones.
+fn signed(value: i64, format: fn(u64) -> String) -> String {
+    let sign = if value < 0 { "-" } else { "" };
+
+    format!("{sign}{}", format(value.unsigned_abs()))
+}
+
+/// Render the comparison of the endpoints.
+fn to_html(rows: &[Row]) -> String {
+    let rows = rows
+        .iter()
+        .map(|row| {
+            let [a, b] = &row.sides;
+            let cells = |value: fn(&Side) -> Option<i64>, format: fn(i64) -> String| {
+                let [a, b] = [a, b].map(|side| side.as_ref().and_then(value));
+
+                format!(
+                    "        <td>{}</td>\n        <td>{}</td>\n        <td>{}</td>\n",
+                    a.map(format).unwrap_or_default(),
+                    b.map(format).unwrap_or_default(),
+                    delta(a, b, format),
+                )
+            };
+
+            format!(
+                "      <tr{regression}>
+        <th scope=\"row\"><code>{endpoint}</code></th>
+        <td>{traffic_class}</td>
+{requests}{median_durations}{bytes}      </tr>
+",
+                regression = if row.regression() > Some(TimeDelta::zero()) {
+                    " data-regression"
+                } else {
+                    ""
+                },
+                endpoint = html::escape(&row.endpoint),
+                traffic_class = row.traffic_class.as_str(),
+                requests = cells(
+                    |side| Some(side.requests as i64),
+                    |requests| signed(requests, |requests| human::count(requests as usize))
+                ),
+                median_durations = cells(
+                    |side| side
+                        .median_duration
+                        .map(|duration| duration.num_milliseconds()),
+                    human::milliseconds
+                ),
+                bytes = cells(
+                    |side| Some(side.bytes as i64),
+                    |bytes| signed(bytes, human::bytes)
+                ),
+            )
+        })
+        .collect::<String>();
+
+    format!(
+        "<section class=\"summary\">
+  <h2>Endpoints, the most regressed first</h2>
+  <table class=\"diff\">
+    <thead>
+      <tr>
+        <th scope=\"col\" rowspan=\"2\">Endpoint</th>
+        <th scope=\"col\" rowspan=\"2\">Traffic class</th>
+        <th scope=\"colgroup\" colspan=\"3\">Requests</th>
+        <th scope=\"colgroup\" colspan=\"3\">Median duration</th>
+        <th scope=\"colgroup\" colspan=\"3\">Bytes</th>
+      </tr>
+      <tr>
+{sides}      </tr>
+    </thead>
+    <tbody>
+{rows}    </tbody>
+  </table>
+</section>
+",
+        sides = "        <th scope=\"col\">A</th>\n        <th scope=\"col\">B</th>\n        <th scope=\"col\">Δ</th>\n"
+            .repeat(3),
+    )
+}
+
+/// An endpoint of the JSON comparison.
+#[derive(Serialize)]
+struct JsonRow<'a> {
+    endpoint: &'a str,
+    traffic_class: &'static str,
+    a: &'a Option<Side>,
+    b: &'a Option<Side>,
+    /// Increase of the median duration from A to B, in milliseconds.
+    regression_ms: Option<i64>,
+}
+
+/// Render the comparison of the endpoints as JSON, the most regressed first.
+fn to_json(rows: &[Row], [a, b]: [&str; 2], shift: TimeDelta) -> String {
+    let endpoints = rows
+        .iter()
+        .map(|row| JsonRow {
+            endpoint: &row.endpoint,
+            traffic_class: row.traffic_class.as_str(),
+            a: &row.sides[0],
+            b: &row.sides[1],
+            regression_ms: row
+                .regression()
+                .map(|regression| regression.num_milliseconds()),
+        })
+        .collect::<Vec<_>>();
+
+    serde_json::json!({
+        "a": a,
+        "b": b,
+        "shift_ms": shift.num_milliseconds(),
+        "endpoints": endpoints,
+    })
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SpansBuilder;
+
+    #[test]
+    fn test_compare() {
+        let spans = |paths_and_durations: &[(&str, i64)]| {
+            let mut spans = SpansBuilder::default();
+
+            for (request_id, (path, duration)) in (0..).zip(paths_and_durations) {
+                spans.span(
+                    "main",
+                    request_id,
+                    &format!("https://example.org/_matrix/client/v3/{path}"),
+                    Some(200),
+                    0,
+                    *duration,
+                );
+            }
+
+            spans.build()
+        };
+        let a = spans(&[
+            ("sync", 100),
+            ("sync", 300),
+            ("versions", 50),
+            ("login", 10),
+        ]);
+        let b = spans(&[("sync", 900), ("versions", 40), ("keys/query", 20)]);
+
+        let rows = compare([&a, &b]);
+
+        assert_eq!(
+            rows.iter()
+                .map(|row| (
+                    row.endpoint.as_str(),
+                    row.sides
+                        .each_ref()
+                        .map(|side| side.as_ref().map(|side| side.requests)),
+                    row.regression()
+                        .map(|regression| regression.num_milliseconds())
+                ))
+                .collect::<Vec<_>>(),
+            [
+                (
+                    "POST /_matrix/client/v3/sync",
+                    [Some(2), Some(1)],
+                    Some(800)
+                ),
+                (
+                    "POST /_matrix/client/v3/versions",
+                    [Some(1), Some(1)],
+                    Some(-10)
+                ),
+                ("POST /_matrix/client/v3/keys/query", [None, Some(1)], None),
+                ("POST /_matrix/client/v3/login", [Some(1), None], None),
+            ]
+        );
+
+        let (spans, _, shift) = combine([(a, Vec::new()), (b, Vec::new())]);
+
+        assert_eq!(spans.keys().collect::<Vec<_>>(), ["A/main", "B/main"]);
+        assert_eq!(shift, TimeDelta::zero());
+
+        let json =
+            serde_json::from_str::<serde_json::Value>(&to_json(&rows, ["a.log", "b.log"], shift))
+                .unwrap();
+
+        assert_eq!(json["endpoints"][0]["regression_ms"], 800);
+        assert_eq!(json["endpoints"][0]["b"]["median_duration_ms"], 900);
+        assert_eq!(json["endpoints"][2]["a"], serde_json::Value::Null);
+    }
+}
@@ -0,0 +1,93 @@
+//! Parse human-readable durations, e.g. `500ms`, `30s`, `1h30m` or `2d`, and
+//! render them back.
+//!
+//! The durations and the offsets passed to the template are in milliseconds,
+//! with a microsecond precision, so that the requests to a local homeserver
+//! aren't all rounded to 0ms.
+
+use chrono::{DateTime, FixedOffset, TimeDelta};
+
+/// Parse a human-readable duration.
+///
+/// A duration is a sequence of integers, each followed by a unit among `ms`,
+/// `s`, `m`, `h` and `d`. `None` is returned if the duration is malformed.
+pub fn parse(duration: &str) -> Option<TimeDelta> {
+    let mut rest = duration.trim();
+    let mut total = TimeDelta::zero();
+
+    if rest.is_empty() {
+        return None;
+    }
+
+    while !rest.is_empty() {
+        let unit_start = rest.find(|character: char| !character.is_ascii_digit())?;
+        let (value, tail) = rest.split_at(unit_start);
+        let value: i64 = value.parse().ok()?;
+        let unit_end = tail
+            .find(|character: char| character.is_ascii_digit())
+            .unwrap_or(tail.len());
+        let (unit, tail) = tail.split_at(unit_end);
+
+        total += match unit {
+            "ms" => TimeDelta::try_milliseconds(value)?,
+            "s" => TimeDelta::try_seconds(value)?,
+            "m" => TimeDelta::try_minutes(value)?,
+            "h" => TimeDelta::try_hours(value)?,
+            "d" => TimeDelta::try_days(value)?,
+            _ => return None,
+        };
+        rest = tail;
+    }
+
+    Some(total)
+}
+
+/// Get a duration in milliseconds, with a microsecond precision, e.g. `0.35`
+/// for 350µs.
+pub fn to_milliseconds(duration: TimeDelta) -> f64 {
+    match duration.num_microseconds() {
+        Some(microseconds) => microseconds as f64 / 1_000.,
+        None => duration.num_milliseconds() as f64,
+    }
+}
+
+/// Get a duration from milliseconds, see [`to_milliseconds`].
+pub fn from_milliseconds(milliseconds: f64) -> TimeDelta {
+    TimeDelta::microseconds((milliseconds * 1_000.).round() as i64)
+}
+
+/// Get the offset of `at` from `origin`, a timestamp in milliseconds, see
+/// [`to_milliseconds`].
+pub fn offset_in_milliseconds(at: DateTime<FixedOffset>, origin: i64) -> f64 {
+    to_milliseconds(TimeDelta::microseconds(
+        at.timestamp_micros()
+            .saturating_sub(origin.saturating_mul(1_000)),
+    ))
+}
+
+/// Render a duration in the format of [`parse`], e.g. `1h30m`, so that it can
+/// be passed again as the value of a flag.
+pub fn to_text(duration: TimeDelta) -> String {
+    let mut milliseconds = duration.num_milliseconds().unsigned_abs();
+
+    if milliseconds == 0 {
+        return "0s".to_owned();
+    }
+
+    let mut text = String::new();
+
+    for (unit, length) in [
+        ("d", 86_400_000),
+        ("h", 3_600_000),
+        ("m", 60_000),
+        ("s", 1_000),
+        ("ms", 1),
+    ] {
+        if milliseconds >= length {
+            text.push_str(&format!("{}{unit}", milliseconds / length));
+            milliseconds %= length;
+        }
+    }
+
+    text
+}
@@ -0,0 +1,159 @@
+//! Assign the spans to duration bands, from thresholds like `500ms,2s,10s`,
+//! so that the rows can be colored by their own idea of slow, independently
+//! of the status.
+
+use std::collections::BTreeMap;
+
+use chrono::TimeDelta;
+
+use crate::{Span, duration, endpoint::Kind, html, human};
+
+/// Ascending duration thresholds, optionally per endpoint kind.
+#[derive(Clone, Debug, Default)]
+pub struct Thresholds {
+    default: Option<Vec<TimeDelta>>,
+    per_kind: BTreeMap<Kind, Vec<TimeDelta>>,
+}
+
+impl Thresholds {
+    /// Parse a list of thresholds like `500ms,2s,10s`, or like
+    /// `sync=5s,35s,60s` to override them for an endpoint kind.
+    pub fn parse(&mut self, value: &str) -> Result<(), String> {
+        let (kind, list) = match value.split_once('=') {
+            Some((kind, list)) => {
+                let kind = Kind::ALL
+                    .into_iter()
+                    .find(|candidate| candidate.as_str() == kind.trim())
+                    .ok_or_else(|| {
+                        format!(
+                            "Unknown endpoint kind `{kind}`; valid kinds are {}",
+                            Kind::ALL
+                                .iter()
+                                .map(|kind| format!("`{}`", kind.as_str()))
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        )
+                    })?;
+
+                (Some(kind), list)
+            }
+            None => (None, value),
+        };
+
+        let thresholds = list
+            .split(',')
+            .map(|threshold| {
+                duration::parse(threshold)
+                    .ok_or_else(|| format!("`{threshold}` isn't a duration like `500ms`"))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if !thresholds.is_sorted_by(|left, right| left < right) {
+            return Err(format!("`{list}` isn't strictly ascending"));
+        }
+
+        match kind {
+            Some(kind) => {
+                self.per_kind.insert(kind, thresholds);
+            }
+            None => self.default = Some(thresholds),
+        }
+
+        Ok(())
+    }
+
+    fn for_kind(&self, kind: Kind) -> Option<&[TimeDelta]> {
+        self.per_kind
+            .get(&kind)
+            .or(self.default.as_ref())
+            .map(Vec::as_slice)
+    }
+
+    /// Get the band of a span: the number of thresholds its duration has
+    /// reached. Cancelled spans, and spans without thresholds, have no band.
+    pub fn band(&self, span: &Span) -> Option<usize> {
+        span.status?;
+
+        let thresholds = self.for_kind(span.kind())?;
+
+        Some(
+            thresholds
+                .iter()
+                .take_while(|threshold| span.duration >= **threshold)
+                .count(),
+        )
+    }
+
+    /// Render the legend of the bands, or nothing if there is no threshold.
+    pub fn legend_to_html(&self) -> String {
+        let default = self.default.as_deref().map(|thresholds| (None, thresholds));
+        let per_kind = self
+            .per_kind
+            .iter()
+            .map(|(kind, thresholds)| (Some(kind.as_str()), thresholds.as_slice()));
+        let items = default
+            .into_iter()
+            .chain(per_kind)
+            .map(|(kind, thresholds)| {
+                let label = |threshold: &TimeDelta| human::duration(*threshold);
+                let bands = (0..=thresholds.len())
+                    .map(|band| {
+                        let range = match (band.checked_sub(1), thresholds.get(band)) {
+                            (None, Some(upper)) => format!("&lt; {}", label(upper)),
+                            (Some(lower), Some(upper)) => {
+                                format!("{} – {}", label(&thresholds[lower]), label(upper))
+                            }
+                            (Some(lower), None) => format!("≥ {}", label(&thresholds[lower])),
+                            (None, None) => unreachable!("There is at least one threshold"),
+                        };
+
+                        format!(" <span data-duration-band=\"{band}\">{range}</span>")
+                    })
+                    .collect::<String>();
+
+                format!(
+                    "  <li>{kind}:{bands}</li>\n",
+                    kind = kind
+                        .map(|kind| format!("<code>{}</code>", html::escape(kind)))
+                        .unwrap_or_else(|| "Durations".to_owned()),
+                )
+            })
+            .collect::<String>();
+
+        if items.is_empty() {
+            return String::new();
+        }
+
+        format!("<ul class=\"duration-bands\">\n{items}</ul>\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let mut thresholds = Thresholds::default();
+
+        assert!(thresholds.parse("500ms,2s,10s").is_ok());
+        assert!(thresholds.parse("sync=5s,35s,60s").is_ok());
+        assert_eq!(
+            thresholds.for_kind(Kind::Media),
+            Some([500, 2_000, 10_000].map(TimeDelta::milliseconds).as_slice())
+        );
+        assert_eq!(
+            thresholds.for_kind(Kind::Sync),
+            Some(
+                [5_000, 35_000, 60_000]
+                    .map(TimeDelta::milliseconds)
+                    .as_slice()
+            )
+        );
+
+        assert!(thresholds.parse("2s,500ms").is_err());
+        assert!(thresholds.parse("1s,1s").is_err());
+        assert!(thresholds.parse("1s,fast").is_err());
+        assert!(thresholds.parse("images=1s").is_err());
+    }
+}
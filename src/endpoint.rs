@@ -0,0 +1,470 @@
+//! Classify the endpoints of the Matrix APIs.
+
+use ada_url::Url;
+
+/// Coarse kind of an endpoint.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Kind {
+    Sync,
+    Media,
+    E2ee,
+    Send,
+    Other,
+}
+
+impl Kind {
+    /// All the kinds, in display order.
+    pub const ALL: [Self; 5] = [Self::Sync, Self::Media, Self::E2ee, Self::Send, Self::Other];
+
+    /// Classify an URI.
+    pub fn of(uri: &str) -> Self {
+        let Ok(uri) = Url::parse(uri, None) else {
+            return Self::Other;
+        };
+        let path = uri.pathname();
+
+        if path.ends_with("/sync") {
+            Self::Sync
+        } else if path.starts_with("/_matrix/media/")
+            || path.starts_with("/_matrix/client/v1/media/")
+        {
+            Self::Media
+        } else if path.contains("/keys/")
+            || path.contains("/room_keys/")
+            || path.contains("/sendToDevice/")
+        {
+            Self::E2ee
+        } else if path.contains("/rooms/") && (path.contains("/send/") || path.contains("/redact/"))
+        {
+            Self::Send
+        } else {
+            Self::Other
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Sync => "sync",
+            Self::Media => "media",
+            Self::E2ee => "e2ee",
+            Self::Send => "send",
+            Self::Other => "other",
+        }
+    }
+}
+
+/// Category of an endpoint of the client-server API, finer than its
+/// [`Kind`], to group the requests by what they do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Category {
+    Sync,
+    SendEvent,
+    Redaction,
+    RoomState,
+    RoomMessages,
+    RoomMembers,
+    Membership,
+    ReceiptsAndTyping,
+    AccountData,
+    MediaDownload,
+    MediaUpload,
+    Media,
+    KeysQuery,
+    KeysClaim,
+    KeysUpload,
+    KeyBackup,
+    ToDevice,
+    Devices,
+    Profile,
+    Presence,
+    Push,
+    Directory,
+    Authentication,
+    Discovery,
+    Other,
+}
+
+impl Category {
+    pub const ALL: [Self; 25] = [
+        Self::Sync,
+        Self::SendEvent,
+        Self::Redaction,
+        Self::RoomState,
+        Self::RoomMessages,
+        Self::RoomMembers,
+        Self::Membership,
+        Self::ReceiptsAndTyping,
+        Self::AccountData,
+        Self::MediaDownload,
+        Self::MediaUpload,
+        Self::Media,
+        Self::KeysQuery,
+        Self::KeysClaim,
+        Self::KeysUpload,
+        Self::KeyBackup,
+        Self::ToDevice,
+        Self::Devices,
+        Self::Profile,
+        Self::Presence,
+        Self::Push,
+        Self::Directory,
+        Self::Authentication,
+        Self::Discovery,
+        Self::Other,
+    ];
+
+    /// Classify an URI by the segments of its path.
+    pub fn of(uri: &str) -> Self {
+        let Ok(uri) = Url::parse(uri, None) else {
+            return Self::Other;
+        };
+        let path = uri.pathname();
+        let segments = path
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .collect::<Vec<_>>();
+        let has = |segment: &str| segments.contains(&segment);
+        let last = segments.last().copied().unwrap_or_default();
+
+        if path.starts_with("/.well-known/") || matches!(last, "versions" | "capabilities") {
+            Self::Discovery
+        } else if last == "sync" {
+            Self::Sync
+        } else if path.starts_with("/_matrix/media/") || has("media") {
+            if has("download") || has("thumbnail") {
+                Self::MediaDownload
+            } else if has("upload") || has("create") {
+                Self::MediaUpload
+            } else {
+                Self::Media
+            }
+        } else if has("keys") && !has("room_keys") {
+            match last {
+                "query" => Self::KeysQuery,
+                "claim" => Self::KeysClaim,
+                _ => Self::KeysUpload,
+            }
+        } else if has("room_keys") {
+            Self::KeyBackup
+        } else if has("sendToDevice") {
+            Self::ToDevice
+        } else if has("account_data") {
+            Self::AccountData
+        } else if has("rooms") && has("send") {
+            Self::SendEvent
+        } else if has("redact") {
+            Self::Redaction
+        } else if has("rooms") && has("state") {
+            Self::RoomState
+        } else if has("rooms")
+            && ["messages", "context", "event", "relations", "threads"]
+                .iter()
+                .any(|segment| has(segment))
+        {
+            Self::RoomMessages
+        } else if has("rooms") && matches!(last, "members" | "joined_members") {
+            Self::RoomMembers
+        } else if matches!(
+            last,
+            "join" | "leave" | "forget" | "invite" | "kick" | "ban" | "unban" | "knock"
+        ) || has("join")
+            || has("knock")
+        {
+            Self::Membership
+        } else if ["receipt", "read_markers", "typing"]
+            .iter()
+            .any(|segment| has(segment))
+        {
+            Self::ReceiptsAndTyping
+        } else if has("devices") || has("delete_devices") {
+            Self::Devices
+        } else if has("profile") {
+            Self::Profile
+        } else if has("presence") {
+            Self::Presence
+        } else if has("pushrules") || has("pushers") || has("notifications") {
+            Self::Push
+        } else if ["publicRooms", "user_directory", "directory", "search"]
+            .iter()
+            .any(|segment| has(segment))
+        {
+            Self::Directory
+        } else if [
+            "login",
+            "logout",
+            "refresh",
+            "register",
+            "account",
+            "auth_metadata",
+            "oauth2",
+        ]
+        .iter()
+        .any(|segment| has(segment))
+        {
+            Self::Authentication
+        } else {
+            Self::Other
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Sync => "sync",
+            Self::SendEvent => "send event",
+            Self::Redaction => "redaction",
+            Self::RoomState => "room state",
+            Self::RoomMessages => "room messages",
+            Self::RoomMembers => "room members",
+            Self::Membership => "membership",
+            Self::ReceiptsAndTyping => "receipts and typing",
+            Self::AccountData => "account data",
+            Self::MediaDownload => "media download",
+            Self::MediaUpload => "media upload",
+            Self::Media => "media",
+            Self::KeysQuery => "keys query",
+            Self::KeysClaim => "keys claim",
+            Self::KeysUpload => "keys upload",
+            Self::KeyBackup => "key backup",
+            Self::ToDevice => "to-device",
+            Self::Devices => "devices",
+            Self::Profile => "profile",
+            Self::Presence => "presence",
+            Self::Push => "push",
+            Self::Directory => "directory",
+            Self::Authentication => "authentication",
+            Self::Discovery => "discovery",
+            Self::Other => "other",
+        }
+    }
+}
+
+/// Get the template of the path of an URI, with the identifiers collapsed,
+/// e.g. `/_matrix/client/v3/rooms/{roomId}/messages`, so that the requests to
+/// the same endpoint can be grouped.
+pub fn template(uri: &str) -> String {
+    let Ok(uri) = Url::parse(uri, None) else {
+        return String::new();
+    };
+    let segments = uri.pathname().split('/').collect::<Vec<_>>();
+
+    segments
+        .iter()
+        .enumerate()
+        .map(|(nth, segment)| {
+            let previous = |distance: usize| {
+                nth.checked_sub(distance)
+                    .map(|nth| segments[nth])
+                    .unwrap_or_default()
+            };
+
+            template_segment(segment, previous(1), previous(2)).unwrap_or(segment)
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Get the placeholder of a path segment, if it's an identifier, given the two
+/// segments before it.
+fn template_segment(segment: &str, previous: &str, before_previous: &str) -> Option<&'static str> {
+    Some(if segment.is_empty() {
+        return None;
+    } else if has_sigil(segment, '!') {
+        "{roomId}"
+    } else if has_sigil(segment, '$') {
+        "{eventId}"
+    } else if has_sigil(segment, '@') {
+        "{userId}"
+    } else if has_sigil(segment, '#') {
+        "{roomAlias}"
+    } else if matches!(before_previous, "send" | "sendToDevice" | "redact") {
+        "{txnId}"
+    } else if before_previous == "state" {
+        "{stateKey}"
+    } else if matches!(previous, "download" | "thumbnail") {
+        "{serverName}"
+    } else if matches!(before_previous, "download" | "thumbnail") {
+        "{mediaId}"
+    } else if previous == "devices" {
+        "{deviceId}"
+    } else if before_previous == "keys" && has_sigil(previous, '!') {
+        "{sessionId}"
+    } else {
+        return None;
+    })
+}
+
+/// Get the ID of the room targeted by an URI, from its `/rooms/{roomId}/`
+/// segment, percent-decoded, e.g. `!abc:example.org`.
+pub fn room_id(uri: &str) -> Option<String> {
+    let uri = Url::parse(uri, None).ok()?;
+    let mut segments = uri.pathname().split('/');
+    segments.find(|segment| *segment == "rooms")?;
+    let segment = segments.next().filter(|segment| !segment.is_empty())?;
+
+    Some(percent_decode(segment))
+}
+
+/// Decode the percent-encoded bytes of a path segment, e.g. `%3A` to `:`.
+pub fn percent_decode(segment: &str) -> String {
+    let mut bytes = Vec::with_capacity(segment.len());
+    let mut rest = segment.as_bytes();
+
+    while let Some((&byte, tail)) = rest.split_first() {
+        match tail
+            .get(..2)
+            .filter(|_| byte == b'%')
+            .and_then(|encoded| std::str::from_utf8(encoded).ok())
+            .and_then(|encoded| u8::from_str_radix(encoded, 16).ok())
+        {
+            Some(decoded) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Whether a path segment starts with the sigil of a Matrix identifier, raw or
+/// percent-encoded.
+pub fn has_sigil(segment: &str, sigil: char) -> bool {
+    segment.starts_with(sigil)
+        || segment
+            .strip_prefix('%')
+            .and_then(|encoded| encoded.get(..2))
+            .and_then(|encoded| u8::from_str_radix(encoded, 16).ok())
+            .is_some_and(|byte| char::from(byte) == sigil)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_template() {
+        for (uri, expected) in [
+            (
+                "https://example.org/_matrix/client/unstable/org.matrix.simplified_msc3575/sync?pos=3",
+                "/_matrix/client/unstable/org.matrix.simplified_msc3575/sync",
+            ),
+            (
+                "https://example.org/_matrix/client/v3/rooms/!abc:example.org/messages",
+                "/_matrix/client/v3/rooms/{roomId}/messages",
+            ),
+            (
+                "https://example.org/_matrix/client/v3/rooms/%21abc%3Aexample.org/send/m.room.message/txn42",
+                "/_matrix/client/v3/rooms/{roomId}/send/m.room.message/{txnId}",
+            ),
+            (
+                "https://example.org/_matrix/client/v3/rooms/!abc:example.org/state/m.room.member/@alice:example.org",
+                "/_matrix/client/v3/rooms/{roomId}/state/m.room.member/{userId}",
+            ),
+            (
+                "https://example.org/_matrix/client/v3/rooms/!abc:example.org/redact/$event/txn42",
+                "/_matrix/client/v3/rooms/{roomId}/redact/{eventId}/{txnId}",
+            ),
+            (
+                "https://example.org/_matrix/client/v1/media/download/example.org/AbCdEf",
+                "/_matrix/client/v1/media/download/{serverName}/{mediaId}",
+            ),
+            (
+                "https://example.org/_matrix/client/v3/room_keys/keys/!abc:example.org/session?version=1",
+                "/_matrix/client/v3/room_keys/keys/{roomId}/{sessionId}",
+            ),
+            (
+                "https://example.org/_matrix/client/v3/sendToDevice/m.room.encrypted/7",
+                "/_matrix/client/v3/sendToDevice/m.room.encrypted/{txnId}",
+            ),
+        ] {
+            assert_eq!(template(uri), expected, "{uri}");
+        }
+    }
+
+    #[test]
+    fn test_room_id() {
+        for (uri, expected) in [
+            (
+                "https://example.org/_matrix/client/v3/rooms/!abc:example.org/messages",
+                Some("!abc:example.org"),
+            ),
+            (
+                "https://example.org/_matrix/client/v3/rooms/%21abc%3Aexample.org/send/m.room.message/txn42",
+                Some("!abc:example.org"),
+            ),
+            ("https://example.org/_matrix/client/v3/sync", None),
+        ] {
+            assert_eq!(room_id(uri).as_deref(), expected, "{uri}");
+        }
+    }
+
+    #[test]
+    fn test_category() {
+        for (path, expected) in [
+            (
+                "/_matrix/client/unstable/org.matrix.simplified_msc3575/sync",
+                Category::Sync,
+            ),
+            (
+                "/_matrix/client/v3/rooms/!abc:example.org/send/m.room.message/txn42",
+                Category::SendEvent,
+            ),
+            (
+                "/_matrix/client/v3/rooms/!abc:example.org/state/m.room.name/",
+                Category::RoomState,
+            ),
+            (
+                "/_matrix/client/v3/rooms/!abc:example.org/messages",
+                Category::RoomMessages,
+            ),
+            (
+                "/_matrix/client/v3/rooms/!abc:example.org/receipt/m.read/$event",
+                Category::ReceiptsAndTyping,
+            ),
+            (
+                "/_matrix/client/v3/user/@alice:example.org/account_data/m.direct",
+                Category::AccountData,
+            ),
+            (
+                "/_matrix/client/v1/media/download/example.org/AbCdEf",
+                Category::MediaDownload,
+            ),
+            ("/_matrix/media/v3/upload", Category::MediaUpload),
+            ("/_matrix/client/v3/keys/query", Category::KeysQuery),
+            ("/_matrix/client/v3/keys/claim", Category::KeysClaim),
+            (
+                "/_matrix/client/v3/keys/device_signing/upload",
+                Category::KeysUpload,
+            ),
+            (
+                "/_matrix/client/v3/room_keys/keys/!abc:example.org/session",
+                Category::KeyBackup,
+            ),
+            (
+                "/_matrix/client/v3/sendToDevice/m.room.encrypted/7",
+                Category::ToDevice,
+            ),
+            (
+                "/_matrix/client/v3/profile/@alice:example.org/displayname",
+                Category::Profile,
+            ),
+            (
+                "/_matrix/client/v3/join/%23room:example.org",
+                Category::Membership,
+            ),
+            ("/_matrix/client/v3/login", Category::Authentication),
+            ("/_matrix/client/versions", Category::Discovery),
+            ("/_matrix/client/v3/voip/turnServer", Category::Other),
+        ] {
+            assert_eq!(
+                Category::of(&format!("https://example.org{path}")),
+                expected,
+                "{path}"
+            );
+        }
+    }
+}
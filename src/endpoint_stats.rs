@@ -0,0 +1,436 @@
+//! Summarize the durations, the errors and the bytes per endpoint, with the
+//! p95 over time, to tell an endpoint which is consistently slow from an
+//! endpoint which has had one bad period.
+//!
+//! The endpoints are grouped by traffic class, so that the latency of the
+//! homeserver stays apart from, e.g., the one of an identity provider. The
+//! IDs of their paths are collapsed, see [`crate::endpoint::template`], so that the
+//! requests to 500 rooms make a single row. The same aggregates are computed
+//! per category of endpoints too, see [`crate::endpoint::Category`].
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, FixedOffset};
+use serde::Serialize;
+
+use crate::{
+    RequestId, Spans, buckets::Aggregate, endpoint::Category, html, human, stats,
+    traffic_class::TrafficClass,
+};
+
+/// Maximum number of time buckets of the sparklines.
+const MAXIMUM_NUMBER_OF_BUCKETS: i64 = 24;
+
+/// Minimum number of spans of an endpoint to draw its sparkline: below, the
+/// p95 of the buckets is noise.
+const MINIMUM_NUMBER_OF_SPANS: usize = 10;
+
+/// Durations of the spans of an endpoint.
+#[derive(Serialize)]
+pub struct Row {
+    pub traffic_class: &'static str,
+    pub endpoint: String,
+    #[serde(flatten)]
+    pub summary: Summary,
+
+    /// The first span of the endpoint, to link to its row.
+    pub first_connection_id: String,
+    pub first_request_id: RequestId,
+
+    /// The p95 duration of each time bucket, in milliseconds, `None` for
+    /// the buckets without any span. Empty if the endpoint has too few spans.
+    pub sparkline: Vec<Option<i64>>,
+}
+
+/// Durations of the spans of a traffic class.
+#[derive(Serialize)]
+pub struct ClassRow {
+    pub traffic_class: &'static str,
+    #[serde(flatten)]
+    pub summary: Summary,
+}
+
+/// Durations of the spans of a category of endpoints.
+#[derive(Serialize)]
+pub struct CategoryRow {
+    pub category: &'static str,
+    #[serde(flatten)]
+    pub summary: Summary,
+}
+
+/// Aggregates of the spans of an endpoint, a traffic class or a category.
+#[derive(Serialize)]
+pub struct Summary {
+    pub requests: usize,
+
+    /// Number of responses whose status isn't 2xx.
+    pub non_2xx: usize,
+
+    /// Ratio of the responses whose status isn't 2xx, among the requests.
+    pub error_rate: f64,
+
+    /// Number of bytes sent and received.
+    pub bytes: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+
+    /// In milliseconds.
+    pub min_duration: Option<i64>,
+    pub p50_duration: Option<i64>,
+    pub p95_duration: Option<i64>,
+    pub max_duration: Option<i64>,
+}
+
+impl Summary {
+    /// Summarize a finished aggregate.
+    fn new(aggregate: &Aggregate) -> Self {
+        let percentile = |percentile| {
+            aggregate
+                .percentile_duration(percentile)
+                .map(|duration| duration.num_milliseconds())
+        };
+
+        Self {
+            requests: aggregate.requests,
+            non_2xx: aggregate.errors,
+            error_rate: if aggregate.requests == 0 {
+                0.
+            } else {
+                aggregate.errors as f64 / aggregate.requests as f64
+            },
+            bytes: aggregate.bytes_down + aggregate.bytes_up,
+            bytes_sent: aggregate.bytes_up,
+            bytes_received: aggregate.bytes_down,
+            min_duration: aggregate
+                .durations
+                .first()
+                .map(|duration| duration.num_milliseconds()),
+            p50_duration: percentile(50.),
+            p95_duration: percentile(95.),
+            max_duration: aggregate
+                .durations
+                .last()
+                .map(|duration| duration.num_milliseconds()),
+        }
+    }
+}
+
+/// Statistics per endpoint.
+#[derive(Default, Serialize)]
+pub struct EndpointStats {
+    /// Duration of a time bucket, in milliseconds.
+    pub bucket_duration: i64,
+
+    /// The traffic classes, in display order.
+    pub classes: Vec<ClassRow>,
+
+    /// The endpoints, by traffic class, the most requested first.
+    pub endpoints: Vec<Row>,
+
+    /// The categories of endpoints, the most requested first.
+    pub categories: Vec<CategoryRow>,
+}
+
+/// Summarize the durations per endpoint, over the time range of the spans.
+pub fn compute(
+    spans: &Spans,
+    (start_at, end_at): (DateTime<FixedOffset>, DateTime<FixedOffset>),
+) -> EndpointStats {
+    // Plus one, so that the end of the last span falls within the last bucket.
+    let total_duration = (end_at - start_at).num_milliseconds() + 1;
+    let number_of_buckets = MAXIMUM_NUMBER_OF_BUCKETS.min(total_duration);
+
+    let mut per_endpoint = BTreeMap::<(TrafficClass, String), (Aggregate, Vec<Vec<i64>>, _)>::new();
+    let mut per_class = BTreeMap::<TrafficClass, Aggregate>::new();
+    let mut per_category = BTreeMap::<Category, Aggregate>::new();
+
+    for (connection_id, spans) in spans {
+        for (request_id, span) in spans {
+            let traffic_class = span.traffic_class();
+            let bucket =
+                (span.start_at - start_at).num_milliseconds() * number_of_buckets / total_duration;
+            let (aggregate, durations, first_span) = per_endpoint
+                .entry((traffic_class, span.endpoint()))
+                .or_insert_with(|| {
+                    (
+                        Aggregate::default(),
+                        vec![Vec::new(); number_of_buckets as usize],
+                        (span.start_at, connection_id, *request_id),
+                    )
+                });
+
+            aggregate.add(span);
+            durations[bucket as usize].push(span.duration.num_milliseconds());
+            *first_span = (*first_span).min((span.start_at, connection_id, *request_id));
+            per_class.entry(traffic_class).or_default().add(span);
+            per_category.entry(span.category()).or_default().add(span);
+        }
+    }
+
+    let mut per_endpoint = per_endpoint
+        .into_iter()
+        .map(|(key, (mut aggregate, durations, first_span))| {
+            aggregate.finish();
+
+            (key, aggregate, durations, first_span)
+        })
+        .collect::<Vec<_>>();
+    // The map is sorted by class then by endpoint, and the sort is stable.
+    per_endpoint.sort_by(
+        |((left, _), left_aggregate, ..), ((right, _), right_aggregate, ..)| {
+            left.cmp(right)
+                .then_with(|| right_aggregate.requests.cmp(&left_aggregate.requests))
+        },
+    );
+
+    let per_endpoint = per_endpoint
+        .into_iter()
+        .map(
+            |((traffic_class, endpoint), aggregate, durations, (_, connection_id, request_id))| {
+                Row {
+                    sparkline: if aggregate.requests < MINIMUM_NUMBER_OF_SPANS {
+                        Vec::new()
+                    } else {
+                        durations
+                            .into_iter()
+                            .map(|mut durations| {
+                                durations.sort_unstable();
+
+                                stats::percentile(&durations, 95.)
+                            })
+                            .collect()
+                    },
+                    summary: Summary::new(&aggregate),
+                    traffic_class: traffic_class.as_str(),
+                    endpoint,
+                    first_connection_id: connection_id.clone(),
+                    first_request_id: request_id,
+                }
+            },
+        )
+        .collect();
+
+    let mut categories = per_category
+        .into_iter()
+        .map(|(category, mut aggregate)| {
+            aggregate.finish();
+
+            CategoryRow {
+                category: category.as_str(),
+                summary: Summary::new(&aggregate),
+            }
+        })
+        .collect::<Vec<_>>();
+    // The map is sorted by category, and the sort is stable.
+    categories.sort_by_key(|row| std::cmp::Reverse(row.summary.requests));
+
+    EndpointStats {
+        bucket_duration: (total_duration + number_of_buckets - 1) / number_of_buckets,
+        classes: per_class
+            .into_iter()
+            .map(|(traffic_class, mut aggregate)| {
+                aggregate.finish();
+
+                ClassRow {
+                    traffic_class: traffic_class.as_str(),
+                    summary: Summary::new(&aggregate),
+                }
+            })
+            .collect(),
+        endpoints: per_endpoint,
+        categories,
+    }
+}
+
+impl Summary {
+    /// Render the cells of the aggregates.
+    fn cells_to_html(&self) -> String {
+        let milliseconds = |value: Option<i64>| value.map(human::milliseconds).unwrap_or_default();
+
+        format!(
+            "        <td>{requests}</td>
+        <td>{non_2xx}</td>
+        <td>{error_rate}</td>
+        <td>{min_duration}</td>
+        <td>{p50_duration}</td>
+        <td>{p95_duration}</td>
+        <td>{max_duration}</td>
+        <td>{bytes_sent}</td>
+        <td>{bytes_received}</td>
+",
+            requests = human::count(self.requests),
+            non_2xx = human::count(self.non_2xx),
+            error_rate = human::percentage(self.non_2xx as f64, self.requests as f64),
+            min_duration = milliseconds(self.min_duration),
+            p50_duration = milliseconds(self.p50_duration),
+            p95_duration = milliseconds(self.p95_duration),
+            max_duration = milliseconds(self.max_duration),
+            bytes_sent = human::bytes(self.bytes_sent),
+            bytes_received = human::bytes(self.bytes_received),
+        )
+    }
+}
+
+impl EndpointStats {
+    /// Render the table, or nothing if there is no span.
+    pub fn to_html(&self) -> String {
+        if self.endpoints.is_empty() {
+            return String::new();
+        }
+
+        let tbodies = self
+            .classes
+            .iter()
+            .map(|class| {
+                // The class is only worth a heading if there are several ones.
+                let heading = if self.classes.len() > 1 {
+                    format!(
+                        "      <tr class=\"traffic-class\">
+        <th scope=\"rowgroup\">{traffic_class}</th>
+{cells}        <td></td>
+      </tr>
+",
+                        traffic_class = class.traffic_class,
+                        cells = class.summary.cells_to_html(),
+                    )
+                } else {
+                    String::new()
+                };
+                let rows = self
+                    .endpoints
+                    .iter()
+                    .filter(|row| row.traffic_class == class.traffic_class)
+                    .map(|row| {
+                        let sparkline = row
+                            .sparkline
+                            .iter()
+                            .map(|p95| p95.map(|p95| p95.to_string()).unwrap_or_default())
+                            .collect::<Vec<_>>()
+                            .join(",");
+
+                        format!(
+                            "      <tr>
+        <th scope=\"row\"><a href=\"#{first_connection_id}-{first_request_id}\" title=\"First request\"><code>{endpoint}</code></a></th>
+{cells}        <td data-sparkline=\"{sparkline}\"></td>
+      </tr>
+",
+                            endpoint = html::escape(&row.endpoint),
+                            first_connection_id = html::escape(&row.first_connection_id),
+                            first_request_id = row.first_request_id,
+                            cells = row.summary.cells_to_html(),
+                        )
+                    })
+                    .collect::<String>();
+
+                format!("    <tbody>\n{heading}{rows}    </tbody>\n")
+            })
+            .collect::<String>();
+
+        let categories = self
+            .categories
+            .iter()
+            .map(|row| {
+                format!(
+                    "      <tr>
+        <th scope=\"row\">{category}</th>
+{cells}      </tr>
+",
+                    category = row.category,
+                    cells = row.summary.cells_to_html(),
+                )
+            })
+            .collect::<String>();
+
+        format!(
+            "  <h3>Statistics per category</h3>
+  <table class=\"category-stats\">
+    <thead>
+      <tr>
+        <th scope=\"col\">Category</th>
+        <th scope=\"col\">Requests</th>
+        <th scope=\"col\">Non-2xx</th>
+        <th scope=\"col\">Error rate</th>
+        <th scope=\"col\">Min duration</th>
+        <th scope=\"col\">Median duration</th>
+        <th scope=\"col\">p95 duration</th>
+        <th scope=\"col\">Max duration</th>
+        <th scope=\"col\">Sent</th>
+        <th scope=\"col\">Received</th>
+      </tr>
+    </thead>
+    <tbody>
+{categories}    </tbody>
+  </table>
+  <h3>Statistics per endpoint</h3>
+  <table class=\"endpoint-stats\" data-bucket-duration=\"{bucket_duration}\">
+    <thead>
+      <tr>
+        <th scope=\"col\">Endpoint</th>
+        <th scope=\"col\">Requests</th>
+        <th scope=\"col\">Non-2xx</th>
+        <th scope=\"col\">Error rate</th>
+        <th scope=\"col\">Min duration</th>
+        <th scope=\"col\">Median duration</th>
+        <th scope=\"col\">p95 duration</th>
+        <th scope=\"col\">Max duration</th>
+        <th scope=\"col\">Sent</th>
+        <th scope=\"col\">Received</th>
+        <th scope=\"col\">p95 over time</th>
+      </tr>
+    </thead>
+{tbodies}  </table>
+",
+            bucket_duration = self.bucket_duration,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SpansBuilder, filters};
+
+    #[test]
+    fn test_compute() {
+        let mut spans = SpansBuilder::default();
+
+        for request_id in 0..500 {
+            let nth = i64::from(request_id);
+            spans.span(
+                "c",
+                request_id,
+                &format!(
+                    "https://example.org/_matrix/client/v3/rooms/!room{nth}:example.org/messages"
+                ),
+                Some(if nth % 100 == 0 { 502 } else { 200 }),
+                (500 - nth) * 1_000,
+                nth,
+            );
+        }
+
+        let spans = spans.build();
+        let stats = compute(&spans, filters::time_range(&spans).unwrap());
+
+        assert_eq!(stats.endpoints.len(), 1);
+        assert_eq!(stats.categories.len(), 1);
+        assert_eq!(stats.categories[0].category, "room messages");
+        assert_eq!(stats.categories[0].summary.requests, 500);
+
+        let row = &stats.endpoints[0];
+
+        assert_eq!(
+            row.endpoint,
+            "POST /_matrix/client/v3/rooms/{roomId}/messages"
+        );
+        assert_eq!(row.summary.requests, 500);
+        assert_eq!(row.summary.non_2xx, 5);
+        assert_eq!(row.summary.error_rate, 0.01);
+        assert_eq!(row.summary.min_duration, Some(0));
+        assert_eq!(row.summary.max_duration, Some(499));
+        // The first span is the one starting first.
+        assert_eq!(
+            (row.first_connection_id.as_str(), row.first_request_id),
+            ("c", 499)
+        );
+    }
+}
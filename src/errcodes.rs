@@ -0,0 +1,87 @@
+//! Aggregate the Matrix error codes of the failed responses.
+
+use std::collections::BTreeMap;
+
+use crate::{Spans, html, human};
+
+/// Error code of an invalid or expired access token.
+const UNKNOWN_TOKEN: &str = "M_UNKNOWN_TOKEN";
+
+/// Count the spans per error code.
+fn count(spans: &Spans) -> BTreeMap<&str, usize> {
+    let mut counts = BTreeMap::new();
+
+    for span in spans.values().flat_map(BTreeMap::values) {
+        if let Some(errcode) = &span.errcode {
+            *counts.entry(errcode.as_str()).or_default() += 1;
+        }
+    }
+
+    counts
+}
+
+/// Count the `M_UNKNOWN_TOKEN` errors followed by a request to refresh the
+/// access token, on any connection.
+fn count_refreshed_unknown_tokens(spans: &Spans) -> usize {
+    let spans = || spans.values().flat_map(BTreeMap::values);
+
+    spans()
+        .filter(|span| span.errcode.as_deref() == Some(UNKNOWN_TOKEN))
+        .filter(|unknown_token| {
+            spans().any(|span| {
+                span.start_at > unknown_token.start_at && span.path().ends_with("/refresh")
+            })
+        })
+        .count()
+}
+
+/// Render the counts per error code as HTML, or nothing if there is no error
+/// code.
+pub fn to_html(spans: &Spans) -> String {
+    let counts = count(spans);
+
+    if counts.is_empty() {
+        return String::new();
+    }
+
+    let rows = counts
+        .iter()
+        .map(|(errcode, count)| {
+            let note = if *errcode == UNKNOWN_TOKEN {
+                format!(
+                    "{} followed by a token refresh",
+                    human::count(count_refreshed_unknown_tokens(spans))
+                )
+            } else {
+                String::new()
+            };
+
+            format!(
+                "      <tr>
+        <td><code>{errcode}</code></td>
+        <td>{count}</td>
+        <td>{note}</td>
+      </tr>
+",
+                errcode = html::escape(errcode),
+                count = human::count(*count),
+            )
+        })
+        .collect::<String>();
+
+    format!(
+        "  <h3>Matrix error codes</h3>
+  <table>
+    <thead>
+      <tr>
+        <th scope=\"col\">Error code</th>
+        <th scope=\"col\">Responses</th>
+        <th scope=\"col\">Note</th>
+      </tr>
+    </thead>
+    <tbody>
+{rows}    </tbody>
+  </table>
+"
+    )
+}
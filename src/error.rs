@@ -0,0 +1,61 @@
+//! Errors ending a run, each with its own exit code so that scripts can tell
+//! a bad invocation from a bad input:
+//!
+//! - `0`: success;
+//! - `1`: usage error, see [`Error::Usage`];
+//! - `2`: parse failure, i.e. an input which can't be used, see
+//!   [`Error::Input`], or a condition of the logs with `--strict`;
+//! - `3`: a file, a socket or a stream which can't be read or written;
+//! - `4`: a budget of `check` exceeded.
+//!
+//! The recoverable conditions of the logs, e.g. a truncated line, aren't
+//! errors: see [`crate::conditions`].
+
+use std::{fmt, io};
+
+/// Exit code of a condition of the logs with `--strict`, a parse failure like
+/// [`Error::Input`].
+pub const STRICT_EXIT_CODE: i32 = 2;
+
+/// Exit code of a budget of `check` exceeded.
+pub const BUDGET_EXIT_CODE: i32 = 4;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The arguments are missing or invalid.
+    Usage(String),
+
+    /// A file, a socket or a stream can't be read or written.
+    Io { path: String, error: io::Error },
+
+    /// An input can't be used, e.g. an export or a template.
+    Input(String),
+}
+
+impl Error {
+    /// Get the exit code of the error.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::Usage(_) => 1,
+            Self::Input(_) => 2,
+            Self::Io { .. } => 3,
+        }
+    }
+
+    /// Make a function mapping an IO error about `path`, for `map_err`.
+    pub fn io(path: impl fmt::Display) -> impl FnOnce(io::Error) -> Self {
+        move |error| Self::Io {
+            path: path.to_string(),
+            error,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Usage(message) | Self::Input(message) => formatter.write_str(message),
+            Self::Io { path, error } => write!(formatter, "`{path}`: {error}"),
+        }
+    }
+}
@@ -0,0 +1,205 @@
+//! Explain why the lines don't match the pattern of the requests and
+//! responses, e.g. because the target is printed differently by a build of
+//! the app: automatically when no line has matched, or with `--explain`.
+//!
+//! Weaker probes count the lines which look like they should have matched,
+//! and the first lines passing a probe but not the full pattern are kept as
+//! near misses, with the piece of the pattern they fail at, see
+//! [`crate::pattern`].
+
+use regex::Regex;
+
+use crate::{human, pattern};
+
+/// Number of near misses kept per probe.
+const NUMBER_OF_NEAR_MISSES: usize = 2;
+
+/// Maximum number of characters of a near miss printed.
+const MAXIMUM_LINE_LENGTH: usize = 200;
+
+/// A line which passes a probe, but not the full pattern.
+struct NearMiss {
+    line_nth: usize,
+    line: String,
+    /// The piece of the pattern, or its named capture group, failing.
+    failure: &'static str,
+}
+
+/// A weak pattern, and the lines passing it.
+struct Probe {
+    description: &'static str,
+    find: Regex,
+    number_of_lines: usize,
+    near_misses: Vec<NearMiss>,
+}
+
+/// Find the piece of the pattern a line fails at, if any: the first prefix of
+/// the pattern it doesn't match, or the first mandatory capture group missing.
+fn failure_of(steps: &[(&'static str, Regex)], full: &Regex, line: &str) -> Option<&'static str> {
+    if let Some((piece, _)) = steps.iter().find(|(_, step)| !step.is_match(line)) {
+        return Some(piece);
+    }
+
+    let captures = full.captures(line)?;
+
+    ["method", "uri"]
+        .into_iter()
+        .find(|name| captures.name(name).is_none())
+}
+
+pub struct Explanation {
+    probes: Vec<Probe>,
+    /// The prefixes of the full pattern, each with the piece it adds.
+    steps: Vec<(&'static str, Regex)>,
+    full: Regex,
+}
+
+impl Explanation {
+    pub fn new() -> Self {
+        let probe = |description, find: &str| Probe {
+            description,
+            find: Regex::new(find).expect("Failed to build a probe regex"),
+            number_of_lines: 0,
+            near_misses: Vec::new(),
+        };
+
+        Self {
+            probes: vec![
+                probe(
+                    "with an RFC 3339 timestamp",
+                    r"\d{4}-\d{2}-\d{2}[Tt\x20]\d{2}:\d{2}:\d{2}(\.\d+)?([Zz]|[+-]\d{2}:\d{2})",
+                ),
+                probe(
+                    "mentioning `matrix_sdk::http_client`",
+                    r"matrix_sdk::http_client",
+                ),
+                probe(r#"mentioning `request_id="REQ-`"#, r#"request_id="REQ-"#),
+            ],
+            steps: vec![
+                ("datetime", pattern::build(&[pattern::DATETIME])),
+                (
+                    "target",
+                    pattern::build(&[pattern::DATETIME, pattern::TARGET]),
+                ),
+                (
+                    "request_id",
+                    pattern::build(&[
+                        pattern::DATETIME,
+                        pattern::TARGET,
+                        pattern::CONNECTION,
+                        pattern::SEND,
+                    ]),
+                ),
+            ],
+            full: pattern::build(&pattern::ALL),
+        }
+    }
+
+    /// Probe a line, the `line_nth`-th of the log.
+    pub fn probe(&mut self, line: &str, line_nth: usize) {
+        let mut failure = None;
+
+        for probe in &mut self.probes {
+            if !probe.find.is_match(line) {
+                continue;
+            }
+
+            probe.number_of_lines += 1;
+
+            if probe.near_misses.len() < NUMBER_OF_NEAR_MISSES
+                && let Some(piece) =
+                    *failure.get_or_insert_with(|| failure_of(&self.steps, &self.full, line))
+            {
+                probe.near_misses.push(NearMiss {
+                    line_nth,
+                    line: line.chars().take(MAXIMUM_LINE_LENGTH).collect(),
+                    failure: piece,
+                });
+            }
+        }
+    }
+
+    /// Render the counts of the probes, and the near misses of the broadest
+    /// one.
+    pub fn to_text(&self) -> String {
+        let counts = self
+            .probes
+            .iter()
+            .map(|probe| {
+                format!(
+                    "  {description}: {count}\n",
+                    description = probe.description,
+                    count = human::count(probe.number_of_lines),
+                )
+            })
+            .collect::<String>();
+        let broadest = self
+            .probes
+            .iter()
+            .rev()
+            .max_by_key(|probe| probe.number_of_lines)
+            .filter(|probe| !probe.near_misses.is_empty());
+        let near_misses = broadest
+            .map(|probe| {
+                format!(
+                    "Lines {description}, but not matching:\n{lines}",
+                    description = probe.description,
+                    lines = probe
+                        .near_misses
+                        .iter()
+                        .map(|near_miss| format!(
+                            "  log line {line_nth}, failing at `{failure}`: {line}\n",
+                            line_nth = near_miss.line_nth,
+                            failure = near_miss.failure,
+                            line = near_miss.line,
+                        ))
+                        .collect::<String>(),
+                )
+            })
+            .unwrap_or_default();
+
+        format!("Lines passing the probes:\n{counts}{near_misses}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_probe() {
+        let mut explanation = Explanation::new();
+
+        for (line_nth, line) in [
+            // The target is printed differently.
+            r#"2024-06-01T09:13:19.035Z DEBUG MatrixSDK.http_client: Sending request | spans: send{request_id="REQ-1" method=GET uri="https://example.org/versions"}"#,
+            // The `uri` is missing.
+            r#"2024-06-01T09:13:19.035Z DEBUG matrix_sdk::http_client: Sending request | spans: send{request_id="REQ-2" method=GET}"#,
+            r#"2024-06-01T09:13:19.035Z DEBUG matrix_sdk::http_client: Sending request | spans: send{request_id="REQ-3" method=GET uri="https://example.org/versions"}"#,
+            "2024-06-01T09:13:19.035Z INFO app: Started",
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            explanation.probe(line, line_nth + 1);
+        }
+
+        assert_eq!(
+            explanation
+                .probes
+                .iter()
+                .map(|probe| probe.number_of_lines)
+                .collect::<Vec<_>>(),
+            [4, 2, 3]
+        );
+
+        let text = explanation.to_text();
+
+        assert!(text.contains("  with an RFC 3339 timestamp: 4\n"), "{text}");
+        assert!(
+            text.contains("  log line 1, failing at `target`: "),
+            "{text}"
+        );
+        assert!(text.contains("  log line 2, failing at `uri`: "), "{text}");
+    }
+}
@@ -0,0 +1,523 @@
+//! Minimal filter expressions evaluated per span, e.g.
+//! `status-family=2 && duration<300ms`.
+//!
+//! An expression is a list of comparisons joined by `&&`. A comparison is a
+//! field, an operator and a value; values are bare words, or quoted with `"`
+//! when they contain spaces or operators.
+
+use std::{cmp::Ordering, fmt};
+
+use chrono::TimeDelta;
+
+use crate::{Span, duration, size};
+
+/// A field of a span.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Field {
+    Status,
+    StatusFamily,
+    Duration,
+    Method,
+    Kind,
+    Class,
+    Endpoint,
+    Domain,
+    Path,
+    Errcode,
+    RequestSize,
+    ResponseSize,
+}
+
+impl Field {
+    const ALL: [Self; 12] = [
+        Self::Status,
+        Self::StatusFamily,
+        Self::Duration,
+        Self::Method,
+        Self::Kind,
+        Self::Class,
+        Self::Endpoint,
+        Self::Domain,
+        Self::Path,
+        Self::Errcode,
+        Self::RequestSize,
+        Self::ResponseSize,
+    ];
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Status => "status",
+            Self::StatusFamily => "status-family",
+            Self::Duration => "duration",
+            Self::Method => "method",
+            Self::Kind => "kind",
+            Self::Class => "class",
+            Self::Endpoint => "endpoint",
+            Self::Domain => "domain",
+            Self::Path => "path",
+            Self::Errcode => "errcode",
+            Self::RequestSize => "request-size",
+            Self::ResponseSize => "response-size",
+        }
+    }
+
+    fn is_ordered(&self) -> bool {
+        matches!(
+            self,
+            Self::Status | Self::Duration | Self::RequestSize | Self::ResponseSize
+        )
+    }
+
+    /// Parse the value compared to the field.
+    fn parse_value(&self, value: &str) -> Result<Value, String> {
+        match self {
+            Self::Status => value
+                .parse()
+                .map(Value::Integer)
+                .map_err(|_| format!("`{value}` isn't a status like `200`")),
+            Self::Duration => duration::parse(value)
+                .map(Value::Duration)
+                .ok_or_else(|| format!("`{value}` isn't a duration like `300ms`")),
+            Self::RequestSize | Self::ResponseSize => size::parse(value)
+                .map(Value::Size)
+                .ok_or_else(|| format!("`{value}` isn't a size like `1.2MB`")),
+            _ => Ok(Value::Text(value.to_owned())),
+        }
+    }
+
+    /// Get the value of the field for a span, if any.
+    fn value_of(&self, span: &Span) -> Option<Value> {
+        Some(match self {
+            Self::Status => Value::Integer(span.status?.into()),
+            Self::StatusFamily => Value::Text(span.status_family()),
+            Self::Duration => Value::Duration(span.duration),
+            Self::Method => Value::Text(span.method.clone()),
+            Self::Kind => Value::Text(span.kind().as_str().to_owned()),
+            Self::Class => Value::Text(span.traffic_class().as_str().to_owned()),
+            Self::Endpoint => Value::Text(span.endpoint()),
+            Self::Domain => Value::Text(span.domain()),
+            Self::Path => Value::Text(span.path()),
+            Self::Errcode => Value::Text(span.errcode.clone()?),
+            Self::RequestSize => Value::Size(span.request_size.as_ref()?.bytes()?),
+            Self::ResponseSize => Value::Size(span.response_size.as_ref()?.bytes()?),
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Operator {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Contains,
+}
+
+impl Operator {
+    fn is_ordering(&self) -> bool {
+        matches!(
+            self,
+            Self::Less | Self::LessOrEqual | Self::Greater | Self::GreaterOrEqual
+        )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    Integer(i64),
+    Duration(TimeDelta),
+    Size(u64),
+    Text(String),
+}
+
+impl Value {
+    fn compare(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (Self::Integer(left), Self::Integer(right)) => Some(left.cmp(right)),
+            (Self::Duration(left), Self::Duration(right)) => Some(left.cmp(right)),
+            (Self::Size(left), Self::Size(right)) => Some(left.cmp(right)),
+            (Self::Text(left), Self::Text(right)) => Some(left.cmp(right)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Comparison {
+    field: Field,
+    operator: Operator,
+    value: Value,
+}
+
+impl Comparison {
+    /// Whether a span satisfies the comparison. A span without a value for
+    /// the field, e.g. the status of a cancelled request, only satisfies `!=`.
+    fn matches(&self, span: &Span) -> bool {
+        let Some(value) = self.field.value_of(span) else {
+            return self.operator == Operator::NotEqual;
+        };
+
+        if let (Operator::Contains, Value::Text(value), Value::Text(needle)) =
+            (self.operator, &value, &self.value)
+        {
+            return value.contains(needle.as_str());
+        }
+
+        let Some(ordering) = value.compare(&self.value) else {
+            return false;
+        };
+
+        match self.operator {
+            Operator::Equal => ordering.is_eq(),
+            Operator::NotEqual => ordering.is_ne(),
+            Operator::Less => ordering.is_lt(),
+            Operator::LessOrEqual => ordering.is_le(),
+            Operator::Greater => ordering.is_gt(),
+            Operator::GreaterOrEqual => ordering.is_ge(),
+            Operator::Contains => false,
+        }
+    }
+}
+
+/// A parsed expression.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Expression {
+    source: String,
+    comparisons: Vec<Comparison>,
+}
+
+impl Expression {
+    /// Parse an expression.
+    pub fn parse(source: &str) -> Result<Self, Error> {
+        let tokens = tokenize(source)?;
+        let mut tokens = tokens.into_iter().peekable();
+        let mut comparisons = Vec::new();
+        let error = |message: String, (start, end): Range| Error {
+            source: source.to_owned(),
+            message,
+            start,
+            end,
+        };
+        let end_of_source = (source.len(), source.len() + 1);
+
+        loop {
+            let (field, field_span) = match tokens.next() {
+                Some((Token::Word(word), span)) => (word, span),
+                Some((token, span)) => {
+                    return Err(error(format!("Expected a field, found {token}"), span));
+                }
+                None => return Err(error("Expected a field".to_owned(), end_of_source)),
+            };
+            let field = Field::ALL
+                .into_iter()
+                .find(|candidate| candidate.as_str() == field)
+                .ok_or_else(|| {
+                    error(
+                        format!(
+                            "Unknown field `{field}`; valid fields are {}",
+                            Field::ALL
+                                .iter()
+                                .map(|field| format!("`{}`", field.as_str()))
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        ),
+                        field_span,
+                    )
+                })?;
+
+            let (operator, operator_span) = match tokens.next() {
+                Some((Token::Operator(operator), span)) => (operator, span),
+                Some((token, span)) => {
+                    return Err(error(
+                        format!(
+                            "Expected an operator after `{}`, found {token}",
+                            field.as_str()
+                        ),
+                        span,
+                    ));
+                }
+                None => {
+                    return Err(error(
+                        format!("Expected an operator after `{}`", field.as_str()),
+                        end_of_source,
+                    ));
+                }
+            };
+
+            if operator.is_ordering() && !field.is_ordered() {
+                return Err(error(
+                    format!(
+                        "`{}` can only be compared with `=`, `!=` or `~`",
+                        field.as_str()
+                    ),
+                    operator_span,
+                ));
+            }
+
+            if operator == Operator::Contains && field.is_ordered() {
+                return Err(error(
+                    format!("`~` only applies to text, not to `{}`", field.as_str()),
+                    operator_span,
+                ));
+            }
+
+            let value = match tokens.next() {
+                Some((Token::Word(value) | Token::Quoted(value), span)) => field
+                    .parse_value(&value)
+                    .map_err(|message| error(message, span))?,
+                Some((token, span)) => {
+                    return Err(error(format!("Expected a value, found {token}"), span));
+                }
+                None => return Err(error("Expected a value".to_owned(), end_of_source)),
+            };
+
+            comparisons.push(Comparison {
+                field,
+                operator,
+                value,
+            });
+
+            match tokens.next() {
+                None => break,
+                Some((Token::And, _)) => {}
+                Some((token, span)) => {
+                    return Err(error(format!("Expected `&&`, found {token}"), span));
+                }
+            }
+        }
+
+        Ok(Self {
+            source: source.to_owned(),
+            comparisons,
+        })
+    }
+
+    /// Whether a span satisfies all the comparisons.
+    pub fn matches(&self, span: &Span) -> bool {
+        self.comparisons
+            .iter()
+            .all(|comparison| comparison.matches(span))
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str(&self.source)
+    }
+}
+
+/// An error in an expression, with the byte range it's about.
+#[derive(Debug, PartialEq)]
+pub struct Error {
+    source: String,
+    message: String,
+    start: usize,
+    end: usize,
+}
+
+impl fmt::Display for Error {
+    /// Display the message, then the expression with the faulty part
+    /// underlined.
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let offset = self.source[..self.start.min(self.source.len())]
+            .chars()
+            .count();
+        let width = self
+            .source
+            .get(self.start..self.end)
+            .map_or(1, |part| part.chars().count().max(1));
+
+        write!(
+            formatter,
+            "{message}\n  {source}\n  {padding}{underline}",
+            message = self.message,
+            source = self.source,
+            padding = " ".repeat(offset),
+            underline = "^".repeat(width),
+        )
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Operator(Operator),
+    And,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Word(word) => write!(formatter, "`{word}`"),
+            Self::Quoted(value) => write!(formatter, "`\"{value}\"`"),
+            Self::Operator(_) => formatter.write_str("an operator"),
+            Self::And => formatter.write_str("`&&`"),
+        }
+    }
+}
+
+/// Byte range of a token in an expression.
+type Range = (usize, usize);
+
+/// Split an expression into tokens, with their byte ranges.
+fn tokenize(source: &str) -> Result<Vec<(Token, Range)>, Error> {
+    let is_special = |character: char| {
+        character.is_whitespace() || matches!(character, '=' | '!' | '<' | '>' | '~' | '&' | '"')
+    };
+    let mut tokens = Vec::new();
+    let mut characters = source.char_indices().peekable();
+
+    while let Some((start, character)) = characters.next() {
+        let next_is = |characters: &mut std::iter::Peekable<std::str::CharIndices<'_>>,
+                       expected: char| {
+            characters
+                .next_if(|(_, character)| *character == expected)
+                .is_some()
+        };
+
+        let token = match character {
+            character if character.is_whitespace() => continue,
+            '&' if next_is(&mut characters, '&') => Token::And,
+            '=' => {
+                next_is(&mut characters, '=');
+
+                Token::Operator(Operator::Equal)
+            }
+            '!' if next_is(&mut characters, '=') => Token::Operator(Operator::NotEqual),
+            '<' if next_is(&mut characters, '=') => Token::Operator(Operator::LessOrEqual),
+            '<' => Token::Operator(Operator::Less),
+            '>' if next_is(&mut characters, '=') => Token::Operator(Operator::GreaterOrEqual),
+            '>' => Token::Operator(Operator::Greater),
+            '~' => Token::Operator(Operator::Contains),
+            '"' => {
+                let mut value = String::new();
+                let mut is_closed = false;
+
+                while let Some((_, character)) = characters.next() {
+                    match character {
+                        '"' => {
+                            is_closed = true;
+
+                            break;
+                        }
+                        '\\' => {
+                            if let Some((_, escaped)) = characters.next() {
+                                value.push(escaped);
+                            }
+                        }
+                        character => value.push(character),
+                    }
+                }
+
+                if !is_closed {
+                    return Err(Error {
+                        source: source.to_owned(),
+                        message: "Unterminated quoted value".to_owned(),
+                        start,
+                        end: source.len(),
+                    });
+                }
+
+                Token::Quoted(value)
+            }
+            character if !is_special(character) => {
+                let mut end = start + character.len_utf8();
+
+                while let Some((index, character)) =
+                    characters.next_if(|(_, character)| !is_special(*character))
+                {
+                    end = index + character.len_utf8();
+                }
+
+                Token::Word(source[start..end].to_owned())
+            }
+            character => {
+                return Err(Error {
+                    source: source.to_owned(),
+                    message: format!("Unexpected `{character}`"),
+                    start,
+                    end: start + character.len_utf8(),
+                });
+            }
+        };
+
+        let end = characters.peek().map_or(source.len(), |(index, _)| *index);
+        tokens.push((token, (start, end)));
+    }
+
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SYNC: &str = "https://example.org/_matrix/client/v3/sync?timeout=30000";
+
+    #[test]
+    fn test_matches() {
+        let fast = Span::for_tests(SYNC, Some(200), TimeDelta::milliseconds(120));
+        let slow = Span::for_tests(SYNC, Some(200), TimeDelta::seconds(2));
+        let failed = Span::for_tests(SYNC, Some(502), TimeDelta::milliseconds(120));
+        let cancelled = Span::for_tests(SYNC, None, TimeDelta::zero());
+
+        let expression = Expression::parse("status-family=2 && duration<300ms").unwrap();
+        assert!(expression.matches(&fast));
+        assert!(!expression.matches(&slow));
+        assert!(!expression.matches(&failed));
+        assert!(!expression.matches(&cancelled));
+
+        let expression =
+            Expression::parse(r#"kind == sync && path ~ "/v3/" && status != 502"#).unwrap();
+        assert!(expression.matches(&fast));
+        assert!(!expression.matches(&failed));
+        assert!(expression.matches(&cancelled));
+
+        let expression = Expression::parse("status>=500").unwrap();
+        assert!(expression.matches(&failed));
+        assert!(!expression.matches(&cancelled));
+    }
+
+    #[test]
+    fn test_errors() {
+        for (source, expected) in [
+            (
+                "stauts=2",
+                "Unknown field `stauts`; valid fields are `status`, `status-family`, `duration`, `method`, `kind`, `class`, `endpoint`, `domain`, `path`, `errcode`, `request-size`, `response-size`\n  stauts=2\n  ^^^^^^",
+            ),
+            (
+                "duration<300",
+                "`300` isn't a duration like `300ms`\n  duration<300\n           ^^^",
+            ),
+            (
+                "method<GET",
+                "`method` can only be compared with `=`, `!=` or `~`\n  method<GET\n        ^",
+            ),
+            (
+                "status=200 &&",
+                "Expected a field\n  status=200 &&\n               ^",
+            ),
+            (
+                "status=200 duration<1s",
+                "Expected `&&`, found `duration`\n  status=200 duration<1s\n             ^^^^^^^^",
+            ),
+            (
+                r#"path~"/sync"#,
+                "Unterminated quoted value\n  path~\"/sync\n       ^^^^^^",
+            ),
+            (
+                "status=200 & kind=sync",
+                "Unexpected `&`\n  status=200 & kind=sync\n             ^",
+            ),
+        ] {
+            assert_eq!(
+                Expression::parse(source).unwrap_err().to_string(),
+                expected,
+                "{source}"
+            );
+        }
+    }
+}
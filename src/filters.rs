@@ -0,0 +1,436 @@
+//! Filter the spans once they are assembled, so that a request whose response
+//! falls outside of a filter is still complete.
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use regex::Regex;
+
+use crate::{Span, Spans, duration, meta::Filter};
+
+/// A bound of the time window of `--from` and `--to`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bound {
+    /// A datetime, e.g. `2024-06-01T09:13:00Z`.
+    At(DateTime<FixedOffset>),
+    /// An offset from the start of the log, e.g. `+5m`.
+    AfterStart(TimeDelta),
+    /// An offset from the end of the log, e.g. `-30m`.
+    BeforeEnd(TimeDelta),
+}
+
+impl Bound {
+    /// Whether the bound is a datetime, which doesn't depend on the time range
+    /// of the log.
+    pub fn is_absolute(&self) -> bool {
+        matches!(self, Self::At(_))
+    }
+
+    /// Parse an RFC 3339 datetime, or an offset from the start or the end of
+    /// the log.
+    pub fn parse(value: &str) -> Option<Self> {
+        if let Some(offset) = value.strip_prefix('+') {
+            return duration::parse(offset).map(Self::AfterStart);
+        }
+
+        if let Some(offset) = value.strip_prefix('-') {
+            return duration::parse(offset).map(Self::BeforeEnd);
+        }
+
+        DateTime::parse_from_rfc3339(value).ok().map(Self::At)
+    }
+
+    /// Get the datetime of the bound, in a log spanning `time_range`.
+    fn resolve(
+        &self,
+        time_range: Option<(DateTime<FixedOffset>, DateTime<FixedOffset>)>,
+    ) -> Option<DateTime<FixedOffset>> {
+        match self {
+            Self::At(date_time) => Some(*date_time),
+            Self::AfterStart(offset) => time_range.map(|(start_at, _)| start_at + *offset),
+            Self::BeforeEnd(offset) => time_range.map(|(_, end_at)| end_at - *offset),
+        }
+    }
+
+    /// Describe the bound, e.g. `30m before the end of the log`.
+    fn describe(&self) -> String {
+        match self {
+            Self::At(date_time) => date_time.to_rfc3339(),
+            Self::AfterStart(offset) => {
+                format!("{} after the start of the log", duration::to_text(*offset))
+            }
+            Self::BeforeEnd(offset) => {
+                format!("{} before the end of the log", duration::to_text(*offset))
+            }
+        }
+    }
+
+    /// Render the bound as the value of its flag.
+    fn to_flag_value(self) -> String {
+        match self {
+            Self::At(date_time) => date_time.to_rfc3339(),
+            Self::AfterStart(offset) => format!("+{}", duration::to_text(offset)),
+            Self::BeforeEnd(offset) => format!("-{}", duration::to_text(offset)),
+        }
+    }
+}
+
+/// A status of `--status`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    /// A family, e.g. `4` for `4xx`.
+    Family(u16),
+    /// A status, e.g. `429`.
+    Exact(u16),
+}
+
+impl Status {
+    /// Parse a family like `4xx`, or a status like `429`.
+    pub fn parse(value: &str) -> Option<Self> {
+        if let Some(family) = value
+            .strip_suffix("xx")
+            .or_else(|| value.strip_suffix("XX"))
+        {
+            return family
+                .parse()
+                .ok()
+                .filter(|family| (1..=5).contains(family))
+                .map(Self::Family);
+        }
+
+        value
+            .parse()
+            .ok()
+            .filter(|status| (100..600).contains(status))
+            .map(Self::Exact)
+    }
+
+    fn matches(&self, status: Option<u16>) -> bool {
+        match (self, status) {
+            (Self::Family(family), Some(status)) => status / 100 == *family,
+            (Self::Exact(expected), Some(status)) => status == *expected,
+            (_, None) => false,
+        }
+    }
+
+    /// Render the status as the value of its flag.
+    fn to_flag_value(self) -> String {
+        match self {
+            Self::Family(family) => format!("{family}xx"),
+            Self::Exact(status) => status.to_string(),
+        }
+    }
+}
+
+/// Selection of the spans, by `--from`, `--to`, `--conn-id`, `--room`,
+/// `--method`, `--status`, `--include-uri` and `--exclude-uri`. A span is selected if it
+/// matches all the given criteria.
+#[derive(Clone, Debug, Default)]
+pub struct Selection {
+    /// The spans starting at or after this bound.
+    pub from: Option<Bound>,
+    /// The spans starting at or before this bound.
+    pub to: Option<Bound>,
+    /// The spans of any of these connections.
+    pub connection_ids: Vec<String>,
+    /// The spans targeting any of these rooms, see [`Span::room_id`].
+    pub room_ids: Vec<String>,
+    /// The spans with any of these methods, in upper case.
+    pub methods: Vec<String>,
+    /// The spans with any of these statuses.
+    pub statuses: Vec<Status>,
+    /// The spans whose URI matches this regex.
+    pub uri_matches: Option<Regex>,
+    /// The spans whose URI doesn't match this regex.
+    pub uri_excludes: Option<Regex>,
+}
+
+impl Selection {
+    fn matches(
+        &self,
+        from: Option<DateTime<FixedOffset>>,
+        to: Option<DateTime<FixedOffset>>,
+        connection_id: &str,
+        span: &Span,
+    ) -> bool {
+        from.is_none_or(|from| span.start_at >= from)
+            && to.is_none_or(|to| span.start_at <= to)
+            && (self.connection_ids.is_empty()
+                || self.connection_ids.iter().any(|id| id == connection_id))
+            && (self.room_ids.is_empty()
+                || span
+                    .room_id()
+                    .is_some_and(|room_id| self.room_ids.contains(&room_id)))
+            && (self.methods.is_empty() || self.methods.contains(&span.method))
+            && (self.statuses.is_empty()
+                || self
+                    .statuses
+                    .iter()
+                    .any(|status| status.matches(span.status)))
+            && self
+                .uri_matches
+                .as_ref()
+                .is_none_or(|uri_matches| uri_matches.is_match(&span.uri))
+            && self
+                .uri_excludes
+                .as_ref()
+                .is_none_or(|uri_excludes| !uri_excludes.is_match(&span.uri))
+    }
+
+    /// Keep only the selected spans. The spans are filtered by their start, so
+    /// that a selected span is complete even if its response is outside of the
+    /// time window. The offsets of `from` and `to` are relative to the time
+    /// range of `spans`.
+    ///
+    /// Returns the number of removed spans.
+    pub fn retain(&self, spans: &mut Spans) -> usize {
+        let time_range = time_range(spans);
+        let from = self.from.and_then(|from| from.resolve(time_range));
+        let to = self.to.and_then(|to| to.resolve(time_range));
+        let mut number_of_removed_spans = 0;
+
+        for (connection_id, spans_for_connection_id) in spans.iter_mut() {
+            let before = spans_for_connection_id.len();
+            spans_for_connection_id.retain(|_, span| self.matches(from, to, connection_id, span));
+            number_of_removed_spans += before - spans_for_connection_id.len();
+        }
+
+        spans.retain(|_, spans_for_connection_id| !spans_for_connection_id.is_empty());
+
+        number_of_removed_spans
+    }
+
+    /// Describe the given criteria, for the metadata of the report.
+    pub fn to_filters(&self) -> Vec<Filter> {
+        let mut filters = Vec::new();
+
+        if let Some(from) = self.from {
+            filters.push(Filter {
+                flag: format!("--from {}", from.to_flag_value()),
+                description: format!("only the spans starting at or after {}", from.describe()),
+            });
+        }
+
+        if let Some(to) = self.to {
+            filters.push(Filter {
+                flag: format!("--to {}", to.to_flag_value()),
+                description: format!("only the spans starting at or before {}", to.describe()),
+            });
+        }
+
+        for (flag, values, description) in [
+            ("--conn-id", &self.connection_ids, "connections"),
+            ("--room", &self.room_ids, "rooms"),
+            ("--method", &self.methods, "methods"),
+        ] {
+            if !values.is_empty() {
+                filters.push(Filter {
+                    flag: values
+                        .iter()
+                        .map(|value| format!("{flag} {value}"))
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                    description: format!(
+                        "only the spans of the {description} {}",
+                        values.join(", ")
+                    ),
+                });
+            }
+        }
+
+        if !self.statuses.is_empty() {
+            let statuses = self
+                .statuses
+                .iter()
+                .map(|status| status.to_flag_value())
+                .collect::<Vec<_>>();
+
+            filters.push(Filter {
+                flag: statuses
+                    .iter()
+                    .map(|status| format!("--status {status}"))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                description: format!("only the spans with the statuses {}", statuses.join(", ")),
+            });
+        }
+
+        if let Some(uri_matches) = &self.uri_matches {
+            filters.push(Filter {
+                flag: format!("--include-uri {uri_matches}"),
+                description: format!("only the spans whose URI matches `{uri_matches}`"),
+            });
+        }
+
+        if let Some(uri_excludes) = &self.uri_excludes {
+            filters.push(Filter {
+                flag: format!("--exclude-uri {uri_excludes}"),
+                description: format!("only the spans whose URI doesn't match `{uri_excludes}`"),
+            });
+        }
+
+        filters
+    }
+}
+
+/// Keep only the spans starting within the final `last` duration of the log,
+/// which ends at `end_at`.
+///
+/// Returns the start of the trim window, and the number of removed spans.
+pub fn trim_to_last(
+    spans: &mut Spans,
+    last: TimeDelta,
+    end_at: DateTime<FixedOffset>,
+) -> (DateTime<FixedOffset>, usize) {
+    let window_start_at = end_at - last;
+    let mut number_of_removed_spans = 0;
+
+    for spans_for_connection_id in spans.values_mut() {
+        let before = spans_for_connection_id.len();
+        spans_for_connection_id.retain(|_, span| span.start_at >= window_start_at);
+        number_of_removed_spans += before - spans_for_connection_id.len();
+    }
+
+    spans.retain(|_, spans_for_connection_id| !spans_for_connection_id.is_empty());
+
+    (window_start_at, number_of_removed_spans)
+}
+
+/// Compute the time range covered by the spans, from the start of the first
+/// one to the end of the last one.
+pub fn time_range(spans: &Spans) -> Option<(DateTime<FixedOffset>, DateTime<FixedOffset>)> {
+    time_range_of(spans.values().flat_map(BTreeMap::values))
+}
+
+/// Compute the time range covered by some spans, e.g. the ones of a single
+/// connection.
+pub fn time_range_of<'a>(
+    spans: impl Iterator<Item = &'a Span> + Clone,
+) -> Option<(DateTime<FixedOffset>, DateTime<FixedOffset>)> {
+    let start_at = spans.clone().map(|span| span.start_at).min()?;
+    let end_at = spans.map(|span| span.start_at + span.duration).max()?;
+
+    Some((start_at, end_at))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{RequestId, SpansBuilder};
+
+    const SYNC: &str = "https://example.org/_matrix/client/v3/sync";
+
+    /// Add a span lasting 10s, whose request ID is its start, in seconds.
+    fn span<'a>(
+        spans: &'a mut SpansBuilder,
+        connection_id: &str,
+        uri: &str,
+        start_at: RequestId,
+    ) -> &'a mut Span {
+        spans.span(
+            connection_id,
+            start_at,
+            uri,
+            Some(200),
+            i64::from(start_at) * 1_000,
+            10_000,
+        )
+    }
+
+    #[test]
+    fn test_selection() {
+        let mut spans = SpansBuilder::default();
+        span(&mut spans, "room-list", SYNC, 0);
+        span(&mut spans, "room-list", SYNC, 60);
+        span(
+            &mut spans,
+            "room-list",
+            "https://example.org/_matrix/client/v3/keys",
+            120,
+        )
+        .method = "GET".to_owned();
+        span(&mut spans, "encryption", SYNC, 60);
+        let mut spans = spans.build();
+        let start_at = spans["room-list"][&0].start_at;
+        let selection = Selection {
+            // The span starting within the window is kept, even if it ends
+            // after it.
+            from: Some(Bound::At(start_at + TimeDelta::seconds(30))),
+            to: Some(Bound::At(start_at + TimeDelta::seconds(65))),
+            connection_ids: vec!["room-list".to_owned()],
+            room_ids: Vec::new(),
+            methods: vec!["POST".to_owned()],
+            statuses: vec![Status::parse("2xx").unwrap()],
+            uri_matches: Some(Regex::new("/sync$").unwrap()),
+            uri_excludes: Some(Regex::new("/keys").unwrap()),
+        };
+
+        assert_eq!(selection.retain(&mut spans), 3);
+        assert_eq!(
+            spans
+                .iter()
+                .flat_map(|(connection_id, spans)| spans
+                    .keys()
+                    .map(move |id| (connection_id.as_str(), *id)))
+                .collect::<Vec<_>>(),
+            [("room-list", 60)]
+        );
+        assert_eq!(selection.to_filters().len(), 7);
+
+        // The log ends at 130s: `-75s` is 55s.
+        let selection = Selection {
+            from: Bound::parse("+30s"),
+            to: Bound::parse("-75s"),
+            ..Selection::default()
+        };
+        let mut spans = SpansBuilder::default();
+        span(&mut spans, "room-list", SYNC, 0);
+        span(&mut spans, "room-list", SYNC, 50);
+        span(&mut spans, "room-list", SYNC, 60);
+        span(
+            &mut spans,
+            "room-list",
+            "https://example.org/_matrix/client/v3/keys",
+            120,
+        )
+        .method = "GET".to_owned();
+        let mut spans = spans.build();
+
+        assert_eq!(selection.retain(&mut spans), 3);
+        assert_eq!(spans["room-list"].keys().copied().collect::<Vec<_>>(), [50]);
+        assert_eq!(selection.to_filters()[1].flag, "--to -1m15s");
+
+        // The room IDs are percent-decoded.
+        let selection = Selection {
+            room_ids: vec!["!abc:example.org".to_owned()],
+            ..Selection::default()
+        };
+        let mut spans = SpansBuilder::default();
+        span(
+            &mut spans,
+            "room-list",
+            "https://example.org/_matrix/client/v3/rooms/%21abc%3Aexample.org/messages",
+            0,
+        )
+        .method = "GET".to_owned();
+        span(
+            &mut spans,
+            "room-list",
+            "https://example.org/_matrix/client/v3/rooms/!def:example.org/messages",
+            10,
+        )
+        .method = "GET".to_owned();
+        span(&mut spans, "room-list", SYNC, 20);
+        let mut spans = spans.build();
+
+        assert_eq!(selection.retain(&mut spans), 2);
+        assert_eq!(spans["room-list"].keys().copied().collect::<Vec<_>>(), [0]);
+        assert_eq!(selection.to_filters()[0].flag, "--room !abc:example.org");
+        assert_eq!(Bound::parse("-soon"), None);
+
+        assert_eq!(Status::parse("429"), Some(Status::Exact(429)));
+        assert_eq!(Status::parse("5XX"), Some(Status::Family(5)));
+        assert_eq!(Status::parse("9xx"), None);
+        assert!(!Status::Family(4).matches(None));
+    }
+}
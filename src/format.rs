@@ -0,0 +1,167 @@
+//! Formats of the outputs, either explicit with `--format`, or inferred from
+//! the extension of each output path.
+
+use std::{collections::HashSet, path::Path};
+
+/// Format of an output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Html,
+    Csv,
+    Xlsx,
+    Parquet,
+    Grafana,
+    Influx,
+    Json,
+    Har,
+    /// OpenTelemetry traces, see [`crate::otlp`].
+    Otlp,
+    /// A waterfall of text, see [`crate::term`].
+    Term,
+}
+
+impl Format {
+    /// Parse the value of `--format`.
+    pub fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "html" => Self::Html,
+            "csv" => Self::Csv,
+            "xlsx" => Self::Xlsx,
+            "parquet" => Self::Parquet,
+            "grafana" => Self::Grafana,
+            "influx" => Self::Influx,
+            "json" => Self::Json,
+            "har" => Self::Har,
+            "otlp" => Self::Otlp,
+            "term" => Self::Term,
+            _ => return None,
+        })
+    }
+
+    /// Infer the format from the extension of `path`. The Grafana, the OTLP
+    /// and the terminal formats have no dedicated extension, and must be
+    /// explicit.
+    pub fn of_path(path: &str) -> Option<Self> {
+        let extension = Path::new(path).extension()?.to_str()?.to_ascii_lowercase();
+
+        Some(match extension.as_str() {
+            "html" | "htm" => Self::Html,
+            "csv" => Self::Csv,
+            "xlsx" => Self::Xlsx,
+            "parquet" => Self::Parquet,
+            "influx" | "lp" => Self::Influx,
+            "json" => Self::Json,
+            "har" => Self::Har,
+            _ => return None,
+        })
+    }
+}
+
+/// A file to write the report to.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Output {
+    pub format: Format,
+    pub path: String,
+}
+
+/// Pick the format of every output path.
+///
+/// An explicit `format` only applies to a single output. Without it, the
+/// format is inferred from the extension; a single output with an unknown
+/// extension is HTML.
+pub fn outputs(format: Option<Format>, paths: Vec<String>) -> Result<Vec<Output>, String> {
+    let mut seen = HashSet::new();
+
+    if let Some(path) = paths.iter().find(|path| !seen.insert(path.as_str())) {
+        return Err(format!("`{path}` is given several times as an output"));
+    }
+
+    match (format, paths.as_slice()) {
+        (_, []) => Err("<output_path> is missing".to_owned()),
+
+        (Some(format), [path]) => Ok(vec![Output {
+            format,
+            path: path.clone(),
+        }]),
+
+        (Some(_), _) => Err(
+            "`--format` cannot be combined with several outputs; the format of each output is inferred from its extension"
+                .to_owned(),
+        ),
+
+        (None, [path]) => Ok(vec![Output {
+            format: Format::of_path(path).unwrap_or(Format::Html),
+            path: path.clone(),
+        }]),
+
+        (None, _) => paths
+            .into_iter()
+            .map(|path| match Format::of_path(&path) {
+                Some(format) => Ok(Output { format, path }),
+                None => Err(format!(
+                    "Cannot infer the format of `{path}`; use one of the `.html`, `.csv`, `.xlsx`, `.parquet`, `.influx`, `.json` or `.har` extensions"
+                )),
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(paths: &[&str]) -> Vec<String> {
+        paths.iter().map(|path| path.to_string()).collect()
+    }
+
+    #[test]
+    fn test_infer_formats() {
+        assert_eq!(
+            outputs(None, paths(&["report.html", "spans.json", "stats.CSV"])),
+            Ok(vec![
+                Output {
+                    format: Format::Html,
+                    path: "report.html".to_owned()
+                },
+                Output {
+                    format: Format::Json,
+                    path: "spans.json".to_owned()
+                },
+                Output {
+                    format: Format::Csv,
+                    path: "stats.CSV".to_owned()
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_single_output_defaults_to_html() {
+        assert_eq!(
+            outputs(None, paths(&["report.txt"])),
+            Ok(vec![Output {
+                format: Format::Html,
+                path: "report.txt".to_owned()
+            }])
+        );
+    }
+
+    #[test]
+    fn test_explicit_format_wins_for_a_single_output() {
+        assert_eq!(
+            outputs(Some(Format::Grafana), paths(&["series.json"])),
+            Ok(vec![Output {
+                format: Format::Grafana,
+                path: "series.json".to_owned()
+            }])
+        );
+    }
+
+    #[test]
+    fn test_conflicts() {
+        assert!(outputs(None, paths(&[])).is_err());
+        assert!(outputs(Some(Format::Csv), paths(&["a.csv", "b.html"])).is_err());
+        assert!(outputs(None, paths(&["a.html", "b.txt"])).is_err());
+        assert!(outputs(None, paths(&["a.html", "a.html"])).is_err());
+    }
+}
@@ -0,0 +1,124 @@
+//! Capture the chain of the tracing spans a request is sent from, e.g.
+//! `root > room_send_queue{room_id="!abc:example.org"} > send{…}`, to tell
+//! which subsystem of the SDK has initiated it: the read receipts, the send
+//! queue, the sync loop, and so on.
+
+use std::fmt;
+
+/// A tracing span of the chain, e.g. `sync_once{conn_id="room-list"}`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub name: String,
+    /// The fields between the braces, if any, as logged.
+    pub fields: Option<String>,
+}
+
+impl Frame {
+    /// Parse a frame as displayed, e.g. `sync_once{conn_id="room-list"}`.
+    pub fn parse(frame: &str) -> Self {
+        match frame
+            .strip_suffix('}')
+            .and_then(|frame| frame.split_once('{'))
+        {
+            Some((name, fields)) => Self {
+                name: name.to_owned(),
+                fields: Some(fields.to_owned()),
+            },
+            None => Self {
+                name: frame.to_owned(),
+                fields: None,
+            },
+        }
+    }
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.fields {
+            Some(fields) => write!(formatter, "{}{{{fields}}}", self.name),
+            None => formatter.write_str(&self.name),
+        }
+    }
+}
+
+/// Capture the frames of the `spans:` chain of a line, before the `send` span
+/// of the request. Returns nothing if the line has no such chain.
+pub fn capture(line: &str) -> Vec<Frame> {
+    let Some((_, mut chain)) = line.split_once("spans: ") else {
+        return Vec::new();
+    };
+    let mut frames = Vec::new();
+
+    loop {
+        let name_length = chain
+            .find(|character: char| !(character.is_alphanumeric() || "_:".contains(character)))
+            .unwrap_or(chain.len());
+        let (name, rest) = chain.split_at(name_length);
+
+        if name.is_empty() {
+            return Vec::new();
+        }
+
+        // The fields end at the first closing brace outside of a quoted
+        // value.
+        let (fields, rest) = match rest.strip_prefix('{') {
+            Some(rest) => {
+                let mut is_quoted = false;
+                let mut is_escaped = false;
+                let Some(end) = rest.find(|character| {
+                    match character {
+                        _ if is_escaped => is_escaped = false,
+                        '\\' if is_quoted => is_escaped = true,
+                        '"' => is_quoted = !is_quoted,
+                        '}' if !is_quoted => return true,
+                        _ => {}
+                    }
+
+                    false
+                }) else {
+                    return Vec::new();
+                };
+
+                (Some(&rest[..end]), &rest[end + 1..])
+            }
+            None => (None, rest),
+        };
+
+        if name == "send" {
+            return frames;
+        }
+
+        frames.push(Frame {
+            name: name.to_owned(),
+            fields: fields.map(ToOwned::to_owned),
+        });
+
+        let Some(next) = rest.strip_prefix(" > ") else {
+            return Vec::new();
+        };
+        chain = next;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capture() {
+        let frames = capture(
+            r#"2024-06-01T10:00:00Z DEBUG matrix_sdk::http_client: Sending request | spans: root > room_send_queue{room_id="!abc:example.org" note="a } b"} > matrix_sdk::send_queue::send > send{request_id="REQ-2" method=PUT uri="…"}"#,
+        );
+
+        assert_eq!(
+            frames.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            [
+                "root",
+                r#"room_send_queue{room_id="!abc:example.org" note="a } b"}"#,
+                "matrix_sdk::send_queue::send",
+            ]
+        );
+        assert_eq!(Frame::parse(&frames[1].to_string()), frames[1]);
+        assert!(capture("2024-06-01T10:00:00Z DEBUG no chain").is_empty());
+    }
+}
@@ -0,0 +1,149 @@
+//! Detect idle periods of the sync loop of a connection, i.e. when no sync is
+//! in flight.
+
+use chrono::{DateTime, FixedOffset, TimeDelta};
+
+use crate::{ConnectionId, RequestId, Spans, columns::Column, html, human};
+
+/// Minimum duration of an idle period to be considered as a gap.
+pub const DEFAULT_THRESHOLD: TimeDelta = TimeDelta::seconds(5);
+
+/// Number of gaps listed in the summary of a run.
+const NUMBER_OF_LONGEST_GAPS: usize = 10;
+
+/// An idle period on a connection.
+pub struct Gap {
+    pub connection_id: ConnectionId,
+    pub start_at: DateTime<FixedOffset>,
+    pub end_at: DateTime<FixedOffset>,
+    /// The sync ending the gap.
+    pub request_id: RequestId,
+}
+
+impl Gap {
+    pub fn duration(&self) -> TimeDelta {
+        self.end_at - self.start_at
+    }
+
+    /// Render the gap as a row of the table, before the row of the sync
+    /// ending it. `origin` is the start of the timeline, in milliseconds.
+    pub fn to_html(&self, columns: &[Column], origin: i64) -> String {
+        let duration = human::milliseconds(self.duration().num_milliseconds());
+        let cells = columns
+            .iter()
+            .map(|column| match column {
+                Column::Connection => format!(
+                    "      <td class=\"connection\"><code>{}</code></td>\n",
+                    html::escape(&self.connection_id)
+                ),
+                Column::Path => "      <td class=\"path\"><em>No sync in flight</em></td>\n".to_owned(),
+                Column::Duration => format!(
+                    "      <td class=\"duration\">
+        <div class=\"span\" style=\"--start-at: {start_at}; --duration: {milliseconds}\"><span>idle for {duration}</span></div>
+      </td>\n",
+                    start_at = self.start_at.timestamp_millis().saturating_sub(origin),
+                    milliseconds = self.duration().num_milliseconds(),
+                ),
+                column => format!("      <td class=\"{}\"></td>\n", column.as_str()),
+            })
+            .collect::<String>();
+
+        format!("    <tr class=\"gap\">\n{cells}    </tr>\n")
+    }
+}
+
+/// Find the gaps longer than `threshold` between the syncs of each
+/// connection.
+///
+/// The syncs are ordered by their start, not by their request ID: the request
+/// IDs of overlapping syncs aren't in the order of time. Idle periods before
+/// the first sync or after the last sync of a connection are not gaps.
+pub fn detect(spans: &Spans, threshold: TimeDelta) -> Vec<Gap> {
+    let mut gaps = Vec::new();
+
+    for (connection_id, spans) in spans {
+        let mut syncs = spans
+            .iter()
+            .filter(|(_, span)| span.is_sync())
+            .collect::<Vec<_>>();
+        syncs.sort_by_key(|(request_id, span)| (span.start_at, **request_id));
+
+        let mut syncs = syncs.into_iter();
+        let Some((_, first)) = syncs.next() else {
+            continue;
+        };
+        let mut idle_since = first.start_at + first.duration;
+
+        for (request_id, span) in syncs {
+            if span.start_at - idle_since > threshold {
+                gaps.push(Gap {
+                    connection_id: connection_id.clone(),
+                    start_at: idle_since,
+                    end_at: span.start_at,
+                    request_id: *request_id,
+                });
+            }
+
+            idle_since = idle_since.max(span.start_at + span.duration);
+        }
+    }
+
+    gaps
+}
+
+/// Render the longest gaps, for the summary of a run, or an empty string if
+/// there is none.
+pub fn longest_to_text(gaps: &[Gap], timezone: FixedOffset) -> String {
+    if gaps.is_empty() {
+        return String::new();
+    }
+
+    let mut longest = gaps.iter().collect::<Vec<_>>();
+    longest.sort_by_key(|gap| (-gap.duration(), gap.start_at));
+
+    format!(
+        "Longest gaps between syncs:\n{}",
+        longest
+            .into_iter()
+            .take(NUMBER_OF_LONGEST_GAPS)
+            .map(|gap| format!(
+                "  {connection_id}: {duration} from {start_at}\n",
+                connection_id = gap.connection_id,
+                duration = human::milliseconds(gap.duration().num_milliseconds()),
+                start_at = gap.start_at.with_timezone(&timezone).to_rfc3339(),
+            ))
+            .collect::<String>()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SpansBuilder;
+
+    #[test]
+    fn test_detect() {
+        let uri = "https://example.org/_matrix/client/unstable/org.matrix.simplified_msc3575/sync";
+        // `REQ-2` overlaps `REQ-1`, and the gap is after `REQ-1`, until
+        // `REQ-3`, despite the order of the request IDs.
+        let mut spans = SpansBuilder::default();
+        spans.span("room-list", 2, uri, Some(200), 1_000, 2_000);
+        spans.span("room-list", 1, uri, Some(200), 0, 10_000);
+        spans.span("room-list", 3, uri, Some(200), 20_000, 1_000);
+        spans.span("room-list", 4, uri, Some(200), 22_000, 1_000);
+        let spans = spans.build();
+
+        let gaps = detect(&spans, DEFAULT_THRESHOLD);
+
+        assert_eq!(
+            gaps.iter()
+                .map(|gap| (gap.request_id, gap.duration().num_seconds()))
+                .collect::<Vec<_>>(),
+            [(3, 10)]
+        );
+        assert_eq!(
+            longest_to_text(&gaps, *gaps[0].start_at.offset()),
+            "Longest gaps between syncs:\n  room-list: 10.0s from 2024-06-01T09:13:29.035+00:00\n"
+        );
+    }
+}
@@ -0,0 +1,86 @@
+//! Export time series for Grafana's JSON and Infinity datasources, in the
+//! `[{"target": "…", "datapoints": [[value, timestamp_ms], …]}, …]` shape.
+//!
+//! Spans are bucketed per minute, by their start time. The series are named
+//! `<metric>.<kind>`, where `<kind>` is an endpoint kind (`sync`, `media`,
+//! `e2ee`, `send`, `other`) or `all`, and `<metric>` is one of:
+//!
+//! - `requests`: number of requests,
+//! - `error_rate`: ratio of failed requests, between 0 and 1,
+//! - `p95_duration_ms`: 95th percentile of the durations, in milliseconds,
+//! - `bytes_down`: number of received bytes,
+//! - `bytes_up`: number of sent bytes.
+//!
+//! Empty buckets are zeros, so that graphs don't interpolate across outages.
+
+use chrono::{DateTime, FixedOffset};
+use serde::Serialize;
+
+use crate::{
+    Spans,
+    buckets::{self, Aggregate},
+    duration, endpoint,
+};
+
+/// Name of a metric, and how to compute it from an aggregate.
+type Metric = (&'static str, fn(&Aggregate) -> f64);
+
+const METRICS: [Metric; 5] = [
+    ("requests", |aggregate| aggregate.requests as f64),
+    ("error_rate", |aggregate| {
+        if aggregate.requests == 0 {
+            0.
+        } else {
+            aggregate.errors as f64 / aggregate.requests as f64
+        }
+    }),
+    ("p95_duration_ms", |aggregate| {
+        aggregate
+            .percentile_duration(95.)
+            .map(duration::to_milliseconds)
+            .unwrap_or_default()
+    }),
+    ("bytes_down", |aggregate| aggregate.bytes_down as f64),
+    ("bytes_up", |aggregate| aggregate.bytes_up as f64),
+];
+
+#[derive(Serialize)]
+struct Series {
+    target: String,
+    datapoints: Vec<(f64, i64)>,
+}
+
+/// Build the time series of the spans, from the minute including `start_at`
+/// to the minute including `end_at`.
+pub fn to_json(spans: &Spans, range: (DateTime<FixedOffset>, DateTime<FixedOffset>)) -> String {
+    let buckets = buckets::per_minute_and_kind(spans, range);
+
+    let kinds = endpoint::Kind::ALL
+        .iter()
+        .map(endpoint::Kind::as_str)
+        .chain(["all"]);
+
+    let series = METRICS
+        .iter()
+        .flat_map(|(metric, value)| {
+            kinds
+                .clone()
+                .enumerate()
+                .map(|(kind_index, kind)| Series {
+                    target: format!("{metric}.{kind}"),
+                    datapoints: buckets
+                        .iter()
+                        .map(|bucket| {
+                            (
+                                value(&bucket.per_kind[kind_index]),
+                                bucket.start_at.timestamp_millis(),
+                            )
+                        })
+                        .collect(),
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    serde_json::to_string(&series).expect("Failed to serialize the series")
+}
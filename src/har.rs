@@ -0,0 +1,287 @@
+//! Export the spans as an HTTP Archive (HAR) 1.2, to open them in the network
+//! panel of the browser devtools, or in any HAR viewer.
+//!
+//! Each connection is a page, and each span an entry of its page. The logs
+//! don't tell everything a HAR can: the headers are the ones telling about an
+//! intermediary, the sizes are the bodies' ones, `-1` when unknown, the bodies
+//! are the ones of `--capture-bodies`, if any, and the whole duration is spent
+//! waiting for the response.
+
+use std::borrow::Cow;
+
+use ada_url::UrlSearchParams;
+use chrono::SecondsFormat;
+use serde::Serialize;
+
+use crate::{ConnectionId, RequestId, Span, Spans, duration, size::Size};
+
+#[derive(Serialize)]
+struct Har<'a> {
+    log: Log<'a>,
+}
+
+#[derive(Serialize)]
+struct Log<'a> {
+    version: &'static str,
+    creator: Creator,
+    pages: Vec<Page<'a>>,
+    entries: Vec<Entry<'a>>,
+}
+
+#[derive(Serialize)]
+struct Creator {
+    name: &'static str,
+    version: &'static str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Page<'a> {
+    started_date_time: String,
+    id: &'a str,
+    title: &'a str,
+    page_timings: PageTimings,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PageTimings {
+    on_content_load: i64,
+    on_load: i64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Entry<'a> {
+    pageref: &'a str,
+    started_date_time: String,
+    time: f64,
+    request: Request<'a>,
+    response: Response<'a>,
+    cache: Cache,
+    timings: Timings,
+    #[serde(rename = "_requestId")]
+    request_id: RequestId,
+    #[serde(rename = "_errcode", skip_serializing_if = "Option::is_none")]
+    errcode: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Request<'a> {
+    method: &'a str,
+    url: &'a str,
+    http_version: &'static str,
+    cookies: Vec<Pair<'a>>,
+    headers: Vec<Pair<'a>>,
+    query_string: Vec<Pair<'a>>,
+    headers_size: i64,
+    body_size: i64,
+    /// The body captured with `--capture-bodies`, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    post_data: Option<PostData<'a>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PostData<'a> {
+    mime_type: &'static str,
+    text: &'a str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Response<'a> {
+    /// `0` if the request has been cancelled, or has no response.
+    status: u16,
+    status_text: &'static str,
+    http_version: &'static str,
+    cookies: Vec<Pair<'a>>,
+    headers: Vec<Pair<'a>>,
+    content: Content<'a>,
+    #[serde(rename = "redirectURL")]
+    redirect_url: &'static str,
+    headers_size: i64,
+    body_size: i64,
+}
+
+#[derive(Serialize)]
+struct Pair<'a> {
+    name: String,
+    value: Cow<'a, str>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Content<'a> {
+    size: i64,
+    mime_type: &'static str,
+    /// The body captured with `--capture-bodies`, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct Cache {}
+
+#[derive(Serialize)]
+struct Timings {
+    send: f64,
+    wait: f64,
+    receive: f64,
+}
+
+/// Get the number of bytes of a size, or `-1` if unknown.
+fn body_size(size: Option<&Size>) -> i64 {
+    size.and_then(Size::bytes)
+        .map(|bytes| bytes as i64)
+        .unwrap_or(-1)
+}
+
+fn entry<'a>(connection_id: &'a ConnectionId, request_id: RequestId, span: &'a Span) -> Entry<'a> {
+    let duration = duration::to_milliseconds(span.duration);
+    let query = span.uri.split_once('?').map(|(_, query)| {
+        query
+            .split_once('#')
+            .map_or(query, |(query, _)| query)
+            .to_owned()
+    });
+    let query_string = query
+        .and_then(|query| UrlSearchParams::parse(&query).ok())
+        .map(|params| {
+            params
+                .entries()
+                .map(|(name, value)| Pair {
+                    name: name.to_owned(),
+                    value: value.to_owned().into(),
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    let response_body_size = body_size(span.response_size.as_ref());
+
+    Entry {
+        pageref: connection_id,
+        started_date_time: span.start_at.to_rfc3339_opts(SecondsFormat::Millis, true),
+        time: duration,
+        request: Request {
+            method: &span.method,
+            url: &span.uri,
+            http_version: "",
+            cookies: Vec::new(),
+            headers: Vec::new(),
+            query_string,
+            headers_size: -1,
+            body_size: body_size(span.request_size.as_ref()),
+            post_data: span.bodies.request.as_deref().map(|text| PostData {
+                mime_type: "",
+                text,
+            }),
+        },
+        response: Response {
+            status: span.status.unwrap_or_default(),
+            status_text: "",
+            http_version: "",
+            cookies: Vec::new(),
+            headers: span
+                .intermediary
+                .values
+                .iter()
+                .map(|(name, value)| Pair {
+                    name: (*name).to_owned(),
+                    value: value.as_str().into(),
+                })
+                .collect(),
+            content: Content {
+                size: response_body_size.max(0),
+                mime_type: "",
+                text: span.bodies.response.as_deref(),
+            },
+            redirect_url: "",
+            headers_size: -1,
+            body_size: response_body_size,
+        },
+        cache: Cache {},
+        timings: Timings {
+            send: 0.,
+            wait: duration,
+            receive: 0.,
+        },
+        request_id,
+        errcode: span.errcode.as_deref(),
+    }
+}
+
+/// Serialize the spans as a HAR. The pages and the entries are ordered by
+/// their start.
+pub fn to_json(spans: &Spans) -> String {
+    let mut pages = spans
+        .iter()
+        .filter_map(|(connection_id, spans)| {
+            let started_at = spans.values().map(|span| span.start_at).min()?;
+
+            Some((started_at, connection_id))
+        })
+        .collect::<Vec<_>>();
+    pages.sort();
+
+    let mut entries = spans
+        .iter()
+        .flat_map(|(connection_id, spans)| {
+            spans
+                .iter()
+                .map(move |(request_id, span)| (span.start_at, connection_id, *request_id, span))
+        })
+        .collect::<Vec<_>>();
+    entries.sort_by_key(|(start_at, connection_id, request_id, _)| {
+        (*start_at, *connection_id, *request_id)
+    });
+
+    let har = Har {
+        log: Log {
+            version: "1.2",
+            creator: Creator {
+                name: env!("CARGO_PKG_NAME"),
+                version: env!("CARGO_PKG_VERSION"),
+            },
+            pages: pages
+                .into_iter()
+                .map(|(started_at, connection_id)| Page {
+                    started_date_time: started_at.to_rfc3339_opts(SecondsFormat::Millis, true),
+                    id: connection_id,
+                    title: connection_id,
+                    page_timings: PageTimings {
+                        on_content_load: -1,
+                        on_load: -1,
+                    },
+                })
+                .collect(),
+            entries: entries
+                .into_iter()
+                .map(|(_, connection_id, request_id, span)| entry(connection_id, request_id, span))
+                .collect(),
+        },
+    };
+
+    serde_json::to_string_pretty(&har).expect("Failed to serialize the HAR")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    #[test]
+    fn test_to_json() {
+        let mut parser = Parser::new();
+
+        for line in include_str!("../fixtures/session.log").lines() {
+            parser.parse_line(line, None);
+        }
+
+        assert_eq!(
+            to_json(&parser.spans),
+            include_str!("../fixtures/session.har").trim_end()
+        );
+    }
+}
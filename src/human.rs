@@ -0,0 +1,143 @@
+//! Format the numbers displayed in the report and on the console, so that
+//! they are formatted the same way everywhere: `1,204,332` lines, `51.1 KiB`,
+//! `350µs`, `1.23s`.
+//!
+//! The machine-readable outputs, e.g. the JSON dataset or the CSV, keep raw
+//! numbers.
+
+use chrono::TimeDelta;
+
+/// Format a count with thousands separators, e.g. `1,204,332`.
+pub fn count(count: usize) -> String {
+    let digits = count.to_string();
+    let mut output = String::with_capacity(digits.len() + digits.len() / 3);
+
+    for (nth, digit) in digits.chars().enumerate() {
+        if nth > 0 && (digits.len() - nth).is_multiple_of(3) {
+            output.push(',');
+        }
+
+        output.push(digit);
+    }
+
+    output
+}
+
+/// Format a derived ratio with two decimals, e.g. `12.35`.
+pub fn ratio(ratio: f64) -> String {
+    format!("{ratio:.2}")
+}
+
+/// Format `part` as a percentage of `total` with two decimals, e.g.
+/// `12.35%`, or `0.00%` if `total` is zero.
+pub fn percentage(part: f64, total: f64) -> String {
+    if total == 0. {
+        return "0.00%".to_owned();
+    }
+
+    format!("{}%", ratio(part * 100. / total))
+}
+
+/// Format a number of bytes with a binary unit, e.g. `51.1 KiB`.
+pub fn bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+
+    if bytes < 1 << 10 {
+        return format!("{bytes} B");
+    }
+
+    let mut value = bytes as f64 / 1024.;
+    let mut unit = UNITS[0];
+
+    for next_unit in &UNITS[1..] {
+        if value < 1024. {
+            break;
+        }
+
+        value /= 1024.;
+        unit = next_unit;
+    }
+
+    format!("{value:.1} {unit}")
+}
+
+/// Format a number of bytes for the report, see [`bytes`], with the raw
+/// value in the `data-bytes` attribute.
+pub fn bytes_to_html(value: u64) -> String {
+    format!("<span data-bytes=\"{value}\">{}</span>", bytes(value))
+}
+
+/// Format a duration in milliseconds, e.g. `450ms`, `1.23s`, `12.3s`,
+/// `2m05s` or `1h02m`.
+pub fn milliseconds(milliseconds: i64) -> String {
+    let sign = if milliseconds < 0 { "-" } else { "" };
+    let milliseconds = milliseconds.unsigned_abs();
+
+    match milliseconds {
+        0..1_000 => format!("{sign}{milliseconds}ms"),
+        1_000..10_000 => format!("{sign}{:.2}s", milliseconds as f64 / 1_000.),
+        10_000..60_000 => format!("{sign}{:.1}s", milliseconds as f64 / 1_000.),
+        60_000..3_600_000 => format!(
+            "{sign}{}m{:02}s",
+            milliseconds / 60_000,
+            milliseconds % 60_000 / 1_000
+        ),
+        _ => format!(
+            "{sign}{}h{:02}m",
+            milliseconds / 3_600_000,
+            milliseconds % 3_600_000 / 60_000
+        ),
+    }
+}
+
+/// Format a duration, see [`milliseconds`], or in microseconds below a
+/// millisecond, e.g. `350µs`, and with two decimals below 10ms, e.g.
+/// `1.25ms`. A zero duration is `0ms`.
+pub fn duration(duration: TimeDelta) -> String {
+    match duration.num_microseconds() {
+        Some(microseconds @ (-999..=-1 | 1..=999)) => format!("{microseconds}µs"),
+        Some(microseconds) if (1_000..10_000).contains(&microseconds.unsigned_abs()) => {
+            format!("{:.2}ms", microseconds as f64 / 1_000.)
+        }
+        _ => milliseconds(duration.num_milliseconds()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count() {
+        assert_eq!(count(0), "0");
+        assert_eq!(count(999), "999");
+        assert_eq!(count(1_000), "1,000");
+        assert_eq!(count(1_204_332), "1,204,332");
+    }
+
+    #[test]
+    fn test_bytes() {
+        assert_eq!(bytes(92), "92 B");
+        assert_eq!(bytes(52_311), "51.1 KiB");
+        assert_eq!(bytes(8_523_700), "8.1 MiB");
+    }
+
+    #[test]
+    fn test_milliseconds() {
+        assert_eq!(milliseconds(450), "450ms");
+        assert_eq!(milliseconds(1_234), "1.23s");
+        assert_eq!(milliseconds(30_000), "30.0s");
+        assert_eq!(milliseconds(61_234), "1m01s");
+        assert_eq!(milliseconds(3_725_000), "1h02m");
+        assert_eq!(milliseconds(-200), "-200ms");
+    }
+
+    #[test]
+    fn test_duration() {
+        assert_eq!(duration(TimeDelta::microseconds(350)), "350µs");
+        assert_eq!(duration(TimeDelta::microseconds(1_250)), "1.25ms");
+        assert_eq!(duration(TimeDelta::microseconds(-2_500)), "-2.50ms");
+        assert_eq!(duration(TimeDelta::milliseconds(450)), "450ms");
+        assert_eq!(duration(TimeDelta::zero()), "0ms");
+    }
+}
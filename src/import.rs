@@ -0,0 +1,660 @@
+//! Re-import the spans of a dataset exported with `--format json`, so that a
+//! report can be regenerated, e.g. with other options, without the original
+//! log.
+//!
+//! The exports up to the schema version 2 are the columnar dataset of the
+//! template, see [`crate::dataset`]; the later ones are flat records, see
+//! [`crate::json`].
+//!
+//! The CSV exports, of the spans or of their hourly aggregates, lose too much
+//! of the spans: they are recognized, but cannot be re-imported.
+
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    io::{self, BufRead},
+};
+
+use chrono::{DateTime, Offset, TimeDelta, Utc};
+use serde::Deserialize;
+
+use crate::{
+    RequestId, Span, Spans, bodies::Bodies, csv, dataset, duration, frames::Frame,
+    json::SCHEMA_VERSION, json_format, lifecycle, retry_after::RetryAfter, server_timing, setup,
+    size::Size, warnings::Warning,
+};
+
+/// Fields of the CSV exports of the hourly and of the per-room aggregates.
+const AGGREGATE_FIELDS: [&str; 11] = [
+    "hour",
+    "partial",
+    "covered_seconds",
+    "requests",
+    "errors",
+    "bytes_down",
+    "bytes_up",
+    "p95_duration_ms",
+    "gaps",
+    "room_id",
+    "error_rate",
+];
+
+/// Whether a content starts with the header of a CSV export, i.e. a first line
+/// made of known fields only, since `--columns` selects the fields of the
+/// export of the spans.
+fn is_csv(content: &[u8]) -> bool {
+    let first_line = content
+        .split(|byte| *byte == b'\n')
+        .next()
+        .unwrap_or_default();
+    let Ok(first_line) = str::from_utf8(first_line.trim_ascii_end()) else {
+        return false;
+    };
+
+    !first_line.is_empty()
+        && first_line.split(',').all(|name| {
+            csv::HEADER
+                .split(',')
+                .chain(AGGREGATE_FIELDS)
+                .any(|field| field == name)
+        })
+}
+
+/// Why an export cannot be imported.
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Json(serde_json::Error),
+    /// The export has been written by a newer version of the tool.
+    NewerSchema {
+        version: u32,
+    },
+    /// The export is a CSV one, of the spans or of their aggregates.
+    Csv,
+    Invalid(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(formatter, "{error}"),
+            Self::Json(error) => write!(formatter, "Invalid JSON dataset: {error}"),
+            Self::NewerSchema { version } => write!(
+                formatter,
+                "The dataset has the schema version {version}, but this version of the tool only supports up to {SCHEMA_VERSION}; upgrade the tool to import it"
+            ),
+            Self::Csv => write!(
+                formatter,
+                "The CSV exports only hold some fields of the spans, or their hourly aggregates; export the spans with `--format json` to re-import them"
+            ),
+            Self::Invalid(message) => write!(formatter, "Invalid dataset: {message}"),
+        }
+    }
+}
+
+/// Spans and lifecycle events read from an export.
+pub struct Import {
+    pub spans: Spans,
+    pub lifecycle_events: Vec<lifecycle::Event>,
+    pub number_of_spans: usize,
+}
+
+/// Whether a file looks like an export rather than a log: a JSON object which
+/// isn't a JSON log line, or a CSV header. Only the first non-blank line is
+/// read, not the whole log.
+pub fn is_export(path: &str) -> bool {
+    let Ok(file) = fs::File::open(path) else {
+        return false;
+    };
+    let mut reader = io::BufReader::new(file);
+    let mut first_line = Vec::new();
+
+    loop {
+        first_line.clear();
+
+        match reader.read_until(b'\n', &mut first_line) {
+            Ok(0) | Err(_) => return false,
+            Ok(_) if first_line.trim_ascii().is_empty() => {}
+            Ok(_) => break,
+        }
+    }
+
+    let first_line = first_line.trim_ascii();
+    let is_json_log =
+        || str::from_utf8(first_line).is_ok_and(|line| json_format::to_text(line).is_some());
+
+    (first_line.starts_with(b"{") && !is_json_log()) || is_csv(first_line)
+}
+
+#[derive(Deserialize)]
+struct Versioned {
+    schema: Schema,
+}
+
+#[derive(Deserialize)]
+struct Schema {
+    version: u32,
+}
+
+#[derive(Deserialize)]
+struct Dataset {
+    meta: Meta,
+    #[serde(default)]
+    summaries: Summaries,
+    strings: Strings,
+    columns: Columns,
+}
+
+#[derive(Deserialize)]
+struct Meta {
+    start_at: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+struct Summaries {
+    #[serde(default)]
+    lifecycle_events: Vec<LifecycleEvent>,
+}
+
+#[derive(Deserialize)]
+struct LifecycleEvent {
+    state: lifecycle::State,
+    at: i64,
+    log_line: usize,
+}
+
+#[derive(Deserialize)]
+struct Strings {
+    connections: Vec<String>,
+    methods: Vec<String>,
+    uris: Vec<String>,
+}
+
+/// The columns needed to rebuild the spans; the others are derived from them.
+/// The columns added after the first version of the schema are optional.
+#[derive(Deserialize)]
+struct Columns {
+    connection: Vec<usize>,
+    request_id: Vec<RequestId>,
+    status: Vec<Option<u16>>,
+    method: Vec<usize>,
+    uri: Vec<usize>,
+    request_size: Vec<Option<String>>,
+    response_size: Vec<Option<String>>,
+    start_at: Vec<f64>,
+    duration: Vec<f64>,
+    request_log_line: Vec<usize>,
+    response_log_line: Vec<Option<usize>>,
+    #[serde(default)]
+    warnings: Vec<Option<String>>,
+    #[serde(default)]
+    server_timing: Vec<Option<String>>,
+    #[serde(default)]
+    retry_after: Vec<Option<i64>>,
+    #[serde(default)]
+    app_state: Vec<Option<lifecycle::State>>,
+    #[serde(default)]
+    intermediary_headers: Vec<Option<String>>,
+    #[serde(default)]
+    errcode: Vec<Option<String>>,
+    #[serde(default)]
+    error_message: Vec<Option<String>>,
+    #[serde(default)]
+    process: Vec<usize>,
+    #[serde(default)]
+    restarted_as: Vec<Option<String>>,
+    #[serde(default)]
+    pos: Vec<Option<String>>,
+    #[serde(default)]
+    pos_stalled: Vec<bool>,
+    #[serde(default)]
+    pos_reset: Vec<bool>,
+    #[serde(default)]
+    txn_id: Vec<Option<String>>,
+    #[serde(default)]
+    fields: Vec<Option<String>>,
+    #[serde(default)]
+    error: Vec<Option<String>>,
+    #[serde(default)]
+    retries: Vec<u32>,
+    #[serde(default)]
+    iteration: Vec<u32>,
+    #[serde(default)]
+    connection_reused: Vec<Option<bool>>,
+    #[serde(default)]
+    dns: Vec<Option<f64>>,
+    #[serde(default)]
+    connect: Vec<Option<f64>>,
+    #[serde(default)]
+    tls: Vec<Option<f64>>,
+    #[serde(default)]
+    ttfb: Vec<Option<f64>>,
+    #[serde(default)]
+    parent: Vec<Option<String>>,
+    #[serde(default)]
+    frames: Vec<Option<String>>,
+    #[serde(default)]
+    request_body: Vec<Option<String>>,
+    #[serde(default)]
+    response_body: Vec<Option<String>>,
+    #[serde(default)]
+    source: Vec<Option<String>>,
+}
+
+#[derive(Deserialize)]
+struct Export {
+    #[serde(default)]
+    summaries: Summaries,
+    spans: Vec<Record>,
+}
+
+/// The fields needed to rebuild a span; the others are derived from it.
+#[derive(Deserialize)]
+struct Record {
+    connection_id: String,
+    request_id: RequestId,
+    method: String,
+    uri: String,
+    status: Option<u16>,
+    request_size: Option<String>,
+    response_size: Option<String>,
+    start_at: String,
+    duration_ms: f64,
+    request_log_line: usize,
+    response_log_line: Option<usize>,
+    errcode: Option<String>,
+    error_message: Option<String>,
+    error: Option<String>,
+    retries: u32,
+    retry_after_ms: Option<i64>,
+    server_timing: Option<String>,
+    pos: Option<String>,
+    pos_stalled: bool,
+    pos_reset: bool,
+    txn_id: Option<String>,
+    iteration: u32,
+    parent: Option<String>,
+    source: Option<String>,
+    process: usize,
+    restarted_as: Option<String>,
+    app_state: Option<lifecycle::State>,
+    warnings: Vec<String>,
+    frames: Vec<String>,
+    intermediary_headers: BTreeMap<String, String>,
+    fields: BTreeMap<String, String>,
+    connection_reused: Option<bool>,
+    dns_ms: Option<f64>,
+    connect_ms: Option<f64>,
+    tls_ms: Option<f64>,
+    ttfb_ms: Option<f64>,
+    request_body: Option<String>,
+    response_body: Option<String>,
+}
+
+/// Read the spans of an export.
+pub fn read(path: &str) -> Result<Import, Error> {
+    let content = fs::read_to_string(path).map_err(Error::Io)?;
+
+    if is_csv(content.trim_start().as_bytes()) {
+        return Err(Error::Csv);
+    }
+
+    parse(&content)
+}
+
+fn parse(content: &str) -> Result<Import, Error> {
+    // The version is checked first, so that a newer layout gets a clear error
+    // rather than a deserialization one.
+    let versioned = serde_json::from_str::<Versioned>(content).map_err(Error::Json)?;
+
+    if versioned.schema.version > SCHEMA_VERSION {
+        return Err(Error::NewerSchema {
+            version: versioned.schema.version,
+        });
+    }
+
+    if versioned.schema.version > dataset::SCHEMA_VERSION {
+        parse_export(content)
+    } else {
+        parse_dataset(content)
+    }
+}
+
+/// Parse the flat records of [`crate::json::to_json`].
+fn parse_export(content: &str) -> Result<Import, Error> {
+    let export = serde_json::from_str::<Export>(content).map_err(Error::Json)?;
+    let number_of_spans = export.spans.len();
+    let mut timezone = None;
+    let mut spans = Spans::new();
+
+    for record in export.spans {
+        let start_at = DateTime::parse_from_rfc3339(&record.start_at).map_err(|error| {
+            Error::Invalid(format!(
+                "the `start_at` of the request `{}-{}` isn't a date: {error}",
+                record.connection_id, record.request_id
+            ))
+        })?;
+        timezone.get_or_insert(*start_at.offset());
+
+        let mut span = Span {
+            status: record.status,
+            method: record.method,
+            uri: record.uri,
+            request_size: record.request_size.as_deref().map(Size::new),
+            response_size: record.response_size.as_deref().map(Size::new),
+            start_at,
+            duration: duration::from_milliseconds(record.duration_ms),
+            request_log_line: record.request_log_line,
+            response_log_line: record.response_log_line,
+            request_context: None,
+            response_context: None,
+            errcode: record.errcode,
+            error_message: record.error_message,
+            warnings: record
+                .warnings
+                .iter()
+                .filter_map(|warning| Warning::parse(warning))
+                .collect(),
+            server_timing: record
+                .server_timing
+                .map(|header| server_timing::parse(&header))
+                .unwrap_or_default(),
+            retry_after: record
+                .retry_after_ms
+                .map(|delay| RetryAfter::Delay(TimeDelta::milliseconds(delay))),
+            app_state: record.app_state,
+            intermediary: Default::default(),
+            setup: setup::Setup {
+                reused: record.connection_reused,
+                dns: record.dns_ms,
+                connect: record.connect_ms,
+                tls: record.tls_ms,
+                ttfb: record.ttfb_ms,
+            },
+            bodies: Bodies {
+                request: record.request_body,
+                response: record.response_body,
+            },
+            process_nth: record.process,
+            restarted_as: record.restarted_as,
+            pos: record.pos,
+            pos_stalled: record.pos_stalled,
+            pos_reset: record.pos_reset,
+            txn_id: record.txn_id,
+            fields: record.fields,
+            error: record.error,
+            retries: record.retries,
+            iteration: record.iteration,
+            parent: record.parent,
+            frames: record
+                .frames
+                .iter()
+                .map(|frame| Frame::parse(frame))
+                .collect(),
+            source: record.source,
+        };
+
+        for (name, value) in &record.intermediary_headers {
+            span.intermediary.insert(name, value);
+        }
+
+        spans
+            .entry(record.connection_id)
+            .or_default()
+            .insert(record.request_id, span);
+    }
+
+    let lifecycle_events = export
+        .summaries
+        .lifecycle_events
+        .into_iter()
+        .filter_map(|event| {
+            Some(lifecycle::Event {
+                state: event.state,
+                at: DateTime::from_timestamp_millis(event.at)?
+                    .with_timezone(&timezone.unwrap_or(Utc.fix())),
+                log_line: event.log_line,
+            })
+        })
+        .collect();
+
+    Ok(Import {
+        spans,
+        lifecycle_events,
+        number_of_spans,
+    })
+}
+
+/// Parse the columnar dataset of the template, of the schema versions 1 and
+/// 2.
+fn parse_dataset(content: &str) -> Result<Import, Error> {
+    let dataset = serde_json::from_str::<Dataset>(content).map_err(Error::Json)?;
+    let columns = &dataset.columns;
+    let number_of_spans = columns.connection.len();
+
+    let origin = match &dataset.meta.start_at {
+        Some(start_at) => DateTime::parse_from_rfc3339(start_at)
+            .map_err(|error| Error::Invalid(format!("`meta.start_at` isn't a date: {error}")))?,
+        None if number_of_spans == 0 => {
+            return Ok(Import {
+                spans: Spans::new(),
+                lifecycle_events: Vec::new(),
+                number_of_spans,
+            });
+        }
+        None => return Err(Error::Invalid("`meta.start_at` is missing".to_owned())),
+    };
+    let timezone = *origin.offset();
+
+    let mut spans = Spans::new();
+
+    for nth in 0..number_of_spans {
+        let missing = |column: &str| Error::Invalid(format!("column `{column}` is too short"));
+        // Optional columns may be absent, but not partial.
+        let optional = |values: &Vec<Option<String>>, column: &str| {
+            if values.is_empty() {
+                Ok(None)
+            } else {
+                values.get(nth).cloned().ok_or_else(|| missing(column))
+            }
+        };
+        let string = |strings: &[String], index: usize, table: &str| {
+            strings
+                .get(index)
+                .cloned()
+                .ok_or_else(|| Error::Invalid(format!("index {index} is out of `strings.{table}`")))
+        };
+
+        let connection_id = string(
+            &dataset.strings.connections,
+            columns.connection[nth],
+            "connections",
+        )?;
+        let request_id = *columns
+            .request_id
+            .get(nth)
+            .ok_or_else(|| missing("request_id"))?;
+        let start_at = columns
+            .start_at
+            .get(nth)
+            .ok_or_else(|| missing("start_at"))?;
+        let start_at = origin + duration::from_milliseconds(*start_at);
+
+        let mut span = Span {
+            status: *columns.status.get(nth).ok_or_else(|| missing("status"))?,
+            method: string(
+                &dataset.strings.methods,
+                *columns.method.get(nth).ok_or_else(|| missing("method"))?,
+                "methods",
+            )?,
+            uri: string(
+                &dataset.strings.uris,
+                *columns.uri.get(nth).ok_or_else(|| missing("uri"))?,
+                "uris",
+            )?,
+            request_size: columns
+                .request_size
+                .get(nth)
+                .ok_or_else(|| missing("request_size"))?
+                .as_deref()
+                .map(Size::new),
+            response_size: columns
+                .response_size
+                .get(nth)
+                .ok_or_else(|| missing("response_size"))?
+                .as_deref()
+                .map(Size::new),
+            start_at,
+            duration: duration::from_milliseconds(
+                *columns
+                    .duration
+                    .get(nth)
+                    .ok_or_else(|| missing("duration"))?,
+            ),
+            request_log_line: *columns
+                .request_log_line
+                .get(nth)
+                .ok_or_else(|| missing("request_log_line"))?,
+            response_log_line: *columns
+                .response_log_line
+                .get(nth)
+                .ok_or_else(|| missing("response_log_line"))?,
+            request_context: None,
+            response_context: None,
+            errcode: optional(&columns.errcode, "errcode")?,
+            error_message: optional(&columns.error_message, "error_message")?,
+            warnings: optional(&columns.warnings, "warnings")?
+                .map(|warnings| warnings.lines().filter_map(Warning::parse).collect())
+                .unwrap_or_default(),
+            server_timing: optional(&columns.server_timing, "server_timing")?
+                .map(|header| server_timing::parse(&header))
+                .unwrap_or_default(),
+            retry_after: columns
+                .retry_after
+                .get(nth)
+                .copied()
+                .flatten()
+                .map(|delay| RetryAfter::Delay(TimeDelta::milliseconds(delay))),
+            app_state: columns.app_state.get(nth).copied().flatten(),
+            intermediary: Default::default(),
+            setup: setup::Setup {
+                reused: columns.connection_reused.get(nth).copied().flatten(),
+                dns: columns.dns.get(nth).copied().flatten(),
+                connect: columns.connect.get(nth).copied().flatten(),
+                tls: columns.tls.get(nth).copied().flatten(),
+                ttfb: columns.ttfb.get(nth).copied().flatten(),
+            },
+            bodies: Bodies {
+                request: optional(&columns.request_body, "request_body")?,
+                response: optional(&columns.response_body, "response_body")?,
+            },
+            process_nth: columns.process.get(nth).copied().unwrap_or(1),
+            restarted_as: optional(&columns.restarted_as, "restarted_as")?,
+            pos: optional(&columns.pos, "pos")?,
+            pos_stalled: columns.pos_stalled.get(nth).copied().unwrap_or_default(),
+            pos_reset: columns.pos_reset.get(nth).copied().unwrap_or_default(),
+            txn_id: optional(&columns.txn_id, "txn_id")?,
+            fields: optional(&columns.fields, "fields")?
+                .iter()
+                .flat_map(|fields| fields.lines())
+                .filter_map(|field| field.split_once('='))
+                .map(|(name, value)| (name.to_owned(), value.to_owned()))
+                .collect(),
+            error: optional(&columns.error, "error")?,
+            retries: columns.retries.get(nth).copied().unwrap_or_default(),
+            iteration: columns.iteration.get(nth).copied().unwrap_or_default(),
+            parent: optional(&columns.parent, "parent")?,
+            frames: optional(&columns.frames, "frames")?
+                .map(|frames| frames.lines().map(Frame::parse).collect())
+                .unwrap_or_default(),
+            source: optional(&columns.source, "source")?,
+        };
+
+        for header in optional(&columns.intermediary_headers, "intermediary_headers")?
+            .iter()
+            .flat_map(|headers| headers.lines())
+        {
+            if let Some((name, value)) = header.split_once(": ") {
+                span.intermediary.insert(name, value);
+            }
+        }
+
+        spans
+            .entry(connection_id)
+            .or_default()
+            .insert(request_id, span);
+    }
+
+    let lifecycle_events = dataset
+        .summaries
+        .lifecycle_events
+        .into_iter()
+        .filter_map(|event| {
+            Some(lifecycle::Event {
+                state: event.state,
+                at: DateTime::from_timestamp_millis(event.at)?.with_timezone(&timezone),
+                log_line: event.log_line,
+            })
+        })
+        .collect();
+
+    Ok(Import {
+        spans,
+        lifecycle_events,
+        number_of_spans,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let import = parse(
+            r#"{
+                "schema": {"version": 1},
+                "meta": {"start_at": "2024-06-01T09:13:19.035+02:00"},
+                "strings": {
+                    "connections": ["c1"],
+                    "methods": ["GET"],
+                    "uris": ["https://example.org/_matrix/client/v3/sync"]
+                },
+                "columns": {
+                    "connection": [0],
+                    "request_id": [4],
+                    "status": [200],
+                    "method": [0],
+                    "uri": [0],
+                    "request_size": [null],
+                    "response_size": ["1.2k"],
+                    "start_at": [1500],
+                    "duration": [300],
+                    "request_log_line": [12],
+                    "response_log_line": [20],
+                    "warnings": ["WARN at log line 15: slow"]
+                }
+            }"#,
+        )
+        .unwrap();
+
+        let span = &import.spans["c1"][&4];
+        assert_eq!(import.number_of_spans, 1);
+        assert_eq!(span.start_at.to_rfc3339(), "2024-06-01T09:13:20.535+02:00");
+        assert_eq!(span.duration, TimeDelta::milliseconds(300));
+        assert_eq!(span.response_size.as_deref(), Some("1.2k"));
+        assert_eq!(span.warnings[0].log_line, 15);
+        assert!(span.server_timing.is_empty());
+
+        assert!(matches!(
+            parse(r#"{"schema": {"version": 4}, "spans": {"future": []}}"#),
+            Err(Error::NewerSchema { version: 4 })
+        ));
+        assert!(matches!(
+            parse(
+                r#"{"schema": {"version": 1}, "meta": {"start_at": null}, "strings": {"connections": [], "methods": [], "uris": []}, "columns": {"connection": [0], "request_id": []}}"#
+            ),
+            Err(Error::Json(_))
+        ));
+    }
+}
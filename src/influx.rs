@@ -0,0 +1,152 @@
+//! Export the spans as InfluxDB line protocol, with one `http_request` point
+//! per span, and one `http_requests` point per minute and per endpoint kind
+//! for the aggregates.
+//!
+//! Raw URIs are never tags, to keep the cardinality low: only the endpoint
+//! kind is.
+
+use chrono::{DateTime, FixedOffset};
+
+use crate::{ConnectionId, RequestId, Span, Spans, buckets, duration, endpoint, size::Size};
+
+/// Render the spans and their aggregates as line protocol.
+///
+/// An error is returned if a date is out of the range of the timestamps of
+/// the line protocol, i.e. before 1677 or after 2262, e.g. a corrupted line.
+pub fn to_line_protocol(
+    spans: &[(&ConnectionId, RequestId, &Span)],
+    all_spans: &Spans,
+    range: Option<(DateTime<FixedOffset>, DateTime<FixedOffset>)>,
+) -> Result<String, String> {
+    let mut output = String::new();
+
+    for (connection_id, request_id, span) in spans {
+        let mut fields = vec![format!("request_id={request_id}i")];
+
+        if let Some(status) = span.status {
+            fields.push(format!("status={status}i"));
+        }
+
+        if span.response_log_line.is_some() {
+            fields.push(format!(
+                "duration_ms={}",
+                duration::to_milliseconds(span.duration)
+            ));
+        }
+
+        if let Some(request_bytes) = span.request_size.as_ref().and_then(Size::bytes) {
+            fields.push(format!("request_bytes={request_bytes}i"));
+        }
+
+        if let Some(response_bytes) = span.response_size.as_ref().and_then(Size::bytes) {
+            fields.push(format!("response_bytes={response_bytes}i"));
+        }
+
+        for metric in &span.server_timing {
+            if let Some(duration) = metric.duration {
+                fields.push(format!(
+                    "server_timing_{name}_ms={duration}",
+                    name = escape_tag(&metric.name)
+                ));
+            }
+        }
+
+        output.push_str(&format!(
+            "http_request,conn={connection_id},endpoint={endpoint},method={method},status_family={status_family} {fields} {timestamp}\n",
+            connection_id = escape_tag(connection_id),
+            endpoint = span.kind().as_str(),
+            method = escape_tag(&span.method),
+            status_family = span
+                .status
+                .map(|status| (status / 100).to_string())
+                .unwrap_or_else(|| "none".to_owned()),
+            fields = fields.join(","),
+            timestamp = timestamp(span.start_at).ok_or_else(|| format!(
+                "The request `{connection_id}-{request_id}` starts at {}, out of the range of the line protocol",
+                span.start_at.to_rfc3339()
+            ))?,
+        ));
+    }
+
+    let Some(range) = range else {
+        return Ok(output);
+    };
+
+    let kinds = endpoint::Kind::ALL
+        .iter()
+        .map(endpoint::Kind::as_str)
+        .chain(["all"]);
+
+    for bucket in buckets::per_minute_and_kind(all_spans, range) {
+        for (kind, aggregate) in kinds.clone().zip(&bucket.per_kind) {
+            let mut fields = vec![
+                format!("requests={}i", aggregate.requests),
+                format!("errors={}i", aggregate.errors),
+                format!("bytes_down={}i", aggregate.bytes_down),
+                format!("bytes_up={}i", aggregate.bytes_up),
+            ];
+
+            if let Some(p95_duration) = aggregate.percentile_duration(95.) {
+                fields.push(format!(
+                    "p95_duration_ms={}",
+                    duration::to_milliseconds(p95_duration)
+                ));
+            }
+
+            output.push_str(&format!(
+                "http_requests,endpoint={kind} {fields} {timestamp}\n",
+                fields = fields.join(","),
+                timestamp = timestamp(bucket.start_at).ok_or_else(|| format!(
+                    "The minute starting at {} is out of the range of the line protocol",
+                    bucket.start_at.to_rfc3339()
+                ))?,
+            ));
+        }
+    }
+
+    Ok(output)
+}
+
+/// Escape a tag key or a tag value.
+fn escape_tag(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    for character in value.chars() {
+        if matches!(character, ',' | '=' | ' ' | '\\') {
+            escaped.push('\\');
+        }
+
+        escaped.push(character);
+    }
+
+    escaped
+}
+
+/// Get the timestamp of a date, in nanoseconds, if it's in range.
+fn timestamp(date_time: DateTime<FixedOffset>) -> Option<i64> {
+    date_time.timestamp_nanos_opt()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+
+    use super::*;
+
+    #[test]
+    fn test_out_of_range() {
+        let mut span = Span::for_tests(
+            "https://example.org/_matrix/client/v3/sync",
+            Some(200),
+            TimeDelta::milliseconds(100),
+        );
+        span.start_at = DateTime::parse_from_rfc3339("2300-01-01T00:00:00Z").unwrap();
+        let connection_id = "main".to_owned();
+
+        assert!(
+            to_line_protocol(&[(&connection_id, 1, &span)], &Spans::new(), None)
+                .unwrap_err()
+                .contains("`main-1`")
+        );
+    }
+}
@@ -0,0 +1,173 @@
+//! Detect the initial syncs, i.e. the first syncs after a login or a cache
+//! clear.
+//!
+//! An initial sync has no `since` or `pos` token: the server sends the whole
+//! state of the account, so it is much longer and larger than the
+//! steady-state syncs, and must be looked at apart from them. Several initial
+//! syncs on the same connection mean that the cache has been cleared
+//! repeatedly, which is a finding on its own.
+
+use std::collections::BTreeSet;
+
+use chrono::{FixedOffset, SecondsFormat};
+use serde::Serialize;
+
+use crate::{ConnectionId, RequestId, Span, Spans, html, human, size::Size};
+
+/// An initial sync.
+#[derive(Serialize)]
+pub struct InitialSync {
+    pub connection_id: ConnectionId,
+    pub request_id: RequestId,
+
+    /// Start of the sync, as RFC 3339.
+    pub start_at: String,
+
+    /// Duration, in milliseconds.
+    pub duration: i64,
+
+    /// Size of the response, in bytes, if known.
+    pub response_size: Option<u64>,
+
+    /// Whether a response has been received.
+    pub completed: bool,
+}
+
+/// All the initial syncs, by start time.
+#[derive(Default, Serialize)]
+#[serde(transparent)]
+pub struct InitialSyncs {
+    syncs: Vec<InitialSync>,
+}
+
+/// Whether a sync continues from a previous one.
+fn has_token(span: &Span) -> bool {
+    span.query_parameter("since").is_some() || span.query_parameter("pos").is_some()
+}
+
+/// Detect the initial syncs: the syncs without a `since` or `pos` token.
+///
+/// If no sync of the log has a token, the query strings are presumably not
+/// logged, and the first sync of each connection is considered initial
+/// instead.
+pub fn detect(spans: &Spans, timezone: FixedOffset) -> InitialSyncs {
+    let syncs = spans.iter().flat_map(|(connection_id, spans)| {
+        spans
+            .iter()
+            .filter(|(_, span)| span.is_sync())
+            .map(move |(request_id, span)| (connection_id, *request_id, span))
+    });
+
+    let mut initial_syncs = if syncs.clone().any(|(_, _, span)| has_token(span)) {
+        syncs.filter(|(_, _, span)| !has_token(span)).collect()
+    } else {
+        spans
+            .keys()
+            .filter_map(|connection_id| {
+                syncs
+                    .clone()
+                    .filter(|(sync_connection_id, ..)| *sync_connection_id == connection_id)
+                    .min_by_key(|(_, request_id, span)| (span.start_at, *request_id))
+            })
+            .collect::<Vec<_>>()
+    };
+    initial_syncs.sort_by_key(|(_, request_id, span)| (span.start_at, *request_id));
+
+    InitialSyncs {
+        syncs: initial_syncs
+            .into_iter()
+            .map(|(connection_id, request_id, span)| InitialSync {
+                connection_id: connection_id.clone(),
+                request_id,
+                start_at: span
+                    .start_at
+                    .with_timezone(&timezone)
+                    .to_rfc3339_opts(SecondsFormat::Millis, false),
+                duration: span.duration.num_milliseconds(),
+                response_size: span.response_size.as_ref().and_then(Size::bytes),
+                completed: span.status.is_some(),
+            })
+            .collect(),
+    }
+}
+
+impl InitialSyncs {
+    /// Whether a span is an initial sync.
+    pub fn contains(&self, connection_id: &str, request_id: RequestId) -> bool {
+        self.syncs
+            .iter()
+            .any(|sync| sync.connection_id == connection_id && sync.request_id == request_id)
+    }
+
+    /// Render the headline metrics of the initial syncs, or nothing if there
+    /// is none.
+    pub fn to_html(&self) -> String {
+        let describe = |sync: &InitialSync| {
+            let size = sync
+                .response_size
+                .map(|response_size| format!(", {}", human::bytes_to_html(response_size)))
+                .unwrap_or_default();
+
+            if sync.completed {
+                format!(
+                    "{duration}{size}",
+                    duration = human::milliseconds(sync.duration)
+                )
+            } else {
+                "cancelled".to_owned()
+            }
+        };
+
+        match self.syncs.as_slice() {
+            [] => String::new(),
+            [sync] => format!(
+                "  <h3>Initial sync</h3>
+  <p>{link} started at {start_at}: {description}.</p>
+",
+                link = link(sync),
+                start_at = sync.start_at,
+                description = describe(sync),
+            ),
+            syncs => {
+                let items = syncs
+                    .iter()
+                    .map(|sync| {
+                        format!(
+                            "    <li>{link} started at {start_at}: {description}.</li>\n",
+                            link = link(sync),
+                            start_at = sync.start_at,
+                            description = describe(sync),
+                        )
+                    })
+                    .collect::<String>();
+                // Each sync loop starts with an initial sync, so only several
+                // ones on the same connection are a finding.
+                let connection_ids = syncs
+                    .iter()
+                    .map(|sync| &sync.connection_id)
+                    .collect::<BTreeSet<_>>();
+                let finding = if connection_ids.len() < syncs.len() {
+                    "  <p>Several initial syncs on the same connection: the cache has been cleared, or the session has logged in again, repeatedly.</p>\n"
+                } else {
+                    ""
+                };
+
+                format!(
+                    "  <h3>Initial syncs</h3>
+{finding}  <ul>
+{items}  </ul>
+"
+                )
+            }
+        }
+    }
+}
+
+/// Render a link to the row of an initial sync.
+fn link(sync: &InitialSync) -> String {
+    format!(
+        "<a href=\"#{connection_id}-{request_id}\"><code>{connection_id}-{request_id}</code></a>",
+        connection_id = html::escape(&sync.connection_id),
+        request_id = sync.request_id,
+    )
+}
@@ -0,0 +1,237 @@
+//! Detect the proxies and CDNs between the client and the homeserver, from
+//! the `Via`, `CF-Ray`, `X-Cache`, `Server`… response headers when they are
+//! logged, and break the latencies down by intermediary and cache status.
+//!
+//! Media served by a CDN often have a bimodal latency distribution, between
+//! the cache hits and the cache misses.
+
+use std::collections::BTreeMap;
+
+use crate::{Spans, html, human, stats};
+
+/// Names of the captured headers, lowercase.
+pub const HEADERS: [&str; 7] = [
+    "via",
+    "server",
+    "cf-ray",
+    "cf-cache-status",
+    "x-cache",
+    "x-served-by",
+    "x-amz-cf-id",
+];
+
+/// Response headers telling about an intermediary.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Headers {
+    /// Pairs of a name among [`HEADERS`] and a value, in the order of the
+    /// log.
+    pub values: Vec<(&'static str, String)>,
+}
+
+impl Headers {
+    /// Add or replace a header. Unknown names are ignored.
+    pub fn insert(&mut self, name: &str, value: &str) {
+        let Some(name) = HEADERS
+            .into_iter()
+            .find(|header| header.eq_ignore_ascii_case(name))
+        else {
+            return;
+        };
+
+        self.values.retain(|(existing, _)| *existing != name);
+        self.values.push((name, value.trim().to_owned()));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    fn get(&self, name: &str) -> Option<&str> {
+        self.values
+            .iter()
+            .find(|(header, _)| *header == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Name the intermediary, e.g. `Cloudflare`, if any.
+    pub fn intermediary(&self) -> Option<String> {
+        let via = self.get("via").map(str::to_ascii_lowercase);
+        let server = self.get("server").map(str::to_ascii_lowercase);
+        let mentions = |name: &str| {
+            [&via, &server]
+                .into_iter()
+                .flatten()
+                .any(|value| value.contains(name))
+        };
+
+        if self.get("cf-ray").is_some() || mentions("cloudflare") {
+            Some("Cloudflare".to_owned())
+        } else if self.get("x-amz-cf-id").is_some() || mentions("cloudfront") {
+            Some("CloudFront".to_owned())
+        } else if self
+            .get("x-served-by")
+            .is_some_and(|value| value.starts_with("cache-"))
+        {
+            Some("Fastly".to_owned())
+        } else if mentions("varnish") {
+            Some("Varnish".to_owned())
+        } else if let Some(via) = self.get("via") {
+            // E.g. `1.1 proxy.example.org (squid)`: the received-by part.
+            Some(via.split_whitespace().nth(1).unwrap_or(via).to_owned())
+        } else {
+            // E.g. `nginx/1.25.3`: a reverse proxy, or the homeserver itself.
+            self.get("server")
+                .map(|server| server.split('/').next().unwrap_or(server).to_owned())
+        }
+    }
+
+    /// Get the cache status, e.g. `HIT` or `MISS`, if any.
+    pub fn cache_status(&self) -> Option<String> {
+        let value = self.get("cf-cache-status").or(self.get("x-cache"))?;
+
+        // `X-Cache` may list several caches, e.g. `MISS, HIT`, or be like `Hit
+        // from cloudfront`: the first word of the nearest cache is kept.
+        value
+            .split(',')
+            .next_back()
+            .and_then(|status| status.split_whitespace().next())
+            .map(str::to_ascii_uppercase)
+    }
+
+    /// Describe the intermediary and its cache status, e.g. `Cloudflare,
+    /// cache MISS`.
+    pub fn describe(&self) -> Option<String> {
+        match (self.intermediary(), self.cache_status()) {
+            (Some(intermediary), Some(cache_status)) => {
+                Some(format!("{intermediary}, cache {cache_status}"))
+            }
+            (Some(intermediary), None) => Some(intermediary),
+            (None, Some(cache_status)) => Some(format!("cache {cache_status}")),
+            (None, None) => None,
+        }
+    }
+}
+
+/// Render the latencies per intermediary and cache status, or nothing if no
+/// header has been captured.
+pub fn to_html(spans: &Spans) -> String {
+    let mut durations = BTreeMap::<(String, String), Vec<i64>>::new();
+
+    for span in spans.values().flat_map(|spans| spans.values()) {
+        if span.intermediary.is_empty() || !span.is_successful() {
+            continue;
+        }
+
+        durations
+            .entry((
+                span.intermediary
+                    .intermediary()
+                    .unwrap_or_else(|| "(unknown)".to_owned()),
+                span.intermediary.cache_status().unwrap_or_default(),
+            ))
+            .or_default()
+            .push(span.duration.num_milliseconds());
+    }
+
+    if durations.is_empty() {
+        return String::new();
+    }
+
+    let rows = durations
+        .into_iter()
+        .map(|((intermediary, cache_status), mut durations)| {
+            durations.sort_unstable();
+
+            let percentile = |percentile| {
+                stats::percentile(&durations, percentile)
+                    .map(human::milliseconds)
+                    .unwrap_or_default()
+            };
+
+            format!(
+                "      <tr>
+        <th scope=\"row\">{intermediary}</th>
+        <td>{cache_status}</td>
+        <td>{requests}</td>
+        <td>{p50}</td>
+        <td>{p95}</td>
+      </tr>
+",
+                intermediary = html::escape(&intermediary),
+                cache_status = html::escape(&cache_status),
+                requests = human::count(durations.len()),
+                p50 = percentile(50.),
+                p95 = percentile(95.),
+            )
+        })
+        .collect::<String>();
+
+    format!(
+        "  <h3>Latency per intermediary</h3>
+  <table class=\"intermediaries\">
+    <thead>
+      <tr>
+        <th scope=\"col\">Intermediary</th>
+        <th scope=\"col\">Cache</th>
+        <th scope=\"col\">Requests</th>
+        <th scope=\"col\">p50</th>
+        <th scope=\"col\">p95</th>
+      </tr>
+    </thead>
+    <tbody>
+{rows}    </tbody>
+  </table>
+"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(values: &[(&str, &str)]) -> Headers {
+        let mut headers = Headers::default();
+
+        for (name, value) in values {
+            headers.insert(name, value);
+        }
+
+        headers
+    }
+
+    #[test]
+    fn test_describe() {
+        for (values, expected) in [
+            (
+                &[("CF-Ray", "8f1b2c3d4e5f-CDG"), ("cf-cache-status", "MISS")][..],
+                Some("Cloudflare, cache MISS"),
+            ),
+            (
+                &[
+                    ("via", "1.1 abc.cloudfront.net (CloudFront)"),
+                    ("x-cache", "Hit from cloudfront"),
+                ],
+                Some("CloudFront, cache HIT"),
+            ),
+            (
+                &[
+                    ("x-served-by", "cache-par-1234-PAR"),
+                    ("x-cache", "MISS, HIT"),
+                ],
+                Some("Fastly, cache HIT"),
+            ),
+            (
+                &[("via", "1.1 proxy.example.org (squid)")],
+                Some("proxy.example.org"),
+            ),
+            (&[("server", "nginx/1.25.3")], Some("nginx")),
+            (&[("x-unknown", "yes")], None),
+        ] {
+            assert_eq!(
+                headers(values).describe().as_deref(),
+                expected,
+                "{values:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,40 @@
+//! Stop reading a source which never ends, e.g. a followed file, on Ctrl-C, so
+//! that the final report is written and the stats are printed.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Catch the next Ctrl-C, instead of being killed by it. A second Ctrl-C kills
+/// the process, e.g. if the final report takes too long.
+pub fn catch() {
+    #[cfg(unix)]
+    // SAFETY: the handler is async-signal-safe: it only stores an atomic, and
+    // restores the default handler.
+    unsafe {
+        libc::signal(
+            libc::SIGINT,
+            on_interrupt as *const () as libc::sighandler_t,
+        );
+    }
+}
+
+#[cfg(unix)]
+extern "C" fn on_interrupt(_: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+
+    // SAFETY: `signal` is async-signal-safe.
+    unsafe {
+        libc::signal(libc::SIGINT, libc::SIG_DFL);
+    }
+}
+
+/// Whether Ctrl-C has been pressed.
+pub fn is_interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+#[cfg(test)]
+pub fn interrupt() {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
@@ -0,0 +1,74 @@
+//! Count the iterations of the sync loops: the `conn_id` of a connection is
+//! the same for the whole session, but each `sync_once` is an iteration, see
+//! [`crate::Span::iteration`].
+
+use std::collections::BTreeSet;
+
+use crate::{Spans, human};
+
+/// Render the number of iterations per connection, and the average number of
+/// requests per iteration, or nothing if no request is sent from a sync loop.
+pub fn per_connection_to_text(spans: &Spans) -> String {
+    let connections = spans
+        .iter()
+        .filter_map(|(connection_id, spans)| {
+            let spans = spans
+                .values()
+                .filter(|span| span.iteration > 0)
+                .collect::<Vec<_>>();
+            let iterations = spans
+                .iter()
+                .map(|span| span.iteration)
+                .collect::<BTreeSet<_>>()
+                .len();
+
+            (iterations > 0).then(|| {
+                format!(
+                    "  {connection_id}: {iterations}, {average:.1} requests per iteration\n",
+                    iterations = human::count(iterations),
+                    average = spans.len() as f64 / iterations as f64,
+                )
+            })
+        })
+        .collect::<String>();
+
+    if connections.is_empty() {
+        return String::new();
+    }
+
+    format!("Iterations of the sync loops per connection:\n{connections}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{NO_CONNECTION_ID, RequestId, SpansBuilder};
+
+    #[test]
+    fn test_per_connection_to_text() {
+        let mut spans = SpansBuilder::default();
+        let mut span = |connection_id, request_id: RequestId, iteration| {
+            spans
+                .span(
+                    connection_id,
+                    request_id,
+                    "https://example.org/_matrix/client/unstable/org.matrix.simplified_msc3575/sync",
+                    Some(200),
+                    0,
+                    10,
+                )
+                .iteration = iteration;
+        };
+        span("room-list", 1, 1);
+        span("room-list", 2, 1);
+        span("room-list", 3, 2);
+        span(NO_CONNECTION_ID, 4, 0);
+        let spans = spans.build();
+
+        assert_eq!(
+            per_connection_to_text(&spans),
+            "Iterations of the sync loops per connection:\n  room-list: 2, 1.5 requests per iteration\n"
+        );
+        assert_eq!(per_connection_to_text(&Spans::new()), "");
+    }
+}
@@ -0,0 +1,195 @@
+//! Export the spans as JSON, with `--format json`, for the scripts and the
+//! other tools, e.g. `jq '.spans[] | select(.status >= 500) | .uri'`.
+//!
+//! Unlike the columnar dataset of the template, see [`crate::dataset`], the
+//! spans are a flat array of objects, with their strings decoded and their
+//! dates absolute. They are in the order of `--sort`, with stable tiebreaks,
+//! and the export holds nothing depending on when it has been generated, so
+//! that the exports of 2 runs on the same log can be diffed.
+//!
+//! The export holds all the fields of the spans, so that it can be imported
+//! again, see [`crate::import`].
+
+use std::collections::BTreeMap;
+
+use chrono::{FixedOffset, SecondsFormat};
+use serde::Serialize;
+
+use crate::{
+    ConnectionId, RequestId, Span, anomalies::Anomalies, dataset::Summaries, duration, lifecycle,
+    meta::Meta, server_timing, size::Size,
+};
+
+/// Version of the layout, bumped on every breaking change. The versions 1
+/// and 2 were the columnar dataset of the template.
+pub const SCHEMA_VERSION: u32 = 3;
+
+#[derive(Serialize)]
+struct Schema {
+    version: u32,
+}
+
+#[derive(Serialize)]
+struct Export<'a> {
+    schema: Schema,
+    meta: &'a Meta<'a>,
+    summaries: &'a Summaries<'a>,
+    spans: Vec<Record<'a>>,
+}
+
+/// A span, and some fields derived from it. The durations are in
+/// milliseconds, with a microsecond precision.
+#[derive(Serialize)]
+struct Record<'a> {
+    connection_id: &'a str,
+    request_id: RequestId,
+    method: &'a str,
+    uri: &'a str,
+    domain: String,
+    path: String,
+    endpoint: String,
+    status: Option<u16>,
+    /// `2` for `200`, `pending` or `cancelled`, see [`Span::status_family`].
+    status_family: String,
+    /// The sizes as logged, e.g. `1.2kB`, and their number of bytes.
+    request_size: Option<&'a str>,
+    response_size: Option<&'a str>,
+    request_bytes: Option<u64>,
+    response_bytes: Option<u64>,
+    /// In RFC 3339, in the timezone of `--timezone`.
+    start_at: String,
+    /// Offset from the start of the first span.
+    start_offset_ms: f64,
+    duration_ms: f64,
+    request_log_line: usize,
+    response_log_line: Option<usize>,
+    errcode: Option<&'a str>,
+    error_message: Option<&'a str>,
+    /// The transport error of a span failed without a response.
+    error: Option<&'a str>,
+    retries: u32,
+    /// The previous attempt of a retry, see [`crate::anomalies`].
+    retry_of: Option<RequestId>,
+    retry_after_ms: Option<i64>,
+    timeout_ms: Option<i64>,
+    server_timing: Option<String>,
+    server_duration_ms: Option<f64>,
+    pos: Option<&'a str>,
+    pos_stalled: bool,
+    pos_reset: bool,
+    txn_id: Option<&'a str>,
+    iteration: u32,
+    parent: Option<&'a str>,
+    source: Option<&'a str>,
+    /// The number of the process of the app, from 1.
+    process: usize,
+    restarted_as: Option<&'a str>,
+    app_state: Option<&'static str>,
+    /// E.g. `stuck-sync`, see [`crate::anomalies`].
+    anomalies: Vec<String>,
+    warnings: Vec<String>,
+    frames: Vec<String>,
+    intermediary_headers: BTreeMap<&'static str, &'a str>,
+    fields: &'a BTreeMap<String, String>,
+    connection_reused: Option<bool>,
+    dns_ms: Option<f64>,
+    connect_ms: Option<f64>,
+    tls_ms: Option<f64>,
+    ttfb_ms: Option<f64>,
+    request_body: Option<&'a str>,
+    response_body: Option<&'a str>,
+}
+
+/// Export the spans, in their order. `smallest_start_at` is the origin of
+/// the offsets, in milliseconds, and the dates are in `timezone`.
+pub fn to_json(
+    spans: &[(&ConnectionId, RequestId, &Span)],
+    smallest_start_at: i64,
+    timezone: FixedOffset,
+    anomalies: &Anomalies<'_>,
+    meta: &Meta<'_>,
+    summaries: &Summaries<'_>,
+) -> String {
+    let spans = spans
+        .iter()
+        .map(|(connection_id, request_id, span)| Record {
+            connection_id,
+            request_id: *request_id,
+            method: &span.method,
+            uri: &span.uri,
+            domain: span.domain(),
+            path: span.path(),
+            endpoint: span.endpoint(),
+            status: span.status,
+            status_family: span.status_family(),
+            request_size: span.request_size.as_deref(),
+            response_size: span.response_size.as_deref(),
+            request_bytes: span.request_size.as_ref().and_then(Size::bytes),
+            response_bytes: span.response_size.as_ref().and_then(Size::bytes),
+            start_at: span
+                .start_at
+                .with_timezone(&timezone)
+                .to_rfc3339_opts(SecondsFormat::Micros, false),
+            start_offset_ms: duration::offset_in_milliseconds(span.start_at, smallest_start_at),
+            duration_ms: duration::to_milliseconds(span.duration),
+            request_log_line: span.request_log_line,
+            response_log_line: span.response_log_line,
+            errcode: span.errcode.as_deref(),
+            error_message: span.error_message.as_deref(),
+            error: span.error.as_deref(),
+            retries: span.retries,
+            retry_of: anomalies
+                .retry_of(connection_id, *request_id)
+                .map(|retry| retry.previous),
+            retry_after_ms: span
+                .retry_after
+                .as_ref()
+                .map(|retry_after| retry_after.delay(span).num_milliseconds()),
+            timeout_ms: span.timeout().map(|timeout| timeout.num_milliseconds()),
+            server_timing: (!span.server_timing.is_empty())
+                .then(|| server_timing::to_header(&span.server_timing)),
+            server_duration_ms: span.server_duration(),
+            pos: span.pos.as_deref(),
+            pos_stalled: span.pos_stalled,
+            pos_reset: span.pos_reset,
+            txn_id: span.txn_id.as_deref(),
+            iteration: span.iteration,
+            parent: span.parent.as_deref(),
+            source: span.source.as_deref(),
+            process: span.process_nth,
+            restarted_as: span.restarted_as.as_deref(),
+            app_state: span.app_state.as_ref().map(lifecycle::State::as_str),
+            anomalies: anomalies
+                .marks(connection_id, *request_id)
+                .split_whitespace()
+                .map(str::to_owned)
+                .collect(),
+            warnings: span.warnings.iter().map(ToString::to_string).collect(),
+            frames: span.frames.iter().map(ToString::to_string).collect(),
+            intermediary_headers: span
+                .intermediary
+                .values
+                .iter()
+                .map(|(name, value)| (*name, value.as_str()))
+                .collect(),
+            fields: &span.fields,
+            connection_reused: span.setup.reused,
+            dns_ms: span.setup.dns,
+            connect_ms: span.setup.connect,
+            tls_ms: span.setup.tls,
+            ttfb_ms: span.setup.ttfb,
+            request_body: span.bodies.request.as_deref(),
+            response_body: span.bodies.response.as_deref(),
+        })
+        .collect();
+
+    serde_json::to_string_pretty(&Export {
+        schema: Schema {
+            version: SCHEMA_VERSION,
+        },
+        meta,
+        summaries,
+        spans,
+    })
+    .expect("Failed to serialize the export")
+}
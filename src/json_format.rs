@@ -0,0 +1,180 @@
+//! Read the log lines in the JSON format of `tracing-subscriber`, i.e.
+//! `tracing_subscriber::fmt().json()`, as emitted by some collectors, with the
+//! fields of the events nested or flattened.
+//!
+//! A JSON line is rewritten in the text format of the SDK, e.g.
+//! `2024-06-01T09:13:19.035Z DEBUG matrix_sdk::http_client: Sending request |
+//! spans: root > send{request_id="REQ-1" method=GET uri="…"}`, so that it is
+//! parsed like any other line.
+
+use serde_json::{Map, Value};
+
+/// Fields of the `send` span, in the order of the text format. The JSON
+/// objects aren't ordered.
+const SEND_FIELDS: [&str; 6] = [
+    "request_id",
+    "method",
+    "uri",
+    "request_size",
+    "status",
+    "response_size",
+];
+
+/// Keys of an event which aren't fields, when the fields are flattened in the
+/// event, i.e. with `flatten_event(true)`.
+const EVENT_KEYS: [&str; 9] = [
+    "timestamp",
+    "level",
+    "target",
+    "filename",
+    "line_number",
+    "threadName",
+    "threadId",
+    "span",
+    "spans",
+];
+
+/// Rewrite a JSON line in the text format, or `None` if it isn't a JSON
+/// event of `tracing-subscriber`.
+pub fn to_text(line: &str) -> Option<String> {
+    if !line.trim_start().starts_with('{') {
+        return None;
+    }
+
+    let Value::Object(event) = serde_json::from_str::<Value>(line).ok()? else {
+        return None;
+    };
+    let timestamp = event.get("timestamp")?.as_str()?;
+    let level = event.get("level")?.as_str()?;
+    let target = event.get("target")?.as_str()?;
+
+    let mut text = format!("{timestamp} {level} {target}:");
+
+    let fields = match event.get("fields") {
+        Some(Value::Object(fields)) => fields,
+        _ => &event,
+    };
+
+    if let Some(message) = fields.get("message").and_then(Value::as_str) {
+        text.push(' ');
+        text.push_str(message);
+    }
+
+    for (name, value) in fields
+        .iter()
+        .filter(|(name, _)| *name != "message" && !EVENT_KEYS.contains(&name.as_str()))
+    {
+        text.push_str(&format!(" {name}={}", value_to_text(value)));
+    }
+
+    if let (Some(filename), Some(line_number)) = (
+        event.get("filename").and_then(Value::as_str),
+        event.get("line_number"),
+    ) {
+        text.push_str(&format!(" | {filename}:{line_number}"));
+    }
+
+    // The list of the spans, from the root, is optional: the current span is
+    // the only one known without it.
+    let spans = match (event.get("spans"), event.get("span")) {
+        (Some(Value::Array(spans)), _) => spans.iter().filter_map(Value::as_object).collect(),
+        (None, Some(Value::Object(span))) => vec![span],
+        _ => Vec::new(),
+    };
+
+    if !spans.is_empty() {
+        text.push_str(" | spans: ");
+        text.push_str(
+            &spans
+                .into_iter()
+                .map(span_to_text)
+                .collect::<Vec<_>>()
+                .join(" > "),
+        );
+    }
+
+    Some(text)
+}
+
+/// Rewrite a span, e.g. `sync_once{conn_id="room-list"}`.
+fn span_to_text(span: &Map<String, Value>) -> String {
+    let name = span.get("name").and_then(Value::as_str).unwrap_or_default();
+    let mut fields = span
+        .iter()
+        .filter(|(field, _)| *field != "name")
+        .collect::<Vec<_>>();
+
+    if name == "send" {
+        fields.sort_by_key(|(field, _)| {
+            SEND_FIELDS
+                .iter()
+                .position(|send_field| send_field == field)
+                .unwrap_or(SEND_FIELDS.len())
+        });
+    }
+
+    if fields.is_empty() {
+        return name.to_owned();
+    }
+
+    format!(
+        "{name}{{{}}}",
+        fields
+            .into_iter()
+            .map(|(field, value)| format!("{field}={}", value_to_text(value)))
+            .collect::<Vec<_>>()
+            .join(" ")
+    )
+}
+
+/// Rewrite the value of a field. A string is quoted, unless it is the debug
+/// representation of a structure, e.g. of the headers, which the text format
+/// shows as is.
+fn value_to_text(value: &Value) -> String {
+    match value {
+        Value::String(string) if string.starts_with('{') || string.starts_with('[') => {
+            string.clone()
+        }
+        Value::String(string) => format!("{string:?}"),
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_text() {
+        assert_eq!(
+            to_text(
+                r#"{"timestamp":"2024-06-01T10:00:00.250000Z","level":"DEBUG","fields":{"message":"Got response"},"target":"matrix_sdk::http_client","filename":"crates/matrix-sdk/src/http_client/mod.rs","line_number":190,"span":{"method":"GET","name":"send","request_id":"REQ-3","status":200,"uri":"https://matrix.example.org/_matrix/client/v3/rooms"},"spans":[{"name":"root"},{"conn_id":"room-list","name":"sync_once"},{"method":"GET","name":"send","request_id":"REQ-3","status":200,"uri":"https://matrix.example.org/_matrix/client/v3/rooms"}]}"#
+            )
+            .as_deref(),
+            Some(
+                r#"2024-06-01T10:00:00.250000Z DEBUG matrix_sdk::http_client: Got response | crates/matrix-sdk/src/http_client/mod.rs:190 | spans: root > sync_once{conn_id="room-list"} > send{request_id="REQ-3" method="GET" uri="https://matrix.example.org/_matrix/client/v3/rooms" status=200}"#
+            )
+        );
+        assert_eq!(
+            to_text(
+                r#"{"timestamp":"2024-06-01T10:00:01Z","level":"WARN","fields":{"message":"Server returned an error","errcode":"M_LIMIT_EXCEEDED","retry_after_ms":2000},"target":"matrix_sdk::http_client"}"#
+            )
+            .as_deref(),
+            Some(
+                r#"2024-06-01T10:00:01Z WARN matrix_sdk::http_client: Server returned an error errcode="M_LIMIT_EXCEEDED" retry_after_ms=2000"#
+            )
+        );
+        // With `flatten_event(true)`.
+        assert_eq!(
+            to_text(
+                r#"{"timestamp":"2024-06-01T10:00:01Z","level":"DEBUG","message":"Got response","target":"matrix_sdk::http_client","threadId":"ThreadId(2)","spans":[{"method":"GET","name":"send","request_id":"REQ-4","status":404,"uri":"https://matrix.example.org/_matrix/client/v3/profile"}]}"#
+            )
+            .as_deref(),
+            Some(
+                r#"2024-06-01T10:00:01Z DEBUG matrix_sdk::http_client: Got response | spans: send{request_id="REQ-4" method="GET" uri="https://matrix.example.org/_matrix/client/v3/profile" status=404}"#
+            )
+        );
+        assert_eq!(to_text("{not json"), None);
+        assert_eq!(to_text("2024-06-01T10:00:01Z INFO app: {}"), None);
+    }
+}
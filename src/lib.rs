@@ -0,0 +1,686 @@
+//! Analyse the network traffic of the Matrix Rust SDK from its logs.
+//!
+//! The logs are parsed into spans, one per request, grouped by connection,
+//! i.e. by the `conn_id` of the `sync_once` span the request is sent from:
+//!
+//! ```no_run
+//! use std::{fs::File, io::BufReader};
+//!
+//! let log_file = File::open("console.log").unwrap();
+//! let session = network_viewer::parse_log("console.log", BufReader::new(log_file)).unwrap();
+//!
+//! for (connection_id, spans) in &session.spans {
+//!     println!("{connection_id}: {} requests", spans.len());
+//! }
+//!
+//! std::fs::write("report.html", network_viewer::render_html(&session)).unwrap();
+//! ```
+//!
+//! To parse the logs as they are written, [`LogParser`] parses one line at a
+//! time, and [`parse_lines`] yields the spans as their responses are parsed:
+//!
+//! ```
+//! let lines = [
+//!     r#"2024-06-01T10:00:00Z DEBUG matrix_sdk::http_client: Sending request | spans: root > sync_once{conn_id="room-list"} > send{request_id="REQ-1" method=POST uri="https://example.org/_matrix/client/v3/sync"}"#,
+//!     r#"2024-06-01T10:00:01Z DEBUG matrix_sdk::http_client: Got response | spans: root > sync_once{conn_id="room-list"} > send{request_id="REQ-1" method=POST uri="https://example.org/_matrix/client/v3/sync" status=200}"#,
+//! ];
+//!
+//! let spans = network_viewer::parse_lines(lines).collect::<Vec<_>>();
+//!
+//! assert_eq!(spans[0].0, "room-list");
+//! assert_eq!(spans[0].1.status(), Some(200));
+//! ```
+//!
+//! To capture the logs of an app in-process, without a log file, see
+//! [`Capture`].
+//!
+//! The `network-viewer` binary is a command line interface over the library,
+//! see [`cli`].
+
+use std::{collections::BTreeMap, io::BufRead, iter};
+
+use ada_url::{Url, UrlSearchParams};
+use chrono::{DateTime, FixedOffset, TimeDelta};
+
+mod anomalies;
+mod bandwidth;
+mod bodies;
+mod buckets;
+mod capture;
+mod check;
+pub mod cli;
+mod cohort;
+mod columns;
+mod concurrency;
+mod conditions;
+mod config;
+mod connections;
+mod context;
+mod csv;
+mod dataset;
+mod dedup;
+mod diff;
+mod duration;
+mod duration_bands;
+mod endpoint;
+mod endpoint_stats;
+mod errcodes;
+pub mod error;
+mod explain;
+mod expression;
+mod filters;
+mod format;
+mod frames;
+mod gaps;
+mod grafana;
+mod har;
+mod html;
+mod human;
+mod import;
+mod influx;
+mod initial_sync;
+mod intermediary;
+mod interrupt;
+mod iterations;
+mod json;
+mod json_format;
+mod lifecycle;
+mod listen;
+mod media;
+mod merge;
+mod meta;
+mod otlp;
+mod parquet;
+mod parser;
+mod pattern;
+mod percentiles;
+mod progress;
+mod rate_limits;
+mod redact;
+mod retry_after;
+mod rooms;
+mod serve;
+mod server_timing;
+mod setup;
+mod size;
+mod slow;
+mod source;
+mod split;
+mod stats;
+mod statsd;
+mod status;
+mod status_matrix;
+mod stream;
+mod summary;
+mod sync_overhead;
+mod template;
+mod term;
+mod ticks;
+mod timestamp;
+mod traffic;
+mod traffic_class;
+mod tui;
+mod warnings;
+mod watch;
+mod xlsx;
+mod zoom;
+
+pub use capture::Capture;
+pub use error::Error;
+
+/// ID of a connection: the `conn_id` of the `sync_once` span a request is sent
+/// from.
+pub type ConnectionId = String;
+
+/// Connection ID used for requests sent outside of a `sync_once` span.
+pub const NO_CONNECTION_ID: &str = "(none)";
+
+/// ID of a request, unique within a connection, e.g. `5` for `REQ-5`.
+pub type RequestId = u32;
+
+/// The spans, by connection then by request.
+pub type Spans = BTreeMap<ConnectionId, BTreeMap<RequestId, Span>>;
+
+/// A parsed log: its spans, and the time range they cover.
+pub struct Session {
+    /// Name of the log, displayed in the report.
+    pub name: String,
+    pub spans: Spans,
+    /// Start of the first span, if any.
+    pub start_at: Option<DateTime<FixedOffset>>,
+    /// End of the last span, if any.
+    pub end_at: Option<DateTime<FixedOffset>>,
+    lifecycle_events: Vec<lifecycle::Event>,
+}
+
+/// Parse the log lines read from `reader`. A line which isn't valid UTF-8 is
+/// skipped.
+pub fn parse_log<R: BufRead>(name: &str, reader: R) -> Result<Session, Error> {
+    let mut log_parser = LogParser::new();
+
+    source::read_all_lines(reader, |line, _| {
+        log_parser.parser.parse(line, None);
+    })
+    .map_err(Error::io(name))?;
+
+    Ok(log_parser.into_session(name))
+}
+
+/// Parse log lines, and yield the spans as their responses are parsed, with
+/// the ID of their connection.
+///
+/// The spans without a response are never completed, hence never yielded:
+/// see [`LogParser`] to get them too.
+pub fn parse_lines<I>(lines: I) -> impl Iterator<Item = (ConnectionId, Span)>
+where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+{
+    let mut lines = lines.into_iter();
+    let mut log_parser = LogParser::new();
+
+    iter::from_fn(move || {
+        loop {
+            let line = lines.next()?;
+
+            if let Some((connection_id, span)) = log_parser.parse_line(line.as_ref()) {
+                return Some((connection_id.clone(), span.clone()));
+            }
+        }
+    })
+}
+
+/// A parser of log lines, one at a time, so that the logs can be streamed.
+pub struct LogParser {
+    parser: parser::Parser,
+}
+
+impl Default for LogParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LogParser {
+    pub fn new() -> Self {
+        Self {
+            parser: parser::Parser::new(),
+        }
+    }
+
+    /// Parse the next log line. Returns the span completed by this line, i.e.
+    /// if it's a response, with the ID of its connection.
+    pub fn parse_line(&mut self, line: &str) -> Option<(&ConnectionId, &Span)> {
+        self.parser.parse_line(line, None)?;
+        let (connection_id, request_id) = self.parser.latest_response()?;
+        let (connection_id, spans) = self.parser.spans.get_key_value(connection_id)?;
+
+        Some((connection_id, spans.get(&request_id)?))
+    }
+
+    /// Get the spans parsed so far, including the ones without a response.
+    pub fn spans(&self) -> &Spans {
+        &self.parser.spans
+    }
+
+    /// Count the lines and the spans parsed so far.
+    pub fn summary(&self) -> ParseSummary {
+        let spans = self.parser.spans.values().flat_map(BTreeMap::values);
+
+        ParseSummary {
+            number_of_analysed_lines: self.parser.number_of_analysed_lines,
+            number_of_matched_lines: self.parser.number_of_matched_lines,
+            number_of_spans: spans.clone().count(),
+            number_of_pending_spans: spans.filter(|span| span.is_pending()).count(),
+            number_of_restarts: self.parser.number_of_restarts(),
+        }
+    }
+
+    /// Get the session parsed so far, named `name`, and keep parsing.
+    pub fn snapshot(&self, name: &str) -> Session {
+        let (start_at, end_at) = filters::time_range(&self.parser.spans).unzip();
+        let mut lifecycle_events = self.parser.lifecycle_events.clone();
+        lifecycle_events.sort_by_key(|event| event.at);
+
+        Session {
+            name: name.to_owned(),
+            spans: self.parser.spans.clone(),
+            start_at,
+            end_at,
+            lifecycle_events,
+        }
+    }
+
+    /// End the parsing, and get the session named `name`.
+    pub fn into_session(mut self, name: &str) -> Session {
+        let (start_at, end_at) = filters::time_range(&self.parser.spans).unzip();
+        self.parser.lifecycle_events.sort_by_key(|event| event.at);
+
+        Session {
+            name: name.to_owned(),
+            spans: self.parser.spans,
+            start_at,
+            end_at,
+            lifecycle_events: self.parser.lifecycle_events,
+        }
+    }
+}
+
+/// The counts of a parse, see [`LogParser::summary`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ParseSummary {
+    pub number_of_analysed_lines: usize,
+    pub number_of_matched_lines: usize,
+    pub number_of_spans: usize,
+    /// Number of spans without a response.
+    pub number_of_pending_spans: usize,
+    /// Number of restarts of the app detected in the logs.
+    pub number_of_restarts: usize,
+}
+
+/// Render the HTML report of a session, with the default options of the
+/// binary. The spans are rendered as is: the restarted connections aren't
+/// merged, and the URIs aren't redacted.
+pub fn render_html(session: &Session) -> String {
+    cli::render_html(
+        session.spans.clone(),
+        &session.lifecycle_events,
+        &session.name,
+    )
+}
+
+/// A request, from the log line sending it to the log line of its response.
+#[derive(Clone, Debug)]
+pub struct Span {
+    pub(crate) status: Option<u16>,
+    pub(crate) method: String,
+    pub(crate) uri: String,
+    pub(crate) request_size: Option<size::Size>,
+    pub(crate) response_size: Option<size::Size>,
+    pub(crate) start_at: DateTime<FixedOffset>,
+    pub(crate) duration: TimeDelta,
+    pub(crate) request_log_line: usize,
+    pub(crate) response_log_line: Option<usize>,
+    pub(crate) request_context: Option<context::Window>,
+    pub(crate) response_context: Option<context::Window>,
+    /// The Matrix error code of a failed response, e.g. `M_LIMIT_EXCEEDED`.
+    pub(crate) errcode: Option<String>,
+    /// The error message of a failed response, truncated.
+    pub(crate) error_message: Option<String>,
+    /// The `WARN` and `ERROR` lines about this span.
+    pub(crate) warnings: Vec<warnings::Warning>,
+    /// The metrics of the `Server-Timing` header of the response, if any.
+    pub(crate) server_timing: Vec<server_timing::Metric>,
+    /// How long the server has asked the client to wait before retrying.
+    pub(crate) retry_after: Option<retry_after::RetryAfter>,
+    /// The state the app was in when the request has started, if lifecycle
+    /// events are logged.
+    pub(crate) app_state: Option<lifecycle::State>,
+    /// The response headers telling about a proxy or a CDN, if logged.
+    pub(crate) intermediary: intermediary::Headers,
+    /// The setup of the connection, if logged at the trace level.
+    pub(crate) setup: setup::Setup,
+    /// The bodies of the request and of the response, if logged at the trace
+    /// level and captured with `--capture-bodies`.
+    pub(crate) bodies: bodies::Bodies,
+    /// Number of the process of the app which has sent the request, from 1:
+    /// the request IDs restart with the process.
+    pub(crate) process_nth: usize,
+    /// On the first span of a connection merged into the one it continues,
+    /// see [`merge`], the original ID of the connection.
+    pub(crate) restarted_as: Option<ConnectionId>,
+    /// The `pos` query parameter of a sliding sync, kept apart from the URI
+    /// so that it can be compared once the URI is redacted.
+    pub(crate) pos: Option<String>,
+    /// Whether the `pos` of a sliding sync is the same as the one of the
+    /// previous request of the connection, i.e. the server hasn't bumped it.
+    pub(crate) pos_stalled: bool,
+    /// Whether a sliding sync has no `pos` while the previous request of the
+    /// connection had one, i.e. the client has restarted the sliding sync
+    /// from scratch, e.g. after an `M_UNKNOWN_POS`.
+    pub(crate) pos_reset: bool,
+    /// The `txn_id` field of the log lines, if any.
+    pub(crate) txn_id: Option<String>,
+    /// The captures of a pattern of `--config` which aren't read by the
+    /// parser, by name, see [`pattern::Pattern`].
+    pub(crate) fields: BTreeMap<String, String>,
+    /// The error of a request which has failed without a response, e.g. a DNS
+    /// failure or a connection reset, or of its latest failed attempt.
+    pub(crate) error: Option<String>,
+    /// Number of times the request has been retried by the SDK after a
+    /// transport error.
+    pub(crate) retries: u32,
+    /// Iteration of the sync loop of the connection, i.e. of its `sync_once`
+    /// span, from 1, or 0 if the request is sent outside of a sync loop.
+    pub(crate) iteration: u32,
+    /// Name of the span the request is sent from, e.g. `sync_once` or
+    /// `download_media`, if logged.
+    pub(crate) parent: Option<String>,
+    /// The chain of the tracing spans the request is sent from, from the
+    /// root to the parent, if logged.
+    pub(crate) frames: Vec<frames::Frame>,
+    /// The log file the request is logged in, if the logs are read from
+    /// several files.
+    pub(crate) source: Option<String>,
+}
+
+impl Span {
+    /// Get the status of the response, if any.
+    pub fn status(&self) -> Option<u16> {
+        self.status
+    }
+
+    pub fn method(&self) -> &str {
+        &self.method
+    }
+
+    pub fn uri(&self) -> &str {
+        &self.uri
+    }
+
+    /// Get the size of the request as logged, e.g. `1.2 KiB`, if any.
+    pub fn request_size(&self) -> Option<&str> {
+        self.request_size.as_deref()
+    }
+
+    /// Get the size of the response as logged, e.g. `1.2 KiB`, if any.
+    pub fn response_size(&self) -> Option<&str> {
+        self.response_size.as_deref()
+    }
+
+    /// Get the number of bytes of the request, if its size is logged.
+    pub fn request_bytes(&self) -> Option<u64> {
+        self.request_size.as_ref().and_then(size::Size::bytes)
+    }
+
+    /// Get the number of bytes of the response, if its size is logged.
+    pub fn response_bytes(&self) -> Option<u64> {
+        self.response_size.as_ref().and_then(size::Size::bytes)
+    }
+
+    pub fn start_at(&self) -> DateTime<FixedOffset> {
+        self.start_at
+    }
+
+    /// Get the duration, from the request to its response or to its
+    /// cancellation.
+    pub fn duration(&self) -> TimeDelta {
+        self.duration
+    }
+
+    /// Get the Matrix error code of a failed response, e.g.
+    /// `M_LIMIT_EXCEEDED`.
+    pub fn errcode(&self) -> Option<&str> {
+        self.errcode.as_deref()
+    }
+
+    /// Get the error message of a failed response, truncated.
+    pub fn error_message(&self) -> Option<&str> {
+        self.error_message.as_deref()
+    }
+
+    /// Get the error of a request which has failed without a response, or of
+    /// its latest failed attempt if it has been retried.
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    /// Get the number of times the request has been retried after a transport
+    /// error.
+    pub fn retries(&self) -> u32 {
+        self.retries
+    }
+
+    /// Get the iteration of the sync loop the request is sent from, from 1, or
+    /// 0 if it's sent outside of a sync loop.
+    pub fn iteration(&self) -> u32 {
+        self.iteration
+    }
+
+    /// Get the name of the span the request is sent from, e.g. `sync_once`,
+    /// `download_media` or `root`, if logged.
+    pub fn parent(&self) -> Option<&str> {
+        self.parent.as_deref()
+    }
+
+    /// Get the log file the request is logged in, if the logs are read from
+    /// several files.
+    pub fn source(&self) -> Option<&str> {
+        self.source.as_deref()
+    }
+
+    /// Whether this span is about a sync, i.e. its path ends with `/sync`.
+    pub fn is_sync(&self) -> bool {
+        Url::parse(&self.uri, None).is_ok_and(|uri| uri.pathname().ends_with("/sync"))
+    }
+
+    /// Get the domain of the URI.
+    pub fn domain(&self) -> String {
+        Url::parse(&self.uri, None)
+            .map(|uri| {
+                let components = uri.components();
+
+                self.uri[components.host_start as usize..components.host_end as usize].to_owned()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Get the path of the URI, including its query.
+    pub fn path(&self) -> String {
+        self.path_start()
+            .map(|pathname_start| self.uri[pathname_start as usize..].to_owned())
+            .unwrap_or_default()
+    }
+
+    /// Get the offset of the path in the URI, if it can be parsed.
+    pub fn path_start(&self) -> Option<u32> {
+        Url::parse(&self.uri, None)
+            .ok()
+            .and_then(|uri| uri.components().pathname_start)
+    }
+
+    /// Get the kind of endpoint targeted by this span.
+    fn kind(&self) -> endpoint::Kind {
+        endpoint::Kind::of(&self.uri)
+    }
+
+    /// Get the category of the endpoint targeted by this span, e.g. keys
+    /// query.
+    fn category(&self) -> endpoint::Category {
+        endpoint::Category::of(&self.uri)
+    }
+
+    /// Get the class of traffic of this span, e.g. client-server API call.
+    fn traffic_class(&self) -> traffic_class::TrafficClass {
+        traffic_class::TrafficClass::of(&self.uri)
+    }
+
+    /// Get the ID of the room targeted by this span, if its path has one.
+    pub fn room_id(&self) -> Option<String> {
+        endpoint::room_id(&self.uri)
+    }
+
+    /// Get the endpoint targeted by this span: its method and the template of
+    /// its path, e.g. `GET /_matrix/client/v3/rooms/{roomId}/messages`.
+    pub fn endpoint(&self) -> String {
+        format!("{} {}", self.method, endpoint::template(&self.uri))
+    }
+
+    /// Whether the request has never received a response, e.g. because the
+    /// app has been killed or the network has dropped.
+    pub fn is_pending(&self) -> bool {
+        self.response_log_line.is_none()
+    }
+
+    /// Get the family of the status, e.g. `4` for `429`, `pending` if the span
+    /// has no response, or `cancelled` if its response has no status.
+    pub fn status_family(&self) -> String {
+        if self.is_pending() {
+            return "pending".to_owned();
+        }
+
+        if self.has_failed() {
+            return "error".to_owned();
+        }
+
+        self.status
+            .map(|status| (if status > 0 { status / 100 } else { 0 }).to_string())
+            .unwrap_or_else(|| "cancelled".to_owned())
+    }
+
+    /// Whether the request has failed without a response, e.g. because of a
+    /// DNS failure, see [`Self::error`].
+    pub fn has_failed(&self) -> bool {
+        self.status.is_none() && self.response_log_line.is_some() && self.error.is_some()
+    }
+
+    /// Get the value of the query parameter `name` of the URI, if any.
+    pub fn query_parameter(&self, name: &str) -> Option<String> {
+        let uri = Url::parse(&self.uri, None).ok()?;
+        let search_params = UrlSearchParams::parse(uri.search().trim_start_matches('?')).ok()?;
+
+        search_params.get(name).map(ToOwned::to_owned)
+    }
+
+    /// Get the `timeout` query parameter of a long-poll, if any.
+    pub fn timeout(&self) -> Option<TimeDelta> {
+        self.query_parameter("timeout")?
+            .parse()
+            .ok()
+            .map(TimeDelta::milliseconds)
+    }
+
+    /// Get the time spent by the server, in milliseconds, according to the
+    /// `Server-Timing` header of the response.
+    ///
+    /// The server can't have spent more time than observed by the client: it
+    /// would mean that the metrics aren't about the whole request.
+    pub fn server_duration(&self) -> Option<f64> {
+        self.response_log_line?;
+
+        server_timing::server_duration(&self.server_timing)
+            .filter(|server_duration| *server_duration <= duration::to_milliseconds(self.duration))
+    }
+
+    /// Whether the span has received a successful response.
+    pub fn is_successful(&self) -> bool {
+        self.status.is_some_and(|status| status / 100 == 2)
+    }
+}
+
+#[cfg(test)]
+impl Span {
+    /// Build a span of a request to `uri`, starting at
+    /// `2024-06-01T09:13:19.035Z`, for the tests.
+    fn for_tests(uri: &str, status: Option<u16>, duration: TimeDelta) -> Self {
+        Self {
+            status,
+            method: "POST".to_owned(),
+            uri: uri.to_owned(),
+            request_size: None,
+            response_size: None,
+            start_at: DateTime::parse_from_rfc3339("2024-06-01T09:13:19.035Z")
+                .expect("The date is valid"),
+            duration,
+            request_log_line: 1,
+            response_log_line: status.map(|_| 2),
+            request_context: None,
+            response_context: None,
+            errcode: None,
+            error_message: None,
+            warnings: Vec::new(),
+            server_timing: Vec::new(),
+            retry_after: None,
+            app_state: None,
+            intermediary: intermediary::Headers::default(),
+            setup: setup::Setup::default(),
+            bodies: bodies::Bodies::default(),
+            process_nth: 1,
+            restarted_as: None,
+            pos: None,
+            pos_stalled: false,
+            pos_reset: false,
+            txn_id: None,
+            fields: BTreeMap::new(),
+            error: None,
+            retries: 0,
+            iteration: 0,
+            parent: None,
+            frames: Vec::new(),
+            source: None,
+        }
+    }
+}
+
+/// Builder of the spans of the tests, grouped by connection like the spans of
+/// a log.
+#[cfg(test)]
+#[derive(Default)]
+struct SpansBuilder {
+    spans: Spans,
+}
+
+#[cfg(test)]
+impl SpansBuilder {
+    /// Add a request to `uri` on `connection_id`, see [`Span::for_tests`],
+    /// starting `start_at` milliseconds later and lasting `duration`
+    /// milliseconds. The span is returned to set its other fields.
+    fn span(
+        &mut self,
+        connection_id: &str,
+        request_id: RequestId,
+        uri: &str,
+        status: Option<u16>,
+        start_at: i64,
+        duration: i64,
+    ) -> &mut Span {
+        let mut span = Span::for_tests(uri, status, TimeDelta::milliseconds(duration));
+        span.start_at += TimeDelta::milliseconds(start_at);
+
+        let spans = self.spans.entry(connection_id.to_owned()).or_default();
+        spans.insert(request_id, span);
+
+        spans
+            .get_mut(&request_id)
+            .expect("The span has just been inserted")
+    }
+
+    fn build(self) -> Spans {
+        self.spans
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_log() {
+        let session = parse_log(
+            "session.log",
+            include_bytes!("../fixtures/session.log").as_slice(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            session.spans.keys().collect::<Vec<_>>(),
+            [NO_CONNECTION_ID, "encryption", "room-list"]
+        );
+        assert_eq!(
+            session.start_at.map(|start_at| start_at.to_rfc3339()),
+            Some("2024-06-01T10:00:00+00:00".to_owned())
+        );
+        assert!(session.end_at > session.start_at);
+
+        let span = &session.spans[NO_CONNECTION_ID][&1];
+
+        assert_eq!(span.method(), "GET");
+        assert_eq!(span.status(), Some(200));
+        assert_eq!(span.response_bytes(), Some(1_200));
+
+        let html = render_html(&session);
+
+        assert!(html.starts_with("<!doctype html>"));
+        assert!(html.contains("<code>session.log</code>"));
+    }
+}
@@ -0,0 +1,292 @@
+//! Capture the app lifecycle events, i.e. the foreground and background
+//! transitions and the push wake-ups, to tell in which state of the app each
+//! span has started.
+//!
+//! The session events of the SDK, i.e. the client being built, the session
+//! being restored, the access token being refreshed and the soft logouts,
+//! are captured the same way, and marked on the timeline too, but they don't
+//! change the state of the app.
+//!
+//! The events are optional: apps which don't log them get no markers and no
+//! app state.
+
+use std::{collections::BTreeMap, fmt};
+
+use chrono::{DateTime, FixedOffset};
+use regex::Regex;
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::{Spans, human, stats};
+
+/// State of the app.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum State {
+    Foreground,
+    Background,
+    PushWakeup,
+    ClientBuilt,
+    SessionRestored,
+    TokenRefreshed,
+    SoftLogout,
+}
+
+impl State {
+    /// All the states, in the order of their default patterns.
+    const ALL: [Self; 7] = [
+        Self::Foreground,
+        Self::Background,
+        Self::PushWakeup,
+        Self::ClientBuilt,
+        Self::SessionRestored,
+        Self::TokenRefreshed,
+        Self::SoftLogout,
+    ];
+
+    fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|state| state.as_str() == name)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Foreground => "foreground",
+            Self::Background => "background",
+            Self::PushWakeup => "push-wakeup",
+            Self::ClientBuilt => "client-built",
+            Self::SessionRestored => "session-restored",
+            Self::TokenRefreshed => "token-refreshed",
+            Self::SoftLogout => "soft-logout",
+        }
+    }
+
+    /// Whether the event is a state of the app, rather than an event of its
+    /// session.
+    pub fn is_app_state(&self) -> bool {
+        matches!(self, Self::Foreground | Self::Background | Self::PushWakeup)
+    }
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str(self.as_str())
+    }
+}
+
+/// A regex matching the lines of a lifecycle event.
+#[derive(Clone)]
+pub struct Pattern {
+    state: State,
+    regex: Regex,
+}
+
+impl Pattern {
+    /// Parse a pattern like `background=applicationDidEnterBackground`.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let (state, regex) = value
+            .split_once('=')
+            .ok_or_else(|| format!("`{value}` isn't like `<state>=<regex>`"))?;
+        let state = State::parse(state).ok_or_else(|| {
+            format!(
+                "Unknown app state `{state}`; valid states are {}",
+                State::ALL.map(|state| format!("`{state}`")).join(", ")
+            )
+        })?;
+        let regex =
+            Regex::new(regex).map_err(|error| format!("Invalid regex for `{state}`: {error}"))?;
+
+        Ok(Self { state, regex })
+    }
+
+    /// Get the patterns for `custom` states, plus the default patterns of
+    /// the other states, which match the lines of Element X Android and iOS,
+    /// and the messages of the SDK for the session events.
+    pub fn with_defaults(custom: Vec<Self>) -> Vec<Self> {
+        let defaults = [
+            (
+                State::Foreground,
+                r"(?i)\b(?:onResume|applicationDidBecomeActive|app(?:lication)? (?:entered|moved to|is in the) foreground)\b",
+            ),
+            (
+                State::Background,
+                r"(?i)\b(?:onPause|applicationDidEnterBackground|app(?:lication)? (?:entered|moved to|is in the) background)\b",
+            ),
+            (
+                State::PushWakeup,
+                r"(?i)\b(?:onMessageReceived|didReceiveRemoteNotification|NotificationServiceExtension|push (?:notification )?received)\b",
+            ),
+            (
+                State::ClientBuilt,
+                r"(?i)\b(?:client initialized|client (?:has been )?built)\b",
+            ),
+            (
+                State::SessionRestored,
+                r"(?i)\b(?:session (?:has been )?restored|restored (?:the )?session)\b",
+            ),
+            (
+                State::TokenRefreshed,
+                r"(?i)\b(?:access token (?:has been )?refreshed|refreshed (?:the )?access token)\b",
+            ),
+            (
+                State::SoftLogout,
+                r"(?i)\b(?:soft_logout(?:: ?|=)true|soft logout)\b",
+            ),
+        ]
+        .into_iter()
+        .filter(|(state, _)| !custom.iter().any(|pattern| pattern.state == *state))
+        .map(|(state, regex)| Self {
+            state,
+            regex: Regex::new(regex).expect("Failed to build a default lifecycle regex"),
+        })
+        .collect::<Vec<_>>();
+
+        custom.into_iter().chain(defaults).collect()
+    }
+
+    /// Get the state of the event of a line, if it matches.
+    pub fn state_of(patterns: &[Self], line: &str) -> Option<State> {
+        patterns
+            .iter()
+            .find(|pattern| pattern.regex.is_match(line))
+            .map(|pattern| pattern.state)
+    }
+}
+
+/// A lifecycle event.
+#[derive(Clone, Debug, Serialize)]
+pub struct Event {
+    pub state: State,
+
+    /// Date of the event, serialized in milliseconds since the Unix epoch,
+    /// like the concurrency timeline.
+    #[serde(serialize_with = "serialize_timestamp")]
+    pub at: DateTime<FixedOffset>,
+
+    pub log_line: usize,
+}
+
+fn serialize_timestamp<S: Serializer>(
+    at: &DateTime<FixedOffset>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_i64(at.timestamp_millis())
+}
+
+/// Render the spans split by the state the app was in when they started, or
+/// nothing if no app state has been captured.
+pub fn to_html(spans: &Spans, events: &[Event]) -> String {
+    let number_of_events = events
+        .iter()
+        .filter(|event| event.state.is_app_state())
+        .count();
+
+    if number_of_events == 0 {
+        return String::new();
+    }
+
+    #[derive(Default)]
+    struct Aggregate {
+        requests: usize,
+        failures: usize,
+        durations: Vec<i64>,
+    }
+
+    let mut aggregates = BTreeMap::<Option<State>, Aggregate>::new();
+
+    for span in spans.values().flat_map(|spans| spans.values()) {
+        let aggregate = aggregates.entry(span.app_state).or_default();
+        aggregate.requests += 1;
+
+        if span.is_successful() {
+            aggregate.durations.push(span.duration.num_milliseconds());
+        } else {
+            aggregate.failures += 1;
+        }
+    }
+
+    let rows = aggregates
+        .into_iter()
+        .map(|(state, mut aggregate)| {
+            aggregate.durations.sort_unstable();
+
+            format!(
+                "      <tr>
+        <th scope=\"row\">{state}</th>
+        <td>{requests}</td>
+        <td>{failures}</td>
+        <td>{failure_rate}</td>
+        <td>{median}</td>
+      </tr>
+",
+                state = state.map_or("unknown", |state| state.as_str()),
+                requests = human::count(aggregate.requests),
+                failures = human::count(aggregate.failures),
+                failure_rate =
+                    human::percentage(aggregate.failures as f64, aggregate.requests as f64),
+                median = stats::percentile(&aggregate.durations, 50.)
+                    .map(human::milliseconds)
+                    .unwrap_or_default(),
+            )
+        })
+        .collect::<String>();
+
+    format!(
+        "  <h3>Requests per app state</h3>
+  <p>{number_of_events} lifecycle events captured. The state of a request is the one the app was in when it started; <em>unknown</em> is before the first event.</p>
+  <table class=\"app-states\">
+    <thead>
+      <tr>
+        <th scope=\"col\">App state</th>
+        <th scope=\"col\">Requests</th>
+        <th scope=\"col\">Failures</th>
+        <th scope=\"col\">Failure rate</th>
+        <th scope=\"col\">Median duration</th>
+      </tr>
+    </thead>
+    <tbody>
+{rows}    </tbody>
+  </table>
+",
+        number_of_events = human::count(number_of_events),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_of() {
+        let patterns = Pattern::with_defaults(vec![
+            Pattern::parse("soft-logout=logged out softly").unwrap(),
+        ]);
+        let state_of = |line| Pattern::state_of(&patterns, line);
+
+        assert_eq!(
+            state_of("2024-06-01T10:00:00Z INFO matrix_sdk::client: Client initialized"),
+            Some(State::ClientBuilt)
+        );
+        assert_eq!(
+            state_of("2024-06-01T10:00:01Z INFO app: Session restored for @alice:example.org"),
+            Some(State::SessionRestored)
+        );
+        assert_eq!(
+            state_of("2024-06-01T10:00:02Z DEBUG app: Access token refreshed"),
+            Some(State::TokenRefreshed)
+        );
+        assert_eq!(
+            state_of("2024-06-01T10:00:03Z WARN app: logged out softly"),
+            Some(State::SoftLogout)
+        );
+        assert_eq!(
+            state_of("2024-06-01T10:00:04Z DEBUG app: soft_logout: false"),
+            None
+        );
+        assert!(!State::SoftLogout.is_app_state());
+        assert_eq!(
+            Pattern::parse("login=Logged in").err().as_deref(),
+            Some(
+                "Unknown app state `login`; valid states are `foreground`, `background`, `push-wakeup`, `client-built`, `session-restored`, `token-refreshed`, `soft-logout`"
+            )
+        );
+    }
+}
@@ -0,0 +1,102 @@
+//! Receive log lines over TCP, for harnesses where the application under test
+//! can be pointed at a log sink.
+//!
+//! Clients are served one at a time, sequentially. A client that stays silent
+//! for longer than the idle timeout is dropped, and the listener stops once no
+//! new client has shown up for the same duration, so that the report doesn't
+//! wait forever.
+
+use std::{
+    io::{self, BufRead, BufReader, ErrorKind},
+    net::{TcpListener, TcpStream},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::source;
+
+/// Default duration after which a silent client, or the listener itself, is
+/// considered done.
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often the listener checks for a new client.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Listen on `address` and call `on_line` for every received line, until the
+/// listener has been idle for `idle_timeout`.
+pub fn receive(
+    address: &str,
+    idle_timeout: Duration,
+    mut on_line: impl FnMut(Result<&str, &[u8]>),
+) -> io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    listener.set_nonblocking(true)?;
+
+    eprintln!("Listening for logs on `{}`", listener.local_addr()?);
+
+    let mut idle_since = Instant::now();
+
+    loop {
+        match listener.accept() {
+            Ok((stream, peer)) => {
+                eprintln!("Receiving logs from `{peer}`");
+
+                stream.set_nonblocking(false)?;
+                stream.set_read_timeout(Some(idle_timeout))?;
+                read_lines(stream, &mut on_line);
+
+                eprintln!("`{peer}` is done");
+
+                idle_since = Instant::now();
+            }
+
+            Err(error) if error.kind() == ErrorKind::WouldBlock => {
+                if idle_since.elapsed() >= idle_timeout {
+                    return Ok(());
+                }
+
+                thread::sleep(POLL_INTERVAL);
+            }
+
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+/// Read the lines of a client until it closes the connection, fails or goes
+/// idle. A partial line left at the end is still handled.
+fn read_lines(stream: TcpStream, on_line: &mut impl FnMut(Result<&str, &[u8]>)) {
+    let mut reader = BufReader::new(stream);
+    let mut line = Vec::new();
+
+    loop {
+        match reader.read_until(b'\n', &mut line) {
+            Ok(0) => break,
+
+            Ok(_) => {
+                if line.ends_with(b"\n") {
+                    on_line(source::decode(&line));
+                    line.clear();
+                }
+            }
+
+            Err(error) if matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                eprintln!("The client has been idle for too long, dropping it");
+
+                break;
+            }
+
+            Err(error) if error.kind() == ErrorKind::Interrupted => {}
+
+            Err(error) => {
+                eprintln!("Failed to read from the client: {error}");
+
+                break;
+            }
+        }
+    }
+
+    if !line.is_empty() {
+        on_line(source::decode(&line));
+    }
+}
@@ -1,288 +1,65 @@
-use ada_url::Url;
-use chrono::{DateTime, FixedOffset, TimeDelta};
-use regex::RegexBuilder;
 use std::{
-    collections::{BTreeMap, btree_map::Entry},
-    env, fs,
-    io::{self, BufRead, Write},
-    ops::Sub,
+    io::{self, Write},
+    process,
 };
 
-const OUTPUT_TEMPLATE: &str = include_str!("../template/index.html");
+use network_viewer::{
+    cli,
+    error::{BUDGET_EXIT_CODE, Error, STRICT_EXIT_CODE},
+};
 
 fn main() {
-    let find_sync = RegexBuilder::new(
-        r#"
-            # Datetime of the log line.
-            (?<datetime>\d{4}-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}\.\d+Z)
-
-            # Ensure it's about the `http_client` scope.
-            .*matrix_sdk::http_client
-
-            # Ensure it's about a sync.
-            .*>\ssync_once\{conn_id="(?<connection_id>[^"]+)"\}
-
-            # Let's capture some data about `send()`!
-            \s>\ssend\{
-                request_id="REQ-(?<request_id>\d+)"
-                \smethod=(?<method>\S+)
-                \suri="(?<uri>[^"]+)"
-                # If there is a `request_size`.
-                (.*\srequest_size="(?<request_size>[^"]+)")?
-                # If this is a response, there is a `status`.
-                (.*\sstatus=(?<status>\d+))?
-                # If there is a `response_size`.
-                (.*\sresponse_size="(?<response_size>[^"]+)")?
-        "#,
-    )
-    .ignore_whitespace(true)
-    .build()
-    .expect("Failed to build the `find_sync_start regex`");
-
-    let mut args = env::args();
-    let this_bin = args.next().expect("<bin-name> is unknown, really?");
-
-    let Some(log_path) = args.next() else {
-        panic!("<log_path> is missing; try `{this_bin} <log_path> <output_path>`");
-    };
-
-    let Some(output_path) = args.next() else {
-        panic!("<output_path> is missing; try `{this_bin} <log_path> <output_path>`");
-    };
-
-    let Ok(log_file) = fs::File::open(&log_path) else {
-        panic!("Failed to open `{log_path}`");
-    };
-
-    let Ok(mut output_file) = fs::File::create(&output_path) else {
-        panic!("Failed to create `{output_path}`");
-    };
-
-    let reader = io::BufReader::new(log_file);
-    let mut number_of_analysed_lines = 0;
-    let mut number_of_matched_lines = 0;
-    let mut smallest_start_at = None;
-    let mut largest_end_at = None;
-
-    let mut spans: BTreeMap<ConnectionId, BTreeMap<RequestId, Span>> = BTreeMap::new();
-
-    for (line_nth, line) in reader.lines().enumerate().map(|(nth, line)| {
-        (
-            nth + 1,
-            line.unwrap_or_else(|error| {
-                panic!("Failed to read line #{nth}\n{error}");
-            }),
-        )
-    }) {
-        number_of_analysed_lines += 1;
-
-        if let Some(captures) = find_sync.captures(&line) {
-            number_of_matched_lines += 1;
-
-            let date_time = DateTime::parse_from_rfc3339(
-                captures
-                    .name("datetime")
-                    .expect("Failed to capture `datetime`")
-                    .as_str(),
-            )
-            .expect("Failed to parse `datetime`");
-            let connection_id = captures
-                .name("connection_id")
-                .expect("Failed to capture `connection_id`")
-                .as_str();
-            let request_id = captures
-                .name("request_id")
-                .expect("Failed to capture `request_id`")
-                .as_str()
-                .parse()
-                .expect("Failed to parse `request_id`");
-            let method = captures
-                .name("method")
-                .expect("Failed to capture `method`")
-                .as_str();
-            let uri = captures
-                .name("uri")
-                .expect("Failed to capture `uri`")
-                .as_str();
-            let request_size = captures
-                .name("request_size")
-                .map(|request_size| request_size.as_str());
-            let response_size = captures
-                .name("response_size")
-                .map(|response_size| response_size.as_str());
-            let status = captures.name("status").map(|status| status.as_str());
-
-            if let Some(smallest_start_at_inner) = smallest_start_at {
-                if smallest_start_at_inner > date_time {
-                    smallest_start_at = Some(date_time.clone());
-                }
+    match cli::run() {
+        Ok(stats) => {
+            // The summary mustn't be mixed with a report written to the
+            // standard output.
+            let written = if stats.report_to_stdout {
+                writeln!(io::stderr().lock(), "{}", stats.summary)
             } else {
-                smallest_start_at = Some(date_time.clone());
+                writeln!(io::stdout().lock(), "{}", stats.summary)
+            };
+
+            // The standard output closed early, e.g. by `network-viewer … | head`,
+            // isn't a failure.
+            if let Err(error) = written
+                && error.kind() != io::ErrorKind::BrokenPipe
+            {
+                let error = Error::Io {
+                    path: "-".to_owned(),
+                    error,
+                };
+                eprintln!("Error: {error}");
+                process::exit(error.exit_code());
             }
 
-            if let Some(largest_end_at_inner) = largest_end_at {
-                if largest_end_at_inner < date_time {
-                    largest_end_at = Some(date_time.clone());
+            if !stats.strict_errors.is_empty() {
+                for error in &stats.strict_errors {
+                    eprintln!("Error: {error}");
                 }
-            } else {
-                largest_end_at = Some(date_time.clone());
-            }
 
-            let spans_for_connection_id = spans.entry(connection_id.to_owned()).or_default();
+                eprintln!(
+                    "Failed because of `--strict`; allow some conditions with `--strict-except <conditions>`"
+                );
+                process::exit(STRICT_EXIT_CODE);
+            }
 
-            match spans_for_connection_id.entry(request_id) {
-                Entry::Vacant(entry) => {
-                    entry.insert(Span {
-                        status: None,
-                        method: method.to_owned(),
-                        uri: uri.to_owned(),
-                        request_size: request_size.map(ToOwned::to_owned),
-                        response_size: response_size.map(ToOwned::to_owned),
-                        start_at: date_time,
-                        duration: TimeDelta::zero(),
-                        request_log_line: line_nth,
-                        response_log_line: None,
-                    });
+            if !stats.budget_violations.is_empty() {
+                for violation in &stats.budget_violations {
+                    eprintln!("Error: {violation}");
                 }
-                Entry::Occupied(mut entry) => {
-                    let span = entry.get_mut();
-
-                    if let Some(status) = status {
-                        if let Ok(status) = status.parse() {
-                            span.status = Some(status);
-                        }
-                    }
-
-                    span.duration = date_time.sub(&span.start_at);
-
-                    if let Some(request_size) = request_size {
-                        span.request_size = Some(request_size.to_owned());
-                    }
-
-                    if let Some(response_size) = response_size {
-                        span.response_size = Some(response_size.to_owned());
-                    }
 
-                    span.response_log_line = Some(line_nth);
-                }
+                eprintln!("Failed because of the budgets of `check`");
+                process::exit(BUDGET_EXIT_CODE);
             }
         }
-    }
-
-    let smallest_start_at = smallest_start_at
-        .map(|date_time| date_time.timestamp_millis())
-        .unwrap_or_default();
-    let largest_end_at = largest_end_at
-        .map(|date_time| date_time.timestamp_millis())
-        .unwrap_or_default();
-    let end_at = largest_end_at.saturating_sub(smallest_start_at).to_string();
-    let rows = spans
-        .iter()
-        .map(|(connection_id, spans)| {
-            spans.iter().map(
-                |(
-                    request_id,
-                    Span {
-                        status,
-                        method,
-                        uri,
-                        request_size,
-                        response_size,
-                        start_at,
-                        duration,
-                        request_log_line,
-                        response_log_line
-                    },
-                )| {
-                    let uri_components = Url::parse(uri, None).map(|uri| uri.components());
-                    let duration = duration.num_milliseconds();
-
-                    format!(
-                        "    <tr id=\"{connection_id}-{request_id}\">
-      <td><code>{connection_id}</code></td>
-      <td><a href=\"#{connection_id}-{request_id}\" title=\"Permalink to this line\"><code>{request_id}</code></a></td>
-      <td data-status-family=\"{status_family}\"><span>{status}</span></td>
-      <td><code>{method}</code></td>
-      <td title=\"{domain}\">{domain}</td>
-      <td title=\"{path}\">{path}</td>
-      <td>{request_size}</td>
-      <td>{response_size}</td>
-      <td>
-        <div class=\"span\" style=\"--start-at: {start_at}; --duration: {duration}\"><span>{duration_label}</span></div>
-        <details>
-          <summary><span class=\"hidden\">information</span></summary>
-          <ul>
-            <li>Request log line number: {request_log_line}</li>
-            <li>Response log line number: {response_log_line}</li>
-          </ul>
-        </dialog>
-      </td>
-    </tr>
-",
-                        connection_id = connection_id.clone(),
-                        status = status
-                            .map(|status| status.to_string())
-                            .unwrap_or_else(|| "×".to_owned()),
-                        status_family = status
-                            .map(|status| (if status > 0 { status / 100 } else { 0 } ).to_string())
-                            .unwrap_or_else(|| "cancelled".to_owned()),
-                        domain = uri_components
-                            .as_ref()
-                            .map(|components| uri[components.host_start as usize..components.host_end as usize].to_string())
-                            .unwrap_or_default(),
-                        path = uri_components
-                            .as_ref()
-                            .map(|components| if let Some(pathname_start) = components.pathname_start { uri[pathname_start as usize..].to_string() } else { "".to_owned() })
-                            .unwrap_or_default(),
-                        request_size = request_size
-                            .clone()
-                            .map(|request_size| request_size.to_string())
-                            .unwrap_or_else(|| "".to_owned()),
-                        response_size = response_size
-                            .clone()
-                            .map(|response_size| response_size.to_string())
-                            .unwrap_or_else(|| "".to_owned()),
-                        start_at = start_at
-                            .timestamp_millis()
-                            .saturating_sub(smallest_start_at),
-                        duration_label = if duration > 0 { format!("{duration}ms") } else { "<em>cancelled</em>".to_owned() },
-                        response_log_line = response_log_line.map(|line| line.to_string()).unwrap_or_else(|| "(none)".to_owned()),
-                    )
-                },
-            )
-        })
-        .flatten()
-        .collect::<String>();
+        Err(error) => {
+            eprintln!("Error: {error}");
 
-    let output = OUTPUT_TEMPLATE
-        .replace("{end_at}", &end_at)
-        .replace("{rows}", &rows);
-
-    output_file
-        .write_all(output.as_bytes())
-        .expect("Failed to write the output");
-
-    println!(
-        "\nNumber of analysed log lines: {number_of_analysed_lines}\n\
-        Number of matched lines: {number_of_matched_lines}\n\
-        Output file: {output_path}\n\
-        Done!"
-    );
-}
-
-type ConnectionId = String;
-
-type RequestId = u32;
+            if let Error::Usage(_) = error {
+                eprintln!("See `--help` for the usage");
+            }
 
-#[derive(Debug)]
-struct Span {
-    status: Option<u8>,
-    method: String,
-    uri: String,
-    request_size: Option<String>,
-    response_size: Option<String>,
-    start_at: DateTime<FixedOffset>,
-    duration: TimeDelta,
-    request_log_line: usize,
-    response_log_line: Option<usize>,
+            process::exit(error.exit_code());
+        }
+    }
 }
@@ -0,0 +1,244 @@
+//! Group the media downloads by their `mxc://` URI, to tell how effective the
+//! media cache of the client is: a media downloaded repeatedly, e.g. an
+//! avatar fetched on every scroll of the room list, wastes the bytes of all
+//! its downloads but the first.
+//!
+//! The downloads of `/_matrix/media/*/download` and of
+//! `/_matrix/client/v1/media/download` are the same media. A thumbnail is a
+//! media of its own, per size.
+
+use std::collections::BTreeMap;
+
+use ada_url::Url;
+
+use crate::{Spans, html, human};
+
+/// Maximum number of media listed in the report.
+const MAXIMUM_NUMBER_OF_ROWS: usize = 20;
+
+/// The successful downloads of a media.
+#[derive(Default)]
+struct Downloads {
+    number_of_downloads: usize,
+    /// Bytes of the responses but the first one, if their size is logged.
+    wasted_bytes: u64,
+}
+
+/// Get the media downloaded by a request, e.g. `mxc://example.org/AbCdEf`, or
+/// `mxc://example.org/AbCdEf (thumbnail 96×96)`, if it's a media download.
+fn media_of(uri: &str) -> Option<String> {
+    let uri = Url::parse(uri, None).ok()?;
+    let segments = uri
+        .pathname()
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect::<Vec<_>>();
+    let rest = match segments.as_slice() {
+        ["_matrix", "media", _version, rest @ ..] => rest,
+        ["_matrix", "client", _version, "media", rest @ ..] => rest,
+        _ => return None,
+    };
+
+    match rest {
+        ["download", server_name, media_id, ..] => Some(format!("mxc://{server_name}/{media_id}")),
+        ["thumbnail", server_name, media_id, ..] => {
+            let parameter = |name| {
+                uri.search()
+                    .trim_start_matches('?')
+                    .split('&')
+                    .find_map(|pair| pair.strip_prefix(name)?.strip_prefix('='))
+                    .unwrap_or("?")
+                    .to_owned()
+            };
+
+            Some(format!(
+                "mxc://{server_name}/{media_id} (thumbnail {}×{})",
+                parameter("width"),
+                parameter("height"),
+            ))
+        }
+        _ => None,
+    }
+}
+
+/// Group the successful media downloads per media.
+fn per_media(spans: &Spans) -> BTreeMap<String, Downloads> {
+    let mut media = BTreeMap::<_, Downloads>::new();
+
+    for span in spans.values().flat_map(BTreeMap::values) {
+        if !span
+            .status
+            .is_some_and(|status| (200..300).contains(&status))
+        {
+            continue;
+        }
+
+        let Some(mxc) = media_of(&span.uri) else {
+            continue;
+        };
+        let downloads = media.entry(mxc).or_default();
+
+        if downloads.number_of_downloads > 0 {
+            downloads.wasted_bytes += span
+                .response_size
+                .as_ref()
+                .and_then(|size| size.bytes())
+                .unwrap_or_default();
+        }
+
+        downloads.number_of_downloads += 1;
+    }
+
+    media
+}
+
+/// Get the media downloaded more than once, the most wasteful first.
+fn repeated(spans: &Spans) -> Vec<(String, Downloads)> {
+    let mut repeated = per_media(spans)
+        .into_iter()
+        .filter(|(_, downloads)| downloads.number_of_downloads > 1)
+        .collect::<Vec<_>>();
+    repeated.sort_by(|(_, a), (_, b)| {
+        (b.wasted_bytes, b.number_of_downloads).cmp(&(a.wasted_bytes, a.number_of_downloads))
+    });
+
+    repeated
+}
+
+/// Render the media downloaded more than once as text, for the console, or
+/// nothing if there is none.
+pub fn repeated_to_text(spans: &Spans) -> String {
+    let repeated = repeated(spans);
+
+    if repeated.is_empty() {
+        return String::new();
+    }
+
+    format!(
+        "Media downloaded more than once: {} ({} wasted)\n",
+        human::count(repeated.len()),
+        human::bytes(
+            repeated
+                .iter()
+                .map(|(_, downloads)| downloads.wasted_bytes)
+                .sum()
+        ),
+    )
+}
+
+/// Render the media downloaded more than once as HTML, or nothing if there
+/// is none.
+pub fn to_html(spans: &Spans) -> String {
+    let repeated = repeated(spans);
+
+    if repeated.is_empty() {
+        return String::new();
+    }
+
+    let wasted_bytes = repeated
+        .iter()
+        .map(|(_, downloads)| downloads.wasted_bytes)
+        .sum();
+    let rows = repeated
+        .iter()
+        .take(MAXIMUM_NUMBER_OF_ROWS)
+        .map(|(mxc, downloads)| {
+            format!(
+                "      <tr>
+        <td><code>{mxc}</code></td>
+        <td>{number_of_downloads}</td>
+        <td>{wasted_bytes}</td>
+      </tr>
+",
+                mxc = html::escape(mxc),
+                number_of_downloads = human::count(downloads.number_of_downloads),
+                wasted_bytes = human::bytes_to_html(downloads.wasted_bytes),
+            )
+        })
+        .collect::<String>();
+    let note = if repeated.len() > MAXIMUM_NUMBER_OF_ROWS {
+        format!(" The {MAXIMUM_NUMBER_OF_ROWS} most wasteful of them are listed.")
+    } else {
+        String::new()
+    };
+
+    format!(
+        "  <h3>Media cache</h3>
+  <p>{number_of_media} media downloaded more than once, wasting {wasted_bytes} that a media cache would have saved.{note}</p>
+  <table>
+    <thead>
+      <tr>
+        <th scope=\"col\">Media</th>
+        <th scope=\"col\">Downloads</th>
+        <th scope=\"col\">Wasted</th>
+      </tr>
+    </thead>
+    <tbody>
+{rows}    </tbody>
+  </table>
+",
+        number_of_media = human::count(repeated.len()),
+        wasted_bytes = human::bytes_to_html(wasted_bytes),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SpansBuilder, size::Size};
+
+    #[test]
+    fn test_repeated() {
+        let mut spans = SpansBuilder::default();
+        let mut span = |request_id, path: &str, status, size: &str| {
+            spans
+                .span(
+                    "main",
+                    request_id,
+                    &format!("https://example.org/_matrix/{path}"),
+                    Some(status),
+                    0,
+                    100,
+                )
+                .response_size = Some(Size::new(size));
+        };
+        span(1, "media/v3/download/example.org/AbCdEf", 200, "2KiB");
+        span(
+            2,
+            "client/v1/media/download/example.org/AbCdEf/avatar.png",
+            200,
+            "2KiB",
+        );
+        span(
+            3,
+            "client/v1/media/download/example.org/AbCdEf",
+            200,
+            "2KiB",
+        );
+        span(4, "client/v1/media/download/example.org/Other", 200, "1KiB");
+        span(5, "client/v1/media/download/example.org/Other", 502, "0B");
+        span(
+            6,
+            "client/v1/media/thumbnail/example.org/AbCdEf?width=96&height=96",
+            200,
+            "1KiB",
+        );
+        span(7, "client/v3/sync", 200, "1KiB");
+        let spans = spans.build();
+
+        let repeated = repeated(&spans);
+
+        assert_eq!(repeated.len(), 1);
+        assert_eq!(repeated[0].0, "mxc://example.org/AbCdEf");
+        assert_eq!(repeated[0].1.number_of_downloads, 3);
+        assert_eq!(repeated[0].1.wasted_bytes, 4_096);
+        assert_eq!(
+            media_of("https://example.org/_matrix/client/v1/media/thumbnail/example.org/AbCdEf?width=96&height=96&method=crop").as_deref(),
+            Some("mxc://example.org/AbCdEf (thumbnail 96×96)")
+        );
+        assert_eq!(
+            repeated_to_text(&spans),
+            "Media downloaded more than once: 1 (4.0 KiB wasted)\n"
+        );
+    }
+}
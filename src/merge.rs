@@ -0,0 +1,334 @@
+//! Merge the connections which are continuations of each other: after a
+//! transient failure, the SDK may tear a sync loop down and recreate it with a
+//! new connection ID, e.g. `room-list`, then `room-list#1`, then
+//! `room-list#2`. Merged, they are displayed as one lane, with a restart
+//! marker on the first span of each continuation.
+//!
+//! A connection is a continuation of another one if all these rules hold:
+//!
+//! 1. the other connection ends with a failure, i.e. its last span isn't
+//!    successful,
+//! 2. its first span starts after the start of this failure, and within the
+//!    window after the end of the other connection,
+//! 3. both spans target the same templated sync endpoint,
+//! 4. its first span resumes the since-token lineage, i.e. its `since` or
+//!    `pos` token is one of the last tokens of the other connection, or, if it
+//!    has no token, its ID is a `#N` continuation of the other ID,
+//! 5. no request ID is shared by both connections.
+//!
+//! In a reconnect storm, all the continuations may resume from the same token:
+//! a connection is merged into the most recently ended of its candidate
+//! predecessors, if it is the earliest of its candidate continuations. To stay
+//! conservative, ties aren't merged.
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, FixedOffset, TimeDelta};
+
+use crate::{ConnectionId, RequestId, Span, Spans, html};
+
+/// Default window after the end of a connection during which a continuation
+/// may start.
+pub const DEFAULT_WINDOW: TimeDelta = TimeDelta::seconds(30);
+
+/// Number of the last sync tokens of a connection which a continuation may
+/// resume from.
+const LINEAGE_LENGTH: usize = 3;
+
+/// The first and last spans of a connection, and the tokens of its last
+/// syncs.
+struct Bounds<'a> {
+    first: &'a Span,
+    last: &'a Span,
+    end_at: DateTime<FixedOffset>,
+    tokens: Vec<String>,
+}
+
+impl<'a> Bounds<'a> {
+    fn of(spans: &'a BTreeMap<RequestId, Span>) -> Option<Self> {
+        let mut by_start_at = spans.values().collect::<Vec<_>>();
+        by_start_at.sort_by_key(|span| span.start_at);
+
+        Some(Self {
+            first: by_start_at.first()?,
+            last: by_start_at.last()?,
+            end_at: by_start_at
+                .iter()
+                .map(|span| span.start_at + span.duration)
+                .max()?,
+            tokens: by_start_at
+                .iter()
+                .rev()
+                .filter(|span| span.is_sync())
+                .filter_map(|span| token(span))
+                .take(LINEAGE_LENGTH)
+                .collect(),
+        })
+    }
+}
+
+/// Get the `since` or `pos` token of a sync.
+fn token(span: &Span) -> Option<String> {
+    span.query_parameter("since")
+        .or_else(|| span.query_parameter("pos"))
+}
+
+/// Whether `connection_id` is like `{previous_id}#N`, or shares its base with
+/// `previous_id` if both are like `{base}#N`.
+fn is_numbered_continuation(previous_id: &str, connection_id: &str) -> bool {
+    let base = |id: &str| match id.rsplit_once('#') {
+        Some((base, number))
+            if !number.is_empty() && number.bytes().all(|c| c.is_ascii_digit()) =>
+        {
+            Some(base.to_owned())
+        }
+        _ => None,
+    };
+
+    base(connection_id).is_some_and(|base_id| {
+        base_id == previous_id || base(previous_id).is_some_and(|previous| previous == base_id)
+    })
+}
+
+/// Whether `next` is a continuation of `previous`, according to the rules 1
+/// to 5.
+fn continues(
+    (previous_id, previous): (&ConnectionId, &Bounds<'_>),
+    (next_id, next): (&ConnectionId, &Bounds<'_>),
+    spans: &Spans,
+    window: TimeDelta,
+) -> bool {
+    let lineage = match token(next.first) {
+        Some(token) => previous.tokens.contains(&token),
+        None => is_numbered_continuation(previous_id, next_id),
+    };
+
+    previous_id != next_id
+        && !previous.last.is_successful()
+        && next.first.start_at >= previous.last.start_at
+        && next.first.start_at <= previous.end_at + window
+        && next.first.is_sync()
+        && next.first.endpoint() == previous.last.endpoint()
+        && lineage
+        && !spans[next_id]
+            .keys()
+            .any(|request_id| spans[previous_id].contains_key(request_id))
+}
+
+/// Get the candidate with the smallest key, or none if several have it.
+fn best(
+    candidates: Vec<&ConnectionId>,
+    key: impl Fn(&ConnectionId) -> i64,
+) -> Option<&ConnectionId> {
+    let best_key = candidates.iter().map(|id| key(id)).min()?;
+    let mut best = candidates.into_iter().filter(|id| key(id) == best_key);
+
+    best.next().filter(|_| best.next().is_none())
+}
+
+/// Merge the continuations into the connection they continue, with `window`
+/// as the delay of the rule 2. Returns the merged connections: each lane,
+/// with the original IDs of the connections merged into it.
+pub fn merge(spans: &mut Spans, window: TimeDelta) -> Vec<(ConnectionId, Vec<ConnectionId>)> {
+    let links = {
+        let bounds = spans
+            .iter()
+            .filter_map(|(connection_id, spans)| Some((connection_id, Bounds::of(spans)?)))
+            .collect::<BTreeMap<_, _>>();
+        let mut candidates = Vec::new();
+
+        for (previous_id, previous) in &bounds {
+            for (next_id, next) in &bounds {
+                if continues((previous_id, previous), (next_id, next), spans, window) {
+                    candidates.push((*previous_id, *next_id));
+                }
+            }
+        }
+
+        let earliest_next = |previous_id: &ConnectionId| {
+            best(
+                candidates
+                    .iter()
+                    .filter(|(previous, _)| *previous == previous_id)
+                    .map(|(_, next)| *next)
+                    .collect(),
+                |id: &ConnectionId| bounds[id].first.start_at.timestamp_millis(),
+            )
+        };
+        let latest_previous = |next_id: &ConnectionId| {
+            best(
+                candidates
+                    .iter()
+                    .filter(|(_, next)| *next == next_id)
+                    .map(|(previous, _)| *previous)
+                    .collect(),
+                |id: &ConnectionId| -bounds[id].end_at.timestamp_millis(),
+            )
+        };
+
+        candidates
+            .iter()
+            .filter(|(previous, next)| {
+                earliest_next(previous) == Some(*next) && latest_previous(next) == Some(*previous)
+            })
+            .map(|(previous, next)| ((*previous).clone(), (*next).clone()))
+            .collect::<BTreeMap<_, _>>()
+    };
+
+    let mut lanes = Vec::new();
+
+    for root in links.keys() {
+        // A root continues no other connection.
+        if links.values().any(|next| next == root) {
+            continue;
+        }
+
+        let mut merged = Vec::new();
+        let mut current = root;
+
+        while let Some(next) = links.get(current) {
+            // A cycle is impossible, since a continuation starts after its
+            // predecessor, but is guarded against anyway.
+            if next == root || merged.contains(next) {
+                break;
+            }
+
+            let Some(mut continuation) = spans.remove(next) else {
+                break;
+            };
+
+            if let Some(first) = continuation.values_mut().min_by_key(|span| span.start_at) {
+                first.restarted_as = Some(next.clone());
+            }
+
+            spans
+                .get_mut(root)
+                .expect("The root connection is never removed")
+                .append(&mut continuation);
+            merged.push(next.clone());
+            current = next;
+        }
+
+        lanes.push((root.clone(), merged));
+    }
+
+    lanes
+}
+
+/// Render the note listing the restarts of the merged connections, or nothing
+/// if there is none.
+pub fn to_html(spans: &Spans) -> String {
+    let lanes = spans
+        .iter()
+        .filter_map(|(connection_id, spans)| {
+            let mut restarts = spans
+                .values()
+                .filter_map(|span| Some((span.start_at, span.restarted_as.as_ref()?)))
+                .collect::<Vec<_>>();
+            restarts.sort();
+
+            (!restarts.is_empty()).then(|| {
+                format!(
+                    "<code>{restarts}</code> into <code>{connection_id}</code>",
+                    restarts = restarts
+                        .iter()
+                        .map(|(_, restarted_as)| html::escape(restarted_as))
+                        .collect::<Vec<_>>()
+                        .join("</code>, <code>"),
+                    connection_id = html::escape(connection_id),
+                )
+            })
+        })
+        .collect::<Vec<_>>();
+
+    if lanes.is_empty() {
+        return String::new();
+    }
+
+    format!(
+        "  <p class=\"merged-connections\">Restarted connections merged by <code>--merge-connections</code>: {}.</p>\n",
+        lanes.join("; ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    const RECONNECT_STORM: &str = include_str!("../fixtures/reconnect-storm.log");
+
+    fn parse(log: &str) -> Spans {
+        let mut parser = Parser::new();
+
+        for line in log.lines() {
+            parser.parse_line(line, None);
+        }
+
+        parser.spans
+    }
+
+    #[test]
+    fn test_is_numbered_continuation() {
+        assert!(is_numbered_continuation("room-list", "room-list#1"));
+        assert!(is_numbered_continuation("room-list#1", "room-list#2"));
+        assert!(!is_numbered_continuation("room-list", "room-list"));
+        assert!(!is_numbered_continuation("room-list", "room-list#"));
+        assert!(!is_numbered_continuation("room-list", "encryption#1"));
+        assert!(!is_numbered_continuation("room-list#1", "room-list"));
+    }
+
+    #[test]
+    fn test_merge_reconnect_storm() {
+        let mut spans = parse(RECONNECT_STORM);
+        let number_of_spans = spans.values().map(BTreeMap::len).sum::<usize>();
+
+        let lanes = merge(&mut spans, DEFAULT_WINDOW);
+
+        // `room-list#1` to `room-list#4` resume the lineage of `room-list`.
+        // `room-list-fresh` starts from scratch, without being numbered, and
+        // `encryption#1` continues `encryption`, which hasn't failed.
+        assert_eq!(
+            lanes,
+            [(
+                "room-list".to_owned(),
+                ["room-list#1", "room-list#2", "room-list#3", "room-list#4"]
+                    .map(ToOwned::to_owned)
+                    .to_vec()
+            )]
+        );
+        assert_eq!(
+            spans.keys().collect::<Vec<_>>(),
+            ["encryption", "encryption#1", "room-list", "room-list-fresh"]
+        );
+        assert_eq!(
+            spans.values().map(BTreeMap::len).sum::<usize>(),
+            number_of_spans
+        );
+
+        let restarts = spans["room-list"]
+            .values()
+            .filter_map(|span| span.restarted_as.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            restarts,
+            ["room-list#1", "room-list#2", "room-list#3", "room-list#4"]
+        );
+    }
+
+    #[test]
+    fn test_merge_outside_of_the_window() {
+        let mut spans = parse(RECONNECT_STORM);
+
+        // The restarts happen 2s after the failures.
+        let lanes = merge(&mut spans, TimeDelta::seconds(1));
+
+        assert!(lanes.is_empty());
+        assert!(
+            spans
+                .values()
+                .flat_map(BTreeMap::values)
+                .all(|span| span.restarted_as.is_none())
+        );
+    }
+}
@@ -0,0 +1,96 @@
+//! Metadata of a report, so that an archived report still tells which logs it
+//! describes, and whether it is complete or filtered.
+
+use serde::Serialize;
+
+use crate::{html, human};
+
+/// Version of the tool generating the reports.
+pub const TOOL_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[derive(Clone, Serialize)]
+pub struct Meta<'a> {
+    pub title: Option<&'a str>,
+    pub sources: &'a [SourceFile],
+    /// Start of the first span, in RFC 3339.
+    pub start_at: Option<String>,
+    /// End of the last span, in RFC 3339.
+    pub end_at: Option<String>,
+    pub tool_version: &'static str,
+    /// When the report has been generated, in RFC 3339. Only displayed, never
+    /// serialized, so that 2 exports of the same log are identical.
+    #[serde(skip)]
+    pub generated_at: String,
+    /// Filters removing or hiding spans. Empty if the report is complete.
+    pub filters: Vec<Filter>,
+}
+
+/// A filter removing or hiding spans.
+#[derive(Clone, Serialize)]
+pub struct Filter {
+    /// The flag enabling the filter, e.g. `--last 30m`.
+    pub flag: String,
+    pub description: String,
+}
+
+/// A source of the logs.
+#[derive(Clone, Serialize)]
+pub struct SourceFile {
+    pub name: String,
+    /// Size, in bytes, if the source is a file.
+    pub size: Option<u64>,
+}
+
+impl Meta<'_> {
+    /// Render the metadata as a description list.
+    pub fn to_html(&self) -> String {
+        let sources = self
+            .sources
+            .iter()
+            .map(|source| match source.size {
+                Some(size) => format!(
+                    "<code>{name}</code> ({size})",
+                    name = html::escape(&source.name),
+                    size = human::bytes_to_html(size),
+                ),
+                None => format!("<code>{}</code>", html::escape(&source.name)),
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        let time_range = match (&self.start_at, &self.end_at) {
+            (Some(start_at), Some(end_at)) => format!("{start_at} to {end_at}"),
+            _ => "<em>No span</em>".to_owned(),
+        };
+        let filters = if self.filters.is_empty() {
+            "<em>None, the report is complete</em>".to_owned()
+        } else {
+            format!(
+                "\n      <ul>\n{}      </ul>\n    ",
+                self.filters
+                    .iter()
+                    .map(|filter| format!(
+                        "        <li><code>{flag}</code>: {description}</li>\n",
+                        flag = html::escape(&filter.flag),
+                        description = html::escape(&filter.description),
+                    ))
+                    .collect::<String>()
+            )
+        };
+
+        format!(
+            "  <dl class=\"meta\">
+    <dt>Sources</dt>
+    <dd>{sources}</dd>
+    <dt>Time range</dt>
+    <dd>{time_range}</dd>
+    <dt>Filters</dt>
+    <dd>{filters}</dd>
+    <dt>Generated</dt>
+    <dd>{generated_at} by network-viewer {tool_version}</dd>
+  </dl>
+",
+            generated_at = self.generated_at,
+            tool_version = self.tool_version,
+        )
+    }
+}
@@ -0,0 +1,367 @@
+//! Export the spans as OpenTelemetry traces, in the JSON encoding of OTLP, to
+//! view them in Jaeger or Grafana Tempo next to the traces of the homeserver.
+//!
+//! Each connection is a trace, and each span a client span of its trace. The
+//! IDs are derived from the connection and request IDs, so that exporting the
+//! same log twice gives the same IDs. The traces are written to a file, or
+//! pushed to a collector with `--otlp-endpoint`, e.g.
+//! `http://localhost:4318/v1/traces`.
+
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::TcpStream,
+    time::Duration,
+};
+
+use serde::Serialize;
+
+use crate::{RequestId, Spans};
+
+/// Scheme of the endpoints: the collectors listen in plain HTTP locally.
+const SCHEME: &str = "http://";
+
+/// Default port of the OTLP/HTTP collectors.
+const DEFAULT_PORT: u16 = 4318;
+
+/// Default path of the traces of the OTLP/HTTP collectors.
+const DEFAULT_PATH: &str = "/v1/traces";
+
+/// Time to wait for the collector.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// `SPAN_KIND_CLIENT`.
+const KIND_CLIENT: u8 = 3;
+
+/// `STATUS_CODE_OK` and `STATUS_CODE_ERROR`, `STATUS_CODE_UNSET` being the
+/// status of the spans without a response.
+const STATUS_OK: u8 = 1;
+const STATUS_ERROR: u8 = 2;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Request<'a> {
+    resource_spans: [ResourceSpans<'a>; 1],
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ResourceSpans<'a> {
+    resource: Resource<'a>,
+    scope_spans: [ScopeSpans<'a>; 1],
+}
+
+#[derive(Serialize)]
+struct Resource<'a> {
+    attributes: Vec<Attribute<'a>>,
+}
+
+#[derive(Serialize)]
+struct ScopeSpans<'a> {
+    scope: Scope,
+    spans: Vec<Span<'a>>,
+}
+
+#[derive(Serialize)]
+struct Scope {
+    name: &'static str,
+    version: &'static str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Span<'a> {
+    trace_id: String,
+    span_id: String,
+    name: String,
+    kind: u8,
+    /// The 64-bit integers are strings in the JSON encoding.
+    start_time_unix_nano: String,
+    end_time_unix_nano: String,
+    attributes: Vec<Attribute<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<Status>,
+}
+
+#[derive(Serialize)]
+struct Status {
+    code: u8,
+}
+
+#[derive(Serialize)]
+struct Attribute<'a> {
+    key: &'static str,
+    value: Value<'a>,
+}
+
+#[derive(Serialize)]
+enum Value<'a> {
+    #[serde(rename = "stringValue")]
+    String(&'a str),
+    /// The 64-bit integers are strings in the JSON encoding.
+    #[serde(rename = "intValue")]
+    Int(String),
+}
+
+impl<'a> Attribute<'a> {
+    fn string(key: &'static str, value: &'a str) -> Self {
+        Self {
+            key,
+            value: Value::String(value),
+        }
+    }
+
+    fn int(key: &'static str, value: impl ToString) -> Self {
+        Self {
+            key,
+            value: Value::Int(value.to_string()),
+        }
+    }
+}
+
+/// Hash `bytes` with FNV-1a, from `seed`. The IDs only need to be stable and
+/// spread, not secure.
+fn hash(seed: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(seed, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100_0000_01b3)
+    })
+}
+
+/// The 16 bytes of the trace ID of a connection, in hexadecimal.
+fn trace_id(connection_id: &str) -> String {
+    format!(
+        "{:016x}{:016x}",
+        hash(0xcbf2_9ce4_8422_2325, connection_id.as_bytes()),
+        hash(0x8422_2325_cbf2_9ce4, connection_id.as_bytes()),
+    )
+}
+
+/// The 8 bytes of the span ID of a request, in hexadecimal.
+fn span_id(connection_id: &str, request_id: RequestId) -> String {
+    format!(
+        "{:016x}",
+        hash(
+            hash(0xcbf2_9ce4_8422_2325, connection_id.as_bytes()),
+            &request_id.to_be_bytes()
+        )
+    )
+}
+
+/// Serialize the spans as an OTLP `ExportTraceServiceRequest`.
+pub fn to_json(spans: &Spans) -> String {
+    let spans = spans
+        .iter()
+        .flat_map(|(connection_id, spans)| {
+            spans.iter().map(move |(request_id, span)| {
+                let start_at = span.start_at.timestamp_nanos_opt().unwrap_or_default();
+                let end_at = (span.start_at + span.duration)
+                    .timestamp_nanos_opt()
+                    .unwrap_or_default();
+                let mut attributes = vec![
+                    Attribute::string("matrix.connection_id", connection_id),
+                    Attribute::int("matrix.request_id", request_id),
+                    Attribute::string("http.request.method", &span.method),
+                    Attribute::string("url.full", &span.uri),
+                ];
+
+                if let Some(status) = span.status {
+                    attributes.push(Attribute::int("http.response.status_code", status));
+                }
+
+                if let Some(bytes) = span.request_bytes() {
+                    attributes.push(Attribute::int("http.request.body.size", bytes));
+                }
+
+                if let Some(bytes) = span.response_bytes() {
+                    attributes.push(Attribute::int("http.response.body.size", bytes));
+                }
+
+                if let Some(errcode) = &span.errcode {
+                    attributes.push(Attribute::string("matrix.errcode", errcode));
+                }
+
+                if let Some(error) = &span.error {
+                    attributes.push(Attribute::string("error.type", error));
+                }
+
+                Span {
+                    trace_id: trace_id(connection_id),
+                    span_id: span_id(connection_id, *request_id),
+                    name: span.endpoint(),
+                    kind: KIND_CLIENT,
+                    start_time_unix_nano: start_at.to_string(),
+                    end_time_unix_nano: end_at.to_string(),
+                    attributes,
+                    status: (!span.is_pending()).then(|| Status {
+                        code: if span.is_successful() {
+                            STATUS_OK
+                        } else {
+                            STATUS_ERROR
+                        },
+                    }),
+                }
+            })
+        })
+        .collect();
+
+    let request = Request {
+        resource_spans: [ResourceSpans {
+            resource: Resource {
+                attributes: vec![Attribute::string("service.name", "matrix-rust-sdk")],
+            },
+            scope_spans: [ScopeSpans {
+                scope: Scope {
+                    name: env!("CARGO_PKG_NAME"),
+                    version: env!("CARGO_PKG_VERSION"),
+                },
+                spans,
+            }],
+        }],
+    };
+
+    serde_json::to_string(&request).expect("Failed to serialize the OTLP request")
+}
+
+/// Whether `path` is the URL of an endpoint rather than a file.
+pub fn is_endpoint(path: &str) -> bool {
+    path.starts_with(SCHEME)
+}
+
+/// Check the URL of an endpoint, e.g. `http://localhost:4318`, and complete
+/// it with the default port and path of the traces.
+pub fn parse_endpoint(url: &str) -> Result<String, String> {
+    let Some(rest) = url.strip_prefix(SCHEME) else {
+        return Err(format!(
+            "`{url}` isn't an `http://` URL; the collectors listen in plain HTTP, e.g. `http://localhost:4318`"
+        ));
+    };
+    let (authority, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+
+    if authority.is_empty() {
+        return Err(format!("`{url}` has no host"));
+    }
+
+    let authority = if authority.contains(':') {
+        authority.to_owned()
+    } else {
+        format!("{authority}:{DEFAULT_PORT}")
+    };
+    let path = match path {
+        "" | "/" => DEFAULT_PATH,
+        path => path,
+    };
+
+    Ok(format!("{SCHEME}{authority}{path}"))
+}
+
+/// Push the traces to the endpoint at `url`, as checked by
+/// [`parse_endpoint`].
+pub fn push(url: &str, body: &[u8]) -> io::Result<()> {
+    let rest = url.strip_prefix(SCHEME).unwrap_or(url);
+    let (authority, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+
+    let mut stream = TcpStream::connect(authority)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+
+    write!(
+        stream,
+        "POST {path} HTTP/1.1\r\nHost: {authority}\r\nContent-Type: application/json\r\nContent-Length: {length}\r\nConnection: close\r\n\r\n",
+        length = body.len(),
+    )?;
+    stream.write_all(body)?;
+    stream.flush()?;
+
+    let mut status_line = String::new();
+    BufReader::new(stream).read_line(&mut status_line)?;
+
+    match status_line.split_whitespace().nth(1) {
+        Some(status) if status.starts_with('2') => Ok(()),
+        _ => Err(io::Error::other(format!(
+            "the collector has answered `{}`",
+            status_line.trim_end()
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Read, net::TcpListener, thread};
+
+    use super::*;
+    use crate::SpansBuilder;
+
+    #[test]
+    fn test_to_json() {
+        let mut spans = SpansBuilder::default();
+        spans.span(
+            "room-list",
+            7,
+            "https://example.org/_matrix/client/v3/sync",
+            Some(200),
+            0,
+            1_500,
+        );
+        let spans = spans.build();
+        let json = serde_json::from_str::<serde_json::Value>(&to_json(&spans)).unwrap();
+        let span = &json["resourceSpans"][0]["scopeSpans"][0]["spans"][0];
+
+        assert_eq!(span["traceId"].as_str().unwrap().len(), 32);
+        assert_eq!(span["spanId"].as_str().unwrap().len(), 16);
+        assert_eq!(span["name"], "POST /_matrix/client/v3/sync");
+        assert_eq!(span["kind"], 3);
+        assert_eq!(
+            span["endTimeUnixNano"]
+                .as_str()
+                .unwrap()
+                .parse::<i64>()
+                .unwrap()
+                - span["startTimeUnixNano"]
+                    .as_str()
+                    .unwrap()
+                    .parse::<i64>()
+                    .unwrap(),
+            1_500_000_000
+        );
+        assert_eq!(span["attributes"][1]["value"]["intValue"], "7");
+        assert_eq!(span["status"]["code"], 1);
+        // The IDs are stable, and differ per request.
+        assert_eq!(span_id("room-list", 7), span_id("room-list", 7));
+        assert_ne!(span_id("room-list", 7), span_id("room-list", 8));
+    }
+
+    #[test]
+    fn test_push() {
+        assert_eq!(
+            parse_endpoint("http://localhost").as_deref(),
+            Ok("http://localhost:4318/v1/traces")
+        );
+        assert_eq!(
+            parse_endpoint("http://tempo:4318/otlp/v1/traces").as_deref(),
+            Ok("http://tempo:4318/otlp/v1/traces")
+        );
+        assert!(parse_endpoint("https://tempo").is_err());
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let collector = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0; 1024];
+
+            while !request.ends_with(b"\r\n\r\n{}") {
+                let length = stream.read(&mut buffer).unwrap();
+                request.extend_from_slice(&buffer[..length]);
+            }
+
+            stream.write_all(b"HTTP/1.1 200 OK\r\n\r\n").unwrap();
+
+            String::from_utf8(request).unwrap()
+        });
+
+        push(&format!("http://{address}/v1/traces"), b"{}").unwrap();
+
+        let request = collector.join().unwrap();
+        assert!(request.starts_with("POST /v1/traces HTTP/1.1\r\n"));
+        assert!(request.ends_with("\r\n\r\n{}"));
+    }
+}
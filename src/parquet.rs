@@ -0,0 +1,297 @@
+//! Export the spans as an Apache Parquet file, for columnar pipelines like
+//! DataFusion or Polars.
+
+use std::sync::Arc;
+
+use ::parquet::{arrow::ArrowWriter, errors::ParquetError, file::properties::WriterProperties};
+use arrow_array::{
+    ArrayRef, DictionaryArray, Float64Array, Int16Array, Int64Array, RecordBatch, StringArray,
+    TimestampMicrosecondArray, UInt32Array, types::Int32Type,
+};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+
+use crate::{ConnectionId, RequestId, Span, duration, server_timing, size::Size, status};
+
+/// Maximum number of rows per row group: large enough to compress well, small
+/// enough to be read by chunks on large logs.
+const ROW_GROUP_SIZE: usize = 64 * 1024;
+
+/// Schema of the file.
+fn schema() -> SchemaRef {
+    let dictionary = || DataType::Dictionary(Box::new(DataType::Int32), Box::new(DataType::Utf8));
+
+    Arc::new(Schema::new(vec![
+        Field::new("connection_id", dictionary(), false),
+        Field::new("request_id", DataType::UInt32, false),
+        Field::new("method", dictionary(), false),
+        Field::new("endpoint_kind", dictionary(), false),
+        Field::new("uri", DataType::Utf8, false),
+        Field::new("status", DataType::Int16, true),
+        Field::new("status_label", DataType::Utf8, true),
+        Field::new(
+            "start_at",
+            DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+            false,
+        ),
+        Field::new("duration_ms", DataType::Float64, true),
+        Field::new("request_bytes", DataType::Int64, true),
+        Field::new("response_bytes", DataType::Int64, true),
+        Field::new("request_log_line", DataType::Int64, false),
+        Field::new("response_log_line", DataType::Int64, true),
+        Field::new("server_timing", DataType::Utf8, true),
+        Field::new("server_duration_ms", DataType::Float64, true),
+    ]))
+}
+
+/// Write the spans as a Parquet file.
+///
+/// The duration of a span without a response is null.
+pub fn to_parquet(spans: &[(&ConnectionId, RequestId, &Span)]) -> Result<Vec<u8>, ParquetError> {
+    let schema = schema();
+    let properties = WriterProperties::builder()
+        .set_max_row_group_row_count(Some(ROW_GROUP_SIZE))
+        .build();
+    let mut writer = ArrowWriter::try_new(Vec::new(), schema.clone(), Some(properties))?;
+
+    for spans in spans.chunks(ROW_GROUP_SIZE) {
+        writer.write(&to_record_batch(schema.clone(), spans)?)?;
+    }
+
+    writer.into_inner()
+}
+
+fn to_record_batch(
+    schema: SchemaRef,
+    spans: &[(&ConnectionId, RequestId, &Span)],
+) -> Result<RecordBatch, ParquetError> {
+    let dictionary = |values: Vec<&str>| -> ArrayRef {
+        Arc::new(values.into_iter().collect::<DictionaryArray<Int32Type>>())
+    };
+
+    let columns: Vec<ArrayRef> = vec![
+        dictionary(
+            spans
+                .iter()
+                .map(|(connection_id, _, _)| connection_id.as_str())
+                .collect(),
+        ),
+        Arc::new(
+            spans
+                .iter()
+                .map(|(_, request_id, _)| *request_id)
+                .collect::<UInt32Array>(),
+        ),
+        dictionary(
+            spans
+                .iter()
+                .map(|(_, _, span)| span.method.as_str())
+                .collect(),
+        ),
+        dictionary(
+            spans
+                .iter()
+                .map(|(_, _, span)| span.kind().as_str())
+                .collect(),
+        ),
+        Arc::new(
+            spans
+                .iter()
+                .map(|(_, _, span)| Some(span.uri.as_str()))
+                .collect::<StringArray>(),
+        ),
+        Arc::new(
+            spans
+                .iter()
+                .map(|(_, _, span)| span.status.and_then(|status| i16::try_from(status).ok()))
+                .collect::<Int16Array>(),
+        ),
+        Arc::new(
+            spans
+                .iter()
+                .map(|(_, _, span)| status::label(span))
+                .collect::<StringArray>(),
+        ),
+        Arc::new(
+            spans
+                .iter()
+                .map(|(_, _, span)| Some(span.start_at.timestamp_micros()))
+                .collect::<TimestampMicrosecondArray>()
+                .with_timezone("UTC"),
+        ),
+        Arc::new(
+            spans
+                .iter()
+                .map(|(_, _, span)| {
+                    span.response_log_line
+                        .map(|_| duration::to_milliseconds(span.duration))
+                })
+                .collect::<Float64Array>(),
+        ),
+        Arc::new(
+            spans
+                .iter()
+                .map(|(_, _, span)| bytes(span.request_size.as_ref()))
+                .collect::<Int64Array>(),
+        ),
+        Arc::new(
+            spans
+                .iter()
+                .map(|(_, _, span)| bytes(span.response_size.as_ref()))
+                .collect::<Int64Array>(),
+        ),
+        Arc::new(
+            spans
+                .iter()
+                .map(|(_, _, span)| Some(span.request_log_line as i64))
+                .collect::<Int64Array>(),
+        ),
+        Arc::new(
+            spans
+                .iter()
+                .map(|(_, _, span)| span.response_log_line.map(|line| line as i64))
+                .collect::<Int64Array>(),
+        ),
+        Arc::new(
+            spans
+                .iter()
+                .map(|(_, _, span)| {
+                    (!span.server_timing.is_empty())
+                        .then(|| server_timing::to_header(&span.server_timing))
+                })
+                .collect::<StringArray>(),
+        ),
+        Arc::new(
+            spans
+                .iter()
+                .map(|(_, _, span)| span.server_duration())
+                .collect::<Float64Array>(),
+        ),
+    ];
+
+    Ok(RecordBatch::try_new(schema, columns)?)
+}
+
+fn bytes(size: Option<&Size>) -> Option<i64> {
+    size.and_then(Size::bytes).map(|bytes| bytes as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use ::parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use arrow_array::{
+        Array,
+        cast::AsArray,
+        types::{Float64Type, TimestampMicrosecondType},
+    };
+    use chrono::TimeDelta;
+
+    use super::*;
+
+    fn span(uri: &str, status: Option<u16>, response_log_line: Option<usize>) -> Span {
+        Span {
+            request_size: Some(Size::new("92B")),
+            response_size: response_log_line.map(|_| Size::new("1.5kB")),
+            request_log_line: 12,
+            response_log_line,
+            ..Span::for_tests(uri, status, TimeDelta::microseconds(487_350))
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let room_list = "room-list".to_owned();
+        let encryption = "encryption".to_owned();
+        let first = span(
+            "https://example.org/_matrix/client/unstable/org.matrix.simplified_msc3575/sync",
+            Some(200),
+            Some(15),
+        );
+        let second = span(
+            "https://example.org/_matrix/client/v3/keys/query",
+            None,
+            None,
+        );
+        let spans = [(&room_list, 1, &first), (&encryption, 2, &second)];
+
+        let file = to_parquet(&spans).unwrap();
+        let batches = ParquetRecordBatchReaderBuilder::try_new(bytes::Bytes::from(file))
+            .unwrap()
+            .build()
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(batches.len(), 1);
+        let batch = &batches[0];
+        assert_eq!(batch.schema().fields(), schema().fields());
+        assert_eq!(batch.num_rows(), spans.len());
+
+        let strings = |name: &str| -> Vec<String> {
+            let column =
+                arrow_cast::cast(batch.column_by_name(name).unwrap(), &DataType::Utf8).unwrap();
+
+            column
+                .as_string::<i32>()
+                .iter()
+                .map(|value| value.unwrap().to_owned())
+                .collect()
+        };
+        let integers = |name: &str| -> Vec<Option<i64>> {
+            let column =
+                arrow_cast::cast(batch.column_by_name(name).unwrap(), &DataType::Int64).unwrap();
+
+            column
+                .as_primitive::<arrow_array::types::Int64Type>()
+                .iter()
+                .collect()
+        };
+
+        for (row, (connection_id, request_id, span)) in spans.iter().enumerate() {
+            assert_eq!(strings("connection_id")[row], **connection_id);
+            assert_eq!(integers("request_id")[row], Some(*request_id as i64));
+            assert_eq!(strings("method")[row], span.method);
+            assert_eq!(strings("endpoint_kind")[row], span.kind().as_str());
+            assert_eq!(strings("uri")[row], span.uri);
+            assert_eq!(integers("status")[row], span.status.map(i64::from));
+            assert_eq!(
+                batch
+                    .column_by_name("start_at")
+                    .unwrap()
+                    .as_primitive::<TimestampMicrosecondType>()
+                    .value(row),
+                span.start_at.timestamp_micros()
+            );
+            assert_eq!(
+                batch
+                    .column_by_name("duration_ms")
+                    .unwrap()
+                    .as_primitive::<Float64Type>()
+                    .iter()
+                    .nth(row)
+                    .unwrap(),
+                span.response_log_line.map(|_| 487.35)
+            );
+            assert_eq!(integers("request_bytes")[row], Some(92));
+            assert_eq!(
+                integers("response_bytes")[row],
+                span.response_size.as_ref().map(|_| 1500)
+            );
+            assert_eq!(
+                integers("request_log_line")[row],
+                Some(span.request_log_line as i64)
+            );
+            assert_eq!(
+                integers("response_log_line")[row],
+                span.response_log_line.map(|line| line as i64)
+            );
+        }
+
+        assert!(batch.column_by_name("status").unwrap().is_null(1));
+        let status_labels = batch
+            .column_by_name("status_label")
+            .unwrap()
+            .as_string::<i32>();
+        assert_eq!(status_labels.value(0), "200 OK");
+        assert!(status_labels.is_null(1));
+    }
+}
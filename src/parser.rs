@@ -0,0 +1,2290 @@
+//! Assemble the spans from the log lines, one line at a time, so that the logs
+//! can be streamed.
+
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap, VecDeque, btree_map::Entry},
+    mem,
+    ops::Sub,
+    thread,
+};
+
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use regex::{Regex, RegexBuilder};
+
+use crate::{
+    ConnectionId, NO_CONNECTION_ID, RequestId, Span, Spans, bodies,
+    conditions::{Condition, Conditions},
+    context::Window,
+    explain::Explanation,
+    frames, intermediary, json_format,
+    lifecycle::{self, Pattern},
+    pattern,
+    retry_after::RetryAfter,
+    server_timing, setup,
+    size::Size,
+    source::Location,
+    timestamp,
+    warnings::{self, Attachment, Target, Warning},
+};
+
+/// Maximum number of characters of the error messages kept on the spans.
+const MAXIMUM_ERROR_MESSAGE_LENGTH: usize = 200;
+
+/// Formats of the datetimes of the log lines, described for the diagnostic of
+/// a log without any matched line.
+const DATETIME_FORMATS: [&str; 6] = [
+    "2024-06-01T12:03:04.123Z",
+    "2024-06-01 12:03:04.123Z",
+    "2024-06-01T12:03:04Z",
+    "2024-06-01 12:03:04Z",
+    "2024-06-01T14:03:04.123+02:00",
+    "2024-06-01 12:03:04.123",
+];
+
+/// Message of the request lines, as opposed to the response lines.
+const REQUEST_MESSAGE: &str = "Sending request";
+
+/// Messages logged once by a process of the app, when its client is built:
+/// the next requests are from a new process.
+const BANNER_MESSAGES: [&str; 2] = ["Starting to build the Client", "Client initialized"];
+
+/// How far back in time a request can be sent after the latest one of the
+/// process. The lines of a process are roughly in the order of time, the
+/// ones of a process logged after it, e.g. of another log file, may not.
+const MAXIMUM_TIMESTAMP_REGRESSION: TimeDelta = TimeDelta::minutes(1);
+
+/// Maximum number of lines a record is reassembled from, see [`Record`], so
+/// that, e.g., a backtrace isn't reassembled with the line before it.
+const MAXIMUM_NUMBER_OF_CONTINUATIONS: usize = 4;
+
+/// Number of lines of a [`Batch`].
+const BATCH_SIZE: usize = 1_024;
+
+/// The latest line with a leading datetime. Some platforms wrap the long
+/// lines, and a line without a leading datetime is the continuation of the
+/// record before it: if this record hasn't matched, it is reassembled with
+/// its continuation, and parsed again.
+struct Record {
+    line: String,
+    line_nth: usize,
+    location: Option<Location>,
+    context: Option<Window>,
+    /// Number of matched lines before the record: the record hasn't matched
+    /// while the number is the same.
+    number_of_matched_lines: usize,
+    number_of_continuations: usize,
+}
+
+/// A Matrix error logged by the SDK, e.g. `errcode=M_LIMIT_EXCEEDED`.
+struct Error {
+    errcode: String,
+    message: Option<String>,
+}
+
+/// An error of a request which has failed without a response, e.g. a DNS
+/// failure, logged by the SDK with `error=`.
+struct TransportError {
+    message: String,
+    /// The `retry_count` field, if any.
+    retry_count: Option<u32>,
+}
+
+/// What the regexes capture in a record. Unlike the assembly of the spans, it
+/// doesn't depend on the state of the parser, so that the records of a batch
+/// are matched in parallel, see [`Parser::parse_batch`].
+#[derive(Default)]
+struct Matches<'h> {
+    lifecycle_event: Option<lifecycle::Event>,
+    server_timing: Option<Vec<server_timing::Metric>>,
+    retry_after: Option<RetryAfter>,
+    intermediary_headers: Vec<(&'h str, &'h str)>,
+    setup: setup::Setup,
+    body: Option<(bodies::Direction, String)>,
+    error: Option<Error>,
+    transport_error: Option<TransportError>,
+    warning: Option<(DateTime<FixedOffset>, Warning)>,
+    captures: Option<pattern::Captures<'h>>,
+}
+
+/// Lines read from a source, buffered to be parsed together, see
+/// [`Parser::parse_batch`]. The lines are kept once parsed, to reuse their
+/// allocations.
+#[derive(Default)]
+pub struct Batch {
+    lines: Vec<BatchLine>,
+    len: usize,
+}
+
+struct BatchLine {
+    /// The line, or its lossy conversion if it isn't valid UTF-8.
+    line: String,
+    is_invalid_utf8: bool,
+    location: Option<Location>,
+    /// The line rewritten in the text format, if it's a JSON one.
+    text: Option<String>,
+}
+
+impl Batch {
+    /// Buffer a line, and tell whether the batch is full.
+    pub fn push(&mut self, line: Result<&str, &[u8]>, location: Option<Location>) -> bool {
+        if self.len == self.lines.len() {
+            self.lines.push(BatchLine {
+                line: String::new(),
+                is_invalid_utf8: false,
+                location: None,
+                text: None,
+            });
+        }
+
+        let buffered = &mut self.lines[self.len];
+        buffered.line.clear();
+        buffered.is_invalid_utf8 = line.is_err();
+        buffered.location = location;
+
+        match line {
+            Ok(line) => buffered.line.push_str(line),
+            Err(line) => buffered.line.push_str(&String::from_utf8_lossy(line)),
+        }
+
+        self.len += 1;
+
+        self.len == BATCH_SIZE
+    }
+}
+
+pub struct Parser {
+    find_errcode: Regex,
+    find_error_message: Regex,
+    find_request_id: Regex,
+    find_connection_id: Regex,
+    find_parent: Regex,
+    find_warning: Regex,
+    find_server_timing: Regex,
+    find_retry_after: Regex,
+    find_intermediary_headers: Regex,
+    find_setup: Regex,
+    find_body: Regex,
+    find_txn_id: Regex,
+    find_transport_error: Regex,
+    find_retry_count: Regex,
+    find_retry_in: Regex,
+    find_datetime: Regex,
+    find_timestamp: Regex,
+    /// Patterns of the request and response lines, see [`pattern::Registry`].
+    pub patterns: pattern::Registry,
+    pub spans: Spans,
+    pub number_of_analysed_lines: usize,
+    pub number_of_matched_lines: usize,
+    /// Number of records wrapped over several lines which have matched once
+    /// reassembled, see [`Record`].
+    pub number_of_reassembled_records: usize,
+    /// Number of lines to record around the requests and the responses, see
+    /// [`crate::context`].
+    pub context: usize,
+    /// Names of the files of the source, by their index in the locations, to
+    /// tag the spans with the file of their request, see [`Span::source`].
+    pub file_names: Vec<String>,
+    /// Targets of the `WARN` and `ERROR` lines to attach to the spans, see
+    /// [`crate::warnings`].
+    pub warning_targets: Vec<Target>,
+    /// Maximum number of `WARN` and `ERROR` lines attached to a span.
+    pub warnings_per_span: usize,
+    /// Number of `WARN` and `ERROR` lines matching several spans.
+    pub number_of_ambiguous_warnings: usize,
+    /// Patterns of the app lifecycle events, see [`crate::lifecycle`].
+    pub lifecycle_patterns: Vec<Pattern>,
+    /// The app lifecycle events, in the order of the log.
+    pub lifecycle_events: Vec<lifecycle::Event>,
+    /// The recoverable conditions of the input, e.g. the malformed lines.
+    pub conditions: Conditions,
+    /// Whether to explain why the lines don't match even if some do, see
+    /// [`crate::explain`].
+    pub explain: bool,
+    /// Whether to capture the bodies of the requests and of the responses,
+    /// see [`crate::bodies`].
+    pub capture_bodies: bool,
+    /// Number of threads matching the records of a batch, see
+    /// [`Self::parse_batch`]. By default, the number of cores.
+    pub threads: usize,
+    /// Probed while no line has matched, or if `explain`.
+    explanation: Explanation,
+    /// Locations of the latest lines, to find the start of the context.
+    recent_locations: VecDeque<Location>,
+    /// The latest record, to reassemble it with its continuations.
+    latest_record: Option<Record>,
+    /// The span of the latest response, to which errors logged without a
+    /// request ID are attached.
+    latest_response: Option<(ConnectionId, RequestId)>,
+    /// The first token looking like a timestamp, and its log line, while no
+    /// line has matched.
+    first_timestamp: Option<(String, usize)>,
+    /// URIs of the requests sent by the current process of the app, per
+    /// request ID. The request IDs restart from `REQ-0` with the process: a
+    /// request ID sent again to another URI, or after its response, means
+    /// that the app has restarted.
+    sent_requests: HashMap<RequestId, String>,
+    /// Request IDs of the current process whose answered spans have been
+    /// taken while streaming, see [`Self::take_completed`], as ranges from
+    /// their first to their last ID: the IDs mostly follow each other, so the
+    /// ranges stay few.
+    taken_requests: BTreeMap<RequestId, RequestId>,
+    /// When the latest request of the current process of the app has been
+    /// sent: a request sent much earlier is from another process.
+    latest_request_at: Option<DateTime<FixedOffset>>,
+    /// Number of the current process of the app, from 1. The spans of the
+    /// next processes are under their connection ID suffixed with `@<nth>`,
+    /// see [`Self::connection_key`].
+    process_nth: usize,
+    /// The current iteration of the sync loop, per connection, see
+    /// [`Span::iteration`].
+    iterations: HashMap<ConnectionId, u32>,
+    /// The format of the datetimes of `--timestamp-format`, if any, instead
+    /// of the known ones.
+    timestamp_format: Option<timestamp::Format>,
+}
+
+impl Parser {
+    pub fn new() -> Self {
+        let find_errcode = Regex::new(r#"\berrcode[=:]\s*"?(?<errcode>M_[A-Z0-9_]+)"#)
+            .expect("Failed to build the `find_errcode` regex");
+        let find_error_message =
+            Regex::new(r#"\b(?:error|message)[=:]\s*"(?<message>(?:[^"\\]|\\.)*)""#)
+                .expect("Failed to build the `find_error_message` regex");
+        let find_request_id = pattern::build(&[pattern::CONNECTION, pattern::SEND]);
+        let find_connection_id =
+            Regex::new(r#">\ssync_once\{conn_id="(?<connection_id>[^"]+)"[^}]*\}"#)
+                .expect("Failed to build the `find_connection_id` regex");
+        let find_parent = Regex::new(r"(?:^|[\s>])(?<parent>[\w:]+)(?:\{[^}]*\})?\s>\ssend\{")
+            .expect("Failed to build the `find_parent` regex");
+        let find_warning = build_find_warning(pattern::DATETIME);
+        let find_server_timing =
+            Regex::new(r#"(?i)\bserver[-_]timing"?\s*[=:]\s*"?(?<value>(?:[^"\\|]|\\.)*)"#)
+                .expect("Failed to build the `find_server_timing` regex");
+        let find_retry_after = RegexBuilder::new(
+            r#"
+                # The `retry_after_ms` field of a Matrix error.
+                \bretry_after_ms"?\s*[=:]\s*"?(?<milliseconds>\d+)
+                # The `Retry-After` header, in seconds or as a HTTP-date.
+                | \bretry-after"?\s*[=:]\s*"?(?<header>
+                    \d+
+                    | [a-z]{3},\ \d{1,2}\ [a-z]{3}\ \d{4}\ \d{2}:\d{2}:\d{2}\ GMT
+                )
+            "#,
+        )
+        .ignore_whitespace(true)
+        .case_insensitive(true)
+        .build()
+        .expect("Failed to build the `find_retry_after` regex");
+        let find_intermediary_headers = RegexBuilder::new(
+            r#"
+                (?:^|[\s{,"])
+                (?<name>via|server|cf-ray|cf-cache-status|x-cache|x-served-by|x-amz-cf-id)
+                "?\s*[=:]\s*
+                # A quoted value, or an unquoted one up to a space or a comma.
+                (?:"(?<quoted>(?:[^"\\]|\\.)*)"|(?<value>[^\s,|"}]+))
+            "#,
+        )
+        .ignore_whitespace(true)
+        .case_insensitive(true)
+        .build()
+        .expect("Failed to build the `find_intermediary_headers` regex");
+        let find_setup = RegexBuilder::new(
+            r#"
+                (?:^|[\s{,"])
+                (?:
+                    # A duration, like `dns=3ms` or `connect_duration="20.5ms"`.
+                    (?<name>dns|connect|tls|ttfb)(?:_duration|_time)?
+                    "?\s*[=:]\s*"?
+                    (?<duration>\d+(?:\.\d+)?(?:ns|µs|us|ms|s)?)
+                    | (?:connection_)?reused"?\s*[=:]\s*"?(?<reused>true|false)
+                    | (?<reuse>reuse\ idle\ connection)
+                    | (?<new>starting\ new\ connection)
+                )
+            "#,
+        )
+        .ignore_whitespace(true)
+        .build()
+        .expect("Failed to build the `find_setup` regex");
+        let find_body = RegexBuilder::new(
+            r#"
+                \b(?<direction>request|response)[_\x20]body
+                # The message, e.g. `Request body`, may be followed by a
+                # `body` field.
+                (?:\x20body)?
+                "?\s*[=:]\s*
+                # A quoted value, or an unquoted one up to the location of the
+                # line, or to its end.
+                (?:"(?<quoted>(?:[^"\\]|\\.)*)"|(?<value>.+?))
+                (?:\x20\|\x20|$)
+            "#,
+        )
+        .ignore_whitespace(true)
+        .case_insensitive(true)
+        .build()
+        .expect("Failed to build the `find_body` regex");
+        let find_txn_id = Regex::new(r#"\btxn_id"?\s*[=:]\s*"?(?<txn_id>[^\s",}|]+)"#)
+            .expect("Failed to build the `find_txn_id` regex");
+        let find_transport_error =
+            Regex::new(r#"\berror"?\s*[=:]\s*(?:"(?<quoted>(?:[^"\\]|\\.)*)"|(?<value>[^\s|]+))"#)
+                .expect("Failed to build the `find_transport_error` regex");
+        let find_retry_count = Regex::new(r#"\bretry_count"?\s*[=:]\s*"?(?<retry_count>\d+)"#)
+            .expect("Failed to build the `find_retry_count` regex");
+        let find_retry_in = Regex::new(r#"\bretry_in"?\s*[=:]\s*"?(?<retry_in>[^\s",}|]+)"#)
+            .expect("Failed to build the `find_retry_in` regex");
+        let find_datetime = pattern::build(&["^", pattern::DATETIME]);
+        let find_timestamp = Regex::new(
+            r"\d{4}[-/]\d{2}[-/]\d{2}(?:[T\x20_]\d{2}:\d{2}\S*)?|\b\d{2}:\d{2}:\d{2}\S*",
+        )
+        .expect("Failed to build the `find_timestamp` regex");
+
+        Self {
+            find_errcode,
+            find_error_message,
+            find_request_id,
+            find_connection_id,
+            find_parent,
+            find_warning,
+            find_server_timing,
+            find_retry_after,
+            find_intermediary_headers,
+            find_setup,
+            find_body,
+            find_txn_id,
+            find_transport_error,
+            find_retry_count,
+            find_retry_in,
+            find_datetime,
+            find_timestamp,
+            patterns: pattern::Registry::default(),
+            spans: BTreeMap::new(),
+            number_of_analysed_lines: 0,
+            number_of_matched_lines: 0,
+            number_of_reassembled_records: 0,
+            context: 0,
+            file_names: Vec::new(),
+            warning_targets: Target::defaults(),
+            warnings_per_span: warnings::DEFAULT_PER_SPAN,
+            number_of_ambiguous_warnings: 0,
+            lifecycle_patterns: Vec::new(),
+            lifecycle_events: Vec::new(),
+            conditions: Conditions::default(),
+            explain: false,
+            capture_bodies: false,
+            threads: thread::available_parallelism()
+                .map(usize::from)
+                .unwrap_or(1),
+            explanation: Explanation::new(),
+            recent_locations: VecDeque::new(),
+            latest_record: None,
+            latest_response: None,
+            first_timestamp: None,
+            sent_requests: HashMap::new(),
+            taken_requests: BTreeMap::new(),
+            latest_request_at: None,
+            process_nth: 1,
+            iterations: HashMap::new(),
+            timestamp_format: None,
+        }
+    }
+
+    /// Match and parse the datetimes of the lines with a format of
+    /// `--timestamp-format`.
+    pub fn set_timestamp_format(&mut self, format: timestamp::Format) {
+        self.find_warning = build_find_warning(format.pattern());
+        self.find_datetime = pattern::build(&["^", format.pattern()]);
+        self.patterns.set_datetime(format.pattern());
+        self.timestamp_format = Some(format);
+    }
+
+    /// Parse the datetime of a line.
+    fn parse_datetime(&self, datetime: &str) -> Option<DateTime<FixedOffset>> {
+        match &self.timestamp_format {
+            Some(format) => format.parse(datetime),
+            None => timestamp::parse(datetime),
+        }
+    }
+
+    /// Parse a line read from a source, or skip it if it isn't valid UTF-8.
+    pub fn parse(
+        &mut self,
+        line: Result<&str, &[u8]>,
+        location: Option<Location>,
+    ) -> Option<&Span> {
+        match line {
+            Ok(line) => self.parse_line(line, location),
+            Err(line) => {
+                self.skip_invalid_utf8(&String::from_utf8_lossy(line));
+
+                None
+            }
+        }
+    }
+
+    /// Skip a line which isn't valid UTF-8, given as its lossy conversion.
+    fn skip_invalid_utf8(&mut self, line: &str) {
+        self.number_of_analysed_lines += 1;
+        self.conditions.record(
+            Condition::InvalidUtf8,
+            "invalid UTF-8",
+            self.number_of_analysed_lines,
+            line,
+        );
+    }
+
+    /// Parse the lines of a batch, and empty it, like [`Self::parse`] would
+    /// one line at a time.
+    ///
+    /// The lines are split in a chunk per thread, whose records are matched
+    /// in parallel, see [`Matches`]. The spans are then assembled from them
+    /// in the order of the lines.
+    pub fn parse_batch(&mut self, batch: &mut Batch) {
+        let lines = &mut batch.lines[..batch.len];
+        let chunk_size = lines.len().div_ceil(self.threads.max(1)).max(1);
+        let first_line_nth = self.number_of_analysed_lines + 1;
+
+        thread::scope(|scope| {
+            for chunk in lines.chunks_mut(chunk_size) {
+                scope.spawn(|| {
+                    for line in chunk {
+                        line.text = (!line.is_invalid_utf8)
+                            .then(|| json_format::to_text(&line.line))
+                            .flatten();
+                    }
+                });
+            }
+        });
+
+        let parser = &*self;
+        let matches = thread::scope(|scope| {
+            let chunks = lines
+                .chunks(chunk_size)
+                .enumerate()
+                .map(|(chunk_nth, chunk)| {
+                    scope.spawn(move || {
+                        chunk
+                            .iter()
+                            .enumerate()
+                            .map(|(nth, line)| {
+                                (!line.is_invalid_utf8).then(|| {
+                                    parser.match_record(
+                                        line.text.as_deref().unwrap_or(&line.line),
+                                        first_line_nth + chunk_nth * chunk_size + nth,
+                                    )
+                                })
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            chunks
+                .into_iter()
+                .flat_map(|chunk| chunk.join().expect("The matching never panics"))
+                .collect::<Vec<_>>()
+        });
+
+        for (line, matches) in lines.iter().zip(matches) {
+            match matches {
+                Some(matches) => {
+                    self.parse_matched(
+                        &line.line,
+                        line.text.as_deref(),
+                        line.location,
+                        Some(matches),
+                    );
+                }
+                None => self.skip_invalid_utf8(&line.line),
+            }
+        }
+
+        batch.len = 0;
+    }
+
+    /// Parse the next log line.
+    ///
+    /// `location` is where the line is in the files of the source, if any.
+    ///
+    /// Returns the span if this line has completed it, i.e. if it's a response.
+    pub fn parse_line(&mut self, line: &str, location: Option<Location>) -> Option<&Span> {
+        // The format is detected per line, so that mixed logs are parsed too.
+        let text = json_format::to_text(line);
+
+        self.parse_matched(line, text.as_deref(), location, None)
+    }
+
+    /// Parse the next log line, `text` being the line rewritten in the text
+    /// format if it's a JSON one, and `matches` its record once matched, if
+    /// it has been matched ahead, see [`Self::parse_batch`].
+    fn parse_matched<'h>(
+        &mut self,
+        line: &'h str,
+        text: Option<&'h str>,
+        location: Option<Location>,
+        matches: Option<Matches<'h>>,
+    ) -> Option<&Span> {
+        self.number_of_analysed_lines += 1;
+
+        let line_nth = self.number_of_analysed_lines;
+        let context = location
+            .filter(|_| self.context > 0)
+            .map(|location| self.record_location(location));
+
+        let has_datetime = text.is_none() && self.find_datetime.is_match(line);
+        let continued_record = (text.is_none() && !has_datetime && !line.trim().is_empty())
+            .then(|| {
+                self.latest_record.take_if(|record| {
+                    record.number_of_matched_lines == self.number_of_matched_lines
+                        && record.number_of_continuations < MAXIMUM_NUMBER_OF_CONTINUATIONS
+                })
+            })
+            .flatten();
+
+        let is_completed = match continued_record {
+            Some(mut record) => {
+                record.line.push_str(line);
+                record.number_of_continuations += 1;
+
+                let line = record.line.clone();
+                let (line_nth, location, context) =
+                    (record.line_nth, record.location, record.context);
+                self.latest_record = Some(record);
+
+                // The beginning of the record has already been parsed: if the
+                // record still doesn't match, its conditions are the same.
+                let number_of_matched_lines = self.number_of_matched_lines;
+                let conditions = self.conditions.clone();
+                let number_of_ambiguous_warnings = self.number_of_ambiguous_warnings;
+                let matches = self.match_record(&line, line_nth);
+                let is_completed =
+                    self.parse_record(&line, matches, line_nth, location, context, true);
+
+                if self.number_of_matched_lines > number_of_matched_lines {
+                    self.number_of_reassembled_records += 1;
+                } else {
+                    self.conditions = conditions;
+                    self.number_of_ambiguous_warnings = number_of_ambiguous_warnings;
+                }
+
+                is_completed
+            }
+            None => {
+                if has_datetime {
+                    let mut record_line = self
+                        .latest_record
+                        .take()
+                        .map(|record| record.line)
+                        .unwrap_or_default();
+                    record_line.clear();
+                    record_line.push_str(line);
+
+                    self.latest_record = Some(Record {
+                        line: record_line,
+                        line_nth,
+                        location,
+                        context,
+                        number_of_matched_lines: self.number_of_matched_lines,
+                        number_of_continuations: 0,
+                    });
+                }
+
+                let line = text.unwrap_or(line);
+                let matches = matches.unwrap_or_else(|| self.match_record(line, line_nth));
+
+                self.parse_record(line, matches, line_nth, location, context, false)
+            }
+        };
+
+        if !is_completed {
+            return None;
+        }
+
+        let (connection_id, request_id) = self.latest_response.as_ref()?;
+
+        self.spans.get(connection_id)?.get(request_id)
+    }
+
+    /// Parse a record, i.e. a line, or a line reassembled with its
+    /// continuations, see [`Record`].
+    ///
+    /// Returns whether this record has completed a span, i.e. if it's a
+    /// response.
+    fn parse_record(
+        &mut self,
+        line: &str,
+        matches: Matches<'_>,
+        line_nth: usize,
+        location: Option<Location>,
+        context: Option<Window>,
+        is_reassembled: bool,
+    ) -> bool {
+        // The beginning of a reassembled record has already been probed.
+        if !is_reassembled && (self.explain || self.number_of_matched_lines == 0) {
+            self.explanation.probe(line, line_nth);
+        }
+
+        if self.number_of_matched_lines == 0 && self.first_timestamp.is_none() {
+            self.first_timestamp = self
+                .find_timestamp
+                .find(line)
+                .map(|timestamp| (timestamp.as_str().to_owned(), line_nth));
+        }
+
+        // A banner before any request is the one of the current process.
+        if !is_reassembled
+            && !self.sent_requests.is_empty()
+            && BANNER_MESSAGES.iter().any(|message| line.contains(message))
+        {
+            self.restart();
+        }
+
+        let Matches {
+            lifecycle_event,
+            server_timing,
+            retry_after,
+            intermediary_headers,
+            setup,
+            body,
+            error,
+            transport_error,
+            warning,
+            captures,
+        } = matches;
+
+        if let Some(event) = lifecycle_event {
+            self.number_of_matched_lines += 1;
+            self.lifecycle_events.push(event);
+
+            return false;
+        }
+
+        let captures = match captures {
+            Some(captures)
+                if (server_timing.is_none()
+                    && retry_after.is_none()
+                    && intermediary_headers.is_empty()
+                    && setup.is_empty()
+                    && body.is_none()
+                    && error.is_none()
+                    && warning.is_none())
+                    || captures.name("status").is_some()
+                    // A transport error ends a request, like a response.
+                    || transport_error.is_some() =>
+            {
+                captures
+            }
+            // A line with the `Server-Timing` header, a retry-after, the
+            // headers of an intermediary, the setup of the connection, a body,
+            // an error or a warning but no status isn't a response: it
+            // describes what happens to a request.
+            _ => {
+                let is_secondary_only =
+                    server_timing.is_none() && error.is_none() && warning.is_none();
+                let is_retry_after_only = is_secondary_only && retry_after.is_some();
+                let setup_is_empty = setup.is_empty();
+
+                // Header values, e.g. `server`, are too generic to be attached
+                // to a span without the request ID of the line.
+                if !intermediary_headers.is_empty()
+                    && let Some((connection_id, request_id)) = self.request_of(line)
+                    && let Some(span) = self
+                        .spans
+                        .get_mut(&*connection_id)
+                        .and_then(|spans| spans.get_mut(&request_id))
+                {
+                    for (name, value) in &intermediary_headers {
+                        span.intermediary.insert(name, value);
+                    }
+
+                    if is_secondary_only && retry_after.is_none() {
+                        self.number_of_matched_lines += 1;
+                    }
+                }
+
+                // Like the headers, the setup is attached with the request ID
+                // of the line only.
+                if !setup.is_empty()
+                    && let Some((connection_id, request_id)) = self.request_of(line)
+                    && let Some(span) = self
+                        .spans
+                        .get_mut(&*connection_id)
+                        .and_then(|spans| spans.get_mut(&request_id))
+                {
+                    span.setup.extend(setup);
+
+                    if is_secondary_only && retry_after.is_none() && intermediary_headers.is_empty()
+                    {
+                        self.number_of_matched_lines += 1;
+                    }
+                }
+
+                // Like the headers too, the bodies are attached with the
+                // request ID of the line only.
+                if let Some((direction, body)) = body
+                    && let Some((connection_id, request_id)) = self.request_of(line)
+                    && let Some(span) = self
+                        .spans
+                        .get_mut(&*connection_id)
+                        .and_then(|spans| spans.get_mut(&request_id))
+                {
+                    match direction {
+                        bodies::Direction::Request => span.bodies.request = Some(body),
+                        bodies::Direction::Response => span.bodies.response = Some(body),
+                    }
+
+                    if is_secondary_only
+                        && retry_after.is_none()
+                        && intermediary_headers.is_empty()
+                        && setup_is_empty
+                    {
+                        self.number_of_matched_lines += 1;
+                    }
+                }
+
+                if let Some(retry_after) = retry_after
+                    && let Some(span) = self.adjacent_span(line)
+                {
+                    span.retry_after = Some(retry_after);
+
+                    if is_retry_after_only {
+                        self.number_of_matched_lines += 1;
+                    }
+                }
+
+                if let Some(metrics) = server_timing {
+                    self.attach_server_timing(line, metrics);
+                } else if let Some(error) = error {
+                    self.number_of_matched_lines += 1;
+                    self.attach_error(line, error);
+                } else if let Some((date_time, warning)) = warning {
+                    self.attach_warning(line, date_time, warning);
+                }
+
+                return false;
+            }
+        };
+
+        let group = |name| {
+            captures
+                .name(name)
+                .ok_or_else(|| (Condition::MalformedCaptures, format!("missing `{name}`")))
+        };
+        let mandatory = (|| {
+            Ok((
+                self.parse_datetime(group("datetime")?.as_str())
+                    .ok_or_else(|| {
+                        (
+                            Condition::UnparseableTimestamps,
+                            "invalid `datetime`".to_owned(),
+                        )
+                    })?,
+                group("request_id")?
+                    .as_str()
+                    .parse::<RequestId>()
+                    .map_err(|_| {
+                        (
+                            Condition::MalformedCaptures,
+                            "invalid `request_id`".to_owned(),
+                        )
+                    })?,
+                group("method")?.as_str(),
+                group("uri")?.as_str(),
+            ))
+        })();
+        let (date_time, request_id, method, uri) = match mandatory {
+            Ok(mandatory) => mandatory,
+            Err((condition, shape)) => {
+                self.conditions.record(condition, &shape, line_nth, line);
+
+                return false;
+            }
+        };
+
+        self.number_of_matched_lines += 1;
+
+        let status = captures.name("status").map(|status| status.as_str());
+
+        let connection_id = captures
+            .name("connection_id")
+            .map(|connection_id| connection_id.as_str())
+            .unwrap_or(NO_CONNECTION_ID);
+
+        // A request ID sent again, to another URI or after its response, is
+        // from a restarted app, unlike a retry. So is a request sent long
+        // before the latest one.
+        if status.is_none() && line.contains(REQUEST_MESSAGE) {
+            if self.latest_request_at.is_some_and(|latest_request_at| {
+                date_time < latest_request_at - MAXIMUM_TIMESTAMP_REGRESSION
+            }) {
+                self.restart();
+            }
+
+            self.latest_request_at = self.latest_request_at.max(Some(date_time));
+
+            let is_answered = self.is_taken(request_id)
+                || self
+                    .spans
+                    .get(&*self.connection_key(connection_id))
+                    .and_then(|spans| spans.get(&request_id))
+                    .is_some_and(|span| span.response_log_line.is_some() && !span.has_failed());
+
+            match self.sent_requests.insert(request_id, uri.to_owned()) {
+                None if is_answered => {
+                    self.restart();
+                    self.sent_requests.insert(request_id, uri.to_owned());
+                }
+                Some(sent_uri) if sent_uri != uri || is_answered => {
+                    self.restart();
+                    self.sent_requests.insert(request_id, uri.to_owned());
+                }
+                _ => {}
+            }
+        }
+
+        let is_in_sync_loop = captures.name("connection_id").is_some();
+        let iteration = captures
+            .name("iteration")
+            .and_then(|iteration| iteration.as_str().parse().ok());
+        let connection_id = self.connection_key(connection_id);
+        let [request_size, response_size] = ["request_size", "response_size"].map(|name| {
+            let size = captures.name(name).map(|size| Size::new(size.as_str()));
+
+            // The size is kept as logged, without its number of bytes.
+            if size.as_ref().is_some_and(|size| size.bytes().is_none()) {
+                self.conditions.record(
+                    Condition::MalformedCaptures,
+                    &format!("invalid `{name}`"),
+                    line_nth,
+                    line,
+                );
+            }
+
+            size
+        });
+
+        let txn_id = self
+            .find_txn_id
+            .captures(line)
+            .map(|captures| captures["txn_id"].to_owned());
+
+        let spans_for_connection_id = self
+            .spans
+            .entry(connection_id.clone().into_owned())
+            .or_default();
+        // The `pos` of a sliding sync is compared to the one of the previous
+        // request of the connection.
+        let previous_pos = spans_for_connection_id
+            .range(..request_id)
+            .next_back()
+            .and_then(|(_, previous)| previous.pos.clone());
+
+        match spans_for_connection_id.entry(request_id) {
+            Entry::Vacant(entry) => {
+                let span = entry.insert(Span {
+                    status: None,
+                    method: method.to_owned(),
+                    uri: uri.to_owned(),
+                    request_size,
+                    response_size,
+                    start_at: date_time,
+                    duration: TimeDelta::zero(),
+                    request_log_line: line_nth,
+                    response_log_line: None,
+                    request_context: context,
+                    response_context: None,
+                    errcode: None,
+                    error_message: None,
+                    warnings: Vec::new(),
+                    server_timing: Vec::new(),
+                    retry_after: None,
+                    app_state: self
+                        .lifecycle_events
+                        .iter()
+                        .rev()
+                        .map(|event| event.state)
+                        .find(lifecycle::State::is_app_state),
+                    intermediary: intermediary::Headers::default(),
+                    setup: setup.clone(),
+                    bodies: bodies::Bodies::default(),
+                    process_nth: self.process_nth,
+                    restarted_as: None,
+                    pos: None,
+                    pos_stalled: false,
+                    pos_reset: false,
+                    txn_id,
+                    fields: captures
+                        .fields
+                        .iter()
+                        .map(|(name, value)| (name.clone(), (*value).to_owned()))
+                        .collect(),
+                    error: None,
+                    retries: 0,
+                    iteration: 0,
+                    parent: self
+                        .find_parent
+                        .captures(line)
+                        .map(|captures| captures["parent"].to_owned()),
+                    frames: frames::capture(line),
+                    source: location
+                        .and_then(|location| self.file_names.get(location.file_nth))
+                        .cloned(),
+                });
+
+                if span.is_sync() {
+                    span.pos = span.query_parameter("pos");
+                    span.pos_stalled = span.pos.is_some() && span.pos == previous_pos;
+                    span.pos_reset = span.pos.is_none() && previous_pos.is_some();
+                }
+
+                // Each `sync_once` sends one sync: without an explicit
+                // iteration, a new sync of a connection opens a new one.
+                if is_in_sync_loop {
+                    let current = self
+                        .iterations
+                        .entry(connection_id.into_owned())
+                        .or_default();
+
+                    match iteration {
+                        Some(iteration) => *current = iteration,
+                        None if span.is_sync() => *current += 1,
+                        None => {}
+                    }
+
+                    span.iteration = *current;
+                }
+
+                false
+            }
+            Entry::Occupied(entry) => {
+                let span = entry.into_mut();
+
+                // A request sent again after a transport error is retried by
+                // the SDK: it is pending again, under the same request ID.
+                if status.is_none() && line.contains(REQUEST_MESSAGE) && span.has_failed() {
+                    span.retries += 1;
+                    span.response_log_line = None;
+                    span.response_context = None;
+
+                    return false;
+                }
+
+                if span.response_log_line.is_some() {
+                    self.conditions.record(
+                        Condition::DuplicateRequestIds,
+                        "a request ID reused after its response",
+                        line_nth,
+                        line,
+                    );
+                }
+
+                if date_time < span.start_at {
+                    self.conditions.record(
+                        Condition::NegativeDurations,
+                        "a response before its request",
+                        line_nth,
+                        line,
+                    );
+                }
+
+                if let Some(status) = status {
+                    match status.parse() {
+                        Ok(status) => span.status = Some(status),
+                        // The response is kept, without a status.
+                        Err(_) => self.conditions.record(
+                            Condition::MalformedCaptures,
+                            "invalid `status`",
+                            line_nth,
+                            line,
+                        ),
+                    }
+                }
+
+                span.duration = date_time.sub(&span.start_at);
+
+                if let Some(request_size) = request_size {
+                    span.request_size = Some(request_size);
+                }
+
+                if let Some(response_size) = response_size {
+                    span.response_size = Some(response_size);
+                }
+
+                span.response_log_line = Some(line_nth);
+                span.response_context = context;
+
+                if let Some(error) = error {
+                    span.errcode = Some(error.errcode);
+                    span.error_message = error.message;
+                }
+
+                if let Some(metrics) = server_timing {
+                    span.server_timing = metrics;
+                }
+
+                if let Some(retry_after) = retry_after {
+                    span.retry_after = Some(retry_after);
+                }
+
+                span.setup.extend(setup);
+
+                for (name, value) in &intermediary_headers {
+                    span.intermediary.insert(name, value);
+                }
+
+                if txn_id.is_some() {
+                    span.txn_id = txn_id;
+                }
+
+                for (name, value) in &captures.fields {
+                    span.fields.insert(name.clone(), (*value).to_owned());
+                }
+
+                if let Some(transport_error) = transport_error {
+                    span.error = Some(transport_error.message);
+                    span.retries = span
+                        .retries
+                        .max(transport_error.retry_count.unwrap_or_default());
+                }
+
+                self.latest_response = Some((connection_id.into_owned(), request_id));
+
+                true
+            }
+        }
+    }
+
+    /// Match a record with the regexes, see [`Matches`].
+    fn match_record<'h>(&self, line: &'h str, line_nth: usize) -> Matches<'h> {
+        if let Some(event) = self.capture_lifecycle_event(line, line_nth) {
+            return Matches {
+                lifecycle_event: Some(event),
+                ..Matches::default()
+            };
+        }
+
+        let server_timing = self
+            .find_server_timing
+            .captures(line)
+            .map(|captures| server_timing::parse(&captures["value"]))
+            .filter(|metrics| !metrics.is_empty());
+        let retry_after = self.capture_retry_after(line);
+        let intermediary_headers = self.capture_intermediary_headers(line);
+        let setup = self.capture_setup(line);
+        let body = self
+            .capture_bodies
+            .then(|| self.capture_body(line))
+            .flatten();
+        let error = self.capture_error(line);
+        let transport_error = error
+            .is_none()
+            .then(|| self.capture_transport_error(line))
+            .flatten();
+        let warning = error
+            .is_none()
+            .then(|| self.capture_warning(line, line_nth))
+            .flatten();
+        Matches {
+            lifecycle_event: None,
+            server_timing,
+            retry_after,
+            intermediary_headers,
+            setup,
+            body,
+            error,
+            transport_error,
+            warning,
+            captures: self.patterns.captures(line),
+        }
+    }
+
+    /// Start a new process of the app: the spans of the previous ones are
+    /// left untouched.
+    fn restart(&mut self) {
+        self.process_nth += 1;
+        self.sent_requests.clear();
+        self.taken_requests.clear();
+        self.latest_request_at = None;
+    }
+
+    /// The connection and the ID of the latest span with a response.
+    pub fn latest_response(&self) -> Option<(&ConnectionId, RequestId)> {
+        self.latest_response
+            .as_ref()
+            .map(|(connection_id, request_id)| (connection_id, *request_id))
+    }
+
+    /// Number of restarts of the app detected in the logs.
+    pub fn number_of_restarts(&self) -> usize {
+        self.process_nth - 1
+    }
+
+    /// Explain why the lines don't match, if no line has matched or if
+    /// `explain`.
+    pub fn explanation(&self) -> Option<String> {
+        (self.number_of_analysed_lines > 0 && (self.explain || self.number_of_matched_lines == 0))
+            .then(|| self.explanation.to_text())
+    }
+
+    /// Explain why no line has matched, if so: the first token looking like
+    /// a timestamp may be in a format which isn't supported.
+    pub fn no_match_diagnostic(&self) -> Option<String> {
+        if self.number_of_matched_lines > 0 || self.number_of_analysed_lines == 0 {
+            return None;
+        }
+
+        let found = match &self.first_timestamp {
+            Some((timestamp, log_line)) => {
+                format!(
+                    "the first timestamp-looking token is `{timestamp}`, at log line {log_line}"
+                )
+            }
+            None => "no timestamp-looking token has been found".to_owned(),
+        };
+
+        Some(format!(
+            "No line has matched: {found}; the supported formats are {formats}",
+            formats = DATETIME_FORMATS
+                .map(|format| format!("`{format}`"))
+                .join(", "),
+        ))
+    }
+
+    /// Take the completed spans out of the parser, to bound its memory while
+    /// streaming, see [`crate::stream`]. The latest span of each connection
+    /// is kept, as the next sync of the connection is compared to it, and so
+    /// is the latest response, to which the next errors may be attached.
+    /// `all` takes every span, once the source is exhausted.
+    ///
+    /// The state kept per request for the taken spans is evicted too, so that
+    /// the memory doesn't grow with the log. Only the IDs of the answered
+    /// requests are kept, as ranges, to detect the restarts sending them
+    /// again.
+    pub fn take_completed(&mut self, all: bool) -> Spans {
+        if all {
+            self.sent_requests.clear();
+            self.taken_requests.clear();
+
+            return mem::take(&mut self.spans);
+        }
+
+        let mut taken = Spans::new();
+
+        for (connection_id, spans) in &mut self.spans {
+            let latest = spans.keys().next_back().copied();
+            let (completed, kept) = mem::take(spans).into_iter().partition::<BTreeMap<_, _>, _>(
+                |(request_id, span)| {
+                    !span.is_pending()
+                        && Some(*request_id) != latest
+                        && self.latest_response.as_ref()
+                            != Some(&(connection_id.clone(), *request_id))
+                },
+            );
+            *spans = kept;
+
+            if !completed.is_empty() {
+                taken.insert(connection_id.clone(), completed);
+            }
+        }
+
+        for (request_id, span) in taken.values().flat_map(BTreeMap::iter) {
+            if span.process_nth != self.process_nth {
+                continue;
+            }
+
+            self.sent_requests.remove(request_id);
+
+            if !span.has_failed() {
+                self.insert_taken(*request_id);
+            }
+        }
+
+        taken
+    }
+
+    /// Whether the answered span of a request ID has been taken, see
+    /// [`Self::taken_requests`].
+    fn is_taken(&self, request_id: RequestId) -> bool {
+        self.taken_requests
+            .range(..=request_id)
+            .next_back()
+            .is_some_and(|(_, last)| *last >= request_id)
+    }
+
+    /// Add a request ID to [`Self::taken_requests`], merging the ranges it
+    /// joins.
+    fn insert_taken(&mut self, request_id: RequestId) {
+        if self.is_taken(request_id) {
+            return;
+        }
+
+        let previous = self
+            .taken_requests
+            .range(..request_id)
+            .next_back()
+            .map(|(first, last)| (*first, *last))
+            .filter(|(_, last)| last.checked_add(1) == Some(request_id));
+        let next = request_id
+            .checked_add(1)
+            .and_then(|next| self.taken_requests.remove_entry(&next));
+        let first = previous.map_or(request_id, |(first, _)| first);
+        let last = next.map_or(request_id, |(_, last)| last);
+
+        self.taken_requests.insert(first, last);
+    }
+
+    /// Record the spans without a response which have started more than
+    /// `threshold` before the end of the log, as unterminated. Call it once the
+    /// source is exhausted.
+    pub fn record_unterminated_spans(&mut self, threshold: TimeDelta) {
+        let Some(end_at) = self
+            .spans
+            .values()
+            .flat_map(|spans| spans.values())
+            .map(|span| span.start_at + span.duration)
+            .max()
+        else {
+            return;
+        };
+
+        for span in self.spans.values().flat_map(|spans| spans.values()) {
+            if span.response_log_line.is_none() && end_at - span.start_at > threshold {
+                self.conditions.record(
+                    Condition::UnterminatedSpans,
+                    &format!("no response to `{}`", span.endpoint()),
+                    span.request_log_line,
+                    &span.uri,
+                );
+            }
+        }
+    }
+
+    /// Capture the app lifecycle event of a line, if any. The lines of the
+    /// HTTP client are never lifecycle events.
+    fn capture_lifecycle_event(&self, line: &str, line_nth: usize) -> Option<lifecycle::Event> {
+        if self.lifecycle_patterns.is_empty() || line.contains("matrix_sdk::http_client") {
+            return None;
+        }
+
+        let state = Pattern::state_of(&self.lifecycle_patterns, line)?;
+        let at = self.parse_datetime(&self.find_datetime.captures(line)?["datetime"])?;
+
+        Some(lifecycle::Event {
+            state,
+            at,
+            log_line: line_nth,
+        })
+    }
+
+    /// Capture the Matrix error of a line, if any.
+    fn capture_error(&self, line: &str) -> Option<Error> {
+        let errcode = self.find_errcode.captures(line)?["errcode"].to_owned();
+        let message = self.find_error_message.captures(line).map(|captures| {
+            let message = captures["message"].replace(r#"\""#, "\"");
+
+            match message.char_indices().nth(MAXIMUM_ERROR_MESSAGE_LENGTH) {
+                Some((end, _)) => format!("{}…", &message[..end]),
+                None => message.to_owned(),
+            }
+        });
+
+        Some(Error { errcode, message })
+    }
+
+    /// Capture the error of a request which has failed without a response, if
+    /// any, with the delay before it is retried.
+    fn capture_transport_error(&self, line: &str) -> Option<TransportError> {
+        let captures = self.find_transport_error.captures(line)?;
+        let message = match (captures.name("quoted"), captures.name("value")) {
+            (Some(quoted), _) => quoted.as_str().replace(r#"\""#, "\""),
+            (None, Some(value)) => value.as_str().to_owned(),
+            (None, None) => return None,
+        };
+        let mut message = match message.char_indices().nth(MAXIMUM_ERROR_MESSAGE_LENGTH) {
+            Some((end, _)) => format!("{}…", &message[..end]),
+            None => message,
+        };
+
+        if let Some(captures) = self.find_retry_in.captures(line) {
+            message.push_str(&format!("; retrying in {}", &captures["retry_in"]));
+        }
+
+        Some(TransportError {
+            message,
+            retry_count: self
+                .find_retry_count
+                .captures(line)
+                .and_then(|captures| captures["retry_count"].parse().ok()),
+        })
+    }
+
+    /// Capture the `WARN` or `ERROR` line of a collected target, if any.
+    fn capture_warning(
+        &self,
+        line: &str,
+        line_nth: usize,
+    ) -> Option<(DateTime<FixedOffset>, Warning)> {
+        if self.warnings_per_span == 0 {
+            return None;
+        }
+
+        let captures = self.find_warning.captures(line)?;
+
+        if !self
+            .warning_targets
+            .iter()
+            .any(|target| target.matches(&captures["target"]))
+        {
+            return None;
+        }
+
+        let date_time = self.parse_datetime(&captures["datetime"])?;
+
+        Some((
+            date_time,
+            Warning {
+                level: captures["level"].to_owned(),
+                message: captures["message"].to_owned(),
+                log_line: line_nth,
+            },
+        ))
+    }
+
+    /// Attach the metrics of a `Server-Timing` header to the span of the
+    /// request ID of the line. Lines without a request ID are ignored.
+    fn attach_server_timing(&mut self, line: &str, metrics: Vec<server_timing::Metric>) {
+        let Some(span) = self
+            .request_of(line)
+            .and_then(|(connection_id, request_id)| {
+                self.spans
+                    .get_mut(&*connection_id)
+                    .and_then(|spans| spans.get_mut(&request_id))
+            })
+        else {
+            return;
+        };
+
+        span.server_timing = metrics;
+        self.number_of_matched_lines += 1;
+    }
+
+    /// Get the key of the spans of a connection in the current process of the
+    /// app: its connection ID, suffixed with `@<nth>` after a restart so that
+    /// the restarted request IDs don't collide.
+    fn connection_key<'a>(&self, connection_id: &'a str) -> Cow<'a, str> {
+        if self.process_nth == 1 {
+            Cow::Borrowed(connection_id)
+        } else {
+            Cow::Owned(format!("{connection_id}@{}", self.process_nth))
+        }
+    }
+
+    /// Get the connection key and the request ID of a line, if any.
+    fn request_of<'a>(&self, line: &'a str) -> Option<(Cow<'a, str>, RequestId)> {
+        let captures = self.find_request_id.captures(line)?;
+
+        Some((
+            self.connection_key(
+                captures
+                    .name("connection_id")
+                    .map(|connection_id| connection_id.as_str())
+                    .unwrap_or(NO_CONNECTION_ID),
+            ),
+            captures.name("request_id")?.as_str().parse().ok()?,
+        ))
+    }
+
+    /// Attach a warning to its span, see [`warnings::attach`].
+    fn attach_warning(&mut self, line: &str, date_time: DateTime<FixedOffset>, warning: Warning) {
+        let request = self.request_of(line);
+        let connection_id = self
+            .find_connection_id
+            .captures(line)
+            .and_then(|captures| captures.name("connection_id"))
+            .map(|connection_id| self.connection_key(connection_id.as_str()));
+
+        match warnings::attach(
+            &mut self.spans,
+            warning,
+            date_time,
+            request
+                .as_ref()
+                .map(|(connection_id, request_id)| (&**connection_id, *request_id)),
+            connection_id.as_deref(),
+            self.warnings_per_span,
+        ) {
+            Attachment::Attached => self.number_of_matched_lines += 1,
+            Attachment::Ambiguous => self.number_of_ambiguous_warnings += 1,
+            Attachment::Unattached => {}
+        }
+    }
+
+    /// Attach an error to its span, see [`Self::adjacent_span`].
+    fn attach_error(&mut self, line: &str, error: Error) {
+        if let Some(span) = self.adjacent_span(line) {
+            span.errcode = Some(error.errcode);
+            span.error_message = error.message;
+        }
+    }
+
+    /// Get the span a line adjacent to a response is about: the one with the
+    /// request ID of the line if any, otherwise the latest response if it has
+    /// failed.
+    fn adjacent_span(&mut self, line: &str) -> Option<&mut Span> {
+        match self.request_of(line) {
+            Some((connection_id, request_id)) => self
+                .spans
+                .get_mut(&*connection_id)
+                .and_then(|spans| spans.get_mut(&request_id)),
+            None => {
+                let (connection_id, request_id) = self.latest_response.as_ref()?;
+
+                self.spans
+                    .get_mut(connection_id)
+                    .and_then(|spans| spans.get_mut(request_id))
+                    .filter(|span| !span.is_successful())
+            }
+        }
+    }
+
+    /// Capture how long the server asks to wait before retrying, if any.
+    fn capture_retry_after(&self, line: &str) -> Option<RetryAfter> {
+        let captures = self.find_retry_after.captures(line)?;
+
+        match (captures.name("milliseconds"), captures.name("header")) {
+            (Some(milliseconds), _) => RetryAfter::from_milliseconds(milliseconds.as_str()),
+            (None, Some(header)) => RetryAfter::from_header(header.as_str()),
+            (None, None) => None,
+        }
+    }
+
+    /// Capture the headers telling about an intermediary, see
+    /// [`crate::intermediary`].
+    fn capture_intermediary_headers<'a>(&self, line: &'a str) -> Vec<(&'a str, &'a str)> {
+        self.find_intermediary_headers
+            .captures_iter(line)
+            .filter_map(|captures| {
+                let value = captures.name("quoted").or(captures.name("value"))?;
+
+                Some((captures.name("name")?.as_str(), value.as_str()))
+            })
+            .collect()
+    }
+
+    /// Capture the body of a request or of a response, if any, see
+    /// [`crate::bodies`].
+    fn capture_body(&self, line: &str) -> Option<(bodies::Direction, String)> {
+        let captures = self.find_body.captures(line)?;
+        let direction = if captures["direction"].eq_ignore_ascii_case("request") {
+            bodies::Direction::Request
+        } else {
+            bodies::Direction::Response
+        };
+        let body = match (captures.name("quoted"), captures.name("value")) {
+            (Some(quoted), _) => quoted.as_str().replace(r#"\""#, "\"").replace(r"\\", r"\"),
+            (None, Some(value)) => value.as_str().to_owned(),
+            (None, None) => return None,
+        };
+
+        Some((direction, bodies::prepare(&body)))
+    }
+
+    /// Capture the setup of the connection of a request, see
+    /// [`crate::setup`].
+    fn capture_setup(&self, line: &str) -> setup::Setup {
+        let mut setup = setup::Setup::default();
+
+        for captures in self.find_setup.captures_iter(line) {
+            if let (Some(name), Some(duration)) = (captures.name("name"), captures.name("duration"))
+            {
+                setup.insert(name.as_str(), duration.as_str());
+            } else if let Some(reused) = captures.name("reused") {
+                setup.insert("reused", reused.as_str());
+            } else if captures.name("reuse").is_some() {
+                setup.reused = Some(true);
+            } else if captures.name("new").is_some() {
+                setup.reused = Some(false);
+            }
+        }
+
+        setup
+    }
+
+    /// Record the location of the current line, and return the window of
+    /// lines around it.
+    fn record_location(&mut self, location: Location) -> Window {
+        if self
+            .recent_locations
+            .back()
+            .is_some_and(|previous| previous.file_nth != location.file_nth)
+        {
+            self.recent_locations.clear();
+        }
+
+        self.recent_locations.push_back(location);
+
+        if self.recent_locations.len() > self.context + 1 {
+            self.recent_locations.pop_front();
+        }
+
+        Window {
+            start_at: self.recent_locations[0],
+            number_of_lines: self.recent_locations.len() + self.context,
+        }
+    }
+}
+
+/// Parse the datetime of a log line, with a `T` or a space between the date
+/// and the time, and with or without fractional seconds.
+/// Build the regex of the `WARN` and `ERROR` lines, whose datetimes are
+/// matched by `datetime`.
+fn build_find_warning(datetime: &str) -> Regex {
+    pattern::build(&[
+        "^",
+        datetime,
+        r"
+            \s+(?<level>WARN|ERROR)
+            \s+(?<target>[\w:]+):
+            \s(?<message>.*?)
+            # The message ends before the location of the line, if any.
+            (\s\|\s|$)
+        ",
+    ])
+}
+
+/// Parse the datetime at the start of a log line, if any.
+pub fn leading_datetime(line: &str) -> Option<DateTime<FixedOffset>> {
+    let text = json_format::to_text(line);
+    let line = text.as_deref().unwrap_or(line);
+    // The seconds, then their fraction, and the offset.
+    let bytes = line.as_bytes();
+    let mut end = "2024-06-01T12:03:04".len();
+
+    while bytes
+        .get(end)
+        .is_some_and(|byte| byte.is_ascii_digit() || *byte == b'.')
+    {
+        end += 1;
+    }
+
+    // The offset, with or without colon, if any.
+    let end = match bytes.get(end) {
+        Some(b'Z') => end + 1,
+        Some(b'+' | b'-') if bytes.get(end + 3) == Some(&b':') => end + "+02:00".len(),
+        Some(b'+' | b'-') => end + "+0200".len(),
+        _ => end,
+    };
+
+    timestamp::parse(line.get(..end)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source;
+
+    #[test]
+    fn test_mixed_traffic() {
+        let mut parser = Parser::new();
+
+        for line in include_str!("../fixtures/mixed-traffic.log").lines() {
+            parser.parse_line(line, None);
+        }
+
+        // The requests sent outside of a sync are grouped under a synthetic
+        // connection, alongside the sync connections.
+        assert_eq!(
+            parser
+                .spans
+                .iter()
+                .map(|(connection_id, spans)| (
+                    connection_id.as_str(),
+                    spans.keys().copied().collect::<Vec<_>>()
+                ))
+                .collect::<Vec<_>>(),
+            [(NO_CONNECTION_ID, vec![2, 3, 4, 5]), ("room-list", vec![1])]
+        );
+
+        let sync = &parser.spans["room-list"][&1];
+
+        assert!(sync.is_sync());
+        assert_eq!(sync.status, Some(200));
+        assert_eq!(sync.duration, TimeDelta::seconds(5));
+        assert_eq!(sync.response_size.as_deref(), Some("4.2kB"));
+        assert_eq!(
+            parser.spans[NO_CONNECTION_ID][&5].duration,
+            TimeDelta::seconds(4)
+        );
+
+        // Every request is tagged with the span it is sent from.
+        assert_eq!(sync.parent(), Some("sync_once"));
+        assert_eq!(
+            parser.spans[NO_CONNECTION_ID]
+                .values()
+                .map(Span::parent)
+                .collect::<Vec<_>>(),
+            [
+                Some("send_queue"),
+                Some("paginate"),
+                Some("root"),
+                Some("download_media")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_json_format() {
+        let text = include_str!("../fixtures/session.log").lines();
+        let json = include_str!("../fixtures/session.jsonl").lines();
+        let parse = |lines: Vec<&str>| {
+            let mut parser = Parser::new();
+
+            for line in lines {
+                parser.parse_line(line, None);
+            }
+
+            (
+                format!("{:?}", parser.spans),
+                parser.number_of_matched_lines,
+            )
+        };
+
+        let (spans, number_of_matched_lines) = parse(text.clone().collect());
+
+        assert_eq!(number_of_matched_lines, 13);
+        assert!(spans.contains("M_LIMIT_EXCEEDED"));
+        assert_eq!(parse(json.clone().collect()), (spans.clone(), 13));
+        // The format is detected per line.
+        assert_eq!(
+            parse(
+                text.zip(json)
+                    .enumerate()
+                    .map(|(nth, (text, json))| if nth % 2 == 0 { text } else { json })
+                    .collect()
+            ),
+            (spans, 13)
+        );
+    }
+
+    #[test]
+    fn test_parse_batch() {
+        let mut lines = include_str!("../fixtures/session.log")
+            .lines()
+            .map(str::as_bytes)
+            .collect::<Vec<_>>();
+        // A record wrapped over 2 batches.
+        lines.push(br#"2024-06-01T10:01:00Z DEBUG matrix_sdk::http_client: Sending request | spans: root > send{request_id="REQ-99" method=GET"#);
+        let split_at = lines.len();
+        lines.push(br#" uri="https://matrix.example.org/_matrix/client/v3/sync"}"#);
+        lines.extend(
+            include_str!("../fixtures/session.jsonl")
+                .lines()
+                .map(str::as_bytes),
+        );
+        lines.push(b"2024-06-01T10:02:00Z INFO caf\xe9");
+        lines.extend(
+            include_str!("../fixtures/reconnect-storm.log")
+                .lines()
+                .map(str::as_bytes),
+        );
+
+        let new_parser = || {
+            let mut parser = Parser::new();
+            parser.lifecycle_patterns = Pattern::with_defaults(Vec::new());
+            parser.threads = 3;
+
+            parser
+        };
+        let outcome = |parser: Parser| {
+            (
+                format!("{:?}", parser.spans),
+                format!("{:?}", parser.lifecycle_events),
+                format!("{:?}", parser.conditions),
+                parser.number_of_analysed_lines,
+                parser.number_of_matched_lines,
+                parser.number_of_reassembled_records,
+            )
+        };
+
+        let mut parser = new_parser();
+
+        for line in &lines {
+            parser.parse(source::decode(line), None);
+        }
+
+        let expected = outcome(parser);
+
+        let mut parser = new_parser();
+        let mut batch = Batch::default();
+
+        for part in [&lines[..split_at], &lines[split_at..]] {
+            for line in part {
+                batch.push(source::decode(line), None);
+            }
+
+            parser.parse_batch(&mut batch);
+        }
+
+        assert_eq!(parser.number_of_reassembled_records, 1);
+        assert_eq!(outcome(parser), expected);
+    }
+
+    #[test]
+    fn test_malformed_lines() {
+        let mut parser = Parser::new();
+
+        for line in [
+            r#"2024-06-01T09:13:19.035000Z DEBUG matrix_sdk::http_client: Sending request | spans: root > send{request_id="REQ-1" method=GET uri="https://matrix.example.org/_matrix/client/v3/rooms"}"#,
+            // Truncated in the middle of the URI.
+            r#"2024-06-01T09:13:19.135000Z DEBUG matrix_sdk::http_client: Sending request | spans: root > send{request_id="REQ-2" method=GET uri="https://matrix.exam"#,
+            r#"2024-06-01T09:13:19.235000Z DEBUG matrix_sdk::http_client: Sending request | spans: root > send{request_id="REQ-3""#,
+            r#"2024-13-01T09:13:19.335000Z DEBUG matrix_sdk::http_client: Sending request | spans: root > send{request_id="REQ-4" method=GET uri="https://matrix.example.org/_matrix/client/v3/rooms"}"#,
+            r#"2024-06-01T09:13:19.435000Z DEBUG matrix_sdk::http_client: Sending request | spans: root > send{request_id="REQ-99999999999" method=GET uri="https://matrix.example.org/_matrix/client/v3/rooms"}"#,
+        ] {
+            parser.parse_line(line, None);
+        }
+
+        assert_eq!(parser.number_of_matched_lines, 1);
+        assert_eq!(parser.spans[NO_CONNECTION_ID].len(), 1);
+        assert_eq!(parser.conditions.count(Condition::MalformedCaptures), 3);
+        assert_eq!(parser.conditions.count(Condition::UnparseableTimestamps), 1);
+        assert_eq!(
+            parser
+                .conditions
+                .examples()
+                .filter(|(condition, _, _)| *condition == Condition::MalformedCaptures)
+                .map(|(_, shape, example)| (shape, example.log_line))
+                .collect::<Vec<_>>(),
+            [
+                ("invalid `request_id`", 5),
+                ("missing `method`", 3),
+                ("missing `uri`", 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_datetime_formats() {
+        let mut parser = Parser::new();
+
+        for line in [
+            r#"2024-06-01 09:13:19.035Z DEBUG matrix_sdk::http_client: Sending request | spans: root > send{request_id="REQ-1" method=GET uri="https://matrix.example.org/_matrix/client/v3/rooms"}"#,
+            // With the offset of the device.
+            r#"2024-06-01T11:13:20+02:00 DEBUG matrix_sdk::http_client: Got response | spans: root > send{request_id="REQ-1" method=GET uri="https://matrix.example.org/_matrix/client/v3/rooms" status=200}"#,
+        ] {
+            parser.parse_line(line, None);
+        }
+
+        assert_eq!(parser.number_of_matched_lines, 2);
+        assert_eq!(
+            parser.spans[NO_CONNECTION_ID][&1].duration,
+            TimeDelta::milliseconds(965)
+        );
+        assert_eq!(parser.no_match_diagnostic(), None);
+        assert_eq!(
+            leading_datetime("2024-06-01T11:13:20.5-01:00 DEBUG"),
+            DateTime::parse_from_rfc3339("2024-06-01T12:13:20.5Z").ok()
+        );
+        assert_eq!(
+            leading_datetime("2024-06-01 11:13:20 DEBUG"),
+            DateTime::parse_from_rfc3339("2024-06-01T11:13:20Z").ok()
+        );
+        assert_eq!(leading_datetime("2024-06-01T11:13 DEBUG"), None);
+
+        let mut parser = Parser::new();
+        parser.parse_line(
+            r#"06-01 09:13:19.035 DEBUG matrix_sdk::http_client: Sending request | spans: root > send{request_id="REQ-1" method=GET uri="https://matrix.example.org/_matrix/client/v3/rooms"}"#,
+            None,
+        );
+
+        assert!(
+            parser
+                .no_match_diagnostic()
+                .is_some_and(|diagnostic| diagnostic.contains("`09:13:19.035`, at log line 1"))
+        );
+    }
+
+    #[test]
+    fn test_statuses() {
+        let mut parser = Parser::new();
+
+        for (request_id, status) in [(1, "200"), (2, "429"), (3, "99999")] {
+            for (at, message, status) in [
+                ("19", "Sending request", String::new()),
+                ("20", "Got response", format!(" status={status}")),
+            ] {
+                parser.parse_line(
+                    &format!(
+                        r#"2024-06-01T09:13:{at}Z DEBUG matrix_sdk::http_client: {message} | spans: root > send{{request_id="REQ-{request_id}" method=GET uri="https://matrix.example.org/_matrix/client/v3/rooms"{status}}}"#
+                    ),
+                    None,
+                );
+            }
+        }
+
+        let spans = &parser.spans[NO_CONNECTION_ID];
+
+        assert_eq!(spans[&1].status, Some(200));
+        assert_eq!(spans[&1].status_family(), "2");
+        assert_eq!(spans[&2].status, Some(429));
+        assert_eq!(spans[&2].status_family(), "4");
+        // The response is kept, without its status.
+        assert_eq!(spans[&3].status, None);
+        assert_eq!(spans[&3].response_log_line, Some(6));
+        assert_eq!(
+            parser
+                .conditions
+                .examples()
+                .map(|(condition, shape, example)| (condition, shape, example.log_line))
+                .collect::<Vec<_>>(),
+            [(Condition::MalformedCaptures, "invalid `status`", 6)]
+        );
+    }
+
+    #[test]
+    fn test_take_completed() {
+        let mut parser = Parser::new();
+
+        for line in [
+            r#"2024-06-01T09:13:19Z DEBUG matrix_sdk::http_client: Sending request | spans: root > send{request_id="REQ-1" method=GET uri="https://example.org/versions"}"#,
+            r#"2024-06-01T09:13:19Z DEBUG matrix_sdk::http_client: Sending request | spans: root > send{request_id="REQ-2" method=GET uri="https://example.org/versions"}"#,
+            r#"2024-06-01T09:13:20Z DEBUG matrix_sdk::http_client: Sending request | spans: root > send{request_id="REQ-3" method=GET uri="https://example.org/versions"}"#,
+            r#"2024-06-01T09:13:20Z DEBUG matrix_sdk::http_client: Got response | spans: root > send{request_id="REQ-2" method=GET uri="https://example.org/versions" status=200}"#,
+            r#"2024-06-01T09:13:21Z DEBUG matrix_sdk::http_client: Got response | spans: root > send{request_id="REQ-1" method=GET uri="https://example.org/versions" status=200}"#,
+        ] {
+            parser.parse_line(line, None);
+        }
+
+        // `REQ-1` is the latest response, and `REQ-3` is pending.
+        let taken = parser.take_completed(false);
+        assert_eq!(taken[NO_CONNECTION_ID].keys().collect::<Vec<_>>(), [&2]);
+        assert_eq!(
+            parser.spans[NO_CONNECTION_ID].keys().collect::<Vec<_>>(),
+            [&1, &3]
+        );
+
+        // The state of the taken request is evicted, but its ID is still
+        // known as answered.
+        let mut sent_requests = parser.sent_requests.keys().collect::<Vec<_>>();
+        sent_requests.sort();
+        assert_eq!(sent_requests, [&1, &3]);
+        assert_eq!(parser.taken_requests, BTreeMap::from([(2, 2)]));
+
+        for request_id in [4, 6, 5] {
+            parser.insert_taken(request_id);
+        }
+
+        assert_eq!(parser.taken_requests, BTreeMap::from([(2, 2), (4, 6)]));
+
+        // `REQ-2` sent again is from a restarted app.
+        parser.parse_line(
+            r#"2024-06-01T09:13:22Z DEBUG matrix_sdk::http_client: Sending request | spans: root > send{request_id="REQ-2" method=GET uri="https://example.org/versions"}"#,
+            None,
+        );
+        assert_eq!(parser.number_of_restarts(), 1);
+
+        let taken = parser.take_completed(true);
+        assert_eq!(taken.values().map(BTreeMap::len).sum::<usize>(), 3);
+        assert!(parser.spans.is_empty());
+        assert!(parser.sent_requests.is_empty());
+        assert!(parser.taken_requests.is_empty());
+    }
+
+    #[test]
+    fn test_sliding_sync_fields() {
+        let mut parser = Parser::new();
+
+        for (request_id, query) in [
+            (1, "timeout=0"),
+            (2, "pos=1&timeout=30000"),
+            (3, "pos=1&timeout=30000"),
+            (4, "pos=2&timeout=30000"),
+            (8, "timeout=0"),
+        ] {
+            parser.parse_line(
+                &format!(
+                    r#"2024-06-01T09:13:19Z DEBUG matrix_sdk::http_client: Sending request | spans: root > sync_once{{conn_id="room-list"}} > send{{request_id="REQ-{request_id}" method=POST uri="https://matrix.example.org/_matrix/client/unstable/org.matrix.simplified_msc3575/sync?{query}"}}"#
+                ),
+                None,
+            );
+        }
+
+        parser.parse_line(
+            r#"2024-06-01T09:13:20Z DEBUG matrix_sdk::http_client: Sending request txn_id="m1717233200.0" | spans: root > send{request_id="REQ-5" method=PUT uri="https://matrix.example.org/_matrix/client/v3/rooms/!abc/send/m.room.message/m1717233200.0"}"#,
+            None,
+        );
+
+        // An explicit iteration, then a request of the same iteration.
+        for (request_id, path) in [
+            (6, "unstable/org.matrix.simplified_msc3575/sync"),
+            (7, "v3/keys/query"),
+        ] {
+            parser.parse_line(
+                &format!(
+                    r#"2024-06-01T09:13:21Z DEBUG matrix_sdk::http_client: Sending request | spans: root > sync_once{{conn_id="encryption" iteration=9}} > send{{request_id="REQ-{request_id}" method=POST uri="https://matrix.example.org/_matrix/client/{path}"}}"#
+                ),
+                None,
+            );
+        }
+
+        let fields = |connection_id: &str| {
+            parser.spans[connection_id]
+                .values()
+                .map(|span| {
+                    (
+                        span.pos.as_deref(),
+                        span.pos_stalled,
+                        span.timeout().map(|timeout| timeout.num_milliseconds()),
+                        span.txn_id.as_deref(),
+                        span.iteration,
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            fields("room-list"),
+            [
+                (None, false, Some(0), None, 1),
+                (Some("1"), false, Some(30000), None, 2),
+                (Some("1"), true, Some(30000), None, 3),
+                (Some("2"), false, Some(30000), None, 4),
+                (None, false, Some(0), None, 5),
+            ]
+        );
+        assert_eq!(
+            parser.spans["room-list"]
+                .values()
+                .map(|span| span.pos_reset)
+                .collect::<Vec<_>>(),
+            [false, false, false, false, true]
+        );
+        assert_eq!(
+            fields(NO_CONNECTION_ID),
+            [(None, false, None, Some("m1717233200.0"), 0)]
+        );
+        assert_eq!(
+            fields("encryption"),
+            [(None, false, None, None, 9), (None, false, None, None, 9)]
+        );
+    }
+
+    #[test]
+    fn test_transport_errors() {
+        let mut parser = Parser::new();
+        let line = |at: &str, request_id: u32, message: &str, status: &str| {
+            format!(
+                r#"2024-06-01T09:13:{at}Z DEBUG matrix_sdk::http_client: {message} | spans: root > send{{request_id="REQ-{request_id}" method=GET uri="https://matrix.example.org/_matrix/client/v3/rooms"{status}}}"#
+            )
+        };
+
+        for line in [
+            line("10", 1, "Sending request", ""),
+            line(
+                "11",
+                1,
+                r#"send failed error="error sending request: connection reset" retry_in=1s"#,
+                "",
+            ),
+            line("12", 1, "Sending request", ""),
+            line("13", 1, "Got response", " status=200"),
+            line("14", 2, "Sending request", ""),
+            line("15", 2, "send failed error=dns retry_count=3", ""),
+        ] {
+            parser.parse_line(&line, None);
+        }
+
+        let spans = &parser.spans[NO_CONNECTION_ID];
+
+        // The retried request has succeeded, under the same request ID.
+        assert_eq!(parser.spans.len(), 1);
+        assert_eq!(spans[&1].status, Some(200));
+        assert_eq!(spans[&1].status_family(), "2");
+        assert_eq!(spans[&1].retries, 1);
+        assert_eq!(spans[&1].duration, TimeDelta::seconds(3));
+        assert_eq!(
+            spans[&1].error.as_deref(),
+            Some("error sending request: connection reset; retrying in 1s")
+        );
+        assert_eq!(spans[&2].status, None);
+        assert_eq!(spans[&2].status_family(), "error");
+        assert_eq!(spans[&2].retries, 3);
+        assert_eq!(spans[&2].error.as_deref(), Some("dns"));
+        assert_eq!(parser.conditions.count(Condition::DuplicateRequestIds), 0);
+    }
+
+    #[test]
+    fn test_connection_setup() {
+        let mut parser = Parser::new();
+        let line = |at: &str, request_id: u32, message: &str, status: &str| {
+            format!(
+                r#"2024-06-01T09:13:{at}Z DEBUG matrix_sdk::http_client: {message} | spans: root > send{{request_id="REQ-{request_id}" method=GET uri="https://matrix.example.org/_matrix/client/v3/keys/query"{status}}}"#
+            )
+        };
+
+        for line in [
+            line("10", 1, "Sending request", ""),
+            line(
+                "10",
+                1,
+                "connection setup dns=3ms connect_duration=20.5ms tls=45ms",
+                "",
+            ),
+            line("11", 1, "Got response", " status=200 ttfb=120ms"),
+            line("12", 2, "Sending request", ""),
+            line(
+                "12",
+                2,
+                r#"reuse idle connection for ("https", matrix.example.org)"#,
+                "",
+            ),
+            line("13", 2, "Got response", " status=200"),
+        ] {
+            parser.parse_line(&line, None);
+        }
+
+        let spans = &parser.spans[NO_CONNECTION_ID];
+
+        assert_eq!(parser.number_of_matched_lines, 6);
+        assert_eq!(
+            spans[&1].setup,
+            setup::Setup {
+                reused: None,
+                dns: Some(3.),
+                connect: Some(20.5),
+                tls: Some(45.),
+                ttfb: Some(120.),
+            }
+        );
+        assert_eq!(spans[&1].status, Some(200));
+        assert_eq!(spans[&2].setup.reused, Some(true));
+        assert_eq!(spans[&2].duration, TimeDelta::seconds(1));
+    }
+
+    #[test]
+    fn test_capture_bodies() {
+        let line = |at: &str, message: &str, status: &str| {
+            format!(
+                r#"2024-06-01T09:13:{at}Z TRACE matrix_sdk::http_client: {message} | crates/matrix-sdk/src/http_client/mod.rs:200 | spans: root > send{{request_id="REQ-1" method=POST uri="https://matrix.example.org/_matrix/client/v3/keys/query"{status}}}"#
+            )
+        };
+        let lines = [
+            line("10", "Sending request", ""),
+            line(
+                "10",
+                r#"Request body body={"device_keys":{"@alice:example.org":[]}}"#,
+                "",
+            ),
+            line("11", r#"Response body body="{\"failures\":{}}""#, ""),
+            line("11", "Got response", " status=200"),
+        ];
+
+        let mut parser = Parser::new();
+
+        for line in &lines {
+            parser.parse_line(line, None);
+        }
+
+        // The bodies are captured with `--capture-bodies` only.
+        assert!(parser.spans[NO_CONNECTION_ID][&1].bodies.request.is_none());
+
+        let mut parser = Parser::new();
+        parser.capture_bodies = true;
+
+        for line in &lines {
+            parser.parse_line(line, None);
+        }
+
+        let span = &parser.spans[NO_CONNECTION_ID][&1];
+
+        assert_eq!(parser.number_of_matched_lines, 4);
+        assert_eq!(
+            span.bodies.request.as_deref(),
+            Some("{\n  \"device_keys\": {\n    \"@alice:example.org\": []\n  }\n}")
+        );
+        assert_eq!(
+            span.bodies.response.as_deref(),
+            Some("{\n  \"failures\": {}\n}")
+        );
+        assert_eq!(span.status, Some(200));
+    }
+
+    #[test]
+    fn test_wrapped_lines() {
+        let mut parser = Parser::new();
+
+        for line in [
+            r#"2024-06-01T09:13:10Z DEBUG matrix_sdk::http_client: Sending request | crates/matrix-sdk/src/http_client/mod.rs:200 | spans: root > send{request_id="REQ-1" method=GET"#,
+            r#" uri="https://matrix.example.org/_matrix/client/v3/sync"}"#,
+            "2024-06-01T09:13:10Z DEBUG matrix_sdk::sync: Unrelated",
+            "  an unrelated continuation",
+            r#"2024-06-01T09:13:11Z DEBUG matrix_sdk::http_client: Got response | crates/matrix-sdk/src/http_client/mod.rs:200 | spans: root > send{request_id="REQ-1" method=GET uri="https://matrix.example.org/_matrix/client/v3/sync" status=200}"#,
+        ] {
+            parser.parse_line(line, None);
+        }
+
+        let span = &parser.spans[NO_CONNECTION_ID][&1];
+
+        assert_eq!(
+            span.uri,
+            "https://matrix.example.org/_matrix/client/v3/sync"
+        );
+        assert_eq!(span.status, Some(200));
+        assert_eq!(parser.number_of_reassembled_records, 1);
+    }
+
+    #[test]
+    fn test_restarted_process() {
+        let mut parser = Parser::new();
+        let line = |at: &str, request_id: u32, path: &str, status: &str| {
+            format!(
+                r#"2024-06-01T09:13:{at}Z DEBUG matrix_sdk::http_client: {message} | spans: root > send{{request_id="REQ-{request_id}" method=GET uri="https://matrix.example.org/_matrix/client/v3/{path}"{status}}}"#,
+                message = if status.is_empty() {
+                    "Sending request"
+                } else {
+                    "Got response"
+                },
+            )
+        };
+
+        for line in [
+            line("10", 0, "versions", ""),
+            line("11", 0, "versions", " status=200"),
+            line("12", 1, "rooms", ""),
+            // The app restarts, and its request IDs too, while `REQ-1` is
+            // pending.
+            line("20", 0, "versions", ""),
+            line("21", 1, "profile", ""),
+            line("22", 1, "profile", " status=200"),
+            line("23", 0, "versions", " status=200"),
+        ] {
+            parser.parse_line(&line, None);
+        }
+
+        let uris = |connection_id: &str| {
+            parser.spans[connection_id]
+                .iter()
+                .map(|(request_id, span)| (*request_id, span.uri.as_str(), span.response_log_line))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            uris(NO_CONNECTION_ID),
+            [
+                (
+                    0,
+                    "https://matrix.example.org/_matrix/client/v3/versions",
+                    Some(2)
+                ),
+                (
+                    1,
+                    "https://matrix.example.org/_matrix/client/v3/rooms",
+                    None
+                ),
+            ]
+        );
+        assert_eq!(
+            uris(&format!("{NO_CONNECTION_ID}@2")),
+            [
+                (
+                    0,
+                    "https://matrix.example.org/_matrix/client/v3/versions",
+                    Some(7)
+                ),
+                (
+                    1,
+                    "https://matrix.example.org/_matrix/client/v3/profile",
+                    Some(6)
+                ),
+            ]
+        );
+        assert_eq!(parser.conditions.count(Condition::DuplicateRequestIds), 0);
+        assert_eq!(parser.number_of_restarts(), 1);
+
+        // The banner of a new process restarts the app, even if the request
+        // ID hasn't been sent by the current one.
+        for line in [
+            "2024-06-01T09:13:30Z INFO matrix_sdk::client::builder: Starting to build the Client"
+                .to_owned(),
+            line("31", 2, "rooms", ""),
+        ] {
+            parser.parse_line(&line, None);
+        }
+
+        assert!(parser.spans[&format!("{NO_CONNECTION_ID}@3")].contains_key(&2));
+        assert!(!parser.spans[&format!("{NO_CONNECTION_ID}@2")].contains_key(&2));
+        assert_eq!(parser.number_of_restarts(), 2);
+        assert_eq!(
+            parser.spans[&format!("{NO_CONNECTION_ID}@3")][&2].process_nth,
+            3
+        );
+
+        // A request sent long before the latest one is from another process,
+        // e.g. of an older log file.
+        parser.parse_line(
+            &line("32", 3, "rooms", "").replace("09:13:32", "09:05:00"),
+            None,
+        );
+
+        assert!(parser.spans[&format!("{NO_CONNECTION_ID}@4")].contains_key(&3));
+        assert_eq!(parser.number_of_restarts(), 3);
+    }
+
+    #[test]
+    fn test_invalid_utf8() {
+        let mut parser = Parser::new();
+
+        for line in [
+            &b"2024-06-01T09:13:19Z DEBUG matrix_sdk::http_client: Sending request | spans: root > send{request_id=\"REQ-1\" method=GET uri=\"https://matrix.example.org/_matrix/client/v3/rooms\"}\n"[..],
+            b"2024-06-01T09:13:19Z INFO caf\xe9\r\n",
+            b"2024-06-01T09:13:20Z DEBUG matrix_sdk::http_client: Got response | spans: root > send{request_id=\"REQ-1\" method=GET uri=\"https://matrix.example.org/_matrix/client/v3/rooms\" status=200}",
+        ] {
+            parser.parse(source::decode(line), None);
+        }
+
+        assert_eq!(parser.number_of_analysed_lines, 3);
+        assert_eq!(parser.number_of_matched_lines, 2);
+        assert_eq!(parser.conditions.count(Condition::InvalidUtf8), 1);
+    }
+
+    #[test]
+    fn test_conditions() {
+        let mut parser = Parser::new();
+        let request = |at: &str, request_id: u32| {
+            format!(
+                r#"2024-06-01T09:{at}.000000Z DEBUG matrix_sdk::http_client: Sending request | spans: root > send{{request_id="REQ-{request_id}" method=GET uri="https://matrix.example.org/_matrix/client/v3/rooms"}}"#
+            )
+        };
+        let response = |at: &str, request_id: u32| {
+            format!(
+                r#"2024-06-01T09:{at}.000000Z DEBUG matrix_sdk::http_client: Got response | spans: root > send{{request_id="REQ-{request_id}" method=GET uri="https://matrix.example.org/_matrix/client/v3/rooms" status=200}}"#
+            )
+        };
+
+        for line in [
+            request("00:00", 1),
+            response("00:01", 1),
+            // Reused after its response.
+            response("00:02", 1),
+            request("00:05", 2),
+            response("00:04", 2),
+            // Never responded.
+            request("00:10", 3),
+            request("05:00", 4),
+            response("05:01", 4),
+        ] {
+            parser.parse_line(&line, None);
+        }
+
+        parser.record_unterminated_spans(TimeDelta::minutes(2));
+
+        assert_eq!(parser.conditions.count(Condition::DuplicateRequestIds), 1);
+        assert_eq!(parser.conditions.count(Condition::NegativeDurations), 1);
+        assert_eq!(parser.conditions.count(Condition::UnterminatedSpans), 1);
+        assert_eq!(
+            parser
+                .conditions
+                .strict_errors(&[Condition::UnterminatedSpans, Condition::NegativeDurations]),
+            ["1 duplicate request IDs (`duplicate-request-ids`)"]
+        );
+    }
+}
@@ -0,0 +1,297 @@
+//! Pieces of the pattern of the request and response lines, e.g.
+//! `2024-06-01T09:13:19.035Z DEBUG matrix_sdk::http_client: Sending request |
+//! spans: root > sync_once{conn_id="room-list"} > send{request_id="REQ-1"
+//! method=POST uri="…"}`.
+//!
+//! The pieces compose the full pattern, and can be matched on their own, see
+//! [`crate::explain`]. They are written for [`RegexBuilder::ignore_whitespace`].
+//!
+//! The full pattern is the first one of the [`Registry`], which the patterns of
+//! `--config` extend, see [`crate::config`].
+
+use std::collections::BTreeMap;
+
+use regex::{Match, Regex, RegexBuilder};
+
+/// Datetime of the log line, in UTC or with the offset of the device, or
+/// without offset, see [`crate::timestamp`].
+pub const DATETIME: &str = r"
+    (?<datetime>\d{4}-\d{2}-\d{2}[T\x20]\d{2}:\d{2}:\d{2}(\.\d+)?(Z|[+-]\d{2}:?\d{2})?)
+";
+
+/// Ensure it's about the `http_client` scope.
+pub const TARGET: &str = r"
+    .*matrix_sdk::http_client
+";
+
+/// If it's about a sync, there is a `conn_id`, and maybe the number of the
+/// iteration of the sync loop.
+pub const CONNECTION: &str = r#"
+    (.*>\ssync_once\{
+        conn_id="(?<connection_id>[^"]+)"
+        ([^}]*\siteration="?(?<iteration>\d+)"?)?
+    [^}]*\})?
+"#;
+
+/// The `send()` span, and its request ID.
+pub const SEND: &str = r#"
+    .*\ssend\{
+        request_id="REQ-(?<request_id>\d+)"
+"#;
+
+/// The fields of the `send()` span.
+pub const FIELDS: &str = r#"
+    # Mandatory, but optional here so that a truncated line is reported as
+    # malformed rather than silently skipped. Quoted in the lines rewritten
+    # from JSON.
+    (\smethod="?(?<method>[^\s"]+)"?)?
+    (\suri="(?<uri>[^"]+)")?
+    # If there is a `request_size`.
+    (.*\srequest_size="(?<request_size>[^"]+)")?
+    # If this is a response, there is a `status`.
+    (.*\sstatus="?(?<status>\d+))?
+    # If there is a `response_size`.
+    (.*\sresponse_size="(?<response_size>[^"]+)")?
+"#;
+
+/// The full pattern.
+pub const ALL: [&str; 5] = [DATETIME, TARGET, CONNECTION, SEND, FIELDS];
+
+/// Build a regex from pieces, in this order.
+pub fn build(pieces: &[&str]) -> Regex {
+    RegexBuilder::new(&pieces.concat())
+        .ignore_whitespace(true)
+        .build()
+        .expect("The pieces compose a valid regex")
+}
+
+/// Names of the captures read by the parser.
+pub const CAPTURES: [&str; 9] = [
+    "datetime",
+    "connection_id",
+    "iteration",
+    "request_id",
+    "method",
+    "uri",
+    "request_size",
+    "status",
+    "response_size",
+];
+
+/// Names of the captures without which a line is malformed.
+const MANDATORY_CAPTURES: [&str; 4] = ["datetime", "request_id", "method", "uri"];
+
+/// A pattern of the request and response lines.
+#[derive(Clone, Debug)]
+pub struct Pattern {
+    regex: Regex,
+    /// Name of the group of the regex per name of capture, from [`CAPTURES`].
+    groups: [Option<String>; CAPTURES.len()],
+    /// Names of the other groups, kept on the spans, see [`Span::fields`].
+    ///
+    /// [`Span::fields`]: crate::Span::fields
+    fields: Vec<String>,
+}
+
+impl Pattern {
+    /// Build a pattern from a regex, whose groups are named after
+    /// [`CAPTURES`], or mapped to them by `captures`, e.g. `uri = "url"`.
+    pub fn new(regex: &str, captures: &BTreeMap<String, String>) -> Result<Self, String> {
+        let regex = Regex::new(regex).map_err(|error| format!("invalid regex: {error}"))?;
+        let names = regex.capture_names().flatten().collect::<Vec<_>>();
+
+        for (capture, group) in captures {
+            if !CAPTURES.contains(&capture.as_str()) {
+                return Err(format!(
+                    "unknown capture `{capture}`; valid captures are {}",
+                    CAPTURES
+                        .iter()
+                        .map(|capture| format!("`{capture}`"))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+            }
+
+            if !names.contains(&group.as_str()) {
+                return Err(format!("the regex has no group named `{group}`"));
+            }
+        }
+
+        let groups = CAPTURES.map(|capture| {
+            captures
+                .get(capture)
+                .map(String::as_str)
+                .or_else(|| names.iter().copied().find(|name| *name == capture))
+                .map(ToOwned::to_owned)
+        });
+
+        if let Some(missing) = MANDATORY_CAPTURES
+            .iter()
+            .find(|capture| groups[index_of(capture)].is_none())
+        {
+            return Err(format!("the regex has no `{missing}` capture"));
+        }
+
+        let fields = names
+            .iter()
+            .filter(|name| !groups.iter().flatten().any(|group| group == *name))
+            .map(|name| (*name).to_owned())
+            .collect();
+
+        Ok(Self {
+            regex,
+            groups,
+            fields,
+        })
+    }
+}
+
+/// Position of a capture in [`CAPTURES`].
+fn index_of(capture: &str) -> usize {
+    CAPTURES
+        .iter()
+        .position(|name| *name == capture)
+        .expect("The capture is one of `CAPTURES`")
+}
+
+/// The captures of a line matching a [`Pattern`].
+pub struct Captures<'h> {
+    matches: [Option<Match<'h>>; CAPTURES.len()],
+    /// The other groups which have matched, by name.
+    pub fields: Vec<(String, &'h str)>,
+}
+
+impl<'h> Captures<'h> {
+    /// The match of a capture from [`CAPTURES`], if any.
+    pub fn name(&self, capture: &str) -> Option<Match<'h>> {
+        self.matches[index_of(capture)]
+    }
+}
+
+/// The patterns of the request and response lines, tried in order: the full
+/// pattern first, then the ones of `--config`.
+#[derive(Clone, Debug)]
+pub struct Registry {
+    patterns: Vec<Pattern>,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self {
+            patterns: vec![Pattern {
+                regex: build(&ALL),
+                groups: CAPTURES.map(|capture| Some(capture.to_owned())),
+                fields: Vec::new(),
+            }],
+        }
+    }
+}
+
+impl Registry {
+    /// Match the datetimes of the full pattern with `datetime` instead of
+    /// [`DATETIME`], see [`crate::timestamp::Format`].
+    pub fn set_datetime(&mut self, datetime: &str) {
+        self.patterns[0].regex = build(&[datetime, TARGET, CONNECTION, SEND, FIELDS]);
+    }
+
+    pub fn extend(&mut self, patterns: impl IntoIterator<Item = Pattern>) {
+        self.patterns.extend(patterns);
+    }
+
+    /// Capture the fields of `line` with the first matching pattern.
+    pub fn captures<'h>(&self, line: &'h str) -> Option<Captures<'h>> {
+        self.patterns.iter().find_map(|pattern| {
+            let captures = pattern.regex.captures(line)?;
+
+            Some(Captures {
+                matches: pattern
+                    .groups
+                    .each_ref()
+                    .map(|group| group.as_deref().and_then(|group| captures.name(group))),
+                fields: pattern
+                    .fields
+                    .iter()
+                    .filter_map(|name| Some((name.clone(), captures.name(name)?.as_str())))
+                    .collect(),
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pieces() {
+        let line = r#"2024-06-01T09:13:19.035Z DEBUG matrix_sdk::http_client: Sending request | spans: root > sync_once{conn_id="room-list"} > send{request_id="REQ-1" method=POST uri="https://example.org/sync"}"#;
+
+        assert_eq!(
+            &build(&[DATETIME]).captures(line).unwrap()["datetime"],
+            "2024-06-01T09:13:19.035Z"
+        );
+        assert!(build(&[TARGET]).is_match(line));
+        assert!(
+            !build(&[TARGET]).is_match("2024-06-01T09:13:19.035Z DEBUG matrix_sdk::client: Ready")
+        );
+
+        let captures = build(&ALL).captures(line).unwrap();
+
+        assert_eq!(&captures["connection_id"], "room-list");
+        assert!(captures.name("iteration").is_none());
+        assert_eq!(&captures["request_id"], "1");
+        assert_eq!(&captures["method"], "POST");
+        assert_eq!(&captures["uri"], "https://example.org/sync");
+        assert!(captures.name("status").is_none());
+
+        let line = line.replace(
+            r#"conn_id="room-list""#,
+            r#"conn_id="room-list" iteration=7"#,
+        );
+        let captures = build(&ALL).captures(&line).unwrap();
+
+        assert_eq!(&captures["connection_id"], "room-list");
+        assert_eq!(&captures["iteration"], "7");
+    }
+
+    #[test]
+    fn test_registry() {
+        let mut registry = Registry::default();
+        registry.extend([Pattern::new(
+            r#"^(?<datetime>\S+Z) .*matrix_sdk_crypto.* id=(?<request_id>\d+) (?<method>[A-Z]+) (?<url>\S+)( (?<status>\d+))?( user=(?<user>\S+))?"#,
+            &BTreeMap::from([("uri".to_owned(), "url".to_owned())]),
+        )
+        .unwrap()]);
+
+        let captures = registry
+            .captures("2024-06-01T09:13:19.035Z DEBUG matrix_sdk_crypto::machine: id=3 POST https://example.org/keys/query 200 user=@alice:example.org")
+            .unwrap();
+
+        assert_eq!(
+            captures.name("uri").unwrap().as_str(),
+            "https://example.org/keys/query"
+        );
+        assert_eq!(captures.name("status").unwrap().as_str(), "200");
+        assert!(captures.name("connection_id").is_none());
+        assert_eq!(captures.fields, [("user".to_owned(), "@alice:example.org")]);
+
+        // The full pattern is tried first.
+        assert!(
+            registry
+                .captures(r#"2024-06-01T09:13:19.035Z DEBUG matrix_sdk::http_client: Sending request | spans: send{request_id="REQ-1" method=POST uri="https://example.org/sync"}"#)
+                .unwrap()
+                .fields
+                .is_empty()
+        );
+
+        assert_eq!(
+            Pattern::new(r"(?<datetime>\S+)", &BTreeMap::new()).unwrap_err(),
+            "the regex has no `request_id` capture"
+        );
+        assert!(
+            Pattern::new("(", &BTreeMap::new())
+                .unwrap_err()
+                .starts_with("invalid regex")
+        );
+    }
+}
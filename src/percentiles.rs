@@ -0,0 +1,142 @@
+//! Rank the duration of each span among the durations of its endpoint, e.g.
+//! a 500ms `POST /_matrix/client/v3/keys/query` is fine when a 500ms
+//! `GET /_matrix/client/versions` is pathological. With `--latency-heatmap`,
+//! the spans are colored by their rank, rather than by their status.
+
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::TimeDelta;
+
+use crate::{RequestId, Spans};
+
+/// Minimum number of answered spans of an endpoint to rank them: below, the
+/// distribution is noise.
+const MINIMUM_NUMBER_OF_SPANS: usize = 10;
+
+/// Percentile of the duration of each span, between 0 and 100, among the
+/// answered spans of its endpoint.
+#[derive(Default)]
+pub struct Percentiles<'a> {
+    per_span: HashMap<(&'a str, RequestId), u8>,
+}
+
+impl<'a> Percentiles<'a> {
+    /// Rank the answered spans of the endpoints with enough of them.
+    pub fn compute(spans: &'a Spans) -> Self {
+        let mut per_endpoint = BTreeMap::<_, Vec<_>>::new();
+
+        for (connection_id, spans) in spans {
+            for (request_id, span) in spans {
+                if span.status.is_some() {
+                    per_endpoint.entry(span.endpoint()).or_default().push((
+                        connection_id.as_str(),
+                        *request_id,
+                        span.duration,
+                    ));
+                }
+            }
+        }
+
+        let mut per_span = HashMap::new();
+
+        for spans in per_endpoint.into_values() {
+            if spans.len() < MINIMUM_NUMBER_OF_SPANS {
+                continue;
+            }
+
+            let mut durations = spans
+                .iter()
+                .map(|(_, _, duration)| *duration)
+                .collect::<Vec<_>>();
+            durations.sort_unstable();
+
+            for (connection_id, request_id, duration) in spans {
+                per_span.insert(
+                    (connection_id, request_id),
+                    percentile_of(&durations, duration),
+                );
+            }
+        }
+
+        Self { per_span }
+    }
+
+    /// Get the percentile of a span, if its endpoint has been ranked.
+    pub fn get(&self, connection_id: &str, request_id: RequestId) -> Option<u8> {
+        self.per_span.get(&(connection_id, request_id)).copied()
+    }
+
+    /// Get the heat of a span, as colored by `--latency-heatmap`: `p50` up to
+    /// the median of its endpoint, `p90` up to the 90th percentile, `above`
+    /// beyond.
+    pub fn heat(&self, connection_id: &str, request_id: RequestId) -> Option<&'static str> {
+        self.get(connection_id, request_id)
+            .map(|percentile| match percentile {
+                0..=50 => "p50",
+                51..=90 => "p90",
+                _ => "above",
+            })
+    }
+}
+
+/// Render the legend of the heats of `--latency-heatmap`.
+pub fn legend_to_html() -> &'static str {
+    "<ul class=\"duration-bands\">
+  <li>Durations, among those of the endpoint: <span data-latency-heat=\"p50\">≤ p50</span> <span data-latency-heat=\"p90\">≤ p90</span> <span data-latency-heat=\"above\">&gt; p90</span></li>
+</ul>
+"
+}
+
+/// Get the percentage of the sorted durations which are less than or equal
+/// to `duration`.
+fn percentile_of(sorted_durations: &[TimeDelta], duration: TimeDelta) -> u8 {
+    let rank = sorted_durations.partition_point(|candidate| *candidate <= duration);
+
+    (rank * 100).div_ceil(sorted_durations.len()) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SpansBuilder;
+
+    #[test]
+    fn test_compute() {
+        let mut spans = SpansBuilder::default();
+        let mut span = |request_id, path: &str, duration| {
+            spans.span(
+                "main",
+                request_id,
+                &format!("https://example.org/_matrix/client/{path}"),
+                Some(200),
+                0,
+                duration,
+            );
+        };
+
+        for (request_id, duration) in
+            (1..=10).zip([500, 600, 700, 800, 900, 1000, 1100, 1200, 1300, 1400])
+        {
+            span(request_id, "v3/keys/query", duration);
+        }
+
+        for (request_id, duration) in (11..=20).zip([10, 10, 10, 10, 10, 10, 10, 10, 20, 500]) {
+            span(request_id, "versions", duration);
+        }
+
+        span(21, "v3/sync", 30_000);
+
+        let spans = spans.build();
+        let percentiles = Percentiles::compute(&spans);
+        let heat = |request_id| percentiles.heat("main", request_id);
+
+        assert_eq!(percentiles.get("main", 1), Some(10));
+        assert_eq!(heat(1), Some("p50"));
+        assert_eq!(heat(9), Some("p90"));
+        assert_eq!(heat(10), Some("above"));
+        assert_eq!(percentiles.get("main", 11), Some(80));
+        assert_eq!(heat(20), Some("above"));
+        // Too few spans to rank them.
+        assert_eq!(percentiles.get("main", 21), None);
+    }
+}
@@ -0,0 +1,159 @@
+//! Report the progress of the parsing of the log files on the standard error,
+//! e.g. `[######--------------]  31%  82.4 MiB of 265.1 MiB, 412,330 lines/s,
+//! ETA 12.3s`, since a log of hundreds of MiB takes a while to be parsed.
+//!
+//! The progress is only displayed if the standard error is a terminal, and
+//! without `--quiet`. The offsets of the lines of a compressed file are in
+//! the decompressed stream, so the size of the logs is unknown if one of
+//! them is compressed: only the bytes read and the rate are displayed then.
+
+use std::{
+    io::{self, IsTerminal, Write},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    human,
+    source::{self, Location, Source},
+};
+
+/// Minimum interval between two redraws of the progress.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Width of the bar, in characters.
+const BAR_WIDTH: usize = 20;
+
+/// Progress of the parsing of the files of a source.
+pub struct Progress {
+    /// Sum of the sizes of the previous files, for each file, and the sum of
+    /// all the sizes, if no file is compressed.
+    file_starts: Option<(Vec<u64>, u64)>,
+    number_of_lines: usize,
+    number_of_bytes: u64,
+    started_at: Instant,
+    drawn_at: Instant,
+}
+
+impl Progress {
+    /// Start the progress of `source`, if it is made of files and the
+    /// standard error is a terminal.
+    pub fn start(source: &Source) -> Option<Self> {
+        let Source::Files(paths) = source else {
+            return None;
+        };
+
+        if !io::stderr().is_terminal() {
+            return None;
+        }
+
+        let file_starts = source.files().iter().zip(paths).try_fold(
+            (Vec::new(), 0),
+            |(mut starts, total), (file, path)| {
+                let size = file.size?;
+
+                if !matches!(source::compression(path), Ok(None)) {
+                    return None;
+                }
+
+                starts.push(total);
+
+                Some((starts, total + size))
+            },
+        );
+        let now = Instant::now();
+
+        Some(Self {
+            file_starts,
+            number_of_lines: 0,
+            number_of_bytes: 0,
+            started_at: now,
+            drawn_at: now,
+        })
+    }
+
+    /// Count a line of `length` bytes, at `location`, and redraw the
+    /// progress if it's due.
+    pub fn update(&mut self, location: Option<Location>, length: usize) {
+        self.number_of_lines += 1;
+
+        match (&self.file_starts, location) {
+            (Some((starts, _)), Some(location)) => {
+                self.number_of_bytes = starts[location.file_nth] + location.offset + length as u64;
+            }
+            _ => self.number_of_bytes += length as u64,
+        }
+
+        // Reading the clock on every line would slow the parsing down.
+        if self.number_of_lines.is_multiple_of(1_024) && self.drawn_at.elapsed() >= REDRAW_INTERVAL
+        {
+            self.drawn_at = Instant::now();
+            self.draw();
+        }
+    }
+
+    /// Erase the progress, once the source is exhausted.
+    pub fn finish(self) {
+        if self.drawn_at > self.started_at {
+            eprint!("\r\x1b[2K");
+        }
+    }
+
+    fn draw(&self) {
+        let line = to_line(
+            self.number_of_bytes,
+            self.file_starts.as_ref().map(|(_, total)| *total),
+            self.number_of_lines,
+            self.started_at.elapsed(),
+        );
+        let mut stderr = io::stderr().lock();
+        let _ = write!(stderr, "\r\x1b[2K{line}");
+        let _ = stderr.flush();
+    }
+}
+
+/// Render the progress after `number_of_bytes` of `total` bytes, and
+/// `number_of_lines` lines read in `elapsed`.
+fn to_line(
+    number_of_bytes: u64,
+    total: Option<u64>,
+    number_of_lines: usize,
+    elapsed: Duration,
+) -> String {
+    let lines_per_second = (number_of_lines as f64 / elapsed.as_secs_f64().max(0.001)) as usize;
+    let rate = format!("{} lines/s", human::count(lines_per_second));
+
+    let Some(total) = total.filter(|total| *total > 0) else {
+        return format!("{} read, {rate}", human::bytes(number_of_bytes));
+    };
+
+    let ratio = (number_of_bytes as f64 / total as f64).min(1.);
+    let filled = (ratio * BAR_WIDTH as f64) as usize;
+    let remaining = elapsed.mul_f64((1. - ratio) / ratio.max(0.001));
+
+    format!(
+        "[{}{}] {:>3}%  {} of {}, {rate}, ETA {}",
+        "#".repeat(filled),
+        "-".repeat(BAR_WIDTH - filled),
+        (ratio * 100.) as u8,
+        human::bytes(number_of_bytes),
+        human::bytes(total),
+        human::milliseconds(remaining.as_millis() as i64),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_line() {
+        assert_eq!(
+            to_line(256 << 20, Some(1 << 30), 1_000_000, Duration::from_secs(2)),
+            "[#####---------------]  25%  256.0 MiB of 1.0 GiB, 500,000 lines/s, ETA 6.00s"
+        );
+        assert_eq!(
+            to_line(2_048, None, 10, Duration::from_secs(1)),
+            "2.0 KiB read, 10 lines/s"
+        );
+    }
+}
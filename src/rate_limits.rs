@@ -0,0 +1,182 @@
+//! Measure the time spent rate limited. After a `429` response, or an
+//! `M_LIMIT_EXCEEDED` error, the client waits for the delay asked by the
+//! server, see [`crate::retry_after`], before retrying.
+//!
+//! The windows of a connection may overlap, e.g. when concurrent requests are
+//! rate limited at once: they are merged, so that the time isn't counted
+//! twice.
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, FixedOffset, TimeDelta};
+
+use crate::{Span, Spans, html, human};
+
+/// Status of the rate limited responses.
+const TOO_MANY_REQUESTS: u16 = 429;
+
+/// Error code of the rate limited responses.
+const LIMIT_EXCEEDED: &str = "M_LIMIT_EXCEEDED";
+
+/// Whether the server has rate limited the request of `span`.
+pub fn is_rate_limited(span: &Span) -> bool {
+    span.status == Some(TOO_MANY_REQUESTS) || span.errcode.as_deref() == Some(LIMIT_EXCEEDED)
+}
+
+/// The window during which the client has been asked to wait, from the
+/// response of `span`, if it's rate limited.
+pub fn window(span: &Span) -> Option<(DateTime<FixedOffset>, DateTime<FixedOffset>)> {
+    let retry_after = span
+        .retry_after
+        .as_ref()
+        .filter(|_| is_rate_limited(span))?;
+    let start_at = span.start_at + span.duration;
+    let end_at = retry_after.until(span);
+
+    (end_at > start_at).then_some((start_at, end_at))
+}
+
+/// The rate limiting of a connection.
+struct Connection {
+    number_of_responses: usize,
+    /// The time covered by the windows.
+    duration: TimeDelta,
+}
+
+/// Measure the rate limiting of the connections having a rate limited
+/// response.
+fn per_connection(spans: &Spans) -> BTreeMap<&str, Connection> {
+    spans
+        .iter()
+        .filter_map(|(connection_id, spans)| {
+            let number_of_responses = spans.values().filter(|span| is_rate_limited(span)).count();
+
+            if number_of_responses == 0 {
+                return None;
+            }
+
+            let mut windows = spans.values().filter_map(window).collect::<Vec<_>>();
+            windows.sort();
+
+            let mut duration = TimeDelta::zero();
+            let mut covered_until = None;
+
+            for (start_at, end_at) in windows {
+                let start_at = covered_until.map_or(start_at, |until| start_at.max(until));
+
+                if end_at > start_at {
+                    duration += end_at - start_at;
+                    covered_until = Some(end_at);
+                }
+            }
+
+            Some((
+                connection_id.as_str(),
+                Connection {
+                    number_of_responses,
+                    duration,
+                },
+            ))
+        })
+        .collect()
+}
+
+/// Render the time spent rate limited per connection, or nothing if no
+/// request has been rate limited.
+pub fn per_connection_to_text(spans: &Spans) -> String {
+    let connections = per_connection(spans)
+        .iter()
+        .map(|(connection_id, connection)| {
+            format!(
+                "  {connection_id}: {duration} (rate limited responses: {number_of_responses})\n",
+                duration = human::duration(connection.duration),
+                number_of_responses = human::count(connection.number_of_responses),
+            )
+        })
+        .collect::<String>();
+
+    if connections.is_empty() {
+        return String::new();
+    }
+
+    format!("Time spent rate limited per connection:\n{connections}")
+}
+
+/// Render the time spent rate limited per connection as HTML, or nothing if
+/// no request has been rate limited.
+pub fn to_html(spans: &Spans) -> String {
+    let connections = per_connection(spans);
+
+    if connections.is_empty() {
+        return String::new();
+    }
+
+    let total = connections
+        .values()
+        .map(|connection| connection.duration)
+        .sum::<TimeDelta>();
+    let rows = connections
+        .iter()
+        .map(|(connection_id, connection)| {
+            format!(
+                "      <tr>
+        <td><code>{connection_id}</code></td>
+        <td>{number_of_responses}</td>
+        <td>{duration}</td>
+      </tr>
+",
+                connection_id = html::escape(connection_id),
+                number_of_responses = human::count(connection.number_of_responses),
+                duration = human::duration(connection.duration),
+            )
+        })
+        .collect::<String>();
+
+    format!(
+        "  <h3>Rate limiting</h3>
+  <p>Time spent rate limited: {total}, waiting for the delays asked by the server.</p>
+  <table>
+    <thead>
+      <tr>
+        <th scope=\"col\">Connection</th>
+        <th scope=\"col\">Rate limited responses</th>
+        <th scope=\"col\">Time rate limited</th>
+      </tr>
+    </thead>
+    <tbody>
+{rows}    </tbody>
+  </table>
+",
+        total = human::duration(total),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SpansBuilder, retry_after::RetryAfter};
+
+    #[test]
+    fn test_per_connection() {
+        let uri = "https://example.org/_matrix/client/v3/keys/query";
+        let mut spans = SpansBuilder::default();
+        // From 100ms to 2.1s, and from 1.1s to 3.1s: 3s once merged.
+        spans
+            .span("encryption", 1, uri, Some(429), 0, 100)
+            .retry_after = Some(RetryAfter::Delay(TimeDelta::seconds(2)));
+        spans
+            .span("encryption", 2, uri, Some(429), 1_000, 100)
+            .retry_after = Some(RetryAfter::Delay(TimeDelta::seconds(2)));
+        spans.span("encryption", 3, uri, Some(200), 0, 100);
+        // Without a delay: counted, but without a window.
+        spans.span("encryption", 4, uri, Some(429), 0, 100);
+        let spans = spans.build();
+
+        assert_eq!(
+            per_connection_to_text(&spans),
+            "Time spent rate limited per connection:\n  encryption: 3.00s (rate limited responses: 3)\n"
+        );
+        assert!(to_html(&spans).contains("Time spent rate limited: 3.00s"));
+        assert!(per_connection_to_text(&BTreeMap::new()).is_empty());
+    }
+}
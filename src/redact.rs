@@ -0,0 +1,443 @@
+//! Redact the secrets and the personal data of the URIs before the spans are
+//! written, e.g. the access tokens, the sync tokens and the user IDs, so that
+//! a report can be pasted into an issue.
+//!
+//! The URIs are redacted on their parsed components: a parameter is
+//! recognised by its decoded name, whatever its encoding. The spans are
+//! redacted once their tokens have been used, e.g. to merge the restarted
+//! connections.
+//!
+//! With `--redact`, the server names and the Matrix identifiers, e.g. the
+//! room and event IDs, are replaced by pseudonyms too, so that the requests to
+//! the same room remain recognisable. The pseudonyms are hashed with a key
+//! drawn for each run: they are the same in all the outputs of a run, but
+//! can't be found back by hashing a guessed name.
+
+use std::hash::{BuildHasher, RandomState};
+
+use ada_url::{Url, UrlSearchParams};
+use regex::{Captures, Regex};
+
+use crate::{Spans, context::Excerpts, endpoint};
+
+/// Replacement of the redacted values.
+pub const PLACEHOLDER: &str = "<redacted>";
+
+/// Query parameters redacted by default.
+pub const DEFAULT_PARAMETERS: [&str; 4] = ["access_token", "pos", "since", "via"];
+
+/// Fields of the bodies redacted by default, besides the parameters, e.g. of
+/// a login or of a refresh.
+const SECRET_FIELDS: [&str; 3] = ["password", "refresh_token", "token"];
+
+/// Sigils of the Matrix identifiers: users, rooms, events and room aliases.
+const SIGILS: [char; 4] = ['@', '!', '$', '#'];
+
+/// Headers identifying a request at an intermediary, see
+/// [`crate::intermediary`].
+const IDENTIFYING_HEADERS: [&str; 2] = ["cf-ray", "x-amz-cf-id"];
+
+#[derive(Clone, Debug)]
+pub struct Redaction {
+    parameters: Vec<String>,
+    /// The parameters in free texts, e.g. in a URI of a warning.
+    find_parameter: Regex,
+    /// The fields of the JSON bodies named like a parameter or a secret.
+    find_secret_field: Regex,
+    /// The key of the pseudonyms, if the server names and the identifiers are
+    /// pseudonymized.
+    pseudonyms: Option<RandomState>,
+    /// The server names and the identifiers in free texts.
+    find_identifier: Regex,
+}
+
+impl Default for Redaction {
+    fn default() -> Self {
+        Self::new(DEFAULT_PARAMETERS.map(ToOwned::to_owned).to_vec())
+    }
+}
+
+impl Redaction {
+    fn new(parameters: Vec<String>) -> Self {
+        let find_parameter = Regex::new(&format!(
+            r#"([?&](?:{})=)[^&#\s"']+"#,
+            parameters
+                .iter()
+                .map(|parameter| regex::escape(parameter))
+                .collect::<Vec<_>>()
+                .join("|")
+        ))
+        .expect("Failed to build the `find_parameter` regex");
+        let find_secret_field = Regex::new(&format!(
+            r#"("(?:{})"\s*:\s*)"(?:[^"\\]|\\.)*""#,
+            parameters
+                .iter()
+                .map(String::as_str)
+                .chain(SECRET_FIELDS)
+                .map(regex::escape)
+                .collect::<Vec<_>>()
+                .join("|")
+        ))
+        .expect("Failed to build the `find_secret_field` regex");
+        let find_identifier = Regex::new(
+            r"\bhttps?://(?<host>[\w.\-]+)|(?<sigil>[@!$#])(?<localpart>[\w.=\-/+]+):(?<server_name>[\w\-]+(?:\.[\w\-]+)+)",
+        )
+        .expect("Failed to build the `find_identifier` regex");
+
+        Self {
+            parameters,
+            find_parameter,
+            find_secret_field,
+            pseudonyms: None,
+            find_identifier,
+        }
+    }
+
+    /// Redact the query parameter `name` too.
+    pub fn add_parameter(&mut self, name: &str) {
+        let mut parameters = self.parameters.clone();
+        parameters.push(name.to_owned());
+
+        let pseudonyms = self.pseudonyms.take();
+
+        *self = Self::new(parameters);
+        self.pseudonyms = pseudonyms;
+    }
+
+    /// Replace the server names and the Matrix identifiers by pseudonyms too.
+    pub fn pseudonymize(&mut self) {
+        self.pseudonyms = Some(RandomState::new());
+    }
+
+    /// The pseudonym of `value`, with the key of the run.
+    fn pseudonym(key: &RandomState, value: &str) -> String {
+        format!("{:08x}", key.hash_one(value) >> 32)
+    }
+
+    /// The pseudonym of a server name, e.g. `server-1f2e3d4c`.
+    fn server_name(key: &RandomState, server_name: &str) -> String {
+        format!("server-{}", Self::pseudonym(key, server_name))
+    }
+
+    /// The pseudonym of a decoded Matrix identifier, keeping its sigil and
+    /// the pseudonym of its server name, e.g. `!5a6b7c8d:server-1f2e3d4c`.
+    fn identifier(key: &RandomState, identifier: &str) -> String {
+        let (sigil, rest) =
+            identifier.split_at(identifier.chars().next().map_or(0, char::len_utf8));
+
+        match rest.split_once(':') {
+            Some((localpart, server_name)) => format!(
+                "{sigil}{}:{}",
+                Self::pseudonym(key, localpart),
+                Self::server_name(key, server_name)
+            ),
+            None => format!("{sigil}{}", Self::pseudonym(key, rest)),
+        }
+    }
+
+    /// Redact the parameters of the query, and the user IDs of the path, of a
+    /// URI, and pseudonymize its server name and identifiers if needed. A URI
+    /// which can't be parsed is redacted like a free text.
+    pub fn uri(&self, uri: &str) -> String {
+        let Ok(mut url) = Url::parse(uri, None) else {
+            return self.text(uri);
+        };
+
+        if let Some(key) = &self.pseudonyms {
+            let server_name = Self::server_name(key, url.hostname());
+            let _ = url.set_hostname(Some(&server_name));
+        }
+
+        let href = url.href();
+        let components = url.components();
+        let Some(pathname_start) = components.pathname_start else {
+            return href.to_owned();
+        };
+        let pathname_start = pathname_start as usize;
+        let search_start = components.search_start.map(|start| start as usize);
+        let hash_start = components.hash_start.map(|start| start as usize);
+        let pathname_end = search_start.or(hash_start).unwrap_or(href.len());
+
+        let path = href[pathname_start..pathname_end]
+            .split('/')
+            .map(|segment| match &self.pseudonyms {
+                Some(key)
+                    if SIGILS
+                        .iter()
+                        .any(|sigil| endpoint::has_sigil(segment, *sigil)) =>
+                {
+                    // A `#` would start the fragment.
+                    Self::identifier(key, &decode(segment)).replacen('#', "%23", 1)
+                }
+                _ if endpoint::has_sigil(segment, '@') => format!("@{PLACEHOLDER}"),
+                _ => segment.to_owned(),
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+        let query = search_start
+            .map(|search_start| {
+                let query = href[search_start + 1..hash_start.unwrap_or(href.len())]
+                    .split('&')
+                    .map(|pair| {
+                        let name = pair.split_once('=').map_or(pair, |(name, _)| name);
+
+                        let value = pair.split_once('=').map(|(_, value)| value);
+
+                        if self.is_redacted(pair) {
+                            format!("{name}={PLACEHOLDER}")
+                        } else if let (Some(key), Some(value)) = (&self.pseudonyms, value)
+                            && SIGILS
+                                .iter()
+                                .any(|sigil| endpoint::has_sigil(value, *sigil))
+                        {
+                            format!(
+                                "{name}={}",
+                                Self::identifier(key, &decode(value)).replacen('#', "%23", 1)
+                            )
+                        } else {
+                            pair.to_owned()
+                        }
+                    })
+                    .collect::<Vec<_>>()
+                    .join("&");
+
+                format!("?{query}")
+            })
+            .unwrap_or_default();
+        let hash = hash_start.map(|start| &href[start..]).unwrap_or_default();
+
+        format!("{}{path}{query}{hash}", &href[..pathname_start])
+    }
+
+    /// Whether a `name=value` pair of a query is a redacted parameter, once
+    /// its name decoded.
+    fn is_redacted(&self, pair: &str) -> bool {
+        UrlSearchParams::parse(pair).is_ok_and(|params| {
+            params
+                .keys()
+                .next()
+                .is_some_and(|name| self.parameters.iter().any(|parameter| parameter == name))
+        })
+    }
+
+    /// Redact the parameters of the URIs of a free text, e.g. of a warning,
+    /// and pseudonymize its server names and identifiers if needed.
+    pub fn text(&self, text: &str) -> String {
+        let text = self
+            .find_parameter
+            .replace_all(text, format!("${{1}}{PLACEHOLDER}"));
+
+        let Some(key) = &self.pseudonyms else {
+            return text.into_owned();
+        };
+
+        self.find_identifier
+            .replace_all(&text, |captures: &Captures<'_>| {
+                match captures.name("host") {
+                    Some(host) => format!(
+                        "{}{}",
+                        &text[captures.get(0).map_or(0, |all| all.start())..host.start()],
+                        Self::server_name(key, host.as_str())
+                    ),
+                    None => Self::identifier(
+                        key,
+                        &format!(
+                            "{}{}:{}",
+                            &captures["sigil"], &captures["localpart"], &captures["server_name"]
+                        ),
+                    ),
+                }
+            })
+            .into_owned()
+    }
+
+    /// Redact the secrets of a body, see [`crate::bodies`]: the values of the
+    /// fields named like a redacted parameter or a secret, e.g.
+    /// `"password": "…"`, and the texts of its values like in [`Self::text`].
+    fn body(&self, body: &str) -> String {
+        self.text(
+            &self
+                .find_secret_field
+                .replace_all(body, format!("${{1}}\"{PLACEHOLDER}\"")),
+        )
+    }
+
+    /// Redact the spans: their URIs and `pos`, the texts of their warnings and
+    /// errors, transport errors included, their bodies, the fields of their
+    /// chain of tracing spans, and the headers identifying them at an
+    /// intermediary.
+    pub fn spans(&self, spans: &mut Spans) {
+        let redacts_pos = self.parameters.iter().any(|parameter| parameter == "pos");
+
+        for span in spans.values_mut().flat_map(|spans| spans.values_mut()) {
+            span.uri = self.uri(&span.uri);
+
+            if let Some(pos) = span.pos.as_mut().filter(|_| redacts_pos) {
+                *pos = PLACEHOLDER.to_owned();
+            }
+
+            if let Some(error_message) = &mut span.error_message {
+                *error_message = self.text(error_message);
+            }
+
+            if let Some(error) = &mut span.error {
+                *error = self.text(error);
+            }
+
+            for warning in &mut span.warnings {
+                warning.message = self.text(&warning.message);
+            }
+
+            for body in [&mut span.bodies.request, &mut span.bodies.response]
+                .into_iter()
+                .flatten()
+            {
+                *body = self.body(body);
+            }
+
+            for fields in span
+                .frames
+                .iter_mut()
+                .filter_map(|frame| frame.fields.as_mut())
+            {
+                *fields = self.text(fields);
+            }
+
+            for (name, value) in &mut span.intermediary.values {
+                if IDENTIFYING_HEADERS.contains(name) {
+                    *value = PLACEHOLDER.to_owned();
+                }
+            }
+        }
+    }
+
+    /// Redact the lines of the excerpts of the logs.
+    pub fn excerpts(&self, excerpts: &mut Excerpts) {
+        for excerpt in excerpts.values_mut() {
+            for line in excerpt.request.iter_mut().chain(&mut excerpt.response) {
+                *line = self.text(line);
+            }
+        }
+    }
+}
+
+/// Decode the percent-encoded bytes of a path segment or of a query value.
+fn decode(encoded: &str) -> String {
+    let bytes = encoded.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut nth = 0;
+
+    while nth < bytes.len() {
+        match encoded
+            .get(nth + 1..nth + 3)
+            .filter(|_| bytes[nth] == b'%')
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            Some(byte) => {
+                decoded.push(byte);
+                nth += 3;
+            }
+            None => {
+                decoded.push(bytes[nth]);
+                nth += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uri() {
+        let mut redaction = Redaction::default();
+
+        for (uri, expected) in [
+            (
+                "https://matrix.example.org/_matrix/client/unstable/org.matrix.simplified_msc3575/sync?pos=123&timeout=30000",
+                "https://matrix.example.org/_matrix/client/unstable/org.matrix.simplified_msc3575/sync?pos=<redacted>&timeout=30000",
+            ),
+            (
+                "https://matrix.example.org/_matrix/client/v3/sync?since=s72594_4483&access%5Ftoken=syt_secret#top",
+                "https://matrix.example.org/_matrix/client/v3/sync?since=<redacted>&access%5Ftoken=<redacted>#top",
+            ),
+            (
+                "https://matrix.example.org/_matrix/client/v3/profile/%40alice%3Aexample.org/displayname",
+                "https://matrix.example.org/_matrix/client/v3/profile/@<redacted>/displayname",
+            ),
+            (
+                "https://matrix.example.org/_matrix/client/v3/join/%23room%3Aexample.org?via=example.org&via=other.org",
+                "https://matrix.example.org/_matrix/client/v3/join/%23room%3Aexample.org?via=<redacted>&via=<redacted>",
+            ),
+            ("x?pos=123", "x?pos=<redacted>"),
+        ] {
+            assert_eq!(redaction.uri(uri), expected, "{uri}");
+        }
+
+        redaction.add_parameter("from");
+
+        assert_eq!(
+            redaction
+                .uri("https://example.org/_matrix/client/v3/rooms/!abc/messages?from=t1&dir=b"),
+            "https://example.org/_matrix/client/v3/rooms/!abc/messages?from=<redacted>&dir=b"
+        );
+        assert_eq!(
+            redaction.text("Failed to send https://example.org/sync?since=s1&timeout=0: timeout"),
+            "Failed to send https://example.org/sync?since=<redacted>&timeout=0: timeout"
+        );
+        assert_eq!(
+            redaction.body(
+                "{\n  \"access_token\": \"syt_secret\",\n  \"from\": \"t\\\"1\",\n  \"password\": \"hunter2\",\n  \"user_id\": \"@alice:example.org\"\n}"
+            ),
+            "{\n  \"access_token\": \"<redacted>\",\n  \"from\": \"<redacted>\",\n  \"password\": \"<redacted>\",\n  \"user_id\": \"@alice:example.org\"\n}"
+        );
+    }
+
+    #[test]
+    fn test_pseudonymize() {
+        let mut redaction = Redaction::default();
+        redaction.pseudonymize();
+
+        let uri = redaction.uri(
+            "https://matrix.example.org/_matrix/client/v3/rooms/%21abc%3Aexample.org/event/$ev1?access_token=syt_secret&user_id=%40alice%3Aexample.org",
+        );
+        let url = Url::parse(&uri, None).unwrap();
+        let server_name = url.hostname().to_owned();
+        let segments = url.pathname().split('/').collect::<Vec<_>>();
+
+        assert!(server_name.starts_with("server-"));
+        assert!(!uri.contains("example.org"), "{uri}");
+        assert!(uri.contains("access_token=<redacted>"), "{uri}");
+        assert!(segments[5].starts_with('!'), "{uri}");
+        assert!(segments[7].starts_with('$'), "{uri}");
+
+        // The pseudonyms are consistent across the URIs and the texts.
+        assert_eq!(
+            redaction
+                .uri("https://matrix.example.org/_matrix/client/v3/rooms/!abc:example.org/members"),
+            format!(
+                "https://{server_name}/_matrix/client/v3/rooms/{}/members",
+                segments[5]
+            )
+        );
+        assert_eq!(
+            redaction.text("Failed to join !abc:example.org on https://matrix.example.org/x"),
+            format!("Failed to join {} on https://{server_name}/x", segments[5])
+        );
+        assert_ne!(
+            redaction
+                .uri("https://matrix.example.org/_matrix/client/v3/rooms/!def:example.org/members"),
+            redaction
+                .uri("https://matrix.example.org/_matrix/client/v3/rooms/!abc:example.org/members"),
+        );
+        // A room alias remains in the path.
+        assert!(
+            redaction
+                .uri("https://matrix.example.org/_matrix/client/v3/directory/room/%23room%3Aexample.org")
+                .contains("/room/%23")
+        );
+    }
+}
@@ -0,0 +1,72 @@
+//! Capture how long the server has asked the client to wait before retrying,
+//! from the `retry_after_ms` field of the Matrix errors, or from the
+//! `Retry-After` header, in seconds or as a HTTP-date.
+
+use chrono::{DateTime, FixedOffset, TimeDelta};
+
+use crate::{Span, human};
+
+/// How long the server has asked the client to wait.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RetryAfter {
+    /// A delay from the response.
+    Delay(TimeDelta),
+
+    /// An absolute date.
+    Date(DateTime<FixedOffset>),
+}
+
+impl RetryAfter {
+    /// Parse the value of a `retry_after_ms` field.
+    pub fn from_milliseconds(value: &str) -> Option<Self> {
+        value
+            .parse()
+            .ok()
+            .and_then(TimeDelta::try_milliseconds)
+            .map(Self::Delay)
+    }
+
+    /// Parse the value of a `Retry-After` header: a number of seconds, or a
+    /// HTTP-date like `Wed, 21 Oct 2015 07:28:00 GMT`.
+    pub fn from_header(value: &str) -> Option<Self> {
+        let value = value.trim();
+
+        match value.parse() {
+            Ok(seconds) => TimeDelta::try_seconds(seconds).map(Self::Delay),
+            Err(_) => DateTime::parse_from_rfc2822(value).ok().map(Self::Date),
+        }
+    }
+
+    /// Get the date until which the client has been asked to wait, counted
+    /// from the response of `span`.
+    pub fn until(&self, span: &Span) -> DateTime<FixedOffset> {
+        match self {
+            Self::Delay(delay) => span.start_at + span.duration + *delay,
+            Self::Date(date) => *date,
+        }
+    }
+
+    /// Get the delay the client has been asked to wait, counted from the
+    /// response of `span`.
+    pub fn delay(&self, span: &Span) -> TimeDelta {
+        match self {
+            Self::Delay(delay) => *delay,
+            Self::Date(date) => *date - (span.start_at + span.duration),
+        }
+    }
+}
+
+/// Render the retry-after of a span, e.g. `2.00s until 09:14:05.235`, with
+/// the date in `timezone`.
+pub fn label(span: &Span, timezone: FixedOffset) -> Option<String> {
+    let retry_after = span.retry_after.as_ref()?;
+
+    Some(format!(
+        "{delay} until {until}",
+        delay = human::duration(retry_after.delay(span)),
+        until = retry_after
+            .until(span)
+            .with_timezone(&timezone)
+            .format("%H:%M:%S%.3f"),
+    ))
+}
@@ -0,0 +1,133 @@
+//! Aggregate the traffic per room, with `--group-by room`, from the
+//! `/rooms/{roomId}/` segment of the paths, so that a misbehaving room stands
+//! out. The requests without a room, like the syncs, are left out.
+
+use std::{cmp::Reverse, collections::BTreeMap};
+
+use crate::{Spans, buckets::Aggregate, html, human, stats};
+
+/// Aggregate the spans per room, the rooms with the most requests first.
+pub fn per_room(spans: &Spans) -> Vec<(String, Aggregate)> {
+    let mut per_room = BTreeMap::<_, Aggregate>::new();
+
+    for span in spans.values().flat_map(|spans| spans.values()) {
+        if let Some(room_id) = span.room_id() {
+            per_room.entry(room_id).or_default().add(span);
+        }
+    }
+
+    let mut per_room = per_room.into_iter().collect::<Vec<_>>();
+
+    for (_, aggregate) in &mut per_room {
+        aggregate.finish();
+    }
+
+    per_room.sort_by_key(|(_, aggregate)| Reverse(aggregate.requests));
+
+    per_room
+}
+
+/// Render the traffic per room as a table.
+pub fn to_html(per_room: &[(String, Aggregate)]) -> String {
+    let rows = per_room
+        .iter()
+        .map(|(room_id, aggregate)| {
+            format!(
+                "      <tr>
+        <td><code>{room_id}</code></td>
+        <td>{requests}</td>
+        <td>{errors}</td>
+        <td>{error_rate}</td>
+        <td>{bytes_down}</td>
+        <td>{bytes_up}</td>
+        <td>{p95_duration}</td>
+      </tr>
+",
+                room_id = html::escape(room_id),
+                requests = human::count(aggregate.requests),
+                errors = human::count(aggregate.errors),
+                error_rate = human::percentage(aggregate.errors as f64, aggregate.requests as f64),
+                bytes_down = human::bytes_to_html(aggregate.bytes_down),
+                bytes_up = human::bytes_to_html(aggregate.bytes_up),
+                p95_duration = aggregate.p95_duration(),
+            )
+        })
+        .collect::<String>();
+
+    format!(
+        "  <h3>Requests per room</h3>
+  <table>
+    <thead>
+      <tr>
+        <th scope=\"col\">Room</th>
+        <th scope=\"col\">Requests</th>
+        <th scope=\"col\">Errors</th>
+        <th scope=\"col\">Error rate</th>
+        <th scope=\"col\">Bytes down</th>
+        <th scope=\"col\">Bytes up</th>
+        <th scope=\"col\">p95 duration</th>
+      </tr>
+    </thead>
+    <tbody>
+{rows}    </tbody>
+  </table>
+"
+    )
+}
+
+/// Render the traffic per room as CSV.
+pub fn to_csv(per_room: &[(String, Aggregate)]) -> String {
+    let mut output =
+        "room_id,requests,errors,error_rate,bytes_down,bytes_up,p95_duration_ms\n".to_owned();
+
+    for (room_id, aggregate) in per_room {
+        output.push_str(&format!(
+            "{room_id},{requests},{errors},{error_rate},{bytes_down},{bytes_up},{p95_duration}\n",
+            // The room IDs have no comma nor quote.
+            room_id = room_id.replace([',', '"'], ""),
+            requests = aggregate.requests,
+            errors = aggregate.errors,
+            error_rate = aggregate.errors as f64 / aggregate.requests as f64,
+            bytes_down = aggregate.bytes_down,
+            bytes_up = aggregate.bytes_up,
+            p95_duration = stats::percentile(&aggregate.durations, 95.)
+                .map(|duration| duration.num_milliseconds().to_string())
+                .unwrap_or_default(),
+        ));
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SpansBuilder;
+
+    #[test]
+    fn test_per_room() {
+        let mut spans = SpansBuilder::default();
+        let mut span = |request_id, path: &str, status| {
+            spans.span(
+                "room-list",
+                request_id,
+                &format!("https://example.org/_matrix/client/v3/{path}"),
+                Some(status),
+                0,
+                100,
+            );
+        };
+        span(1, "rooms/!abc:example.org/messages", 200);
+        span(2, "rooms/!def:example.org/messages", 200);
+        span(3, "rooms/%21def%3Aexample.org/typing/@alice", 502);
+        span(4, "sync", 200);
+        let spans = spans.build();
+
+        assert_eq!(
+            to_csv(&per_room(&spans)),
+            "room_id,requests,errors,error_rate,bytes_down,bytes_up,p95_duration_ms\n\
+            !def:example.org,2,1,0.5,0,0,100\n\
+            !abc:example.org,1,0,0,0,0,100\n"
+        );
+    }
+}
@@ -0,0 +1,212 @@
+//! Serve the report over HTTP from memory, with
+//! `network-viewer serve [options] <log_path>...`, instead of writing it.
+//!
+//! Besides the HTML report at `/`, a small JSON API is exposed for the
+//! frontend: `/api/spans`, the dataset of the spans as exported by
+//! `--format json`, and `/api/summary`, the counts of the log, see
+//! [`summary`](crate::summary). With `--follow`, the pages are updated as the
+//! log grows, and the report reloads itself. The server listens on the
+//! loopback interface only, since the report may hold the tokens of the log.
+
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream},
+    sync::{Arc, Mutex, PoisonError},
+    thread,
+    time::Duration,
+};
+
+use crate::interrupt;
+
+/// Default port of the server.
+pub const DEFAULT_PORT: u16 = 8080;
+
+/// Time to wait for a client to send its request.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often the server checks for Ctrl-C.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The served pages. They are shared, so that a response is written without
+/// holding the lock of the pages, which are replaced in the meantime in
+/// follow mode.
+#[derive(Clone)]
+pub struct Pages {
+    pub report: Arc<[u8]>,
+    pub spans: Arc<[u8]>,
+    pub summary: Arc<[u8]>,
+}
+
+impl Default for Pages {
+    fn default() -> Self {
+        Self {
+            report: Arc::from(&b""[..]),
+            spans: Arc::from(&b""[..]),
+            summary: Arc::from(&b""[..]),
+        }
+    }
+}
+
+pub struct Server {
+    address: SocketAddr,
+    pages: Arc<Mutex<Pages>>,
+}
+
+impl Server {
+    /// Listen on `port` of the loopback interface, `0` for any free port, and
+    /// serve the pages in the background.
+    pub fn bind(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        let address = listener.local_addr()?;
+        let pages = Arc::new(Mutex::new(Pages::default()));
+        let served_pages = Arc::clone(&pages);
+
+        // Each client is served by its own thread, so that an idle one, e.g. a
+        // preconnection of a browser, doesn't block the others.
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let pages = Arc::clone(&served_pages);
+
+                thread::spawn(move || {
+                    if let Err(error) = respond(stream, &pages) {
+                        eprintln!("Failed to serve a request: {error}");
+                    }
+                });
+            }
+        });
+
+        Ok(Self { address, pages })
+    }
+
+    /// The URL of the report.
+    pub fn url(&self) -> String {
+        format!("http://{}/", self.address)
+    }
+
+    /// Replace the served pages.
+    pub fn update(&self, pages: Pages) {
+        *self.pages.lock().unwrap_or_else(PoisonError::into_inner) = pages;
+    }
+
+    /// Keep serving until Ctrl-C.
+    pub fn wait(&self) {
+        interrupt::catch();
+
+        while !interrupt::is_interrupted() {
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+/// Read the request of a client, and answer with the page of its path.
+fn respond(stream: TcpStream, pages: &Mutex<Pages>) -> io::Result<()> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    // The headers are ignored.
+    let mut header = String::new();
+
+    while reader.read_line(&mut header)? > 0 && !header.trim().is_empty() {
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let path = parts
+        .next()
+        .unwrap_or_default()
+        .split(['?', '#'])
+        .next()
+        .unwrap_or_default();
+
+    let pages = pages.lock().unwrap_or_else(PoisonError::into_inner).clone();
+    let (status, content_type, body): (_, _, &[u8]) = match (method, path) {
+        ("GET", "/" | "/index.html") => ("200 OK", "text/html; charset=utf-8", &pages.report),
+        ("GET", "/api/spans") => ("200 OK", "application/json", &pages.spans),
+        ("GET", "/api/summary") => ("200 OK", "application/json", &pages.summary),
+        ("GET", _) => ("404 Not Found", "text/plain", b"Not found"),
+        _ => (
+            "405 Method Not Allowed",
+            "text/plain",
+            b"Only GET is allowed",
+        ),
+    };
+
+    let mut stream = reader.into_inner();
+
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {length}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n",
+        length = body.len(),
+    )?;
+    stream.write_all(body)?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+    use crate::SpansBuilder;
+
+    #[test]
+    fn test_serve() {
+        let server = Server::bind(0).unwrap();
+        let mut spans = SpansBuilder::default();
+        spans.span(
+            "room-list",
+            1,
+            "https://example.org/_matrix/client/v3/sync",
+            Some(502),
+            0,
+            100,
+        );
+        let spans = spans.build();
+
+        server.update(Pages {
+            report: Arc::from(&b"<!doctype html>"[..]),
+            spans: Arc::from(&b"{}"[..]),
+            summary: Arc::from(crate::summary::to_json(
+                "app.log",
+                3,
+                2,
+                &spans,
+                chrono::FixedOffset::east_opt(0).unwrap(),
+            )),
+        });
+
+        let get = |request: &str| {
+            let mut stream = TcpStream::connect(server.address).unwrap();
+            stream.set_read_timeout(Some(TIMEOUT / 2)).unwrap();
+            stream.write_all(request.as_bytes()).unwrap();
+
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+
+            response
+        };
+
+        // An idle client blocks neither the others nor the updates.
+        let _idle = TcpStream::connect(server.address).unwrap();
+        let pages = server.pages.lock().unwrap().clone();
+        server.update(pages);
+
+        let response = get("GET /?x=1 HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("\r\n\r\n<!doctype html>"));
+
+        let response = get("GET /api/summary HTTP/1.1\r\n\r\n");
+        let summary = response.split("\r\n\r\n").nth(1).unwrap();
+        let summary = serde_json::from_str::<serde_json::Value>(summary).unwrap();
+        assert_eq!(summary["number_of_spans"], 1);
+        assert_eq!(summary["number_of_failed_spans"], 1);
+
+        assert!(get("GET /missing HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 404 Not Found\r\n"));
+        assert!(get("POST / HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+    }
+}
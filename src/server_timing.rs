@@ -0,0 +1,72 @@
+//! Parse the `Server-Timing` headers of the responses, e.g.
+//! `db;dur=53, app;dur=47.2`, to tell the time spent by the server from the
+//! time spent on the network or queueing.
+
+use std::fmt;
+
+/// A metric of a `Server-Timing` header.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Metric {
+    pub name: String,
+
+    /// Duration, in milliseconds.
+    pub duration: Option<f64>,
+}
+
+impl fmt::Display for Metric {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.duration {
+            Some(duration) => write!(formatter, "{};dur={duration}", self.name),
+            None => write!(formatter, "{}", self.name),
+        }
+    }
+}
+
+/// Parse the value of a `Server-Timing` header. Parameters other than `dur`,
+/// e.g. `desc`, are ignored.
+pub fn parse(value: &str) -> Vec<Metric> {
+    value
+        .split(',')
+        .filter_map(|metric| {
+            let mut parts = metric.split(';').map(str::trim);
+            let name = parts.next().filter(|name| !name.is_empty())?;
+            let duration = parts.find_map(|parameter| {
+                let (key, value) = parameter.split_once('=')?;
+
+                key.trim()
+                    .eq_ignore_ascii_case("dur")
+                    .then(|| value.trim().trim_matches('"').parse().ok())
+                    .flatten()
+            });
+
+            Some(Metric {
+                name: name.to_owned(),
+                duration,
+            })
+        })
+        .collect()
+}
+
+/// Get the time spent by the server, in milliseconds: the `total` metric if
+/// any, otherwise the longest metric, since metrics may overlap.
+pub fn server_duration(metrics: &[Metric]) -> Option<f64> {
+    metrics
+        .iter()
+        .find(|metric| metric.name.eq_ignore_ascii_case("total"))
+        .and_then(|metric| metric.duration)
+        .or_else(|| {
+            metrics
+                .iter()
+                .filter_map(|metric| metric.duration)
+                .max_by(f64::total_cmp)
+        })
+}
+
+/// Format the metrics back as the value of a `Server-Timing` header.
+pub fn to_header(metrics: &[Metric]) -> String {
+    metrics
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -0,0 +1,106 @@
+//! Capture the setup of the connection of a request, logged at the trace
+//! level: whether an idle connection of the pool is reused, and the durations
+//! of the DNS resolution, of the TCP connection, of the TLS handshake, and
+//! the time to the first byte of the response. It tells whether a latency
+//! comes from the network setup or from the processing of the homeserver.
+//!
+//! The durations are fields like `dns=3ms`, `connect_duration=20.5ms` or
+//! `tls=45ms`, in milliseconds without a unit. The reuse is a field like
+//! `connection_reused=true`, or the `reuse idle connection` and `starting new
+//! connection` messages of the HTTP client.
+
+/// The setup of the connection of a request. The durations are in
+/// milliseconds.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Setup {
+    pub reused: Option<bool>,
+    pub dns: Option<f64>,
+    pub connect: Option<f64>,
+    pub tls: Option<f64>,
+    pub ttfb: Option<f64>,
+}
+
+impl Setup {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Set a captured field: a duration, `dns`, `connect`, `tls` or `ttfb`,
+    /// like `20.5ms`, or `reused`, `true` or `false`. Unknown names and
+    /// malformed values are ignored.
+    pub fn insert(&mut self, name: &str, value: &str) {
+        if name == "reused" {
+            self.reused = value.parse().ok().or(self.reused);
+
+            return;
+        }
+
+        let unit_start = value
+            .find(|character: char| !character.is_ascii_digit() && character != '.')
+            .unwrap_or(value.len());
+        let (number, unit) = value.split_at(unit_start);
+        let Some(duration) = number.parse::<f64>().ok().and_then(|number| {
+            Some(match unit {
+                "" | "ms" => number,
+                "s" => number * 1_000.,
+                "µs" | "us" => number / 1_000.,
+                "ns" => number / 1_000_000.,
+                _ => return None,
+            })
+        }) else {
+            return;
+        };
+
+        match name {
+            "dns" => self.dns = Some(duration),
+            "connect" => self.connect = Some(duration),
+            "tls" => self.tls = Some(duration),
+            "ttfb" => self.ttfb = Some(duration),
+            _ => {}
+        }
+    }
+
+    /// Add the fields captured on another line of the same request.
+    pub fn extend(&mut self, other: Self) {
+        self.reused = other.reused.or(self.reused);
+        self.dns = other.dns.or(self.dns);
+        self.connect = other.connect.or(self.connect);
+        self.tls = other.tls.or(self.tls);
+        self.ttfb = other.ttfb.or(self.ttfb);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert() {
+        let mut setup = Setup::default();
+        setup.insert("dns", "3");
+        setup.insert("connect", "20.5ms");
+        setup.insert("tls", "0.045s");
+        setup.insert("ttfb", "1500µs");
+        setup.insert("reused", "false");
+        setup.insert("tls", "many");
+
+        assert_eq!(
+            setup,
+            Setup {
+                reused: Some(false),
+                dns: Some(3.),
+                connect: Some(20.5),
+                tls: Some(45.),
+                ttfb: Some(1.5),
+            }
+        );
+
+        setup.extend(Setup {
+            reused: Some(true),
+            ..Setup::default()
+        });
+
+        assert_eq!(setup.reused, Some(true));
+        assert!(Setup::default().is_empty());
+    }
+}
@@ -0,0 +1,93 @@
+//! Parse the human-readable sizes logged by the SDK, e.g. `92B`, `1.2 kB` or
+//! `3,4MiB`. See [`crate::human::bytes`] to format them.
+
+use std::{fmt, ops::Deref};
+
+/// A size as logged, e.g. `1.2 kB`, with its number of bytes if it can be
+/// parsed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Size {
+    logged: String,
+    bytes: Option<u64>,
+}
+
+impl Size {
+    pub fn new(logged: &str) -> Self {
+        Self {
+            logged: logged.to_owned(),
+            bytes: parse(logged),
+        }
+    }
+
+    /// Get the number of bytes, `None` if the size can't be parsed.
+    pub fn bytes(&self) -> Option<u64> {
+        self.bytes
+    }
+}
+
+/// The size as logged.
+impl Deref for Size {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.logged
+    }
+}
+
+impl fmt::Display for Size {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str(&self.logged)
+    }
+}
+
+/// Parse a human-readable size into a number of bytes.
+///
+/// Both `.` and `,` are accepted as the decimal separator. `None` is returned
+/// if the number or the unit is unknown.
+pub fn parse(size: &str) -> Option<u64> {
+    let size = size.trim();
+    let unit_start = size
+        .find(|character: char| {
+            !(character.is_ascii_digit() || character == '.' || character == ',')
+        })
+        .unwrap_or(size.len());
+    let (value, unit) = size.split_at(unit_start);
+    let value: f64 = value.replace(',', ".").parse().ok()?;
+
+    let multiplier: u64 = match unit.trim() {
+        "" | "B" => 1,
+        "kB" | "KB" => 1_000,
+        "KiB" => 1 << 10,
+        "MB" => 1_000_000,
+        "MiB" => 1 << 20,
+        "GB" => 1_000_000_000,
+        "GiB" => 1 << 30,
+        _ => return None,
+    };
+
+    Some((value * multiplier as f64).round() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_size() {
+        for (logged, bytes) in [
+            ("92B", Some(92)),
+            ("12.4 kB", Some(12_400)),
+            ("1,5KiB", Some(1_536)),
+            ("1.2 MB", Some(1_200_000)),
+            ("2MiB", Some(2_097_152)),
+            ("1GB", Some(1_000_000_000)),
+            ("3 parsecs", None),
+        ] {
+            let size = Size::new(logged);
+
+            assert_eq!(size.bytes(), bytes, "{logged}");
+            // The size is displayed as logged.
+            assert_eq!(size.to_string(), logged);
+        }
+    }
+}
@@ -0,0 +1,179 @@
+//! Mark the requests slower than a threshold, with `--slow-threshold`, so that
+//! the few requests which took 8 seconds stand out of the report.
+//!
+//! The threshold can be overridden per category of endpoints, see
+//! [`Category`], e.g. `keys_query=1s`, with `--slow-threshold` or in the
+//! `[slow_thresholds]` table of `--config`. Only the requests with a response
+//! are marked.
+
+use std::collections::BTreeMap;
+
+use chrono::TimeDelta;
+
+use crate::{Span, Spans, duration, endpoint::Category, html, human};
+
+/// Duration thresholds, optionally per category.
+#[derive(Clone, Debug, Default)]
+pub struct Thresholds {
+    default: Option<TimeDelta>,
+    per_category: BTreeMap<Category, TimeDelta>,
+}
+
+impl Thresholds {
+    /// Parse a threshold like `2s`, or a number of milliseconds, or like
+    /// `keys_query=1s` to override it for a category.
+    pub fn parse(&mut self, value: &str) -> Result<(), String> {
+        match value.split_once('=') {
+            Some((category, threshold)) => self.insert(Some(category), threshold),
+            None => self.insert(None, value),
+        }
+    }
+
+    /// Set the threshold of a category, or the default one.
+    pub fn insert(&mut self, category: Option<&str>, threshold: &str) -> Result<(), String> {
+        let threshold = threshold.trim();
+        let threshold = threshold
+            .parse()
+            .ok()
+            .and_then(TimeDelta::try_milliseconds)
+            .or_else(|| duration::parse(threshold))
+            .ok_or_else(|| format!("`{threshold}` isn't a duration like `2s` or `500ms`"))?;
+
+        match category {
+            Some(category) => {
+                // The names of the categories have spaces: `keys_query` and
+                // `keys-query` are accepted too.
+                let normalize = |name: &str| name.trim().replace(['_', '-'], " ");
+                let category = Category::ALL
+                    .into_iter()
+                    .find(|candidate| normalize(candidate.as_str()) == normalize(category))
+                    .ok_or_else(|| {
+                        format!(
+                            "Unknown category `{category}`; valid categories are {}",
+                            Category::ALL
+                                .iter()
+                                .map(|category| format!("`{}`", category.as_str()))
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        )
+                    })?;
+
+                self.per_category.insert(category, threshold);
+            }
+            None => self.default = Some(threshold),
+        }
+
+        Ok(())
+    }
+
+    /// Add the thresholds of `other`, e.g. of `--config`, overriding the
+    /// ones already set.
+    pub fn extend(&mut self, other: Self) {
+        self.default = other.default.or(self.default);
+        self.per_category.extend(other.per_category);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.default.is_none() && self.per_category.is_empty()
+    }
+
+    /// Whether a span with a response is slower than its threshold.
+    pub fn is_slow(&self, span: &Span) -> bool {
+        span.response_log_line.is_some()
+            && self
+                .per_category
+                .get(&span.category())
+                .or(self.default.as_ref())
+                .is_some_and(|threshold| span.duration > *threshold)
+    }
+
+    fn count(&self, spans: &Spans) -> usize {
+        spans
+            .values()
+            .flat_map(|spans| spans.values())
+            .filter(|span| self.is_slow(span))
+            .count()
+    }
+
+    /// Describe the thresholds, e.g. `2.00s, keys query: 1.00s`.
+    fn describe(&self) -> String {
+        self.default
+            .iter()
+            .map(|threshold| human::duration(*threshold))
+            .chain(self.per_category.iter().map(|(category, threshold)| {
+                format!("{}: {}", category.as_str(), human::duration(*threshold))
+            }))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Render the number of slow requests, or nothing without threshold.
+    pub fn to_text(&self, spans: &Spans) -> String {
+        if self.is_empty() {
+            return String::new();
+        }
+
+        format!(
+            "Number of slow requests: {number_of_slow_requests} (thresholds: {thresholds})\n",
+            number_of_slow_requests = human::count(self.count(spans)),
+            thresholds = self.describe(),
+        )
+    }
+
+    /// Render the number of slow requests as HTML, or nothing without
+    /// threshold.
+    pub fn to_html(&self, spans: &Spans) -> String {
+        if self.is_empty() {
+            return String::new();
+        }
+
+        format!(
+            "  <p class=\"slow-requests\">Slow requests: {number_of_slow_requests}, above the thresholds of <code>--slow-threshold</code> ({thresholds}).</p>\n",
+            number_of_slow_requests = human::count(self.count(spans)),
+            thresholds = html::escape(&self.describe()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SpansBuilder;
+
+    #[test]
+    fn test_thresholds() {
+        let mut thresholds = Thresholds::default();
+        thresholds.parse("2000").unwrap();
+        thresholds.parse("keys_query=1s").unwrap();
+
+        let span = |path: &str, duration| {
+            Span::for_tests(
+                &format!("https://example.org/_matrix/client/v3/{path}"),
+                Some(200),
+                TimeDelta::milliseconds(duration),
+            )
+        };
+
+        assert!(thresholds.is_slow(&span("rooms/!abc:example.org/messages", 8_000)));
+        assert!(!thresholds.is_slow(&span("rooms/!abc:example.org/messages", 1_500)));
+        assert!(thresholds.is_slow(&span("keys/query", 1_500)));
+
+        let keys_query = "https://example.org/_matrix/client/v3/keys/query";
+        let mut spans = SpansBuilder::default();
+        spans.span("c", 1, keys_query, Some(200), 0, 1_500);
+        spans.span("c", 2, keys_query, Some(200), 0, 10);
+        let spans = spans.build();
+
+        assert_eq!(
+            thresholds.to_text(&spans),
+            "Number of slow requests: 1 (thresholds: 2.00s, keys query: 1.00s)\n"
+        );
+        assert!(
+            thresholds
+                .parse("typing=1s")
+                .unwrap_err()
+                .starts_with("Unknown category `typing`")
+        );
+        assert!(Thresholds::default().to_text(&spans).is_empty());
+    }
+}
@@ -0,0 +1,551 @@
+//! Where the logs are read from.
+//!
+//! The log files can be gzipped, e.g. `console.log.gz` from a rageshake, or
+//! compressed with zstd, e.g. `console.log.zst`: they are decompressed on the
+//! fly. There is no zstd decoder in the dependencies, so the `zstd` command
+//! decompresses them.
+
+use std::{
+    fs,
+    io::{self, BufRead, Read},
+    path::Path,
+    process::{Child, ChildStdout, Command, Stdio},
+    thread,
+    time::Duration,
+};
+
+use chrono::{DateTime, FixedOffset};
+use flate2::bufread::MultiGzDecoder;
+
+use crate::{dedup::Deduplicator, error::Error, interrupt, listen, meta::SourceFile, parser};
+
+/// Magic bytes starting a gzip stream.
+pub const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+
+/// Magic bytes starting a zstd frame.
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// Extensions of the log files read from a directory.
+pub const LOG_EXTENSIONS: [&str; 3] = [".log", ".log.gz", ".log.zst"];
+
+pub enum Source {
+    /// Log files, read one after the other. Lines already present in a
+    /// previous file are skipped.
+    Files(Vec<String>),
+    /// The standard input, e.g. piped from the running application, or from
+    /// `adb logcat`. It can be gzipped too.
+    Stdin,
+    /// A TCP socket, see [`listen`].
+    Listen {
+        address: String,
+        idle_timeout: Duration,
+    },
+    /// A log file which is still written, read until Ctrl-C, see [`follow`].
+    Follow {
+        path: String,
+        poll_interval: Duration,
+    },
+}
+
+impl Source {
+    /// Make a source from log files, or directories whose `*.log`,
+    /// `*.log.gz` and `*.log.zst` files are read.
+    ///
+    /// The files are read in the order of their first datetime, not in the
+    /// order of the arguments, so that rotated files, e.g. `console.log` and
+    /// `console.1.log`, form a single timeline: a response is matched to its
+    /// request even if they are in different files.
+    pub fn from_paths(paths: Vec<String>) -> Result<Self, Error> {
+        let mut files = Vec::new();
+
+        for path in paths {
+            if !Path::new(&path).is_dir() {
+                files.push(path);
+
+                continue;
+            }
+
+            let mut log_files = fs::read_dir(&path)
+                .map_err(Error::io(&path))?
+                .filter_map(|entry| Some(entry.ok()?.path()))
+                .filter(|path| path.is_file())
+                .map(|path| path.to_string_lossy().into_owned())
+                .filter(|path| {
+                    LOG_EXTENSIONS
+                        .iter()
+                        .any(|extension| path.ends_with(extension))
+                })
+                .collect::<Vec<_>>();
+
+            if log_files.is_empty() {
+                return Err(Error::Usage(format!(
+                    "`{path}` has no `*.log`, `*.log.gz` or `*.log.zst` files"
+                )));
+            }
+
+            log_files.sort();
+            files.extend(log_files);
+        }
+
+        let mut first_datetimes = files
+            .iter()
+            .map(|path| Ok((first_datetime(path)?, path)))
+            .collect::<Result<Vec<_>, Error>>()?;
+        // The files without any datetime are read last, in the order of the
+        // arguments.
+        first_datetimes
+            .sort_by_key(|(first_datetime, _)| (first_datetime.is_none(), *first_datetime));
+
+        let ordered_files = first_datetimes
+            .into_iter()
+            .map(|(_, path)| path.clone())
+            .collect::<Vec<_>>();
+
+        if files.len() > 1 && ordered_files != files {
+            eprintln!(
+                "Reading {} in the order of their first datetime",
+                ordered_files
+                    .iter()
+                    .map(|path| format!("`{path}`"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+
+        Ok(Self::Files(ordered_files))
+    }
+
+    /// Name of the source, as displayed in the report.
+    pub fn name(&self) -> String {
+        match self {
+            Self::Files(paths) => paths.join(", "),
+            Self::Stdin => "(stdin)".to_owned(),
+            Self::Listen { address, .. } => format!("tcp://{address}"),
+            Self::Follow { path, .. } => path.clone(),
+        }
+    }
+
+    /// Files of the source, with their sizes, for the metadata of the report.
+    pub fn files(&self) -> Vec<SourceFile> {
+        match self {
+            Self::Files(paths) => paths
+                .iter()
+                .map(|path| SourceFile {
+                    name: path.clone(),
+                    size: fs::metadata(path).ok().map(|metadata| metadata.len()),
+                })
+                .collect(),
+            Self::Follow { path, .. } => vec![SourceFile {
+                name: path.clone(),
+                size: fs::metadata(path).ok().map(|metadata| metadata.len()),
+            }],
+            _ => vec![SourceFile {
+                name: self.name(),
+                size: None,
+            }],
+        }
+    }
+
+    /// Call `on_line` for every line, until the source is exhausted. A line
+    /// which isn't valid UTF-8 is given as bytes. The location of a line is
+    /// known only if the source is a file.
+    pub fn read_lines(
+        &self,
+        mut on_line: impl FnMut(Result<&str, &[u8]>, Option<Location>),
+    ) -> Result<(), Error> {
+        match self {
+            Self::Files(paths) => {
+                let mut deduplicator = Deduplicator::default();
+
+                for (file_nth, path) in paths.iter().enumerate() {
+                    let log_file = open(path).map_err(Error::io(path))?;
+
+                    read_all_lines(log_file, |line, offset| {
+                        let location = Some(Location { file_nth, offset });
+
+                        match line {
+                            // A single file has no overlap: its lines aren't
+                            // hashed, which would grow with the log.
+                            Ok(line)
+                                if paths.len() > 1 && deduplicator.is_duplicate(file_nth, line) => {
+                            }
+                            line => on_line(line, location),
+                        }
+                    })
+                    .map_err(Error::io(path))?;
+                }
+
+                for ((first_file_nth, file_nth), number_of_lines) in deduplicator.skipped() {
+                    eprintln!(
+                        "Skipped {number_of_lines} lines of `{}` already present in `{}`",
+                        paths[*file_nth], paths[*first_file_nth],
+                    );
+                }
+
+                Ok(())
+            }
+
+            Self::Stdin => open_stdin()
+                .and_then(|stdin| read_all_lines(stdin, |line, _| on_line(line, None)))
+                .map_err(Error::io(self.name())),
+
+            Self::Listen {
+                address,
+                idle_timeout,
+            } => listen::receive(address, *idle_timeout, |line| on_line(line, None))
+                .map_err(Error::io(self.name())),
+
+            Self::Follow {
+                path,
+                poll_interval,
+            } => follow(path, *poll_interval, |line, offset| {
+                on_line(
+                    line,
+                    Some(Location {
+                        file_nth: 0,
+                        offset,
+                    }),
+                )
+            })
+            .map_err(Error::io(path)),
+        }
+    }
+}
+
+/// Location of a line in the files of a source.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Location {
+    /// Index of the file.
+    pub file_nth: usize,
+    /// Offset of the line in the file, in bytes.
+    pub offset: u64,
+}
+
+/// Call `on_line` for every line, with its offset in bytes. A line which isn't
+/// valid UTF-8 is given as bytes.
+pub fn read_all_lines(
+    mut reader: impl BufRead,
+    mut on_line: impl FnMut(Result<&str, &[u8]>, u64),
+) -> io::Result<()> {
+    let mut line = Vec::new();
+    let mut offset = 0;
+
+    loop {
+        line.clear();
+
+        let number_of_bytes = reader.read_until(b'\n', &mut line)?;
+
+        if number_of_bytes == 0 {
+            return Ok(());
+        }
+
+        on_line(decode(&line), offset);
+
+        offset += number_of_bytes as u64;
+    }
+}
+
+/// Compression of a log file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// Detect the compression of a file from its name, or from its first
+    /// bytes.
+    fn detect(path: &str, first_bytes: &[u8]) -> Option<Self> {
+        if path.ends_with(".gz") || first_bytes.starts_with(GZIP_MAGIC) {
+            Some(Self::Gzip)
+        } else if path.ends_with(".zst") || first_bytes.starts_with(ZSTD_MAGIC) {
+            Some(Self::Zstd)
+        } else {
+            None
+        }
+    }
+
+    /// Name of the compression, for the messages.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Gzip => "gzipped",
+            Self::Zstd => "compressed with zstd",
+        }
+    }
+}
+
+/// Get the compression of the file at `path`, if any.
+pub fn compression(path: &str) -> io::Result<Option<Compression>> {
+    let mut first_bytes = Vec::with_capacity(ZSTD_MAGIC.len());
+    fs::File::open(path)?
+        .take(ZSTD_MAGIC.len() as u64)
+        .read_to_end(&mut first_bytes)?;
+
+    Ok(Compression::detect(path, &first_bytes))
+}
+
+/// Open the log file at `path`, decompressing it on the fly if it is
+/// compressed. The offsets of its lines are then in the decompressed stream.
+pub fn open(path: &str) -> io::Result<Box<dyn BufRead>> {
+    let mut log_file = io::BufReader::new(fs::File::open(path)?);
+
+    match Compression::detect(path, log_file.fill_buf()?) {
+        None => Ok(Box::new(log_file)),
+        Some(Compression::Gzip) => Ok(Box::new(io::BufReader::new(Gunzip(MultiGzDecoder::new(
+            Counter {
+                reader: log_file,
+                number_of_bytes: 0,
+            },
+        ))))),
+        Some(Compression::Zstd) => Ok(Box::new(io::BufReader::new(Unzstd::spawn(path)?))),
+    }
+}
+
+/// Open the standard input, decompressing it if it's gzipped, e.g. piped
+/// from `cat console.log.gz`.
+fn open_stdin() -> io::Result<Box<dyn BufRead>> {
+    let mut stdin = io::stdin().lock();
+
+    match Compression::detect("", stdin.fill_buf()?) {
+        None => Ok(Box::new(stdin)),
+        Some(Compression::Gzip) => Ok(Box::new(io::BufReader::new(Gunzip(MultiGzDecoder::new(
+            Counter {
+                reader: stdin,
+                number_of_bytes: 0,
+            },
+        ))))),
+        // `zstd` reads a path, not the standard input of this process.
+        Some(Compression::Zstd) => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "the standard input is compressed with zstd; pipe it through `zstd --decompress --stdout` first",
+        )),
+    }
+}
+
+/// A reader counting the bytes consumed from it.
+struct Counter<R> {
+    reader: R,
+    number_of_bytes: u64,
+}
+
+impl<R: BufRead> Read for Counter<R> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let number_of_bytes = self.reader.read(buffer)?;
+        self.number_of_bytes += number_of_bytes as u64;
+
+        Ok(number_of_bytes)
+    }
+}
+
+impl<R: BufRead> BufRead for Counter<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.reader.fill_buf()
+    }
+
+    fn consume(&mut self, number_of_bytes: usize) {
+        self.number_of_bytes += number_of_bytes as u64;
+        self.reader.consume(number_of_bytes);
+    }
+}
+
+/// A gzip decoder whose errors tell where the compressed stream is corrupt.
+struct Gunzip<R>(MultiGzDecoder<Counter<R>>);
+
+impl<R: BufRead> Read for Gunzip<R> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buffer).map_err(|error| {
+            io::Error::new(
+                error.kind(),
+                format!(
+                    "corrupt gzip stream, around byte {}: {error}",
+                    self.0.get_ref().number_of_bytes
+                ),
+            )
+        })
+    }
+}
+
+/// A zstd decoder: the output of `zstd --decompress`, whose errors are the
+/// ones of the command.
+struct Unzstd {
+    child: Child,
+    stdout: ChildStdout,
+}
+
+impl Unzstd {
+    fn spawn(path: &str) -> io::Result<Self> {
+        let mut child = Command::new("zstd")
+            .args(["--decompress", "--stdout", "--quiet", "--", path])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|error| match error.kind() {
+                io::ErrorKind::NotFound => io::Error::new(
+                    error.kind(),
+                    "the file is compressed with zstd, and the `zstd` command to decompress it isn't installed",
+                ),
+                _ => error,
+            })?;
+        let stdout = child.stdout.take().expect("The standard output is piped");
+
+        Ok(Self { child, stdout })
+    }
+}
+
+impl Read for Unzstd {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let number_of_bytes = self.stdout.read(buffer)?;
+
+        // The stream ends early if it's corrupt: the command tells why.
+        if number_of_bytes == 0 && !buffer.is_empty() {
+            let status = self.child.wait()?;
+
+            if !status.success() {
+                let mut message = String::new();
+
+                if let Some(stderr) = self.child.stderr.as_mut() {
+                    stderr.read_to_string(&mut message)?;
+                }
+
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("corrupt zstd stream: {}", message.trim()),
+                ));
+            }
+        }
+
+        Ok(number_of_bytes)
+    }
+}
+
+impl Drop for Unzstd {
+    /// Stop the command if the file isn't read until its end, e.g. to get its
+    /// first datetime only.
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Call `on_line` for every line of the file at `path`, with its offset in
+/// bytes, waiting `poll_interval` for new lines at its end, until Ctrl-C.
+///
+/// A line is given once complete, i.e. once its line ending is written. The
+/// file is read again from the start if it shrinks, i.e. if it has been
+/// truncated or rotated.
+pub fn follow(
+    path: &str,
+    poll_interval: Duration,
+    mut on_line: impl FnMut(Result<&str, &[u8]>, u64),
+) -> io::Result<()> {
+    let mut reader = io::BufReader::new(fs::File::open(path)?);
+    let mut line = Vec::new();
+    let mut offset = 0;
+    let mut number_of_read_bytes = 0;
+
+    while !interrupt::is_interrupted() {
+        let number_of_bytes = reader.read_until(b'\n', &mut line)?;
+        number_of_read_bytes += number_of_bytes as u64;
+
+        if line.ends_with(b"\n") {
+            on_line(decode(&line), offset);
+
+            offset += line.len() as u64;
+            line.clear();
+
+            continue;
+        }
+
+        if number_of_bytes == 0 {
+            if fs::metadata(path)?.len() < number_of_read_bytes {
+                eprintln!("`{path}` has shrunk, reading it again from the start");
+
+                reader = io::BufReader::new(fs::File::open(path)?);
+                line.clear();
+                offset = 0;
+                number_of_read_bytes = 0;
+            } else {
+                thread::sleep(poll_interval);
+            }
+        }
+    }
+
+    // The last line may never be ended.
+    if !line.is_empty() {
+        on_line(decode(&line), offset);
+    }
+
+    Ok(())
+}
+
+/// Get the datetime of the first log line of the file at `path` having one.
+fn first_datetime(path: &str) -> Result<Option<DateTime<FixedOffset>>, Error> {
+    let log_file = open(path).map_err(Error::io(path))?;
+
+    for line in log_file.split(b'\n') {
+        if let Some(datetime) = decode(&line.map_err(Error::io(path))?)
+            .ok()
+            .and_then(parser::leading_datetime)
+        {
+            return Ok(Some(datetime));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Decode a line, without its line ending.
+pub fn decode(line: &[u8]) -> Result<&str, &[u8]> {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+
+    str::from_utf8(line).map_err(|_| line)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, io::Write, sync::mpsc};
+
+    use super::*;
+
+    #[test]
+    fn test_follow() {
+        let path =
+            env::temp_dir().join(format!("network-viewer-follow-{}.log", std::process::id()));
+        let path = path.to_string_lossy().into_owned();
+        fs::write(&path, "a\nb").unwrap();
+
+        let (sender, receiver) = mpsc::channel();
+        let follower = {
+            let path = path.clone();
+
+            thread::spawn(move || {
+                follow(&path, Duration::from_millis(10), |line, offset| {
+                    sender.send((line.unwrap().to_owned(), offset)).unwrap();
+                })
+            })
+        };
+
+        let next_line = || receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        assert_eq!(next_line(), ("a".to_owned(), 0));
+
+        // The partial line is given once ended.
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"c\n")
+            .unwrap();
+        assert_eq!(next_line(), ("bc".to_owned(), 2));
+
+        // The file is truncated, and written again.
+        fs::write(&path, "d\n").unwrap();
+        assert_eq!(next_line(), ("d".to_owned(), 0));
+
+        interrupt::interrupt();
+        follower.join().unwrap().unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+}
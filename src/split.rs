@@ -0,0 +1,122 @@
+//! Split the report in one file per calendar day, plus an index linking them.
+
+use std::{collections::BTreeMap, path::Path};
+
+use chrono::{DateTime, FixedOffset, TimeDelta};
+
+use crate::{Spans, buckets, human};
+
+/// Spans starting during one calendar day.
+pub struct Day {
+    pub start_at: DateTime<FixedOffset>,
+    pub spans: Spans,
+    /// Name of the file of the report of this day.
+    pub file_name: String,
+}
+
+impl Day {
+    pub fn end_at(&self) -> DateTime<FixedOffset> {
+        self.start_at + TimeDelta::days(1)
+    }
+}
+
+/// Split the spans by the calendar day, in `timezone`, they start on. The
+/// report of each day is named after `output_path`, e.g. `report.html` gives
+/// `report-2024-06-01.html`.
+pub fn by_day(spans: Spans, timezone: FixedOffset, output_path: &str) -> Vec<Day> {
+    let mut days = BTreeMap::<_, Spans>::new();
+
+    for (connection_id, spans_for_connection_id) in spans {
+        for (request_id, span) in spans_for_connection_id {
+            days.entry(buckets::truncate_to_day(
+                span.start_at.with_timezone(&timezone),
+            ))
+            .or_default()
+            .entry(connection_id.clone())
+            .or_default()
+            .insert(request_id, span);
+        }
+    }
+
+    let output_path = Path::new(output_path);
+    let stem = output_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = output_path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+
+    days.into_iter()
+        .map(|(start_at, spans)| Day {
+            file_name: format!(
+                "{stem}-{date}{extension}",
+                date = start_at.format("%Y-%m-%d")
+            ),
+            start_at,
+            spans,
+        })
+        .collect()
+}
+
+/// Path of the report of a day, next to `output_path`.
+pub fn day_path(output_path: &str, day: &Day) -> String {
+    Path::new(output_path)
+        .with_file_name(&day.file_name)
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Render the index of the days, with their headline statistics.
+pub fn index_to_html(days: &[Day]) -> String {
+    let rows = days
+        .iter()
+        .map(|day| {
+            let (aggregate, _) = buckets::per_kind(&day.spans);
+
+            format!(
+                "      <tr>
+        <td><a href=\"{file_name}\">{date}</a></td>
+        <td>{requests}</td>
+        <td>{errors}</td>
+        <td>{error_rate}</td>
+        <td>{bytes_down}</td>
+        <td>{bytes_up}</td>
+        <td>{p95_duration}</td>
+      </tr>
+",
+                file_name = day.file_name,
+                date = day.start_at.format("%Y-%m-%d %:z"),
+                requests = human::count(aggregate.requests),
+                errors = human::count(aggregate.errors),
+                error_rate = human::percentage(aggregate.errors as f64, aggregate.requests as f64),
+                bytes_down = human::bytes_to_html(aggregate.bytes_down),
+                bytes_up = human::bytes_to_html(aggregate.bytes_up),
+                p95_duration = aggregate.p95_duration(),
+            )
+        })
+        .collect::<String>();
+
+    format!(
+        "<section class=\"summary\">
+  <h2>Days</h2>
+  <table>
+    <thead>
+      <tr>
+        <th scope=\"col\">Day</th>
+        <th scope=\"col\">Requests</th>
+        <th scope=\"col\">Errors</th>
+        <th scope=\"col\">Error rate</th>
+        <th scope=\"col\">Bytes down</th>
+        <th scope=\"col\">Bytes up</th>
+        <th scope=\"col\">p95 duration</th>
+      </tr>
+    </thead>
+    <tbody>
+{rows}    </tbody>
+  </table>
+</section>
+"
+    )
+}
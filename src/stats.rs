@@ -0,0 +1,13 @@
+//! Statistical helpers.
+
+/// Get the `percentile`-th percentile of some sorted values, with the
+/// nearest-rank method.
+pub fn percentile<T: Copy>(sorted_values: &[T], percentile: f64) -> Option<T> {
+    if sorted_values.is_empty() {
+        return None;
+    }
+
+    let rank = (percentile / 100. * sorted_values.len() as f64).ceil() as usize;
+
+    Some(sorted_values[rank.clamp(1, sorted_values.len()) - 1])
+}
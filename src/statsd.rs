@@ -0,0 +1,89 @@
+//! Emit StatsD metrics for the spans: one timing per completed span, e.g.
+//! `matrix.http.sync.duration:318|ms`, and one counter per status family,
+//! e.g. `matrix.http.sync.status.2xx:1|c`.
+//!
+//! StatsD carries no timestamp, so replaying a whole log makes every metric
+//! land at the time it is sent. Sending is best effort: a failing UDP send is
+//! reported but never stops the analysis.
+
+use std::{
+    io,
+    net::{ToSocketAddrs, UdpSocket},
+};
+
+use crate::Span;
+
+/// Prefix of all the metric paths.
+const PREFIX: &str = "matrix.http";
+
+pub struct Client {
+    socket: UdpSocket,
+    number_of_failures: usize,
+}
+
+impl Client {
+    /// Create a client sending the metrics to `address`, e.g. `localhost:8125`.
+    pub fn connect(address: impl ToSocketAddrs) -> io::Result<Self> {
+        let socket = UdpSocket::bind(("0.0.0.0", 0))?;
+        socket.connect(address)?;
+
+        Ok(Self {
+            socket,
+            number_of_failures: 0,
+        })
+    }
+
+    /// Send the metrics of a span. Spans without a response are ignored.
+    pub fn send(&mut self, span: &Span) {
+        let Some(packet) = packet(span) else {
+            return;
+        };
+
+        if let Err(error) = self.socket.send(packet.as_bytes()) {
+            if self.number_of_failures == 0 {
+                eprintln!("Failed to send StatsD metrics, carrying on: {error}");
+            }
+
+            self.number_of_failures += 1;
+        }
+    }
+
+    /// Number of packets that have failed to be sent.
+    pub fn number_of_failures(&self) -> usize {
+        self.number_of_failures
+    }
+}
+
+/// Build the packet holding all the metrics of a span, one per line.
+fn packet(span: &Span) -> Option<String> {
+    span.response_log_line?;
+
+    let endpoint = sanitize(span.kind().as_str());
+    let mut packet = format!(
+        "{PREFIX}.{endpoint}.duration:{}|ms",
+        span.duration.num_milliseconds()
+    );
+
+    if let Some(status) = span.status {
+        packet.push_str(&format!(
+            "\n{PREFIX}.{endpoint}.status.{}xx:1|c",
+            status / 100
+        ));
+    }
+
+    Some(packet)
+}
+
+/// Turn a name into a single metric path segment: `.`, `:`, `|`, `@` and
+/// whitespaces all have a meaning in the protocol.
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|character| {
+            if character.is_ascii_alphanumeric() || matches!(character, '-' | '_') {
+                character
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
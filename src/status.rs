@@ -0,0 +1,148 @@
+//! Describe the HTTP status codes of the responses: their reason phrase, and
+//! what they mean for the Matrix SDK.
+
+use crate::{Span, Spans, endpoint, human};
+
+/// Get the reason phrase of a HTTP status code, if it is a known one.
+pub fn reason(status: u16) -> Option<&'static str> {
+    Some(match status {
+        100 => "Continue",
+        101 => "Switching Protocols",
+        200 => "OK",
+        201 => "Created",
+        202 => "Accepted",
+        204 => "No Content",
+        206 => "Partial Content",
+        301 => "Moved Permanently",
+        302 => "Found",
+        304 => "Not Modified",
+        307 => "Temporary Redirect",
+        308 => "Permanent Redirect",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        409 => "Conflict",
+        410 => "Gone",
+        411 => "Length Required",
+        413 => "Content Too Large",
+        414 => "URI Too Long",
+        415 => "Unsupported Media Type",
+        416 => "Range Not Satisfiable",
+        429 => "Too Many Requests",
+        499 => "Client Closed Request",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        504 => "Gateway Timeout",
+        _ => return None,
+    })
+}
+
+/// Get a one-line hint about what a status code means for the SDK, for the
+/// handful of codes where it isn't obvious.
+pub fn hint(span: &Span) -> Option<&'static str> {
+    match span.status? {
+        401 => Some(
+            "The access token was rejected; with `soft_logout`, the SDK keeps the session and only asks to log in again.",
+        ),
+        403 if span.kind() == endpoint::Kind::E2ee => Some(
+            "On key endpoints, a 403 usually means an outdated key backup version or a missing cross-signing authorisation, not a permission error.",
+        ),
+        429 => Some("The SDK retries the request once the `retry_after_ms` delay has elapsed."),
+        _ => None,
+    }
+}
+
+/// Get the label of the status of a span, e.g. `429 Too Many Requests`, or
+/// `429 M_LIMIT_EXCEEDED` if the Matrix error code is known, `error` if the
+/// request has failed without a response, or `None` if the span has no
+/// response.
+pub fn label(span: &Span) -> Option<String> {
+    let Some(status) = span.status else {
+        return span.has_failed().then(|| "error".to_owned());
+    };
+
+    Some(match (&span.errcode, reason(status)) {
+        (Some(errcode), _) => format!("{status} {errcode}"),
+        (None, Some(reason)) => format!("{status} {reason}"),
+        (None, None) => status.to_string(),
+    })
+}
+
+/// Get the tooltip of the status of a span: its reason phrase, followed by the
+/// error message, the hint and the retries, if any. The tooltip of a request
+/// failed without a response is its transport error.
+pub fn tooltip(span: &Span) -> Option<String> {
+    let Some(status) = span.status else {
+        return span
+            .error
+            .as_ref()
+            .filter(|_| span.has_failed())
+            .map(|error| format!("Failed without a response: {error}{}", retries(span)));
+    };
+    let mut tooltip = match reason(status) {
+        Some(reason) => format!("{status} {reason}"),
+        None => status.to_string(),
+    };
+
+    if let Some(errcode) = &span.errcode {
+        tooltip.push_str(&format!(" ({errcode})"));
+    }
+
+    if let Some(error_message) = &span.error_message {
+        tooltip.push_str(&format!(": {}", error_message.trim_end_matches('.')));
+    }
+
+    if let Some(hint) = hint(span) {
+        tooltip.push_str(&format!(". {hint}"));
+    }
+
+    tooltip.push_str(&retries(span));
+
+    if let Some(error) = span.error.as_ref().filter(|_| span.retries > 0) {
+        tooltip.push_str(&format!(", the latest failure being: {error}"));
+    }
+
+    Some(tooltip)
+}
+
+/// Describe the retries of a span, e.g. `. Retried 2 times`, or nothing if it
+/// hasn't been retried.
+fn retries(span: &Span) -> String {
+    match span.retries {
+        0 => String::new(),
+        1 => ". Retried once".to_owned(),
+        retries => format!(". Retried {retries} times"),
+    }
+}
+
+/// Summarize the requests which have never received a response per
+/// connection, for the end of a run, or nothing if all have.
+pub fn pending_per_connection_to_text(spans: &Spans) -> String {
+    let connections = spans
+        .iter()
+        .map(|(connection_id, spans)| {
+            (
+                connection_id,
+                spans.values().filter(|span| span.is_pending()).count(),
+            )
+        })
+        .filter(|(_, number_of_pending_spans)| *number_of_pending_spans > 0)
+        .map(|(connection_id, number_of_pending_spans)| {
+            format!(
+                "  {connection_id}: {}\n",
+                human::count(number_of_pending_spans)
+            )
+        })
+        .collect::<String>();
+
+    if connections.is_empty() {
+        return String::new();
+    }
+
+    format!("Unanswered requests per connection:\n{connections}")
+}
@@ -0,0 +1,172 @@
+//! Count the spans per endpoint and per status family, as a health
+//! fingerprint of the log, from which the rows of the report can be filtered.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::Serialize;
+
+use crate::{ConnectionId, RequestId, Span, html, human};
+
+/// Counts of the spans per endpoint and per status family.
+#[derive(Serialize)]
+pub struct StatusMatrix {
+    /// The status families, e.g. `2`, `cancelled` or `pending`, in display order.
+    pub families: Vec<String>,
+
+    /// The endpoints, the most problematic first.
+    pub endpoints: Vec<Row>,
+}
+
+/// Counts of the spans of an endpoint.
+#[derive(Serialize)]
+pub struct Row {
+    pub endpoint: String,
+
+    /// Number of spans per status family, in the order of
+    /// [`StatusMatrix::families`].
+    pub counts: Vec<usize>,
+
+    /// Number of spans without a successful or redirected response.
+    pub problems: usize,
+
+    pub total: usize,
+}
+
+/// Count the spans per endpoint and per status family.
+///
+/// Endpoints are ordered by their number of problems, then by their number of
+/// spans.
+pub fn compute(spans: &[(&ConnectionId, RequestId, &Span)]) -> StatusMatrix {
+    let mut counts = BTreeMap::<String, BTreeMap<String, usize>>::new();
+
+    for (_, _, span) in spans {
+        *counts
+            .entry(span.endpoint())
+            .or_default()
+            .entry(span.status_family())
+            .or_default() += 1;
+    }
+
+    let mut families = counts
+        .values()
+        .flat_map(BTreeMap::keys)
+        .cloned()
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect::<Vec<_>>();
+    // Digits sort before `cancelled`, `error` and `pending`, which are kept
+    // last.
+    families.sort_by_key(|family| {
+        (
+            matches!(family.as_str(), "cancelled" | "error" | "pending"),
+            family.clone(),
+        )
+    });
+
+    let mut endpoints = counts
+        .into_iter()
+        .map(|(endpoint, counts_per_family)| {
+            let counts = families
+                .iter()
+                .map(|family| counts_per_family.get(family).copied().unwrap_or_default())
+                .collect::<Vec<_>>();
+            let problems = counts_per_family
+                .iter()
+                .filter(|(family, _)| !matches!(family.as_str(), "2" | "3"))
+                .map(|(_, count)| count)
+                .sum();
+
+            Row {
+                endpoint,
+                total: counts.iter().sum(),
+                counts,
+                problems,
+            }
+        })
+        .collect::<Vec<_>>();
+    endpoints.sort_by(|left, right| {
+        right
+            .problems
+            .cmp(&left.problems)
+            .then(right.total.cmp(&left.total))
+            .then_with(|| left.endpoint.cmp(&right.endpoint))
+    });
+
+    StatusMatrix {
+        families,
+        endpoints,
+    }
+}
+
+impl StatusMatrix {
+    /// Render the matrix as a grid, whose cells filter the rows of the
+    /// report.
+    pub fn to_html(&self) -> String {
+        if self.endpoints.is_empty() {
+            return String::new();
+        }
+
+        let headers = self
+            .families
+            .iter()
+            .map(|family| {
+                format!(
+                    "        <th scope=\"col\">{}</th>\n",
+                    match family.as_str() {
+                        "cancelled" => "Cancelled".to_owned(),
+                        "error" => "Error".to_owned(),
+                        "pending" => "Pending".to_owned(),
+                        family => format!("{family}xx"),
+                    }
+                )
+            })
+            .collect::<String>();
+        let rows = self
+            .endpoints
+            .iter()
+            .map(|row| {
+                let endpoint = html::escape(&row.endpoint);
+                let cells = self
+                    .families
+                    .iter()
+                    .zip(&row.counts)
+                    .map(|(family, count)| match count {
+                        0 => "        <td></td>\n".to_owned(),
+                        count => format!(
+                            "        <td><button type=\"button\" data-endpoint=\"{endpoint}\" data-status-family=\"{family}\" aria-pressed=\"false\">{count}</button></td>\n",
+                            count = human::count(*count),
+                        ),
+                    })
+                    .collect::<String>();
+
+                format!(
+                    "      <tr>
+        <th scope=\"row\"><code>{endpoint}</code></th>
+{cells}      </tr>
+"
+                )
+            })
+            .collect::<String>();
+
+        format!(
+            "  <h3>Endpoints per status</h3>
+  <table class=\"status-matrix\">
+    <thead>
+      <tr>
+        <th scope=\"col\">Endpoint</th>
+{headers}      </tr>
+    </thead>
+    <tbody>
+{rows}    </tbody>
+  </table>
+"
+        )
+    }
+
+    /// Serialize the matrix, safe to embed in a `<script>` element.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self)
+            .expect("Failed to serialize the status matrix")
+            .replace("</", "<\\/")
+    }
+}
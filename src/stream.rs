@@ -0,0 +1,136 @@
+//! Stream the spans of a log as CSV rows while it is read, with `--stream`,
+//! so that a multi-gigabyte log doesn't have to fit in memory.
+//!
+//! The completed spans are taken out of the parser every
+//! [`LINES_PER_BATCH`] lines, selected, redacted, and appended to the
+//! output. The offsets of the spans are relative to the first request of the
+//! first batch, completed or not. The rows are in the order of the responses,
+//! and sorted by start within a batch.
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+};
+
+use crate::{
+    Spans,
+    cli::{Options, STDIO},
+    csv,
+    error::Error,
+    filters,
+    parser::{Batch, Parser},
+    source::Source,
+};
+
+/// Number of lines read between two batches.
+const LINES_PER_BATCH: usize = 10_000;
+
+/// Write the rows of a batch of spans.
+struct Writer<'a> {
+    options: &'a Options,
+    output: Box<dyn Write>,
+    /// Start of the first span, in milliseconds, once known.
+    origin: Option<i64>,
+    is_header_written: bool,
+    number_of_spans: usize,
+    number_of_unselected_spans: usize,
+}
+
+impl Writer<'_> {
+    fn write(&mut self, mut spans: Spans) -> io::Result<()> {
+        if !self.is_header_written {
+            self.output
+                .write_all(csv::header(&self.options.columns).as_bytes())?;
+            self.output.write_all(b"\n")?;
+            self.is_header_written = true;
+        }
+
+        if spans.is_empty() {
+            return Ok(());
+        }
+
+        let origin = *self.origin.get_or_insert_with(|| {
+            filters::time_range(&spans)
+                .map(|(start_at, _)| start_at.timestamp_millis())
+                .unwrap_or_default()
+        });
+        self.number_of_unselected_spans += self.options.selection.retain(&mut spans);
+
+        if let Some(redaction) = &self.options.redaction {
+            redaction.spans(&mut spans);
+        }
+
+        let mut rows = spans
+            .iter()
+            .flat_map(|(connection_id, spans)| {
+                spans
+                    .iter()
+                    .map(move |(request_id, span)| (connection_id, *request_id, span))
+            })
+            .collect::<Vec<_>>();
+        rows.sort_by_key(|(_, _, span)| span.start_at);
+
+        self.number_of_spans += rows.len();
+        self.output.write_all(
+            csv::to_rows(&rows, origin, self.options.timezone, &self.options.columns).as_bytes(),
+        )
+    }
+}
+
+/// Read `source` with `parser`, and stream the rows of its spans to
+/// `output_path`. Returns the number of written spans, and of spans removed
+/// by the filters.
+pub fn run(
+    options: &Options,
+    parser: &mut Parser,
+    source: &Source,
+    output_path: &str,
+) -> Result<(usize, usize), Error> {
+    let output: Box<dyn Write> = if output_path == STDIO {
+        Box::new(BufWriter::new(io::stdout().lock()))
+    } else {
+        Box::new(BufWriter::new(
+            File::create(output_path).map_err(Error::io(output_path))?,
+        ))
+    };
+    let mut writer = Writer {
+        options,
+        output,
+        origin: None,
+        is_header_written: false,
+        number_of_spans: 0,
+        number_of_unselected_spans: 0,
+    };
+    let mut result = Ok(());
+
+    let mut lines = Batch::default();
+    let mut number_of_read_lines = 0_usize;
+
+    source.read_lines(|line, location| {
+        number_of_read_lines += 1;
+
+        let is_due = number_of_read_lines.is_multiple_of(LINES_PER_BATCH);
+
+        if lines.push(line, location) || is_due {
+            parser.parse_batch(&mut lines);
+        }
+
+        if result.is_ok() && is_due {
+            // The first request may still be pending.
+            if writer.origin.is_none() {
+                writer.origin = filters::time_range(&parser.spans)
+                    .map(|(start_at, _)| start_at.timestamp_millis());
+            }
+
+            result = writer.write(parser.take_completed(false));
+        }
+    })?;
+    parser.parse_batch(&mut lines);
+
+    result
+        .and_then(|()| writer.write(parser.take_completed(true)))
+        .and_then(|()| writer.output.flush())
+        .map_err(Error::io(output_path))?;
+
+    Ok((writer.number_of_spans, writer.number_of_unselected_spans))
+}
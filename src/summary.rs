@@ -0,0 +1,93 @@
+//! Summarize a log as JSON, for `--stats-out` and for the `/api/summary` of
+//! `serve`, so that a CI job can assert on the numbers of a run instead of
+//! parsing the summary printed to the terminal.
+
+use std::collections::BTreeMap;
+
+use chrono::{FixedOffset, SecondsFormat};
+
+use crate::{Spans, filters};
+
+/// Render the summary of the log named `log_name`. The bounds of the time
+/// range are in `timezone`.
+pub fn to_json(
+    log_name: &str,
+    number_of_analysed_lines: usize,
+    number_of_matched_lines: usize,
+    spans: &Spans,
+    timezone: FixedOffset,
+) -> Vec<u8> {
+    let spans_of = || spans.values().flat_map(|spans| spans.values());
+    let mut statuses = BTreeMap::<String, usize>::new();
+
+    // The status code, or `pending`, `error` or `cancelled` without one.
+    for span in spans_of() {
+        let status = match span.status {
+            Some(status) if !span.is_pending() => status.to_string(),
+            _ => span.status_family(),
+        };
+
+        *statuses.entry(status).or_default() += 1;
+    }
+
+    let time_range = filters::time_range(spans).map(|(start_at, end_at)| {
+        [start_at, end_at].map(|datetime| {
+            datetime
+                .with_timezone(&timezone)
+                .to_rfc3339_opts(SecondsFormat::Millis, true)
+        })
+    });
+
+    serde_json::json!({
+        "source": log_name,
+        "number_of_analysed_lines": number_of_analysed_lines,
+        "number_of_matched_lines": number_of_matched_lines,
+        "number_of_connections": spans.len(),
+        "number_of_spans": spans_of().count(),
+        "number_of_complete_spans": spans_of().filter(|span| !span.is_pending()).count(),
+        "number_of_pending_spans": spans_of().filter(|span| span.is_pending()).count(),
+        "number_of_failed_spans": spans_of()
+            .filter(|span| !span.is_pending() && !span.is_successful())
+            .count(),
+        "statuses": statuses,
+        "start_at": time_range.as_ref().map(|[start_at, _]| start_at),
+        "end_at": time_range.as_ref().map(|[_, end_at]| end_at),
+    })
+    .to_string()
+    .into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SpansBuilder;
+
+    #[test]
+    fn test_to_json() {
+        let uri = "https://example.org/_matrix/client/v3/sync";
+        let mut spans = SpansBuilder::default();
+        spans.span("room-list", 1, uri, Some(200), 0, 100);
+        spans.span("room-list", 2, uri, Some(502), 0, 100);
+        spans.span("room-list", 3, uri, None, 0, 100);
+        let spans = spans.build();
+
+        let summary = serde_json::from_slice::<serde_json::Value>(&to_json(
+            "app.log",
+            10,
+            6,
+            &spans,
+            FixedOffset::east_opt(0).unwrap(),
+        ))
+        .unwrap();
+
+        assert_eq!(summary["number_of_spans"], 3);
+        assert_eq!(summary["number_of_complete_spans"], 2);
+        assert_eq!(summary["number_of_pending_spans"], 1);
+        assert_eq!(summary["number_of_failed_spans"], 1);
+        assert_eq!(
+            summary["statuses"],
+            serde_json::json!({ "200": 1, "502": 1, "pending": 1 })
+        );
+        assert!(summary["start_at"].as_str().unwrap().ends_with('Z'));
+    }
+}
@@ -0,0 +1,217 @@
+//! Estimate the time spent by the server and the network on the syncs, apart
+//! from the time spent waiting for new data.
+//!
+//! A long-poll sync lasts about `timeout` + server processing + network when
+//! it expires without new data, and about server processing + network when
+//! data arrives early. An immediate sync, i.e. with a zero or no `timeout`,
+//! doesn't wait at all.
+
+use std::collections::BTreeMap;
+
+use chrono::TimeDelta;
+use serde::Serialize;
+
+use crate::{ConnectionId, Span, Spans, html, human, stats};
+
+/// How a sync has returned.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Poll {
+    /// The sync has no timeout: it returns as soon as possible.
+    Immediate,
+
+    /// The sync has returned before its timeout, with new data.
+    Early,
+
+    /// The sync has expired, without new data.
+    Expired,
+}
+
+impl Poll {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Immediate => "immediate sync",
+            Self::Early => "long-poll returned early",
+            Self::Expired => "long-poll expired",
+        }
+    }
+}
+
+/// Classify a successful sync, and estimate its server+network overhead, i.e.
+/// its duration minus its timeout if it has expired, or its whole duration
+/// otherwise.
+pub fn estimate(span: &Span) -> Option<(Poll, TimeDelta)> {
+    if !span.is_sync() || !span.is_successful() {
+        return None;
+    }
+
+    Some(match span.timeout().filter(|timeout| !timeout.is_zero()) {
+        None => (Poll::Immediate, span.duration),
+        Some(timeout) if span.duration >= timeout => (Poll::Expired, span.duration - timeout),
+        Some(_) => (Poll::Early, span.duration),
+    })
+}
+
+/// Describe the estimation of a span, for its details.
+pub fn describe(span: &Span) -> Option<String> {
+    let (poll, overhead) = estimate(span)?;
+
+    Some(format!(
+        "Estimated server+network overhead: {overhead} ({poll})",
+        overhead = human::duration(overhead),
+        poll = poll.as_str(),
+    ))
+}
+
+/// Distribution of the estimations of a connection.
+#[derive(Serialize)]
+pub struct Row {
+    pub connection_id: ConnectionId,
+    pub immediate: usize,
+    pub early: usize,
+    pub expired: usize,
+
+    /// In milliseconds.
+    pub p50_overhead: Option<i64>,
+    pub p95_overhead: Option<i64>,
+    pub max_overhead: Option<i64>,
+}
+
+/// Estimations per connection, the connections without syncs excluded.
+#[derive(Default, Serialize)]
+pub struct SyncOverhead {
+    pub connections: Vec<Row>,
+}
+
+pub fn compute(spans: &Spans) -> SyncOverhead {
+    let mut per_connection = BTreeMap::<&ConnectionId, Vec<(Poll, i64)>>::new();
+
+    for (connection_id, spans) in spans {
+        for span in spans.values() {
+            if let Some((poll, overhead)) = estimate(span) {
+                per_connection
+                    .entry(connection_id)
+                    .or_default()
+                    .push((poll, overhead.num_milliseconds()));
+            }
+        }
+    }
+
+    SyncOverhead {
+        connections: per_connection
+            .into_iter()
+            .map(|(connection_id, estimations)| {
+                let count = |poll| estimations.iter().filter(|(of, _)| *of == poll).count();
+                let mut overheads = estimations
+                    .iter()
+                    .map(|(_, overhead)| *overhead)
+                    .collect::<Vec<_>>();
+                overheads.sort_unstable();
+
+                Row {
+                    connection_id: connection_id.clone(),
+                    immediate: count(Poll::Immediate),
+                    early: count(Poll::Early),
+                    expired: count(Poll::Expired),
+                    p50_overhead: stats::percentile(&overheads, 50.),
+                    p95_overhead: stats::percentile(&overheads, 95.),
+                    max_overhead: overheads.last().copied(),
+                }
+            })
+            .collect(),
+    }
+}
+
+impl SyncOverhead {
+    /// Render the table and its footnote, or nothing if there is no sync.
+    pub fn to_html(&self) -> String {
+        if self.connections.is_empty() {
+            return String::new();
+        }
+
+        let milliseconds = |value: Option<i64>| value.map(human::milliseconds).unwrap_or_default();
+        let rows = self
+            .connections
+            .iter()
+            .map(|row| {
+                format!(
+                    "      <tr>
+        <th scope=\"row\"><code>{connection_id}</code></th>
+        <td>{immediate}</td>
+        <td>{early}</td>
+        <td>{expired}</td>
+        <td>{p50}</td>
+        <td>{p95}</td>
+        <td>{max}</td>
+      </tr>
+",
+                    connection_id = html::escape(&row.connection_id),
+                    immediate = human::count(row.immediate),
+                    early = human::count(row.early),
+                    expired = human::count(row.expired),
+                    p50 = milliseconds(row.p50_overhead),
+                    p95 = milliseconds(row.p95_overhead),
+                    max = milliseconds(row.max_overhead),
+                )
+            })
+            .collect::<String>();
+
+        format!(
+            "  <h3>Estimated server+network overhead of the syncs</h3>
+  <table class=\"sync-overhead\">
+    <thead>
+      <tr>
+        <th scope=\"col\">Connection</th>
+        <th scope=\"col\">Immediate</th>
+        <th scope=\"col\">Returned early</th>
+        <th scope=\"col\">Expired</th>
+        <th scope=\"col\">p50 overhead</th>
+        <th scope=\"col\">p95 overhead</th>
+        <th scope=\"col\">Max overhead</th>
+      </tr>
+    </thead>
+    <tbody>
+{rows}    </tbody>
+  </table>
+  <p class=\"footnote\">Estimated over the successful syncs. A long-poll lasting at least its <code>timeout</code> is assumed to have expired without new data: its overhead is its duration minus its <code>timeout</code>. A long-poll returning earlier is assumed to have returned as soon as the data was available, and an immediate sync not to wait at all: their overhead is their whole duration, which overestimates it if the server has waited for the data.</p>
+"
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate() {
+        let span = |uri: &str, status, duration| {
+            Span::for_tests(uri, status, TimeDelta::milliseconds(duration))
+        };
+        let sync = "https://example.org/_matrix/client/v3/sync";
+
+        assert_eq!(
+            estimate(&span(&format!("{sync}?timeout=30000"), Some(200), 30_450)),
+            Some((Poll::Expired, TimeDelta::milliseconds(450)))
+        );
+        assert_eq!(
+            estimate(&span(&format!("{sync}?timeout=30000"), Some(200), 1_200)),
+            Some((Poll::Early, TimeDelta::milliseconds(1_200)))
+        );
+        assert_eq!(
+            estimate(&span(&format!("{sync}?timeout=0"), Some(200), 300)),
+            Some((Poll::Immediate, TimeDelta::milliseconds(300)))
+        );
+        assert_eq!(
+            estimate(&span(&format!("{sync}?timeout=30000"), Some(502), 30_450)),
+            None
+        );
+        assert_eq!(
+            estimate(&span(
+                "https://example.org/_matrix/client/v3/keys/query",
+                Some(200),
+                300
+            )),
+            None
+        );
+    }
+}
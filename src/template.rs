@@ -0,0 +1,366 @@
+//! Load the template of the HTML report: a single HTML file, or a directory
+//! holding `index.html`, `style.css` and `script.js`.
+//!
+//! The style and the script of a directory are inlined in place of the
+//! `{style}` and `{script}` placeholders of `index.html`, so that the report
+//! remains a single file, trivial to share. The embedded default template is
+//! such a directory, and is inlined the same way.
+//!
+//! With `--assets external`, the report is split instead: `index.html` loads
+//! `style.css`, `app.js` and the JSON data of the report from `data.json`,
+//! so that the report can be re-styled without parsing the logs again. The
+//! data is fetched, so the report has to be served over HTTP. Each JSON
+//! placeholder is loaded in the element whose ID is its name, with dashes,
+//! e.g. `{status_matrix}` in `#status-matrix`.
+//!
+//! The placeholders are substituted in a single pass: a value is never
+//! scanned for placeholders itself, so that a log line can't inject a
+//! placeholder, nor markup, in the report. The values coming from the logs
+//! are escaped by the renderers, see [`crate::html::escape`].
+
+use std::{fs, io, path::Path};
+
+use regex::{Captures, Regex};
+
+const INDEX: &str = include_str!("../template/index.html");
+const STYLE: &str = include_str!("../template/style.css");
+const SCRIPT: &str = include_str!("../template/script.js");
+
+/// Files of a template directory.
+const FILES: [&str; 3] = ["index.html", "style.css", "script.js"];
+
+/// The placeholders a template can use, besides `{style}` and `{script}`.
+pub const PLACEHOLDERS: [&str; 20] = [
+    "theme",
+    "refresh",
+    "title",
+    "rollup",
+    "header",
+    "meta",
+    "summary",
+    "anomalies",
+    "duration_bands",
+    "columns",
+    "start_at_iso",
+    "headers",
+    "tbody",
+    "dataset",
+    "status_matrix",
+    "concurrency",
+    "bandwidth",
+    "zoom",
+    "ticks",
+    "lifecycle",
+];
+
+/// The placeholders holding the JSON data of the report.
+const DATA_PLACEHOLDERS: [&str; 7] = [
+    "dataset",
+    "status_matrix",
+    "concurrency",
+    "bandwidth",
+    "zoom",
+    "ticks",
+    "lifecycle",
+];
+
+/// Names of the files next to `index.html` with external assets.
+pub const STYLE_FILE: &str = "style.css";
+pub const SCRIPT_FILE: &str = "app.js";
+pub const DATA_FILE: &str = "data.json";
+
+/// Script of `index.html` with external assets, loading the data before the
+/// script of the report.
+const LOADER: &str = "fetch('data.json')
+  .then((response) => response.json())
+  .then((data) => {
+    for (const [name, value] of Object.entries(data)) {
+      document.getElementById(name.replaceAll('_', '-')).textContent = JSON.stringify(value);
+    }
+
+    document.body.append(Object.assign(document.createElement('script'), { src: 'app.js' }));
+  });
+";
+
+/// Whether the style, the script and the data are inlined in the report.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Assets {
+    #[default]
+    Inline,
+    External,
+}
+
+impl Assets {
+    /// Parse the value of `--assets`.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "inline" => Some(Self::Inline),
+            "external" => Some(Self::External),
+            _ => None,
+        }
+    }
+}
+
+/// The color theme of the HTML report, in the `data-theme` attribute of the
+/// `<html>` element.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Theme {
+    #[default]
+    Dark,
+    Light,
+    /// The color scheme preferred by the browser.
+    Auto,
+}
+
+impl Theme {
+    /// Parse the value of `--theme`.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "dark" => Some(Self::Dark),
+            "light" => Some(Self::Light),
+            "auto" => Some(Self::Auto),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Dark => "dark",
+            Self::Light => "light",
+            Self::Auto => "auto",
+        }
+    }
+}
+
+/// A report split into `index.html` and its external assets.
+pub struct External {
+    pub index: String,
+    pub style: String,
+    pub script: String,
+    /// The JSON data, an object of the values of [`DATA_PLACEHOLDERS`].
+    pub data: String,
+}
+
+/// A template, checked to only use known placeholders.
+#[derive(Clone)]
+pub struct Template {
+    index: String,
+    style: String,
+    script: String,
+    find_placeholder: Regex,
+}
+
+impl Default for Template {
+    /// The embedded default template.
+    fn default() -> Self {
+        Self::new(INDEX, STYLE, SCRIPT).expect("The default template is valid")
+    }
+}
+
+impl Template {
+    /// Load the template at `path`, a file or a directory.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let path = Path::new(path);
+        let read = |path: &Path| {
+            fs::read_to_string(path).map_err(|error| match error.kind() {
+                io::ErrorKind::NotFound => format!(
+                    "`{path}` is missing; a template is an HTML file, or a directory holding {files}",
+                    path = path.display(),
+                    files = FILES.map(|file| format!("`{file}`")).join(", "),
+                ),
+                _ => format!("Failed to read `{}`: {error}", path.display()),
+            })
+        };
+
+        if !path.is_dir() {
+            return Self::new(&read(path)?, "", "");
+        }
+
+        let [index, style, script] = FILES.map(|file| read(&path.join(file)));
+        let (index, style, script) = (index?, style?, script?);
+
+        for placeholder in ["{style}", "{script}"] {
+            if !index.contains(placeholder) {
+                return Err(format!("`index.html` has no `{placeholder}` placeholder"));
+            }
+        }
+
+        Self::new(&index, &style, &script)
+    }
+
+    /// Check the placeholders of `index`, and the content of `style` and
+    /// `script`.
+    fn new(index: &str, style: &str, script: &str) -> Result<Self, String> {
+        // The content of the elements ends at their first closing tag.
+        if style.contains("</style") {
+            return Err("`style.css` cannot contain `</style`".to_owned());
+        }
+
+        if script.contains("</script") {
+            return Err("`script.js` cannot contain `</script`".to_owned());
+        }
+
+        // A `${…}` is an interpolation of a JavaScript template literal.
+        let find_placeholder = Regex::new(r"(?<interpolation>\$)?\{(?<name>[a-z_]+)\}")
+            .expect("Failed to build the `find_placeholder` regex");
+        let mut unknown = Vec::new();
+
+        for captures in find_placeholder.captures_iter(index) {
+            let placeholder = format!("`{}`", &captures[0]);
+
+            if captures.name("interpolation").is_none()
+                && !is_known(&captures["name"])
+                && !unknown.contains(&placeholder)
+            {
+                unknown.push(placeholder);
+            }
+        }
+
+        if !unknown.is_empty() {
+            return Err(format!(
+                "Unknown placeholders {unknown}; the known ones are {known}",
+                unknown = unknown.join(", "),
+                known = ["style", "script"]
+                    .iter()
+                    .chain(&PLACEHOLDERS)
+                    .map(|name| format!("`{{{name}}}`"))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            ));
+        }
+
+        Ok(Self {
+            index: index.to_owned(),
+            style: style.to_owned(),
+            script: script.to_owned(),
+            find_placeholder,
+        })
+    }
+
+    /// Substitute the placeholders with `values`, in a single pass. The
+    /// placeholders without a value are rendered empty.
+    pub fn render(&self, values: &[(&str, &str)]) -> String {
+        self.substitute(values, &self.style, &self.script, false)
+    }
+
+    /// Render the report with external assets: the data is moved to its own
+    /// file, and `index.html` imports the style and loads the script.
+    pub fn render_external(&self, values: &[(&str, &str)]) -> External {
+        let data = DATA_PLACEHOLDERS
+            .iter()
+            .filter_map(|name| {
+                values
+                    .iter()
+                    .find(|(placeholder, _)| placeholder == name)
+                    .map(|(_, value)| format!("\"{name}\":{value}"))
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+
+        External {
+            index: self.substitute(
+                values,
+                &format!("@import url(\"{STYLE_FILE}\");\n"),
+                LOADER,
+                true,
+            ),
+            style: self.style.clone(),
+            script: self.script.clone(),
+            data: format!("{{{data}}}"),
+        }
+    }
+
+    /// Substitute the placeholders with `values`, `style` and `script`. The
+    /// data is left `null` if `is_data_external`.
+    fn substitute(
+        &self,
+        values: &[(&str, &str)],
+        style: &str,
+        script: &str,
+        is_data_external: bool,
+    ) -> String {
+        self.find_placeholder
+            .replace_all(&self.index, |captures: &Captures<'_>| {
+                let name = &captures["name"];
+
+                if captures.name("interpolation").is_some() || !is_known(name) {
+                    return captures[0].to_owned();
+                }
+
+                match name {
+                    "style" => style.to_owned(),
+                    "script" => script.to_owned(),
+                    _ if is_data_external && DATA_PLACEHOLDERS.contains(&name) => "null".to_owned(),
+                    _ => values
+                        .iter()
+                        .find(|(placeholder, _)| *placeholder == name)
+                        .map(|(_, value)| (*value).to_owned())
+                        .unwrap_or_default(),
+                }
+            })
+            .into_owned()
+    }
+}
+
+/// Whether `name` is a placeholder of the templates.
+fn is_known(name: &str) -> bool {
+    matches!(name, "style" | "script") || PLACEHOLDERS.contains(&name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let template = Template::new(
+            "<style>{style}</style><h1>{title}</h1>{summary}<script>{script}</script>",
+            "a {}",
+            "b(`${title}`);",
+        )
+        .unwrap();
+
+        // The values aren't scanned for placeholders.
+        assert_eq!(
+            template.render(&[("title", "{summary}"), ("summary", "<p>2</p>")]),
+            "<style>a {}</style><h1>{summary}</h1><p>2</p><script>b(`${title}`);</script>"
+        );
+        assert!(
+            Template::default()
+                .render(&[])
+                .contains("function formatCount")
+        );
+        assert!(
+            Template::default()
+                .render(&[("theme", Theme::Light.as_str())])
+                .contains("<html lang=\"en\" data-theme=\"light\">")
+        );
+
+        let external = template.render_external(&[("title", "A"), ("dataset", "[1]")]);
+
+        assert_eq!(
+            external.index,
+            format!(
+                "<style>@import url(\"style.css\");\n</style><h1>A</h1><script>{LOADER}</script>"
+            )
+        );
+        assert_eq!(external.script, "b(`${title}`);");
+        assert_eq!(external.data, r#"{"dataset":[1]}"#);
+    }
+
+    #[test]
+    fn test_invalid_templates() {
+        assert_eq!(
+            Template::new("{title}{stats}{rows}{stats}", "", "").err(),
+            Some(format!(
+                "Unknown placeholders `{{stats}}`, `{{rows}}`; the known ones are `{{style}}`, `{{script}}`, {}",
+                PLACEHOLDERS.map(|name| format!("`{{{name}}}`")).join(", ")
+            ))
+        );
+        assert!(Template::new("const f = (x) => `${x}`;", "", "").is_ok());
+        assert_eq!(
+            Template::new("{style}{script}", "", "'</script>'").err(),
+            Some("`script.js` cannot contain `</script`".to_owned())
+        );
+    }
+}
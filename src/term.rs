@@ -0,0 +1,297 @@
+//! Render the spans as a waterfall of text, with `--format term`, to glance
+//! at the timeline from a terminal without a browser, e.g. over SSH:
+//!
+//! ```text
+//! room-list          200 POST   ….matrix.simplified_msc3575/sync    30100ms |##                |
+//! encryption     pending POST   ….matrix.simplified_msc3575/sync        0ms | !                |
+//! ```
+//!
+//! The failed and the unanswered requests are drawn with `!`, and in red if
+//! the standard output is a terminal.
+
+use std::{
+    env,
+    io::{self, IsTerminal},
+};
+
+use crate::{ConnectionId, RequestId, Span};
+
+/// Width of the waterfall if the width of the terminal can't be detected.
+pub const DEFAULT_WIDTH: usize = 120;
+
+/// Widths of the columns before the bar.
+const CONNECTION_WIDTH: usize = 14;
+const STATUS_WIDTH: usize = 7;
+const METHOD_WIDTH: usize = 6;
+const PATH_WIDTH: usize = 32;
+const DURATION_WIDTH: usize = 8;
+
+/// Width of the spaces, of the `ms` unit and of the borders of the bar.
+const SEPARATORS_WIDTH: usize = 9;
+
+/// Minimum width of the bar, however narrow the terminal is.
+const MINIMUM_BAR_WIDTH: usize = 10;
+
+/// Where the waterfall is printed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Terminal {
+    /// Number of columns.
+    pub width: usize,
+    /// Whether colors can be used.
+    pub color: bool,
+}
+
+impl Default for Terminal {
+    /// A file, or a pipe.
+    fn default() -> Self {
+        Self {
+            width: DEFAULT_WIDTH,
+            color: false,
+        }
+    }
+}
+
+impl Terminal {
+    /// Detect the standard output: the width of the terminal, or `COLUMNS`,
+    /// and colors only if it's a terminal.
+    pub fn stdout() -> Self {
+        let color = io::stdout().is_terminal();
+
+        Self {
+            width: color
+                .then(window_width)
+                .flatten()
+                .or_else(|| env::var("COLUMNS").ok()?.parse().ok())
+                .filter(|width| *width > 0)
+                .unwrap_or(DEFAULT_WIDTH),
+            color,
+        }
+    }
+}
+
+/// Get the number of columns of the terminal of the standard output.
+fn window_width() -> Option<usize> {
+    window_size()
+        .map(|(_, columns)| columns)
+        .filter(|columns| *columns > 0)
+}
+
+/// Get the number of rows and of columns of the terminal of the standard
+/// output.
+#[cfg(unix)]
+pub fn window_size() -> Option<(usize, usize)> {
+    let mut size = libc::winsize {
+        ws_row: 0,
+        ws_col: 0,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+
+    // SAFETY: `TIOCGWINSZ` only writes a `winsize` to the given pointer.
+    let result = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) };
+
+    (result == 0).then_some((usize::from(size.ws_row), usize::from(size.ws_col)))
+}
+
+#[cfg(not(unix))]
+pub fn window_size() -> Option<(usize, usize)> {
+    None
+}
+
+/// Truncate `text` to `width` characters, ending with an ellipsis if it is
+/// too long.
+fn truncate(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        return text.to_owned();
+    }
+
+    text.chars()
+        .take(width.saturating_sub(1))
+        .chain(Some('…'))
+        .collect()
+}
+
+/// Truncate a path to `width` characters, starting with an ellipsis if it is
+/// too long: like in the HTML report, its end is the most telling part.
+fn truncate_path(path: &str, width: usize) -> String {
+    let length = path.chars().count();
+
+    if length <= width {
+        return path.to_owned();
+    }
+
+    Some('…')
+        .into_iter()
+        .chain(path.chars().skip(length + 1 - width.max(1)))
+        .collect()
+}
+
+/// Scale a span starting at `start_at` for `duration` milliseconds, on a
+/// timeline of `end_at` milliseconds, to the offset and the length of its
+/// bar, in characters. A very short span is one character long, and the bar
+/// never overflows `width`.
+fn scale(start_at: i64, duration: i64, end_at: i64, width: usize) -> (usize, usize) {
+    if width == 0 {
+        return (0, 0);
+    }
+
+    let to_characters = |milliseconds: i64| {
+        if end_at <= 0 {
+            return 0;
+        }
+
+        (milliseconds.clamp(0, end_at) as f64 * width as f64 / end_at as f64).round() as usize
+    };
+    let offset = to_characters(start_at).min(width - 1);
+    let length = to_characters(duration).clamp(1, width - offset);
+
+    (offset, length)
+}
+
+/// Render the waterfall of the spans. `smallest_start_at` and
+/// `largest_end_at` are the bounds of the timeline, in milliseconds.
+pub fn to_text(
+    spans: &[(&ConnectionId, RequestId, &Span)],
+    smallest_start_at: i64,
+    largest_end_at: i64,
+    terminal: Terminal,
+) -> String {
+    let bar_width = terminal
+        .width
+        .saturating_sub(
+            CONNECTION_WIDTH
+                + STATUS_WIDTH
+                + METHOD_WIDTH
+                + PATH_WIDTH
+                + DURATION_WIDTH
+                + SEPARATORS_WIDTH,
+        )
+        .max(MINIMUM_BAR_WIDTH);
+    let end_at = largest_end_at.saturating_sub(smallest_start_at);
+
+    spans
+        .iter()
+        .map(|(connection_id, _, span)| {
+            let status = match span.status {
+                Some(status) => status.to_string(),
+                None if span.is_pending() => "pending".to_owned(),
+                None if span.has_failed() => "error".to_owned(),
+                None => "×".to_owned(),
+            };
+            let is_failed = span.is_pending()
+                || span.has_failed()
+                || span.status.is_some_and(|status| status >= 400);
+            let duration = span.duration.num_milliseconds();
+            let (offset, length) = scale(
+                span.start_at
+                    .timestamp_millis()
+                    .saturating_sub(smallest_start_at),
+                duration,
+                end_at,
+                bar_width,
+            );
+            let bar = (if is_failed { "!" } else { "#" }).repeat(length);
+            let bar = if terminal.color && is_failed {
+                format!("\x1b[31m{bar}\x1b[0m")
+            } else {
+                bar
+            };
+
+            format!(
+                "{connection:<CONNECTION_WIDTH$} {status:>STATUS_WIDTH$} {method:<METHOD_WIDTH$} {path:<PATH_WIDTH$} {duration:>DURATION_WIDTH$}ms |{before}{bar}{after}|\n",
+                connection = truncate(connection_id, CONNECTION_WIDTH),
+                method = truncate(&span.method, METHOD_WIDTH),
+                path = truncate_path(
+                    span.path().split('?').next().unwrap_or_default(),
+                    PATH_WIDTH
+                ),
+                before = " ".repeat(offset),
+                after = " ".repeat(bar_width - offset - length),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+
+    use super::*;
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("/sync", 10), "/sync");
+        assert_eq!(truncate("/sync", 5), "/sync");
+        assert_eq!(truncate("/_matrix/client", 6), "/_mat…");
+        assert_eq!(truncate("/sync", 0), "…");
+
+        assert_eq!(truncate_path("/sync", 5), "/sync");
+        assert_eq!(truncate_path("/_matrix/client/v3/sync", 8), "…v3/sync");
+        assert_eq!(truncate_path("/sync", 0), "…");
+    }
+
+    #[test]
+    fn test_scale() {
+        // The whole timeline.
+        assert_eq!(scale(0, 1_000, 1_000, 50), (0, 50));
+        // The second half.
+        assert_eq!(scale(500, 500, 1_000, 50), (25, 25));
+        // A sub-millisecond span is at least one character.
+        assert_eq!(scale(500, 0, 1_000, 50), (25, 1));
+        // A span ending at the end of the timeline doesn't overflow it.
+        assert_eq!(scale(1_000, 0, 1_000, 50), (49, 1));
+        assert_eq!(scale(990, 100, 1_000, 50), (49, 1));
+        // A timeline of a single instant.
+        assert_eq!(scale(0, 0, 0, 50), (0, 1));
+    }
+
+    #[test]
+    fn test_to_text() {
+        let connection_id = "room-list".to_owned();
+        let ok = Span::for_tests(
+            "https://example.org/_matrix/client/v3/sync",
+            Some(200),
+            TimeDelta::milliseconds(1_000),
+        );
+        let pending = Span::for_tests(
+            "https://example.org/_matrix/client/v3/sync",
+            None,
+            TimeDelta::zero(),
+        );
+        let start_at = ok.start_at.timestamp_millis();
+        let terminal = Terminal {
+            width: 100,
+            color: false,
+        };
+
+        let text = to_text(
+            &[(&connection_id, 1, &ok), (&connection_id, 2, &pending)],
+            start_at,
+            start_at + 1_000,
+            terminal,
+        );
+        let lines = text.lines().collect::<Vec<_>>();
+
+        assert_eq!(lines.len(), 2);
+        assert!(
+            lines.iter().all(|line| line.chars().count() == 100),
+            "{text}"
+        );
+        assert!(lines[0].starts_with("room-list          200 POST   /_matrix/client/v3/sync"));
+        assert!(lines[0].ends_with(&format!("1000ms |{}|", "#".repeat(24))));
+        assert!(lines[1].contains("pending"));
+        assert!(lines[1].ends_with(&format!("0ms |!{}|", " ".repeat(23))));
+
+        let text = to_text(
+            &[(&connection_id, 2, &pending)],
+            start_at,
+            start_at + 1_000,
+            Terminal {
+                color: true,
+                ..terminal
+            },
+        );
+
+        assert!(text.contains("|\x1b[31m!\x1b[0m"));
+    }
+}
@@ -0,0 +1,148 @@
+//! Compute the ticks of the time axis of the timeline, at round wall-clock
+//! times, so that a slow request can be correlated with a server-side log or
+//! a user report, e.g. “it froze at 14:32:05”.
+
+use chrono::{DateTime, FixedOffset};
+use serde::Serialize;
+
+/// Maximum number of ticks on the axis.
+const MAXIMUM_NUMBER_OF_TICKS: i64 = 10;
+
+/// Intervals to choose from, the finest first, in milliseconds.
+const INTERVALS: [i64; 21] = [
+    100, 200, 500, 1_000, 2_000, 5_000, 10_000, 15_000, 30_000, 60_000, 120_000, 300_000, 600_000,
+    900_000, 1_800_000, 3_600_000, 7_200_000, 10_800_000, 21_600_000, 43_200_000, 86_400_000,
+];
+
+/// A tick of the axis.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Tick {
+    /// Offset from the start of the timeline, in milliseconds.
+    pub at: i64,
+    /// The wall-clock time, e.g. `14:32:05`.
+    pub label: String,
+}
+
+/// The ticks of the axis, every `interval` milliseconds.
+#[derive(Debug, Serialize)]
+pub struct Ticks {
+    pub interval: i64,
+    pub ticks: Vec<Tick>,
+}
+
+/// Choose the finest interval keeping the number of ticks readable. A
+/// duration longer than the coarsest interval allows is ticked every day.
+fn interval(duration: i64) -> i64 {
+    INTERVALS
+        .into_iter()
+        .find(|interval| duration / interval <= MAXIMUM_NUMBER_OF_TICKS)
+        .unwrap_or(INTERVALS[INTERVALS.len() - 1])
+}
+
+/// Compute the ticks from `start_at`, the origin of the timeline, to
+/// `end_at`, at round times in `timezone`.
+pub fn compute(
+    (start_at, end_at): (DateTime<FixedOffset>, DateTime<FixedOffset>),
+    timezone: FixedOffset,
+) -> Ticks {
+    let origin = start_at.timestamp_millis();
+    let end = end_at.timestamp_millis();
+    let interval = interval(end - origin);
+    let label_format = match interval {
+        ..1_000 => "%H:%M:%S%.3f",
+        1_000..60_000 => "%H:%M:%S",
+        _ => "%H:%M",
+    };
+
+    // The ticks are round in `timezone`, not in UTC, e.g. for the offsets of
+    // half an hour.
+    let offset = i64::from(timezone.local_minus_utc()) * 1_000;
+    let mut at = (origin + offset).div_euclid(interval) * interval - offset;
+
+    if at < origin {
+        at += interval;
+    }
+
+    let ticks = (0..)
+        .map(|nth| at + nth * interval)
+        .take_while(|at| *at <= end)
+        .filter_map(|at| {
+            Some(Tick {
+                at: at - origin,
+                label: DateTime::from_timestamp_millis(at)?
+                    .with_timezone(&timezone)
+                    .format(label_format)
+                    .to_string(),
+            })
+        })
+        .collect();
+
+    Ticks { interval, ticks }
+}
+
+impl Ticks {
+    /// Serialize the ticks, safe to embed in a `<script>` element.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self)
+            .expect("Failed to serialize the ticks")
+            .replace("</", "<\\/")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+
+    use super::*;
+
+    #[test]
+    fn test_interval() {
+        for (duration, expected) in [
+            (0, 100),
+            (800, 100),
+            (10_000, 1_000),
+            (45_000, 5_000),
+            (90_000, 10_000),
+            (15 * 60_000, 120_000),
+            (60 * 60_000, 600_000),
+            (6 * 3_600_000, 3_600_000),
+            (30 * 86_400_000, 86_400_000),
+        ] {
+            assert_eq!(interval(duration), expected, "{duration}ms");
+        }
+    }
+
+    #[test]
+    fn test_compute() {
+        let start_at = DateTime::parse_from_rfc3339("2024-06-01T09:13:19.035Z").unwrap();
+        let utc = FixedOffset::east_opt(0).unwrap();
+
+        let ticks = compute((start_at, start_at + TimeDelta::seconds(10)), utc);
+
+        assert_eq!(ticks.interval, 1_000);
+        assert_eq!(ticks.ticks.len(), 10);
+        assert_eq!(
+            ticks.ticks[0],
+            Tick {
+                at: 965,
+                label: "09:13:20".to_owned()
+            }
+        );
+
+        // Round in the timezone of the report, at half past the hour in UTC.
+        let ticks = compute(
+            (start_at, start_at + TimeDelta::hours(6)),
+            FixedOffset::east_opt(5 * 3_600 + 1_800).unwrap(),
+        );
+
+        assert_eq!(ticks.interval, 3_600_000);
+        assert_eq!(
+            ticks.ticks.first(),
+            Some(&Tick {
+                at: (16 * 60 + 40) * 1_000 + 965,
+                label: "15:00".to_owned()
+            })
+        );
+        assert_eq!(ticks.ticks.len(), 6);
+    }
+}
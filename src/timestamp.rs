@@ -0,0 +1,155 @@
+//! Parse the timestamps of the log lines. The SDK logs RFC 3339 datetimes,
+//! but the logs of some platforms, e.g. the unified logging of iOS or older
+//! Android builds, have `2024-01-02 03:04:05.678`, without offset, or with an
+//! offset without colon. A datetime without offset is in UTC.
+//!
+//! Other shapes are parsed with `--timestamp-format`, a `strftime`-like
+//! format of [`chrono::format::strftime`], e.g. `%d/%m/%Y %H:%M:%S`: the
+//! datetimes of the log lines are matched by a regex derived from it.
+
+use chrono::{DateTime, FixedOffset, NaiveDateTime};
+
+/// Formats of the known datetimes without offset, or with one without colon,
+/// once the space between the date and the time is replaced by a `T`.
+const FORMATS: [&str; 2] = ["%Y-%m-%dT%H:%M:%S%.f%z", "%Y-%m-%dT%H:%M:%S%.f"];
+
+/// Parse a datetime of a known format.
+pub fn parse(datetime: &str) -> Option<DateTime<FixedOffset>> {
+    let datetime = datetime.replacen(' ', "T", 1);
+
+    DateTime::parse_from_rfc3339(&datetime)
+        .ok()
+        .or_else(|| DateTime::parse_from_str(&datetime, FORMATS[0]).ok())
+        .or_else(|| {
+            NaiveDateTime::parse_from_str(&datetime, FORMATS[1])
+                .ok()
+                .map(|datetime| datetime.and_utc().fixed_offset())
+        })
+}
+
+/// A format of the datetimes, from `--timestamp-format`.
+#[derive(Clone, Debug)]
+pub struct Format {
+    format: String,
+    /// The `datetime` group of the regex matching the datetimes.
+    pattern: String,
+}
+
+impl Format {
+    /// Parse a `strftime`-like format, e.g. `%d/%m/%Y %H:%M:%S%.f`.
+    pub fn new(format: &str) -> Result<Self, String> {
+        let mut pattern = String::new();
+        let mut characters = format.chars().peekable();
+
+        while let Some(character) = characters.next() {
+            if character != '%' {
+                pattern.push_str(&match character {
+                    // The regexes ignore the whitespaces.
+                    ' ' => r"\x20".to_owned(),
+                    character => regex::escape(&character.to_string()),
+                });
+
+                continue;
+            }
+
+            // `%.f`, `%.3f`, `%:z`, and so on, have a prefix.
+            let mut specifier = String::new();
+
+            while let Some(character) =
+                characters.next_if(|character| matches!(character, '.' | ':' | '0'..='9'))
+            {
+                specifier.push(character);
+            }
+
+            specifier.extend(characters.next());
+
+            pattern.push_str(match specifier.as_str() {
+                "Y" => r"\d{4}",
+                "m" | "d" | "H" | "I" | "M" | "S" | "y" => r"\d{2}",
+                "e" => r"[\x20\d]\d",
+                "j" => r"\d{3}",
+                "F" => r"\d{4}-\d{2}-\d{2}",
+                "T" => r"\d{2}:\d{2}:\d{2}",
+                "b" | "h" | "a" => r"[A-Za-z]{3}",
+                "B" | "A" => r"[A-Za-z]+",
+                "p" | "P" => r"[AaPp][Mm]",
+                "f" | "s" => r"\d+",
+                "z" => r"[+-]\d{2}:?\d{2}",
+                ":z" => r"[+-]\d{2}:\d{2}",
+                "%" => "%",
+                ".f" => r"(?:\.\d+)?",
+                specifier if specifier.starts_with('.') && specifier.ends_with('f') => r"\.\d+",
+                specifier => {
+                    return Err(format!(
+                        "`%{specifier}` of `{format}` isn't a supported specifier"
+                    ));
+                }
+            });
+        }
+
+        Ok(Self {
+            format: format.to_owned(),
+            pattern: format!("(?<datetime>{pattern})"),
+        })
+    }
+
+    /// The regex piece matching the datetimes, to replace
+    /// [`crate::pattern::DATETIME`].
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Parse a datetime, in UTC if the format has no offset.
+    pub fn parse(&self, datetime: &str) -> Option<DateTime<FixedOffset>> {
+        DateTime::parse_from_str(datetime, &self.format)
+            .ok()
+            .or_else(|| {
+                NaiveDateTime::parse_from_str(datetime, &self.format)
+                    .ok()
+                    .map(|datetime| datetime.and_utc().fixed_offset())
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use regex::RegexBuilder;
+
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let at = |datetime: &str| parse(datetime).map(|datetime| datetime.to_rfc3339());
+
+        assert_eq!(
+            at("2024-01-02T03:04:05.678Z").as_deref(),
+            Some("2024-01-02T03:04:05.678+00:00")
+        );
+        assert_eq!(
+            at("2024-01-02 03:04:05.678").as_deref(),
+            Some("2024-01-02T03:04:05.678+00:00")
+        );
+        assert_eq!(
+            at("2024-01-02 03:04:05.678901+0100").as_deref(),
+            Some("2024-01-02T03:04:05.678901+01:00")
+        );
+        assert_eq!(at("2024-01-02 03:04").as_deref(), None);
+    }
+
+    #[test]
+    fn test_format() {
+        let format = Format::new("%d/%m/%Y %H:%M:%S%.3f").unwrap();
+        let regex = RegexBuilder::new(format.pattern())
+            .ignore_whitespace(true)
+            .build()
+            .unwrap();
+        let datetime = &regex.captures("[02/01/2024 03:04:05.678] DEBUG").unwrap()["datetime"];
+
+        assert_eq!(datetime, "02/01/2024 03:04:05.678");
+        assert_eq!(
+            format.parse(datetime).unwrap().to_rfc3339(),
+            "2024-01-02T03:04:05.678+00:00"
+        );
+        assert!(Format::new("%Y-%m-%d %Q").is_err());
+    }
+}
@@ -0,0 +1,180 @@
+//! Rank the endpoints by the bytes they have transferred, to answer “why did
+//! the app use 200 MB overnight”.
+
+use serde::Serialize;
+
+use crate::{Spans, buckets, html, human};
+
+/// Maximum number of endpoints in the table; the others are rolled up in one
+/// row.
+const MAXIMUM_NUMBER_OF_ENDPOINTS: usize = 20;
+
+/// Bytes transferred by the spans of an endpoint.
+#[derive(Default, Serialize)]
+pub struct Row {
+    pub endpoint: String,
+    pub requests: usize,
+    pub bytes_down: u64,
+    pub bytes_up: u64,
+}
+
+impl Row {
+    fn bytes(&self) -> u64 {
+        self.bytes_down + self.bytes_up
+    }
+}
+
+/// Bytes transferred per endpoint.
+#[derive(Serialize)]
+pub struct Traffic {
+    /// The endpoints which have transferred the most bytes, by response bytes
+    /// then request bytes.
+    pub endpoints: Vec<Row>,
+
+    /// The remaining endpoints, rolled up, if any.
+    pub others: Option<Row>,
+
+    pub bytes_down: u64,
+    pub bytes_up: u64,
+}
+
+/// Rank the endpoints by the bytes they have transferred.
+pub fn compute(spans: &Spans) -> Traffic {
+    let mut endpoints = buckets::per_endpoint(spans)
+        .into_iter()
+        .map(|(endpoint, aggregate)| Row {
+            endpoint,
+            requests: aggregate.requests,
+            bytes_down: aggregate.bytes_down,
+            bytes_up: aggregate.bytes_up,
+        })
+        .collect::<Vec<_>>();
+    endpoints.sort_by(|left, right| {
+        (right.bytes_down, right.bytes_up).cmp(&(left.bytes_down, left.bytes_up))
+    });
+
+    let bytes_down = endpoints.iter().map(|row| row.bytes_down).sum();
+    let bytes_up = endpoints.iter().map(|row| row.bytes_up).sum();
+    let others = (endpoints.len() > MAXIMUM_NUMBER_OF_ENDPOINTS).then(|| {
+        endpoints.drain(MAXIMUM_NUMBER_OF_ENDPOINTS..).fold(
+            Row {
+                endpoint: "Others".to_owned(),
+                ..Row::default()
+            },
+            |others, row| Row {
+                requests: others.requests + row.requests,
+                bytes_down: others.bytes_down + row.bytes_down,
+                bytes_up: others.bytes_up + row.bytes_up,
+                ..others
+            },
+        )
+    });
+
+    Traffic {
+        endpoints,
+        others,
+        bytes_down,
+        bytes_up,
+    }
+}
+
+/// Summarize the bytes sent and received per connection, for the end of a
+/// run, or nothing if no span has a size.
+pub fn per_connection_to_text(spans: &Spans) -> String {
+    let connections = spans
+        .iter()
+        .map(|(connection_id, spans)| {
+            let mut aggregate = buckets::Aggregate::default();
+
+            for span in spans.values() {
+                aggregate.add(span);
+            }
+
+            (connection_id, aggregate.bytes_up, aggregate.bytes_down)
+        })
+        .filter(|(_, bytes_up, bytes_down)| bytes_up + bytes_down > 0)
+        .map(|(connection_id, bytes_up, bytes_down)| {
+            format!(
+                "  {connection_id}: {sent} sent, {received} received\n",
+                sent = human::bytes(bytes_up),
+                received = human::bytes(bytes_down),
+            )
+        })
+        .collect::<String>();
+
+    if connections.is_empty() {
+        return String::new();
+    }
+
+    format!("Bytes per connection:\n{connections}")
+}
+
+impl Traffic {
+    /// Render the table, or nothing if no span has a size.
+    pub fn to_html(&self) -> String {
+        let total = self.bytes_down + self.bytes_up;
+
+        if total == 0 {
+            return String::new();
+        }
+
+        let row_to_html = |row: &Row, endpoint: String| {
+            let average = |bytes: u64| {
+                if row.requests == 0 {
+                    String::new()
+                } else {
+                    human::bytes_to_html(bytes / row.requests as u64)
+                }
+            };
+
+            format!(
+                "      <tr>
+        <th scope=\"row\">{endpoint}</th>
+        <td>{requests}</td>
+        <td>{bytes_down}</td>
+        <td>{bytes_up}</td>
+        <td>{average_down}</td>
+        <td>{average_up}</td>
+        <td>{share}</td>
+      </tr>
+",
+                requests = human::count(row.requests),
+                bytes_down = human::bytes_to_html(row.bytes_down),
+                bytes_up = human::bytes_to_html(row.bytes_up),
+                average_down = average(row.bytes_down),
+                average_up = average(row.bytes_up),
+                share = human::percentage(row.bytes() as f64, total as f64),
+            )
+        };
+        let rows = self
+            .endpoints
+            .iter()
+            .map(|row| row_to_html(row, format!("<code>{}</code>", html::escape(&row.endpoint))))
+            .chain(
+                self.others
+                    .iter()
+                    .map(|others| row_to_html(others, html::escape(&others.endpoint))),
+            )
+            .collect::<String>();
+
+        format!(
+            "  <h3>Bytes per endpoint</h3>
+  <table class=\"traffic\">
+    <thead>
+      <tr>
+        <th scope=\"col\">Endpoint</th>
+        <th scope=\"col\">Requests</th>
+        <th scope=\"col\">Bytes down</th>
+        <th scope=\"col\">Bytes up</th>
+        <th scope=\"col\">Avg. response</th>
+        <th scope=\"col\">Avg. request</th>
+        <th scope=\"col\">Share of traffic</th>
+      </tr>
+    </thead>
+    <tbody>
+{rows}    </tbody>
+  </table>
+"
+        )
+    }
+}
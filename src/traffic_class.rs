@@ -0,0 +1,160 @@
+//! Classify the traffic of a client: the client-server API calls to the
+//! homeserver, and the others, e.g. to an OpenID Connect provider, which must
+//! not pollute the latency of the homeserver.
+
+use ada_url::Url;
+
+/// Class of traffic, in display order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TrafficClass {
+    ClientServer,
+    Auth,
+    Discovery,
+    MediaCdn,
+    Other,
+}
+
+/// How a rule matches a part of an URI.
+#[derive(Clone, Copy)]
+enum Pattern {
+    Any,
+    Prefix(&'static str),
+    Suffix(&'static str),
+    Contains(&'static str),
+}
+
+impl Pattern {
+    fn matches(&self, value: &str) -> bool {
+        match self {
+            Self::Any => true,
+            Self::Prefix(prefix) => value.starts_with(prefix),
+            Self::Suffix(suffix) => value.ends_with(suffix),
+            Self::Contains(needle) => value.contains(needle),
+        }
+    }
+}
+
+/// The rules, matching the host and the path of an URI; the first one
+/// matching both wins.
+const RULES: &[(Pattern, Pattern, TrafficClass)] = {
+    use Pattern::*;
+    use TrafficClass::*;
+
+    &[
+        (Any, Prefix("/.well-known/matrix/"), Discovery),
+        (Any, Prefix("/.well-known/openid-configuration"), Auth),
+        (Any, Prefix("/.well-known/oauth-authorization-server"), Auth),
+        // The push gateways and the identity servers aren't the homeserver.
+        (Any, Prefix("/_matrix/push/"), Other),
+        (Any, Prefix("/_matrix/identity/"), Other),
+        (Any, Prefix("/_matrix/"), ClientServer),
+        (Any, Prefix("/_synapse/client/oidc/"), Auth),
+        (Any, Prefix("/_synapse/client/"), ClientServer),
+        // Matrix Authentication Service, Keycloak, and the usual OAuth 2.0
+        // paths.
+        (Any, Prefix("/oauth2/"), Auth),
+        (Any, Contains("/protocol/openid-connect/"), Auth),
+        (Any, Suffix("/authorize"), Auth),
+        (Any, Suffix("/token"), Auth),
+        (Any, Suffix("/userinfo"), Auth),
+        (Any, Suffix("/jwks"), Auth),
+        (Any, Suffix("/jwks.json"), Auth),
+        (Suffix(".cloudfront.net"), Any, MediaCdn),
+        (Suffix(".r2.cloudflarestorage.com"), Any, MediaCdn),
+        (Contains(".s3."), Any, MediaCdn),
+        (Prefix("s3."), Any, MediaCdn),
+        (Suffix(".blob.core.windows.net"), Any, MediaCdn),
+        (Prefix("storage.googleapis.com"), Any, MediaCdn),
+        (Prefix("cdn."), Any, MediaCdn),
+    ]
+};
+
+impl TrafficClass {
+    /// Classify an URI.
+    pub fn of(uri: &str) -> Self {
+        let Ok(uri) = Url::parse(uri, None) else {
+            return Self::Other;
+        };
+        let (host, path) = (uri.hostname(), uri.pathname());
+
+        RULES
+            .iter()
+            .find(|(host_pattern, path_pattern, _)| {
+                host_pattern.matches(host) && path_pattern.matches(path)
+            })
+            .map(|(.., class)| *class)
+            .unwrap_or(Self::Other)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::ClientServer => "client-server",
+            Self::Auth => "auth/oidc",
+            Self::Discovery => "discovery",
+            Self::MediaCdn => "media-cdn",
+            Self::Other => "other",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_of() {
+        use TrafficClass::*;
+
+        for (uri, expected) in [
+            (
+                "https://matrix-client.matrix.org/_matrix/client/v3/sync?timeout=30000",
+                ClientServer,
+            ),
+            (
+                "https://matrix.example.org/_matrix/client/unstable/org.matrix.simplified_msc3575/sync",
+                ClientServer,
+            ),
+            (
+                "https://matrix-client.matrix.org/_matrix/client/v1/media/download/matrix.org/AbCdEf",
+                ClientServer,
+            ),
+            (
+                "https://matrix.example.org/_matrix/media/v3/thumbnail/example.org/AbCdEf?width=96",
+                ClientServer,
+            ),
+            (
+                "https://matrix.example.org/_synapse/client/rendezvous",
+                ClientServer,
+            ),
+            ("https://matrix.org/.well-known/matrix/client", Discovery),
+            ("https://example.org/.well-known/matrix/server", Discovery),
+            (
+                "https://account.matrix.org/.well-known/openid-configuration",
+                Auth,
+            ),
+            ("https://auth.example.org/oauth2/token", Auth),
+            ("https://auth.example.org/oauth2/registration", Auth),
+            (
+                "https://sso.example.org/realms/matrix/protocol/openid-connect/token",
+                Auth,
+            ),
+            ("https://login.example.com/oauth/authorize", Auth),
+            (
+                "https://matrix.example.org/_synapse/client/oidc/callback",
+                Auth,
+            ),
+            (
+                "https://d1234abcd.cloudfront.net/media/AbCdEf.jpg",
+                MediaCdn,
+            ),
+            ("https://bucket.s3.eu-west-1.amazonaws.com/AbCdEf", MediaCdn),
+            ("https://cdn.example.org/AbCdEf", MediaCdn),
+            ("https://matrix.org/_matrix/push/v1/notify", Other),
+            ("https://vector.im/_matrix/identity/v2/lookup", Other),
+            ("https://example.org/index.html", Other),
+            ("not an URI", Other),
+        ] {
+            assert_eq!(TrafficClass::of(uri), expected, "{uri}");
+        }
+    }
+}
@@ -0,0 +1,352 @@
+//! Browse the spans in the terminal with `--tui`, e.g. over SSH on a test
+//! device: the waterfall of `--format term`, scrollable and zoomable, and the
+//! statistics per endpoint.
+//!
+//! The terminal is put in raw mode, on its alternate screen, and restored on
+//! exit. The keys are the ones of `less`: `↑`/`↓` or `k`/`j` scroll, `PgUp`
+//! and `PgDn` or `Space` scroll by a page, `g` and `G` go to the top and to
+//! the bottom, `←`/`→` or `h`/`l` pan the timeline, `+` and `-` zoom it,
+//! `Tab` switches between the requests and the endpoints, and `q` quits.
+
+use std::{
+    collections::BTreeMap,
+    io::{self, Read, Write},
+};
+
+use crate::{ConnectionId, RequestId, Span, buckets::Aggregate, human, term};
+
+/// Shortest window of the timeline, in milliseconds.
+const MINIMUM_WINDOW: i64 = 10;
+
+/// A key pressed by the user.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Key {
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Top,
+    Bottom,
+    Left,
+    Right,
+    ZoomIn,
+    ZoomOut,
+    Switch,
+    Quit,
+}
+
+impl Key {
+    /// Parse the bytes read from the terminal at once, if they are a known
+    /// key.
+    fn parse(bytes: &[u8]) -> Option<Self> {
+        Some(match bytes {
+            b"\x1b[A" | b"k" => Self::Up,
+            b"\x1b[B" | b"j" => Self::Down,
+            b"\x1b[5~" | b"b" => Self::PageUp,
+            b"\x1b[6~" | b" " => Self::PageDown,
+            b"\x1b[H" | b"g" => Self::Top,
+            b"\x1b[F" | b"G" => Self::Bottom,
+            b"\x1b[D" | b"h" => Self::Left,
+            b"\x1b[C" | b"l" => Self::Right,
+            b"+" | b"=" => Self::ZoomIn,
+            b"-" => Self::ZoomOut,
+            b"\t" => Self::Switch,
+            b"q" | b"\x1b" | b"\x03" => Self::Quit,
+            _ => return None,
+        })
+    }
+}
+
+/// What is displayed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum View {
+    Requests,
+    Endpoints,
+}
+
+/// The scroll position and the window of the timeline.
+#[derive(Debug, PartialEq, Eq)]
+struct Viewport {
+    view: View,
+    /// First displayed row.
+    top: usize,
+    /// Window of the timeline, in milliseconds from its start.
+    window_start_at: i64,
+    window_end_at: i64,
+    /// Duration of the whole timeline.
+    end_at: i64,
+}
+
+impl Viewport {
+    fn new(end_at: i64) -> Self {
+        Self {
+            view: View::Requests,
+            top: 0,
+            window_start_at: 0,
+            window_end_at: end_at,
+            end_at,
+        }
+    }
+
+    /// Apply a key, for `number_of_rows` rows of which `height` are
+    /// displayed.
+    fn apply(&mut self, key: Key, number_of_rows: usize, height: usize) {
+        let bottom = number_of_rows.saturating_sub(height);
+        let window = self.window_end_at - self.window_start_at;
+
+        match key {
+            Key::Up => self.top = self.top.saturating_sub(1),
+            Key::Down => self.top = (self.top + 1).min(bottom),
+            Key::PageUp => self.top = self.top.saturating_sub(height),
+            Key::PageDown => self.top = (self.top + height).min(bottom),
+            Key::Top => self.top = 0,
+            Key::Bottom => self.top = bottom,
+            Key::Left => self.pan(-window / 4),
+            Key::Right => self.pan(window / 4),
+            Key::ZoomIn => {
+                let window = (window / 2).max(MINIMUM_WINDOW.min(self.end_at));
+                let center = self.window_start_at + (self.window_end_at - self.window_start_at) / 2;
+
+                self.window_start_at = center - window / 2;
+                self.window_end_at = self.window_start_at + window;
+                self.pan(0);
+            }
+            Key::ZoomOut => {
+                let window = (window * 2).min(self.end_at);
+                let center = self.window_start_at + (self.window_end_at - self.window_start_at) / 2;
+
+                self.window_start_at = center - window / 2;
+                self.window_end_at = self.window_start_at + window;
+                self.pan(0);
+            }
+            Key::Switch => {
+                self.view = match self.view {
+                    View::Requests => View::Endpoints,
+                    View::Endpoints => View::Requests,
+                };
+                self.top = 0;
+            }
+            Key::Quit => {}
+        }
+    }
+
+    /// Move the window by `delta` milliseconds, within the timeline.
+    fn pan(&mut self, delta: i64) {
+        let window = self.window_end_at - self.window_start_at;
+
+        self.window_start_at = (self.window_start_at + delta).clamp(0, self.end_at - window);
+        self.window_end_at = self.window_start_at + window;
+    }
+}
+
+/// The terminal in raw mode, on its alternate screen, until dropped.
+struct RawMode {
+    #[cfg(unix)]
+    original: libc::termios,
+}
+
+impl RawMode {
+    #[cfg(unix)]
+    fn enable() -> io::Result<Self> {
+        // SAFETY: `tcgetattr` only writes a `termios` to the given pointer.
+        let mut original = unsafe { std::mem::zeroed::<libc::termios>() };
+
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut raw = original;
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+        raw.c_cc[libc::VMIN] = 1;
+        raw.c_cc[libc::VTIME] = 0;
+
+        // SAFETY: `tcsetattr` only reads the given `termios`.
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, &raw) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // The alternate screen, without the cursor.
+        print!("\x1b[?1049h\x1b[?25l");
+        io::stdout().flush()?;
+
+        Ok(Self { original })
+    }
+
+    #[cfg(not(unix))]
+    fn enable() -> io::Result<Self> {
+        Err(io::Error::other("the terminal can't be put in raw mode"))
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        print!("\x1b[?25h\x1b[?1049l");
+        let _ = io::stdout().flush();
+
+        // SAFETY: `tcsetattr` only reads the given `termios`.
+        #[cfg(unix)]
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, &self.original);
+        }
+    }
+}
+
+/// Render the statistics per endpoint, the slowest median first.
+fn endpoints_to_lines(per_endpoint: &BTreeMap<String, Aggregate>) -> Vec<String> {
+    let mut endpoints = per_endpoint.iter().collect::<Vec<_>>();
+    endpoints.sort_by_key(|(_, aggregate)| std::cmp::Reverse(aggregate.percentile_duration(50.)));
+
+    let duration = |aggregate: &Aggregate, percentile| {
+        aggregate
+            .percentile_duration(percentile)
+            .map(human::duration)
+            .unwrap_or_default()
+    };
+
+    Some(format!(
+        "{:>8} {:>7} {:>9} {:>9}  Endpoint",
+        "Requests", "Errors", "Median", "p95"
+    ))
+    .into_iter()
+    .chain(endpoints.iter().map(|(endpoint, aggregate)| {
+        format!(
+            "{requests:>8} {errors:>7} {median:>9} {p95:>9}  {endpoint}",
+            requests = human::count(aggregate.requests),
+            errors = human::count(aggregate.errors),
+            median = duration(aggregate, 50.),
+            p95 = duration(aggregate, 95.),
+        )
+    }))
+    .collect()
+}
+
+/// Browse `spans`, whose timeline is from `smallest_start_at` to
+/// `largest_end_at`, in milliseconds, and their aggregates per endpoint,
+/// until the user quits.
+pub fn run(
+    spans: &[(&ConnectionId, RequestId, &Span)],
+    per_endpoint: &BTreeMap<String, Aggregate>,
+    smallest_start_at: i64,
+    largest_end_at: i64,
+) -> io::Result<()> {
+    let _raw_mode = RawMode::enable()?;
+    let endpoints = endpoints_to_lines(per_endpoint);
+    let mut viewport = Viewport::new(largest_end_at.saturating_sub(smallest_start_at));
+    let mut stdin = io::stdin().lock();
+    let mut buffer = [0; 8];
+
+    loop {
+        let (rows, columns) = term::window_size()
+            .filter(|(rows, columns)| *rows > 2 && *columns > 0)
+            .unwrap_or((24, term::DEFAULT_WIDTH));
+        let height = rows - 2;
+        let window_start_at = smallest_start_at + viewport.window_start_at;
+        let window_end_at = smallest_start_at + viewport.window_end_at;
+        let lines = match viewport.view {
+            View::Requests => term::to_text(
+                // The spans overlapping the window only.
+                &spans
+                    .iter()
+                    .filter(|(_, _, span)| {
+                        let start_at = span.start_at.timestamp_millis();
+
+                        start_at <= window_end_at
+                            && start_at + span.duration.num_milliseconds() >= window_start_at
+                    })
+                    .copied()
+                    .collect::<Vec<_>>(),
+                window_start_at,
+                window_end_at,
+                term::Terminal {
+                    width: columns,
+                    color: true,
+                },
+            )
+            .lines()
+            .map(ToOwned::to_owned)
+            .collect::<Vec<_>>(),
+            View::Endpoints => endpoints.clone(),
+        };
+        let header = match viewport.view {
+            View::Requests => format!(
+                "Requests: {number_of_spans}, from {from} to {to} of {end_at}",
+                number_of_spans = human::count(spans.len()),
+                from = human::milliseconds(viewport.window_start_at),
+                to = human::milliseconds(viewport.window_end_at),
+                end_at = human::milliseconds(viewport.end_at),
+            ),
+            View::Endpoints => format!("Endpoints: {}", human::count(endpoints.len() - 1)),
+        };
+        let footer = "↑↓ scroll, PgUp PgDn page, ←→ pan, + - zoom, Tab requests/endpoints, q quit";
+
+        let mut screen = format!("\x1b[H\x1b[2J\x1b[7m{header:<columns$}\x1b[0m\r\n");
+
+        for line in lines.iter().skip(viewport.top).take(height) {
+            screen.push_str(line);
+            screen.push_str("\x1b[0m\r\n");
+        }
+
+        screen.push_str(&format!(
+            "\x1b[{rows};1H\x1b[7m{footer:<columns$}\x1b[0m",
+            footer = footer.chars().take(columns).collect::<String>(),
+        ));
+
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(screen.as_bytes())?;
+        stdout.flush()?;
+
+        let length = stdin.read(&mut buffer)?;
+
+        match Key::parse(&buffer[..length]) {
+            None if length == 0 => return Ok(()),
+            None => {}
+            Some(Key::Quit) => return Ok(()),
+            Some(key) => viewport.apply(key, lines.len(), height),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_viewport() {
+        assert_eq!(Key::parse(b"\x1b[B"), Some(Key::Down));
+        assert_eq!(Key::parse(b"\x1b[6~"), Some(Key::PageDown));
+        assert_eq!(Key::parse(b"x"), None);
+
+        let mut viewport = Viewport::new(1_000);
+
+        // The scroll stops at the last page.
+        viewport.apply(Key::PageDown, 30, 20);
+        assert_eq!(viewport.top, 10);
+        viewport.apply(Key::Down, 30, 20);
+        assert_eq!(viewport.top, 10);
+        viewport.apply(Key::Top, 30, 20);
+        assert_eq!(viewport.top, 0);
+
+        // The window is zoomed around its center, and panned within the
+        // timeline.
+        viewport.apply(Key::ZoomIn, 30, 20);
+        assert_eq!(
+            (viewport.window_start_at, viewport.window_end_at),
+            (250, 750)
+        );
+        viewport.apply(Key::Right, 30, 20);
+        viewport.apply(Key::Right, 30, 20);
+        viewport.apply(Key::Right, 30, 20);
+        assert_eq!(
+            (viewport.window_start_at, viewport.window_end_at),
+            (500, 1_000)
+        );
+        viewport.apply(Key::ZoomOut, 30, 20);
+        assert_eq!(
+            (viewport.window_start_at, viewport.window_end_at),
+            (0, 1_000)
+        );
+
+        viewport.apply(Key::Switch, 30, 20);
+        assert_eq!(viewport.view, View::Endpoints);
+    }
+}
@@ -0,0 +1,206 @@
+//! Attach the `WARN` and `ERROR` log lines, e.g. “timeout waiting for
+//! response” or “retrying in 2s”, to the spans they are about.
+//!
+//! A line is attached to the span of its request ID when it has one.
+//! Otherwise, it is attached to the span in flight at the time of the line, or
+//! to the span completed shortly before it. To avoid false positives, a line
+//! matching several spans, e.g. because several requests are in flight at the
+//! same time, is attached to none of them and is counted as ambiguous.
+
+use std::fmt;
+
+use chrono::{DateTime, FixedOffset, TimeDelta};
+
+use crate::{ConnectionId, RequestId, Span, Spans, html};
+
+/// Default maximum number of lines attached to a span.
+pub const DEFAULT_PER_SPAN: usize = 3;
+
+/// Delay after the completion of a span during which a line can still be
+/// about it.
+const AFTER_COMPLETION: TimeDelta = TimeDelta::seconds(2);
+
+/// Delay after which a span without a response isn't considered in flight
+/// anymore, so that cancelled requests don't make every line ambiguous.
+const MAXIMUM_PENDING: TimeDelta = TimeDelta::minutes(2);
+
+/// A `WARN` or `ERROR` log line attached to a span.
+#[derive(Clone, Debug)]
+pub struct Warning {
+    pub level: String,
+    pub message: String,
+    pub log_line: usize,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            formatter,
+            "{} at log line {}: {}",
+            self.level, self.log_line, self.message
+        )
+    }
+}
+
+impl Warning {
+    /// Parse a warning formatted by its [`Display`](fmt::Display)
+    /// implementation, e.g. in an exported dataset.
+    pub fn parse(value: &str) -> Option<Self> {
+        let (level, rest) = value.split_once(" at log line ")?;
+        let (log_line, message) = rest.split_once(": ")?;
+
+        Some(Self {
+            level: level.to_owned(),
+            message: message.to_owned(),
+            log_line: log_line.parse().ok()?,
+        })
+    }
+}
+
+/// A pattern of the targets of the lines to collect, e.g. `matrix_sdk*`.
+///
+/// A trailing `*` matches any suffix; otherwise the pattern matches the
+/// target itself and its child modules.
+#[derive(Clone, Debug)]
+pub struct Target(String);
+
+impl Target {
+    /// Parse a comma-separated list of targets.
+    pub fn parse_list(value: &str) -> Vec<Self> {
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|pattern| !pattern.is_empty())
+            .map(|pattern| Self(pattern.to_owned()))
+            .collect()
+    }
+
+    /// The default targets: the crates of the Matrix SDK.
+    pub fn defaults() -> Vec<Self> {
+        vec![Self("matrix_sdk*".to_owned())]
+    }
+
+    pub fn matches(&self, target: &str) -> bool {
+        match self.0.strip_suffix('*') {
+            Some(prefix) => target.starts_with(prefix),
+            None => {
+                target == self.0
+                    || target
+                        .strip_prefix(self.0.as_str())
+                        .is_some_and(|rest| rest.starts_with("::"))
+            }
+        }
+    }
+}
+
+/// Where a line has been attached.
+pub enum Attachment {
+    /// To a single span.
+    Attached,
+
+    /// To no span, because no span matches.
+    Unattached,
+
+    /// To no span, because several spans match.
+    Ambiguous,
+}
+
+/// Attach a line to its span.
+///
+/// `request` is the connection ID and the request ID of the line, if it has
+/// some; `connection_id` alone restricts the spans matched by time.
+pub fn attach(
+    spans: &mut Spans,
+    warning: Warning,
+    at: DateTime<FixedOffset>,
+    request: Option<(&str, RequestId)>,
+    connection_id: Option<&str>,
+    per_span: usize,
+) -> Attachment {
+    let key = match request {
+        Some((connection_id, request_id)) => spans
+            .get(connection_id)
+            .filter(|spans| spans.contains_key(&request_id))
+            .map(|_| (connection_id.to_owned(), request_id)),
+        None => match find_by_time(spans, at, connection_id) {
+            Ok(key) => key,
+            Err(()) => return Attachment::Ambiguous,
+        },
+    };
+
+    let Some(span) = key.and_then(|(connection_id, request_id)| {
+        spans
+            .get_mut(&connection_id)
+            .and_then(|spans| spans.get_mut(&request_id))
+    }) else {
+        return Attachment::Unattached;
+    };
+
+    if span.warnings.len() < per_span {
+        span.warnings.push(warning);
+    }
+
+    Attachment::Attached
+}
+
+/// Find the single span in flight at `at`, or else the single span completed
+/// shortly before `at`.
+///
+/// Returns an error if several spans match.
+fn find_by_time(
+    spans: &Spans,
+    at: DateTime<FixedOffset>,
+    connection_id: Option<&str>,
+) -> Result<Option<(ConnectionId, RequestId)>, ()> {
+    let candidates = || {
+        spans
+            .iter()
+            .filter(move |(candidate, _)| connection_id.is_none_or(|id| id == candidate.as_str()))
+            .flat_map(|(connection_id, spans)| {
+                spans
+                    .iter()
+                    .map(move |(request_id, span)| (connection_id, *request_id, span))
+            })
+    };
+    let in_flight = |span: &Span| {
+        span.start_at <= at
+            && match span.response_log_line {
+                Some(_) => at <= span.start_at + span.duration,
+                None => at - span.start_at <= MAXIMUM_PENDING,
+            }
+    };
+    let just_completed = |span: &Span| {
+        span.response_log_line.is_some() && {
+            let end_at = span.start_at + span.duration;
+
+            end_at <= at && at - end_at <= AFTER_COMPLETION
+        }
+    };
+
+    for matches in [&in_flight as &dyn Fn(&Span) -> bool, &just_completed] {
+        let mut found = candidates().filter(|(_, _, span)| matches(span));
+
+        match (found.next(), found.next()) {
+            (Some((connection_id, request_id, _)), None) => {
+                return Ok(Some((connection_id.clone(), request_id)));
+            }
+            (Some(_), Some(_)) => return Err(()),
+            (None, _) => {}
+        }
+    }
+
+    Ok(None)
+}
+
+/// Render the warnings of a span in its details, as HTML list items.
+pub fn to_html(warnings: &[Warning]) -> String {
+    warnings
+        .iter()
+        .map(|warning| {
+            format!(
+                "            <li class=\"warning\">{}</li>\n",
+                html::escape(&warning.to_string())
+            )
+        })
+        .collect()
+}
@@ -0,0 +1,213 @@
+//! Watch a directory of logs, e.g. where a device drops its rotated log
+//! files, and regenerate the report whenever a log file is added or updated:
+//! `network-viewer --watch <directory> <output_path>`.
+//!
+//! The directory is polled: a file is updated when its size or its
+//! modification time changes. All the log files are parsed again on every
+//! change, on a single timeline, and the outputs are replaced atomically, so
+//! that a browser reloading the report never reads half of it.
+
+use std::{
+    fs,
+    path::Path,
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    cli::{Options, Stats, write_reports},
+    error::Error,
+    format::Output,
+    human, interrupt,
+    parser::Parser,
+    source::{LOG_EXTENSIONS, Source},
+};
+
+/// A log file of the directory, as last seen.
+type Fingerprint = (String, u64, Option<SystemTime>);
+
+/// Watch `directory` until Ctrl-C, and write the report of its log files to
+/// `outputs` on every change. The files are those whose name matches `glob`,
+/// or the `*.log`, `*.log.gz` and `*.log.zst` files by default.
+pub fn run(
+    options: &Options,
+    new_parser: impl Fn() -> Parser,
+    directory: &str,
+    glob: Option<&str>,
+    poll_interval: Duration,
+    outputs: &[Output],
+) -> Result<Stats, Error> {
+    interrupt::catch();
+    eprintln!("Watching `{directory}`, until Ctrl-C");
+
+    let mut last_fingerprints = Vec::new();
+    let mut number_of_regenerations = 0;
+
+    while !interrupt::is_interrupted() {
+        let fingerprints = fingerprints(directory, glob)?;
+
+        if !fingerprints.is_empty() && fingerprints != last_fingerprints {
+            let paths = fingerprints
+                .iter()
+                .map(|(path, ..)| path.clone())
+                .collect::<Vec<_>>();
+
+            // A file being written may be unreadable for a moment, e.g. a
+            // truncated gzip stream: the next change regenerates the report.
+            match regenerate(options, &new_parser, paths, outputs) {
+                Ok(number_of_matched_lines) => {
+                    number_of_regenerations += 1;
+                    eprintln!(
+                        "Regenerated the report after {} matched lines",
+                        human::count(number_of_matched_lines)
+                    );
+                }
+                Err(error) => eprintln!("Failed to regenerate the report: {error}"),
+            }
+
+            last_fingerprints = fingerprints;
+        }
+
+        thread::sleep(poll_interval);
+    }
+
+    Ok(Stats::new(format!(
+        "\nSource: {directory}\n\
+        Number of regenerations: {number_of_regenerations}\n\
+        {output_files}\
+        Done!",
+        number_of_regenerations = human::count(number_of_regenerations),
+        output_files = outputs
+            .iter()
+            .map(|output| format!("Output file: {}\n", output.path))
+            .collect::<String>(),
+    )))
+}
+
+/// Parse the log files at `paths`, and write the report. Returns the number
+/// of matched lines.
+fn regenerate(
+    options: &Options,
+    new_parser: &impl Fn() -> Parser,
+    paths: Vec<String>,
+    outputs: &[Output],
+) -> Result<usize, Error> {
+    let source = Source::from_paths(paths)?;
+    let mut parser = new_parser();
+
+    if let Source::Files(paths) = &source
+        && paths.len() > 1
+    {
+        parser.file_names = paths.clone();
+    }
+
+    source.read_lines(|line, location| {
+        parser.parse(line, location);
+    })?;
+
+    if let Some(diagnostic) = parser.no_match_diagnostic() {
+        return Err(Error::Input(diagnostic));
+    }
+
+    parser.lifecycle_events.sort_by_key(|event| event.at);
+
+    let options = Options {
+        sources: source.files(),
+        ..options.clone()
+    };
+    write_reports(
+        &options,
+        parser.spans,
+        &parser.lifecycle_events,
+        &source.name(),
+        outputs,
+    )?;
+
+    Ok(parser.number_of_matched_lines)
+}
+
+/// The log files of `directory`, sorted by path.
+fn fingerprints(directory: &str, glob: Option<&str>) -> Result<Vec<Fingerprint>, Error> {
+    let mut fingerprints = fs::read_dir(directory)
+        .map_err(Error::io(directory))?
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let metadata = entry
+                .metadata()
+                .ok()
+                .filter(|metadata| metadata.is_file())?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+
+            let is_log_file = match glob {
+                Some(glob) => matches(glob, &name),
+                None => LOG_EXTENSIONS
+                    .iter()
+                    .any(|extension| name.ends_with(extension)),
+            };
+
+            is_log_file.then(|| {
+                (
+                    Path::new(directory)
+                        .join(name)
+                        .to_string_lossy()
+                        .into_owned(),
+                    metadata.len(),
+                    metadata.modified().ok(),
+                )
+            })
+        })
+        .collect::<Vec<_>>();
+    fingerprints.sort();
+
+    Ok(fingerprints)
+}
+
+/// Whether a file name matches a glob, where `*` matches any characters, and
+/// `?` a single one.
+fn matches(glob: &str, name: &str) -> bool {
+    let glob = glob.chars().collect::<Vec<_>>();
+    let name = name.chars().collect::<Vec<_>>();
+    // The last `*`, and the position of the name it's matched up to, to
+    // backtrack when the rest doesn't match.
+    let mut star = None;
+    let (mut glob_nth, mut name_nth) = (0, 0);
+
+    while name_nth < name.len() {
+        match glob.get(glob_nth) {
+            Some('*') => {
+                star = Some((glob_nth, name_nth));
+                glob_nth += 1;
+            }
+            Some(character) if *character == '?' || *character == name[name_nth] => {
+                glob_nth += 1;
+                name_nth += 1;
+            }
+            _ => match star {
+                Some((star_nth, matched_nth)) => {
+                    star = Some((star_nth, matched_nth + 1));
+                    glob_nth = star_nth + 1;
+                    name_nth = matched_nth + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+
+    glob[glob_nth..].iter().all(|character| *character == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches() {
+        assert!(matches("*.log", "console.log"));
+        assert!(matches("console.*.log*", "console.1.log.gz"));
+        assert!(matches("console.?.log", "console.1.log"));
+        assert!(matches("*", "console.log"));
+        assert!(!matches("*.log", "console.log.gz"));
+        assert!(!matches("console.?.log", "console.12.log"));
+        assert!(!matches("*.log", "log"));
+    }
+}
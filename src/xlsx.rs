@@ -0,0 +1,236 @@
+//! Export the spans and their aggregates as an Excel workbook.
+
+use std::fmt;
+
+use chrono::FixedOffset;
+use rust_xlsxwriter::{Format, FormatAlign, Workbook, Worksheet, XlsxError};
+
+use crate::{
+    ConnectionId, RequestId, Span,
+    buckets::{Aggregate, Bucket},
+    duration, endpoint, server_timing,
+    size::Size,
+    status,
+};
+
+/// Maximum number of rows of a worksheet, including the header row.
+const MAXIMUM_NUMBER_OF_ROWS: usize = 1_048_576;
+
+pub enum Error {
+    /// There are more spans than Excel can hold in one worksheet.
+    TooManyRows(usize),
+
+    Xlsx(XlsxError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooManyRows(number_of_spans) => write!(
+                formatter,
+                "{number_of_spans} spans exceed Excel's limit of {} rows per worksheet; \
+                reduce the number of spans with `--last` or `--every`, or use the CSV format",
+                MAXIMUM_NUMBER_OF_ROWS - 1,
+            ),
+            Self::Xlsx(error) => write!(formatter, "{error}"),
+        }
+    }
+}
+
+impl From<XlsxError> for Error {
+    fn from(error: XlsxError) -> Self {
+        Self::Xlsx(error)
+    }
+}
+
+/// Build a workbook with a “Spans” sheet, a “Summary” sheet, and a “Per
+/// endpoint” sheet.
+///
+/// Start times are Excel datetimes in the display `timezone`.
+pub fn to_xlsx(
+    spans: &[(&ConnectionId, RequestId, &Span)],
+    (all, per_kind): &(Aggregate, Vec<(endpoint::Kind, Aggregate)>),
+    hourly_buckets: &[Bucket],
+    timezone: FixedOffset,
+) -> Result<Vec<u8>, Error> {
+    if spans.len() >= MAXIMUM_NUMBER_OF_ROWS {
+        return Err(Error::TooManyRows(spans.len()));
+    }
+
+    let header_format = Format::new()
+        .set_bold()
+        .set_background_color("#DDDDDD")
+        .set_align(FormatAlign::Center);
+    let datetime_format = Format::new().set_num_format("yyyy-mm-dd hh:mm:ss.000");
+
+    let mut workbook = Workbook::new();
+
+    let worksheet = workbook.add_worksheet().set_name("Spans")?;
+    write_header(
+        worksheet,
+        &header_format,
+        &[
+            ("Connection ID", 16),
+            ("Request ID", 11),
+            ("Status", 8),
+            ("Reason", 22),
+            ("Method", 8),
+            ("Domain", 28),
+            ("Path", 60),
+            ("Request bytes", 14),
+            ("Response bytes", 15),
+            ("Start at", 24),
+            ("Duration (ms)", 14),
+            ("Request log line", 16),
+            ("Response log line", 17),
+            ("Server-Timing", 30),
+            ("Server duration (ms)", 20),
+        ],
+    )?;
+
+    for (row, (connection_id, request_id, span)) in (1..).zip(spans) {
+        worksheet.write_string(row, 0, connection_id.as_str())?;
+        worksheet.write_number(row, 1, *request_id)?;
+
+        if let Some(status) = span.status {
+            worksheet.write_number(row, 2, status)?;
+        }
+
+        if let Some(label) = status::label(span) {
+            worksheet.write_string(row, 3, label)?;
+        }
+
+        worksheet.write_string(row, 4, &span.method)?;
+        worksheet.write_string(row, 5, span.domain())?;
+        worksheet.write_string(row, 6, span.path())?;
+
+        if let Some(request_bytes) = span.request_size.as_ref().and_then(Size::bytes) {
+            worksheet.write_number(row, 7, request_bytes as f64)?;
+        }
+
+        if let Some(response_bytes) = span.response_size.as_ref().and_then(Size::bytes) {
+            worksheet.write_number(row, 8, response_bytes as f64)?;
+        }
+
+        worksheet.write_datetime_with_format(
+            row,
+            9,
+            span.start_at.with_timezone(&timezone).naive_local(),
+            &datetime_format,
+        )?;
+
+        if span.response_log_line.is_some() {
+            worksheet.write_number(row, 10, duration::to_milliseconds(span.duration))?;
+        }
+
+        worksheet.write_number(row, 11, span.request_log_line as f64)?;
+
+        if let Some(response_log_line) = span.response_log_line {
+            worksheet.write_number(row, 12, response_log_line as f64)?;
+        }
+
+        if !span.server_timing.is_empty() {
+            worksheet.write_string(row, 13, server_timing::to_header(&span.server_timing))?;
+        }
+
+        if let Some(server_duration) = span.server_duration() {
+            worksheet.write_number(row, 14, server_duration)?;
+        }
+    }
+
+    worksheet.set_freeze_panes(1, 0)?;
+    worksheet.autofilter(0, 0, spans.len() as u32, 14)?;
+
+    let worksheet = workbook.add_worksheet().set_name("Summary")?;
+    write_header(
+        worksheet,
+        &header_format,
+        &[
+            ("Hour", 24),
+            ("Partial", 8),
+            ("Requests", 10),
+            ("Errors", 8),
+            ("Bytes down", 14),
+            ("Bytes up", 14),
+            ("p95 duration (ms)", 18),
+            ("Gaps", 6),
+        ],
+    )?;
+
+    for (row, bucket) in (1..).zip(hourly_buckets) {
+        worksheet.write_datetime_with_format(
+            row,
+            0,
+            bucket.start_at.naive_local(),
+            &datetime_format,
+        )?;
+        worksheet.write_boolean(row, 1, bucket.is_partial())?;
+        write_aggregate(worksheet, row, 2, &bucket.aggregate)?;
+        worksheet.write_number(row, 7, bucket.gaps as f64)?;
+    }
+
+    let total_row = hourly_buckets.len() as u32 + 1;
+    worksheet.write_string_with_format(total_row, 0, "Total", &header_format)?;
+    write_aggregate(worksheet, total_row, 2, all)?;
+    worksheet.set_freeze_panes(1, 0)?;
+
+    let worksheet = workbook.add_worksheet().set_name("Per endpoint")?;
+    write_header(
+        worksheet,
+        &header_format,
+        &[
+            ("Endpoint kind", 14),
+            ("Requests", 10),
+            ("Errors", 8),
+            ("Bytes down", 14),
+            ("Bytes up", 14),
+            ("p95 duration (ms)", 18),
+        ],
+    )?;
+
+    for (row, (kind, aggregate)) in (1..).zip(
+        per_kind
+            .iter()
+            .filter(|(_, aggregate)| aggregate.requests > 0),
+    ) {
+        worksheet.write_string(row, 0, kind.as_str())?;
+        write_aggregate(worksheet, row, 1, aggregate)?;
+    }
+
+    worksheet.set_freeze_panes(1, 0)?;
+
+    Ok(workbook.save_to_buffer()?)
+}
+
+fn write_header(
+    worksheet: &mut Worksheet,
+    format: &Format,
+    columns: &[(&str, u16)],
+) -> Result<(), XlsxError> {
+    for (column, (name, width)) in (0..).zip(columns) {
+        worksheet.write_string_with_format(0, column, *name, format)?;
+        worksheet.set_column_width(column, *width)?;
+    }
+
+    Ok(())
+}
+
+/// Write the requests, errors, bytes down, bytes up, and p95 duration of an
+/// aggregate, from `column`.
+fn write_aggregate(
+    worksheet: &mut Worksheet,
+    row: u32,
+    column: u16,
+    aggregate: &Aggregate,
+) -> Result<(), XlsxError> {
+    worksheet.write_number(row, column, aggregate.requests as f64)?;
+    worksheet.write_number(row, column + 1, aggregate.errors as f64)?;
+    worksheet.write_number(row, column + 2, aggregate.bytes_down as f64)?;
+    worksheet.write_number(row, column + 3, aggregate.bytes_up as f64)?;
+
+    if let Some(p95_duration) = aggregate.percentile_duration(95.) {
+        worksheet.write_number(row, column + 4, duration::to_milliseconds(p95_duration))?;
+    }
+
+    Ok(())
+}
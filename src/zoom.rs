@@ -0,0 +1,330 @@
+//! Precompute the zoom levels of the timeline: the whole log, its hours, and
+//! the minutes of each hour, or the steps of `--timeline-resolution`.
+//!
+//! Each segment knows the rows starting in it, as ranges of indices in the
+//! order of the table, and the origin and the scale rescaling their bars, so
+//! that zooming in the report only shows some rows and updates 2 CSS
+//! variables.
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use serde::Serialize;
+
+use crate::{ConnectionId, RequestId, Span, duration};
+
+/// Maximum number of segments of the tree: beyond, the minutes, then the
+/// hours, are dropped, to keep the report light.
+const MAXIMUM_NUMBER_OF_SEGMENTS: usize = 2_000;
+
+const MILLISECONDS_PER_HOUR: i64 = 3_600_000;
+const MILLISECONDS_PER_MINUTE: i64 = 60_000;
+
+/// Default step of the finest zoom level.
+pub const DEFAULT_RESOLUTION: TimeDelta = TimeDelta::minutes(1);
+
+/// Check the step of the finest zoom level: at least a second, and at most an
+/// hour, an hour being the coarsest level.
+pub fn parse_resolution(value: &str) -> Option<TimeDelta> {
+    duration::parse(value)
+        .filter(|resolution| *resolution >= TimeDelta::seconds(1))
+        .filter(|resolution| *resolution <= TimeDelta::hours(1))
+}
+
+#[derive(Debug, Serialize)]
+pub struct Segment {
+    pub label: String,
+
+    /// Start of the bars when zoomed on the segment, in milliseconds since the
+    /// start of the log.
+    pub origin: i64,
+
+    /// Duration of the timeline when zoomed on the segment, until the end of
+    /// its last span, in milliseconds.
+    pub scale: i64,
+
+    pub spans: usize,
+
+    /// Worst status family of the spans, e.g. `5` if any has failed with a
+    /// `5xx`.
+    pub worst_status_family: String,
+
+    /// Indices of the rows starting in the segment, as half-open ranges in the
+    /// order of the table. A single range if the rows are in chronological
+    /// order.
+    pub rows: Vec<[usize; 2]>,
+
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<Segment>,
+}
+
+/// Rank the status families, from the best to the worst.
+fn severity(status_family: &str) -> u8 {
+    match status_family {
+        "2" => 0,
+        "3" => 1,
+        "4" => 2,
+        "cancelled" | "pending" | "0" => 3,
+        _ => 4,
+    }
+}
+
+/// Build the segment of the rows at `members`, indices in `rows` in increasing
+/// order.
+fn segment(
+    label: String,
+    start_at: i64,
+    rows: &[(i64, i64, String)],
+    members: &[usize],
+) -> Segment {
+    let origin = start_at.max(0);
+    let end_at = members
+        .iter()
+        .map(|index| rows[*index].1)
+        .max()
+        .unwrap_or(origin);
+    let mut ranges = Vec::<[usize; 2]>::new();
+
+    for index in members {
+        match ranges.last_mut() {
+            Some([_, end]) if end == index => *end += 1,
+            _ => ranges.push([*index, index + 1]),
+        }
+    }
+
+    Segment {
+        label,
+        origin,
+        scale: (end_at - origin).max(1),
+        spans: members.len(),
+        worst_status_family: members
+            .iter()
+            .map(|index| rows[*index].2.as_str())
+            .max_by_key(|status_family| severity(status_family))
+            .unwrap_or("2")
+            .to_owned(),
+        rows: ranges,
+        children: Vec::new(),
+    }
+}
+
+/// Group the rows at `members` by the `step` of time they start in, shifted
+/// by `offset` to align them to the local time.
+fn group(
+    rows: &[(i64, i64, String)],
+    members: &[usize],
+    step: i64,
+    offset: i64,
+) -> BTreeMap<i64, Vec<usize>> {
+    let mut groups = BTreeMap::<i64, Vec<usize>>::new();
+
+    for index in members {
+        groups
+            .entry((rows[*index].0 + offset).div_euclid(step) * step - offset)
+            .or_default()
+            .push(*index);
+    }
+
+    groups
+}
+
+/// Compute the tree of segments of the displayed spans, in the order of the
+/// table, or `None` if there is no span. The hours are split in steps of
+/// `resolution`, unless it is an hour.
+pub fn compute(
+    displayed_spans: &[(&ConnectionId, RequestId, &Span)],
+    smallest_start_at: i64,
+    timezone: FixedOffset,
+    resolution: TimeDelta,
+) -> Option<Segment> {
+    if displayed_spans.is_empty() {
+        return None;
+    }
+
+    // Start, end and status family of each row, relative to the start of the
+    // log.
+    let rows = displayed_spans
+        .iter()
+        .map(|(_, _, span)| {
+            let start_at = span.start_at.timestamp_millis() - smallest_start_at;
+
+            (
+                start_at,
+                start_at + span.duration.num_milliseconds(),
+                span.status_family(),
+            )
+        })
+        .collect::<Vec<_>>();
+    let all = (0..rows.len()).collect::<Vec<_>>();
+    let offset = smallest_start_at + i64::from(timezone.local_minus_utc()) * 1_000;
+    let format = |start_at: i64, format: &str| {
+        DateTime::from_timestamp_millis(smallest_start_at + start_at)
+            .map(|date_time| {
+                date_time
+                    .with_timezone(&timezone)
+                    .format(format)
+                    .to_string()
+            })
+            .unwrap_or_default()
+    };
+
+    let step = resolution
+        .num_milliseconds()
+        .clamp(1, MILLISECONDS_PER_HOUR);
+    // The seconds are labelled if the steps don't fall on whole minutes.
+    let step_format = if step % MILLISECONDS_PER_MINUTE == 0 {
+        "%H:%M"
+    } else {
+        "%H:%M:%S"
+    };
+
+    let mut root = segment("Whole log".to_owned(), 0, &rows, &all);
+    let hours = group(&rows, &all, MILLISECONDS_PER_HOUR, offset);
+    let minutes = hours
+        .values()
+        .map(|members| group(&rows, members, step, offset))
+        .collect::<Vec<_>>();
+    let number_of_minutes = minutes.iter().map(BTreeMap::len).sum::<usize>();
+
+    if 1 + hours.len() > MAXIMUM_NUMBER_OF_SEGMENTS {
+        return Some(root);
+    }
+
+    let with_minutes = step < MILLISECONDS_PER_HOUR
+        && 1 + hours.len() + number_of_minutes <= MAXIMUM_NUMBER_OF_SEGMENTS;
+
+    root.children = hours
+        .iter()
+        .zip(minutes)
+        .map(|((start_at, members), minutes)| {
+            let mut hour = segment(
+                format(*start_at, "%Y-%m-%d %H:00"),
+                *start_at,
+                &rows,
+                members,
+            );
+
+            if with_minutes {
+                hour.children = minutes
+                    .iter()
+                    .map(|(start_at, members)| {
+                        segment(format(*start_at, step_format), *start_at, &rows, members)
+                    })
+                    .collect();
+            }
+
+            hour
+        })
+        .collect();
+
+    Some(root)
+}
+
+/// Serialize the tree of segments, `null` if none.
+pub fn to_json(root: Option<&Segment>) -> String {
+    serde_json::to_string(&root).expect("Failed to serialize the zoom segments")
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+
+    use super::*;
+
+    #[test]
+    fn test_compute() {
+        let span = |at: &str, status, duration| {
+            let mut span = Span::for_tests(
+                "https://example.org/_matrix/client/v3/keys/query",
+                status,
+                TimeDelta::milliseconds(duration),
+            );
+            span.start_at = DateTime::parse_from_rfc3339(at).unwrap();
+
+            span
+        };
+        let connection_id = "c".to_owned();
+        let spans = [
+            span("2026-01-01T10:59:30Z", Some(200), 1_000),
+            span("2026-01-01T11:00:10Z", Some(500), 1_000),
+            span("2026-01-01T10:59:50Z", Some(200), 30_000),
+            span("2026-01-01T11:01:00Z", None, 1_000),
+        ];
+        let displayed_spans = spans
+            .iter()
+            .enumerate()
+            .map(|(nth, span)| (&connection_id, nth as RequestId, span))
+            .collect::<Vec<_>>();
+        let smallest_start_at = spans[0].start_at.timestamp_millis();
+        let timezone = FixedOffset::east_opt(0).unwrap();
+        let root = compute(
+            &displayed_spans,
+            smallest_start_at,
+            timezone,
+            DEFAULT_RESOLUTION,
+        )
+        .unwrap();
+
+        assert_eq!(root.rows, [[0, 4]]);
+        assert_eq!(root.scale, 91_000);
+        assert_eq!(root.worst_status_family, "5");
+
+        let [before, after] = &root.children[..] else {
+            panic!("Expected 2 hours, got {:?}", root.children);
+        };
+
+        assert_eq!(before.label, "2026-01-01 10:00");
+        // The hour starts before the log: its bars start with the log.
+        assert_eq!(before.origin, 0);
+        // The long span overflows the hour, but fits the scale.
+        assert_eq!(before.scale, 50_000);
+        assert_eq!(before.rows, [[0, 1], [2, 3]]);
+        assert_eq!(before.worst_status_family, "2");
+
+        assert_eq!(after.origin, 30_000);
+        assert_eq!(after.rows, [[1, 2], [3, 4]]);
+        assert_eq!(
+            after
+                .children
+                .iter()
+                .map(|minute| (
+                    minute.label.as_str(),
+                    minute.spans,
+                    minute.worst_status_family.as_str()
+                ))
+                .collect::<Vec<_>>(),
+            [("11:00", 1, "5"), ("11:01", 1, "pending")]
+        );
+
+        // Finer steps, then hours only.
+        let root = compute(
+            &displayed_spans,
+            smallest_start_at,
+            timezone,
+            TimeDelta::seconds(10),
+        )
+        .unwrap();
+
+        assert_eq!(
+            root.children[0]
+                .children
+                .iter()
+                .map(|step| step.label.as_str())
+                .collect::<Vec<_>>(),
+            ["10:59:30", "10:59:50"]
+        );
+
+        let root = compute(
+            &displayed_spans,
+            smallest_start_at,
+            timezone,
+            TimeDelta::hours(1),
+        )
+        .unwrap();
+
+        assert_eq!(root.children.len(), 2);
+        assert!(root.children.iter().all(|hour| hour.children.is_empty()));
+        assert_eq!(parse_resolution("500ms"), None);
+        assert_eq!(parse_resolution("2h"), None);
+    }
+}
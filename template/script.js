@@ -0,0 +1,729 @@
+// Format the numbers like the `human` module of the generator does.
+function formatCount(count) {
+  return count.toLocaleString('en-US');
+}
+
+function formatBytes(bytes) {
+  const units = ['KiB', 'MiB', 'GiB', 'TiB'];
+
+  if (bytes < 1024) {
+    return `${bytes} B`;
+  }
+
+  let value = bytes / 1024;
+  let unit = 0;
+
+  while (value >= 1024 && unit < units.length - 1) {
+    value /= 1024;
+    unit += 1;
+  }
+
+  return `${value.toFixed(1)} ${units[unit]}`;
+}
+
+function formatDuration(milliseconds) {
+  const sign = milliseconds < 0 ? '-' : '';
+  const value = Math.abs(milliseconds);
+  const pad = (number) => String(Math.floor(number)).padStart(2, '0');
+
+  if (value < 1) {
+    return `${sign}${Math.round(value * 1000)}µs`;
+  } else if (value < 10) {
+    return `${sign}${value.toFixed(2)}ms`;
+  } else if (value < 1000) {
+    return `${sign}${Math.round(value)}ms`;
+  } else if (value < 10000) {
+    return `${sign}${(value / 1000).toFixed(2)}s`;
+  } else if (value < 60000) {
+    return `${sign}${(value / 1000).toFixed(1)}s`;
+  } else if (value < 3600000) {
+    return `${sign}${Math.floor(value / 60000)}m${pad(value % 60000 / 1000)}s`;
+  }
+
+  return `${sign}${Math.floor(value / 3600000)}h${pad(value % 3600000 / 60000)}m`;
+}
+
+// Draw the sparklines of the p95 duration per endpoint over time. Buckets
+// without any span break the line.
+for (const cell of document.querySelectorAll('.endpoint-stats td[data-sparkline]')) {
+  const values = cell.dataset.sparkline.split(',').map((value) => value === '' ? null : Number(value));
+
+  if (values.length < 2) {
+    continue;
+  }
+
+  const maximum = Math.max(1, ...values.filter((value) => value !== null));
+  const segments = [[]];
+
+  values.forEach((value, nth) => {
+    if (value === null) {
+      segments.push([]);
+    } else {
+      segments.at(-1).push(`${nth},${10 - value * 10 / maximum}`);
+    }
+  });
+
+  cell.innerHTML = `<svg class="sparkline" viewBox="0 0 ${values.length - 1} 10" preserveAspectRatio="none">${
+    segments.filter((points) => points.length > 0).map((points) => `<polyline points="${points.join(' ')}" />`).join('')
+  }</svg>`;
+  cell.title = `p95 per ${formatDuration(Number(cell.closest('table').dataset.bucketDuration))}, up to ${formatDuration(maximum)}`;
+}
+
+// Draw the requests in flight as a stacked area chart, one area per
+// endpoint kind.
+(() => {
+  const concurrency = JSON.parse(document.getElementById('concurrency').textContent);
+  const { resolution, kinds } = concurrency;
+
+  if (concurrency.samples.length === 0) {
+    return;
+  }
+
+  // A single sample is drawn as wide as its resolution.
+  const samples = concurrency.samples.length === 1
+    ? [concurrency.samples[0], concurrency.samples[0]]
+    : concurrency.samples;
+
+  const figure = document.querySelector('.concurrency');
+  const svg = figure.querySelector('svg');
+  const legend = figure.querySelector('figcaption ul');
+  const namespace = 'http://www.w3.org/2000/svg';
+
+  // Cumulated counts, per kind, from the bottom of the stack.
+  let bottoms = samples.map(() => 0);
+  const maximum = Math.max(1, ...samples.map((counts) => counts.reduce((sum, count) => sum + count, 0)));
+
+  svg.setAttribute('viewBox', `0 0 ${samples.length - 1} ${maximum}`);
+
+  kinds.forEach((kind, index) => {
+    const tops = samples.map((counts, sample) => bottoms[sample] + counts[index]);
+
+    if (tops.some((top, sample) => top !== bottoms[sample])) {
+      const points = [
+        ...tops.map((top, sample) => `${sample},${maximum - top}`),
+        ...bottoms.map((bottom, sample) => `${sample},${maximum - bottom}`).reverse(),
+      ];
+
+      const polygon = document.createElementNS(namespace, 'polygon');
+      polygon.dataset.kind = kind;
+      polygon.setAttribute('points', points.join(' '));
+      svg.append(polygon);
+
+      const item = document.createElement('li');
+      item.dataset.kind = kind;
+      item.textContent = kind;
+      legend.append(item);
+    }
+
+    bottoms = tops;
+  });
+
+  // Mark the app lifecycle events, if any, with vertical lines.
+  const events = JSON.parse(document.getElementById('lifecycle').textContent);
+  const markers = figure.querySelector('.markers');
+
+  for (const { state, at, log_line: logLine } of events) {
+    const x = concurrency.samples.length === 1 ? 0 : (at - concurrency.start_at) / resolution;
+
+    const line = document.createElementNS(namespace, 'line');
+    line.dataset.state = state;
+    line.setAttribute('x1', x);
+    line.setAttribute('x2', x);
+    line.setAttribute('y1', 0);
+    line.setAttribute('y2', maximum);
+    svg.append(line);
+
+    const marker = document.createElement('li');
+    marker.dataset.state = state;
+    marker.style.left = `${x / (samples.length - 1) * 100}%`;
+    marker.textContent = state;
+    marker.title = `${state} at log line ${logLine}`;
+    markers.append(marker);
+  }
+
+  // Show the peak and the resolution, as the chart has no axis.
+  legend.insertAdjacentHTML('beforeend', `<li>peak ${formatCount(maximum)}, per ${formatDuration(resolution)}</li>`);
+  figure.hidden = false;
+})();
+
+// Draw the bytes transferred per bucket as stacked bars, the received bytes
+// above the sent ones, and tell which kinds of endpoints transferred them.
+(() => {
+  const { resolution, sent, received, per_kind: perKind } = JSON.parse(document.getElementById('bandwidth').textContent);
+  const totals = received.map((bytes, bucket) => bytes + sent[bucket]);
+  const maximum = Math.max(0, ...totals);
+
+  if (maximum === 0) {
+    return;
+  }
+
+  const figure = document.querySelector('.bandwidth');
+  const svg = figure.querySelector('svg');
+  const legend = figure.querySelector('figcaption ul');
+  const namespace = 'http://www.w3.org/2000/svg';
+
+  svg.setAttribute('viewBox', `0 0 ${totals.length} ${maximum}`);
+
+  for (const [direction, bottoms, tops] of [['sent', totals.map(() => 0), sent], ['received', sent, totals]]) {
+    const path = document.createElementNS(namespace, 'path');
+    path.dataset.direction = direction;
+    path.setAttribute('d', tops.map((top, bucket) => `M${bucket} ${maximum - bottoms[bucket]}V${maximum - top}h1V${maximum - bottoms[bucket]}Z`).join(''));
+    svg.append(path);
+
+    const total = (direction === 'sent' ? sent : received).reduce((sum, bytes) => sum + bytes, 0);
+    legend.insertAdjacentHTML('beforeend', `<li data-direction="${direction}">${direction} ${formatBytes(total)}</li>`);
+  }
+
+  // The kinds with the most bytes first, and the one dominating the peak.
+  const peak = totals.indexOf(maximum);
+  const kinds = Object.entries(perKind)
+    .map(([kind, buckets]) => [kind, buckets.reduce((sum, bytes) => sum + bytes, 0), buckets[peak]])
+    .sort(([, left], [, right]) => right - left);
+  const [peakKind] = kinds.reduce((dominant, kind) => (kind[2] > dominant[2] ? kind : dominant), kinds[0]);
+
+  for (const [kind, total] of kinds) {
+    legend.insertAdjacentHTML('beforeend', `<li data-kind="${kind}">${kind} ${formatBytes(total)}</li>`);
+  }
+
+  legend.insertAdjacentHTML('beforeend', `<li>peak ${formatBytes(maximum)}, per ${formatDuration(resolution)}, mostly ${peakKind}</li>`);
+  figure.hidden = false;
+})();
+
+// Status families of the toggles above the table, and their labels.
+const STATUS_FAMILIES = [
+  ['2', '2xx'],
+  ['3', '3xx'],
+  ['4', '4xx'],
+  ['5', '5xx'],
+  ['error', 'error'],
+  ['cancelled', 'cancelled'],
+  ['pending', 'pending'],
+];
+
+// Whether a row matches the filter of the `rowfilter` events. `text` is the
+// searchable text of the row, in lower case.
+function matchesRowFilter(filter, endpoint, statusFamily, text) {
+  return (filter.cell === null || (endpoint === filter.cell.endpoint && statusFamily === filter.cell.statusFamily))
+    && !filter.hiddenStatusFamilies.has(statusFamily)
+    && (filter.text === '' || text.includes(filter.text));
+}
+
+// Compare two sort keys in `direction`, 1 or -1: numbers as numbers, the
+// rest as text, with the missing values last either way.
+const sortKeyCollator = new Intl.Collator(undefined, { numeric: true });
+
+function compareSortKeys(left, right, direction) {
+  if (left === null || left === undefined || right === null || right === undefined) {
+    return (left === null || left === undefined) - (right === null || right === undefined);
+  }
+
+  return direction * (typeof left === 'number' && typeof right === 'number'
+    ? left - right
+    : sortKeyCollator.compare(String(left), String(right)));
+}
+
+// Filter the rows: by endpoint and status family, from the cells of the
+// status matrix, where clicking the selected cell again removes the filter;
+// and by free text and status families, from the form above the table.
+(() => {
+  const filter = { cell: null, text: '', hiddenStatusFamilies: new Set() };
+  const dispatch = () => document.dispatchEvent(new CustomEvent('rowfilter', { detail: filter }));
+  const matrix = document.querySelector('.status-matrix');
+  let selected = null;
+
+  matrix?.addEventListener('click', (event) => {
+    const button = event.target.closest('button[data-endpoint]');
+
+    if (button === null) {
+      return;
+    }
+
+    selected?.setAttribute('aria-pressed', 'false');
+    selected = selected === button ? null : button;
+    selected?.setAttribute('aria-pressed', 'true');
+
+    filter.cell = selected && { endpoint: selected.dataset.endpoint, statusFamily: selected.dataset.statusFamily };
+    dispatch();
+  });
+
+  const form = document.querySelector('form.filters');
+
+  if (form !== null) {
+    const toggles = form.querySelector('.status-families');
+
+    for (const [statusFamily, label] of STATUS_FAMILIES) {
+      const toggle = document.createElement('button');
+      toggle.type = 'button';
+      toggle.textContent = label;
+      toggle.dataset.statusFamily = statusFamily;
+      toggle.setAttribute('aria-pressed', 'true');
+      toggle.addEventListener('click', () => {
+        const shown = filter.hiddenStatusFamilies.has(statusFamily);
+        filter.hiddenStatusFamilies[shown ? 'delete' : 'add'](statusFamily);
+        toggle.setAttribute('aria-pressed', String(shown));
+        dispatch();
+      });
+      toggles?.append(toggle);
+    }
+
+    form.querySelector('input[type="search"]')?.addEventListener('input', (event) => {
+      filter.text = event.target.value.trim().toLowerCase();
+      dispatch();
+    });
+    form.addEventListener('submit', (event) => event.preventDefault());
+    form.hidden = false;
+  }
+
+  // The virtual table filters its own rows.
+  if (JSON.parse(document.getElementById('dataset').textContent) !== null) {
+    return;
+  }
+
+  const texts = new Map();
+
+  document.addEventListener('rowfilter', ({ detail: filter }) => {
+    for (const row of document.querySelectorAll('main > table > tbody > tr[data-endpoint]')) {
+      if (!texts.has(row)) {
+        texts.set(row, row.textContent.toLowerCase());
+      }
+
+      row.hidden = !matchesRowFilter(filter, row.dataset.endpoint, row.dataset.statusFamily, texts.get(row));
+    }
+  });
+})();
+
+// Sort the rows by a column, from the buttons of the headers: ascending,
+// descending, then back to the order of the generator.
+(() => {
+  const table = document.querySelector('main > table');
+  const headers = table?.querySelectorAll(':scope > thead th[class]') ?? [];
+  let sort = null;
+
+  for (const header of headers) {
+    const column = header.className;
+    const button = document.createElement('button');
+    button.type = 'button';
+    button.className = 'sort';
+    button.title = 'Sort by this column';
+    button.addEventListener('click', () => {
+      if (sort?.column !== column) {
+        sort = { column, direction: 1 };
+      } else if (sort.direction === 1) {
+        sort = { column, direction: -1 };
+      } else {
+        sort = null;
+      }
+
+      for (const other of headers) {
+        other.removeAttribute('aria-sort');
+      }
+
+      if (sort !== null) {
+        header.setAttribute('aria-sort', sort.direction === 1 ? 'ascending' : 'descending');
+      }
+
+      document.dispatchEvent(new CustomEvent('rowsort', { detail: sort }));
+    });
+    header.append(button);
+  }
+
+  // The virtual table sorts its own rows.
+  if (table === null || JSON.parse(document.getElementById('dataset').textContent) !== null) {
+    return;
+  }
+
+  const key = (row, column) => {
+    if (column === 'duration') {
+      return Date.parse(row.dataset.endIso) - Date.parse(row.dataset.startIso);
+    }
+
+    const cell = row.querySelector(`:scope > td.${column}`);
+
+    const number = cell?.dataset.bytes ?? cell?.dataset.milliseconds;
+
+    return number !== undefined ? Number(number) : cell?.textContent.trim() || null;
+  };
+  const tbodies = [...table.querySelectorAll(':scope > tbody')].map((tbody) => [tbody, [...tbody.children]]);
+
+  document.addEventListener('rowsort', ({ detail: sort }) => {
+    for (const [tbody, rows] of tbodies) {
+      // The gaps and the headers of the sections only make sense in the
+      // order of the generator.
+      tbody.toggleAttribute('data-sorted', sort !== null);
+
+      if (sort === null) {
+        tbody.append(...rows);
+        continue;
+      }
+
+      const keys = new Map(rows.filter((row) => row.dataset.endpoint !== undefined).map((row) => [row, key(row, sort.column)]));
+      tbody.append(...[...keys.keys()].sort((left, right) => compareSortKeys(keys.get(left), keys.get(right), sort.direction)));
+    }
+  });
+})();
+
+// Collapse the sections of the connections with `--origin per-connection`,
+// from the buttons of their headers.
+(() => {
+  for (const button of document.querySelectorAll('main > table > tbody > tr.origin > th > button')) {
+    button.addEventListener('click', () => {
+      const tbody = button.closest('tbody');
+      const collapsed = tbody.toggleAttribute('data-collapsed');
+      button.setAttribute('aria-expanded', String(!collapsed));
+    });
+  }
+})();
+
+// Zoom on the segments of the timeline precomputed by the generator: the
+// whole log, its hours, and their minutes. Only the rows starting in the
+// segment are shown, and the bars are rescaled to it.
+(() => {
+  const root = JSON.parse(document.getElementById('zoom').textContent);
+  const nav = document.querySelector('nav.zoom');
+
+  if (root === null || root.children === undefined || nav === null) {
+    return;
+  }
+
+  const tbody = document.querySelector('main > table > tbody');
+  const rows = tbody.querySelectorAll(':scope > tr[data-endpoint]');
+  const button = (segment, path, pressed) => {
+    const element = document.createElement('button');
+    element.type = 'button';
+    element.textContent = segment.label;
+    element.title = `${formatCount(segment.spans)} spans`;
+    element.dataset.statusFamily = segment.worst_status_family;
+    element.setAttribute('aria-pressed', String(pressed));
+    element.addEventListener('click', () => zoom(path));
+
+    return element;
+  };
+  const zoom = (path) => {
+    const segment = path.at(-1);
+    const shown = new Uint8Array(rows.length);
+
+    for (const [start, end] of segment.rows) {
+      shown.fill(1, start, end);
+    }
+
+    rows.forEach((row, index) => {
+      row.toggleAttribute('data-zoomed-out', shown[index] === 0);
+    });
+    tbody.style.setProperty('--zoom-origin', segment.origin);
+    tbody.style.setProperty('--end-at', segment.scale);
+
+    // The path to the segment, then the children of the segment, or those
+    // of its parent if it has none.
+    const parent = segment.children === undefined ? path.slice(0, -1) : path;
+    const children = parent.at(-1).children.map((child) => button(child, [...parent, child], child === segment));
+    nav.replaceChildren(
+      ...parent.map((ancestor, nth) => button(ancestor, parent.slice(0, nth + 1), ancestor === segment)),
+      document.createElement('hr'),
+      ...children,
+    );
+  };
+
+  zoom([root]);
+  nav.hidden = false;
+})();
+
+// Draw the time axis in the header of the timeline, and a ruler following the
+// pointer with the wall-clock time, in the timezone of the report and in UTC.
+(() => {
+  const ticks = JSON.parse(document.getElementById('ticks').textContent);
+  const table = document.querySelector('main > table');
+  const header = table?.querySelector(':scope > thead th.duration');
+
+  if (ticks === null || header === null || header === undefined) {
+    return;
+  }
+
+  const tbody = table.querySelector(':scope > tbody');
+  const axis = document.createElement('ol');
+  axis.className = 'ticks';
+  axis.setAttribute('aria-hidden', 'true');
+
+  for (const { at, label } of ticks.ticks) {
+    const tick = document.createElement('li');
+    tick.style.setProperty('--at', at);
+    tick.textContent = label;
+    axis.append(tick);
+  }
+
+  header.append(axis);
+
+  // The axis follows the zoom of the timeline.
+  const scale = () => {
+    const style = getComputedStyle(tbody);
+
+    for (const name of ['--zoom-origin', '--end-at']) {
+      header.style.setProperty(name, style.getPropertyValue(name) || null);
+    }
+  };
+  new MutationObserver(scale).observe(tbody, { attributes: true, attributeFilter: ['style'] });
+  scale();
+
+  const origin = Date.parse(table.dataset.startAt);
+  const [, sign, hours, minutes] = table.dataset.startAt.match(/([+-])(\d{2}):(\d{2})$/) ?? [];
+  const offset = sign === undefined ? 0 : (sign === '-' ? -1 : 1) * (Number(hours) * 60 + Number(minutes)) * 60000;
+  const clock = (at) => new Date(at).toISOString().slice(11, 23);
+
+  const ruler = document.createElement('div');
+  ruler.className = 'ruler';
+  ruler.hidden = true;
+  document.body.append(ruler);
+
+  tbody.addEventListener('pointermove', (event) => {
+    const cell = event.target.closest('td.duration');
+
+    if (cell === null) {
+      ruler.hidden = true;
+
+      return;
+    }
+
+    // The axis is as wide as the bars can be.
+    const { left, width } = axis.getBoundingClientRect();
+    const style = getComputedStyle(tbody);
+    const zoomOrigin = Number(style.getPropertyValue('--zoom-origin') || 0);
+    const endAt = Number(style.getPropertyValue('--end-at'));
+    const at = origin + zoomOrigin + (event.clientX - left) / width * endAt;
+
+    ruler.style.left = `${event.clientX}px`;
+    ruler.textContent = offset === 0 ? `${clock(at)} UTC` : `${clock(at + offset)} (${clock(at)} UTC)`;
+    ruler.hidden = false;
+  });
+  tbody.addEventListener('pointerleave', () => {
+    ruler.hidden = true;
+  });
+})();
+
+// Render a windowed table from the dataset, if any: only the visible rows,
+// plus some overscan, exist in the DOM.
+(() => {
+  const dataset = JSON.parse(document.getElementById('dataset').textContent);
+
+  if (dataset === null) {
+    return;
+  }
+
+  const { strings, columns, summaries } = dataset;
+  const origin = Date.parse(dataset.meta.start_at);
+  const toIso = (offset) => new Date(origin + offset).toISOString();
+  // The start times are displayed in the timezone of `meta.start_at`, see
+  // `--timezone`.
+  const [, sign, hours, minutes] = (dataset.meta.start_at ?? '').match(/([+-])(\d{2}):(\d{2})$/) ?? [];
+  const timezoneOffset = sign === undefined ? 0 : (sign === '-' ? -1 : 1) * (Number(hours) * 60 + Number(minutes)) * 60000;
+  const toLocalIso = (offset) => new Date(origin + offset + timezoneOffset).toISOString().replace('Z', sign === undefined ? '+00:00' : `${sign}${hours}:${minutes}`);
+  const initialSyncs = new Set(summaries.initial_syncs.map(({ connection_id, request_id }) => `${connection_id}-${request_id}`));
+  const tbody = document.querySelector('main > table > tbody');
+  const selectedColumns = document.querySelector('main > table').dataset.columns.split(' ');
+  const statusFamily = (index) => {
+    if (columns.response_log_line[index] === null) {
+      return 'pending';
+    }
+
+    if (columns.status[index] === null) {
+      return columns.error[index] === null ? 'cancelled' : 'error';
+    }
+
+    return String(Math.floor(columns.status[index] / 100));
+  };
+  // Indices of the spans matching the filter, if any, in the sorted order.
+  let visible = columns.request_id.map((_, index) => index);
+  let filter = null;
+  let sort = null;
+  // The searchable text of each span, in lower case, computed on the first
+  // search.
+  let texts = null;
+  const text = (index) => [
+    strings.connections[columns.connection[index]],
+    columns.request_id[index],
+    strings.methods[columns.method[index]],
+    strings.uris[columns.uri[index]],
+    columns.status_label[index] ?? statusFamily(index),
+    columns.errcode[index],
+    columns.error[index],
+    columns.pos[index],
+    columns.txn_id[index],
+    columns.fields[index],
+    columns.frames[index],
+  ].join(' ').toLowerCase();
+  // From the names of the displayed columns to the names of the columns of
+  // the dataset.
+  const sortColumns = { request: 'request_id', start: 'start_at', path: 'uri', request_size: 'request_bytes', response_size: 'response_bytes' };
+  const sortKey = (column, index) => {
+    const name = sortColumns[column] ?? column;
+    const value = columns[name]?.[index] ?? null;
+    const schema = dataset.schema.columns.find((schema) => schema.name === name);
+
+    return schema?.type === 'index' && value !== null ? strings[schema.strings][value] : value;
+  };
+  const update = () => {
+    visible = columns.request_id.map((_, index) => index);
+
+    if (filter !== null) {
+      texts ??= filter.text === '' ? null : visible.map(text);
+      visible = visible.filter((index) => matchesRowFilter(
+        filter,
+        strings.endpoints[columns.endpoint[index]],
+        statusFamily(index),
+        texts?.[index] ?? '',
+      ));
+    }
+
+    if (sort !== null) {
+      const keys = new Map(visible.map((index) => [index, sortKey(sort.column, index)]));
+      visible.sort((left, right) => compareSortKeys(keys.get(left), keys.get(right), sort.direction));
+    }
+
+    render();
+  };
+  const overscan = 20;
+  let rowHeight = 28;
+
+  const escape = (value) => String(value ?? '').replace(
+    /[&<>"]/g,
+    (character) => ({ '&': '&amp;', '<': '&lt;', '>': '&gt;', '"': '&quot;' })[character],
+  );
+
+  // Like `size_cell` of the generator.
+  const sizeCell = (name, logged, bytes) => bytes === null
+    ? `<td class="${name}">${escape(logged)}</td>`
+    : `<td class="${name}" data-bytes="${bytes}" title="${escape(logged)}">${formatBytes(bytes)}</td>`;
+
+  // Like `setup_cell` of the generator.
+  const setupCell = (name, milliseconds) => milliseconds === null
+    ? `<td class="${name}"></td>`
+    : `<td class="${name}" data-milliseconds="${milliseconds}">${formatDuration(Math.round(milliseconds))}</td>`;
+
+  const row = (index) => {
+    const connection = escape(strings.connections[columns.connection[index]]);
+    const requestId = columns.request_id[index];
+    const status = columns.status[index];
+    const uri = strings.uris[columns.uri[index]];
+    const duration = columns.duration[index];
+    const responseLogLine = columns.response_log_line[index];
+    const warnings = columns.warnings[index]?.split('\n') ?? [];
+    const serverTiming = columns.server_timing[index];
+    const serverDuration = columns.server_duration[index];
+    const appState = columns.app_state[index];
+    const durationBand = columns.duration_band[index];
+    const latencyHeat = columns.latency_heat[index];
+    const restartedAs = columns.restarted_as[index];
+    const retryOf = columns.retry_of[index];
+    const rateLimited = columns.rate_limited[index];
+    const timeout = columns.timeout[index];
+    const intermediary = columns.intermediary[index];
+    const syncOverhead = columns.sync_overhead_label[index];
+    const domain = escape(strings.domains[columns.domain[index]]);
+    const path = escape(uri.slice(columns.path_start[index] ?? uri.length));
+
+    const cells = {
+      connection: `<td class="connection"><code>${connection}</code></td>`,
+      parent: columns.frames[index] === null
+        ? `<td class="parent"><code>${escape(columns.parent[index])}</code></td>`
+        : `<td class="parent"><details><summary><code>${escape(columns.parent[index] ?? columns.frames[index].split('\n').at(-1).split('{')[0])}</code></summary><ol class="frames">${columns.frames[index].split('\n').map((frame) => `<li><code>${escape(frame)}</code></li>`).join('')}</ol></details></td>`,
+      source: `<td class="source"><code>${escape(columns.source[index])}</code></td>`,
+      request: `<td class="request"><a href="#${connection}-${requestId}" title="Permalink to this line"><code>${requestId}</code></a></td>`,
+      start: `<td class="start"><time datetime="${toLocalIso(columns.start_at[index])}" title="${toLocalIso(columns.start_at[index])}">${toLocalIso(columns.start_at[index]).slice(11, 23)}</time></td>`,
+      status: `<td class="status" data-status-family="${statusFamily(index)}"><span title="${escape(columns.status_tooltip[index] ?? (responseLogLine === null ? 'No response in the log' : 'Cancelled'))}">${escape(columns.status_label[index] ?? (responseLogLine === null ? 'pending' : '×'))}</span></td>`,
+      error: `<td class="error" title="${escape(columns.error[index])}">${escape(columns.error[index])}</td>`,
+      method: `<td class="method"><code>${escape(strings.methods[columns.method[index]])}</code></td>`,
+      traffic_class: `<td class="traffic_class">${escape(columns.traffic_class[index])}</td>`,
+      category: `<td class="category">${escape(columns.category[index])}</td>`,
+      domain: `<td class="domain" title="${domain}">${domain}</td>`,
+      path: `<td class="path" title="${path}">${path}</td>`,
+      pos: `<td class="pos"><code>${escape(columns.pos[index])}</code></td>`,
+      iteration: `<td class="iteration">${columns.iteration[index] > 0 ? columns.iteration[index] : ''}</td>`,
+      timeout: `<td class="timeout">${timeout === null ? '' : formatDuration(timeout)}</td>`,
+      txn_id: `<td class="txn_id"><code>${escape(columns.txn_id[index])}</code></td>`,
+      fields: `<td class="fields">${(columns.fields[index] ?? '').split('\n').filter((field) => field !== '').map((field) => `<code>${escape(field)}</code>`).join(' ')}</td>`,
+      request_size: sizeCell('request_size', columns.request_size[index], columns.request_bytes[index]),
+      response_size: sizeCell('response_size', columns.response_size[index], columns.response_bytes[index]),
+      retry_after: `<td class="retry_after">${escape(columns.retry_after_label[index])}</td>`,
+      retries: `<td class="retries">${columns.retries[index] > 0 ? columns.retries[index] : ''}</td>`,
+      concurrency: `<td class="concurrency">${columns.concurrency[index] ?? ''}</td>`,
+      dns: setupCell('dns', columns.dns[index]),
+      connect: columns.connect[index] === null && columns.connection_reused[index] === true
+        ? '<td class="connect" title="Idle connection of the pool">reused</td>'
+        : setupCell('connect', columns.connect[index]),
+      tls: setupCell('tls', columns.tls[index]),
+      ttfb: setupCell('ttfb', columns.ttfb[index]),
+      duration: `<td class="duration">
+        <div class="span" style="--start-at: ${columns.start_at[index]}; --duration: ${duration}; --lane: ${columns.lane[index] ?? 0}">${serverDuration === null ? '' : `<div class="server" style="--server-duration: ${serverDuration}" title="server: ${formatDuration(serverDuration)}, network/queueing: ${formatDuration(duration - serverDuration)}"></div>`}<span>${responseLogLine === null ? '<em>pending</em>' : duration > 0 ? formatDuration(duration) : '<em>cancelled</em>'}</span></div>${rateLimited === null ? '' : `<div class="rate-limited" style="--start-at: ${columns.start_at[index] + duration}; --duration: ${rateLimited}" title="rate limited for ${formatDuration(rateLimited)}"></div>`}
+        <details>
+          <summary><span class="hidden">information</span></summary>
+          <ul>
+            <li>Request log line number: ${columns.request_log_line[index]}</li>
+            <li>Response log line number: ${responseLogLine ?? '(none)'}</li>
+            ${appState === null ? '' : `<li>App state: ${escape(appState)}</li>`}
+            ${intermediary === null ? '' : `<li>Intermediary: ${escape(intermediary)}</li>`}
+            ${serverTiming === null ? '' : `<li>Server-Timing: <code>${escape(serverTiming)}</code>${serverDuration === null ? '' : ` (server: ${formatDuration(serverDuration)}, network/queueing: ${formatDuration(duration - serverDuration)})`}</li>`}
+            ${syncOverhead === null ? '' : `<li>${escape(syncOverhead)}</li>`}
+            ${[['Request body', columns.request_body[index]], ['Response body', columns.response_body[index]]].filter(([, body]) => body !== null).map(([label, body]) => `<li><details class="body"><summary>${label}</summary><pre><code>${escape(body)}</code></pre></details></li>`).join('')}
+            ${warnings.map((warning) => `<li class="warning">${escape(warning)}</li>`).join('')}
+          </ul>
+        </details>
+      </td>`,
+    };
+
+    return `<tr id="${connection}-${requestId}" data-endpoint="${escape(strings.endpoints[columns.endpoint[index]])}" data-status-family="${statusFamily(index)}" data-anomalies="${escape(columns.anomalies[index])}" data-start-iso="${toIso(columns.start_at[index])}" data-end-iso="${toIso(columns.start_at[index] + duration)}"${initialSyncs.has(`${strings.connections[columns.connection[index]]}-${requestId}`) ? ' data-initial-sync' : ''}${restartedAs === null ? '' : ` data-restarted-as="${escape(restartedAs)}"`}${retryOf === null ? '' : ` data-retry-of="${retryOf}" title="Retry of REQ-${retryOf}, ${formatDuration(columns.retry_latency[index])} since the first attempt"`}${columns.pos_stalled[index] ? ' data-pos-stalled="true"' : ''}${columns.pos_reset[index] ? ' data-pos-reset="true"' : ''}${columns.iteration[index] > 0 ? ` data-iteration="${columns.iteration[index]}" data-iteration-parity="${columns.iteration[index] % 2 === 0 ? 'even' : 'odd'}"` : ''}${durationBand === null ? '' : ` data-duration-band="${durationBand}"`}${latencyHeat === null ? '' : ` data-latency-heat="${latencyHeat}"`}${warnings.length > 0 ? ` data-warnings="${warnings.length}"` : ''}${columns.slow[index] ? ' class="slow"' : ''}>
+      ${selectedColumns.map((column) => cells[column]).join('')}
+    </tr>`;
+  };
+
+  const tbodyTop = () => tbody.getBoundingClientRect().top + window.scrollY;
+
+  const render = () => {
+    const first = Math.max(0, Math.floor((window.scrollY - tbodyTop()) / rowHeight) - overscan);
+    const last = Math.min(visible.length, first + Math.ceil(window.innerHeight / rowHeight) + 2 * overscan);
+    let html = `<tr class="spacer" style="height: ${first * rowHeight}px"></tr>`;
+
+    for (let nth = first; nth < last; nth += 1) {
+      html += row(visible[nth]);
+    }
+
+    html += `<tr class="spacer" style="height: ${(visible.length - last) * rowHeight}px"></tr>`;
+    tbody.innerHTML = html;
+  };
+
+  // Permalinks target rows that may not be rendered yet.
+  const scrollToHash = () => {
+    const nth = visible.findIndex(
+      (index) => `#${strings.connections[columns.connection[index]]}-${columns.request_id[index]}` === decodeURIComponent(location.hash),
+    );
+
+    if (nth !== -1) {
+      window.scrollTo(0, tbodyTop() + nth * rowHeight - window.innerHeight / 2);
+      render();
+    }
+  };
+
+  let scheduled = false;
+
+  window.addEventListener('scroll', () => {
+    if (!scheduled) {
+      scheduled = true;
+      requestAnimationFrame(() => {
+        scheduled = false;
+        render();
+      });
+    }
+  });
+  window.addEventListener('resize', render);
+  window.addEventListener('hashchange', scrollToHash);
+  document.addEventListener('rowfilter', ({ detail }) => {
+    filter = detail;
+    update();
+  });
+  document.addEventListener('rowsort', ({ detail }) => {
+    sort = detail;
+    update();
+  });
+
+  render();
+  rowHeight = tbody.querySelector('tr:not(.spacer)')?.getBoundingClientRect().height || rowHeight;
+  render();
+  scrollToHash();
+})();
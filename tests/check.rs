@@ -0,0 +1,39 @@
+//! Drive the binary with `check`, whose exit code gates a CI job.
+
+use std::process::Command;
+
+fn check(budgets: &[&str]) -> (Option<i32>, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_network-viewer"))
+        .arg("check")
+        .arg("fixtures/mixed-traffic.log")
+        .args(budgets)
+        .output()
+        .unwrap();
+
+    (
+        output.status.code(),
+        String::from_utf8(output.stdout).unwrap() + &String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+#[test]
+fn test_check() {
+    let (code, output) = check(&["--max-error-rate", "100%"]);
+    assert_eq!(code, Some(0), "{output}");
+    assert!(
+        output.contains("Error rate: ") && output.contains("(budget: 100.00%)"),
+        "{output}"
+    );
+
+    let (code, output) = check(&["--max-p95-ms", "0"]);
+    assert_eq!(code, Some(4), "{output}");
+    assert!(
+        output.contains("above the budget of `--max-p95-ms` (0ms)"),
+        "{output}"
+    );
+    assert!(output.contains("Failed because of the budgets of `check`"));
+
+    // `check` writes no report.
+    let (code, output) = check(&["--max-unfinished", "0", "-o", "report.html"]);
+    assert_eq!(code, Some(1), "{output}");
+}
@@ -0,0 +1,56 @@
+//! Drive the binary into each of its exit codes, documented by `--help`.
+
+use std::{env, fs, process::Command};
+
+fn run(args: &[&str]) -> (Option<i32>, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_network-viewer"))
+        .args(args)
+        .output()
+        .unwrap();
+
+    (
+        output.status.code(),
+        String::from_utf8_lossy(&output.stderr).into_owned(),
+    )
+}
+
+#[test]
+fn test_exit_codes() {
+    let (code, stderr) = run(&["fixtures/session.log", "-"]);
+    assert_eq!(code, Some(0), "{stderr}");
+
+    let (code, stderr) = run(&["--unknown", "fixtures/session.log", "-"]);
+    assert_eq!(code, Some(1), "{stderr}");
+    assert!(stderr.contains("See `--help` for the usage"), "{stderr}");
+
+    // A corrupt line is skipped, unless with `--strict`.
+    let path = env::temp_dir().join(format!(
+        "network-viewer-exit-codes-{}.log",
+        std::process::id()
+    ));
+    let mut log = fs::read("fixtures/session.log").unwrap();
+    log.extend(b"2024-06-01T10:00:40Z INFO caf\xe9\n");
+    fs::write(&path, log).unwrap();
+    let log_path = path.to_str().unwrap();
+
+    let (code, stderr) = run(&[log_path, "-"]);
+    assert_eq!(code, Some(0), "{stderr}");
+
+    let (code, stderr) = run(&["--strict", log_path, "-"]);
+    assert_eq!(code, Some(2), "{stderr}");
+    assert!(stderr.contains("Failed because of `--strict`"), "{stderr}");
+
+    // A CSV export can't be re-imported.
+    fs::write(&path, "status,duration_ms\n200,120\n").unwrap();
+
+    let (code, stderr) = run(&[log_path, "-"]);
+    assert_eq!(code, Some(2), "{stderr}");
+
+    fs::remove_file(&path).unwrap();
+
+    let (code, stderr) = run(&["fixtures/missing.log", "-"]);
+    assert_eq!(code, Some(3), "{stderr}");
+
+    let (code, stderr) = run(&["check", "--max-p95-ms", "0", "fixtures/session.log"]);
+    assert_eq!(code, Some(4), "{stderr}");
+}
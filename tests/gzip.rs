@@ -0,0 +1,59 @@
+//! Drive the binary with gzipped log files, decompressed on the fly.
+
+use std::{env, fs, process::Command};
+
+fn run(arguments: &[&str]) -> (bool, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_network-viewer"))
+        .args(arguments)
+        .output()
+        .unwrap();
+
+    (
+        output.status.success(),
+        String::from_utf8(output.stdout).unwrap() + &String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+#[test]
+fn test_gzipped_log() {
+    let directory = env::temp_dir().join(format!("network-viewer-gzip-{}", std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    let output_path = directory.join("report.html");
+    let output_path = output_path.to_str().unwrap();
+
+    let (success, plain) = run(&["fixtures/mixed-traffic.log", output_path]);
+    assert!(success, "{plain}");
+    let plain_report = fs::read_to_string(output_path).unwrap();
+
+    let (success, gzipped) = run(&["fixtures/mixed-traffic.log.gz", output_path]);
+    assert!(success, "{gzipped}");
+    let gzipped_report = fs::read_to_string(output_path).unwrap();
+
+    let matched_lines = |summary: &str| {
+        summary
+            .lines()
+            .find(|line| line.starts_with("Number of matched lines"))
+            .map(ToOwned::to_owned)
+    };
+    assert!(matched_lines(&plain).is_some());
+    assert_eq!(matched_lines(&gzipped), matched_lines(&plain));
+    assert_eq!(
+        gzipped_report.matches("<tr id=").count(),
+        plain_report.matches("<tr id=").count()
+    );
+
+    // A truncated stream is an error naming the file, not a panic.
+    let gzipped = fs::read("fixtures/mixed-traffic.log.gz").unwrap();
+    let truncated_path = directory.join("truncated.log.gz");
+    fs::write(&truncated_path, &gzipped[..gzipped.len() / 2]).unwrap();
+
+    let (success, error) = run(&[truncated_path.to_str().unwrap(), output_path]);
+    assert!(!success);
+    assert!(error.contains("truncated.log.gz"), "{error}");
+    assert!(
+        error.contains("corrupt gzip stream, around byte"),
+        "{error}"
+    );
+
+    fs::remove_dir_all(&directory).unwrap();
+}
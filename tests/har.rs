@@ -0,0 +1,143 @@
+//! Drive the binary with `--format har`, whose export must be a valid HTTP
+//! Archive 1.2, with the fields required by the specification, to be opened
+//! by the browser devtools.
+
+use std::process::Command;
+
+use chrono::DateTime;
+use serde_json::Value;
+
+fn export(format: &str) -> Value {
+    let output = Command::new(env!("CARGO_BIN_EXE_network-viewer"))
+        .args(["--format", format, "fixtures/session.log", "-"])
+        .output()
+        .unwrap();
+
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+/// A field, and whether its value has the expected type.
+type Field<'a> = (&'a str, fn(&Value) -> bool);
+
+/// Assert that `object` has the fields, of the expected types.
+fn assert_fields(object: &Value, fields: &[Field<'_>]) {
+    for (name, is_valid) in fields {
+        assert!(
+            object.get(name).is_some_and(is_valid),
+            "`{name}` is missing or invalid in {object}"
+        );
+    }
+}
+
+#[test]
+fn test_har_export() {
+    let har = export("har");
+    let log = &har["log"];
+
+    assert_eq!(log["version"], "1.2");
+    assert_fields(
+        &log["creator"],
+        &[("name", Value::is_string), ("version", Value::is_string)],
+    );
+
+    let pages = log["pages"].as_array().unwrap();
+
+    for page in pages {
+        assert_fields(
+            page,
+            &[
+                ("startedDateTime", Value::is_string),
+                ("id", Value::is_string),
+                ("title", Value::is_string),
+                ("pageTimings", Value::is_object),
+            ],
+        );
+    }
+
+    // An entry per span.
+    let entries = log["entries"].as_array().unwrap();
+
+    assert_eq!(
+        entries.len(),
+        export("json")["spans"].as_array().unwrap().len()
+    );
+
+    for entry in entries {
+        assert_fields(
+            entry,
+            &[
+                ("startedDateTime", Value::is_string),
+                ("time", Value::is_number),
+                ("request", Value::is_object),
+                ("response", Value::is_object),
+                ("cache", Value::is_object),
+                ("timings", Value::is_object),
+            ],
+        );
+        assert!(
+            DateTime::parse_from_rfc3339(entry["startedDateTime"].as_str().unwrap()).is_ok(),
+            "{entry}"
+        );
+        assert!(
+            pages.iter().any(|page| page["id"] == entry["pageref"]),
+            "{entry}"
+        );
+
+        assert_fields(
+            &entry["request"],
+            &[
+                ("method", Value::is_string),
+                ("url", Value::is_string),
+                ("httpVersion", Value::is_string),
+                ("cookies", Value::is_array),
+                ("headers", Value::is_array),
+                ("queryString", Value::is_array),
+                ("headersSize", Value::is_i64),
+                ("bodySize", Value::is_i64),
+            ],
+        );
+        assert_fields(
+            &entry["response"],
+            &[
+                ("status", Value::is_u64),
+                ("statusText", Value::is_string),
+                ("httpVersion", Value::is_string),
+                ("cookies", Value::is_array),
+                ("headers", Value::is_array),
+                ("content", Value::is_object),
+                ("redirectURL", Value::is_string),
+                ("headersSize", Value::is_i64),
+                ("bodySize", Value::is_i64),
+            ],
+        );
+        assert_fields(
+            &entry["response"]["content"],
+            &[("size", Value::is_i64), ("mimeType", Value::is_string)],
+        );
+
+        // The time of an entry is the sum of its timings.
+        let timings = &entry["timings"];
+        assert_fields(
+            timings,
+            &[
+                ("send", Value::is_number),
+                ("wait", Value::is_number),
+                ("receive", Value::is_number),
+            ],
+        );
+        assert_eq!(
+            ["send", "wait", "receive"]
+                .iter()
+                .map(|name| timings[name].as_f64().unwrap())
+                .sum::<f64>(),
+            entry["time"].as_f64().unwrap(),
+            "{entry}"
+        );
+    }
+}
@@ -0,0 +1,119 @@
+//! Drive the binary to export the spans, and with an export as its input: a
+//! JSON export is re-imported, while a CSV one is recognized but cannot be.
+
+use std::{env, fs, process::Command};
+
+use serde_json::Value;
+
+#[test]
+fn test_json_export_round_trip() {
+    let path = env::temp_dir().join(format!("network-viewer-import-{}.json", std::process::id()));
+
+    let output = Command::new(env!("CARGO_BIN_EXE_network-viewer"))
+        .arg("fixtures/session.log")
+        .arg(&path)
+        .output()
+        .unwrap();
+
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let export = serde_json::from_str::<Value>(&fs::read_to_string(&path).unwrap()).unwrap();
+    let span = &export["spans"][0];
+
+    // The spans are flat records, with their strings decoded and their dates
+    // absolute.
+    assert_eq!(span["connection_id"], "(none)");
+    assert_eq!(span["method"], "GET");
+    assert_eq!(
+        span["uri"],
+        "https://matrix.example.org/_matrix/client/versions"
+    );
+    assert_eq!(span["path"], "/_matrix/client/versions");
+    assert_eq!(span["start_at"], "2024-06-01T10:00:00.000000+00:00");
+    assert_eq!(span["duration_ms"], 120.0);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_network-viewer"))
+        .args(["--format", "json"])
+        .arg(&path)
+        .arg("-")
+        .output()
+        .unwrap();
+
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let reexport = serde_json::from_slice::<Value>(&output.stdout).unwrap();
+
+    assert_eq!(reexport["spans"], export["spans"]);
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_json_export_is_reproducible() {
+    let export = || {
+        let output = Command::new(env!("CARGO_BIN_EXE_network-viewer"))
+            .args(["--format", "json", "fixtures/mixed-traffic.log", "-"])
+            .output()
+            .unwrap();
+
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+
+        output.stdout
+    };
+
+    let first = export();
+
+    // Across a second boundary, for a timestamp of the run to show up.
+    std::thread::sleep(std::time::Duration::from_millis(1_100));
+
+    assert!(first == export());
+}
+
+#[test]
+fn test_csv_export_with_columns() {
+    let path = env::temp_dir().join(format!("network-viewer-import-{}.csv", std::process::id()));
+
+    let output = Command::new(env!("CARGO_BIN_EXE_network-viewer"))
+        .args(["--columns", "status,duration", "fixtures/session.log"])
+        .arg(&path)
+        .output()
+        .unwrap();
+
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(
+        fs::read_to_string(&path)
+            .unwrap()
+            .starts_with("status,status_family,duration_ms,server_duration_ms\n")
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_network-viewer"))
+        .arg(&path)
+        .arg("-")
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+
+    assert_eq!(output.status.code(), Some(2), "{stderr}");
+    assert!(
+        stderr.contains("export the spans with `--format json`"),
+        "{stderr}"
+    );
+
+    fs::remove_file(&path).unwrap();
+}
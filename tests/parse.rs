@@ -0,0 +1,42 @@
+//! Parse a log with the library, without the binary.
+
+use std::{fs, io::BufReader};
+
+use network_viewer::{LogParser, parse_lines, parse_log};
+
+#[test]
+fn test_parse_lines() {
+    let log = fs::read_to_string("fixtures/mixed-traffic.log").unwrap();
+    let mut log_parser = LogParser::new();
+    let mut completed = Vec::new();
+
+    for line in log.lines() {
+        if let Some((connection_id, span)) = log_parser.parse_line(line) {
+            completed.push((connection_id.clone(), span.uri().to_owned()));
+        }
+    }
+
+    let summary = log_parser.summary();
+
+    assert_eq!(summary.number_of_analysed_lines, log.lines().count());
+    assert!(summary.number_of_matched_lines > 0);
+    assert_eq!(
+        completed.len(),
+        summary.number_of_spans - summary.number_of_pending_spans
+    );
+
+    // The iterator yields the same spans, in the same order.
+    assert_eq!(
+        parse_lines(log.lines())
+            .map(|(connection_id, span)| (connection_id, span.uri().to_owned()))
+            .collect::<Vec<_>>(),
+        completed
+    );
+
+    let session = log_parser.into_session("mixed-traffic.log");
+    let parsed = parse_log("mixed-traffic.log", BufReader::new(log.as_bytes())).unwrap();
+
+    assert_eq!(session.spans.len(), parsed.spans.len());
+    assert_eq!(session.start_at, parsed.start_at);
+    assert_eq!(session.end_at, parsed.end_at);
+}
@@ -0,0 +1,52 @@
+//! Drive the binary with a log whose last request never received a
+//! response: it is tracked as pending, see `Span::is_pending`, rather than as
+//! a fast success.
+
+use std::process::Command;
+
+#[test]
+fn test_pending_requests() {
+    let output = Command::new(env!("CARGO_BIN_EXE_network-viewer"))
+        .args(["fixtures/session.log", "-"])
+        .output()
+        .unwrap();
+    let report = String::from_utf8(output.stdout).unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+
+    assert!(output.status.success(), "{stderr}");
+
+    // The request `REQ-6` of `room-list` is only sent.
+    let row_start = report.find(r#"<tr id="room-list-6""#).unwrap();
+    let row = &report[row_start..][..report[row_start..].find("</tr>").unwrap()];
+
+    assert!(row.contains(r#"data-status-family="pending""#), "{row}");
+    assert!(
+        row.contains(r#"<span title="No response in the log">pending</span>"#),
+        "{row}"
+    );
+    // Its bar is striped until the end of the timeline, with no duration.
+    assert!(row.contains("<span><em>pending</em></span>"), "{row}");
+    assert!(report.contains(r#"tr[data-status-family="pending"] &"#));
+
+    // It is counted in the summary.
+    assert!(
+        stderr.contains("Unanswered requests per connection:\n  room-list: 1\n"),
+        "{stderr}"
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_network-viewer"))
+        .args(["--format", "json", "fixtures/session.log", "-"])
+        .output()
+        .unwrap();
+    let export = serde_json::from_slice::<serde_json::Value>(&output.stdout).unwrap();
+    let span = export["spans"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|span| span["connection_id"] == "room-list" && span["request_id"] == 6)
+        .unwrap();
+
+    assert_eq!(span["status"], serde_json::Value::Null);
+    assert_eq!(span["status_family"], "pending");
+    assert_eq!(span["response_log_line"], serde_json::Value::Null);
+}
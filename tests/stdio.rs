@@ -0,0 +1,104 @@
+//! Drive the binary with the logs piped to its standard input, and the report
+//! written to its standard output.
+
+use std::{
+    env, fs,
+    io::Write,
+    process::{Command, Stdio},
+};
+
+#[test]
+fn test_stdin_to_stdout() {
+    let log = fs::read("fixtures/mixed-traffic.log").unwrap();
+    // A gzipped log is decompressed, e.g. piped from `cat console.log.gz`.
+    let gzipped_log = fs::read("fixtures/mixed-traffic.log.gz").unwrap();
+
+    for (log, arguments) in [
+        (&log, &["-", "-"][..]),
+        (&log, &["-"]),
+        (&gzipped_log, &["-", "-"]),
+    ] {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_network-viewer"))
+            .args(arguments)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+
+        child.stdin.take().unwrap().write_all(log).unwrap();
+
+        let output = child.wait_with_output().unwrap();
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let stderr = String::from_utf8(output.stderr).unwrap();
+
+        assert!(output.status.success(), "{arguments:?}: {stderr}");
+        assert!(stdout.starts_with("<!doctype html>"), "{arguments:?}");
+        assert!(stdout.contains("(stdin)"), "{arguments:?}");
+        assert!(
+            !stdout.contains("Number of analysed log lines"),
+            "{arguments:?}"
+        );
+        assert!(stderr.contains("Source: (stdin)"), "{arguments:?}");
+        assert!(stderr.contains("Output file: (stdout)"), "{arguments:?}");
+    }
+}
+
+#[test]
+fn test_stdin_is_never_read_implicitly() {
+    // With the logs piped, a single path is still the output path: the log
+    // path is missing, rather than the standard input being read and the
+    // file overwritten by the report.
+    let path = env::temp_dir().join(format!("network-viewer-stdio-{}.log", std::process::id()));
+    fs::copy("fixtures/session.log", &path).unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_network-viewer"))
+        .arg(&path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // The binary may exit before reading its standard input, closing it.
+    let _ = child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(&fs::read("fixtures/mixed-traffic.log").unwrap());
+
+    let output = child.wait_with_output().unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let log = fs::read("fixtures/session.log").unwrap();
+
+    assert_eq!(output.status.code(), Some(1), "{stderr}");
+    assert!(stderr.contains("<log_path> is missing"), "{stderr}");
+    assert_eq!(fs::read(&path).unwrap(), log);
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_closed_stdout() {
+    // The summary is written to a standard output closed early, e.g. by
+    // `network-viewer … | head`.
+    let path = env::temp_dir().join(format!("network-viewer-stdio-{}.html", std::process::id()));
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_network-viewer"))
+        .arg("fixtures/session.log")
+        .arg(&path)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    drop(child.stdout.take());
+
+    let output = child.wait_with_output().unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+
+    assert_eq!(output.status.code(), Some(0), "{stderr}");
+    assert!(!stderr.contains("panicked"), "{stderr}");
+
+    fs::remove_file(&path).unwrap();
+}